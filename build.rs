@@ -0,0 +1,23 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    generate_header();
+}
+
+/// Generates the C header for the `extern "C"` functions in `src/ffi.rs`
+/// into `OUT_DIR`. The copy committed in `include/take_home.h` is checked
+/// against it by `tests/ffi_integration.rs` rather than overwritten.
+#[cfg(feature = "ffi")]
+fn generate_header() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml"))
+        .expect("invalid cbindgen.toml");
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("failed to generate C header")
+        .write_to_file(format!("{}/take_home.h", std::env::var("OUT_DIR").unwrap()));
+}
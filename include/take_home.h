@@ -0,0 +1,57 @@
+#ifndef TAKE_HOME_H
+#define TAKE_HOME_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Signs a JSON object with HMAC-SHA256 and returns the hex signature, or
+// NULL if `json` is not a JSON object or the key is empty.
+//
+// # Safety
+// `key` must point to `key_len` readable bytes and `json` must be a valid
+// NUL-terminated string.
+char *sign_json(const uint8_t *key, size_t key_len, const char *json);
+
+// Returns 1 if `signature` is valid for the JSON object `json`, 0 if it is
+// not, and -1 if the inputs are invalid.
+//
+// # Safety
+// `key` must point to `key_len` readable bytes; `json` and `signature` must
+// be valid NUL-terminated strings.
+int verify_json(const uint8_t *key, size_t key_len, const char *json, const char *signature);
+
+// Encrypts a single JSON value and returns the ciphertext as JSON text, or
+// NULL if `json` is not valid JSON.
+//
+// # Safety
+// `json` must be a valid NUL-terminated string.
+char *encrypt_value(const char *json);
+
+// Decrypts a ciphertext produced by [`encrypt_value`] and returns the
+// original value as JSON text, or NULL if the input is not ciphertext.
+//
+// # Safety
+// `json` must be a valid NUL-terminated string.
+char *decrypt_value(const char *json);
+
+// Releases a string returned by this library. Passing NULL is a no-op.
+//
+// # Safety
+// `ptr` must be NULL or a pointer returned by this library that has not
+// been freed yet.
+void free_string(char *ptr);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TAKE_HOME_H */
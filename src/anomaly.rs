@@ -0,0 +1,355 @@
+//! Flags callers whose signatures fail verification unusually often, an
+//! early sign of tampering or of a client signing with a stale key. Every
+//! verification is counted per caller, as named by its `X-Client-Id` and
+//! `X-Tenant-Id` headers, over a sliding window; a caller crossing both
+//! thresholds raises an [`Alert`], logged under the `anomaly` tracing
+//! target and handed to any other [`AlertSink`], such as a webhook.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, Uri, header};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use serde::Serialize;
+
+use crate::clock::Clock;
+use crate::config::AnomalyConfig;
+
+/// Sub-windows a window is counted in; the oldest drops out as time moves.
+const BUCKETS: usize = 10;
+/// Callers tracked before further ones are counted together. Caller names
+/// are not authenticated, so they must not grow the map without bound.
+const MAX_CALLERS: usize = 10_000;
+/// Stands in for the client of callers past [`MAX_CALLERS`].
+pub const OVERFLOW_CLIENT: &str = "_other";
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Who a request claims to come from. Neither header is authenticated.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Caller {
+    pub client: Option<String>,
+    pub tenant: Option<String>,
+}
+
+/// When a caller's failures raise an alert.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub window: Duration,
+    /// Failed verifications within the window.
+    pub min_failures: u64,
+    /// Share of the window's verifications that failed.
+    pub min_failure_ratio: f64,
+    /// Alerts for the same caller are at least this far apart.
+    pub cooldown: Duration,
+}
+
+impl AnomalyConfig {
+    pub fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            window: Duration::from_secs(self.window_secs),
+            min_failures: self.min_failures,
+            min_failure_ratio: self.min_failure_ratio,
+            cooldown: Duration::from_secs(self.cooldown_secs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Alert {
+    /// Unix time in seconds.
+    pub at: u64,
+    pub client: Option<String>,
+    pub tenant: Option<String>,
+    /// Verifications that failed within the window.
+    pub failures: u64,
+    /// Verifications within the window.
+    pub attempts: u64,
+    pub window_secs: u64,
+}
+
+pub trait AlertSink: Send + Sync {
+    fn alert(&self, alert: &Alert);
+}
+
+/// Logs each alert at `warn` under the `anomaly` target.
+#[derive(Debug, Default)]
+pub struct TracingAlertSink;
+
+impl AlertSink for TracingAlertSink {
+    fn alert(&self, alert: &Alert) {
+        tracing::warn!(
+            target: "anomaly",
+            at = alert.at,
+            client = alert.client.as_deref(),
+            tenant = alert.tenant.as_deref(),
+            failures = alert.failures,
+            attempts = alert.attempts,
+            window_secs = alert.window_secs,
+            "signature verification failures above threshold"
+        );
+    }
+}
+
+/// Keeps alerts in memory, for tests and embedders that ship them
+/// elsewhere.
+#[derive(Debug, Default)]
+pub struct MemoryAlertSink {
+    alerts: Mutex<Vec<Alert>>,
+}
+
+impl MemoryAlertSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alerts(&self) -> Vec<Alert> {
+        self.alerts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl AlertSink for MemoryAlertSink {
+    fn alert(&self, alert: &Alert) {
+        self.alerts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(alert.clone());
+    }
+}
+
+/// POSTs each alert as JSON to an `http://` URL, in the background. A
+/// failed delivery is logged and not retried.
+pub struct WebhookAlertSink {
+    url: Uri,
+    client: Client<HttpConnector, Body>,
+}
+
+impl WebhookAlertSink {
+    pub fn new(url: Uri) -> Self {
+        Self {
+            url,
+            client: Client::builder(TokioExecutor::new()).build_http(),
+        }
+    }
+}
+
+impl AlertSink for WebhookAlertSink {
+    fn alert(&self, alert: &Alert) {
+        let body = serde_json::to_vec(alert).expect("alerts serialize");
+        let request = Request::post(self.url.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .expect("a valid request");
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            let failure = match tokio::time::timeout(WEBHOOK_TIMEOUT, client.request(request)).await
+            {
+                Ok(Ok(response)) if response.status().is_success() => return,
+                Ok(Ok(response)) => format!("status {}", response.status()),
+                Ok(Err(err)) => err.to_string(),
+                Err(_) => "timed out".into(),
+            };
+            tracing::warn!(target: "anomaly", error = %failure, "alert webhook failed");
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    /// Which sub-window of time the counts are for.
+    index: u64,
+    attempts: u64,
+    failures: u64,
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    buckets: [Bucket; BUCKETS],
+    last_seen: u64,
+    last_alert: Option<u64>,
+}
+
+/// Counts verifications per caller and raises alerts.
+pub struct AnomalyDetector {
+    thresholds: Thresholds,
+    sinks: Vec<Arc<dyn AlertSink>>,
+    callers: Mutex<HashMap<Caller, Window>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl AnomalyDetector {
+    /// A detector that logs its alerts.
+    pub fn new(thresholds: Thresholds) -> Self {
+        Self {
+            thresholds,
+            sinks: vec![Arc::new(TracingAlertSink)],
+            callers: Mutex::new(HashMap::new()),
+            clock: crate::clock::system(),
+        }
+    }
+
+    /// The clock failures are bucketed by. Defaults to the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Also hands alerts to `sink`.
+    pub fn with_sink(mut self, sink: Arc<dyn AlertSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Counts one verification by `caller`, alerting if it takes the caller
+    /// over the thresholds.
+    pub fn record(&self, caller: &Caller, verified: bool) {
+        if let Some(alert) = self.record_at(caller, verified, self.clock.now()) {
+            for sink in &self.sinks {
+                sink.alert(&alert);
+            }
+        }
+    }
+
+    fn record_at(&self, caller: &Caller, verified: bool, now: u64) -> Option<Alert> {
+        let window_secs = self.thresholds.window.as_secs().max(1);
+        let width = window_secs.div_ceil(BUCKETS as u64);
+        let index = now / width;
+        let mut callers = self
+            .callers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if callers.len() >= MAX_CALLERS && !callers.contains_key(caller) {
+            callers.retain(|_, window| now.saturating_sub(window.last_seen) < window_secs);
+        }
+        let overflow;
+        let caller = if callers.len() < MAX_CALLERS || callers.contains_key(caller) {
+            caller
+        } else {
+            overflow = Caller {
+                client: Some(OVERFLOW_CLIENT.into()),
+                tenant: None,
+            };
+            &overflow
+        };
+        let window = callers.entry(caller.clone()).or_default();
+        window.last_seen = now;
+        let bucket = &mut window.buckets[(index % BUCKETS as u64) as usize];
+        if bucket.index != index {
+            *bucket = Bucket {
+                index,
+                ..Bucket::default()
+            };
+        }
+        bucket.attempts += 1;
+        if verified {
+            return None;
+        }
+        bucket.failures += 1;
+
+        let (attempts, failures) = window
+            .buckets
+            .iter()
+            .filter(|bucket| index.saturating_sub(bucket.index) < BUCKETS as u64)
+            .fold((0, 0), |(attempts, failures), bucket| {
+                (attempts + bucket.attempts, failures + bucket.failures)
+            });
+        let cooled_down = window
+            .last_alert
+            .is_none_or(|at| now.saturating_sub(at) >= self.thresholds.cooldown.as_secs());
+        let over = failures >= self.thresholds.min_failures
+            && failures as f64 >= self.thresholds.min_failure_ratio * attempts as f64;
+        if !(over && cooled_down) {
+            return None;
+        }
+        window.last_alert = Some(now);
+        Some(Alert {
+            at: now,
+            client: caller.client.clone(),
+            tenant: caller.tenant.clone(),
+            failures,
+            attempts,
+            window_secs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> AnomalyDetector {
+        AnomalyDetector::new(Thresholds {
+            window: Duration::from_secs(60),
+            min_failures: 3,
+            min_failure_ratio: 0.5,
+            cooldown: Duration::from_secs(300),
+        })
+    }
+
+    fn caller(client: &str) -> Caller {
+        Caller {
+            client: Some(client.into()),
+            tenant: Some("acme".into()),
+        }
+    }
+
+    #[test]
+    fn repeated_failures_raise_one_alert() {
+        let detector = detector();
+        let mallory = caller("mallory");
+        assert_eq!(detector.record_at(&mallory, false, 1000), None);
+        assert_eq!(detector.record_at(&mallory, false, 1010), None);
+        let alert = detector.record_at(&mallory, false, 1020).unwrap();
+        assert_eq!(
+            alert,
+            Alert {
+                at: 1020,
+                client: Some("mallory".into()),
+                tenant: Some("acme".into()),
+                failures: 3,
+                attempts: 3,
+                window_secs: 60,
+            }
+        );
+        // Held back by the cooldown.
+        assert_eq!(detector.record_at(&mallory, false, 1030), None);
+        assert!(detector.record_at(&mallory, false, 1400).is_none());
+        assert!(detector.record_at(&mallory, false, 1401).is_none());
+        assert!(detector.record_at(&mallory, false, 1402).is_some());
+    }
+
+    #[test]
+    fn failures_age_out_of_the_window() {
+        let detector = detector();
+        let alice = caller("alice");
+        detector.record_at(&alice, false, 1000);
+        detector.record_at(&alice, false, 1001);
+        assert_eq!(detector.record_at(&alice, false, 1100), None);
+    }
+
+    #[test]
+    fn mostly_successful_callers_are_not_flagged() {
+        let detector = detector();
+        let busy = caller("busy");
+        for at in 1000..1010 {
+            detector.record_at(&busy, true, at);
+        }
+        for at in 1010..1014 {
+            assert_eq!(detector.record_at(&busy, false, at), None);
+        }
+    }
+
+    #[test]
+    fn callers_are_counted_apart() {
+        let detector = detector();
+        detector.record_at(&caller("a"), false, 1000);
+        detector.record_at(&caller("b"), false, 1000);
+        assert_eq!(detector.record_at(&caller("c"), false, 1000), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! API keys carrying scopes, so callers can be handed only the operations
+//! they need, such as verify-only credentials for external partners. Once
+//! any key is configured, every request that uses a key, and every
+//! protected admin request, must present one in `X-Api-Key` whose scopes
+//! include the request's [`Action`]. The key's name is then known to the
+//! authorization [`policy`](crate::policy) as `api_key`.
+
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::http::HeaderName;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::crypto::constant_time;
+use crate::error::Error;
+use crate::policy::Action;
+use crate::state::AppState;
+
+/// Request header carrying the caller's API key.
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+/// An API key that authenticated a request, added to its extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub name: String,
+    pub scopes: Vec<Action>,
+}
+
+impl ApiKey {
+    pub fn allows(&self, action: Action) -> bool {
+        self.scopes.contains(&action)
+    }
+}
+
+/// The configured API keys.
+#[derive(Debug, Default)]
+pub struct ApiKeys {
+    secrets: Vec<Vec<u8>>,
+    keys: Vec<Arc<ApiKey>>,
+}
+
+impl ApiKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the key `secret`, named `name`, granting `scopes`.
+    pub fn with_key(mut self, name: &str, secret: &[u8], scopes: Vec<Action>) -> Self {
+        self.secrets.push(secret.to_vec());
+        self.keys.push(Arc::new(ApiKey {
+            name: name.to_string(),
+            scopes,
+        }));
+        self
+    }
+
+    /// The key whose secret is `presented`. Every key is compared, so the
+    /// time taken does not reveal which one matched.
+    pub fn find(&self, presented: &[u8]) -> Option<&Arc<ApiKey>> {
+        constant_time::position(&self.secrets, presented).map(|i| &self.keys[i])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Middleware requiring data-plane requests to present an API key scoped
+/// for their action.
+pub async fn authenticate(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    match Action::of(request.method(), request.uri().path()) {
+        Some(action) => check(&state, action, request, next).await,
+        None => next.run(request).await,
+    }
+}
+
+/// Middleware requiring protected admin requests to present an API key
+/// with the `admin` scope.
+pub async fn authenticate_admin(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    check(&state, Action::Admin, request, next).await
+}
+
+async fn check(state: &AppState, action: Action, mut request: Request, next: Next) -> Response {
+    let Some(keys) = &state.api_keys else {
+        return next.run(request).await;
+    };
+    let keys = keys.load();
+    let Some(presented) = request.headers().get(API_KEY_HEADER) else {
+        return Error::Unauthorized("missing API key".into()).into_response();
+    };
+    let Some(key) = keys.find(presented.as_bytes()) else {
+        return Error::Unauthorized("invalid API key".into()).into_response();
+    };
+    if !key.allows(action) {
+        return Error::Forbidden(format!("API key `{}` lacks the `{action}` scope", key.name))
+            .into_response();
+    }
+    request.extensions_mut().insert(key.clone());
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_found_by_secret() {
+        let keys = ApiKeys::new()
+            .with_key("partner", b"verify-only", vec![Action::Verify])
+            .with_key("ops", b"everything", vec![Action::Sign, Action::Admin]);
+
+        let partner = keys.find(b"verify-only").unwrap();
+        assert_eq!(partner.name, "partner");
+        assert!(partner.allows(Action::Verify));
+        assert!(!partner.allows(Action::Sign));
+        assert!(keys.find(b"everything").unwrap().allows(Action::Admin));
+        assert!(keys.find(b"verify").is_none());
+        assert!(ApiKeys::new().find(b"").is_none());
+    }
+}
@@ -0,0 +1,351 @@
+#[cfg(all(feature = "admin", feature = "signing"))]
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "signing")]
+use axum::http::HeaderValue;
+use axum::middleware::from_fn_with_state;
+#[cfg(all(feature = "admin", feature = "tenancy"))]
+use axum::routing::delete;
+#[cfg(any(
+    feature = "admin",
+    feature = "blobs",
+    feature = "signing",
+    feature = "asymmetric"
+))]
+use axum::routing::get;
+#[cfg(any(feature = "encryption", feature = "signing"))]
+use axum::routing::post;
+#[cfg(any(
+    feature = "blobs",
+    feature = "vault",
+    all(feature = "admin", feature = "tenancy")
+))]
+use axum::routing::put;
+use axum::{Router, extract::DefaultBodyLimit, http::StatusCode};
+use tower_http::{timeout::TimeoutLayer, trace::TraceLayer};
+
+use crate::config::Config;
+#[cfg(all(feature = "admin", feature = "signing"))]
+use crate::crypto::hmac::HMacSigner;
+#[cfg(all(feature = "admin", feature = "signing"))]
+use crate::crypto::http_signature::Keyring;
+#[cfg(feature = "response-encryption")]
+use crate::crypto::seal::parse_public_key;
+#[cfg(any(
+    feature = "encryption",
+    feature = "signing",
+    feature = "admin",
+    feature = "blobs",
+    feature = "vault"
+))]
+use crate::handlers;
+#[cfg(feature = "response-encryption")]
+use crate::layers::EncryptResponseLayer;
+#[cfg(feature = "signing")]
+use crate::layers::SignResponseLayer;
+#[cfg(all(feature = "admin", feature = "signing"))]
+use crate::layers::VerifyHttpSignatureLayer;
+use crate::state::AppState;
+use crate::{api_keys, policy, quota};
+
+/// Builds the data-plane router with the backends selected by `config` and
+/// all production middleware applied.
+pub fn app(config: &Config) -> Router {
+    router(AppState::from_config(config), config)
+}
+
+/// Same as [`app`], but with caller-provided backends.
+pub fn router(state: AppState, config: &Config) -> Router {
+    let router = Router::new();
+    #[cfg(feature = "encryption")]
+    let router = router
+        .route("/encrypt", post(handlers::encryption::encrypt))
+        .route("/decrypt", post(handlers::encryption::decrypt))
+        .route(
+            "/encrypt/pointers",
+            post(handlers::encryption::encrypt_pointers),
+        )
+        .route(
+            "/decrypt/pointers",
+            post(handlers::encryption::decrypt_pointers),
+        )
+        .route("/encrypt/patch", post(handlers::encryption::encrypt_patch))
+        .route_layer(from_fn_with_state(
+            state.clone(),
+            crate::deprecation::base64_ciphertext,
+        ));
+    #[cfg(feature = "blobs")]
+    let router = router
+        .route("/blobs", put(handlers::blobs::put))
+        .route("/blobs/{hash}", get(handlers::blobs::get));
+    #[cfg(feature = "vault")]
+    let router = router.route(
+        "/vault/{name}",
+        put(handlers::vault::put)
+            .get(handlers::vault::get)
+            .delete(handlers::vault::delete),
+    );
+    #[cfg(feature = "signing")]
+    let router = router
+        .route("/sign", post(handlers::signing::sign))
+        .route("/verify", post(handlers::signing::verify))
+        .route("/verify/batch", post(handlers::signing::verify_batch))
+        .route("/canonicalize", post(handlers::signing::canonicalize))
+        .route("/testvectors", get(handlers::signing::test_vectors))
+        .route(
+            "/http-signatures/sign",
+            post(handlers::http_signature::sign),
+        )
+        .route(
+            "/http-signatures/verify",
+            post(handlers::http_signature::verify),
+        )
+        .route("/sigv4/verify", post(handlers::sigv4::verify))
+        .route("/challenge", post(handlers::challenge::issue))
+        .route("/challenge/respond", post(handlers::challenge::respond))
+        .route(
+            "/webhooks/verify/{provider}",
+            post(handlers::webhook::verify),
+        );
+    #[cfg(feature = "asymmetric")]
+    let router = if state.transparency.is_some() {
+        router
+            .route("/transparency/roots", get(handlers::transparency::roots))
+            .route(
+                "/transparency/consistency",
+                get(handlers::transparency::consistency),
+            )
+    } else {
+        router
+    };
+    #[cfg(feature = "asymmetric")]
+    let router = router.route("/.well-known/jwks.json", get(handlers::jwks::jwks));
+    #[cfg(feature = "asymmetric")]
+    let router = match &state.token_issuer {
+        Some(issuer) => {
+            let router = router
+                .route("/oauth/token", post(handlers::oauth::token))
+                .route("/oauth/dpop/verify", post(handlers::oauth::verify_dpop))
+                .route(
+                    "/.well-known/openid-configuration",
+                    get(handlers::oauth::openid_configuration),
+                );
+            if issuer.issues_refresh_tokens() {
+                router.route("/oauth/revoke", post(handlers::oauth::revoke))
+            } else {
+                router
+            }
+        }
+        None => router,
+    };
+    // Inside the policy, so denied requests use up no quota.
+    let router = if state.quotas.is_some() {
+        router.route_layer(from_fn_with_state(state.clone(), quota::enforce))
+    } else {
+        router
+    };
+    // Only matched routes are authorized, so unknown paths still get 404.
+    let router = if state.policy.is_some() {
+        router.route_layer(from_fn_with_state(state.clone(), policy::authorize))
+    } else {
+        router
+    };
+    // Outside the policy, which decides on the key's name.
+    let router = if state.api_keys.is_some() {
+        router.route_layer(from_fn_with_state(state.clone(), api_keys::authenticate))
+    } else {
+        router
+    };
+    #[cfg(feature = "signing")]
+    let sign_responses = sign_response_layer(&state, config);
+    #[cfg(feature = "response-encryption")]
+    let encrypt_responses = encrypt_response_layer(&state, config);
+    let mut router = router.with_state(state);
+    #[cfg(feature = "response-encryption")]
+    if config.response_encryption.enabled {
+        router = router.layer(encrypt_responses);
+    }
+    // Outside response encryption, so the signature covers the bytes the
+    // caller actually receives.
+    #[cfg(feature = "signing")]
+    if let Some(layer) = sign_responses {
+        router = router.layer(layer);
+    }
+    router = router.layer(DefaultBodyLimit::max(config.limits.max_body_bytes));
+    if let Some(secs) = config.middleware.request_timeout_secs {
+        router = router.layer(TimeoutLayer::with_status_code(
+            StatusCode::REQUEST_TIMEOUT,
+            Duration::from_secs(secs),
+        ));
+    }
+    if config.middleware.trace_requests {
+        router = router.layer(TraceLayer::new_for_http());
+    }
+    router
+}
+
+#[cfg(feature = "signing")]
+fn sign_response_layer(state: &AppState, config: &Config) -> Option<SignResponseLayer> {
+    if !config.middleware.sign_responses {
+        return None;
+    }
+    let key_id = HeaderValue::from_str(&config.signing.key_id)
+        .expect("validated configuration has a header-safe key id");
+    let layer = SignResponseLayer::new(state.signers.default_signer().clone())
+        .key_id(key_id)
+        .max_body_bytes(config.limits.max_body_bytes);
+    Some(layer)
+}
+
+#[cfg(feature = "response-encryption")]
+fn encrypt_response_layer(state: &AppState, config: &Config) -> EncryptResponseLayer {
+    let settings = &config.response_encryption;
+    let layer = EncryptResponseLayer::new()
+        .required(settings.required)
+        .max_body_bytes(config.limits.max_body_bytes)
+        .blocking_pool(state.blocking.clone(), config.blocking.min_seal_bytes);
+    settings.clients.iter().fold(layer, |layer, (client, key)| {
+        let key = parse_public_key(key).expect("validated configuration has valid client keys");
+        layer.client_key(client, key)
+    })
+}
+
+/// Builds the router served on the admin listener.
+#[cfg(feature = "admin")]
+pub fn admin_app(config: &Config) -> Router {
+    admin_router(AppState::from_config(config), config)
+}
+
+/// Same as [`admin_app`], but with caller-provided backends.
+#[cfg(feature = "admin")]
+pub fn admin_router(state: AppState, config: &Config) -> Router {
+    let protected = Router::new()
+        .route("/metrics", get(handlers::admin::metrics))
+        .route("/keys/usage", get(handlers::admin::key_usage))
+        .route("/events", get(handlers::admin::events));
+    #[cfg(feature = "encryption")]
+    let protected = protected.route(
+        "/encryption/migrate",
+        post(handlers::admin::migrate_encryption),
+    );
+    #[cfg(feature = "signing")]
+    let protected = {
+        let protected = protected.route("/algorithms", get(handlers::admin::algorithms));
+        #[cfg(feature = "escrow")]
+        let protected = protected.route("/keys/escrow", post(handlers::admin::escrow_export));
+        #[cfg(feature = "escrow")]
+        let protected = if state.ceremonies.is_some() {
+            protected
+                .route("/ceremonies", post(handlers::admin::start_ceremony))
+                .route(
+                    "/ceremonies/{id}",
+                    get(handlers::admin::ceremony).delete(handlers::admin::abort_ceremony),
+                )
+                .route(
+                    "/ceremonies/{id}/shares",
+                    post(handlers::admin::contribute_share),
+                )
+                .route(
+                    "/ceremonies/{id}/bundle",
+                    get(handlers::admin::ceremony_bundle),
+                )
+        } else {
+            protected
+        };
+        #[cfg(feature = "tenancy")]
+        let protected = protected
+            .route("/tenants", get(handlers::admin::list_tenants))
+            .route(
+                "/tenants/cache",
+                delete(handlers::admin::invalidate_tenants),
+            )
+            .route(
+                "/tenants/{tenant}/cache",
+                delete(handlers::admin::invalidate_tenant),
+            )
+            .route(
+                "/tenants/{tenant}/key",
+                put(handlers::admin::rotate_tenant_key),
+            )
+            .route(
+                "/tenants/{tenant}/state",
+                put(handlers::admin::set_tenant_key_state),
+            );
+        // Inside signature verification, so only authentic requests are
+        // decided.
+        let protected = authorize_admin(protected, &state);
+        if config.middleware.verify_admin_requests {
+            protected.route_layer(admin_signature_layer(&state, config))
+        } else {
+            protected
+        }
+    };
+    #[cfg(not(feature = "signing"))]
+    let protected = authorize_admin(protected, &state);
+    #[cfg(feature = "signing")]
+    let sign_responses = sign_response_layer(&state, config);
+    let mut router = Router::new()
+        .route("/healthz", get(handlers::admin::healthz))
+        .route("/readyz", get(handlers::admin::readyz))
+        .merge(protected)
+        .with_state(state);
+    #[cfg(feature = "signing")]
+    if let Some(layer) = sign_responses {
+        router = router.layer(layer);
+    }
+    if config.middleware.trace_requests {
+        router = router.layer(TraceLayer::new_for_http());
+    }
+    router
+}
+
+/// Verifies admin requests against `admin_signatures.keys`, never the
+/// signing key: signatures must cover the method, target and body, be
+/// fresh, and carry a nonce that is accepted once.
+#[cfg(all(feature = "admin", feature = "signing"))]
+fn admin_signature_layer(state: &AppState, config: &Config) -> VerifyHttpSignatureLayer {
+    let admin = &config.admin_signatures;
+    let keys = admin
+        .keys
+        .iter()
+        .fold(Keyring::new(), |keys, (keyid, key)| {
+            keys.with_key(keyid.clone(), HMacSigner::new(key.expose().to_vec()))
+        });
+    let store = admin
+        .store()
+        .expect("validated configuration has a valid admin nonce store");
+    let skew_secs = config.clock.skew_secs;
+    // Nonces are kept for as long as their signature could be accepted.
+    let ttl = Duration::from_secs(
+        admin
+            .max_age_secs
+            .saturating_add(skew_secs.saturating_mul(2)),
+    );
+    VerifyHttpSignatureLayer::new(Arc::new(keys))
+        .required_components(["@method", "@request-target", "content-digest"])
+        .max_age_secs(admin.max_age_secs)
+        .replay_store(store, ttl)
+        .clock(state.clock.clone())
+        .clock_skew_secs(skew_secs)
+        .max_body_bytes(config.limits.max_body_bytes)
+}
+
+/// Requires the admin routes added so far to present an API key with the
+/// `admin` scope and decides them with [`AppState::policy`], if either is
+/// set.
+#[cfg(feature = "admin")]
+fn authorize_admin(protected: Router<AppState>, state: &AppState) -> Router<AppState> {
+    let protected = if state.policy.is_some() {
+        protected.route_layer(from_fn_with_state(state.clone(), policy::authorize_admin))
+    } else {
+        protected
+    };
+    if state.api_keys.is_none() {
+        return protected;
+    }
+    protected.route_layer(from_fn_with_state(
+        state.clone(),
+        api_keys::authenticate_admin,
+    ))
+}
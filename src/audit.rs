@@ -0,0 +1,496 @@
+//! Record of who touched which stored secret, and when. Every access goes
+//! to an [`AuditSink`]; by default events are logged under the `audit`
+//! tracing target. [`BatchingAuditSink`] queues them for durable storage
+//! such as Postgres instead.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::clock::Clock;
+use crate::crypto::BoxFuture;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error(
+        "audit tables must be named by 1 to 63 characters of `a-z A-Z 0-9 _`, not starting with a digit"
+    )]
+    InvalidTable,
+    #[error("audit storage failed: {0}")]
+    Backend(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditEvent {
+    /// Unix time in seconds.
+    pub at: u64,
+    /// What was attempted, e.g. `vault.read`.
+    pub action: &'static str,
+    /// What it was attempted on, e.g. the vault entry name.
+    pub resource: String,
+    /// Caller as named by its `X-Client-Id` header. Not authenticated.
+    pub client: Option<String>,
+    pub success: bool,
+}
+
+pub trait AuditSink: Send + Sync {
+    fn record(&self, event: AuditEvent);
+
+    /// Like [`record`](Self::record), but may wait until the sink has room
+    /// for the event. Handlers record through this, so a sink that falls
+    /// behind slows them down instead of losing events.
+    fn record_async(&self, event: AuditEvent) -> BoxFuture<'_, ()> {
+        self.record(event);
+        Box::pin(async {})
+    }
+}
+
+/// Logs each event at `info` under the `audit` target.
+#[derive(Debug, Default)]
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+    fn record(&self, event: AuditEvent) {
+        tracing::info!(
+            target: "audit",
+            at = event.at,
+            action = event.action,
+            resource = %event.resource,
+            client = event.client.as_deref(),
+            success = event.success,
+        );
+    }
+}
+
+/// Keeps events in memory, for tests and embedders that ship them
+/// elsewhere.
+#[derive(Debug, Default)]
+pub struct MemoryAuditSink {
+    events: Mutex<Vec<AuditEvent>>,
+}
+
+impl MemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<AuditEvent> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl AuditSink for MemoryAuditSink {
+    fn record(&self, event: AuditEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(event);
+    }
+}
+
+/// Durable storage that [`BatchingAuditSink`] hands events to.
+pub trait AuditWriter: Send + Sync {
+    /// Stores `events` all at once, or none of them.
+    fn write<'a>(&'a self, events: &'a [AuditEvent]) -> BoxFuture<'a, Result<(), AuditError>>;
+
+    /// Deletes the events recorded before `before` (Unix seconds) and
+    /// returns how many there were.
+    fn prune(&self, before: u64) -> BoxFuture<'_, Result<u64, AuditError>>;
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchSettings {
+    /// Most events written at once.
+    pub batch_size: usize,
+    /// Longest an event waits for its batch to fill up.
+    pub flush_interval: Duration,
+    /// Events queued before [`AuditSink::record_async`] waits and
+    /// [`AuditSink::record`] drops.
+    pub queue_capacity: usize,
+    /// Age from which events are pruned; kept forever if `None`.
+    pub retention: Option<Duration>,
+    /// How often pruning runs.
+    pub prune_interval: Duration,
+    /// The clock event ages are measured by.
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        Self {
+            batch_size: 500,
+            flush_interval: Duration::from_secs(1),
+            queue_capacity: 10_000,
+            retention: None,
+            prune_interval: Duration::from_secs(3600),
+            clock: crate::clock::system(),
+        }
+    }
+}
+
+/// Queues events for an [`AuditWriter`] and writes them in batches from a
+/// background task. Failed writes are retried with backoff until they
+/// succeed, so events are not lost while the storage is down; the queue
+/// fills up instead, and [`record_async`](AuditSink::record_async) then
+/// waits for room. Events queued when the process exits are lost.
+pub struct BatchingAuditSink {
+    sender: mpsc::Sender<AuditEvent>,
+    dropped: AtomicU64,
+}
+
+impl BatchingAuditSink {
+    /// Starts the writer task, and the pruning task if events have a
+    /// retention, on the current Tokio runtime. Both stop once the sink is
+    /// dropped and its queue is written.
+    pub fn spawn(writer: Arc<dyn AuditWriter>, settings: BatchSettings) -> Self {
+        let (sender, receiver) = mpsc::channel(settings.queue_capacity.max(1));
+        if let Some(retention) = settings.retention {
+            tokio::spawn(prune(
+                writer.clone(),
+                sender.downgrade(),
+                retention,
+                settings.prune_interval,
+                settings.clock.clone(),
+            ));
+        }
+        tokio::spawn(write_batches(writer, receiver, settings));
+        Self {
+            sender,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Events [`record`](AuditSink::record) dropped because the queue was
+    /// full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl AuditSink for BatchingAuditSink {
+    fn record(&self, event: AuditEvent) {
+        if let Err(err) = self.sender.try_send(event) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            let event = err.into_inner();
+            tracing::error!(
+                target: "audit",
+                action = event.action,
+                resource = %event.resource,
+                "audit queue is full, event dropped"
+            );
+        }
+    }
+
+    fn record_async(&self, event: AuditEvent) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            // The writer task only ends once every sender is gone.
+            let _ = self.sender.send(event).await;
+        })
+    }
+}
+
+async fn write_batches(
+    writer: Arc<dyn AuditWriter>,
+    mut receiver: mpsc::Receiver<AuditEvent>,
+    settings: BatchSettings,
+) {
+    let batch_size = settings.batch_size.max(1);
+    let mut batch = Vec::with_capacity(batch_size);
+    while let Some(event) = receiver.recv().await {
+        batch.push(event);
+        let deadline = tokio::time::Instant::now() + settings.flush_interval;
+        while batch.len() < batch_size {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(event)) => batch.push(event),
+                Ok(None) | Err(_) => break,
+            }
+        }
+        let mut backoff = Duration::from_millis(100);
+        while let Err(err) = writer.write(&batch).await {
+            tracing::warn!(
+                target: "audit",
+                error = %err,
+                events = batch.len(),
+                "writing audit events failed, retrying"
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(Duration::from_secs(30));
+        }
+        batch.clear();
+    }
+}
+
+async fn prune(
+    writer: Arc<dyn AuditWriter>,
+    sender: mpsc::WeakSender<AuditEvent>,
+    retention: Duration,
+    every: Duration,
+    clock: Arc<dyn Clock>,
+) {
+    let mut interval = tokio::time::interval(every);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if sender.upgrade().is_none() {
+            return;
+        }
+        let before = clock.now().saturating_sub(retention.as_secs());
+        match writer.prune(before).await {
+            Ok(pruned) => tracing::debug!(target: "audit", pruned, "pruned audit events"),
+            Err(err) => {
+                tracing::warn!(target: "audit", error = %err, "pruning audit events failed")
+            }
+        }
+    }
+}
+
+/// Stores events in a Postgres table, created on first use:
+///
+/// ```sql
+/// CREATE TABLE <table> (
+///     id BIGSERIAL PRIMARY KEY,
+///     at BIGINT NOT NULL,
+///     action TEXT NOT NULL,
+///     resource TEXT NOT NULL,
+///     client TEXT,
+///     success BOOLEAN NOT NULL
+/// );
+/// CREATE INDEX <table>_at ON <table> (at);
+/// ```
+#[cfg(feature = "postgres")]
+pub struct PostgresAuditWriter {
+    connection: crate::postgres::Connection,
+    table: String,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresAuditWriter {
+    /// Parses `url`; the connection is opened on first use and reopened
+    /// after it drops. Connections are not encrypted.
+    pub fn new(url: &str, table: impl Into<String>) -> Result<Self, AuditError> {
+        let table = table.into();
+        let valid = (1..=63).contains(&table.len())
+            && !table.starts_with(|c: char| c.is_ascii_digit())
+            && table
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_');
+        if !valid {
+            return Err(AuditError::InvalidTable);
+        }
+        Ok(Self {
+            connection: crate::postgres::Connection::new(url).map_err(backend)?,
+            table,
+        })
+    }
+
+    async fn client(&self) -> Result<Arc<tokio_postgres::Client>, AuditError> {
+        let (client, opened) = self.connection.client().await.map_err(backend)?;
+        if opened {
+            let table = &self.table;
+            client
+                .batch_execute(&format!(
+                    "CREATE TABLE IF NOT EXISTS {table} (
+                        id BIGSERIAL PRIMARY KEY,
+                        at BIGINT NOT NULL,
+                        action TEXT NOT NULL,
+                        resource TEXT NOT NULL,
+                        client TEXT,
+                        success BOOLEAN NOT NULL
+                    );
+                    CREATE INDEX IF NOT EXISTS {table}_at ON {table} (at);"
+                ))
+                .await
+                .map_err(backend)?;
+        }
+        Ok(client)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl AuditWriter for PostgresAuditWriter {
+    fn write<'a>(&'a self, events: &'a [AuditEvent]) -> BoxFuture<'a, Result<(), AuditError>> {
+        Box::pin(async move {
+            let client = self.client().await?;
+            // One statement with an array per column, however large the
+            // batch.
+            let at: Vec<i64> = events.iter().map(|e| to_i64(e.at)).collect();
+            let action: Vec<&str> = events.iter().map(|e| e.action).collect();
+            let resource: Vec<&str> = events.iter().map(|e| e.resource.as_str()).collect();
+            let caller: Vec<Option<&str>> = events.iter().map(|e| e.client.as_deref()).collect();
+            let success: Vec<bool> = events.iter().map(|e| e.success).collect();
+            client
+                .execute(
+                    &format!(
+                        "INSERT INTO {} (at, action, resource, client, success) \
+                         SELECT * FROM UNNEST($1::BIGINT[], $2::TEXT[], $3::TEXT[], $4::TEXT[], $5::BOOLEAN[])",
+                        self.table
+                    ),
+                    &[&at, &action, &resource, &caller, &success],
+                )
+                .await
+                .map_err(backend)?;
+            Ok(())
+        })
+    }
+
+    fn prune(&self, before: u64) -> BoxFuture<'_, Result<u64, AuditError>> {
+        Box::pin(async move {
+            let client = self.client().await?;
+            client
+                .execute(
+                    &format!("DELETE FROM {} WHERE at < $1", self.table),
+                    &[&to_i64(before)],
+                )
+                .await
+                .map_err(backend)
+        })
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn to_i64(secs: u64) -> i64 {
+    i64::try_from(secs).unwrap_or(i64::MAX)
+}
+
+#[cfg(feature = "postgres")]
+fn backend(err: tokio_postgres::Error) -> AuditError {
+    AuditError::Backend(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::clock::TestClock;
+
+    /// Fails its first `failures` writes, then keeps what it is given.
+    #[derive(Default)]
+    struct TestWriter {
+        failures: AtomicUsize,
+        batches: Mutex<Vec<Vec<AuditEvent>>>,
+        pruned_before: Mutex<Vec<u64>>,
+    }
+
+    impl TestWriter {
+        fn batches(&self) -> Vec<Vec<AuditEvent>> {
+            self.batches.lock().unwrap().clone()
+        }
+    }
+
+    impl AuditWriter for TestWriter {
+        fn write<'a>(&'a self, events: &'a [AuditEvent]) -> BoxFuture<'a, Result<(), AuditError>> {
+            Box::pin(async move {
+                let failing = self
+                    .failures
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                    .is_ok();
+                if failing {
+                    return Err(AuditError::Backend("down".into()));
+                }
+                self.batches.lock().unwrap().push(events.to_vec());
+                Ok(())
+            })
+        }
+
+        fn prune(&self, before: u64) -> BoxFuture<'_, Result<u64, AuditError>> {
+            self.pruned_before.lock().unwrap().push(before);
+            Box::pin(async { Ok(0) })
+        }
+    }
+
+    fn event(n: usize) -> AuditEvent {
+        AuditEvent {
+            at: n as u64,
+            action: "vault.read",
+            resource: n.to_string(),
+            client: None,
+            success: true,
+        }
+    }
+
+    async fn settle(writer: &TestWriter, events: usize) {
+        while writer.batches().iter().map(Vec::len).sum::<usize>() < events {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn events_are_written_in_batches() {
+        let writer = Arc::new(TestWriter::default());
+        let settings = BatchSettings {
+            batch_size: 4,
+            ..BatchSettings::default()
+        };
+        let sink = BatchingAuditSink::spawn(writer.clone(), settings);
+        for n in 0..10 {
+            sink.record_async(event(n)).await;
+        }
+        settle(&writer, 10).await;
+        let sizes: Vec<_> = writer.batches().iter().map(Vec::len).collect();
+        assert_eq!(sizes, [4, 4, 2]);
+        let written: Vec<_> = writer.batches().concat();
+        assert_eq!(written, (0..10).map(event).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_writes_are_retried() {
+        let writer = Arc::new(TestWriter {
+            failures: AtomicUsize::new(3),
+            ..TestWriter::default()
+        });
+        let sink = BatchingAuditSink::spawn(writer.clone(), BatchSettings::default());
+        sink.record(event(0));
+        settle(&writer, 1).await;
+        assert_eq!(writer.batches(), [vec![event(0)]]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn full_queues_drop_synchronous_records() {
+        let writer = Arc::new(TestWriter {
+            failures: AtomicUsize::new(usize::MAX),
+            ..TestWriter::default()
+        });
+        let settings = BatchSettings {
+            batch_size: 1,
+            queue_capacity: 2,
+            ..BatchSettings::default()
+        };
+        let sink = BatchingAuditSink::spawn(writer, settings);
+        // The first event is taken by the stuck writer, two more fill the
+        // queue.
+        sink.record(event(0));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        for n in 1..5 {
+            sink.record(event(n));
+        }
+        assert_eq!(sink.dropped(), 2);
+        let waiting = tokio::time::timeout(Duration::from_secs(60), sink.record_async(event(5)));
+        assert!(waiting.await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn old_events_are_pruned() {
+        let writer = Arc::new(TestWriter::default());
+        let settings = BatchSettings {
+            retention: Some(Duration::from_secs(86_400)),
+            prune_interval: Duration::from_secs(60),
+            clock: Arc::new(TestClock::new(100_000)),
+            ..BatchSettings::default()
+        };
+        let sink = BatchingAuditSink::spawn(writer.clone(), settings);
+        tokio::time::sleep(Duration::from_secs(150)).await;
+        let pruned = writer.pruned_before.lock().unwrap().clone();
+        assert_eq!(pruned, [13_600; 3]);
+
+        drop(sink);
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        assert_eq!(writer.pruned_before.lock().unwrap().len(), 3);
+    }
+}
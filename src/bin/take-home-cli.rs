@@ -0,0 +1,607 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand};
+use serde_json::{Value, json};
+
+use take_home::crypto::base64::Base64Encryptor;
+#[cfg(feature = "config-encryption")]
+use take_home::crypto::config_value::{self, ConfigCipher};
+use take_home::crypto::encryptor::{ENCRYPTION_KEY_ID, decrypt_fields, encrypt_fields};
+#[cfg(feature = "escrow")]
+use take_home::crypto::escrow::{EscrowBundle, KeyMaterial};
+use take_home::crypto::hmac::HMacSigner;
+use take_home::crypto::jwk::{Jwk, JwkSet};
+#[cfg(feature = "asymmetric")]
+use take_home::crypto::keys::{KeyType, PrivateKey};
+use take_home::crypto::migrate::{LEGACY_ALGORITHM, Migration};
+use take_home::crypto::signer::Signer;
+
+/// Offline counterpart of the HTTP API: runs the same crypto code against
+/// local files or stdin, without a server.
+#[derive(Parser)]
+#[command(name = "take-home-cli", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypt every top-level property of a JSON document
+    Encrypt(Input),
+    /// Decrypt every top-level property of a JSON document
+    Decrypt(Input),
+    /// Print the signature of a JSON object
+    Sign {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Check a `{"signature": ..., "data": {...}}` document; exits with 1 if
+    /// the signature is invalid
+    Verify {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Record the base64 values of NDJSON documents, one per line, in
+    /// `_crypto` sidecars, so the server's `/encryption/migrate` can move
+    /// them to another encryptor; with `--dry-run`, print what would be done
+    /// and exit with 1 if any document cannot be migrated
+    Migrate(MigrateArgs),
+    /// Convert keys to and from JSON Web Keys
+    #[command(subcommand)]
+    Jwk(JwkCommand),
+    /// Generate a signing keypair as PEM and JWK files and print the server
+    /// settings that load it
+    #[cfg(feature = "asymmetric")]
+    Keygen(KeygenArgs),
+    /// Print a new BIP39 mnemonic to derive signing keys from with
+    /// `keygen --mnemonic-file`
+    #[cfg(feature = "hd-keys")]
+    Mnemonic {
+        /// Number of words: 12, 15, 18, 21 or 24
+        #[arg(long, default_value_t = 24)]
+        words: usize,
+    },
+    /// Create escrow keys and recover signing keys exported to them
+    #[cfg(feature = "escrow")]
+    #[command(subcommand)]
+    Escrow(EscrowCommand),
+    /// Encrypt secrets into `enc:v1:` values the server decrypts from its
+    /// config file with CONFIG_MASTER_KEY
+    #[cfg(feature = "config-encryption")]
+    #[command(subcommand)]
+    ConfigValue(ConfigValueCommand),
+}
+
+#[cfg(feature = "config-encryption")]
+#[derive(Subcommand)]
+enum ConfigValueCommand {
+    /// Print a new random master key
+    Keygen,
+    /// Encrypt a secret (trailing newline is ignored) and print the value to
+    /// put in the config file
+    Encrypt {
+        #[command(flatten)]
+        input: TextInput,
+        #[command(flatten)]
+        key: MasterKeyArgs,
+    },
+    /// Print the plaintext of an `enc:v1:` value
+    Decrypt {
+        #[command(flatten)]
+        input: TextInput,
+        #[command(flatten)]
+        key: MasterKeyArgs,
+    },
+}
+
+#[cfg(feature = "config-encryption")]
+#[derive(Args)]
+struct TextInput {
+    /// File to read; reads stdin when omitted or `-`
+    file: Option<PathBuf>,
+}
+
+#[cfg(feature = "config-encryption")]
+#[derive(Args)]
+struct MasterKeyArgs {
+    /// Master key the server is given as CONFIG_MASTER_KEY
+    #[arg(long, env = "CONFIG_MASTER_KEY", hide_env_values = true)]
+    master_key: Option<String>,
+    /// File containing the master key (trailing newline is ignored)
+    #[arg(long, env = "CONFIG_MASTER_KEY_FILE", conflicts_with = "master_key")]
+    master_key_file: Option<PathBuf>,
+}
+
+#[cfg(feature = "escrow")]
+#[derive(Subcommand)]
+enum EscrowCommand {
+    /// Generate an X25519 escrow keypair: writes the private JWK to
+    /// `<name>.jwk` and prints the `[escrow.keys]` entry for the server
+    Keygen {
+        /// Name the server refers to the key by, also the file name stem
+        #[arg(long, default_value = "escrow")]
+        name: String,
+        /// Directory the key is written to; existing files are never
+        /// overwritten
+        #[arg(long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+    /// Open a bundle from `POST /keys/escrow` and write the signing key to
+    /// `<kid>.jwk` (HMAC) or `<kid>.pem` (asymmetric), ready to be loaded by
+    /// the server
+    Import {
+        #[command(flatten)]
+        bundle: Input,
+        /// Private escrow JWK written by `escrow keygen`
+        #[arg(long)]
+        escrow_key: PathBuf,
+        /// Directory the signing key is written to; existing files are never
+        /// overwritten
+        #[arg(long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+}
+
+#[cfg(feature = "asymmetric")]
+#[derive(Args)]
+struct KeygenArgs {
+    /// Key type: `ed25519`, `p256` or `rsa`
+    #[arg(long = "type", default_value = "ed25519", value_parser = parse_key_type)]
+    key_type: KeyType,
+    /// RSA modulus size in bits
+    #[arg(long, default_value_t = 2048)]
+    bits: usize,
+    /// Derive an Ed25519 key from the BIP39 mnemonic in this file instead of
+    /// generating a random one; the same mnemonic and path always give the
+    /// same key
+    #[cfg(feature = "hd-keys")]
+    #[arg(long, requires = "path", conflicts_with_all = ["key_type", "bits"])]
+    mnemonic_file: Option<PathBuf>,
+    /// SLIP-0010 derivation path, hardened indices only, e.g. `m/44'/0'/3'`
+    #[cfg(feature = "hd-keys")]
+    #[arg(long, requires = "mnemonic_file")]
+    path: Option<String>,
+    /// BIP39 passphrase of the mnemonic
+    #[cfg(feature = "hd-keys")]
+    #[arg(
+        long,
+        env = "MNEMONIC_PASSPHRASE",
+        hide_env_values = true,
+        default_value = ""
+    )]
+    passphrase: String,
+    /// `kid` of the key, also used as the file name stem
+    #[arg(long, default_value = "signing")]
+    kid: String,
+    /// Directory the key files are written to; existing files are never
+    /// overwritten
+    #[arg(long, default_value = ".")]
+    out_dir: PathBuf,
+}
+
+#[cfg(feature = "asymmetric")]
+fn parse_key_type(value: &str) -> Result<KeyType, String> {
+    value
+        .parse()
+        .map_err(|_| format!("unknown key type `{value}`"))
+}
+
+#[derive(Subcommand)]
+enum JwkCommand {
+    /// Print the public keys of a JWK or JWK Set as a JWK Set (symmetric keys
+    /// are left out)
+    Public(Input),
+    /// Print the HMAC secret as an `oct` JWK
+    Export {
+        #[command(flatten)]
+        key: KeyArgs,
+        /// `kid` of the exported key
+        #[arg(long)]
+        kid: Option<String>,
+    },
+}
+
+#[derive(Args)]
+struct MigrateArgs {
+    /// NDJSON file to read; reads stdin when omitted or `-`
+    file: Option<PathBuf>,
+    /// JSON Pointer of a base64 value in the documents without a `_crypto`
+    /// sidecar; may be repeated
+    #[arg(long = "pointer", value_name = "POINTER")]
+    pointers: Vec<String>,
+    /// Check every document and count its base64 values, without writing
+    /// any
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct Input {
+    /// JSON file to read; reads stdin when omitted or `-`
+    file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct KeyArgs {
+    /// Secret key used for HMAC signing
+    #[arg(long, env = "HMAC_SECRET", hide_env_values = true)]
+    secret: Option<String>,
+    /// File containing the HMAC secret (trailing newline is ignored)
+    #[arg(long, env = "HMAC_SECRET_FILE", conflicts_with = "secret")]
+    secret_file: Option<PathBuf>,
+    /// JWK file holding the HMAC secret as a single `oct` key
+    #[arg(long, conflicts_with_all = ["secret", "secret_file"])]
+    jwk_file: Option<PathBuf>,
+}
+
+impl Input {
+    fn read(&self) -> Result<Value, String> {
+        let contents = read_input(self.file.as_deref())?;
+        serde_json::from_str(&contents).map_err(|e| format!("invalid JSON input: {e}"))
+    }
+}
+
+#[cfg(feature = "config-encryption")]
+impl TextInput {
+    fn read(&self) -> Result<String, String> {
+        Ok(read_input(self.file.as_deref())?
+            .trim_end_matches(['\r', '\n'])
+            .to_string())
+    }
+}
+
+#[cfg(feature = "config-encryption")]
+impl MasterKeyArgs {
+    fn cipher(&self) -> Result<ConfigCipher, String> {
+        let key = match (&self.master_key, &self.master_key_file) {
+            (Some(key), _) => key.clone(),
+            (None, Some(path)) => read(path)?.trim_end_matches(['\r', '\n']).to_string(),
+            (None, None) => {
+                return Err("no master key: pass --master-key or --master-key-file".into());
+            }
+        };
+        if key.is_empty() {
+            return Err("the master key is empty".into());
+        }
+        Ok(ConfigCipher::new(key.as_bytes()))
+    }
+}
+
+/// Reads `file`, or stdin when it is `None` or `-`.
+fn read_input(file: Option<&Path>) -> Result<String, String> {
+    match file {
+        Some(path) if path.as_os_str() != "-" => read(path),
+        _ => {
+            let mut contents = String::new();
+            std::io::stdin()
+                .read_to_string(&mut contents)
+                .map_err(|e| format!("failed to read stdin: {e}"))?;
+            Ok(contents)
+        }
+    }
+}
+
+impl KeyArgs {
+    fn secret(&self) -> Result<Vec<u8>, String> {
+        let secret = match (&self.secret, &self.secret_file, &self.jwk_file) {
+            (Some(secret), _, _) => secret.clone().into_bytes(),
+            (None, Some(path), _) => read(path)?
+                .trim_end_matches(['\r', '\n'])
+                .to_string()
+                .into_bytes(),
+            (None, None, Some(path)) => JwkSet::parse(&read(path)?)
+                .and_then(|set| set.find(None)?.symmetric_key())
+                .map_err(|e| format!("{}: {e}", path.display()))?,
+            (None, None, None) => {
+                return Err("no HMAC secret: pass --secret, --secret-file or --jwk-file".into());
+            }
+        };
+        if secret.is_empty() {
+            return Err("the HMAC secret is empty".into());
+        }
+        Ok(secret)
+    }
+
+    fn signer(&self) -> Result<HMacSigner, String> {
+        Ok(HMacSigner::new(self.secret()?))
+    }
+}
+
+/// Documents between two progress lines of `migrate`.
+const MIGRATION_PROGRESS_EVERY: usize = 1000;
+
+/// Streams the documents of `args.file` through a [`Migration`] to base64,
+/// one line at a time, writing them to stdout as NDJSON.
+fn migrate(args: MigrateArgs) -> Result<bool, String> {
+    let reader: Box<dyn BufRead> = match &args.file {
+        Some(path) if path.as_os_str() != "-" => {
+            Box::new(BufReader::new(File::open(path).map_err(|e| {
+                format!("failed to read {}: {e}", path.display())
+            })?))
+        }
+        _ => Box::new(std::io::stdin().lock()),
+    };
+    let migration = Migration::new(LEGACY_ALGORITHM, ENCRYPTION_KEY_ID).pointers(args.pointers);
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let (mut documents, mut values, mut failures) = (0, 0, Vec::new());
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("failed to read line {}: {e}", index + 1))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        documents += 1;
+        let result = serde_json::from_str(&line)
+            .map_err(|e| format!("invalid JSON: {e}"))
+            .and_then(|mut document| {
+                let migrated = migration
+                    .migrate(&Base64Encryptor, &mut document, args.dry_run)
+                    .map_err(|e| e.to_string())?;
+                Ok((document, migrated))
+            });
+        match result {
+            Ok((document, migrated)) => {
+                values += migrated;
+                if !args.dry_run {
+                    writeln!(out, "{document}").map_err(|e| format!("failed to write: {e}"))?;
+                }
+            }
+            Err(err) if args.dry_run => failures.push(json!({ "line": index + 1, "error": err })),
+            Err(err) => return Err(format!("line {}: {err}", index + 1)),
+        }
+        if documents % MIGRATION_PROGRESS_EVERY == 0 {
+            eprintln!("{documents} documents, {values} values");
+        }
+    }
+    out.flush().map_err(|e| format!("failed to write: {e}"))?;
+    if args.dry_run {
+        let report = json!({ "documents": documents, "values": values, "failures": failures });
+        println!("{report}");
+        return Ok(failures.is_empty());
+    }
+    Ok(true)
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))
+}
+
+/// Writes `<kid>.pem`, `<kid>.pub.pem`, `<kid>.jwk` and `<kid>.pub.jwk`.
+/// Private files are created with mode 0600 on Unix.
+#[cfg(feature = "asymmetric")]
+fn keygen(args: KeygenArgs) -> Result<Value, String> {
+    let key_type = match args.key_type {
+        KeyType::Rsa { .. } => KeyType::Rsa { bits: args.bits },
+        other => other,
+    };
+    #[cfg(feature = "hd-keys")]
+    let key = match (&args.mnemonic_file, &args.path) {
+        (Some(file), Some(path)) => derive_key(file, path, &args.passphrase)?,
+        _ => PrivateKey::generate(key_type).map_err(|e| e.to_string())?,
+    };
+    #[cfg(not(feature = "hd-keys"))]
+    let key = PrivateKey::generate(key_type).map_err(|e| e.to_string())?;
+    let jwk = Jwk::from_private_key(Some(args.kid.clone()), &key);
+    let public_jwk = jwk.to_public().map_err(|e| e.to_string())?;
+    let pretty = |value: &Jwk| serde_json::to_string_pretty(value).unwrap() + "\n";
+
+    let dir = std::path::absolute(&args.out_dir)
+        .map_err(|e| format!("invalid --out-dir {}: {e}", args.out_dir.display()))?;
+    let path = |suffix: &str| dir.join(format!("{}{suffix}", args.kid));
+    let files = [
+        (
+            path(".pem"),
+            key.to_pkcs8_pem().map_err(|e| e.to_string())?,
+            true,
+        ),
+        (
+            path(".pub.pem"),
+            key.public_key_pem().map_err(|e| e.to_string())?,
+            false,
+        ),
+        (path(".jwk"), pretty(&jwk), true),
+        (path(".pub.jwk"), pretty(&public_jwk), false),
+    ];
+    if let Some((existing, _, _)) = files.iter().find(|(path, _, _)| path.exists()) {
+        return Err(format!("{} already exists", existing.display()));
+    }
+    for (path, contents, private) in &files {
+        write_new(path, contents, *private)
+            .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    }
+
+    let private_key = files[0].0.display();
+    Ok(json!({
+        "algorithm": key.algorithm(),
+        "kid": args.kid,
+        "files": {
+            "private_key": files[0].0,
+            "public_key": files[1].0,
+            "jwk": files[2].0,
+            "public_jwk": files[3].0,
+        },
+        "public_jwk": public_jwk,
+        "env": format!("SIGNING_KEY_FILE={private_key}"),
+        "config": format!("[signing]\nprivate_key_file = \"{private_key}\"\n"),
+    }))
+}
+
+/// Writes `<name>.jwk` holding a fresh X25519 secret key.
+#[cfg(feature = "escrow")]
+fn escrow_keygen(name: &str, out_dir: &Path) -> Result<Value, String> {
+    use base64::Engine as _;
+    use base64::engine::general_purpose::STANDARD;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    let secret = StaticSecret::random_from_rng(rand_core::OsRng);
+    let public = STANDARD.encode(PublicKey::from(&secret).as_bytes());
+    let jwk = Jwk::from_x25519_secret(Some(name.to_string()), &secret);
+    let path = absolute(out_dir)?.join(format!("{name}.jwk"));
+    write_new(
+        &path,
+        &(serde_json::to_string_pretty(&jwk).unwrap() + "\n"),
+        true,
+    )
+    .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    Ok(json!({
+        "name": name,
+        "public_key": public,
+        "files": { "private_jwk": path },
+        "config": format!("[escrow.keys]\n{name} = \"{public}\"\n"),
+    }))
+}
+
+/// Opens an escrow bundle and writes the key it holds in the format the
+/// server loads it from.
+#[cfg(feature = "escrow")]
+fn escrow_import(bundle: &Input, escrow_key: &Path, out_dir: &Path) -> Result<Value, String> {
+    let bundle: EscrowBundle =
+        serde_json::from_value(bundle.read()?).map_err(|e| format!("invalid bundle: {e}"))?;
+    let secret = JwkSet::parse(&read(escrow_key)?)
+        .and_then(|set| set.find(None)?.x25519_secret_key())
+        .map_err(|e| format!("{}: {e}", escrow_key.display()))?;
+    let key = take_home::crypto::escrow::unwrap(&bundle, &secret).map_err(|e| e.to_string())?;
+
+    let dir = absolute(out_dir)?;
+    let (path, contents, setting) = match &key.material {
+        KeyMaterial::Jwk(jwk) => (
+            dir.join(format!("{}.jwk", key.kid)),
+            serde_json::to_string_pretty(jwk).unwrap() + "\n",
+            "jwk_file",
+        ),
+        KeyMaterial::Pkcs8Pem(pem) => (
+            dir.join(format!("{}.pem", key.kid)),
+            pem.clone(),
+            "private_key_file",
+        ),
+    };
+    write_new(&path, &contents, true)
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    Ok(json!({
+        "alg": key.alg,
+        "kid": key.kid,
+        "files": { "key": path },
+        "config": format!(
+            "[signing]\nkey_id = \"{}\"\n{setting} = \"{}\"\n",
+            key.kid,
+            path.display()
+        ),
+    }))
+}
+
+#[cfg(feature = "escrow")]
+fn absolute(dir: &Path) -> Result<PathBuf, String> {
+    std::path::absolute(dir).map_err(|e| format!("invalid --out-dir {}: {e}", dir.display()))
+}
+
+/// The Ed25519 key at `path` below the mnemonic stored in `file`.
+#[cfg(feature = "hd-keys")]
+fn derive_key(file: &Path, path: &str, passphrase: &str) -> Result<PrivateKey, String> {
+    use take_home::crypto::hd::{DerivationPath, Seed};
+
+    let seed = Seed::from_mnemonic(read(file)?.trim(), passphrase)
+        .map_err(|e| format!("{}: {e}", file.display()))?;
+    let path: DerivationPath = path.parse().map_err(|e| format!("{e}"))?;
+    Ok(seed.derive_ed25519(&path))
+}
+
+#[cfg(any(feature = "asymmetric", feature = "escrow"))]
+fn write_new(path: &Path, contents: &str, private: bool) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(if private { 0o600 } else { 0o644 });
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+fn run(command: Command) -> Result<bool, String> {
+    let output = match command {
+        Command::Encrypt(input) => {
+            encrypt_fields(&Base64Encryptor, input.read()?).map_err(|e| e.to_string())?
+        }
+        Command::Decrypt(input) => {
+            decrypt_fields(&Base64Encryptor, input.read()?).map_err(|e| e.to_string())?
+        }
+        Command::Sign { input, key } => {
+            let signer = key.signer()?;
+            match input.read()? {
+                Value::Object(map) => json!({ "signature": signer.sign(&map) }),
+                _ => return Err("the document to sign must be a JSON object".into()),
+            }
+        }
+        Command::Verify { input, key } => {
+            let signer = key.signer()?;
+            let payload = input.read()?;
+            let signature = payload.get("signature").and_then(|s| s.as_str());
+            return match (signature, payload.get("data")) {
+                (Some(sig), Some(Value::Object(map))) => {
+                    let valid = signer.verify(map, sig);
+                    eprintln!("{}", if valid { "valid" } else { "invalid" });
+                    Ok(valid)
+                }
+                _ => Err("expected {\"signature\": string, \"data\": object}".into()),
+            };
+        }
+        Command::Migrate(args) => return migrate(args),
+        Command::Jwk(JwkCommand::Public(input)) => {
+            let set = JwkSet::parse(&input.read()?.to_string()).map_err(|e| e.to_string())?;
+            json!(set.to_public())
+        }
+        Command::Jwk(JwkCommand::Export { key, kid }) => json!(Jwk::symmetric(kid, &key.secret()?)),
+        #[cfg(feature = "asymmetric")]
+        Command::Keygen(args) => keygen(args)?,
+        #[cfg(feature = "hd-keys")]
+        Command::Mnemonic { words } => {
+            json!({ "mnemonic": take_home::crypto::hd::generate_mnemonic(words).map_err(|e| e.to_string())? })
+        }
+        #[cfg(feature = "escrow")]
+        Command::Escrow(EscrowCommand::Keygen { name, out_dir }) => escrow_keygen(&name, &out_dir)?,
+        #[cfg(feature = "escrow")]
+        Command::Escrow(EscrowCommand::Import {
+            bundle,
+            escrow_key,
+            out_dir,
+        }) => escrow_import(&bundle, &escrow_key, &out_dir)?,
+        #[cfg(feature = "config-encryption")]
+        Command::ConfigValue(ConfigValueCommand::Keygen) => {
+            let key = config_value::generate_master_key();
+            json!({ "master_key": key, "env": format!("CONFIG_MASTER_KEY={key}") })
+        }
+        #[cfg(feature = "config-encryption")]
+        Command::ConfigValue(ConfigValueCommand::Encrypt { input, key }) => {
+            json!({ "value": key.cipher()?.encrypt(&input.read()?) })
+        }
+        #[cfg(feature = "config-encryption")]
+        Command::ConfigValue(ConfigValueCommand::Decrypt { input, key }) => {
+            let value = key.cipher()?.decrypt(input.read()?.trim());
+            json!({ "value": value.map_err(|e| e.to_string())? })
+        }
+    };
+    println!("{output}");
+    Ok(true)
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! Content-addressable blob storage behind `PUT /blobs` and
+//! `GET /blobs/{hash}`. A blob is addressed by the SHA-256 of its
+//! plaintext, encrypted at rest with the configured encryptor, and hashed
+//! again after every read so a corrupted or swapped blob is never served
+//! under an address it does not match.
+//!
+//! With [`ChunkedSealing`], blobs are instead sealed in fixed-size
+//! ChaCha20-Poly1305 chunks bound to their address (see
+//! [`crate::crypto::stream`]), and a byte range is read by opening only
+//! the chunks it covers.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::crypto::BoxFuture;
+use crate::crypto::encryptor::{AsyncEncryptor, DecryptError, EncryptError};
+use crate::crypto::stream::{self, HEADER_LEN, StreamError, StreamHeader, StreamKey};
+use crate::crypto::{codec, constant_time};
+
+/// HKDF info deriving the chunk key from `blobs.key`.
+const BLOB_KEY_INFO: &[u8] = b"take-home blobs v1";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlobError {
+    #[error("blob address must be 64 lowercase hex digits")]
+    InvalidAddress,
+    #[error("no blob with address {0}")]
+    NotFound(BlobAddress),
+    #[error("stored blob {0} does not match its address")]
+    Corrupted(BlobAddress),
+    #[error(transparent)]
+    Encrypt(#[from] EncryptError),
+    #[error(transparent)]
+    Decrypt(DecryptError),
+    #[error("blob storage failed: {0}")]
+    Storage(#[from] std::io::Error),
+    #[error("blob {0} is sealed in chunks, but no blob key is configured")]
+    NoChunkKey(BlobAddress),
+    #[error(transparent)]
+    TooLarge(StreamError),
+    #[error("range not satisfiable for a blob of {0} bytes")]
+    RangeNotSatisfiable(u64),
+}
+
+/// SHA-256 of a blob's plaintext, written as lowercase hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlobAddress([u8; 32]);
+
+impl BlobAddress {
+    pub fn of(bytes: &[u8]) -> Self {
+        Self(Sha256::digest(bytes).into())
+    }
+}
+
+impl fmt::Display for BlobAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+impl FromStr for BlobAddress {
+    type Err = BlobError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digit = |c: u8| match c {
+            b'0'..=b'9' => Some(c - b'0'),
+            b'a'..=b'f' => Some(c - b'a' + 10),
+            _ => None,
+        };
+        if s.len() != 64 {
+            return Err(BlobError::InvalidAddress);
+        }
+        let mut address = [0u8; 32];
+        for (byte, pair) in address.iter_mut().zip(s.as_bytes().chunks(2)) {
+            *byte = digit(pair[0])
+                .zip(digit(pair[1]))
+                .map(|(hi, lo)| hi << 4 | lo)
+                .ok_or(BlobError::InvalidAddress)?;
+        }
+        Ok(Self(address))
+    }
+}
+
+/// Where sealed blobs are kept. Stores only ever see ciphertext.
+pub trait BlobStore: Send + Sync {
+    /// Stores `sealed` under `address` unless a blob is already there.
+    /// Returns whether it was stored.
+    fn put<'a>(
+        &'a self,
+        address: BlobAddress,
+        sealed: Vec<u8>,
+    ) -> BoxFuture<'a, Result<bool, BlobError>>;
+    fn get<'a>(&'a self, address: BlobAddress)
+    -> BoxFuture<'a, Result<Option<Vec<u8>>, BlobError>>;
+
+    /// The bytes of the blob at `address` within `range`, fewer if it ends
+    /// sooner. Reads the whole blob unless the store can seek.
+    fn get_range<'a>(
+        &'a self,
+        address: BlobAddress,
+        range: Range<u64>,
+    ) -> BoxFuture<'a, Result<Option<Vec<u8>>, BlobError>> {
+        Box::pin(async move {
+            let blob = self.get(address).await?;
+            Ok(blob.map(|blob| clamp(&blob, range).to_vec()))
+        })
+    }
+}
+
+/// The part of `bytes` within `range`.
+fn clamp(bytes: &[u8], range: Range<u64>) -> &[u8] {
+    let end = usize::try_from(range.end).map_or(bytes.len(), |end| end.min(bytes.len()));
+    let start = usize::try_from(range.start).map_or(end, |start| start.min(end));
+    &bytes[start..end]
+}
+
+/// Keeps blobs in memory; they are lost on restart.
+#[derive(Default)]
+pub struct MemoryBlobStore {
+    blobs: RwLock<HashMap<BlobAddress, Vec<u8>>>,
+}
+
+impl MemoryBlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BlobStore for MemoryBlobStore {
+    fn put<'a>(
+        &'a self,
+        address: BlobAddress,
+        sealed: Vec<u8>,
+    ) -> BoxFuture<'a, Result<bool, BlobError>> {
+        let mut blobs = self.blobs.write().unwrap_or_else(|e| e.into_inner());
+        let created = !blobs.contains_key(&address);
+        if created {
+            blobs.insert(address, sealed);
+        }
+        Box::pin(async move { Ok(created) })
+    }
+
+    fn get<'a>(
+        &'a self,
+        address: BlobAddress,
+    ) -> BoxFuture<'a, Result<Option<Vec<u8>>, BlobError>> {
+        let blob = self
+            .blobs
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&address)
+            .cloned();
+        Box::pin(async move { Ok(blob) })
+    }
+
+    fn get_range<'a>(
+        &'a self,
+        address: BlobAddress,
+        range: Range<u64>,
+    ) -> BoxFuture<'a, Result<Option<Vec<u8>>, BlobError>> {
+        let part = self
+            .blobs
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&address)
+            .map(|blob| clamp(blob, range).to_vec());
+        Box::pin(async move { Ok(part) })
+    }
+}
+
+/// Keeps each blob in a file named after its address. Files are written
+/// under a temporary name and renamed, so readers never see partial blobs.
+pub struct DirBlobStore {
+    dir: PathBuf,
+}
+
+impl DirBlobStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl BlobStore for DirBlobStore {
+    fn put<'a>(
+        &'a self,
+        address: BlobAddress,
+        sealed: Vec<u8>,
+    ) -> BoxFuture<'a, Result<bool, BlobError>> {
+        Box::pin(async move {
+            let path = self.dir.join(address.to_string());
+            if tokio::fs::try_exists(&path).await? {
+                return Ok(false);
+            }
+            tokio::fs::create_dir_all(&self.dir).await?;
+            let partial = self.dir.join(format!(".{address}.{}", std::process::id()));
+            tokio::fs::write(&partial, sealed).await?;
+            tokio::fs::rename(&partial, &path).await?;
+            Ok(true)
+        })
+    }
+
+    fn get<'a>(
+        &'a self,
+        address: BlobAddress,
+    ) -> BoxFuture<'a, Result<Option<Vec<u8>>, BlobError>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.dir.join(address.to_string())).await {
+                Ok(sealed) => Ok(Some(sealed)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        })
+    }
+
+    fn get_range<'a>(
+        &'a self,
+        address: BlobAddress,
+        range: Range<u64>,
+    ) -> BoxFuture<'a, Result<Option<Vec<u8>>, BlobError>> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        Box::pin(async move {
+            let mut file = match tokio::fs::File::open(self.dir.join(address.to_string())).await {
+                Ok(file) => file,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            file.seek(std::io::SeekFrom::Start(range.start)).await?;
+            let mut part = Vec::new();
+            file.take(range.end.saturating_sub(range.start))
+                .read_to_end(&mut part)
+                .await?;
+            Ok(Some(part))
+        })
+    }
+}
+
+/// Seals blobs in fixed-size chunks under a key derived from `blobs.key`.
+pub struct ChunkedSealing {
+    key: StreamKey,
+    chunk_size: u32,
+}
+
+impl ChunkedSealing {
+    pub fn new(secret: &[u8], chunk_size: u32) -> Self {
+        Self {
+            key: StreamKey::derive(secret, BLOB_KEY_INFO),
+            chunk_size,
+        }
+    }
+}
+
+/// A byte range asked of a blob, as in an HTTP `Range: bytes=` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `first-last`, both inclusive.
+    Bounded { first: u64, last: u64 },
+    /// `first-`: to the end.
+    From(u64),
+    /// `-length`: the last `length` bytes.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Parses a `Range` header naming a single byte range. Anything else,
+    /// including several ranges, is `None`, and the whole blob is served.
+    pub fn parse(header: &str) -> Option<Self> {
+        let spec = header.trim().strip_prefix("bytes=")?;
+        let (first, last) = spec.trim().split_once('-')?;
+        let number = |digits: &str| match digits.bytes().all(|b| b.is_ascii_digit()) {
+            true => digits.parse::<u64>().ok(),
+            false => None,
+        };
+        match (first.is_empty(), last.is_empty()) {
+            (true, false) => number(last).map(Self::Suffix),
+            (false, true) => number(first).map(Self::From),
+            (false, false) => {
+                let (first, last) = (number(first)?, number(last)?);
+                (first <= last).then_some(Self::Bounded { first, last })
+            }
+            (true, true) => None,
+        }
+    }
+
+    /// The bytes of a `length`-byte blob this selects, or `None` if it
+    /// selects none.
+    pub fn resolve(self, length: u64) -> Option<Range<u64>> {
+        let range = match self {
+            Self::Bounded { first, last } => first..last.saturating_add(1).min(length),
+            Self::From(first) => first..length,
+            Self::Suffix(suffix) => length.saturating_sub(suffix)..length,
+        };
+        (range.start < range.end).then_some(range)
+    }
+}
+
+/// Part of a blob read by [`get_blob_range`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobPart {
+    pub bytes: Vec<u8>,
+    /// Where `bytes` lie in the blob.
+    pub range: Range<u64>,
+    /// Bytes in the whole blob.
+    pub length: u64,
+}
+
+/// Encrypts `bytes` and stores them under their address. Returns the
+/// address and whether the blob was new.
+pub async fn put_blob(
+    store: &dyn BlobStore,
+    encryptor: &dyn AsyncEncryptor,
+    chunked: Option<&ChunkedSealing>,
+    bytes: &[u8],
+) -> Result<(BlobAddress, bool), BlobError> {
+    let address = BlobAddress::of(bytes);
+    let sealed = match chunked {
+        Some(chunked) => stream::seal(&chunked.key, &address.0, bytes, chunked.chunk_size)
+            .map_err(BlobError::TooLarge)?,
+        None => {
+            let sealed = encryptor
+                .encrypt(&Value::String(codec::encode(bytes)))
+                .await?;
+            serde_json::to_vec(&sealed).map_err(EncryptError::from)?
+        }
+    };
+    let created = store.put(address, sealed).await?;
+    Ok((address, created))
+}
+
+/// Loads and decrypts the blob at `address`, checking that its plaintext
+/// still hashes to it.
+pub async fn get_blob(
+    store: &dyn BlobStore,
+    encryptor: &dyn AsyncEncryptor,
+    chunked: Option<&ChunkedSealing>,
+    address: BlobAddress,
+) -> Result<Vec<u8>, BlobError> {
+    let corrupted = || BlobError::Corrupted(address);
+    let sealed = store
+        .get(address)
+        .await?
+        .ok_or(BlobError::NotFound(address))?;
+    let plaintext = if StreamHeader::is_stream(&sealed) {
+        let chunked = chunked.ok_or(BlobError::NoChunkKey(address))?;
+        stream::open(&chunked.key, &address.0, &sealed).map_err(|_| corrupted())?
+    } else {
+        let sealed: Value = serde_json::from_slice(&sealed).map_err(|_| corrupted())?;
+        match encryptor.decrypt(&sealed).await {
+            Ok(Value::String(encoded)) => codec::decode(&encoded).ok_or_else(corrupted)?,
+            Ok(_) | Err(DecryptError::NotCiphertext | DecryptError::AuthenticationFailed) => {
+                return Err(corrupted());
+            }
+            Err(err) => return Err(BlobError::Decrypt(err)),
+        }
+    };
+    if !constant_time::eq(&BlobAddress::of(&plaintext).0, &address.0) {
+        return Err(corrupted());
+    }
+    Ok(plaintext)
+}
+
+/// Reads `range` of a blob sealed in chunks, opening only the chunks it
+/// covers. Each chunk is authenticated, and bound to `address`, but the
+/// whole blob is not hashed again. `None` if the blob was sealed by the
+/// encryptor, which can only be read whole.
+pub async fn get_blob_range(
+    store: &dyn BlobStore,
+    chunked: Option<&ChunkedSealing>,
+    address: BlobAddress,
+    range: ByteRange,
+) -> Result<Option<BlobPart>, BlobError> {
+    let corrupted = |_| BlobError::Corrupted(address);
+    let header = store
+        .get_range(address, 0..HEADER_LEN as u64)
+        .await?
+        .ok_or(BlobError::NotFound(address))?;
+    if !StreamHeader::is_stream(&header) {
+        return Ok(None);
+    }
+    let chunked = chunked.ok_or(BlobError::NoChunkKey(address))?;
+    let header = StreamHeader::parse(&header).map_err(corrupted)?;
+    let range = range
+        .resolve(header.length)
+        .ok_or(BlobError::RangeNotSatisfiable(header.length))?;
+    let chunks = header.chunk_of(range.start)..=header.chunk_of(range.end - 1);
+    let first = header.sealed_range(*chunks.start()).start;
+    let last = header.sealed_range(*chunks.end()).end;
+    let sealed = store
+        .get_range(address, first..last)
+        .await?
+        .ok_or(BlobError::NotFound(address))?;
+    let mut bytes = Vec::with_capacity((range.end - range.start) as usize);
+    for index in chunks {
+        let sealed_range = header.sealed_range(index);
+        let chunk = clamp(
+            &sealed,
+            sealed_range.start - first..sealed_range.end - first,
+        );
+        let plaintext = stream::open_chunk(&chunked.key, &address.0, &header, index, chunk)
+            .map_err(corrupted)?;
+        let chunk_range = header.plaintext_range(index);
+        let wanted = range.start.max(chunk_range.start) - chunk_range.start
+            ..range.end.min(chunk_range.end) - chunk_range.start;
+        bytes.extend_from_slice(clamp(&plaintext, wanted));
+    }
+    Ok(Some(BlobPart {
+        bytes,
+        range,
+        length: header.length,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::base64::Base64Encryptor;
+
+    const HELLO: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn address_is_the_sha256_in_hex() {
+        let address = BlobAddress::of(b"hello");
+        assert_eq!(address.to_string(), HELLO);
+        assert_eq!(HELLO.parse::<BlobAddress>().unwrap(), address);
+    }
+
+    #[test]
+    fn malformed_addresses_are_refused() {
+        for bad in [
+            "",
+            "abc",
+            &HELLO.to_uppercase(),
+            &format!("{HELLO}00"),
+            &HELLO.replace('2', "g"),
+        ] {
+            assert!(
+                matches!(bad.parse::<BlobAddress>(), Err(BlobError::InvalidAddress)),
+                "{bad}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn blobs_round_trip_and_are_stored_encrypted() {
+        let store = MemoryBlobStore::new();
+        let (address, created) = put_blob(&store, &Base64Encryptor, None, b"hello")
+            .await
+            .unwrap();
+        assert!(created);
+        let sealed = store.get(address).await.unwrap().unwrap();
+        assert!(!sealed.windows(5).any(|w| w == b"hello"));
+        assert_eq!(
+            get_blob(&store, &Base64Encryptor, None, address)
+                .await
+                .unwrap(),
+            b"hello"
+        );
+
+        let (_, created) = put_blob(&store, &Base64Encryptor, None, b"hello")
+            .await
+            .unwrap();
+        assert!(!created);
+    }
+
+    #[tokio::test]
+    async fn swapped_blob_fails_its_integrity_check() {
+        let store = MemoryBlobStore::new();
+        let (other, _) = put_blob(&store, &Base64Encryptor, None, b"other")
+            .await
+            .unwrap();
+        let sealed = store.get(other).await.unwrap().unwrap();
+        let address = BlobAddress::of(b"hello");
+        store.put(address, sealed).await.unwrap();
+        assert!(matches!(
+            get_blob(&store, &Base64Encryptor, None, address).await,
+            Err(BlobError::Corrupted(a)) if a == address
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_blob_is_not_found() {
+        let address = BlobAddress::of(b"hello");
+        assert!(matches!(
+            get_blob(&MemoryBlobStore::new(), &Base64Encryptor, None, address).await,
+            Err(BlobError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn dir_store_persists_across_instances() {
+        let dir = std::env::temp_dir().join(format!("take-home-blobs-{}", std::process::id()));
+        let (address, _) = put_blob(&DirBlobStore::new(&dir), &Base64Encryptor, None, b"hello")
+            .await
+            .unwrap();
+        let read = get_blob(&DirBlobStore::new(&dir), &Base64Encryptor, None, address).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(read.unwrap(), b"hello");
+    }
+
+    fn chunked() -> ChunkedSealing {
+        ChunkedSealing::new(b"blob key", 4)
+    }
+
+    #[test]
+    fn byte_ranges_parse_and_resolve() {
+        let parse = |header| ByteRange::parse(header).and_then(|range| range.resolve(10));
+        assert_eq!(parse("bytes=2-5"), Some(2..6));
+        assert_eq!(parse("bytes=2-"), Some(2..10));
+        assert_eq!(parse("bytes=-3"), Some(7..10));
+        assert_eq!(parse("bytes=8-100"), Some(8..10));
+        assert_eq!(parse("bytes=-100"), Some(0..10));
+        assert_eq!(parse("bytes=10-"), None);
+        for ignored in [
+            "bytes=5-2",
+            "bytes=-",
+            "bytes=0-1,4-5",
+            "items=0-1",
+            "bytes=+1-2",
+        ] {
+            assert_eq!(ByteRange::parse(ignored), None, "{ignored}");
+        }
+    }
+
+    #[tokio::test]
+    async fn chunked_blobs_round_trip_and_read_ranges() {
+        let store = MemoryBlobStore::new();
+        let plaintext = b"the quick brown fox";
+        let (address, _) = put_blob(&store, &Base64Encryptor, Some(&chunked()), plaintext)
+            .await
+            .unwrap();
+        let sealed = store.get(address).await.unwrap().unwrap();
+        assert!(StreamHeader::is_stream(&sealed));
+        assert_eq!(
+            get_blob(&store, &Base64Encryptor, Some(&chunked()), address)
+                .await
+                .unwrap(),
+            plaintext
+        );
+
+        for (range, expected) in [
+            (ByteRange::Bounded { first: 5, last: 13 }, 5..14),
+            (ByteRange::Bounded { first: 0, last: 0 }, 0..1),
+            (ByteRange::From(16), 16..19),
+            (ByteRange::Suffix(4), 15..19),
+        ] {
+            let part = get_blob_range(&store, Some(&chunked()), address, range)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(part.range, expected);
+            assert_eq!(part.length, 19);
+            assert_eq!(
+                part.bytes,
+                &plaintext[expected.start as usize..expected.end as usize]
+            );
+        }
+        assert!(matches!(
+            get_blob_range(&store, Some(&chunked()), address, ByteRange::From(19)).await,
+            Err(BlobError::RangeNotSatisfiable(19))
+        ));
+    }
+
+    #[tokio::test]
+    async fn ranges_of_encryptor_sealed_blobs_are_not_read() {
+        let store = MemoryBlobStore::new();
+        let (address, _) = put_blob(&store, &Base64Encryptor, None, b"hello")
+            .await
+            .unwrap();
+        let part = get_blob_range(&store, Some(&chunked()), address, ByteRange::From(1)).await;
+        assert_eq!(part.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn tampered_chunks_fail_their_range_reads() {
+        let store = MemoryBlobStore::new();
+        let (address, _) = put_blob(&store, &Base64Encryptor, Some(&chunked()), b"0123456789")
+            .await
+            .unwrap();
+        let mut sealed = store.get(address).await.unwrap().unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        let store = MemoryBlobStore::new();
+        store.put(address, sealed).await.unwrap();
+        let range = |first, last| ByteRange::Bounded { first, last };
+        assert!(
+            get_blob_range(&store, Some(&chunked()), address, range(0, 7))
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            get_blob_range(&store, Some(&chunked()), address, range(0, 8)).await,
+            Err(BlobError::Corrupted(_))
+        ));
+        assert!(matches!(
+            get_blob(&store, &Base64Encryptor, Some(&chunked()), address).await,
+            Err(BlobError::Corrupted(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn chunked_blobs_need_the_key_to_be_read() {
+        let store = MemoryBlobStore::new();
+        let (address, _) = put_blob(&store, &Base64Encryptor, Some(&chunked()), b"hello")
+            .await
+            .unwrap();
+        assert!(matches!(
+            get_blob(&store, &Base64Encryptor, None, address).await,
+            Err(BlobError::NoChunkKey(_))
+        ));
+        let other = ChunkedSealing::new(b"other key", 4);
+        assert!(matches!(
+            get_blob(&store, &Base64Encryptor, Some(&other), address).await,
+            Err(BlobError::Corrupted(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn dir_store_reads_ranges_of_chunked_blobs() {
+        let dir =
+            std::env::temp_dir().join(format!("take-home-blob-ranges-{}", std::process::id()));
+        let store = DirBlobStore::new(&dir);
+        let (address, _) = put_blob(&store, &Base64Encryptor, Some(&chunked()), b"0123456789")
+            .await
+            .unwrap();
+        let part = get_blob_range(&store, Some(&chunked()), address, ByteRange::Suffix(3)).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(part.unwrap().unwrap().bytes, b"789");
+    }
+}
@@ -0,0 +1,267 @@
+//! Runs CPU-heavy crypto (RSA and ECDSA signatures, sealing large response
+//! bodies, encrypting large documents) on tokio's blocking pool, so a slow
+//! operation never holds up the async workers serving other requests. A
+//! semaphore bounds how many run at once; the rest wait in a queue whose
+//! depth and timings are reported by the admin `GET /metrics` endpoint.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Semaphore;
+
+#[cfg(feature = "signing")]
+use crate::crypto::BoxFuture;
+#[cfg(feature = "signing")]
+use crate::crypto::signer::{AsyncSigner, SignError, Signer};
+use crate::models::BlockingPoolStats;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum BlockingError {
+    #[error("blocking task failed: {0}")]
+    Failed(String),
+}
+
+/// Cheap to clone; clones share the same permits and counters.
+#[derive(Debug, Clone)]
+pub struct BlockingPool {
+    permits: Arc<Semaphore>,
+    max_concurrent: usize,
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    queued: AtomicU64,
+    running: AtomicU64,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    wait_micros: AtomicU64,
+    run_micros: AtomicU64,
+}
+
+impl Default for BlockingPool {
+    /// One slot per available core.
+    fn default() -> Self {
+        Self::new(std::thread::available_parallelism().map_or(1, usize::from))
+    }
+}
+
+impl BlockingPool {
+    /// A pool running at most `max_concurrent` tasks at once (at least one).
+    pub fn new(max_concurrent: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            max_concurrent,
+            counters: Arc::default(),
+        }
+    }
+
+    /// Runs `task` on the blocking pool once a slot is free. A task keeps its
+    /// slot until it finishes, even if the caller stops waiting for it.
+    pub async fn run<F, T>(&self, task: F) -> Result<T, BlockingError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let queued = Gauge::enter(&self.counters.queued);
+        let waiting = Instant::now();
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the pool never closes its semaphore");
+        drop(queued);
+        add_micros(&self.counters.wait_micros, waiting.elapsed());
+
+        let counters = self.counters.clone();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let _running = Running::start(counters);
+            task()
+        })
+        .await
+        .map_err(|err| BlockingError::Failed(err.to_string()))
+    }
+
+    pub fn stats(&self) -> BlockingPoolStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        BlockingPoolStats {
+            max_concurrent: self.max_concurrent as u64,
+            queued: load(&self.counters.queued),
+            running: load(&self.counters.running),
+            completed: load(&self.counters.completed),
+            failed: load(&self.counters.failed),
+            wait_micros_total: load(&self.counters.wait_micros),
+            run_micros_total: load(&self.counters.run_micros),
+        }
+    }
+}
+
+/// Counts a task as queued for as long as it is alive.
+struct Gauge<'a>(&'a AtomicU64);
+
+impl<'a> Gauge<'a> {
+    fn enter(counter: &'a AtomicU64) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for Gauge<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts a task as running until it returns or panics.
+struct Running {
+    counters: Arc<Counters>,
+    started: Instant,
+}
+
+impl Running {
+    fn start(counters: Arc<Counters>) -> Self {
+        counters.running.fetch_add(1, Ordering::Relaxed);
+        Self {
+            counters,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let counters = &self.counters;
+        counters.running.fetch_sub(1, Ordering::Relaxed);
+        add_micros(&counters.run_micros, self.started.elapsed());
+        let outcome = if std::thread::panicking() {
+            &counters.failed
+        } else {
+            &counters.completed
+        };
+        outcome.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn add_micros(counter: &AtomicU64, elapsed: Duration) {
+    let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+    counter.fetch_add(micros, Ordering::Relaxed);
+}
+
+/// Wraps an in-process [`Signer`] whose operations are too slow for an async
+/// worker (RSA, ECDSA) so they run on a [`BlockingPool`].
+#[cfg(feature = "signing")]
+pub struct OffloadedSigner {
+    inner: Arc<dyn Signer>,
+    pool: BlockingPool,
+}
+
+#[cfg(feature = "signing")]
+impl OffloadedSigner {
+    pub fn new(inner: Arc<dyn Signer>, pool: BlockingPool) -> Self {
+        Self { inner, pool }
+    }
+}
+
+#[cfg(feature = "signing")]
+impl AsyncSigner for OffloadedSigner {
+    fn sign_bytes<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+        let inner = self.inner.clone();
+        let bytes = bytes.to_vec();
+        Box::pin(async move {
+            self.pool
+                .run(move || Signer::sign_bytes(inner.as_ref(), &bytes))
+                .await
+                .map_err(|err| SignError::Backend(err.to_string()))
+        })
+    }
+
+    fn verify_bytes<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        let inner = self.inner.clone();
+        let bytes = bytes.to_vec();
+        let signature = signature.to_string();
+        Box::pin(async move {
+            self.pool
+                .run(move || Signer::verify_bytes(inner.as_ref(), &bytes, &signature))
+                .await
+                .map_err(|err| SignError::Backend(err.to_string()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn tasks_run_and_are_counted() {
+        let pool = BlockingPool::new(2);
+        assert_eq!(pool.run(|| 2 + 2).await, Ok(4));
+        let stats = pool.stats();
+        assert_eq!(stats.max_concurrent, 2);
+        assert_eq!((stats.queued, stats.running), (0, 0));
+        assert_eq!((stats.completed, stats.failed), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn panics_are_reported_as_failures() {
+        let pool = BlockingPool::new(1);
+        let result = pool.run(|| panic!("boom")).await;
+        assert!(matches!(result, Err(BlockingError::Failed(_))));
+        assert_eq!(pool.stats().failed, 1);
+        // The slot is released again.
+        assert_eq!(pool.run(|| "ok").await, Ok("ok"));
+    }
+
+    #[tokio::test]
+    async fn tasks_beyond_the_limit_wait_in_the_queue() {
+        let pool = BlockingPool::new(1);
+        let (release, blocked) = std::sync::mpsc::channel::<()>();
+        let first = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(move || blocked.recv().unwrap()).await }
+        });
+        while pool.stats().running == 0 {
+            tokio::task::yield_now().await;
+        }
+        let second = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(|| ()).await }
+        });
+        while pool.stats().queued == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(pool.stats().running, 1);
+
+        release.send(()).unwrap();
+        first.await.unwrap().unwrap();
+        second.await.unwrap().unwrap();
+        let stats = pool.stats();
+        assert_eq!((stats.queued, stats.running, stats.completed), (0, 0, 2));
+    }
+
+    #[cfg(feature = "signing")]
+    #[tokio::test]
+    async fn offloaded_signers_match_the_inner_signer() {
+        use crate::crypto::hmac::HMacSigner;
+
+        let inner = Arc::new(HMacSigner::new(b"secret".to_vec()));
+        let pool = BlockingPool::new(1);
+        let signer = OffloadedSigner::new(inner.clone(), pool.clone());
+        let signature = AsyncSigner::sign_bytes(&signer, b"payload").await.unwrap();
+        assert_eq!(signature, Signer::sign_bytes(inner.as_ref(), b"payload"));
+        assert!(
+            AsyncSigner::verify_bytes(&signer, b"payload", &signature)
+                .await
+                .unwrap()
+        );
+        assert_eq!(pool.stats().completed, 2);
+    }
+}
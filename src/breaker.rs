@@ -0,0 +1,187 @@
+//! Circuit breaker for calls to remote key backends. After
+//! `failure_threshold` consecutive failures or timeouts the circuit opens
+//! and calls fail at once, without reaching the backend, for `open_for`.
+//! Then one trial call is let through: if it succeeds the circuit closes,
+//! otherwise it opens again. A slow backend so costs each request at most
+//! one `call_timeout` until it trips, and nothing while it is open.
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum BreakerError<E> {
+    #[error("circuit open after repeated backend failures")]
+    Open,
+    #[error("backend did not respond within {0:?}")]
+    Timeout(Duration),
+    #[error(transparent)]
+    Inner(E),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerSettings {
+    /// Consecutive failures that open the circuit.
+    pub failure_threshold: u32,
+    /// Calls slower than this fail, and count as failures.
+    pub call_timeout: Duration,
+    /// How long the circuit stays open before a trial call.
+    pub open_for: Duration,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    settings: BreakerSettings,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// One trial call is in flight. Should its caller be cancelled, another
+    /// trial is allowed once the first would have timed out.
+    HalfOpen {
+        started: Instant,
+    },
+}
+
+impl CircuitBreaker {
+    pub fn new(settings: BreakerSettings) -> Self {
+        Self {
+            settings,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether calls currently fail without reaching the backend.
+    pub fn is_open(&self) -> bool {
+        match *self.state() {
+            State::Closed { .. } => false,
+            State::Open { until } => Instant::now() < until,
+            State::HalfOpen { .. } => true,
+        }
+    }
+
+    /// Runs `call` unless the circuit is open, failing it if it does not
+    /// finish within the call timeout.
+    pub async fn call<T, E>(
+        &self,
+        call: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, BreakerError<E>> {
+        self.admit()?;
+        let result = match tokio::time::timeout(self.settings.call_timeout, call).await {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(err)) => Err(BreakerError::Inner(err)),
+            Err(_) => Err(BreakerError::Timeout(self.settings.call_timeout)),
+        };
+        let mut state = self.state();
+        *state = match (result.is_ok(), *state) {
+            (true, _) => State::Closed { failures: 0 },
+            (false, State::Closed { failures })
+                if failures + 1 < self.settings.failure_threshold =>
+            {
+                State::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (false, _) => State::Open {
+                until: Instant::now() + self.settings.open_for,
+            },
+        };
+        result
+    }
+
+    fn admit<E>(&self) -> Result<(), BreakerError<E>> {
+        let mut state = self.state();
+        let now = Instant::now();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now >= until => {
+                *state = State::HalfOpen { started: now };
+                Ok(())
+            }
+            State::HalfOpen { started } if now >= started + self.settings.call_timeout => {
+                *state = State::HalfOpen { started: now };
+                Ok(())
+            }
+            State::Open { .. } | State::HalfOpen { .. } => Err(BreakerError::Open),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        // Every write leaves a complete state behind.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(BreakerSettings {
+            failure_threshold: 2,
+            call_timeout: Duration::from_millis(50),
+            open_for: Duration::from_secs(30),
+        })
+    }
+
+    async fn fail(breaker: &CircuitBreaker) -> BreakerError<&'static str> {
+        breaker
+            .call(async { Err::<(), _>("refused") })
+            .await
+            .unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn consecutive_failures_open_the_circuit() {
+        let breaker = breaker();
+        assert_eq!(fail(&breaker).await, BreakerError::Inner("refused"));
+        breaker.call(async { Ok::<_, ()>(()) }).await.unwrap();
+        assert_eq!(fail(&breaker).await, BreakerError::Inner("refused"));
+        assert!(!breaker.is_open());
+        assert_eq!(fail(&breaker).await, BreakerError::Inner("refused"));
+        assert!(breaker.is_open());
+
+        let reached = breaker.call(async { Ok::<_, ()>(()) }).await;
+        assert_eq!(reached, Err(BreakerError::Open));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_calls_time_out_and_count_as_failures() {
+        let breaker = breaker();
+        for _ in 0..2 {
+            let slow = breaker
+                .call(async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    Ok::<_, ()>(())
+                })
+                .await;
+            assert_eq!(slow, Err(BreakerError::Timeout(Duration::from_millis(50))));
+        }
+        assert!(breaker.is_open());
+    }
+
+    #[tokio::test]
+    async fn a_trial_call_closes_or_reopens_the_circuit() {
+        let breaker = CircuitBreaker::new(BreakerSettings {
+            open_for: Duration::ZERO,
+            ..breaker().settings
+        });
+        fail(&breaker).await;
+        fail(&breaker).await;
+        // The trial fails, so the circuit opens again.
+        assert_eq!(fail(&breaker).await, BreakerError::Inner("refused"));
+        breaker.call(async { Ok::<_, ()>(()) }).await.unwrap();
+        assert!(!breaker.is_open());
+        assert_eq!(fail(&breaker).await, BreakerError::Inner("refused"));
+        assert!(!breaker.is_open());
+    }
+}
@@ -0,0 +1,405 @@
+//! Key ceremonies: a new HMAC-SHA256 master key created from a share of
+//! every custodian, so no single custodian ever knows it. Custodians are
+//! named by their [API keys](crate::api_keys), and each hands in its share
+//! in a request of its own, authenticated by its own key. Once the last
+//! share is in, the key is derived from all of them with HKDF-SHA256,
+//! sealed to the [escrow](crate::crypto::escrow) key chosen when the
+//! ceremony started, and forgotten along with the shares: only the sealed
+//! bundle, opened offline with `take-home-cli escrow`, and the key's
+//! fingerprint remain.
+//!
+//! A ceremony moves from `collecting` to `complete`, or to `aborted` or
+//! `expired` if it is called off or not finished within
+//! `ceremony.ttl_secs`. Ceremonies are kept in memory: one still collecting
+//! shares when the process restarts has to be started over.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use hkdf::Hkdf;
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::clock::Clock;
+use crate::config::SigningAlgorithm;
+use crate::crypto::escrow::{EscrowBundle, EscrowError, EscrowedKey, KeyEscrow};
+use crate::models::CeremonyStatus;
+
+/// Fewest bytes a share may have: as many as the key it goes into.
+pub const MIN_SHARE_BYTES: usize = 32;
+
+const KEY_INFO: &[u8] = b"take-home ceremony master key v1";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CeremonyError {
+    #[error("no ceremony `{0}`")]
+    UnknownCeremony(String),
+    #[error("`{0}` is not a custodian of this ceremony")]
+    NotCustodian(String),
+    #[error("custodian `{0}` already contributed a share")]
+    AlreadyContributed(String),
+    #[error("the ceremony is {0}, not collecting shares")]
+    NotCollecting(CeremonyState),
+    #[error("the ceremony is {0}; its key is only sealed once it is complete")]
+    NotComplete(CeremonyState),
+    #[error("a share must be at least {MIN_SHARE_BYTES} random bytes, in base64")]
+    InvalidShare,
+    #[error("`kid` must be non-empty printable ASCII")]
+    InvalidKeyId,
+    #[error(transparent)]
+    Escrow(#[from] EscrowError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CeremonyState {
+    Collecting,
+    Complete,
+    Aborted,
+    Expired,
+}
+
+impl CeremonyState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CeremonyState::Collecting => "collecting",
+            CeremonyState::Complete => "complete",
+            CeremonyState::Aborted => "aborted",
+            CeremonyState::Expired => "expired",
+        }
+    }
+}
+
+impl fmt::Display for CeremonyState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+struct Ceremony {
+    id: String,
+    kid: String,
+    escrow_key: String,
+    started_by: Option<String>,
+    state: CeremonyState,
+    /// By custodian; emptied once the ceremony leaves `collecting`.
+    shares: BTreeMap<String, Vec<u8>>,
+    contributed: Vec<String>,
+    started_at: u64,
+    expires_at: u64,
+    ended_at: Option<u64>,
+    fingerprint: Option<String>,
+    bundle: Option<EscrowBundle>,
+}
+
+impl Ceremony {
+    fn end(&mut self, state: CeremonyState, now: u64) {
+        self.state = state;
+        self.ended_at = Some(now);
+        self.shares.clear();
+    }
+}
+
+/// Every ceremony of this process, and the custodians each one needs.
+#[derive(Debug)]
+pub struct Ceremonies {
+    custodians: Vec<String>,
+    ttl_secs: u64,
+    ceremonies: Mutex<HashMap<String, Ceremony>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Ceremonies {
+    /// Ceremonies needing a share from each of `custodians`, API key names,
+    /// within `ttl` of being started.
+    pub fn new(custodians: Vec<String>, ttl: Duration) -> Self {
+        Self {
+            custodians,
+            ttl_secs: ttl.as_secs(),
+            ceremonies: Mutex::default(),
+            clock: crate::clock::system(),
+        }
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Starts a ceremony for a key to be named `kid` and sealed to
+    /// `escrow_key`, which `escrow` must know. `started_by` is the API key
+    /// of the administrator, if any.
+    pub fn start(
+        &self,
+        kid: &str,
+        escrow_key: &str,
+        escrow: &KeyEscrow,
+        started_by: Option<&str>,
+    ) -> Result<CeremonyStatus, CeremonyError> {
+        if kid.is_empty() || !kid.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(CeremonyError::InvalidKeyId);
+        }
+        escrow.recipient(escrow_key)?;
+        let mut id = [0; 16];
+        OsRng.fill_bytes(&mut id);
+        let now = self.clock.now();
+        let ceremony = Ceremony {
+            id: id.iter().map(|b| format!("{b:02x}")).collect(),
+            kid: kid.to_string(),
+            escrow_key: escrow_key.to_string(),
+            started_by: started_by.map(str::to_string),
+            state: CeremonyState::Collecting,
+            shares: BTreeMap::new(),
+            contributed: Vec::new(),
+            started_at: now,
+            expires_at: now.saturating_add(self.ttl_secs),
+            ended_at: None,
+            fingerprint: None,
+            bundle: None,
+        };
+        let status = self.status_of(&ceremony);
+        self.slot().insert(ceremony.id.clone(), ceremony);
+        Ok(status)
+    }
+
+    pub fn status(&self, id: &str) -> Result<CeremonyStatus, CeremonyError> {
+        self.with(id, |ceremony, _| Ok(self.status_of(ceremony)))
+    }
+
+    /// Records `custodian`'s share, and once every custodian's is in,
+    /// derives the key and seals it with `escrow`.
+    pub fn contribute(
+        &self,
+        id: &str,
+        custodian: &str,
+        share: &[u8],
+        escrow: &KeyEscrow,
+    ) -> Result<CeremonyStatus, CeremonyError> {
+        if !self.custodians.iter().any(|name| name == custodian) {
+            return Err(CeremonyError::NotCustodian(custodian.to_string()));
+        }
+        if share.len() < MIN_SHARE_BYTES {
+            return Err(CeremonyError::InvalidShare);
+        }
+        self.with(id, |ceremony, now| {
+            if ceremony.state != CeremonyState::Collecting {
+                return Err(CeremonyError::NotCollecting(ceremony.state));
+            }
+            if ceremony.shares.contains_key(custodian) {
+                return Err(CeremonyError::AlreadyContributed(custodian.to_string()));
+            }
+            ceremony
+                .shares
+                .insert(custodian.to_string(), share.to_vec());
+            ceremony.contributed.push(custodian.to_string());
+            if ceremony.shares.len() == self.custodians.len() {
+                let key = derive_key(&ceremony.id, &ceremony.shares);
+                let escrowed =
+                    EscrowedKey::hmac(SigningAlgorithm::HmacSha256.as_str(), &ceremony.kid, &key);
+                match escrow.seal(&escrowed, &ceremony.escrow_key) {
+                    Ok(bundle) => {
+                        ceremony.fingerprint = Some(fingerprint(&key));
+                        ceremony.bundle = Some(bundle);
+                        ceremony.end(CeremonyState::Complete, now);
+                    }
+                    // E.g. the escrow key was removed by a reload: the
+                    // shares cannot be kept, so the ceremony is over.
+                    Err(err) => {
+                        ceremony.end(CeremonyState::Aborted, now);
+                        return Err(err.into());
+                    }
+                }
+            }
+            Ok(self.status_of(ceremony))
+        })
+    }
+
+    /// Calls off a ceremony still collecting shares, dropping those in.
+    pub fn abort(&self, id: &str) -> Result<CeremonyStatus, CeremonyError> {
+        self.with(id, |ceremony, now| {
+            if ceremony.state != CeremonyState::Collecting {
+                return Err(CeremonyError::NotCollecting(ceremony.state));
+            }
+            ceremony.end(CeremonyState::Aborted, now);
+            Ok(self.status_of(ceremony))
+        })
+    }
+
+    /// The key of a complete ceremony, sealed to its escrow key.
+    pub fn bundle(&self, id: &str) -> Result<EscrowBundle, CeremonyError> {
+        self.with(id, |ceremony, _| {
+            ceremony
+                .bundle
+                .clone()
+                .ok_or(CeremonyError::NotComplete(ceremony.state))
+        })
+    }
+
+    /// Runs `f` on ceremony `id`, after expiring it if its time is up.
+    fn with<T>(
+        &self,
+        id: &str,
+        f: impl FnOnce(&mut Ceremony, u64) -> Result<T, CeremonyError>,
+    ) -> Result<T, CeremonyError> {
+        let now = self.clock.now();
+        let mut ceremonies = self.slot();
+        let ceremony = ceremonies
+            .get_mut(id)
+            .ok_or_else(|| CeremonyError::UnknownCeremony(id.to_string()))?;
+        if ceremony.state == CeremonyState::Collecting && now >= ceremony.expires_at {
+            ceremony.end(CeremonyState::Expired, ceremony.expires_at);
+        }
+        f(ceremony, now)
+    }
+
+    fn status_of(&self, ceremony: &Ceremony) -> CeremonyStatus {
+        CeremonyStatus {
+            id: ceremony.id.clone(),
+            kid: ceremony.kid.clone(),
+            algorithm: SigningAlgorithm::HmacSha256.as_str().to_string(),
+            escrow_key: ceremony.escrow_key.clone(),
+            state: ceremony.state.to_string(),
+            started_by: ceremony.started_by.clone(),
+            custodians: self.custodians.clone(),
+            contributed: ceremony.contributed.clone(),
+            started_at: ceremony.started_at,
+            expires_at: ceremony.expires_at,
+            ended_at: ceremony.ended_at,
+            fingerprint: ceremony.fingerprint.clone(),
+        }
+    }
+
+    fn slot(&self) -> MutexGuard<'_, HashMap<String, Ceremony>> {
+        self.ceremonies.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// HKDF-SHA256 of every share, length-prefixed in custodian order, salted
+/// with the ceremony id. The key is as unpredictable as the most random
+/// share.
+fn derive_key(id: &str, shares: &BTreeMap<String, Vec<u8>>) -> [u8; 32] {
+    let mut ikm = Vec::new();
+    for share in shares.values() {
+        ikm.extend_from_slice(&(share.len() as u64).to_be_bytes());
+        ikm.extend_from_slice(share);
+    }
+    let mut key = [0; 32];
+    Hkdf::<Sha256>::new(Some(id.as_bytes()), &ikm)
+        .expand(KEY_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// Lowercase hex SHA-256 of `key`, for custodians to check the key that is
+/// eventually installed.
+pub fn fingerprint(key: &[u8]) -> String {
+    Sha256::digest(key)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    use super::*;
+    use crate::clock::TestClock;
+    use crate::crypto::escrow::{KeyMaterial, unwrap};
+
+    fn escrow_secret() -> StaticSecret {
+        StaticSecret::from([7u8; 32])
+    }
+
+    fn escrow() -> KeyEscrow {
+        KeyEscrow::new().with_recipient("dr", PublicKey::from(&escrow_secret()))
+    }
+
+    fn ceremonies(clock: Arc<TestClock>) -> Ceremonies {
+        Ceremonies::new(vec!["alice".into(), "bob".into()], Duration::from_secs(600)).clock(clock)
+    }
+
+    #[test]
+    fn the_key_is_sealed_once_every_custodian_contributed() {
+        let ceremonies = ceremonies(Arc::new(TestClock::new(1_000)));
+        let started = ceremonies
+            .start("master-1", "dr", &escrow(), Some("ops"))
+            .unwrap();
+        assert_eq!(started.state, "collecting");
+        assert_eq!(started.expires_at, 1_600);
+
+        let id = &started.id;
+        let status = ceremonies
+            .contribute(id, "bob", &[2; 32], &escrow())
+            .unwrap();
+        assert_eq!(status.contributed, ["bob"]);
+        assert!(matches!(
+            ceremonies.bundle(id),
+            Err(CeremonyError::NotComplete(CeremonyState::Collecting))
+        ));
+        let status = ceremonies
+            .contribute(id, "alice", &[1; 32], &escrow())
+            .unwrap();
+        assert_eq!(status.state, "complete");
+
+        let key = derive_key(
+            id,
+            &BTreeMap::from([("alice".into(), vec![1; 32]), ("bob".into(), vec![2; 32])]),
+        );
+        assert_eq!(status.fingerprint, Some(fingerprint(&key)));
+        let opened = unwrap(&ceremonies.bundle(id).unwrap(), &escrow_secret()).unwrap();
+        assert_eq!(opened.kid, "master-1");
+        let KeyMaterial::Jwk(jwk) = opened.material else {
+            panic!("HMAC keys are escrowed as JWKs");
+        };
+        assert_eq!(jwk.symmetric_key().unwrap(), key);
+    }
+
+    #[test]
+    fn custodians_contribute_once_while_the_ceremony_collects() {
+        let clock = Arc::new(TestClock::new(1_000));
+        let ceremonies = ceremonies(clock.clone());
+        let id = ceremonies
+            .start("master-1", "dr", &escrow(), None)
+            .unwrap()
+            .id;
+        assert!(matches!(
+            ceremonies.contribute(&id, "mallory", &[0; 32], &escrow()),
+            Err(CeremonyError::NotCustodian(_))
+        ));
+        assert!(matches!(
+            ceremonies.contribute(&id, "alice", &[0; 16], &escrow()),
+            Err(CeremonyError::InvalidShare)
+        ));
+        ceremonies
+            .contribute(&id, "alice", &[1; 32], &escrow())
+            .unwrap();
+        assert!(matches!(
+            ceremonies.contribute(&id, "alice", &[3; 32], &escrow()),
+            Err(CeremonyError::AlreadyContributed(_))
+        ));
+
+        clock.advance(600);
+        let status = ceremonies.status(&id).unwrap();
+        assert_eq!(
+            (status.state.as_str(), status.ended_at),
+            ("expired", Some(1_600))
+        );
+        assert!(matches!(
+            ceremonies.contribute(&id, "bob", &[2; 32], &escrow()),
+            Err(CeremonyError::NotCollecting(CeremonyState::Expired))
+        ));
+
+        let id = ceremonies
+            .start("master-2", "dr", &escrow(), None)
+            .unwrap()
+            .id;
+        assert_eq!(ceremonies.abort(&id).unwrap().state, "aborted");
+        assert!(matches!(
+            ceremonies.start("master-3", "offsite", &escrow(), None),
+            Err(CeremonyError::Escrow(EscrowError::UnknownEscrowKey { .. }))
+        ));
+    }
+}
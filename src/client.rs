@@ -0,0 +1,292 @@
+//! Typed async client for the HTTP API, so Rust services do not have to
+//! hand-write requests against it.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), take_home::client::ClientError> {
+//! use serde_json::json;
+//!
+//! let client = take_home::client::Client::new("http://localhost:3000")?;
+//! let data = json!({"message": "Hello World"});
+//! let signature = client.sign(&data).await?;
+//! assert!(client.verify(&data, &signature).await?);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use reqwest::{StatusCode, Url};
+use serde_json::{Value, json};
+
+use crate::models::{
+    CanonicalizeResponse, DecryptStatusResponse, ErrorResponse, PatchResponse, SignResponse,
+    VerifyBatchResponse, VerifyRequest,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("invalid base URL: {0}")]
+    InvalidUrl(String),
+    #[error("request failed: {0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("server responded with {status}: {body}")]
+    Status {
+        status: StatusCode,
+        /// The `error.code` reported by the server, when the body has one.
+        code: Option<String>,
+        body: String,
+    },
+    #[error("unexpected response: {0}")]
+    InvalidResponse(String),
+}
+
+impl ClientError {
+    /// Whether retrying the same request might succeed.
+    fn is_transient(&self) -> bool {
+        match self {
+            ClientError::Transport(err) => err.is_connect() || err.is_timeout(),
+            ClientError::Status { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct ClientBuilder {
+    base_url: String,
+    max_retries: u32,
+    initial_backoff: Duration,
+    timeout: Option<Duration>,
+    api_key: Option<String>,
+}
+
+impl ClientBuilder {
+    /// Number of retries after the first attempt for transient failures
+    /// (connection errors, timeouts, 429 and 5xx). Defaults to 2.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Delay before the first retry; doubled on every subsequent retry.
+    /// Defaults to 100ms.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Per-attempt timeout. No timeout by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Key sent in `X-Api-Key` with every request, for servers that
+    /// require one.
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Client, ClientError> {
+        let mut http = reqwest::Client::builder();
+        if let Some(timeout) = self.timeout {
+            http = http.timeout(timeout);
+        }
+        // Paths are joined relative to the base URL, which drops its last
+        // segment unless it ends with a slash.
+        let mut base_url = self.base_url;
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        Ok(Client {
+            http: http.build()?,
+            base_url: Url::parse(&base_url).map_err(|e| ClientError::InvalidUrl(e.to_string()))?,
+            max_retries: self.max_retries,
+            initial_backoff: self.initial_backoff,
+            api_key: self.api_key,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: Url,
+    max_retries: u32,
+    initial_backoff: Duration,
+    api_key: Option<String>,
+}
+
+impl Client {
+    pub fn new(base_url: &str) -> Result<Self, ClientError> {
+        Self::builder(base_url).build()
+    }
+
+    pub fn builder(base_url: &str) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.to_string(),
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(100),
+            timeout: None,
+            api_key: None,
+        }
+    }
+
+    /// Calls `/encrypt`, returning the payload with its top-level values
+    /// encrypted.
+    pub async fn encrypt(&self, payload: &Value) -> Result<Value, ClientError> {
+        self.post_json("encrypt", payload).await
+    }
+
+    /// Calls `/decrypt`, returning the payload with its top-level values
+    /// decrypted.
+    pub async fn decrypt(&self, payload: &Value) -> Result<Value, ClientError> {
+        self.post_json("decrypt", payload).await
+    }
+
+    /// Calls `/encrypt/pointers`, returning `data` with the values at
+    /// `pointers` encrypted.
+    pub async fn encrypt_pointers(
+        &self,
+        data: &Value,
+        pointers: &[&str],
+    ) -> Result<Value, ClientError> {
+        let body = json!({ "data": data, "pointers": pointers });
+        self.post_json("encrypt/pointers", &body).await
+    }
+
+    /// Calls `/encrypt/patch`, returning the JSON text `document` with only
+    /// the values at `pointers` replaced by ciphertext.
+    pub async fn encrypt_patch(
+        &self,
+        document: &str,
+        pointers: &[&str],
+    ) -> Result<String, ClientError> {
+        let body = json!({ "document": document, "pointers": pointers });
+        let response = self.post("encrypt/patch", &body).await?;
+        let body: PatchResponse = response
+            .json()
+            .await
+            .map_err(|e| ClientError::InvalidResponse(e.to_string()))?;
+        Ok(body.document)
+    }
+
+    /// Calls `/decrypt/pointers`, returning `data` with the values at
+    /// `pointers` decrypted.
+    pub async fn decrypt_pointers(
+        &self,
+        data: &Value,
+        pointers: &[&str],
+    ) -> Result<Value, ClientError> {
+        let body = json!({ "data": data, "pointers": pointers });
+        self.post_json("decrypt/pointers", &body).await
+    }
+
+    /// Calls `/decrypt?field_status=true`, returning the payload together
+    /// with whether each property was decrypted, passed through or failed
+    /// authentication.
+    pub async fn decrypt_with_status(
+        &self,
+        payload: &Value,
+    ) -> Result<DecryptStatusResponse, ClientError> {
+        let response = self.post("decrypt?field_status=true", payload).await?;
+        response
+            .json()
+            .await
+            .map_err(|e| ClientError::InvalidResponse(e.to_string()))
+    }
+
+    /// Calls `/sign` and returns the signature of `data`, which must be a
+    /// JSON object.
+    pub async fn sign(&self, data: &Value) -> Result<String, ClientError> {
+        let response = self.post("sign", data).await?;
+        let body: SignResponse = response
+            .json()
+            .await
+            .map_err(|e| ClientError::InvalidResponse(e.to_string()))?;
+        Ok(body.signature)
+    }
+
+    /// Calls `/canonicalize`, returning the bytes the server signs for
+    /// `data` and their SHA-256.
+    pub async fn canonicalize(&self, data: &Value) -> Result<CanonicalizeResponse, ClientError> {
+        let response = self.post("canonicalize", data).await?;
+        response
+            .json()
+            .await
+            .map_err(|e| ClientError::InvalidResponse(e.to_string()))
+    }
+
+    /// Calls `/verify`. Returns `Ok(false)` when the server rejects the
+    /// signature; malformed requests are still reported as errors.
+    pub async fn verify(&self, data: &Value, signature: &str) -> Result<bool, ClientError> {
+        let body = json!({ "signature": signature, "data": data });
+        match self.post("verify", &body).await {
+            Ok(_) => Ok(true),
+            Err(ClientError::Status {
+                code: Some(code), ..
+            }) if code == "invalid_signature" => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Calls `/verify/batch`, returning a verdict for each pair of `items`,
+    /// in the same order.
+    pub async fn verify_batch(
+        &self,
+        items: &[VerifyRequest],
+    ) -> Result<VerifyBatchResponse, ClientError> {
+        let response = self
+            .post("verify/batch", &json!({ "items": items }))
+            .await?;
+        response
+            .json()
+            .await
+            .map_err(|e| ClientError::InvalidResponse(e.to_string()))
+    }
+
+    async fn post_json(&self, path: &str, body: &Value) -> Result<Value, ClientError> {
+        let response = self.post(path, body).await?;
+        Ok(response.json().await?)
+    }
+
+    /// Sends a POST request, retrying transient failures with exponential
+    /// backoff. All endpoints are side-effect free, so retries are safe.
+    async fn post(&self, path: &str, body: &Value) -> Result<reqwest::Response, ClientError> {
+        let url = self
+            .base_url
+            .join(path)
+            .map_err(|e| ClientError::InvalidUrl(e.to_string()))?;
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            match self.send_once(url.clone(), body).await {
+                Err(err) if err.is_transient() && attempt < self.max_retries => {
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn send_once(&self, url: Url, body: &Value) -> Result<reqwest::Response, ClientError> {
+        let mut request = self.http.post(url).json(body);
+        if let Some(key) = &self.api_key {
+            request = request.header("x-api-key", key);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        let code = serde_json::from_str::<ErrorResponse>(&body)
+            .ok()
+            .map(|e| e.error.code);
+        Err(ClientError::Status { status, code, body })
+    }
+}
@@ -0,0 +1,103 @@
+//! The time every expiry, timestamp and audit record is taken from.
+//! [`SystemClock`] reads the system time, optionally shifted by
+//! `clock.offset_secs` so a staging deployment can run ahead of (or behind)
+//! the real date; [`TestClock`] only moves when told to, so expiry can be
+//! tested without waiting for it. Elapsed-time measurements (timeouts,
+//! backoff, replay store TTLs) keep using monotonic [`Instant`]s.
+//!
+//! [`Instant`]: std::time::Instant
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Current Unix time in seconds.
+    fn now(&self) -> u64;
+}
+
+/// The system clock, shifted by `offset_secs`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock {
+    offset_secs: i64,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports times `offset_secs` ahead of the system clock, or behind it
+    /// if negative.
+    pub fn offset(offset_secs: i64) -> Self {
+        Self { offset_secs }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        now.saturating_add_signed(self.offset_secs)
+    }
+}
+
+/// A clock set by hand, for tests.
+#[derive(Debug, Default)]
+pub struct TestClock {
+    now: AtomicU64,
+}
+
+impl TestClock {
+    /// A clock stopped at Unix time `now`.
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now),
+        }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, secs: u64) {
+        self.now.fetch_add(secs, Ordering::Relaxed);
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::Relaxed)
+    }
+}
+
+/// The unshifted system clock, the default of every component that takes
+/// a clock.
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_shift_the_system_clock() {
+        let now = SystemClock::new().now();
+        let ahead = SystemClock::offset(86_400).now();
+        assert!((86_400..=86_401).contains(&(ahead - now)));
+        assert_eq!(SystemClock::offset(-i64::MAX).now(), 0);
+    }
+
+    #[test]
+    fn test_clocks_move_only_when_told() {
+        let clock = TestClock::new(1_000);
+        assert_eq!(clock.now(), 1_000);
+        clock.advance(60);
+        assert_eq!(clock.now(), 1_060);
+        clock.set(5);
+        assert_eq!(clock.now(), 5);
+    }
+}
@@ -0,0 +1,325 @@
+//! Coalescing of identical signing requests in flight at the same time.
+//! When many workers re-sign the same manifest at once, the first request
+//! for a payload runs the key operation and every identical request that
+//! arrives before it finishes waits for it and gets the same signature.
+//! Requests are identical when they are signed by the same key id and
+//! algorithm over the same canonical bytes. Nothing is kept once the
+//! operation completes; see [`crate::crypto::cache`] for that.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+
+use crate::crypto::BoxFuture;
+use crate::crypto::cache::HashWriter;
+use crate::crypto::canonical::{write_canonical, write_canonical_array};
+use crate::crypto::signer::{AsyncSigner, SignError};
+use crate::models::CoalescingStats;
+
+type FlightKey = [u8; 32];
+type Flight = Arc<OnceCell<Result<String, SignError>>>;
+
+/// Signing operations in flight, shared by the [`CoalescingSigner`]s of one
+/// instance.
+#[derive(Debug, Default)]
+pub struct SignFlights {
+    flights: Mutex<HashMap<FlightKey, Flight>>,
+    /// Operations run on behalf of one or more requests.
+    led: AtomicU64,
+    /// Requests served by another request's operation.
+    coalesced: AtomicU64,
+}
+
+impl SignFlights {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> CoalescingStats {
+        CoalescingStats {
+            in_flight: self.flights().len() as u64,
+            led: self.led.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+
+    fn flights(&self) -> std::sync::MutexGuard<'_, HashMap<FlightKey, Flight>> {
+        // The map holds no invariant a panicking holder could break.
+        self.flights
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Runs `sign` unless an operation under `key` is in flight, in which
+    /// case its result is shared.
+    async fn join(
+        &self,
+        key: FlightKey,
+        sign: BoxFuture<'_, Result<String, SignError>>,
+    ) -> Result<String, SignError> {
+        let leave = Leave {
+            flights: self,
+            key,
+            flight: self.flights().entry(key).or_default().clone(),
+        };
+        let led = AtomicBool::new(false);
+        let result = leave
+            .flight
+            .get_or_init(|| {
+                led.store(true, Ordering::Relaxed);
+                sign
+            })
+            .await
+            .clone();
+        drop(leave);
+        let counter = if led.into_inner() {
+            &self.led
+        } else {
+            &self.coalesced
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+}
+
+/// Removes a flight once it has completed, or once its last waiter gave up,
+/// so later requests start a new operation.
+struct Leave<'a> {
+    flights: &'a SignFlights,
+    key: FlightKey,
+    flight: Flight,
+}
+
+impl Drop for Leave<'_> {
+    fn drop(&mut self) {
+        let mut flights = self.flights.flights();
+        let Some(current) = flights.get(&self.key) else {
+            return;
+        };
+        // The map and this waiter hold the only references when nobody
+        // else waits.
+        let abandoned = Arc::strong_count(&self.flight) <= 2;
+        if Arc::ptr_eq(current, &self.flight) && (self.flight.initialized() || abandoned) {
+            flights.remove(&self.key);
+        }
+    }
+}
+
+/// Shares the signatures of identical concurrent requests through
+/// [`SignFlights`]. Verification is passed through.
+pub struct CoalescingSigner {
+    inner: Arc<dyn AsyncSigner>,
+    flights: Arc<SignFlights>,
+    /// `<kid>/<alg>`, hashed into every key.
+    scope: String,
+}
+
+impl CoalescingSigner {
+    pub fn new(
+        inner: Arc<dyn AsyncSigner>,
+        flights: Arc<SignFlights>,
+        kid: &str,
+        alg: &str,
+    ) -> Self {
+        Self {
+            inner,
+            flights,
+            scope: format!("{kid}/{alg}"),
+        }
+    }
+
+    /// `kind` keeps raw bytes apart from canonical objects and arrays with
+    /// the same text.
+    fn hasher(&self, kind: u8) -> Sha256 {
+        let mut hasher = Sha256::new();
+        hasher.update(self.scope.as_bytes());
+        hasher.update([0, kind]);
+        hasher
+    }
+
+    fn key_of_bytes(&self, bytes: &[u8]) -> FlightKey {
+        let mut hasher = self.hasher(b'b');
+        hasher.update(bytes);
+        hasher.finalize().into()
+    }
+
+    /// Hashes the canonical form of `map` as it is written, so it is never
+    /// built in full.
+    fn key_of_map(&self, map: &Map<String, Value>) -> FlightKey {
+        let mut hasher = HashWriter(self.hasher(b'o'));
+        write_canonical(&mut hasher, map).expect("updating a hash cannot fail");
+        hasher.0.finalize().into()
+    }
+
+    /// [`key_of_map`](Self::key_of_map) for a top-level array.
+    fn key_of_array(&self, items: &[Value]) -> FlightKey {
+        let mut hasher = HashWriter(self.hasher(b'a'));
+        write_canonical_array(&mut hasher, items).expect("updating a hash cannot fail");
+        hasher.0.finalize().into()
+    }
+}
+
+impl AsyncSigner for CoalescingSigner {
+    fn sign_bytes<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async move {
+            let key = self.key_of_bytes(bytes);
+            self.flights.join(key, self.inner.sign_bytes(bytes)).await
+        })
+    }
+
+    fn verify_bytes<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        self.inner.verify_bytes(bytes, signature)
+    }
+
+    fn sign<'a>(&'a self, map: &'a Map<String, Value>) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async move {
+            let key = self.key_of_map(map);
+            self.flights.join(key, self.inner.sign(map)).await
+        })
+    }
+
+    fn verify<'a>(
+        &'a self,
+        map: &'a Map<String, Value>,
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        self.inner.verify(map, signature)
+    }
+
+    fn sign_array<'a>(&'a self, items: &'a [Value]) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async move {
+            let key = self.key_of_array(items);
+            self.flights.join(key, self.inner.sign_array(items)).await
+        })
+    }
+
+    fn verify_array<'a>(
+        &'a self,
+        items: &'a [Value],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        self.inner.verify_array(items, signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Counts its signatures and takes a while over each.
+    #[derive(Default)]
+    struct SlowSigner {
+        signed: AtomicU64,
+    }
+
+    impl AsyncSigner for SlowSigner {
+        fn sign_bytes<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+            Box::pin(async move {
+                let n = self.signed.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(format!("{n}:{}", bytes.len()))
+            })
+        }
+
+        fn verify_bytes<'a>(
+            &'a self,
+            _bytes: &'a [u8],
+            _signature: &'a str,
+        ) -> BoxFuture<'a, Result<bool, SignError>> {
+            Box::pin(async { Ok(true) })
+        }
+    }
+
+    fn signer(inner: Arc<SlowSigner>, flights: Arc<SignFlights>) -> CoalescingSigner {
+        CoalescingSigner::new(inner, flights, "primary", "hmac-sha256")
+    }
+
+    #[tokio::test]
+    async fn concurrent_identical_requests_share_one_signature() {
+        let inner = Arc::new(SlowSigner::default());
+        let flights = Arc::new(SignFlights::new());
+        let signer = signer(inner.clone(), flights.clone());
+        let manifest = json!({"b": 2, "a": 1});
+        let reordered = json!({"a": 1, "b": 2});
+        let (first, second, third) = tokio::join!(
+            signer.sign(manifest.as_object().unwrap()),
+            signer.sign(reordered.as_object().unwrap()),
+            signer.sign(manifest.as_object().unwrap()),
+        );
+
+        assert_eq!(inner.signed.load(Ordering::SeqCst), 1);
+        assert_eq!(first, second);
+        assert_eq!(first, third);
+        let stats = flights.stats();
+        assert_eq!((stats.in_flight, stats.led, stats.coalesced), (0, 1, 2));
+
+        // Later requests sign afresh.
+        signer.sign(manifest.as_object().unwrap()).await.unwrap();
+        assert_eq!(inner.signed.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn different_payloads_and_keys_are_not_coalesced() {
+        let inner = Arc::new(SlowSigner::default());
+        let flights = Arc::new(SignFlights::new());
+        let primary = signer(inner.clone(), flights.clone());
+        let other = CoalescingSigner::new(inner.clone(), flights, "other", "hmac-sha256");
+        let object = json!({"a": 1});
+        let array = json!([{"a": 1}]);
+        let _ = tokio::join!(
+            primary.sign(object.as_object().unwrap()),
+            primary.sign_array(array.as_array().unwrap()),
+            primary.sign_bytes(br#"{"a":1}"#),
+            other.sign(object.as_object().unwrap()),
+        );
+        assert_eq!(inner.signed.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn keys_hash_the_canonical_form_as_it_is_written() {
+        use crate::crypto::canonical::{canonicalize, canonicalize_array};
+
+        let signer = signer(Arc::default(), Arc::default());
+        let manifest = json!({"files": [{"path": "a", "size": 1}], "v": 2});
+        let map = manifest.as_object().unwrap();
+        let mut built = signer.hasher(b'o');
+        built.update(canonicalize(map).as_bytes());
+        assert_eq!(signer.key_of_map(map), <[u8; 32]>::from(built.finalize()));
+
+        let items = [manifest.clone(), json!(1)];
+        let mut built = signer.hasher(b'a');
+        built.update(canonicalize_array(&items).as_bytes());
+        assert_eq!(
+            signer.key_of_array(&items),
+            <[u8; 32]>::from(built.finalize())
+        );
+    }
+
+    #[tokio::test]
+    async fn abandoned_requests_leave_no_flight_behind() {
+        let inner = Arc::new(SlowSigner::default());
+        let flights = Arc::new(SignFlights::new());
+        let signer = signer(inner.clone(), flights.clone());
+        let payload = json!({"a": 1});
+        let timed_out = tokio::time::timeout(
+            Duration::from_millis(1),
+            signer.sign(payload.as_object().unwrap()),
+        )
+        .await;
+        assert!(timed_out.is_err());
+        assert_eq!(flights.stats().in_flight, 0);
+        assert!(signer.sign(payload.as_object().unwrap()).await.is_ok());
+    }
+}
@@ -0,0 +1,132 @@
+//! Signers backed by an RSA, ECDSA P-256 or Ed25519 private key. Algorithms
+//! are named after their RFC 9421 identifiers and signatures are base64url
+//! without padding, as in JWS.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ed25519_dalek::Verifier as _;
+use rsa::signature::{Keypair as _, SignatureEncoding as _, Signer as _};
+use sha2::Sha256;
+
+use crate::crypto::keys::PrivateKey;
+use crate::crypto::signer::Signer;
+
+pub struct AsymmetricSigner {
+    algorithm: &'static str,
+    key: SigningKey,
+}
+
+enum SigningKey {
+    Rsa(rsa::pkcs1v15::SigningKey<Sha256>),
+    EcdsaP256(p256::ecdsa::SigningKey),
+    Ed25519(ed25519_dalek::SigningKey),
+}
+
+impl AsymmetricSigner {
+    pub fn new(key: PrivateKey) -> Self {
+        let algorithm = key.algorithm();
+        let key = match key {
+            PrivateKey::Rsa(key) => SigningKey::Rsa(rsa::pkcs1v15::SigningKey::new(*key)),
+            PrivateKey::EcdsaP256(key) => SigningKey::EcdsaP256(key.into()),
+            PrivateKey::Ed25519(key) => SigningKey::Ed25519(key),
+        };
+        Self { algorithm, key }
+    }
+
+    /// The RFC 9421 algorithm name, used as the registry key.
+    pub fn algorithm(&self) -> &'static str {
+        self.algorithm
+    }
+}
+
+impl Signer for AsymmetricSigner {
+    fn sign_bytes(&self, bytes: &[u8]) -> String {
+        let signature = match &self.key {
+            SigningKey::Rsa(key) => key.sign(bytes).to_vec(),
+            SigningKey::EcdsaP256(key) => {
+                let signature: p256::ecdsa::Signature = key.sign(bytes);
+                signature.to_vec()
+            }
+            SigningKey::Ed25519(key) => key.sign(bytes).to_vec(),
+        };
+        URL_SAFE_NO_PAD.encode(signature)
+    }
+
+    fn verify_bytes(&self, bytes: &[u8], signature: &str) -> bool {
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+        match &self.key {
+            SigningKey::Rsa(key) => rsa::pkcs1v15::Signature::try_from(signature.as_slice())
+                .is_ok_and(|signature| key.verifying_key().verify(bytes, &signature).is_ok()),
+            SigningKey::EcdsaP256(key) => p256::ecdsa::Signature::from_slice(&signature)
+                .is_ok_and(|signature| key.verifying_key().verify(bytes, &signature).is_ok()),
+            SigningKey::Ed25519(key) => ed25519_dalek::Signature::from_slice(&signature)
+                .is_ok_and(|signature| key.verify(bytes, &signature).is_ok()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keys::load_private_key;
+
+    fn signer(pem: &str) -> AsymmetricSigner {
+        AsymmetricSigner::new(load_private_key(pem.as_bytes(), None).unwrap())
+    }
+
+    fn ed25519() -> AsymmetricSigner {
+        signer(include_str!("../../tests/fixtures/keys/ed25519.pem"))
+    }
+
+    /// Reference signatures over `hello` produced with `openssl pkeyutl` /
+    /// `openssl dgst -sha256 -sign`.
+    #[test]
+    fn deterministic_signatures_match_openssl() {
+        assert_eq!(
+            ed25519().sign_bytes(b"hello"),
+            "OoxswgczaAZGMauwpWVN9d3fgn8xU2y2P8I0vgAQ4N6HNBrIOaPM-fIW2DbuTzEgDfw52EdFaxp8NYF0JVE0Ag"
+        );
+        let rsa = signer(include_str!("../../tests/fixtures/keys/rsa.pem"));
+        assert_eq!(
+            rsa.sign_bytes(b"hello"),
+            "uqD0tDPUu5nmPPsq50qiHUo_tGH_wY-F7kDKhcvrQFRL931tZzu7_f6lbFf8gF25B6Vz1L0sHJafACL4LoVz\
+             MiYpz6zqsxMDzVF4GQNwEzjP7sez1uwXjs8pQWOFZ4yvsgF54_sUrm6fpJ2g1Z6mp2Y_YzPoIvtlLlbQ4OTf\
+             3EL-SWB90iWn_44CdTXt7g0OckjgamzauVzbJED7XlgqN25dH1sK2M52hzID9kKbT1CU_GiJHHoSaEc5_-H6\
+             ewhacFhSkaoT4j2LgPXcfTCUjuX_ioY4U551QtNM9wI0waCo1FprBAuUT50sIpwt7VfPhWBtOERJAo5tcBCg\
+             cx_2yg"
+        );
+    }
+
+    #[test]
+    fn every_key_type_round_trips() {
+        for (pem, alg) in [
+            (
+                include_str!("../../tests/fixtures/keys/rsa.pem"),
+                "rsa-v1_5-sha256",
+            ),
+            (
+                include_str!("../../tests/fixtures/keys/p256.pem"),
+                "ecdsa-p256-sha256",
+            ),
+            (
+                include_str!("../../tests/fixtures/keys/ed25519.pem"),
+                "ed25519",
+            ),
+        ] {
+            let signer = signer(pem);
+            assert_eq!(signer.algorithm(), alg);
+            let signature = signer.sign_bytes(b"payload");
+            assert!(signer.verify_bytes(b"payload", &signature), "{alg}");
+            assert!(!signer.verify_bytes(b"tampered", &signature), "{alg}");
+        }
+    }
+
+    #[test]
+    fn malformed_signatures_are_rejected() {
+        let signer = ed25519();
+        assert!(!signer.verify_bytes(b"hello", "not base64!"));
+        assert!(!signer.verify_bytes(b"hello", "AAAA"));
+    }
+}
@@ -1,8 +1,7 @@
-use base64::Engine as _;
-use base64::engine::general_purpose::STANDARD;
 use serde_json::Value;
 
-use super::encryptor::Encryptor;
+use super::encryptor::{DecryptError, EncryptError, Encryptor};
+use super::{buffers, codec};
 
 #[derive(Default)]
 pub struct Base64Encryptor;
@@ -14,21 +13,27 @@ impl Base64Encryptor {
 }
 
 impl Encryptor for Base64Encryptor {
-    fn encrypt(&self, value: &Value) -> Value {
-        let bytes = serde_json::to_vec(value).expect("failed to serialize JSON value");
-        let encoded = STANDARD.encode(bytes);
-        Value::String(encoded)
+    fn encrypt(&self, value: &Value) -> Result<Value, EncryptError> {
+        buffers::with_bytes(|bytes| {
+            serde_json::to_writer(&mut *bytes, value)?;
+            Ok(Value::String(codec::encode(bytes)))
+        })
     }
 
-    fn decrypt(&self, value: &Value) -> Option<Value> {
-        if let Value::String(s) = value {
-            if let Ok(decoded) = STANDARD.decode(s) {
-                if let Ok(json) = serde_json::from_slice(&decoded) {
-                    return Some(json);
-                }
+    /// Base64 carries no integrity check, so anything that does not decode
+    /// to JSON is reported as [`DecryptError::NotCiphertext`].
+    fn decrypt(&self, value: &Value) -> Result<Value, DecryptError> {
+        let Value::String(s) = value else {
+            return Err(DecryptError::NotCiphertext);
+        };
+        buffers::with_bytes(|decoded| {
+            if codec::decode_into(s, decoded)
+                && let Ok(json) = serde_json::from_slice(decoded)
+            {
+                return Ok(json);
             }
-        }
-        None
+            Err(DecryptError::NotCiphertext)
+        })
     }
 }
 
@@ -40,7 +45,7 @@ mod tests {
     #[test]
     fn encrypt_string_value() {
         let encryptor = Base64Encryptor;
-        let result = encryptor.encrypt(&json!("hello"));
+        let result = encryptor.encrypt(&json!("hello")).unwrap();
         assert!(result.is_string());
     }
 
@@ -48,8 +53,8 @@ mod tests {
     fn encrypt_then_decrypt_string() {
         let encryptor = Base64Encryptor;
         let original = json!("hello");
-        let encrypted = encryptor.encrypt(&original);
-        let decrypted = encryptor.decrypt(&encrypted).unwrap_or(Value::Null);
+        let encrypted = encryptor.encrypt(&original).unwrap();
+        let decrypted = encryptor.decrypt(&encrypted).unwrap();
         assert_eq!(decrypted, original);
     }
 
@@ -57,8 +62,8 @@ mod tests {
     fn encrypt_then_decrypt_number() {
         let encryptor = Base64Encryptor;
         let original = json!(42);
-        let encrypted = encryptor.encrypt(&original);
-        let decrypted = encryptor.decrypt(&encrypted).unwrap_or(Value::Null);
+        let encrypted = encryptor.encrypt(&original).unwrap();
+        let decrypted = encryptor.decrypt(&encrypted).unwrap();
         assert_eq!(decrypted, original);
     }
 
@@ -66,8 +71,8 @@ mod tests {
     fn encrypt_then_decrypt_boolean() {
         let encryptor = Base64Encryptor;
         let original = json!(true);
-        let encrypted = encryptor.encrypt(&original);
-        let decrypted = encryptor.decrypt(&encrypted).unwrap_or(Value::Null);
+        let encrypted = encryptor.encrypt(&original).unwrap();
+        let decrypted = encryptor.decrypt(&encrypted).unwrap();
         assert_eq!(decrypted, original);
     }
 
@@ -75,8 +80,8 @@ mod tests {
     fn encrypt_then_decrypt_null() {
         let encryptor = Base64Encryptor;
         let original = json!(null);
-        let encrypted = encryptor.encrypt(&original);
-        let decrypted = encryptor.decrypt(&encrypted).unwrap_or(Value::Null);
+        let encrypted = encryptor.encrypt(&original).unwrap();
+        let decrypted = encryptor.decrypt(&encrypted).unwrap();
         assert_eq!(decrypted, original);
     }
 
@@ -84,8 +89,8 @@ mod tests {
     fn encrypt_then_decrypt_object() {
         let encryptor = Base64Encryptor;
         let original = json!({"key": "value", "num": 123, "empty": ""});
-        let encrypted = encryptor.encrypt(&original);
-        let decrypted = encryptor.decrypt(&encrypted).unwrap_or(Value::Null);
+        let encrypted = encryptor.encrypt(&original).unwrap();
+        let decrypted = encryptor.decrypt(&encrypted).unwrap();
         assert_eq!(decrypted, original);
     }
 
@@ -93,8 +98,8 @@ mod tests {
     fn encrypt_then_decrypt_array() {
         let encryptor = Base64Encryptor;
         let original = json!([1, "two", false]);
-        let encrypted = encryptor.encrypt(&original);
-        let decrypted = encryptor.decrypt(&encrypted).unwrap_or(Value::Null);
+        let encrypted = encryptor.encrypt(&original).unwrap();
+        let decrypted = encryptor.decrypt(&encrypted).unwrap();
         assert_eq!(decrypted, original);
     }
 
@@ -119,31 +124,40 @@ mod tests {
                 }
             }
         });
-        let encrypted = encryptor.encrypt(&original);
-        let decrypted = encryptor.decrypt(&encrypted).unwrap_or(Value::Null);
+        let encrypted = encryptor.encrypt(&original).unwrap();
+        let decrypted = encryptor.decrypt(&encrypted).unwrap();
         assert_eq!(decrypted, original);
     }
 
     #[test]
-    fn decrypt_invalid_base64_returns_null() {
+    fn decrypt_invalid_base64_is_not_ciphertext() {
         let encryptor = Base64Encryptor;
         let result = encryptor.decrypt(&json!("not-valid-base64!!!"));
-        assert_eq!(result, None);
+        assert_eq!(result, Err(DecryptError::NotCiphertext));
     }
 
     #[test]
-    fn decrypt_valid_base64_but_invalid_json_returns_null() {
+    fn decrypt_valid_base64_but_invalid_json_is_not_ciphertext() {
         let encryptor = Base64Encryptor;
-        let invalid_json = STANDARD.encode("this is not json".as_bytes());
+        let invalid_json = codec::encode(b"this is not json");
         let result = encryptor.decrypt(&json!(invalid_json));
-        assert_eq!(result, None);
+        assert_eq!(result, Err(DecryptError::NotCiphertext));
     }
 
     #[test]
-    fn decrypt_non_string_value_returns_null() {
+    fn decrypt_non_string_value_is_not_ciphertext() {
         let encryptor = Base64Encryptor;
-        assert_eq!(encryptor.decrypt(&json!(12345)), None);
-        assert_eq!(encryptor.decrypt(&json!(true)), None);
-        assert_eq!(encryptor.decrypt(&json!(null)), None);
+        assert_eq!(
+            encryptor.decrypt(&json!(12345)),
+            Err(DecryptError::NotCiphertext)
+        );
+        assert_eq!(
+            encryptor.decrypt(&json!(true)),
+            Err(DecryptError::NotCiphertext)
+        );
+        assert_eq!(
+            encryptor.decrypt(&json!(null)),
+            Err(DecryptError::NotCiphertext)
+        );
     }
 }
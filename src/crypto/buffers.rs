@@ -0,0 +1,84 @@
+//! Per-thread scratch buffers for the temporaries of serialization: the
+//! JSON bytes `Base64Encryptor` encodes or decodes, and the canonical form a
+//! signer consumes. Each thread keeps one buffer of each kind and clears it
+//! between uses instead of allocating a fresh one per value.
+
+use std::cell::RefCell;
+
+/// Buffers that grew past this size are released after use, so one large
+/// document does not pin its memory to the thread.
+const MAX_RETAINED: usize = 1024 * 1024;
+
+thread_local! {
+    static BYTES: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static TEXT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Runs `f` with this thread's empty scratch byte buffer. A nested call
+/// gets a fresh buffer instead.
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+pub(crate) fn with_bytes<R>(f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+    BYTES.with(|cell| match cell.try_borrow_mut() {
+        Ok(mut buffer) => {
+            buffer.clear();
+            let result = f(&mut buffer);
+            if buffer.capacity() > MAX_RETAINED {
+                *buffer = Vec::new();
+            }
+            result
+        }
+        Err(_) => f(&mut Vec::new()),
+    })
+}
+
+/// [`with_bytes`] for text.
+pub(crate) fn with_string<R>(f: impl FnOnce(&mut String) -> R) -> R {
+    TEXT.with(|cell| match cell.try_borrow_mut() {
+        Ok(mut buffer) => {
+            buffer.clear();
+            let result = f(&mut buffer);
+            if buffer.capacity() > MAX_RETAINED {
+                *buffer = String::new();
+            }
+            result
+        }
+        Err(_) => f(&mut String::new()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused_empty() {
+        let first = with_bytes(|buffer| {
+            buffer.extend_from_slice(b"hello");
+            buffer.as_ptr()
+        });
+        let second = with_bytes(|buffer| {
+            assert!(buffer.is_empty());
+            assert!(buffer.capacity() >= 5);
+            buffer.as_ptr()
+        });
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn nested_calls_get_their_own_buffer() {
+        with_string(|outer| {
+            outer.push_str("outer");
+            with_string(|inner| {
+                assert!(inner.is_empty());
+                inner.push_str("inner");
+            });
+            assert_eq!(outer, "outer");
+        });
+    }
+
+    #[test]
+    fn large_buffers_are_released() {
+        with_bytes(|buffer| buffer.resize(MAX_RETAINED + 1, 0));
+        with_bytes(|buffer| assert_eq!(buffer.capacity(), 0));
+    }
+}
@@ -0,0 +1,369 @@
+//! Bounded LRU of signatures for deterministic signers (HMAC, RSA
+//! PKCS#1 v1.5, Ed25519, RFC 6979 ECDSA). Entries are keyed by a SHA-256
+//! of the signer's key id, algorithm and the signed bytes, so repeated
+//! `/sign` and `/verify` calls for the same payload skip the key operation.
+
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::crypto::BoxFuture;
+use crate::crypto::canonical::{write_canonical, write_canonical_array};
+use crate::crypto::constant_time;
+use crate::crypto::signer::{AsyncSigner, SignError};
+
+type CacheKey = [u8; 32];
+
+/// Shared by the [`CachingSigner`]s of one instance.
+pub struct SignatureCache {
+    entries: Mutex<LruCache<CacheKey, String>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Point-in-time counters of a [`SignatureCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub capacity: usize,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl SignatureCache {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries();
+        CacheStats {
+            capacity: entries.cap().get(),
+            entries: entries.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Drops every signature, e.g. once the keys that made them were
+    /// replaced. The counters are kept.
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<String> {
+        let found = self.entries().get(key).cloned();
+        let counter = if found.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    fn put(&self, key: CacheKey, signature: String) {
+        self.entries().put(key, signature);
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, LruCache<CacheKey, String>> {
+        // The cache holds no invariant a panicking holder could break.
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Debug for SignatureCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignatureCache")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+/// Serves signatures from a [`SignatureCache`] and records new ones.
+/// Verification only uses the cache to accept a signature equal to the
+/// cached one; anything else is checked by the inner signer, so encodings
+/// it accepts (e.g. upper-case hex) still verify. Only wrap deterministic
+/// signers: a randomized one would pin the first signature it produced.
+pub struct CachingSigner {
+    inner: Arc<dyn AsyncSigner>,
+    cache: Arc<SignatureCache>,
+    /// `<kid>/<alg>`, hashed into every key.
+    scope: String,
+}
+
+impl CachingSigner {
+    pub fn new(
+        inner: Arc<dyn AsyncSigner>,
+        cache: Arc<SignatureCache>,
+        kid: &str,
+        alg: &str,
+    ) -> Self {
+        Self {
+            inner,
+            cache,
+            scope: format!("{kid}/{alg}"),
+        }
+    }
+
+    fn hasher(&self) -> Sha256 {
+        let mut hasher = Sha256::new();
+        hasher.update(self.scope.as_bytes());
+        hasher.update([0]);
+        hasher
+    }
+
+    fn key_of_bytes(&self, bytes: &[u8]) -> CacheKey {
+        let mut hasher = self.hasher();
+        hasher.update(bytes);
+        hasher.finalize().into()
+    }
+
+    /// Same key as [`key_of_bytes`](Self::key_of_bytes) of the canonical
+    /// form, which is hashed as it is written.
+    fn key_of_map(&self, map: &Map<String, Value>) -> CacheKey {
+        let mut hasher = HashWriter(self.hasher());
+        write_canonical(&mut hasher, map).expect("updating a hash cannot fail");
+        hasher.0.finalize().into()
+    }
+
+    /// [`key_of_map`](Self::key_of_map) for a top-level array.
+    fn key_of_array(&self, items: &[Value]) -> CacheKey {
+        let mut hasher = HashWriter(self.hasher());
+        write_canonical_array(&mut hasher, items).expect("updating a hash cannot fail");
+        hasher.0.finalize().into()
+    }
+
+    fn matches_cached(&self, key: &CacheKey, signature: &str) -> bool {
+        self.cache
+            .get(key)
+            .is_some_and(|cached| constant_time::eq(cached.as_bytes(), signature.as_bytes()))
+    }
+}
+
+/// Feeds text written with [`fmt::Write`] into a hash, so a canonical form
+/// can be hashed without being built.
+pub(crate) struct HashWriter(pub(crate) Sha256);
+
+impl fmt::Write for HashWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.update(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl AsyncSigner for CachingSigner {
+    fn sign_bytes<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async move {
+            let key = self.key_of_bytes(bytes);
+            if let Some(signature) = self.cache.get(&key) {
+                return Ok(signature);
+            }
+            let signature = self.inner.sign_bytes(bytes).await?;
+            self.cache.put(key, signature.clone());
+            Ok(signature)
+        })
+    }
+
+    fn verify_bytes<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(async move {
+            if self.matches_cached(&self.key_of_bytes(bytes), signature) {
+                return Ok(true);
+            }
+            self.inner.verify_bytes(bytes, signature).await
+        })
+    }
+
+    fn sign<'a>(&'a self, map: &'a Map<String, Value>) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async move {
+            let key = self.key_of_map(map);
+            if let Some(signature) = self.cache.get(&key) {
+                return Ok(signature);
+            }
+            let signature = self.inner.sign(map).await?;
+            self.cache.put(key, signature.clone());
+            Ok(signature)
+        })
+    }
+
+    fn verify<'a>(
+        &'a self,
+        map: &'a Map<String, Value>,
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(async move {
+            if self.matches_cached(&self.key_of_map(map), signature) {
+                return Ok(true);
+            }
+            self.inner.verify(map, signature).await
+        })
+    }
+
+    fn sign_array<'a>(&'a self, items: &'a [Value]) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async move {
+            let key = self.key_of_array(items);
+            if let Some(signature) = self.cache.get(&key) {
+                return Ok(signature);
+            }
+            let signature = self.inner.sign_array(items).await?;
+            self.cache.put(key, signature.clone());
+            Ok(signature)
+        })
+    }
+
+    fn verify_array<'a>(
+        &'a self,
+        items: &'a [Value],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(async move {
+            if self.matches_cached(&self.key_of_array(items), signature) {
+                return Ok(true);
+            }
+            self.inner.verify_array(items, signature).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::crypto::canonical::{canonicalize, canonicalize_array};
+    use crate::crypto::hmac::HMacSigner;
+    use crate::crypto::signer::Signer;
+
+    /// Counts the operations that reach it.
+    struct Counting {
+        inner: HMacSigner,
+        calls: AtomicUsize,
+    }
+
+    impl Signer for Counting {
+        fn sign_bytes(&self, bytes: &[u8]) -> String {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            Signer::sign_bytes(&self.inner, bytes)
+        }
+
+        fn verify_bytes(&self, bytes: &[u8], signature: &str) -> bool {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            Signer::verify_bytes(&self.inner, bytes, signature)
+        }
+    }
+
+    fn setup(capacity: usize) -> (Arc<Counting>, Arc<SignatureCache>, CachingSigner) {
+        let counting = Arc::new(Counting {
+            inner: HMacSigner::new(b"secret".to_vec()),
+            calls: AtomicUsize::new(0),
+        });
+        let cache = Arc::new(SignatureCache::new(NonZeroUsize::new(capacity).unwrap()));
+        let signer = CachingSigner::new(counting.clone(), cache.clone(), "default", "hmac-sha256");
+        (counting, cache, signer)
+    }
+
+    fn payload(n: i64) -> Map<String, Value> {
+        match json!({"amount": n, "currency": "EUR"}) {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn repeated_payloads_are_signed_once() {
+        let (counting, cache, signer) = setup(8);
+        let first = signer.sign(&payload(1)).await.unwrap();
+        let second = signer.sign(&payload(1)).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(counting.calls.load(Ordering::Relaxed), 1);
+        assert!(signer.verify(&payload(1), &first).await.unwrap());
+        assert_eq!(counting.calls.load(Ordering::Relaxed), 1);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn maps_and_their_canonical_bytes_share_entries() {
+        let (counting, _, signer) = setup(8);
+        let signature = signer.sign(&payload(1)).await.unwrap();
+        let canonical = canonicalize(&payload(1));
+        assert_eq!(
+            signer.sign_bytes(canonical.as_bytes()).await.unwrap(),
+            signature
+        );
+        assert_eq!(counting.calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn arrays_are_cached_under_their_canonical_bytes() {
+        let (counting, _, signer) = setup(8);
+        let events = [Value::Object(payload(1)), Value::Object(payload(2))];
+        let signature = signer.sign_array(&events).await.unwrap();
+        assert!(signer.verify_array(&events, &signature).await.unwrap());
+        let canonical = canonicalize_array(&events);
+        assert_eq!(
+            signer.sign_bytes(canonical.as_bytes()).await.unwrap(),
+            signature
+        );
+        assert_eq!(counting.calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn other_signatures_are_checked_by_the_inner_signer() {
+        let (counting, _, signer) = setup(8);
+        let signature = signer.sign(&payload(1)).await.unwrap();
+        assert!(!signer.verify(&payload(1), "00").await.unwrap());
+        assert!(
+            signer
+                .verify(&payload(1), &signature.to_uppercase())
+                .await
+                .unwrap()
+        );
+        assert_eq!(counting.calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_entries_are_evicted() {
+        let (counting, cache, signer) = setup(2);
+        for n in [1, 2, 1, 3] {
+            signer.sign(&payload(n)).await.unwrap();
+        }
+        assert_eq!(cache.stats().entries, 2);
+        signer.sign(&payload(1)).await.unwrap();
+        assert_eq!(counting.calls.load(Ordering::Relaxed), 3);
+        signer.sign(&payload(2)).await.unwrap();
+        assert_eq!(counting.calls.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test]
+    async fn keys_are_scoped_to_kid_and_algorithm() {
+        let (_, cache, signer) = setup(8);
+        let other_key = CachingSigner::new(
+            Arc::new(HMacSigner::new(b"other".to_vec())),
+            cache,
+            "rotated",
+            "hmac-sha256",
+        );
+        assert_ne!(
+            signer.sign(&payload(1)).await.unwrap(),
+            other_key.sign(&payload(1)).await.unwrap()
+        );
+    }
+}
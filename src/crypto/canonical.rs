@@ -0,0 +1,560 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::crypto::buffers;
+
+/// How non-integer numbers (`0.1`, `1.0`, `1e3`) in signed payloads are
+/// treated. Clients serialize the same double differently (`1.0` vs `1`,
+/// `1e21` vs `1000000000000000000000`), which breaks verification unless
+/// both sides agree on one form.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FloatPolicy {
+    /// Sign numbers as `serde_json` writes them back (`1.0` stays `1.0`,
+    /// `1e3` becomes `1000.0`), so signatures issued before the other policies
+    /// existed still verify.
+    #[default]
+    Legacy,
+    /// Sign the RFC 8785 (JCS) form: the shortest round-trip digits, with
+    /// ECMAScript exponent rules, so `1.0` and `1` sign identically.
+    Canonical,
+    /// Refuse payloads containing non-integer numbers, or integers beyond
+    /// the range of `i64` / `u64`, which are parsed as doubles and would
+    /// lose digits.
+    Reject,
+    /// Sign non-integer numbers as strings holding their canonical form, so
+    /// `0.1` and `"0.1"` sign identically.
+    String,
+}
+
+impl FloatPolicy {
+    /// Name used in `signing.float_policy`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FloatPolicy::Legacy => "legacy",
+            FloatPolicy::Canonical => "canonical",
+            FloatPolicy::Reject => "reject",
+            FloatPolicy::String => "string",
+        }
+    }
+}
+
+/// A number [`FloatPolicy::Reject`] refuses, by JSON Pointer.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum RejectedNumber {
+    #[error("non-integer number at `{0}` is not allowed in signed payloads")]
+    NonInteger(String),
+    #[error("integer at `{0}` is beyond the 64-bit range and would not be signed exactly")]
+    OutOfRange(String),
+}
+
+/// Builds a deterministic string from a JSON object by sorting entries
+/// alphabetically by key. This is the exact input the signers authenticate.
+/// Numbers are written as `serde_json` writes them; see
+/// [`canonicalize_with`] for the RFC 8785 form.
+///
+/// NOTE: Nested object values are written in the compact form of
+/// `serde_json`'s `Display`, whose key order depends on insertion order (not
+/// sorted). This means two objects that are semantically identical but have
+/// differently-ordered nested keys would produce different signatures.
+/// Because the API operates at depth 1 (same as `/encrypt`), this is
+/// acceptable for the current scope. A recursive canonicalization (sorting
+/// keys at every depth) would remove this limitation if deeper guarantees
+/// were needed. Top-level arrays have a form of their own,
+/// [`canonicalize_array`], which does sort nested keys.
+pub fn canonicalize(map: &Map<String, Value>) -> String {
+    canonicalize_with(map, FloatPolicy::Legacy)
+}
+
+/// The [`canonicalize`] form of `map` with numbers written as `policy`
+/// signs them: under [`FloatPolicy::Canonical`], non-integer numbers take
+/// their RFC 8785 form at every depth (see [`format_number`]). Signers
+/// build the [`canonicalize`] form themselves, so a payload signed under
+/// `Canonical` is signed as these bytes.
+pub fn canonicalize_with(map: &Map<String, Value>, policy: FloatPolicy) -> String {
+    let mut out = String::new();
+    write_entries(&mut out, map, Style::object(policy)).expect("writing to a String cannot fail");
+    out
+}
+
+/// Runs `f` on the [`canonicalize`] form of `map`, built in a reused
+/// per-thread buffer rather than a fresh string.
+pub fn with_canonical<R>(map: &Map<String, Value>, f: impl FnOnce(&[u8]) -> R) -> R {
+    buffers::with_string(|out| {
+        write_canonical(out, map).expect("writing to a String cannot fail");
+        f(out.as_bytes())
+    })
+}
+
+/// Writes the [`canonicalize`] form of `map` to `out` piece by piece, so a
+/// signer can feed it straight into its hash instead of holding a copy of
+/// the whole document as one string.
+pub fn write_canonical(out: &mut impl fmt::Write, map: &Map<String, Value>) -> fmt::Result {
+    write_entries(out, map, Style::object(FloatPolicy::Legacy))
+}
+
+fn write_entries(out: &mut impl fmt::Write, map: &Map<String, Value>, style: Style) -> fmt::Result {
+    let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+    entries.sort_by(|a, b| entry_order(*a, *b, style));
+    for (key, value) in entries {
+        write!(out, "{key}=")?;
+        write_value(out, value, style)?;
+        out.write_char(';')?;
+    }
+    Ok(())
+}
+
+/// Builds the deterministic string signed for a top-level array, such as a
+/// batch of events: the elements in their given order, as compact JSON
+/// with the keys of every object sorted at every depth (by UTF-16 code
+/// units, as in RFC 8785). Non-integer numbers always take their RFC 8785
+/// form, whatever the float policy: arrays were signed this way from the
+/// start.
+///
+/// The form always ends with `]`, while that of an object is empty or ends
+/// with `;`, so a signature over an array never verifies an object.
+pub fn canonicalize_array(items: &[Value]) -> String {
+    let mut out = String::new();
+    write_canonical_array(&mut out, items).expect("writing to a String cannot fail");
+    out
+}
+
+/// [`with_canonical`] for the [`canonicalize_array`] form.
+pub fn with_canonical_array<R>(items: &[Value], f: impl FnOnce(&[u8]) -> R) -> R {
+    buffers::with_string(|out| {
+        write_canonical_array(out, items).expect("writing to a String cannot fail");
+        f(out.as_bytes())
+    })
+}
+
+/// Writes the [`canonicalize_array`] form of `items` to `out` piece by
+/// piece.
+pub fn write_canonical_array(out: &mut impl fmt::Write, items: &[Value]) -> fmt::Result {
+    write_items(out, items, Style::ARRAY)
+}
+
+/// How [`write_value`] writes nested values.
+#[derive(Clone, Copy)]
+struct Style {
+    /// Sort object keys by UTF-16 code units rather than keep insertion
+    /// order.
+    sort_keys: bool,
+    /// Write non-integer numbers in their RFC 8785 form rather than as
+    /// `serde_json` does.
+    rfc8785_numbers: bool,
+}
+
+impl Style {
+    const ARRAY: Style = Style {
+        sort_keys: true,
+        rfc8785_numbers: true,
+    };
+
+    fn object(policy: FloatPolicy) -> Self {
+        Style {
+            sort_keys: false,
+            rfc8785_numbers: policy == FloatPolicy::Canonical,
+        }
+    }
+}
+
+/// Orders entries the way sorting their `key=value;` strings would. That is
+/// the order of `key=` unless one of those is a prefix of the other (a key
+/// containing `=`), where the values decide.
+fn entry_order(a: (&String, &Value), b: (&String, &Value), style: Style) -> Ordering {
+    let prefixes = |short: &str, long: &str| {
+        long.len() > short.len() && long.starts_with(short) && long.as_bytes()[short.len()] == b'='
+    };
+    if prefixes(a.0, b.0) || prefixes(b.0, a.0) {
+        let entry = |(key, value): (&String, &Value)| {
+            let mut entry = format!("{key}=");
+            write_value(&mut entry, value, style).expect("writing to a String cannot fail");
+            entry
+        };
+        return entry(a).cmp(&entry(b));
+    }
+    key_eq(a.0).cmp(key_eq(b.0))
+}
+
+fn key_eq(key: &str) -> impl Iterator<Item = u8> + '_ {
+    key.bytes().chain(std::iter::once(b'='))
+}
+
+/// Applies `policy` to every number in `map`, borrowing it when nothing
+/// changes.
+pub fn apply_float_policy(
+    map: &Map<String, Value>,
+    policy: FloatPolicy,
+) -> Result<Cow<'_, Map<String, Value>>, RejectedNumber> {
+    match policy {
+        FloatPolicy::Legacy | FloatPolicy::Canonical => Ok(Cow::Borrowed(map)),
+        FloatPolicy::Reject => {
+            for (key, value) in map {
+                reject_floats(value, &pointer_segment("", key))?;
+            }
+            Ok(Cow::Borrowed(map))
+        }
+        FloatPolicy::String => Ok(Cow::Owned(
+            map.iter()
+                .map(|(key, value)| (key.clone(), floats_to_strings(value)))
+                .collect(),
+        )),
+    }
+}
+
+/// [`apply_float_policy`] for the elements of a top-level array; pointers
+/// in errors start with the element's index.
+pub fn apply_float_policy_array(
+    items: &[Value],
+    policy: FloatPolicy,
+) -> Result<Cow<'_, [Value]>, RejectedNumber> {
+    match policy {
+        FloatPolicy::Legacy | FloatPolicy::Canonical => Ok(Cow::Borrowed(items)),
+        FloatPolicy::Reject => {
+            for (i, item) in items.iter().enumerate() {
+                reject_floats(item, &pointer_segment("", &i.to_string()))?;
+            }
+            Ok(Cow::Borrowed(items))
+        }
+        FloatPolicy::String => Ok(Cow::Owned(items.iter().map(floats_to_strings).collect())),
+    }
+}
+
+/// Formats a double the way ECMAScript's `Number.prototype.toString` does,
+/// as RFC 8785 requires: `1.0` → `1`, `1e21` → `1e+21`, `1e-7` → `1e-7`.
+pub fn format_number(value: f64) -> String {
+    if value == 0.0 {
+        return "0".into();
+    }
+    // `{:e}` yields the shortest round-trip digits, e.g. `-1.2345e-7`.
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("`{:e}` always has an exponent");
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().expect("exponent is an integer") + 1;
+    let sign = if value < 0.0 { "-" } else { "" };
+    let body = if k <= n && n <= 21 {
+        format!("{digits}{}", "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{digits}", "0".repeat(-n as usize))
+    } else {
+        let fraction = if k > 1 {
+            format!(".{}", &digits[1..])
+        } else {
+            String::new()
+        };
+        let exponent_sign = if n - 1 < 0 { "-" } else { "+" };
+        format!(
+            "{}{fraction}e{exponent_sign}{}",
+            &digits[..1],
+            (n - 1).abs()
+        )
+    };
+    format!("{sign}{body}")
+}
+
+/// Writes `value` as compact JSON, with object keys and numbers as `style`
+/// says.
+fn write_value(out: &mut impl fmt::Write, value: &Value, style: Style) -> fmt::Result {
+    match value {
+        Value::Number(number) => match number.as_f64() {
+            Some(float) if style.rfc8785_numbers && number.is_f64() => {
+                out.write_str(&format_number(float))
+            }
+            _ => write!(out, "{number}"),
+        },
+        Value::Array(items) => write_items(out, items, style),
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            if style.sort_keys {
+                entries.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+            }
+            out.write_char('{')?;
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.write_char(',')?;
+                }
+                write!(out, "{}", Value::String(key.clone()))?;
+                out.write_char(':')?;
+                write_value(out, item, style)?;
+            }
+            out.write_char('}')
+        }
+        // `Display` escapes strings straight into `out`.
+        scalar => write!(out, "{scalar}"),
+    }
+}
+
+fn write_items(out: &mut impl fmt::Write, items: &[Value], style: Style) -> fmt::Result {
+    out.write_char('[')?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        write_value(out, item, style)?;
+    }
+    out.write_char(']')
+}
+
+fn reject_floats(value: &Value, pointer: &str) -> Result<(), RejectedNumber> {
+    match value {
+        // Integers outside both `i64` and `u64` are parsed as doubles too;
+        // they are told apart by their magnitude, as `1.0` fits in range.
+        // Both bounds are powers of two, which the nearest integers beyond
+        // them round to.
+        Value::Number(number) if number.is_f64() => {
+            let float = number.as_f64().unwrap_or_default();
+            if float.fract() == 0.0 && (float <= i64::MIN as f64 || float >= u64::MAX as f64) {
+                Err(RejectedNumber::OutOfRange(pointer.to_string()))
+            } else {
+                Err(RejectedNumber::NonInteger(pointer.to_string()))
+            }
+        }
+        Value::Array(items) => items.iter().enumerate().try_for_each(|(i, item)| {
+            reject_floats(item, &pointer_segment(pointer, &i.to_string()))
+        }),
+        Value::Object(map) => map
+            .iter()
+            .try_for_each(|(key, item)| reject_floats(item, &pointer_segment(pointer, key))),
+        _ => Ok(()),
+    }
+}
+
+fn floats_to_strings(value: &Value) -> Value {
+    match value {
+        Value::Number(number) if number.is_f64() => {
+            Value::String(format_number(number.as_f64().unwrap_or_default()))
+        }
+        Value::Array(items) => Value::Array(items.iter().map(floats_to_strings).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, item)| (key.clone(), floats_to_strings(item)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Appends an RFC 6901 JSON Pointer segment.
+fn pointer_segment(pointer: &str, segment: &str) -> String {
+    format!(
+        "{pointer}/{}",
+        segment.replace('~', "~0").replace('/', "~1")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn canonicalize_sorts_keys_alphabetically() {
+        let mut map = Map::new();
+        map.insert("name".into(), json!("Alice"));
+        map.insert("age".into(), json!(30));
+        assert_eq!(canonicalize(&map), "age=30;name=\"Alice\";");
+    }
+
+    #[test]
+    fn canonicalize_empty_map_returns_empty_string() {
+        assert_eq!(canonicalize(&Map::new()), "");
+    }
+
+    #[test]
+    fn canonicalize_single_entry() {
+        let mut map = Map::new();
+        map.insert("key".into(), json!("value"));
+        assert_eq!(canonicalize(&map), "key=\"value\";");
+    }
+
+    /// The form before entries were written one by one: every `key=value;`
+    /// string built, sorted and joined.
+    fn joined_entries(map: &Map<String, Value>) -> String {
+        let mut entries: Vec<String> = map
+            .iter()
+            .map(|(key, value)| {
+                let mut entry = format!("{key}=");
+                write_value(&mut entry, value, Style::object(FloatPolicy::Legacy)).unwrap();
+                entry.push(';');
+                entry
+            })
+            .collect();
+        entries.sort();
+        entries.concat()
+    }
+
+    #[test]
+    fn keys_containing_separators_sort_like_whole_entries() {
+        let map = object(json!({
+            "a": 2,
+            "a.b": 1,
+            "a=": 0,
+            "a=1": "x",
+            "a=3": 0,
+            "b;": null,
+            "b": [1.5],
+        }));
+        assert_eq!(canonicalize(&map), joined_entries(&map));
+    }
+
+    #[test]
+    fn nested_values_keep_compact_json_form() {
+        let map = object(json!({"a": {"b": true, "z": [1, "x\"y", null]}}));
+        assert_eq!(canonicalize(&map), r#"a={"b":true,"z":[1,"x\"y",null]};"#);
+    }
+
+    /// RFC 8785 Appendix B sample values.
+    #[test]
+    fn numbers_follow_rfc8785() {
+        for (value, expected) in [
+            (0.0, "0"),
+            (-0.0, "0"),
+            (1.0, "1"),
+            (0.1, "0.1"),
+            (-1.5, "-1.5"),
+            (1e21, "1e+21"),
+            (1e20, "100000000000000000000"),
+            (123456789012345680000.0, "123456789012345680000"),
+            (0.000001, "0.000001"),
+            (1e-7, "1e-7"),
+            (4.5e-7, "4.5e-7"),
+            (5e-324, "5e-324"),
+            (1.7976931348623157e308, "1.7976931348623157e+308"),
+            (9007199254740992.0, "9007199254740992"),
+            (333333333.3333333, "333333333.3333333"),
+        ] {
+            assert_eq!(format_number(value), expected, "{value:e}");
+        }
+    }
+
+    #[test]
+    fn equal_doubles_canonicalize_identically() {
+        let a = object(json!({"amount": 1.0, "rate": 0.10}));
+        let b = object(serde_json::from_str(r#"{"amount": 1.00, "rate": 1e-1}"#).unwrap());
+        let canonical = |map| canonicalize_with(map, FloatPolicy::Canonical);
+        assert_eq!(canonical(&a), "amount=1;rate=0.1;");
+        assert_eq!(canonical(&a), canonical(&b));
+    }
+
+    /// The form signed before `float_policy` existed: every entry written
+    /// with `serde_json`'s `Display`.
+    #[test]
+    fn default_form_keeps_numbers_as_serde_json_writes_them() {
+        let map = object(json!({"amount": 1.0, "big": 1e3, "n": {"x": [0.5, 2]}}));
+        let legacy: String = {
+            let mut entries: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{key}={value};"))
+                .collect();
+            entries.sort();
+            entries.concat()
+        };
+        assert_eq!(
+            canonicalize(&map),
+            r#"amount=1.0;big=1000.0;n={"x":[0.5,2]};"#
+        );
+        assert_eq!(canonicalize(&map), legacy);
+        assert_eq!(canonicalize_with(&map, FloatPolicy::Legacy), legacy);
+        assert_eq!(FloatPolicy::default(), FloatPolicy::Legacy);
+    }
+
+    #[test]
+    fn reject_policy_reports_the_first_float() {
+        let map = object(json!({"count": 3, "items": [{"a/b": 2.5}]}));
+        assert_eq!(
+            apply_float_policy(&map, FloatPolicy::Reject),
+            Err(RejectedNumber::NonInteger("/items/0/a~1b".into()))
+        );
+        let integers = object(json!({"count": 3, "nested": {"n": -4}}));
+        assert!(apply_float_policy(&integers, FloatPolicy::Reject).is_ok());
+    }
+
+    #[test]
+    fn reject_policy_refuses_integers_beyond_64_bits() {
+        let parse = |json: &str| object(serde_json::from_str(json).unwrap());
+        let limits = parse(r#"{"max": 18446744073709551615, "min": -9223372036854775808}"#);
+        assert!(apply_float_policy(&limits, FloatPolicy::Reject).is_ok());
+        for json in [
+            r#"{"id": 18446744073709551616}"#,
+            r#"{"id": -9223372036854775809}"#,
+            r#"{"id": 1e21}"#,
+        ] {
+            assert_eq!(
+                apply_float_policy(&parse(json), FloatPolicy::Reject),
+                Err(RejectedNumber::OutOfRange("/id".into())),
+                "{json}"
+            );
+        }
+        assert_eq!(
+            apply_float_policy(&parse(r#"{"id": 1.0}"#), FloatPolicy::Reject),
+            Err(RejectedNumber::NonInteger("/id".into()))
+        );
+    }
+
+    #[test]
+    fn string_policy_signs_floats_like_strings() {
+        let numbers = object(json!({"price": 0.1, "tags": [2.50], "qty": 2}));
+        let strings = object(json!({"price": "0.1", "tags": ["2.5"], "qty": 2}));
+        let coerced = apply_float_policy(&numbers, FloatPolicy::String).unwrap();
+        assert_eq!(canonicalize(&coerced), canonicalize(&strings));
+    }
+    #[test]
+    fn arrays_keep_element_order_and_sort_nested_keys() {
+        let events = [
+            json!({"type": "click", "at": 2, "meta": {"z": 1, "a": [{"y": 0.50, "b": null}]}}),
+            json!("x\"y"),
+            json!(1.0),
+        ];
+        assert_eq!(
+            canonicalize_array(&events),
+            r#"[{"at":2,"meta":{"a":[{"b":null,"y":0.5}],"z":1},"type":"click"},"x\"y",1]"#
+        );
+        let reversed: Vec<Value> = events.iter().rev().cloned().collect();
+        assert_ne!(canonicalize_array(&reversed), canonicalize_array(&events));
+    }
+
+    #[test]
+    fn array_keys_sort_by_utf16_code_units() {
+        // U+E000 sorts after U+1F600 in UTF-16 but before it in UTF-8.
+        let items = [json!({"\u{e000}": 1, "\u{1f600}": 2})];
+        assert_eq!(
+            canonicalize_array(&items),
+            "[{\"\u{1f600}\":2,\"\u{e000}\":1}]"
+        );
+    }
+
+    #[test]
+    fn array_form_never_matches_an_object_form() {
+        assert_eq!(canonicalize_array(&[]), "[]");
+        assert_eq!(canonicalize(&Map::new()), "");
+        let map = object(json!({"[": "]"}));
+        assert_eq!(canonicalize(&map), "[=\"]\";");
+    }
+
+    #[test]
+    fn float_policies_apply_to_array_elements() {
+        let items = [json!({"n": 1}), json!([2.5])];
+        assert_eq!(
+            apply_float_policy_array(&items, FloatPolicy::Reject),
+            Err(RejectedNumber::NonInteger("/1/0".into()))
+        );
+        let coerced = apply_float_policy_array(&items, FloatPolicy::String).unwrap();
+        assert_eq!(canonicalize_array(&coerced), r#"[{"n":1},["2.5"]]"#);
+    }
+}
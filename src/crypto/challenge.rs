@@ -0,0 +1,142 @@
+//! Challenges for proof of key possession. The service issues a
+//! time-limited nonce for a `keyid`, signed with its own key so it needs no
+//! storage, and the holder of `keyid` answers with an HMAC-SHA256 over the
+//! challenge string. A challenge is `<payload>.<signature>`, where the
+//! payload is the base64url JSON `{"exp","iat","keyid","nonce"}`.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::hmac::HMacSigner;
+use crate::crypto::signer::Signer;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ChallengeError {
+    #[error("malformed challenge: {0}")]
+    Malformed(&'static str),
+    /// The challenge was not issued by this service.
+    #[error("challenge signature does not match")]
+    Forged,
+    #[error("challenge expired")]
+    Expired,
+    #[error("unknown keyid")]
+    UnknownKey,
+    #[error("challenge response does not match")]
+    Invalid,
+    #[error("challenge was already answered")]
+    Replayed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Challenge {
+    /// Unix seconds after which responses are refused.
+    pub exp: u64,
+    /// Unix seconds the challenge was issued.
+    pub iat: u64,
+    /// Key whose holder is challenged.
+    pub keyid: String,
+    /// Base64url random bytes.
+    pub nonce: String,
+}
+
+impl Challenge {
+    /// A challenge for `keyid` issued at `now`, valid for `ttl_secs`.
+    pub fn new(keyid: &str, nonce: &[u8], now: u64, ttl_secs: u64) -> Self {
+        Self {
+            exp: now.saturating_add(ttl_secs),
+            iat: now,
+            keyid: keyid.to_string(),
+            nonce: URL_SAFE_NO_PAD.encode(nonce),
+        }
+    }
+
+    /// The part of the challenge the service signs.
+    pub fn payload(&self) -> String {
+        let json = serde_json::to_vec(self).expect("challenges serialize");
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    /// Splits `challenge` into the decoded challenge, its payload and the
+    /// service's signature over the payload.
+    pub fn parse(challenge: &str) -> Result<(Self, &str, &str), ChallengeError> {
+        // Base64url has no `.`, so the first one ends the payload even when
+        // the signature is an envelope.
+        let (payload, signature) = challenge.split_once('.').ok_or(ChallengeError::Malformed(
+            "expected `<payload>.<signature>`",
+        ))?;
+        let json = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| ChallengeError::Malformed("payload is not base64url"))?;
+        let decoded = serde_json::from_slice(&json)
+            .map_err(|_| ChallengeError::Malformed("payload is not a challenge"))?;
+        Ok((decoded, payload, signature))
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now > self.exp
+    }
+}
+
+/// The answer of the holder of `key` to `challenge`: its hex HMAC-SHA256.
+pub fn respond(key: &HMacSigner, challenge: &str) -> String {
+    key.sign_bytes(challenge.as_bytes())
+}
+
+/// Checks `response` to `challenge` against the challenged `key`.
+pub fn verify_response(
+    key: &HMacSigner,
+    challenge: &str,
+    response: &str,
+) -> Result<(), ChallengeError> {
+    if key.verify_bytes(challenge.as_bytes(), response) {
+        Ok(())
+    } else {
+        Err(ChallengeError::Invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenges_round_trip() {
+        let challenge = Challenge::new("partner", &[7; 16], 1_000, 60);
+        assert_eq!(challenge.exp, 1_060);
+        let token = format!("{}.v1.hmac-sha256.abcd", challenge.payload());
+
+        let (parsed, payload, signature) = Challenge::parse(&token).unwrap();
+        assert_eq!(parsed, challenge);
+        assert_eq!(payload, challenge.payload());
+        assert_eq!(signature, "v1.hmac-sha256.abcd");
+        assert!(!parsed.is_expired(1_060));
+        assert!(parsed.is_expired(1_061));
+    }
+
+    #[test]
+    fn malformed_challenges_are_rejected() {
+        for token in ["", "no-signature", "!!.sig", "e30.sig"] {
+            assert!(matches!(
+                Challenge::parse(token),
+                Err(ChallengeError::Malformed(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn responses_need_the_challenged_key() {
+        let key = HMacSigner::new(b"partner-secret".to_vec());
+        let response = respond(&key, "challenge");
+        assert_eq!(verify_response(&key, "challenge", &response), Ok(()));
+        assert_eq!(
+            verify_response(&key, "other", &response),
+            Err(ChallengeError::Invalid)
+        );
+        let other = HMacSigner::new(b"other-secret".to_vec());
+        assert_eq!(
+            verify_response(&other, "challenge", &response),
+            Err(ChallengeError::Invalid)
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Standard-alphabet, padded base64 for bulk data: `Base64Encryptor`
+//! ciphertexts, sealed response bodies and blobs. With the `simd-base64`
+//! feature the work is done by `base64-simd` (AVX2 / SSE4.1 / NEON picked at
+//! runtime, simd128 on wasm); both engines produce the same strings and
+//! reject the same non-canonical input.
+
+#[cfg(not(feature = "simd-base64"))]
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+#[cfg(feature = "simd-base64")]
+use base64_simd::STANDARD;
+
+#[cfg_attr(
+    not(any(
+        feature = "encryption",
+        feature = "response-encryption",
+        feature = "escrow"
+    )),
+    allow(dead_code)
+)]
+pub fn encode(bytes: &[u8]) -> String {
+    #[cfg(feature = "simd-base64")]
+    return STANDARD.encode_to_string(bytes);
+    #[cfg(not(feature = "simd-base64"))]
+    return STANDARD.encode(bytes);
+}
+
+/// `None` for anything that is not canonical padded base64.
+#[cfg_attr(
+    not(any(feature = "blobs", feature = "response-encryption", feature = "escrow")),
+    allow(dead_code)
+)]
+pub fn decode(encoded: &str) -> Option<Vec<u8>> {
+    #[cfg(feature = "simd-base64")]
+    return STANDARD.decode_to_vec(encoded).ok();
+    #[cfg(not(feature = "simd-base64"))]
+    return STANDARD.decode(encoded).ok();
+}
+
+/// [`decode`], appending to `out`; `false` for non-canonical input.
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+pub fn decode_into(encoded: &str, out: &mut Vec<u8>) -> bool {
+    #[cfg(feature = "simd-base64")]
+    return STANDARD.decode_append(encoded, out).is_ok();
+    #[cfg(not(feature = "simd-base64"))]
+    return STANDARD.decode_vec(encoded, out).is_ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4648 section 10.
+    #[test]
+    fn matches_rfc4648_vectors() {
+        for (plain, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode(plain.as_bytes()), encoded);
+            assert_eq!(decode(encoded).as_deref(), Some(plain.as_bytes()));
+            let mut out = b"prefix".to_vec();
+            assert!(decode_into(encoded, &mut out));
+            assert_eq!(out, [b"prefix", plain.as_bytes()].concat());
+        }
+    }
+
+    #[test]
+    fn large_inputs_round_trip() {
+        let bytes: Vec<u8> = (0..=255).cycle().take(1 << 20).collect();
+        assert_eq!(decode(&encode(&bytes)), Some(bytes));
+    }
+
+    #[test]
+    fn non_canonical_input_is_rejected() {
+        for bad in ["Zg", "Zh==", "Zm9v!", "Zm 9v", "Zm9v-_==", "Z==="] {
+            assert_eq!(decode(bad), None, "{bad}");
+            assert!(!decode_into(bad, &mut Vec::new()), "{bad}");
+        }
+    }
+}
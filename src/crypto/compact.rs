@@ -0,0 +1,236 @@
+//! Compact binary signatures, returned by `/sign` to clients that send
+//! `Accept: application/octet-stream` (see [`requested`]) and cannot afford
+//! JSON and text encodings. A fixed header names the algorithm and key, followed by the
+//! raw signature bytes:
+//!
+//! ```text
+//! version (1) | alg length (1) | alg | kid length (1) | kid | signature
+//! ```
+//!
+//! Lengths are single bytes, so `alg` and `kid` are at most 255 bytes of
+//! ASCII; the signature runs to the end of the body.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+use crate::crypto::hmac::decode_hex;
+
+pub const CONTENT_TYPE: &str = "application/octet-stream";
+
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactSignature<'a> {
+    pub alg: &'a str,
+    pub kid: &'a str,
+    pub signature: &'a [u8],
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum CompactError {
+    #[error("unsupported compact signature version {0}")]
+    UnsupportedVersion(u8),
+    #[error("compact signature is truncated")]
+    Truncated,
+    #[error("`{0}` must be 1 to 255 bytes of printable ASCII")]
+    InvalidField(&'static str),
+    #[error("compact signature carries no signature bytes")]
+    EmptySignature,
+}
+
+impl<'a> CompactSignature<'a> {
+    pub fn new(alg: &'a str, kid: &'a str, signature: &'a [u8]) -> Self {
+        Self {
+            alg,
+            kid,
+            signature,
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, CompactError> {
+        let mut out =
+            Vec::with_capacity(3 + self.alg.len() + self.kid.len() + self.signature.len());
+        out.push(VERSION);
+        for (name, field) in [("alg", self.alg), ("kid", self.kid)] {
+            if !valid_field(field.as_bytes()) {
+                return Err(CompactError::InvalidField(name));
+            }
+            out.push(field.len() as u8);
+            out.extend_from_slice(field.as_bytes());
+        }
+        if self.signature.is_empty() {
+            return Err(CompactError::EmptySignature);
+        }
+        out.extend_from_slice(self.signature);
+        Ok(out)
+    }
+
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, CompactError> {
+        let (&version, mut rest) = bytes.split_first().ok_or(CompactError::Truncated)?;
+        if version != VERSION {
+            return Err(CompactError::UnsupportedVersion(version));
+        }
+        let mut fields = [""; 2];
+        for (slot, name) in fields.iter_mut().zip(["alg", "kid"]) {
+            let (&len, tail) = rest.split_first().ok_or(CompactError::Truncated)?;
+            let (field, tail) = tail
+                .split_at_checked(len.into())
+                .ok_or(CompactError::Truncated)?;
+            if !valid_field(field) {
+                return Err(CompactError::InvalidField(name));
+            }
+            *slot = std::str::from_utf8(field).expect("printable ASCII is UTF-8");
+            rest = tail;
+        }
+        if rest.is_empty() {
+            return Err(CompactError::EmptySignature);
+        }
+        let [alg, kid] = fields;
+        Ok(Self::new(alg, kid, rest))
+    }
+}
+
+fn valid_field(field: &[u8]) -> bool {
+    (1..=255).contains(&field.len()) && field.iter().all(u8::is_ascii_graphic)
+}
+
+/// Whether `Accept` header values ask for a compact signature: they name
+/// `application/octet-stream` itself with a nonzero quality, no lower than
+/// that of JSON. Wildcards never select it, so `*/*` keeps JSON responses.
+pub fn requested<'a>(accept: impl IntoIterator<Item = &'a str>) -> bool {
+    let ranges: Vec<(&str, f32)> = accept
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .filter_map(media_range)
+        .collect();
+    // The most specific range naming one of `types` decides.
+    let quality = |types: &[&str]| {
+        types.iter().find_map(|media_type| {
+            ranges
+                .iter()
+                .find(|(range, _)| range.eq_ignore_ascii_case(media_type))
+                .map(|&(_, quality)| quality)
+        })
+    };
+    let Some(binary) = quality(&[CONTENT_TYPE]) else {
+        return false;
+    };
+    let json = quality(&["application/json", "application/*", "*/*"]).unwrap_or(0.0);
+    binary > 0.0 && binary >= json
+}
+
+/// A media range of `Accept` and its quality, or `None` if it is
+/// malformed.
+fn media_range(range: &str) -> Option<(&str, f32)> {
+    let mut parts = range.split(';');
+    let media_type = parts.next()?.trim();
+    if media_type.is_empty() {
+        return None;
+    }
+    let mut quality = 1.0;
+    for param in parts {
+        let (name, value) = param.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("q") {
+            quality = value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|quality| (0.0..=1.0).contains(quality))?;
+        }
+    }
+    Some((media_type, quality))
+}
+
+/// The raw bytes of a `signature` produced by an `alg` signer: HMAC tags
+/// are hex, asymmetric signatures base64url without padding.
+pub fn raw_signature(alg: &str, signature: &str) -> Option<Vec<u8>> {
+    if alg.starts_with("hmac-") {
+        decode_hex(signature)
+    } else {
+        URL_SAFE_NO_PAD.decode(signature).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_the_binary_layout() {
+        let tag = [0xab; 32];
+        let compact = CompactSignature::new("hmac-sha256", "default", &tag);
+        let bytes = compact.encode().unwrap();
+        assert_eq!(bytes[..2], [1, 11]);
+        assert_eq!(&bytes[2..13], b"hmac-sha256");
+        assert_eq!(bytes[13], 7);
+        assert_eq!(&bytes[14..21], b"default");
+        assert_eq!(bytes[21..], tag);
+        assert_eq!(CompactSignature::parse(&bytes), Ok(compact));
+    }
+
+    #[test]
+    fn malformed_bodies_are_rejected() {
+        let bytes = CompactSignature::new("ed25519", "k1", &[1, 2, 3])
+            .encode()
+            .unwrap();
+        for len in 0..bytes.len() - 3 {
+            assert_eq!(
+                CompactSignature::parse(&bytes[..len]),
+                Err(CompactError::Truncated),
+                "{len} bytes"
+            );
+        }
+        assert_eq!(
+            CompactSignature::parse(&bytes[..bytes.len() - 3]),
+            Err(CompactError::EmptySignature)
+        );
+        let mut other = bytes.clone();
+        other[0] = 2;
+        assert_eq!(
+            CompactSignature::parse(&other),
+            Err(CompactError::UnsupportedVersion(2))
+        );
+        let long = "k".repeat(256);
+        assert_eq!(
+            CompactSignature::new("ed25519", &long, &[1]).encode(),
+            Err(CompactError::InvalidField("kid"))
+        );
+    }
+
+    #[test]
+    fn accept_headers_are_negotiated_by_quality() {
+        for (accept, compact) in [
+            ("application/octet-stream", true),
+            ("Application/Octet-Stream ; charset=x", true),
+            ("application/json, application/octet-stream", true),
+            (
+                "application/json;q=0.5, application/octet-stream;q=0.9",
+                true,
+            ),
+            ("application/octet-stream;q=0", false),
+            ("application/octet-stream; q=0.0", false),
+            ("application/octet-stream;q=0.5, application/json", false),
+            ("application/octet-stream;q=0.5, */*", false),
+            ("application/octet-streamx", false),
+            ("application/x-octet-stream", false),
+            ("text/plain; note=application/octet-stream", false),
+            ("application/octet-stream;q=2", false),
+            ("*/*", false),
+            ("application/*", false),
+        ] {
+            assert_eq!(requested([accept]), compact, "{accept}");
+        }
+        assert!(requested([
+            "application/json;q=0.1",
+            "application/octet-stream"
+        ]));
+        assert!(!requested([]));
+    }
+
+    #[test]
+    fn signatures_are_decoded_by_algorithm() {
+        assert_eq!(raw_signature("hmac-sha256", "00ff"), Some(vec![0, 255]));
+        assert_eq!(raw_signature("ed25519", "AP8"), Some(vec![0, 255]));
+        assert_eq!(raw_signature("hmac-sha256", "AP8"), None);
+    }
+}
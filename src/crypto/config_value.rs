@@ -0,0 +1,143 @@
+//! Encrypted configuration values. A secret meant for the config file (a
+//! database password, a backend API key) is encrypted with
+//! `take-home-cli config-value encrypt` and written as `enc:v1:...`; the
+//! server decrypts it at startup with the master key from
+//! `CONFIG_MASTER_KEY`, so config management only ever holds ciphertext.
+//!
+//! The value key is derived from the master key with HKDF-SHA256 and values
+//! are sealed with ChaCha20-Poly1305 under a random nonce:
+//! `enc:v1:<base64url(nonce || ciphertext)>`.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Marks a string as an encrypted value.
+pub const PREFIX: &str = "enc:v1:";
+
+const HKDF_INFO: &[u8] = b"take-home config values v1";
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ConfigValueError {
+    #[error("not an encrypted value (expected the `{PREFIX}` prefix)")]
+    NotEncrypted,
+    #[error("encrypted value is not base64url")]
+    Malformed,
+    #[error("wrong master key or tampered value")]
+    Forged,
+    #[error("decrypted value is not UTF-8")]
+    NotUtf8,
+}
+
+/// Whether `value` is an encrypted value rather than a plaintext.
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// A new random master key: 32 bytes, base64url-encoded so it can be passed
+/// as `CONFIG_MASTER_KEY` as is.
+pub fn generate_master_key() -> String {
+    URL_SAFE_NO_PAD.encode(ChaCha20Poly1305::generate_key(&mut OsRng))
+}
+
+/// Encrypts and decrypts values under one master key.
+pub struct ConfigCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl ConfigCipher {
+    pub fn new(master_key: &[u8]) -> Self {
+        let mut key = Key::default();
+        Hkdf::<Sha256>::new(None, master_key)
+            .expand(HKDF_INFO, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        Self {
+            cipher: ChaCha20Poly1305::new(&key),
+        }
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, payload(plaintext.as_bytes()))
+            .expect("ChaCha20-Poly1305 encrypts any value of config size");
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        format!("{PREFIX}{}", URL_SAFE_NO_PAD.encode(sealed))
+    }
+
+    pub fn decrypt(&self, value: &str) -> Result<String, ConfigValueError> {
+        let encoded = value
+            .strip_prefix(PREFIX)
+            .ok_or(ConfigValueError::NotEncrypted)?;
+        let sealed = URL_SAFE_NO_PAD
+            .decode(encoded.trim())
+            .map_err(|_| ConfigValueError::Malformed)?;
+        if sealed.len() < NONCE_LEN {
+            return Err(ConfigValueError::Malformed);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), payload(ciphertext))
+            .map_err(|_| ConfigValueError::Forged)?;
+        String::from_utf8(plaintext).map_err(|_| ConfigValueError::NotUtf8)
+    }
+}
+
+/// The prefix is authenticated too, so a value cannot be moved to a later
+/// format version without re-encryption.
+fn payload(msg: &[u8]) -> Payload<'_, '_> {
+    Payload {
+        msg,
+        aad: PREFIX.as_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let cipher = ConfigCipher::new(b"master key");
+        let value = cipher.encrypt("postgres://app:hunter2@db/app");
+        assert!(is_encrypted(&value));
+        assert!(!value.contains("hunter2"));
+        assert_eq!(
+            cipher.decrypt(&value).unwrap(),
+            "postgres://app:hunter2@db/app"
+        );
+        assert_ne!(cipher.encrypt("same"), cipher.encrypt("same"));
+        assert_ne!(generate_master_key(), generate_master_key());
+    }
+
+    #[test]
+    fn other_keys_and_tampering_are_detected() {
+        let value = ConfigCipher::new(b"master key").encrypt("secret");
+        assert_eq!(
+            ConfigCipher::new(b"other key").decrypt(&value),
+            Err(ConfigValueError::Forged)
+        );
+        let mut tampered = value.clone().into_bytes();
+        let last = tampered.len() - 1;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        let cipher = ConfigCipher::new(b"master key");
+        assert!(cipher.decrypt(&tampered).is_err());
+        assert_eq!(
+            cipher.decrypt("secret"),
+            Err(ConfigValueError::NotEncrypted)
+        );
+        assert_eq!(
+            cipher.decrypt("enc:v1:***"),
+            Err(ConfigValueError::Malformed)
+        );
+    }
+}
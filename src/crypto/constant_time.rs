@@ -0,0 +1,78 @@
+//! Comparisons whose running time does not depend on where the compared
+//! values differ. Checks of caller-supplied MACs, digests and key ids go
+//! through here rather than `==`, which stops at the first differing byte.
+//! Lengths are not hidden: every value compared here has a public length.
+
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
+
+/// Whether `a` and `b` hold the same bytes.
+pub fn eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Whether any of `matches` holds. Unlike [`Iterator::any`], every item is
+/// evaluated, so the time taken does not reveal which one matched.
+pub fn any(matches: impl IntoIterator<Item = bool>) -> bool {
+    matches
+        .into_iter()
+        .fold(Choice::from(0), |found, matched| {
+            found | Choice::from(u8::from(matched))
+        })
+        .into()
+}
+
+/// Index of the first of `keys` equal to `needle`. Every key is compared,
+/// so the time taken does not reveal which one matched, or whether any did.
+pub fn position<K: AsRef<[u8]>>(keys: impl IntoIterator<Item = K>, needle: &[u8]) -> Option<usize> {
+    let mut found = Choice::from(0);
+    let mut index = 0u64;
+    for (i, key) in keys.into_iter().enumerate() {
+        let matched = key.as_ref().ct_eq(needle) & !found;
+        index.conditional_assign(&(i as u64), matched);
+        found |= matched;
+    }
+    bool::from(found).then_some(index as usize)
+}
+
+/// Value stored under `id` in `entries`, looked up with [`position`].
+pub fn find<'a, T>(entries: &'a [(String, T)], id: &str) -> Option<&'a T> {
+    position(entries.iter().map(|(key, _)| key), id.as_bytes()).map(|i| &entries[i].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eq_compares_contents_and_length() {
+        assert!(eq(b"tag", b"tag"));
+        assert!(!eq(b"tag", b"tab"));
+        assert!(!eq(b"tag", b"tags"));
+    }
+
+    #[test]
+    fn any_evaluates_every_item() {
+        let mut evaluated = 0;
+        let matched = any([true, false, true].map(|m| {
+            evaluated += 1;
+            m
+        }));
+        assert!(matched);
+        assert_eq!(evaluated, 3);
+        assert!(!any([false, false]));
+    }
+
+    #[test]
+    fn position_returns_the_first_match() {
+        assert_eq!(position(["a", "b", "b"], b"b"), Some(1));
+        assert_eq!(position(["a", "b"], b"c"), None);
+        assert_eq!(position(Vec::<&str>::new(), b"a"), None);
+    }
+
+    #[test]
+    fn find_looks_up_by_id() {
+        let entries = vec![("a".to_string(), 1), ("b".to_string(), 2)];
+        assert_eq!(find(&entries, "b"), Some(&2));
+        assert_eq!(find(&entries, "c"), None);
+    }
+}
@@ -0,0 +1,298 @@
+//! DPoP proofs (RFC 9449). A client proves possession of a key pair by
+//! signing, for each request, a short-lived JWT naming the request's method
+//! and URL, with the public key in its header. Access tokens issued to a
+//! client that presented a proof carry the key's thumbprint in `cnf.jkt`,
+//! so a stolen token is useless without the private key.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::crypto::jwk::{Jwk, JwkError};
+
+/// JWS algorithms accepted for proofs.
+pub const ALGORITHMS: [&str; 3] = ["RS256", "ES256", "EdDSA"];
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum DpopError {
+    #[error("malformed DPoP proof: {0}")]
+    Malformed(&'static str),
+    #[error("unsupported DPoP proof algorithm `{0}`")]
+    UnsupportedAlgorithm(String),
+    #[error("invalid DPoP proof key: {0}")]
+    Key(#[from] JwkError),
+    #[error("DPoP proof signature does not match")]
+    Forged,
+    /// The proof was made for another request.
+    #[error("DPoP proof does not match the request's {0}")]
+    Mismatch(&'static str),
+    #[error(
+        "DPoP proof is more than {max_age_secs}s (+{skew_secs}s clock skew) old or in the future"
+    )]
+    Stale { max_age_secs: u64, skew_secs: u64 },
+    #[error("DPoP proof was already used")]
+    Replayed,
+    #[error("access token is not bound to a DPoP key")]
+    Unbound,
+    #[error("access token is bound to another key")]
+    WrongKey,
+}
+
+#[derive(Deserialize)]
+struct ProofHeader {
+    typ: String,
+    alg: String,
+    jwk: Jwk,
+}
+
+/// Claims of a proof.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofClaims {
+    /// Unique per proof, so each is accepted once.
+    pub jti: String,
+    /// HTTP method of the request.
+    pub htm: String,
+    /// URL of the request, without query and fragment.
+    pub htu: String,
+    pub iat: u64,
+    /// Hash of the access token sent with the request; see [`ath`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ath: Option<String>,
+}
+
+/// A proof whose signature and claims were checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    pub claims: ProofClaims,
+    /// RFC 7638 thumbprint of the proof key, as in `cnf.jkt`.
+    pub jkt: String,
+}
+
+/// The request a proof must have been made for.
+#[derive(Debug, Clone, Copy)]
+pub struct Expected<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    /// The access token sent with the request, which the proof's `ath`
+    /// must hash to; `None` at the token endpoint.
+    pub access_token: Option<&'a str>,
+    pub now: u64,
+    /// How far `iat` may be from `now`, either way.
+    pub max_age_secs: u64,
+    /// Clock skew allowed on top of `max_age_secs`.
+    pub skew_secs: u64,
+}
+
+/// The `ath` of proofs sent with `access_token`: its base64url SHA-256.
+pub fn ath(access_token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(access_token.as_bytes()))
+}
+
+/// Checks `proof` against the `expected` request (RFC 9449 §4.3). Whether
+/// its `jti` was seen before is left to the caller.
+pub fn verify(proof: &str, expected: &Expected<'_>) -> Result<Proof, DpopError> {
+    let parts: Vec<&str> = proof.split('.').collect();
+    let [header, claims, signature] = parts[..] else {
+        return Err(DpopError::Malformed("expected a compact JWS"));
+    };
+    let header: ProofHeader = decode_json(header, "header is not a JWS header")?;
+    if header.typ != "dpop+jwt" {
+        return Err(DpopError::Malformed("`typ` must be `dpop+jwt`"));
+    }
+    if !ALGORITHMS.contains(&header.alg.as_str()) {
+        return Err(DpopError::UnsupportedAlgorithm(header.alg));
+    }
+    let jwk = header.jwk;
+    if jwk.d.is_some() || jwk.k.is_some() {
+        return Err(DpopError::Malformed("`jwk` must be a public key"));
+    }
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| DpopError::Malformed("signature is not base64url"))?;
+    let signing_input = &proof[..proof.len() - parts[2].len() - 1];
+    if !jwk.verify_jws(&header.alg, signing_input.as_bytes(), &signature)? {
+        return Err(DpopError::Forged);
+    }
+    let claims: ProofClaims = decode_json(claims, "claims are not DPoP claims")?;
+    if claims.jti.is_empty() {
+        return Err(DpopError::Malformed("`jti` is empty"));
+    }
+    if claims.htm != expected.method {
+        return Err(DpopError::Mismatch("method"));
+    }
+    if without_query(&claims.htu) != without_query(expected.url) {
+        return Err(DpopError::Mismatch("URL"));
+    }
+    if claims.iat.abs_diff(expected.now) > expected.max_age_secs.saturating_add(expected.skew_secs)
+    {
+        return Err(DpopError::Stale {
+            max_age_secs: expected.max_age_secs,
+            skew_secs: expected.skew_secs,
+        });
+    }
+    if let Some(access_token) = expected.access_token {
+        let hash = ath(access_token);
+        if claims.ath.as_deref() != Some(hash.as_str()) {
+            return Err(DpopError::Mismatch("access token"));
+        }
+    }
+    Ok(Proof {
+        claims,
+        jkt: jwk.thumbprint()?,
+    })
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(
+    part: &str,
+    malformed: &'static str,
+) -> Result<T, DpopError> {
+    let json = URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| DpopError::Malformed(malformed))?;
+    serde_json::from_slice(&json).map_err(|_| DpopError::Malformed(malformed))
+}
+
+fn without_query(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::asymmetric::AsymmetricSigner;
+    use crate::crypto::keys::load_private_key;
+    use crate::crypto::signer::Signer;
+
+    /// A proof by the fixture Ed25519 key, and the key's thumbprint.
+    fn proof(claims: &ProofClaims) -> (String, String) {
+        let key = load_private_key(
+            include_str!("../../tests/fixtures/keys/ed25519.pem").as_bytes(),
+            None,
+        )
+        .unwrap();
+        let jwk = Jwk::from_private_key(None, &key).to_public().unwrap();
+        let header = serde_json::json!({"typ": "dpop+jwt", "alg": "EdDSA", "jwk": jwk});
+        let encode = |value: &[u8]| URL_SAFE_NO_PAD.encode(value);
+        let signing_input = format!(
+            "{}.{}",
+            encode(&serde_json::to_vec(&header).unwrap()),
+            encode(&serde_json::to_vec(claims).unwrap())
+        );
+        let signature = AsymmetricSigner::new(key).sign_bytes(signing_input.as_bytes());
+        (
+            format!("{signing_input}.{signature}"),
+            jwk.thumbprint().unwrap(),
+        )
+    }
+
+    fn claims() -> ProofClaims {
+        ProofClaims {
+            jti: "j1".into(),
+            htm: "GET".into(),
+            htu: "https://api.internal/reports".into(),
+            iat: 1_000,
+            ath: Some(ath("token")),
+        }
+    }
+
+    fn expected() -> Expected<'static> {
+        Expected {
+            method: "GET",
+            url: "https://api.internal/reports?page=2",
+            access_token: Some("token"),
+            now: 1_030,
+            max_age_secs: 60,
+            skew_secs: 0,
+        }
+    }
+
+    #[test]
+    fn proofs_name_their_request_and_key() {
+        let (token, jkt) = proof(&claims());
+        let verified = verify(&token, &expected()).unwrap();
+        assert_eq!(verified.jkt, jkt);
+        assert_eq!(verified.claims, claims());
+    }
+
+    #[test]
+    fn proofs_for_other_requests_are_rejected() {
+        let (token, _) = proof(&claims());
+        for (expected, err) in [
+            (
+                Expected {
+                    method: "POST",
+                    ..expected()
+                },
+                DpopError::Mismatch("method"),
+            ),
+            (
+                Expected {
+                    url: "https://api.internal/admin",
+                    ..expected()
+                },
+                DpopError::Mismatch("URL"),
+            ),
+            (
+                Expected {
+                    access_token: Some("other"),
+                    ..expected()
+                },
+                DpopError::Mismatch("access token"),
+            ),
+            (
+                Expected {
+                    now: 1_061,
+                    ..expected()
+                },
+                DpopError::Stale {
+                    max_age_secs: 60,
+                    skew_secs: 0,
+                },
+            ),
+        ] {
+            assert_eq!(verify(&token, &expected), Err(err));
+        }
+    }
+
+    #[test]
+    fn clock_skew_widens_the_iat_window() {
+        let (token, _) = proof(&claims());
+        let skewed = Expected {
+            now: 1_065,
+            skew_secs: 5,
+            ..expected()
+        };
+        assert!(verify(&token, &skewed).is_ok());
+        assert_eq!(
+            verify(
+                &token,
+                &Expected {
+                    now: 1_066,
+                    ..skewed
+                }
+            ),
+            Err(DpopError::Stale {
+                max_age_secs: 60,
+                skew_secs: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn tampered_and_malformed_proofs_are_rejected() {
+        let (token, _) = proof(&claims());
+        let (other, _) = proof(&ProofClaims {
+            htm: "POST".into(),
+            ..claims()
+        });
+        let parts: Vec<&str> = token.split('.').collect();
+        let other_claims = other.split('.').nth(1).unwrap();
+        let tampered = format!("{}.{other_claims}.{}", parts[0], parts[2]);
+        assert_eq!(verify(&tampered, &expected()), Err(DpopError::Forged));
+        assert!(matches!(
+            verify("a.b", &expected()),
+            Err(DpopError::Malformed(_))
+        ));
+    }
+}
@@ -1,6 +1,127 @@
 use serde_json::Value;
 
-pub trait Encryptor {
-    fn encrypt(&self, value: &Value) -> Value;
-    fn decrypt(&self, value: &Value) -> Option<Value>;
+use crate::crypto::BoxFuture;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptError {
+    #[error("failed to serialize value: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("encryption backend failed: {0}")]
+    Backend(String),
+    #[error("encryption backend timed out")]
+    Timeout,
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum DecryptError {
+    /// The value was not produced by this encryptor. Callers that accept
+    /// mixed plaintext and ciphertext pass such values through.
+    #[error("value is not ciphertext")]
+    NotCiphertext,
+    /// The value looks like ciphertext but failed its integrity check, so
+    /// it was tampered with or encrypted under a different key.
+    #[error("ciphertext failed authentication")]
+    AuthenticationFailed,
+    #[error("decryption backend failed: {0}")]
+    Backend(String),
+    #[error("decryption backend timed out")]
+    Timeout,
+}
+
+/// Key id the service's encryptor is recorded under, in `_crypto` sidecars
+/// and key usage counts.
+pub const ENCRYPTION_KEY_ID: &str = "encryption";
+
+pub trait Encryptor: Send + Sync {
+    fn encrypt(&self, value: &Value) -> Result<Value, EncryptError>;
+    fn decrypt(&self, value: &Value) -> Result<Value, DecryptError>;
+}
+
+/// Async counterpart of [`Encryptor`] for out-of-process backends (KMS,
+/// Vault, HSM). Every [`Encryptor`] is an `AsyncEncryptor`.
+pub trait AsyncEncryptor: Send + Sync {
+    fn encrypt<'a>(&'a self, value: &'a Value) -> BoxFuture<'a, Result<Value, EncryptError>>;
+    fn decrypt<'a>(&'a self, value: &'a Value) -> BoxFuture<'a, Result<Value, DecryptError>>;
+}
+
+impl<T: Encryptor + ?Sized> AsyncEncryptor for T {
+    fn encrypt<'a>(&'a self, value: &'a Value) -> BoxFuture<'a, Result<Value, EncryptError>> {
+        let result = Encryptor::encrypt(self, value);
+        Box::pin(async move { result })
+    }
+
+    fn decrypt<'a>(&'a self, value: &'a Value) -> BoxFuture<'a, Result<Value, DecryptError>> {
+        let result = Encryptor::decrypt(self, value);
+        Box::pin(async move { result })
+    }
+}
+
+/// Encrypts every property at depth 1 of `payload` (or the value itself if
+/// it is not an object), replacing each value in place.
+pub fn encrypt_fields(encryptor: &dyn Encryptor, payload: Value) -> Result<Value, EncryptError> {
+    apply_method_to_values(payload, &|v| {
+        *v = encryptor.encrypt(v)?;
+        Ok(())
+    })
+}
+
+/// Decrypts every property at depth 1 of `payload`, leaving values that are
+/// not ciphertext unchanged. Fails if any value fails authentication.
+pub fn decrypt_fields(encryptor: &dyn Encryptor, payload: Value) -> Result<Value, DecryptError> {
+    apply_method_to_values(payload, &|v| match encryptor.decrypt(v) {
+        Ok(decrypted) => {
+            *v = decrypted;
+            Ok(())
+        }
+        Err(DecryptError::NotCiphertext) => Ok(()),
+        Err(err) => Err(err),
+    })
+}
+
+/// Async form of [`encrypt_fields`]. Fields are encrypted one after the
+/// other.
+pub async fn encrypt_fields_async(
+    encryptor: &dyn AsyncEncryptor,
+    mut payload: Value,
+) -> Result<Value, EncryptError> {
+    for value in values_mut(&mut payload) {
+        *value = encryptor.encrypt(value).await?;
+    }
+    Ok(payload)
+}
+
+/// Async form of [`decrypt_fields`].
+pub async fn decrypt_fields_async(
+    encryptor: &dyn AsyncEncryptor,
+    mut payload: Value,
+) -> Result<Value, DecryptError> {
+    for value in values_mut(&mut payload) {
+        match encryptor.decrypt(value).await {
+            Ok(decrypted) => *value = decrypted,
+            Err(DecryptError::NotCiphertext) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(payload)
+}
+
+/// Applies `method` to each top-level value of `values`, which it owns and
+/// updates in place, so neither keys nor untouched values are copied.
+fn apply_method_to_values<E>(
+    mut values: Value,
+    method: &dyn Fn(&mut Value) -> Result<(), E>,
+) -> Result<Value, E> {
+    values_mut(&mut values).try_for_each(method)?;
+    Ok(values)
+}
+
+/// The properties of an object, or the value itself otherwise.
+fn values_mut(payload: &mut Value) -> impl Iterator<Item = &mut Value> {
+    let (map, other) = match payload {
+        Value::Object(map) => (Some(map), None),
+        other => (None, Some(other)),
+    };
+    map.into_iter()
+        .flat_map(|map| map.values_mut())
+        .chain(other)
 }
@@ -0,0 +1,71 @@
+use std::fmt;
+
+use crate::crypto::constant_time;
+
+/// Signature tagged with the algorithm that produced it, serialized as
+/// `v1.<alg>.<signature>`, so verifiers can dispatch without being told the
+/// algorithm out of band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureEnvelope<'a> {
+    pub alg: &'a str,
+    pub signature: &'a str,
+}
+
+const VERSION: &str = "v1";
+
+impl<'a> SignatureEnvelope<'a> {
+    pub fn new(alg: &'a str, signature: &'a str) -> Self {
+        Self { alg, signature }
+    }
+
+    /// Parses an enveloped signature. Returns `None` for bare signatures.
+    /// Only the separators decide where parsing stops; the signature bytes
+    /// are never inspected.
+    pub fn parse(value: &'a str) -> Option<Self> {
+        let (version, rest) = value.split_once('.')?;
+        let (alg, signature) = rest.split_once('.')?;
+        let valid = constant_time::eq(version.as_bytes(), VERSION.as_bytes()) & !alg.is_empty();
+        valid.then_some(Self { alg, signature })
+    }
+}
+
+impl fmt::Display for SignatureEnvelope<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{VERSION}.{}.{}", self.alg, self.signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_display() {
+        let envelope = SignatureEnvelope::new("hmac-sha256", "abc123");
+        let encoded = envelope.to_string();
+        assert_eq!(encoded, "v1.hmac-sha256.abc123");
+        assert_eq!(SignatureEnvelope::parse(&encoded), Some(envelope));
+    }
+
+    #[test]
+    fn bare_signatures_are_not_envelopes() {
+        assert_eq!(SignatureEnvelope::parse("abc123"), None);
+    }
+
+    #[test]
+    fn unknown_version_is_not_an_envelope() {
+        assert_eq!(SignatureEnvelope::parse("v2.hmac-sha256.abc123"), None);
+    }
+
+    #[test]
+    fn signature_may_contain_dots() {
+        let envelope = SignatureEnvelope::parse("v1.ed25519.a.b").unwrap();
+        assert_eq!(envelope.alg, "ed25519");
+        assert_eq!(envelope.signature, "a.b");
+    }
+
+    #[test]
+    fn empty_alg_is_rejected() {
+        assert_eq!(SignatureEnvelope::parse("v1..abc"), None);
+    }
+}
@@ -0,0 +1,225 @@
+//! Hierarchical deterministic signing keys: a BIP39 mnemonic is stretched
+//! into a seed, and Ed25519 keys are derived from it along SLIP-0010 paths
+//! such as `m/44'/1237'/0'/3'`. Every key can be recreated from the backed-up
+//! mnemonic and its path, so per-purpose keys need not be stored.
+
+use std::fmt;
+use std::str::FromStr;
+
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use sha2::Sha512;
+
+use crate::crypto::keys::PrivateKey;
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// Index offset of hardened children, written with a trailing `'`.
+const HARDENED: u32 = 1 << 31;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum HdError {
+    #[error("invalid BIP39 mnemonic: {0}")]
+    InvalidMnemonic(String),
+    #[error(
+        "invalid derivation path `{0}`: expected `m` followed by hardened \
+         indices, e.g. `m/44'/1237'/0'`"
+    )]
+    InvalidPath(String),
+    #[error("mnemonics have 12, 15, 18, 21 or 24 words")]
+    InvalidWordCount,
+}
+
+/// A SLIP-0010 path of hardened child indices. Ed25519 has no public
+/// derivation, so every index must be hardened (`'` or `h`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl FromStr for DerivationPath {
+    type Err = HdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || HdError::InvalidPath(s.to_string());
+        let mut segments = s.split('/');
+        if segments.next() != Some("m") {
+            return Err(invalid());
+        }
+        segments
+            .map(|segment| {
+                let index = segment
+                    .strip_suffix(['\'', 'h'])
+                    .ok_or_else(invalid)?
+                    .parse::<u32>()
+                    .map_err(|_| invalid())?;
+                if index < HARDENED {
+                    Ok(index | HARDENED)
+                } else {
+                    Err(invalid())
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("m")?;
+        self.0
+            .iter()
+            .try_for_each(|index| write!(f, "/{}'", index & !HARDENED))
+    }
+}
+
+/// Root of a key hierarchy, usually the 64-byte BIP39 seed of a mnemonic.
+/// Never printed.
+pub struct Seed(Vec<u8>);
+
+impl fmt::Debug for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Seed(***)")
+    }
+}
+
+impl Seed {
+    pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Self {
+        Self(bytes.into())
+    }
+
+    /// Checks the mnemonic's words and checksum and stretches it, with the
+    /// optional passphrase, into a seed.
+    pub fn from_mnemonic(mnemonic: &str, passphrase: &str) -> Result<Self, HdError> {
+        let mnemonic = bip39::Mnemonic::parse(mnemonic)
+            .map_err(|err| HdError::InvalidMnemonic(err.to_string()))?;
+        Ok(Self(mnemonic.to_seed(passphrase).to_vec()))
+    }
+
+    /// The 32-byte private key at `path` (SLIP-0010, Ed25519 curve).
+    pub fn derive(&self, path: &DerivationPath) -> [u8; 32] {
+        let (mut key, mut chain_code) = split(hmac_sha512(b"ed25519 seed", &[&self.0]));
+        for index in &path.0 {
+            (key, chain_code) = split(hmac_sha512(
+                &chain_code,
+                &[&[0], &key, &index.to_be_bytes()],
+            ));
+        }
+        key
+    }
+
+    /// The Ed25519 signing key at `path`.
+    pub fn derive_ed25519(&self, path: &DerivationPath) -> PrivateKey {
+        PrivateKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&self.derive(path)))
+    }
+}
+
+/// A fresh English mnemonic of `words` words.
+pub fn generate_mnemonic(words: usize) -> Result<String, HdError> {
+    if !matches!(words, 12 | 15 | 18 | 21 | 24) {
+        return Err(HdError::InvalidWordCount);
+    }
+    let mut entropy = vec![0u8; words / 3 * 4];
+    OsRng.fill_bytes(&mut entropy);
+    let mnemonic = bip39::Mnemonic::from_entropy(&entropy)
+        .expect("entropy of a valid word count is always accepted");
+    Ok(mnemonic.to_string())
+}
+
+fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+    let mut mac = HmacSha512::new_from_slice(key).expect("HMAC accepts keys of any length");
+    parts.iter().for_each(|part| mac.update(part));
+    mac.finalize().into_bytes().into()
+}
+
+fn split(bytes: [u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let (left, right) = bytes.split_at(32);
+    (
+        left.try_into().expect("split in halves"),
+        right.try_into().expect("split in halves"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    const ABANDON: &str = "abandon abandon abandon abandon abandon abandon \
+                           abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn paths_must_be_hardened() {
+        let path: DerivationPath = "m/44'/1237h/0'".parse().unwrap();
+        assert_eq!(path.to_string(), "m/44'/1237'/0'");
+        assert_eq!("m".parse::<DerivationPath>().unwrap().to_string(), "m");
+        for bad in ["", "44'", "m/44", "m/x'", "m/2147483648'", "m//0'"] {
+            assert!(
+                matches!(bad.parse::<DerivationPath>(), Err(HdError::InvalidPath(_))),
+                "{bad}"
+            );
+        }
+    }
+
+    // BIP39 reference vector.
+    #[test]
+    fn mnemonic_seed_matches_bip39() {
+        let Seed(seed) = Seed::from_mnemonic(ABANDON, "TREZOR").unwrap();
+        assert_eq!(
+            hex(&seed),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+    }
+
+    // SLIP-0010 test vector 1 for ed25519.
+    #[test]
+    fn derivation_matches_slip10() {
+        let seed = Seed::from_bytes((0u8..16).collect::<Vec<_>>());
+        assert_eq!(
+            hex(&seed.derive(&"m".parse().unwrap())),
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            hex(&seed.derive(&"m/0'".parse().unwrap())),
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3"
+        );
+    }
+
+    #[test]
+    fn invalid_mnemonics_are_refused() {
+        let bad_checksum = ABANDON.replace("about", "abandon");
+        assert!(matches!(
+            Seed::from_mnemonic(&bad_checksum, ""),
+            Err(HdError::InvalidMnemonic(_))
+        ));
+    }
+
+    #[test]
+    fn generated_mnemonics_round_trip() {
+        let mnemonic = generate_mnemonic(24).unwrap();
+        assert_eq!(mnemonic.split(' ').count(), 24);
+        assert!(Seed::from_mnemonic(&mnemonic, "").is_ok());
+        assert_eq!(generate_mnemonic(13), Err(HdError::InvalidWordCount));
+    }
+
+    #[test]
+    fn keys_are_reproducible_and_distinct_per_path() {
+        let seed = Seed::from_mnemonic(ABANDON, "").unwrap();
+        let path = |p: &str| p.parse::<DerivationPath>().unwrap();
+        assert_eq!(
+            seed.derive(&path("m/44'/1237'/0'")),
+            Seed::from_mnemonic(ABANDON, "")
+                .unwrap()
+                .derive(&path("m/44'/1237'/0'"))
+        );
+        assert_ne!(
+            seed.derive(&path("m/44'/1237'/0'")),
+            seed.derive(&path("m/44'/1237'/1'"))
+        );
+        assert_eq!(
+            seed.derive_ed25519(&path("m/44'/1237'/0'")).algorithm(),
+            "ed25519"
+        );
+    }
+}
@@ -1,68 +1,197 @@
+use std::fmt;
+
 use hmac::{Hmac, Mac};
 use serde_json::{Map, Value};
-use sha2::Sha256;
+use sha2::{Sha256, Sha384, Sha512};
 
+use crate::crypto::canonical::{write_canonical, write_canonical_array};
+use crate::crypto::constant_time;
 use crate::crypto::signer::Signer;
 
+/// Hash function inside the MAC. Each is registered as its own algorithm,
+/// `hmac-<digest>`, so the envelope of a signature records which one made
+/// it and the default can move off SHA-256 while older signatures still
+/// verify.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HmacDigest {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HmacDigest {
+    pub const ALL: [HmacDigest; 3] = [HmacDigest::Sha256, HmacDigest::Sha384, HmacDigest::Sha512];
+
+    /// Name used in the `digest` query parameter.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HmacDigest::Sha256 => "sha256",
+            HmacDigest::Sha384 => "sha384",
+            HmacDigest::Sha512 => "sha512",
+        }
+    }
+
+    /// Name of the algorithm, as in the `alg` query parameter and in
+    /// signature envelopes.
+    pub fn algorithm(self) -> &'static str {
+        match self {
+            HmacDigest::Sha256 => "hmac-sha256",
+            HmacDigest::Sha384 => "hmac-sha384",
+            HmacDigest::Sha512 => "hmac-sha512",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|digest| digest.as_str() == name)
+    }
+}
+
 pub struct HMacSigner {
-    key: Vec<u8>,
+    /// HMAC state with the key already absorbed; cloned for each operation
+    /// instead of re-deriving the padded key blocks every time.
+    prepared: Prepared,
+}
+
+enum Prepared {
+    Sha256(Hmac<Sha256>),
+    Sha384(Hmac<Sha384>),
+    Sha512(Hmac<Sha512>),
 }
 
 impl HMacSigner {
+    /// HMAC-SHA256 with `key`.
     pub fn new(key: Vec<u8>) -> Self {
-        Self { key }
+        Self::with_digest(key, HmacDigest::Sha256)
+    }
+
+    pub fn with_digest(key: Vec<u8>, digest: HmacDigest) -> Self {
+        // HMAC accepts keys of any length.
+        let prepared = match digest {
+            HmacDigest::Sha256 => Prepared::Sha256(Hmac::new_from_slice(&key).unwrap()),
+            HmacDigest::Sha384 => Prepared::Sha384(Hmac::new_from_slice(&key).unwrap()),
+            HmacDigest::Sha512 => Prepared::Sha512(Hmac::new_from_slice(&key).unwrap()),
+        };
+        Self { prepared }
+    }
+
+    pub fn digest(&self) -> HmacDigest {
+        match self.prepared {
+            Prepared::Sha256(_) => HmacDigest::Sha256,
+            Prepared::Sha384(_) => HmacDigest::Sha384,
+            Prepared::Sha512(_) => HmacDigest::Sha512,
+        }
+    }
+
+    /// Raw HMAC tag of `bytes`, for formats that encode it themselves.
+    pub fn mac(&self, bytes: &[u8]) -> Vec<u8> {
+        self.tag(|mac| mac.write(bytes))
+    }
+
+    /// Verifies a raw tag using constant-time comparison to prevent timing
+    /// attacks.
+    pub fn verify_mac(&self, bytes: &[u8], tag: &[u8]) -> bool {
+        constant_time::eq(&self.mac(bytes), tag)
+    }
+
+    /// HMAC of the canonical form of `map`, fed to the MAC as it is written
+    /// rather than built as one string first.
+    fn mac_canonical(&self, map: &Map<String, Value>) -> Vec<u8> {
+        self.tag(|mut mac| write_canonical(&mut mac, map).expect("updating a MAC cannot fail"))
+    }
+
+    /// [`mac_canonical`](Self::mac_canonical) for a top-level array.
+    fn mac_canonical_array(&self, items: &[Value]) -> Vec<u8> {
+        self.tag(|mut mac| {
+            write_canonical_array(&mut mac, items).expect("updating a MAC cannot fail")
+        })
+    }
+
+    /// Runs `write` against a fresh copy of the prepared state and returns
+    /// the tag.
+    fn tag(&self, write: impl FnOnce(&mut dyn MacSink)) -> Vec<u8> {
+        fn finish<M: Mac + Clone>(prepared: &M, write: impl FnOnce(&mut dyn MacSink)) -> Vec<u8> {
+            let mut mac = MacWriter(prepared.clone());
+            write(&mut mac);
+            mac.0.finalize().into_bytes().to_vec()
+        }
+        match &self.prepared {
+            Prepared::Sha256(prepared) => finish(prepared, write),
+            Prepared::Sha384(prepared) => finish(prepared, write),
+            Prepared::Sha512(prepared) => finish(prepared, write),
+        }
     }
 }
 
-impl HMacSigner {
-    /// Builds a deterministic string from a JSON object by sorting entries
-    /// alphabetically by key.
-    ///
-    /// NOTE: Nested object values are serialized using `serde_json`'s `Display`,
-    /// whose key order depends on insertion order (not sorted). This means two
-    /// objects that are semantically identical but have differently-ordered nested
-    /// keys would produce different signatures. Because the API operates at
-    /// depth 1 (same as `/encrypt`), this is acceptable for the current scope.
-    /// A recursive canonicalization (sorting keys at every depth) would remove
-    /// this limitation if deeper guarantees were needed.
-    fn map_to_string(&self, map: &Map<String, Value>) -> String {
-        let mut to_sign: Vec<String> = map.iter().map(|(k, v)| format!("{k}={v};")).collect();
-        to_sign.sort();
-        to_sign.join("")
+/// A MAC being fed, whichever its digest.
+trait MacSink: fmt::Write {
+    fn write(&mut self, bytes: &[u8]);
+}
+
+struct MacWriter<M>(M);
+
+impl<M: Mac> MacSink for MacWriter<M> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+impl<M: Mac> fmt::Write for MacWriter<M> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.update(s.as_bytes());
+        Ok(())
     }
 }
 
 impl Signer for HMacSigner {
-    fn sign(&self, map: &Map<String, Value>) -> Value {
-        let concatenated = self.map_to_string(map);
+    fn sign_bytes(&self, bytes: &[u8]) -> String {
+        hex(&self.mac(bytes))
+    }
+
+    fn verify_bytes(&self, bytes: &[u8], signature: &str) -> bool {
+        match decode_hex(signature) {
+            Some(tag) => self.verify_mac(bytes, &tag),
+            None => false,
+        }
+    }
 
-        let mut signature = Hmac::<Sha256>::new_from_slice(self.key.as_slice()).unwrap();
-        signature.update(concatenated.as_bytes());
-        let result = signature.finalize();
-        Value::String(format!("{:x}", result.into_bytes()))
+    fn sign(&self, map: &Map<String, Value>) -> String {
+        hex(&self.mac_canonical(map))
     }
 
-    /// Verifies a signature against a map using constant-time comparison
-    /// to prevent timing attacks.
     fn verify(&self, map: &Map<String, Value>, signature: &str) -> bool {
-        let concatenated = self.map_to_string(map);
-
-        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.as_slice()).unwrap();
-        mac.update(concatenated.as_bytes());
+        match decode_hex(signature) {
+            Some(tag) => constant_time::eq(&self.mac_canonical(map), &tag),
+            None => false,
+        }
+    }
 
-        // Decode the hex signature back to bytes
-        let sig_bytes: Result<Vec<u8>, _> = (0..signature.len())
-            .step_by(2)
-            .map(|i| u8::from_str_radix(&signature[i..i + 2], 16))
-            .collect();
+    fn sign_array(&self, items: &[Value]) -> String {
+        hex(&self.mac_canonical_array(items))
+    }
 
-        match sig_bytes {
-            Ok(bytes) => mac.verify_slice(&bytes).is_ok(),
-            Err(_) => false,
+    fn verify_array(&self, items: &[Value], signature: &str) -> bool {
+        match decode_hex(signature) {
+            Some(tag) => constant_time::eq(&self.mac_canonical_array(items), &tag),
+            None => false,
         }
     }
 }
 
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Decodes a hex string, returning `None` on odd lengths or non-hex input.
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,31 +208,6 @@ mod tests {
         map
     }
 
-    // ── map_to_string ──────────────────────────────────────────────
-
-    #[test]
-    fn map_to_string_sorts_keys_alphabetically() {
-        let signer = make_signer();
-        let map = sample_map(); // keys: "age", "name"
-        let result = signer.map_to_string(&map);
-        assert_eq!(result, "age=30;name=\"Alice\";");
-    }
-
-    #[test]
-    fn map_to_string_empty_map_returns_empty_string() {
-        let signer = make_signer();
-        let map = Map::new();
-        assert_eq!(signer.map_to_string(&map), "");
-    }
-
-    #[test]
-    fn map_to_string_single_entry() {
-        let signer = make_signer();
-        let mut map = Map::new();
-        map.insert("key".into(), json!("value"));
-        assert_eq!(signer.map_to_string(&map), "key=\"value\";");
-    }
-
     // ── sign ───────────────────────────────────────────────────────
 
     #[test]
@@ -112,7 +216,7 @@ mod tests {
         let map = sample_map();
         let signature = signer.sign(&map);
         // Signature should be a hex-encoded string (64 hex chars for SHA-256)
-        let sig_str = signature.as_str().unwrap();
+        let sig_str = signature.as_str();
         assert_eq!(sig_str.len(), 64);
         assert!(sig_str.chars().all(|c| c.is_ascii_hexdigit()));
     }
@@ -150,7 +254,7 @@ mod tests {
         let signer = make_signer();
         let map = sample_map();
         let signature = signer.sign(&map);
-        let sig_str = signature.as_str().unwrap();
+        let sig_str = signature.as_str();
         assert!(signer.verify(&map, sig_str));
     }
 
@@ -159,7 +263,7 @@ mod tests {
         let signer = make_signer();
         let map = sample_map();
         let signature = signer.sign(&map);
-        let sig_str = signature.as_str().unwrap();
+        let sig_str = signature.as_str();
 
         let mut tampered = Map::new();
         tampered.insert("name".into(), json!("Eve"));
@@ -182,22 +286,106 @@ mod tests {
         assert!(!signer.verify(&map, "not-valid-hex!!"));
     }
 
+    #[test]
+    fn verify_returns_false_for_odd_length_or_non_ascii_hex() {
+        let signer = make_signer();
+        let map = sample_map();
+        assert!(!signer.verify(&map, "abc"));
+        assert!(!signer.verify(&map, "é0"));
+    }
+
     #[test]
     fn verify_returns_false_for_different_key() {
         let signer_a = HMacSigner::new(b"key-a".to_vec());
         let signer_b = HMacSigner::new(b"key-b".to_vec());
         let map = sample_map();
         let sig = signer_a.sign(&map);
-        let sig_str = sig.as_str().unwrap();
+        let sig_str = sig.as_str();
         assert!(!signer_b.verify(&map, sig_str));
     }
 
+    #[test]
+    fn sign_matches_sign_bytes_of_canonical_form() {
+        let signer = make_signer();
+        let map = sample_map();
+        let canonical = crate::crypto::canonical::canonicalize(&map);
+        assert_eq!(signer.sign(&map), signer.sign_bytes(canonical.as_bytes()));
+    }
+
+    #[test]
+    fn sign_array_matches_sign_bytes_of_canonical_form() {
+        let signer = make_signer();
+        let events = [json!({"b": 1, "a": 2}), json!({"a": 3})];
+        let canonical = crate::crypto::canonical::canonicalize_array(&events);
+        let signature = signer.sign_array(&events);
+        assert_eq!(signature, signer.sign_bytes(canonical.as_bytes()));
+        assert!(signer.verify_array(&events, &signature));
+        assert!(!signer.verify_array(&[events[1].clone(), events[0].clone()], &signature));
+    }
+
+    #[test]
+    fn sign_bytes_then_verify_bytes_round_trip() {
+        let signer = make_signer();
+        let signature = signer.sign_bytes(b"raw body");
+        assert!(signer.verify_bytes(b"raw body", &signature));
+        assert!(!signer.verify_bytes(b"raw body!", &signature));
+    }
+
+    // RFC 4231, test case 2.
+    #[test]
+    fn mac_matches_rfc4231() {
+        let signer = HMacSigner::new(b"Jefe".to_vec());
+        assert_eq!(
+            signer.sign_bytes(b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    // RFC 4231, test case 2.
+    #[test]
+    fn longer_digests_match_rfc4231() {
+        let data = b"what do ya want for nothing?";
+        let sha384 = HMacSigner::with_digest(b"Jefe".to_vec(), HmacDigest::Sha384);
+        assert_eq!(
+            sha384.sign_bytes(data),
+            "af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e\
+             8e2240ca5e69e2c78b3239ecfab21649"
+        );
+        let sha512 = HMacSigner::with_digest(b"Jefe".to_vec(), HmacDigest::Sha512);
+        assert_eq!(
+            sha512.sign_bytes(data),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+             9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+        );
+    }
+
+    #[test]
+    fn digests_do_not_verify_each_other() {
+        let sha256 = make_signer();
+        let sha512 = HMacSigner::with_digest(b"super-secret-key".to_vec(), HmacDigest::Sha512);
+        let map = sample_map();
+        assert_eq!(sha512.sign(&map).len(), 128);
+        assert!(sha512.verify(&map, &sha512.sign(&map)));
+        assert!(!sha512.verify(&map, &sha256.sign(&map)));
+        assert_eq!(HmacDigest::from_name("sha384"), Some(HmacDigest::Sha384));
+        assert_eq!(HmacDigest::from_name("md5"), None);
+    }
+
+    #[test]
+    fn operations_do_not_share_state() {
+        let signer = make_signer();
+        let first = signer.mac(b"second");
+        signer.mac(b"first");
+        assert!(signer.verify_mac(b"other", &signer.mac(b"other")));
+        assert_eq!(signer.mac(b"second"), first);
+    }
+
     #[test]
     fn verify_empty_map_round_trip() {
         let signer = make_signer();
         let map = Map::new();
         let sig = signer.sign(&map);
-        let sig_str = sig.as_str().unwrap();
+        let sig_str = sig.as_str();
         assert!(signer.verify(&map, sig_str));
     }
 }
@@ -0,0 +1,863 @@
+//! HTTP Message Signatures (RFC 9421) with the `hmac-sha256` algorithm, and
+//! the `Content-Digest` field (RFC 9530) that lets a signature cover the
+//! message body.
+//!
+//! Only request signatures are supported. Component identifiers may be
+//! header fields or the derived components `@method`, `@target-uri`,
+//! `@authority`, `@scheme`, `@request-target`, `@path` and `@query`;
+//! component parameters (`;sf`, `;key`, `;req`, ...) are rejected.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use sha2::{Digest, Sha256};
+
+use crate::crypto::constant_time;
+use crate::crypto::hmac::HMacSigner;
+
+/// The only `alg` value this module signs and verifies.
+pub const ALGORITHM: &str = "hmac-sha256";
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum HttpSignatureError {
+    #[error("missing `{0}` header")]
+    MissingHeader(&'static str),
+    #[error("malformed signature: {0}")]
+    Malformed(String),
+    #[error("no signature labelled `{0}`")]
+    UnknownLabel(String),
+    #[error("covered component `{0}` is not present in the message")]
+    MissingComponent(String),
+    #[error("unsupported component `{0}`")]
+    UnsupportedComponent(String),
+    #[error("unsupported algorithm `{0}`")]
+    UnsupportedAlgorithm(String),
+    #[error("no key for the signature's keyid")]
+    UnknownKey,
+    #[error("signature expired at {expires} (+{skew_secs}s clock skew)")]
+    Expired { expires: u64, skew_secs: u64 },
+    #[error(
+        "signature created at {created} is outside the {max_age_secs}s window \
+         (+{skew_secs}s clock skew)"
+    )]
+    Stale {
+        created: u64,
+        max_age_secs: u64,
+        skew_secs: u64,
+    },
+    #[error("signature does not match")]
+    Invalid,
+    #[error("content digest does not match the body")]
+    DigestMismatch,
+}
+
+/// The parts of an HTTP request a signature can cover.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpRequest<'a> {
+    pub method: &'a str,
+    /// Absolute URI, e.g. `https://example.com/foo?bar=1`.
+    pub target_uri: &'a str,
+    /// Header fields. Names are matched case-insensitively and repeated
+    /// fields are combined in order.
+    pub headers: &'a [(String, String)],
+}
+
+/// Metadata of a signature, serialized into the `Signature-Input` field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SignatureParams {
+    /// Covered component identifiers, in signing order.
+    pub components: Vec<String>,
+    pub created: Option<u64>,
+    pub expires: Option<u64>,
+    pub keyid: Option<String>,
+    pub alg: Option<String>,
+    pub nonce: Option<String>,
+    pub tag: Option<String>,
+}
+
+impl SignatureParams {
+    /// Serializes the parameters as a `Signature-Input` member value, e.g.
+    /// `("@method" "@path");created=1618884473;keyid="k1"`.
+    pub fn serialize(&self) -> Result<String, HttpSignatureError> {
+        let mut out = String::from("(");
+        for (i, component) in self.components.iter().enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            out.push_str(&sf_string(component)?);
+        }
+        out.push(')');
+        if let Some(created) = self.created {
+            out.push_str(&format!(";created={created}"));
+        }
+        if let Some(expires) = self.expires {
+            out.push_str(&format!(";expires={expires}"));
+        }
+        for (name, value) in [
+            ("keyid", &self.keyid),
+            ("alg", &self.alg),
+            ("nonce", &self.nonce),
+            ("tag", &self.tag),
+        ] {
+            if let Some(value) = value {
+                out.push_str(&format!(";{name}={}", sf_string(value)?));
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Values of the `Signature-Input` and `Signature` header fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureHeaders {
+    pub signature_input: String,
+    pub signature: String,
+}
+
+/// A signature that verified successfully.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedSignature {
+    pub label: String,
+    pub params: SignatureParams,
+}
+
+/// Shared secrets keyed by `keyid`, looked up in constant time.
+#[derive(Default)]
+pub struct Keyring {
+    keys: Vec<(String, HMacSigner)>,
+    default: Option<String>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_key(mut self, keyid: impl Into<String>, key: HMacSigner) -> Self {
+        let keyid = keyid.into();
+        self.keys.retain(|(id, _)| *id != keyid);
+        self.keys.push((keyid, key));
+        self
+    }
+
+    /// Key used for signatures that carry no `keyid`.
+    pub fn with_default(mut self, keyid: impl Into<String>) -> Self {
+        self.default = Some(keyid.into());
+        self
+    }
+
+    pub fn default_keyid(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    pub fn get(&self, keyid: Option<&str>) -> Option<&HMacSigner> {
+        constant_time::find(&self.keys, keyid.or(self.default.as_deref())?)
+    }
+}
+
+/// Signs `request` over the components listed in `params`.
+pub fn sign(
+    key: &HMacSigner,
+    request: &HttpRequest,
+    label: &str,
+    params: &SignatureParams,
+) -> Result<SignatureHeaders, HttpSignatureError> {
+    if !is_key(label) {
+        return Err(HttpSignatureError::Malformed(format!(
+            "`{label}` is not a valid signature label"
+        )));
+    }
+    check_alg(params.alg.as_deref())?;
+    let raw = params.serialize()?;
+    let base = signature_base(request, &params.components, &raw)?;
+    Ok(SignatureHeaders {
+        signature_input: format!("{label}={raw}"),
+        signature: format!("{label}=:{}:", STANDARD.encode(key.mac(base.as_bytes()))),
+    })
+}
+
+/// Verifies the signature labelled `label` (or the first one) carried in the
+/// `Signature-Input` / `Signature` headers of `request`. `key_for` maps the
+/// signature's `keyid` to a key; `now` is the current Unix time, used to
+/// reject signatures expired more than `skew_secs` ago.
+pub fn verify<'k>(
+    request: &HttpRequest,
+    label: Option<&str>,
+    now: u64,
+    skew_secs: u64,
+    key_for: impl Fn(Option<&str>) -> Option<&'k HMacSigner>,
+) -> Result<VerifiedSignature, HttpSignatureError> {
+    let input = header(request.headers, "signature-input")
+        .ok_or(HttpSignatureError::MissingHeader("Signature-Input"))?;
+    let signatures = header(request.headers, "signature")
+        .ok_or(HttpSignatureError::MissingHeader("Signature"))?;
+
+    let inputs = parse_signature_input(&input)?;
+    let (label, params, raw) = match label {
+        Some(label) => inputs
+            .into_iter()
+            .find(|(l, _, _)| l == label)
+            .ok_or_else(|| HttpSignatureError::UnknownLabel(label.to_string()))?,
+        None => inputs
+            .into_iter()
+            .next()
+            .ok_or_else(|| HttpSignatureError::Malformed("empty Signature-Input".into()))?,
+    };
+    let tag = parse_byte_dictionary(&signatures)?
+        .into_iter()
+        .find(|(l, _)| *l == label)
+        .map(|(_, tag)| tag)
+        .ok_or_else(|| HttpSignatureError::UnknownLabel(label.clone()))?;
+
+    check_alg(params.alg.as_deref())?;
+    if let Some(expires) = params.expires
+        && expires.saturating_add(skew_secs) <= now
+    {
+        return Err(HttpSignatureError::Expired { expires, skew_secs });
+    }
+    let key = key_for(params.keyid.as_deref()).ok_or(HttpSignatureError::UnknownKey)?;
+    let base = signature_base(request, &params.components, &raw)?;
+    if !key.verify_mac(base.as_bytes(), &tag) {
+        return Err(HttpSignatureError::Invalid);
+    }
+    Ok(VerifiedSignature { label, params })
+}
+
+/// Builds the signature base (RFC 9421 §2.5) for `components`, ending with
+/// the `@signature-params` line carrying `params` verbatim.
+pub fn signature_base(
+    request: &HttpRequest,
+    components: &[String],
+    params: &str,
+) -> Result<String, HttpSignatureError> {
+    let target = Target::parse(request.target_uri)?;
+    let mut base = String::new();
+    for (i, component) in components.iter().enumerate() {
+        if components[..i].contains(component) {
+            return Err(HttpSignatureError::Malformed(format!(
+                "component `{component}` is covered twice"
+            )));
+        }
+        let value = component_value(request, &target, component)?;
+        base.push_str(&format!("\"{component}\": {value}\n"));
+    }
+    base.push_str(&format!("\"@signature-params\": {params}"));
+    Ok(base)
+}
+
+/// `Content-Digest` value of `body` using SHA-256.
+pub fn content_digest(body: &[u8]) -> String {
+    format!("sha-256=:{}:", STANDARD.encode(Sha256::digest(body)))
+}
+
+/// Checks the SHA-256 entry of a `Content-Digest` field against `body`.
+pub fn verify_content_digest(field: &str, body: &[u8]) -> Result<(), HttpSignatureError> {
+    let digests = parse_byte_dictionary(field)?;
+    let (_, expected) = digests
+        .iter()
+        .find(|(alg, _)| alg == "sha-256")
+        .ok_or_else(|| HttpSignatureError::UnsupportedAlgorithm("content-digest".into()))?;
+    if constant_time::eq(expected, &Sha256::digest(body)) {
+        Ok(())
+    } else {
+        Err(HttpSignatureError::DigestMismatch)
+    }
+}
+
+fn check_alg(alg: Option<&str>) -> Result<(), HttpSignatureError> {
+    match alg {
+        Some(alg) if alg != ALGORITHM => Err(HttpSignatureError::UnsupportedAlgorithm(alg.into())),
+        _ => Ok(()),
+    }
+}
+
+fn component_value(
+    request: &HttpRequest,
+    target: &Target,
+    component: &str,
+) -> Result<String, HttpSignatureError> {
+    let value = match component {
+        "@method" => request.method.to_string(),
+        "@target-uri" => target.uri.to_string(),
+        "@authority" => target.authority.to_ascii_lowercase(),
+        "@scheme" => target.scheme.to_ascii_lowercase(),
+        "@request-target" => match target.query {
+            Some(query) => format!("{}?{query}", target.path),
+            None => target.path.to_string(),
+        },
+        "@path" => target.path.to_string(),
+        "@query" => format!("?{}", target.query.unwrap_or_default()),
+        derived if derived.starts_with('@') => {
+            return Err(HttpSignatureError::UnsupportedComponent(derived.into()));
+        }
+        name if name.bytes().any(|b| b.is_ascii_uppercase()) => {
+            return Err(HttpSignatureError::Malformed(format!(
+                "component `{name}` must be lowercase"
+            )));
+        }
+        name => header(request.headers, name)
+            .ok_or_else(|| HttpSignatureError::MissingComponent(name.into()))?,
+    };
+    Ok(value)
+}
+
+/// Combined value of every `name` field, trimmed and joined with `, `.
+fn header(headers: &[(String, String)], name: &str) -> Option<String> {
+    let values: Vec<&str> = headers
+        .iter()
+        .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.trim())
+        .collect();
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+struct Target<'a> {
+    uri: &'a str,
+    scheme: &'a str,
+    authority: &'a str,
+    path: &'a str,
+    query: Option<&'a str>,
+}
+
+impl<'a> Target<'a> {
+    fn parse(uri: &'a str) -> Result<Self, HttpSignatureError> {
+        let uri = uri.split_once('#').map_or(uri, |(uri, _)| uri);
+        let (scheme, rest) = uri
+            .split_once("://")
+            .ok_or_else(|| HttpSignatureError::Malformed("target URI must be absolute".into()))?;
+        let end = rest.find(['/', '?']).unwrap_or(rest.len());
+        let (authority, rest) = rest.split_at(end);
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+        Ok(Self {
+            uri,
+            scheme,
+            authority,
+            path: if path.is_empty() { "/" } else { path },
+            query,
+        })
+    }
+}
+
+// ── structured fields (RFC 8941), limited to what signatures use ───
+
+fn is_key(key: &str) -> bool {
+    let mut bytes = key.bytes();
+    matches!(bytes.next(), Some(b'a'..=b'z' | b'*'))
+        && bytes.all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b'*'))
+}
+
+fn sf_string(value: &str) -> Result<String, HttpSignatureError> {
+    if !value.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+        return Err(HttpSignatureError::Malformed(format!(
+            "`{value}` is not printable ASCII"
+        )));
+    }
+    Ok(format!(
+        "\"{}\"",
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+enum BareItem {
+    Integer(u64),
+    String(String),
+    Other,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    fn error(&self, what: &str) -> HttpSignatureError {
+        HttpSignatureError::Malformed(format!("{what} at offset {}", self.pos))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let matched = self.peek() == Some(byte);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn skip_ows(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos == self.input.len()
+    }
+
+    /// Parses `member ("," member)*`, calling `member` after each `key=`.
+    fn dictionary<T>(
+        &mut self,
+        mut member: impl FnMut(&mut Self, String) -> Result<T, HttpSignatureError>,
+    ) -> Result<Vec<T>, HttpSignatureError> {
+        let mut out = Vec::new();
+        self.skip_ows();
+        while !self.at_end() {
+            let key = self.key()?;
+            if !self.eat(b'=') {
+                return Err(self.error("expected `=`"));
+            }
+            out.push(member(self, key)?);
+            self.skip_ows();
+            if self.at_end() {
+                break;
+            }
+            if !self.eat(b',') {
+                return Err(self.error("expected `,`"));
+            }
+            self.skip_ows();
+        }
+        Ok(out)
+    }
+
+    fn key(&mut self) -> Result<String, HttpSignatureError> {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b'*')
+        ) {
+            self.pos += 1;
+        }
+        let key = &self.input[start..self.pos];
+        if is_key(key) {
+            Ok(key.to_string())
+        } else {
+            Err(self.error("expected a key"))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, HttpSignatureError> {
+        if !self.eat(b'"') {
+            return Err(self.error("expected a string"));
+        }
+        let mut out = String::new();
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c @ (b'"' | b'\\')) => out.push(c as char),
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                Some(c @ 0x20..0x7f) => out.push(c as char),
+                _ => return Err(self.error("unterminated string")),
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn byte_sequence(&mut self) -> Result<Vec<u8>, HttpSignatureError> {
+        if !self.eat(b':') {
+            return Err(self.error("expected a byte sequence"));
+        }
+        let start = self.pos;
+        let end = self.input[start..]
+            .find(':')
+            .map(|i| start + i)
+            .ok_or_else(|| self.error("unterminated byte sequence"))?;
+        self.pos = end + 1;
+        STANDARD
+            .decode(&self.input[start..end])
+            .map_err(|_| HttpSignatureError::Malformed("invalid base64 in byte sequence".into()))
+    }
+
+    fn bare_item(&mut self) -> Result<BareItem, HttpSignatureError> {
+        match self.peek() {
+            Some(b'"') => Ok(BareItem::String(self.string()?)),
+            Some(b'0'..=b'9') => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(b'0'..=b'9')) {
+                    self.pos += 1;
+                }
+                self.input[start..self.pos]
+                    .parse()
+                    .map(BareItem::Integer)
+                    .map_err(|_| self.error("integer out of range"))
+            }
+            Some(b':') => self.byte_sequence().map(|_| BareItem::Other),
+            Some(b'?' | b'A'..=b'Z' | b'a'..=b'z' | b'*' | b'-') => {
+                self.pos += 1;
+                while matches!(self.peek(), Some(c) if c.is_ascii_graphic() && !b"(),;=\"".contains(&c))
+                {
+                    self.pos += 1;
+                }
+                Ok(BareItem::Other)
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    /// Parses `(";" key ["=" bare-item])*`.
+    fn parameters(&mut self) -> Result<Vec<(String, BareItem)>, HttpSignatureError> {
+        let mut out = Vec::new();
+        while self.eat(b';') {
+            self.skip_ows();
+            let key = self.key()?;
+            let value = if self.eat(b'=') {
+                self.bare_item()?
+            } else {
+                BareItem::Other
+            };
+            out.push((key, value));
+        }
+        Ok(out)
+    }
+}
+
+/// Parses a `Signature-Input` field into `(label, params, raw member value)`.
+fn parse_signature_input(
+    field: &str,
+) -> Result<Vec<(String, SignatureParams, String)>, HttpSignatureError> {
+    Parser::new(field).dictionary(|p, label| {
+        let start = p.pos;
+        if !p.eat(b'(') {
+            return Err(p.error("expected an inner list"));
+        }
+        let mut components = Vec::new();
+        loop {
+            while p.eat(b' ') {}
+            if p.eat(b')') {
+                break;
+            }
+            let component = p.string()?;
+            if p.peek() == Some(b';') {
+                return Err(HttpSignatureError::UnsupportedComponent(component));
+            }
+            components.push(component);
+        }
+        let mut params = SignatureParams {
+            components,
+            ..SignatureParams::default()
+        };
+        for (name, value) in p.parameters()? {
+            match (name.as_str(), value) {
+                ("created", BareItem::Integer(v)) => params.created = Some(v),
+                ("expires", BareItem::Integer(v)) => params.expires = Some(v),
+                ("keyid", BareItem::String(v)) => params.keyid = Some(v),
+                ("alg", BareItem::String(v)) => params.alg = Some(v),
+                ("nonce", BareItem::String(v)) => params.nonce = Some(v),
+                ("tag", BareItem::String(v)) => params.tag = Some(v),
+                ("created" | "expires" | "keyid" | "alg" | "nonce" | "tag", _) => {
+                    return Err(HttpSignatureError::Malformed(format!(
+                        "invalid `{name}` parameter"
+                    )));
+                }
+                _ => {}
+            }
+        }
+        Ok((label, params, p.input[start..p.pos].to_string()))
+    })
+}
+
+/// Parses a dictionary whose members are byte sequences (`Signature`,
+/// `Content-Digest`).
+fn parse_byte_dictionary(field: &str) -> Result<Vec<(String, Vec<u8>)>, HttpSignatureError> {
+    Parser::new(field).dictionary(|p, key| {
+        let bytes = p.byte_sequence()?;
+        p.parameters()?;
+        Ok((key, bytes))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Request and shared secret from RFC 9421 Appendix B.2.
+    fn rfc_headers() -> Vec<(String, String)> {
+        [
+            ("Host", "example.com"),
+            ("Date", "Tue, 20 Apr 2021 02:07:55 GMT"),
+            ("Content-Type", "application/json"),
+            (
+                "Content-Digest",
+                "sha-512=:WZDPaVn/7XgHaAy8pmojAkGWoRx2UFChF41A2svX+TaPm+AbwAgBWnrIiYllu7BNNyealdVLvRwEmTHWXvJwew==:",
+            ),
+            ("Content-Length", "18"),
+        ]
+        .into_iter()
+        .map(|(n, v)| (n.to_string(), v.to_string()))
+        .collect()
+    }
+
+    fn rfc_key() -> HMacSigner {
+        HMacSigner::new(
+            STANDARD
+                .decode(
+                    "uzvJfB4u3N0Jy4T7NZ75MDVcr8zSTInedJtkgcu46YW4XByzNJjxBdtjUkdJPBtbmHhIDi6pcl8jsasjlTMtDQ==",
+                )
+                .unwrap(),
+        )
+    }
+
+    fn rfc_params() -> SignatureParams {
+        SignatureParams {
+            components: vec!["date".into(), "@authority".into(), "content-type".into()],
+            created: Some(1618884473),
+            keyid: Some("test-shared-secret".into()),
+            ..SignatureParams::default()
+        }
+    }
+
+    fn request(headers: &[(String, String)]) -> HttpRequest<'_> {
+        HttpRequest {
+            method: "POST",
+            target_uri: "https://example.com/foo?param=Value&Pet=dog",
+            headers,
+        }
+    }
+
+    fn with_signature(
+        mut headers: Vec<(String, String)>,
+        signed: &SignatureHeaders,
+    ) -> Vec<(String, String)> {
+        headers.push(("Signature-Input".into(), signed.signature_input.clone()));
+        headers.push(("Signature".into(), signed.signature.clone()));
+        headers
+    }
+
+    // ── signing ────────────────────────────────────────────────────
+
+    #[test]
+    fn matches_rfc_9421_hmac_test_vector() {
+        let headers = rfc_headers();
+        let signed = sign(&rfc_key(), &request(&headers), "sig-b25", &rfc_params()).unwrap();
+        assert_eq!(
+            signed.signature_input,
+            r#"sig-b25=("date" "@authority" "content-type");created=1618884473;keyid="test-shared-secret""#
+        );
+        assert_eq!(
+            signed.signature,
+            "sig-b25=:pxcQw6G3AjtMBQjwo8XzkZf/bws5LelbaMk5rGIGtE8=:"
+        );
+    }
+
+    #[test]
+    fn derived_components() {
+        let headers = rfc_headers();
+        let components: Vec<String> = [
+            "@method",
+            "@target-uri",
+            "@authority",
+            "@scheme",
+            "@request-target",
+            "@path",
+            "@query",
+        ]
+        .map(String::from)
+        .to_vec();
+        let base = signature_base(&request(&headers), &components, "()").unwrap();
+        assert_eq!(
+            base,
+            "\"@method\": POST\n\
+             \"@target-uri\": https://example.com/foo?param=Value&Pet=dog\n\
+             \"@authority\": example.com\n\
+             \"@scheme\": https\n\
+             \"@request-target\": /foo?param=Value&Pet=dog\n\
+             \"@path\": /foo\n\
+             \"@query\": ?param=Value&Pet=dog\n\
+             \"@signature-params\": ()"
+        );
+    }
+
+    #[test]
+    fn missing_header_component_is_rejected() {
+        let params = SignatureParams {
+            components: vec!["x-missing".into()],
+            ..SignatureParams::default()
+        };
+        assert_eq!(
+            sign(&rfc_key(), &request(&[]), "sig", &params),
+            Err(HttpSignatureError::MissingComponent("x-missing".into()))
+        );
+    }
+
+    #[test]
+    fn unsupported_derived_component_is_rejected() {
+        let params = SignatureParams {
+            components: vec!["@status".into()],
+            ..SignatureParams::default()
+        };
+        assert!(matches!(
+            sign(&rfc_key(), &request(&[]), "sig", &params),
+            Err(HttpSignatureError::UnsupportedComponent(_))
+        ));
+    }
+
+    // ── verification ───────────────────────────────────────────────
+
+    #[test]
+    fn sign_then_verify() {
+        let headers = rfc_headers();
+        let signed = sign(&rfc_key(), &request(&headers), "sig1", &rfc_params()).unwrap();
+        let headers = with_signature(headers, &signed);
+        let key = rfc_key();
+        let verified = verify(&request(&headers), None, 1618884473, 0, |keyid| {
+            (keyid == Some("test-shared-secret")).then_some(&key)
+        })
+        .unwrap();
+        assert_eq!(verified.label, "sig1");
+        assert_eq!(verified.params, rfc_params());
+    }
+
+    #[test]
+    fn tampered_component_is_invalid() {
+        let headers = rfc_headers();
+        let signed = sign(&rfc_key(), &request(&headers), "sig1", &rfc_params()).unwrap();
+        let mut headers = with_signature(headers, &signed);
+        headers[2].1 = "text/plain".into();
+        let key = rfc_key();
+        assert_eq!(
+            verify(&request(&headers), None, 0, 0, |_| Some(&key)),
+            Err(HttpSignatureError::Invalid)
+        );
+    }
+
+    #[test]
+    fn expired_signature_is_rejected() {
+        let params = SignatureParams {
+            expires: Some(100),
+            ..rfc_params()
+        };
+        let headers = rfc_headers();
+        let signed = sign(&rfc_key(), &request(&headers), "sig1", &params).unwrap();
+        let headers = with_signature(headers, &signed);
+        let key = rfc_key();
+        assert!(verify(&request(&headers), None, 99, 0, |_| Some(&key)).is_ok());
+        assert_eq!(
+            verify(&request(&headers), None, 100, 0, |_| Some(&key)),
+            Err(HttpSignatureError::Expired {
+                expires: 100,
+                skew_secs: 0
+            })
+        );
+        assert!(verify(&request(&headers), None, 129, 30, |_| Some(&key)).is_ok());
+        assert_eq!(
+            verify(&request(&headers), None, 130, 30, |_| Some(&key)),
+            Err(HttpSignatureError::Expired {
+                expires: 100,
+                skew_secs: 30
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_key_and_label_are_rejected() {
+        let headers = rfc_headers();
+        let signed = sign(&rfc_key(), &request(&headers), "sig1", &rfc_params()).unwrap();
+        let headers = with_signature(headers, &signed);
+        assert_eq!(
+            verify(&request(&headers), None, 0, 0, |_| None),
+            Err(HttpSignatureError::UnknownKey)
+        );
+        let key = rfc_key();
+        assert_eq!(
+            verify(&request(&headers), Some("sig2"), 0, 0, |_| Some(&key)),
+            Err(HttpSignatureError::UnknownLabel("sig2".into()))
+        );
+    }
+
+    #[test]
+    fn selects_signature_by_label() {
+        let headers = rfc_headers();
+        let first = sign(&rfc_key(), &request(&headers), "a", &rfc_params()).unwrap();
+        let other = HMacSigner::new(b"other".to_vec());
+        let second = sign(&other, &request(&headers), "b", &rfc_params()).unwrap();
+        let mut headers = headers;
+        headers.push((
+            "Signature-Input".into(),
+            format!("{}, {}", first.signature_input, second.signature_input),
+        ));
+        headers.push((
+            "Signature".into(),
+            format!("{}, {}", first.signature, second.signature),
+        ));
+        assert!(verify(&request(&headers), Some("b"), 0, 0, |_| Some(&other)).is_ok());
+        assert_eq!(
+            verify(&request(&headers), Some("a"), 0, 0, |_| Some(&other)),
+            Err(HttpSignatureError::Invalid)
+        );
+    }
+
+    #[test]
+    fn other_algorithms_are_rejected() {
+        let params = SignatureParams {
+            alg: Some("ed25519".into()),
+            ..rfc_params()
+        };
+        let headers = rfc_headers();
+        assert_eq!(
+            sign(&rfc_key(), &request(&headers), "sig1", &params),
+            Err(HttpSignatureError::UnsupportedAlgorithm("ed25519".into()))
+        );
+    }
+
+    #[test]
+    fn malformed_signature_input_is_rejected() {
+        let mut headers = rfc_headers();
+        headers.push(("Signature-Input".into(), "sig1=\"date\"".into()));
+        headers.push(("Signature".into(), "sig1=:AAAA:".into()));
+        let key = rfc_key();
+        assert!(matches!(
+            verify(&request(&headers), None, 0, 0, |_| Some(&key)),
+            Err(HttpSignatureError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn keyring_falls_back_to_default_key() {
+        let keyring = Keyring::new()
+            .with_key("a", HMacSigner::new(b"a".to_vec()))
+            .with_default("a");
+        assert!(keyring.get(None).is_some());
+        assert!(keyring.get(Some("a")).is_some());
+        assert!(keyring.get(Some("b")).is_none());
+        assert!(Keyring::new().get(None).is_none());
+    }
+
+    // ── content digest ─────────────────────────────────────────────
+
+    #[test]
+    fn content_digest_matches_rfc_9530_example() {
+        assert_eq!(
+            content_digest(br#"{"hello": "world"}"#),
+            "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"
+        );
+    }
+
+    #[test]
+    fn content_digest_verification() {
+        let field = content_digest(b"body");
+        assert_eq!(verify_content_digest(&field, b"body"), Ok(()));
+        assert_eq!(
+            verify_content_digest(&field, b"other"),
+            Err(HttpSignatureError::DigestMismatch)
+        );
+        assert!(matches!(
+            verify_content_digest("sha-512=:AAAA:", b"body"),
+            Err(HttpSignatureError::UnsupportedAlgorithm(_))
+        ));
+    }
+}
@@ -0,0 +1,634 @@
+//! JSON Web Keys (RFC 7517): symmetric `oct` keys for the HMAC signers,
+//! `OKP` X25519 keys (RFC 8037) for response sealing and, with the
+//! `asymmetric` feature, `RSA` / `EC` / `OKP` Ed25519 signing keys, so key
+//! material can be exchanged with standard JOSE tooling.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::constant_time;
+#[cfg(feature = "asymmetric")]
+use crate::crypto::keys::PrivateKey;
+#[cfg(any(feature = "response-encryption", feature = "escrow"))]
+use x25519_dalek::{PublicKey, StaticSecret};
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum JwkError {
+    #[error("invalid JWK document: {0}")]
+    Json(String),
+    #[error("unsupported key type `{0}`")]
+    UnsupportedKeyType(String),
+    #[error("JWK is missing the `{0}` member")]
+    MissingMember(&'static str),
+    #[error("JWK member `{0}` is not valid base64url")]
+    InvalidBase64(&'static str),
+    #[error("JWK member `{0}` has the wrong length")]
+    InvalidLength(&'static str),
+    #[error("no key with kid `{0}`")]
+    NoMatchingKey(String),
+    #[error("the key set holds several keys; a kid is required")]
+    AmbiguousKey,
+    #[error("symmetric keys have no public form")]
+    NoPublicForm,
+}
+
+/// A single JSON Web Key. Members this crate does not use are dropped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, rename = "use", skip_serializing_if = "Option::is_none")]
+    pub key_use: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    /// Public key (`OKP`) or x coordinate (`EC`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    /// y coordinate (`EC`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+    /// Private key (`OKP`, `EC`) or private exponent (`RSA`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub d: Option<String>,
+    /// Modulus (`RSA`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    /// Public exponent (`RSA`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    /// CRT parameters of an `RSA` private key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dq: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qi: Option<String>,
+    /// Key value (`oct`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub k: Option<String>,
+}
+
+/// A JWK Set document.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+impl JwkSet {
+    /// Parses either a JWK Set or a single JWK (as a one-key set).
+    pub fn parse(document: &str) -> Result<Self, JwkError> {
+        let value: serde_json::Value =
+            serde_json::from_str(document).map_err(|e| JwkError::Json(e.to_string()))?;
+        let set = if value.get("keys").is_some() {
+            serde_json::from_value(value)
+        } else {
+            serde_json::from_value(value).map(|key| JwkSet { keys: vec![key] })
+        };
+        set.map_err(|e| JwkError::Json(e.to_string()))
+    }
+
+    /// The key with `kid`, or the only key when `kid` is `None`.
+    pub fn find(&self, kid: Option<&str>) -> Result<&Jwk, JwkError> {
+        match kid {
+            Some(kid) => constant_time::position(
+                self.keys
+                    .iter()
+                    .map(|key| key.kid.as_deref().unwrap_or_default()),
+                kid.as_bytes(),
+            )
+            .map(|i| &self.keys[i])
+            .filter(|key| key.kid.is_some())
+            .ok_or_else(|| JwkError::NoMatchingKey(kid.to_string())),
+            None => match self.keys.as_slice() {
+                [key] => Ok(key),
+                _ => Err(JwkError::AmbiguousKey),
+            },
+        }
+    }
+
+    /// The public form of every asymmetric key; symmetric keys are skipped.
+    pub fn to_public(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .keys
+                .iter()
+                .filter_map(|key| key.to_public().ok())
+                .collect(),
+        }
+    }
+}
+
+/// A public JWK Set as served to verifiers: its JSON form, and a strong
+/// entity tag that changes whenever a key is added, removed or rotated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishedJwks {
+    pub json: String,
+    /// Quoted, as sent in `ETag`.
+    pub etag: String,
+}
+
+impl PublishedJwks {
+    /// Publishes the public form of `set`.
+    pub fn new(set: &JwkSet) -> Self {
+        use sha2::{Digest, Sha256};
+
+        let json =
+            serde_json::to_string(&set.to_public()).expect("JWK Sets always serialize to JSON");
+        let digest = Sha256::digest(json.as_bytes());
+        let etag = format!("\"{}\"", URL_SAFE_NO_PAD.encode(&digest[..16]));
+        Self { json, etag }
+    }
+
+    /// Whether an `If-None-Match` value names this version, so a `304 Not
+    /// Modified` can be sent instead of the set. Weak tags match too, as
+    /// RFC 9110 asks for this header.
+    pub fn matches(&self, if_none_match: &str) -> bool {
+        if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == self.etag)
+    }
+}
+
+impl Jwk {
+    /// An `oct` key holding an HMAC secret.
+    pub fn symmetric(kid: Option<String>, key: &[u8]) -> Self {
+        Self {
+            kty: "oct".into(),
+            kid,
+            k: Some(URL_SAFE_NO_PAD.encode(key)),
+            ..Self::default()
+        }
+    }
+
+    /// The secret of an `oct` key.
+    pub fn symmetric_key(&self) -> Result<Vec<u8>, JwkError> {
+        self.expect_kty("oct")?;
+        let key = decode(self.k.as_deref(), "k")?;
+        if key.is_empty() {
+            return Err(JwkError::InvalidLength("k"));
+        }
+        Ok(key)
+    }
+
+    /// The key without its private members. Fails for `oct` keys, which are
+    /// entirely secret.
+    pub fn to_public(&self) -> Result<Jwk, JwkError> {
+        match self.kty.as_str() {
+            "oct" => Err(JwkError::NoPublicForm),
+            "OKP" | "EC" | "RSA" => Ok(Jwk {
+                d: None,
+                p: None,
+                q: None,
+                dp: None,
+                dq: None,
+                qi: None,
+                k: None,
+                ..self.clone()
+            }),
+            other => Err(JwkError::UnsupportedKeyType(other.into())),
+        }
+    }
+
+    /// The private JWK of a signing key, with the matching JWS `alg`.
+    #[cfg(feature = "asymmetric")]
+    pub fn from_private_key(kid: Option<String>, key: &PrivateKey) -> Self {
+        use p256::elliptic_curve::sec1::ToEncodedPoint;
+        use rsa::traits::{PrivateKeyParts, PublicKeyParts};
+
+        let b64 = |bytes: &[u8]| Some(URL_SAFE_NO_PAD.encode(bytes));
+        let base = Self {
+            kid,
+            key_use: Some("sig".into()),
+            ..Self::default()
+        };
+        match key {
+            PrivateKey::Rsa(key) => {
+                let uint = |n: &rsa::BigUint| b64(&n.to_bytes_be());
+                let crt = |n: Option<rsa::BigUint>| n.and_then(|n| uint(&n));
+                let (p, q) = match key.primes() {
+                    [p, q] => (uint(p), uint(q)),
+                    _ => (None, None),
+                };
+                Self {
+                    kty: "RSA".into(),
+                    alg: Some("RS256".into()),
+                    n: uint(key.n()),
+                    e: uint(key.e()),
+                    d: uint(key.d()),
+                    p,
+                    q,
+                    dp: key.dp().and_then(uint),
+                    dq: key.dq().and_then(uint),
+                    qi: crt(key.crt_coefficient()),
+                    ..base
+                }
+            }
+            PrivateKey::EcdsaP256(key) => {
+                let point = key.public_key().to_encoded_point(false);
+                Self {
+                    kty: "EC".into(),
+                    alg: Some("ES256".into()),
+                    crv: Some("P-256".into()),
+                    x: point.x().and_then(|x| b64(x)),
+                    y: point.y().and_then(|y| b64(y)),
+                    d: b64(&key.to_bytes()),
+                    ..base
+                }
+            }
+            PrivateKey::Ed25519(key) => Self {
+                kty: "OKP".into(),
+                alg: Some("EdDSA".into()),
+                crv: Some("Ed25519".into()),
+                x: b64(key.verifying_key().as_bytes()),
+                d: b64(key.as_bytes()),
+                ..base
+            },
+        }
+    }
+
+    /// The RFC 7638 SHA-256 thumbprint of an asymmetric key, base64url: the
+    /// hash of its required public members in lexicographic order.
+    #[cfg(feature = "asymmetric")]
+    pub fn thumbprint(&self) -> Result<String, JwkError> {
+        use sha2::{Digest, Sha256};
+
+        // Members are embedded verbatim, so only base64url and curve names
+        // hash to what other parties compute.
+        fn member<'a>(value: &'a Option<String>, name: &'static str) -> Result<&'a str, JwkError> {
+            let value = value.as_deref().ok_or(JwkError::MissingMember(name))?;
+            if value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                Ok(value)
+            } else {
+                Err(JwkError::InvalidBase64(name))
+            }
+        }
+
+        let canonical = match self.kty.as_str() {
+            "EC" => format!(
+                r#"{{"crv":"{}","kty":"EC","x":"{}","y":"{}"}}"#,
+                member(&self.crv, "crv")?,
+                member(&self.x, "x")?,
+                member(&self.y, "y")?
+            ),
+            "OKP" => format!(
+                r#"{{"crv":"{}","kty":"OKP","x":"{}"}}"#,
+                member(&self.crv, "crv")?,
+                member(&self.x, "x")?
+            ),
+            "RSA" => format!(
+                r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+                member(&self.e, "e")?,
+                member(&self.n, "n")?
+            ),
+            "oct" => return Err(JwkError::NoPublicForm),
+            other => return Err(JwkError::UnsupportedKeyType(other.into())),
+        };
+        Ok(URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes())))
+    }
+
+    /// Checks a JWS `signature` (RS256, ES256 or EdDSA) over
+    /// `signing_input` with this public key.
+    #[cfg(feature = "asymmetric")]
+    pub fn verify_jws(
+        &self,
+        alg: &str,
+        signing_input: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, JwkError> {
+        use rsa::signature::Verifier as _;
+
+        let unsupported = || JwkError::UnsupportedKeyType(format!("{}/{alg}", self.kty));
+        match (alg, self.kty.as_str(), self.crv.as_deref()) {
+            ("RS256", "RSA", _) => {
+                let n = decode(self.n.as_deref(), "n")?;
+                // Shorter moduli are too weak to prove anything.
+                if n.len() < 256 {
+                    return Err(JwkError::InvalidLength("n"));
+                }
+                let e = decode(self.e.as_deref(), "e")?;
+                let key = rsa::RsaPublicKey::new(
+                    rsa::BigUint::from_bytes_be(&n),
+                    rsa::BigUint::from_bytes_be(&e),
+                )
+                .map_err(|_| JwkError::InvalidLength("n"))?;
+                let key = rsa::pkcs1v15::VerifyingKey::<sha2::Sha256>::new(key);
+                Ok(rsa::pkcs1v15::Signature::try_from(signature)
+                    .is_ok_and(|signature| key.verify(signing_input, &signature).is_ok()))
+            }
+            ("ES256", "EC", Some("P-256")) => {
+                let x = decode(self.x.as_deref(), "x")?;
+                let y = decode(self.y.as_deref(), "y")?;
+                if x.len() != 32 {
+                    return Err(JwkError::InvalidLength("x"));
+                }
+                if y.len() != 32 {
+                    return Err(JwkError::InvalidLength("y"));
+                }
+                let point = p256::EncodedPoint::from_affine_coordinates(
+                    x.as_slice().into(),
+                    y.as_slice().into(),
+                    false,
+                );
+                let key = p256::ecdsa::VerifyingKey::from_encoded_point(&point)
+                    .map_err(|_| JwkError::InvalidLength("x"))?;
+                Ok(p256::ecdsa::Signature::from_slice(signature)
+                    .is_ok_and(|signature| key.verify(signing_input, &signature).is_ok()))
+            }
+            ("EdDSA", "OKP", Some("Ed25519")) => {
+                let x: [u8; 32] = decode(self.x.as_deref(), "x")?
+                    .try_into()
+                    .map_err(|_| JwkError::InvalidLength("x"))?;
+                let key = ed25519_dalek::VerifyingKey::from_bytes(&x)
+                    .map_err(|_| JwkError::InvalidLength("x"))?;
+                Ok(ed25519_dalek::Signature::from_slice(signature)
+                    .is_ok_and(|signature| key.verify(signing_input, &signature).is_ok()))
+            }
+            _ => Err(unsupported()),
+        }
+    }
+
+    #[cfg(any(feature = "response-encryption", feature = "escrow"))]
+    pub fn from_x25519_public(kid: Option<String>, key: &PublicKey) -> Self {
+        Self {
+            kty: "OKP".into(),
+            kid,
+            crv: Some("X25519".into()),
+            x: Some(URL_SAFE_NO_PAD.encode(key.as_bytes())),
+            ..Self::default()
+        }
+    }
+
+    #[cfg(any(feature = "response-encryption", feature = "escrow"))]
+    pub fn from_x25519_secret(kid: Option<String>, key: &StaticSecret) -> Self {
+        Self {
+            d: Some(URL_SAFE_NO_PAD.encode(key.as_bytes())),
+            ..Self::from_x25519_public(kid, &PublicKey::from(key))
+        }
+    }
+
+    #[cfg(any(feature = "response-encryption", feature = "escrow"))]
+    pub fn x25519_public_key(&self) -> Result<PublicKey, JwkError> {
+        self.expect_x25519()?;
+        Ok(PublicKey::from(decode_32(self.x.as_deref(), "x")?))
+    }
+
+    #[cfg(any(feature = "response-encryption", feature = "escrow"))]
+    pub fn x25519_secret_key(&self) -> Result<StaticSecret, JwkError> {
+        self.expect_x25519()?;
+        Ok(StaticSecret::from(decode_32(self.d.as_deref(), "d")?))
+    }
+
+    #[cfg(any(feature = "response-encryption", feature = "escrow"))]
+    fn expect_x25519(&self) -> Result<(), JwkError> {
+        self.expect_kty("OKP")?;
+        match self.crv.as_deref() {
+            Some("X25519") => Ok(()),
+            Some(other) => Err(JwkError::UnsupportedKeyType(format!("OKP/{other}"))),
+            None => Err(JwkError::MissingMember("crv")),
+        }
+    }
+
+    fn expect_kty(&self, kty: &str) -> Result<(), JwkError> {
+        if self.kty == kty {
+            Ok(())
+        } else {
+            Err(JwkError::UnsupportedKeyType(self.kty.clone()))
+        }
+    }
+}
+
+fn decode(value: Option<&str>, member: &'static str) -> Result<Vec<u8>, JwkError> {
+    let value = value.ok_or(JwkError::MissingMember(member))?;
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| JwkError::InvalidBase64(member))
+}
+
+#[cfg(any(feature = "response-encryption", feature = "escrow"))]
+fn decode_32(value: Option<&str>, member: &'static str) -> Result<[u8; 32], JwkError> {
+    decode(value, member)?
+        .try_into()
+        .map_err(|_| JwkError::InvalidLength(member))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 7515 Appendix A.1 HMAC key.
+    const RFC7515_KEY: &str = r#"{"kty":"oct",
+        "k":"AyM1SysPpbyDfgZld3umj1qzKObwVMkoqQ-EstJQLr_T-1qS0gZH75aKtMN3Yj0iPS4hcgUuTwjAzZr1Z9CAow"}"#;
+
+    #[test]
+    fn single_key_parses_as_one_key_set() {
+        let set = JwkSet::parse(RFC7515_KEY).unwrap();
+        let key = set.find(None).unwrap().symmetric_key().unwrap();
+        assert_eq!(key.len(), 64);
+        assert_eq!(&key[..4], &[0x03, 0x23, 0x35, 0x4b]);
+    }
+
+    #[test]
+    fn keys_are_selected_by_kid() {
+        let set = JwkSet::parse(
+            r#"{"keys": [
+                {"kty": "oct", "kid": "a", "k": "YQ"},
+                {"kty": "oct", "kid": "b", "k": "Yg"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(set.find(Some("b")).unwrap().symmetric_key().unwrap(), b"b");
+        assert_eq!(set.find(None), Err(JwkError::AmbiguousKey));
+        assert_eq!(
+            set.find(Some("c")),
+            Err(JwkError::NoMatchingKey("c".into()))
+        );
+    }
+
+    #[test]
+    fn symmetric_key_round_trips() {
+        let jwk = Jwk::symmetric(Some("k1".into()), b"secret");
+        let json = serde_json::to_string(&jwk).unwrap();
+        assert_eq!(json, r#"{"kty":"oct","kid":"k1","k":"c2VjcmV0"}"#);
+        let parsed = JwkSet::parse(&json).unwrap();
+        assert_eq!(parsed.keys[0].symmetric_key().unwrap(), b"secret");
+    }
+
+    #[test]
+    fn unsupported_and_malformed_keys_are_rejected() {
+        let rsa = JwkSet::parse(r#"{"kty": "RSA", "n": "AQAB", "e": "AQAB"}"#).unwrap();
+        assert_eq!(
+            rsa.keys[0].symmetric_key(),
+            Err(JwkError::UnsupportedKeyType("RSA".into()))
+        );
+        let missing = JwkSet::parse(r#"{"kty": "oct"}"#).unwrap();
+        assert_eq!(
+            missing.keys[0].symmetric_key(),
+            Err(JwkError::MissingMember("k"))
+        );
+        let padded = JwkSet::parse(r#"{"kty": "oct", "k": "c2VjcmV0=="}"#).unwrap();
+        assert_eq!(
+            padded.keys[0].symmetric_key(),
+            Err(JwkError::InvalidBase64("k"))
+        );
+        assert!(matches!(JwkSet::parse("[]"), Err(JwkError::Json(_))));
+    }
+
+    #[test]
+    fn symmetric_keys_have_no_public_form() {
+        let set = JwkSet::parse(RFC7515_KEY).unwrap();
+        assert_eq!(set.keys[0].to_public(), Err(JwkError::NoPublicForm));
+        assert!(set.to_public().keys.is_empty());
+    }
+
+    /// RFC 8037 Appendix A.1 Ed25519 key.
+    #[cfg(feature = "asymmetric")]
+    #[test]
+    fn ed25519_private_key_matches_rfc8037() {
+        let seed = URL_SAFE_NO_PAD
+            .decode("nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A")
+            .unwrap();
+        let key = ed25519_dalek::SigningKey::from_bytes(&seed.try_into().unwrap());
+        let jwk = Jwk::from_private_key(None, &PrivateKey::Ed25519(key));
+        assert_eq!(
+            jwk.x.as_deref(),
+            Some("11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo")
+        );
+        assert_eq!(jwk.alg.as_deref(), Some("EdDSA"));
+        // RFC 8037 Appendix A.3.
+        assert_eq!(
+            jwk.to_public().unwrap().thumbprint().unwrap(),
+            "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"
+        );
+    }
+
+    #[cfg(feature = "asymmetric")]
+    #[test]
+    fn jws_signatures_verify_against_public_keys() {
+        use crate::crypto::asymmetric::AsymmetricSigner;
+        use crate::crypto::keys::load_private_key;
+        use crate::crypto::signer::Signer;
+
+        for (pem, alg) in [
+            (include_str!("../../tests/fixtures/keys/rsa.pem"), "RS256"),
+            (include_str!("../../tests/fixtures/keys/p256.pem"), "ES256"),
+            (
+                include_str!("../../tests/fixtures/keys/ed25519.pem"),
+                "EdDSA",
+            ),
+        ] {
+            let key = load_private_key(pem.as_bytes(), None).unwrap();
+            let jwk = Jwk::from_private_key(None, &key).to_public().unwrap();
+            let signature = AsymmetricSigner::new(key).sign_bytes(b"input");
+            let signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+            assert_eq!(jwk.verify_jws(alg, b"input", &signature), Ok(true), "{alg}");
+            assert_eq!(
+                jwk.verify_jws(alg, b"other", &signature),
+                Ok(false),
+                "{alg}"
+            );
+            assert!(jwk.verify_jws("HS256", b"input", &signature).is_err());
+        }
+    }
+
+    #[cfg(feature = "asymmetric")]
+    #[test]
+    fn rsa_and_ec_keys_export_without_private_members() {
+        use crate::crypto::keys::load_private_key;
+
+        for (pem, kty) in [
+            (include_str!("../../tests/fixtures/keys/rsa.pem"), "RSA"),
+            (include_str!("../../tests/fixtures/keys/p256.pem"), "EC"),
+        ] {
+            let key = load_private_key(pem.as_bytes(), None).unwrap();
+            let jwk = Jwk::from_private_key(Some("k1".into()), &key);
+            assert_eq!(jwk.kty, kty);
+            assert!(jwk.d.is_some());
+
+            let public = jwk.to_public().unwrap();
+            let json = serde_json::to_value(&public).unwrap();
+            for member in ["d", "p", "q", "dp", "dq", "qi"] {
+                assert!(json.get(member).is_none(), "{kty} leaks `{member}`");
+            }
+            assert_eq!(public.kid.as_deref(), Some("k1"));
+        }
+    }
+
+    #[cfg(feature = "response-encryption")]
+    #[test]
+    fn x25519_keys_round_trip_and_export_without_private_part() {
+        // RFC 8037 Appendix A.6 (Alice's key).
+        let jwk = JwkSet::parse(
+            r#"{"kty": "OKP", "crv": "X25519", "kid": "alice",
+                "x": "hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTmo",
+                "d": "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo"}"#,
+        )
+        .unwrap()
+        .keys
+        .remove(0);
+        let secret = jwk.x25519_secret_key().unwrap();
+        assert_eq!(PublicKey::from(&secret), jwk.x25519_public_key().unwrap());
+        assert_eq!(Jwk::from_x25519_secret(Some("alice".into()), &secret), jwk);
+
+        let public = jwk.to_public().unwrap();
+        assert_eq!(public.d, None);
+        assert_eq!(public.x, jwk.x);
+        assert_eq!(
+            public.x25519_secret_key().err(),
+            Some(JwkError::MissingMember("d"))
+        );
+    }
+
+    #[cfg(feature = "response-encryption")]
+    #[test]
+    fn other_curves_are_rejected() {
+        let jwk = Jwk {
+            kty: "OKP".into(),
+            crv: Some("Ed25519".into()),
+            x: Some("11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo".into()),
+            ..Jwk::default()
+        };
+        assert_eq!(
+            jwk.x25519_public_key(),
+            Err(JwkError::UnsupportedKeyType("OKP/Ed25519".into()))
+        );
+    }
+
+    #[test]
+    fn published_sets_are_public_and_tagged_by_content() {
+        let set = JwkSet::parse(RFC7515_KEY).unwrap();
+        let empty = PublishedJwks::new(&set);
+        assert_eq!(empty.json, r#"{"keys":[]}"#);
+
+        let okp = JwkSet {
+            keys: vec![Jwk {
+                kty: "OKP".into(),
+                crv: Some("Ed25519".into()),
+                x: Some("public".into()),
+                d: Some("private".into()),
+                ..Jwk::default()
+            }],
+        };
+        let published = PublishedJwks::new(&okp);
+        assert!(!published.json.contains("private"));
+        assert_ne!(published.etag, empty.etag);
+        assert_eq!(PublishedJwks::new(&okp), published);
+
+        let etag = published.etag.clone();
+        assert!(published.matches(&etag));
+        assert!(published.matches(&format!("\"other\", W/{etag}")));
+        assert!(published.matches("*"));
+        assert!(!published.matches(&empty.etag));
+    }
+}
@@ -0,0 +1,316 @@
+//! RFC 6962 Merkle tree hashes and consistency proofs, as used by
+//! certificate transparency logs (checked as in RFC 9162 §2.1.4.2). A
+//! consistency proof shows that a tree of `first` leaves is a prefix of a
+//! tree of `second` leaves, so a log that rewrote or dropped an entry
+//! cannot produce one between roots it published before and after.
+
+use std::ops::Range;
+
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+/// The hash of a leaf holding `data`: `SHA-256(0x00 || data)`.
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    Sha256::new()
+        .chain_update([0])
+        .chain_update(data)
+        .finalize()
+        .into()
+}
+
+/// The hash of an interior node: `SHA-256(0x01 || left || right)`.
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([1])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// The root of the tree over `leaves`, which are leaf hashes; the SHA-256
+/// of nothing for an empty tree.
+pub fn root(leaves: &[Hash]) -> Hash {
+    match leaves {
+        [] => Sha256::digest([]).into(),
+        [leaf] => *leaf,
+        _ => {
+            let split = split(leaves.len());
+            node_hash(&root(&leaves[..split]), &root(&leaves[split..]))
+        }
+    }
+}
+
+/// Builds roots as leaves are appended, keeping only the roots of the
+/// perfect subtrees along the right edge: `O(log n)` memory and hashes per
+/// root.
+#[derive(Debug, Clone, Default)]
+pub struct Frontier {
+    /// Subtree roots with their sizes, largest (leftmost) first.
+    subtrees: Vec<(u64, Hash)>,
+    size: u64,
+}
+
+impl Frontier {
+    pub fn push(&mut self, leaf: Hash) {
+        let mut merged = (1, leaf);
+        while let Some(&(size, hash)) = self.subtrees.last() {
+            if size != merged.0 {
+                break;
+            }
+            self.subtrees.pop();
+            merged = (size * 2, node_hash(&hash, &merged.1));
+        }
+        self.subtrees.push(merged);
+        self.size += 1;
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The root of the tree over every leaf pushed so far.
+    pub fn root(&self) -> Hash {
+        let mut subtrees = self.subtrees.iter().rev();
+        let Some(&(_, last)) = subtrees.next() else {
+            return root(&[]);
+        };
+        subtrees.fold(last, |right, (_, left)| node_hash(left, &right))
+    }
+
+    /// The root of the perfect subtree over `leaves`, if it is one of
+    /// those kept.
+    fn subtree(&self, leaves: Range<usize>) -> Option<Hash> {
+        let mut start = 0;
+        self.subtrees.iter().find_map(|&(size, hash)| {
+            let size = usize::try_from(size).ok()?;
+            let found = start == leaves.start && start + size == leaves.end;
+            start += size;
+            found.then_some(hash)
+        })
+    }
+}
+
+/// The proof that the tree over the first `first` of `leaves` is a prefix
+/// of the tree over all of them (RFC 6962 §2.1.2). Empty when `first` is 0
+/// or covers every leaf.
+pub fn consistency_proof(first: usize, leaves: &[Hash]) -> Vec<Hash> {
+    let mut proof = Vec::new();
+    if first > 0 && first < leaves.len() {
+        let node = |range: Range<usize>| root(&leaves[range]);
+        subproof(first, 0..leaves.len(), true, &node, &mut proof);
+    }
+    proof
+}
+
+/// The same proof, between the tree `first` was built from and that tree
+/// with `appended` added, without the leaves under `first`: every node
+/// the proof takes from the first tree is one of the subtrees a
+/// [`Frontier`] keeps.
+pub fn consistency_proof_from(first: &Frontier, appended: &[Hash]) -> Vec<Hash> {
+    let mut proof = Vec::new();
+    let size = usize::try_from(first.size()).expect("frontier sizes fit in memory");
+    if size > 0 && !appended.is_empty() {
+        let node = |range: Range<usize>| {
+            if range.end <= size {
+                first
+                    .subtree(range)
+                    .expect("proofs only take frontier subtrees from the first tree")
+            } else {
+                root(&appended[range.start - size..range.end - size])
+            }
+        };
+        subproof(size, 0..size + appended.len(), true, &node, &mut proof);
+    }
+    proof
+}
+
+/// Pushes the nodes proving that the first `first` leaves of `leaves` are
+/// a prefix of them, hashing each as `node` does.
+fn subproof(
+    first: usize,
+    leaves: Range<usize>,
+    complete: bool,
+    node: &dyn Fn(Range<usize>) -> Hash,
+    proof: &mut Vec<Hash>,
+) {
+    if first == leaves.len() {
+        if !complete {
+            proof.push(node(leaves));
+        }
+        return;
+    }
+    let mid = leaves.start + split(leaves.len());
+    if first <= mid - leaves.start {
+        subproof(first, leaves.start..mid, complete, node, proof);
+        proof.push(node(mid..leaves.end));
+    } else {
+        let first = first - (mid - leaves.start);
+        subproof(first, mid..leaves.end, false, node, proof);
+        proof.push(node(leaves.start..mid));
+    }
+}
+
+/// Checks a [`consistency_proof`] between the root of a `first`-leaf tree
+/// and that of a `second`-leaf tree (RFC 9162 §2.1.4.2).
+pub fn verify_consistency(
+    first: u64,
+    second: u64,
+    first_root: &Hash,
+    second_root: &Hash,
+    proof: &[Hash],
+) -> bool {
+    if first > second {
+        return false;
+    }
+    if first == second {
+        return proof.is_empty() && first_root == second_root;
+    }
+    if first == 0 {
+        return proof.is_empty();
+    }
+    let mut path = Vec::with_capacity(proof.len() + 1);
+    if first.is_power_of_two() {
+        path.push(*first_root);
+    }
+    path.extend_from_slice(proof);
+    let Some((start, rest)) = path.split_first() else {
+        return false;
+    };
+    let (mut fnode, mut snode) = (first - 1, second - 1);
+    while fnode & 1 == 1 {
+        fnode >>= 1;
+        snode >>= 1;
+    }
+    let (mut fr, mut sr) = (*start, *start);
+    for hash in rest {
+        if snode == 0 {
+            return false;
+        }
+        if fnode & 1 == 1 || fnode == snode {
+            fr = node_hash(hash, &fr);
+            sr = node_hash(hash, &sr);
+            while fnode & 1 == 0 && fnode != 0 {
+                fnode >>= 1;
+                snode >>= 1;
+            }
+        } else {
+            sr = node_hash(&sr, hash);
+        }
+        fnode >>= 1;
+        snode >>= 1;
+    }
+    &fr == first_root && &sr == second_root && snode == 0
+}
+
+/// The size of the left subtree of a tree of `n > 1` leaves: the largest
+/// power of two below `n`.
+fn split(n: usize) -> usize {
+    1 << (n - 1).ilog2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: usize) -> Vec<Hash> {
+        (0..n).map(|i| leaf_hash(&i.to_be_bytes())).collect()
+    }
+
+    #[test]
+    fn empty_and_single_leaf_roots_follow_rfc_6962() {
+        assert_eq!(
+            hex(&root(&[])),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&leaf_hash(b"")),
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d"
+        );
+        assert_eq!(root(&leaves(1)), leaves(1)[0]);
+    }
+
+    #[test]
+    fn frontiers_match_full_roots() {
+        let all = leaves(40);
+        let mut frontier = Frontier::default();
+        assert_eq!(frontier.root(), root(&[]));
+        for n in 1..=all.len() {
+            frontier.push(all[n - 1]);
+            assert_eq!(frontier.size(), n as u64);
+            assert_eq!(frontier.root(), root(&all[..n]), "{n} leaves");
+        }
+    }
+
+    #[test]
+    fn consistency_proofs_verify_between_every_pair_of_sizes() {
+        let all = leaves(24);
+        for second in 1..=all.len() {
+            let second_root = root(&all[..second]);
+            for first in 1..=second {
+                let first_root = root(&all[..first]);
+                let proof = consistency_proof(first, &all[..second]);
+                assert!(
+                    verify_consistency(
+                        first as u64,
+                        second as u64,
+                        &first_root,
+                        &second_root,
+                        &proof
+                    ),
+                    "{first} -> {second}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn proofs_from_a_frontier_match_those_from_every_leaf() {
+        let all = leaves(24);
+        for first in 1..=all.len() {
+            let mut frontier = Frontier::default();
+            all[..first].iter().for_each(|leaf| frontier.push(*leaf));
+            for second in first..=all.len() {
+                assert_eq!(
+                    consistency_proof_from(&frontier, &all[first..second]),
+                    consistency_proof(first, &all[..second]),
+                    "{first} -> {second}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rewritten_history_fails_consistency() {
+        let all = leaves(13);
+        let mut rewritten = all.clone();
+        rewritten[2] = leaf_hash(b"forged");
+        let first_root = root(&all[..7]);
+        let proof = consistency_proof(7, &rewritten);
+        assert!(!verify_consistency(
+            7,
+            13,
+            &first_root,
+            &root(&rewritten),
+            &proof
+        ));
+
+        let proof = consistency_proof(7, &all);
+        let mut tampered = proof.clone();
+        tampered[0][0] ^= 1;
+        assert!(!verify_consistency(
+            7,
+            13,
+            &first_root,
+            &root(&all),
+            &tampered
+        ));
+        let other_root = root(&all[..6]);
+        assert!(!verify_consistency(7, 13, &other_root, &root(&all), &proof));
+    }
+
+    fn hex(hash: &Hash) -> String {
+        hash.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
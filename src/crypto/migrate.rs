@@ -0,0 +1,345 @@
+//! Moving documents off the legacy base64 encoding onto another encryptor.
+//!
+//! Base64 carries no marker, so a plain string can decode to JSON just as
+//! well as a ciphertext can. Which values to move is therefore never
+//! guessed: a document's `_crypto` sidecar lists them, or, for documents
+//! without one, the caller names them with JSON Pointers. Migrated
+//! documents always leave with a sidecar recording their values under the
+//! new algorithm, so running a migration twice is harmless.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::crypto::base64::Base64Encryptor;
+use crate::crypto::encryptor::{AsyncEncryptor, EncryptError, Encryptor};
+use crate::crypto::pointer::{self, PointerError};
+
+/// Top-level member holding a document's sidecar.
+pub const SIDECAR: &str = "_crypto";
+
+/// Algorithm recorded for values of the legacy base64 encoding.
+pub const LEGACY_ALGORITHM: &str = "base64";
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrateError {
+    #[error("document is not a JSON object")]
+    NotAnObject,
+    #[error("invalid `{SIDECAR}` sidecar: {0}")]
+    InvalidSidecar(String),
+    #[error("document has no `{SIDECAR}` sidecar and no pointers were given")]
+    NoPointers,
+    #[error("`{pointer}` was encrypted with {alg} key `{kid}`, which this service does not hold")]
+    ForeignKey {
+        pointer: String,
+        alg: String,
+        kid: String,
+    },
+    #[error("`{0}` names the whole document or its sidecar")]
+    Reserved(String),
+    #[error("`{0}` is not {LEGACY_ALGORITHM} ciphertext")]
+    NotLegacy(String),
+    #[error(transparent)]
+    Pointer(#[from] PointerError),
+    #[error(transparent)]
+    Encrypt(#[from] EncryptError),
+}
+
+/// Same shape as the server's `CryptoSidecar`, which is only built with
+/// the `server` and `client` features.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Sidecar {
+    paths: BTreeMap<String, EncryptedPath>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EncryptedPath {
+    alg: String,
+    kid: String,
+}
+
+/// Moves documents onto the encryptor recorded as `alg` under key `kid`.
+pub struct Migration {
+    alg: String,
+    kid: String,
+    pointers: Vec<String>,
+}
+
+/// Values of one document still to be moved, as plaintext, by pointer.
+struct Pending {
+    sidecar: Sidecar,
+    values: Vec<(String, Value)>,
+}
+
+impl Migration {
+    pub fn new(alg: impl Into<String>, kid: impl Into<String>) -> Self {
+        Self {
+            alg: alg.into(),
+            kid: kid.into(),
+            pointers: Vec::new(),
+        }
+    }
+
+    /// Values holding base64 ciphertext in documents without a sidecar;
+    /// such documents are refused when none are given. Documents with a
+    /// sidecar are migrated by what it lists.
+    pub fn pointers(mut self, pointers: Vec<String>) -> Self {
+        self.pointers = pointers;
+        self
+    }
+
+    /// Re-encrypts the legacy values of `document` in place with
+    /// `encryptor`, returning how many there were. With `dry_run` the
+    /// document is only checked and left as it is.
+    pub fn migrate(
+        &self,
+        encryptor: &dyn Encryptor,
+        document: &mut Value,
+        dry_run: bool,
+    ) -> Result<usize, MigrateError> {
+        let mut pending = self.pending(document)?;
+        let migrated = pending.values.len();
+        if !dry_run {
+            for (_, value) in &mut pending.values {
+                *value = encryptor.encrypt(value)?;
+            }
+            self.finish(document, pending);
+        }
+        Ok(migrated)
+    }
+
+    /// Async form of [`Migration::migrate`].
+    pub async fn migrate_async(
+        &self,
+        encryptor: &dyn AsyncEncryptor,
+        document: &mut Value,
+        dry_run: bool,
+    ) -> Result<usize, MigrateError> {
+        let mut pending = self.pending(document)?;
+        let migrated = pending.values.len();
+        if !dry_run {
+            for (_, value) in &mut pending.values {
+                *value = encryptor.encrypt(value).await?;
+            }
+            self.finish(document, pending);
+        }
+        Ok(migrated)
+    }
+
+    /// Decodes the values of `document` to move. Values its sidecar already
+    /// lists under this migration's algorithm and key are left out.
+    fn pending(&self, document: &Value) -> Result<Pending, MigrateError> {
+        let map = document.as_object().ok_or(MigrateError::NotAnObject)?;
+        let (sidecar, pointers) = match map.get(SIDECAR) {
+            Some(sidecar) => {
+                let sidecar: Sidecar = serde_json::from_value(sidecar.clone())
+                    .map_err(|err| MigrateError::InvalidSidecar(err.to_string()))?;
+                let mut pointers = Vec::new();
+                for (pointer, path) in &sidecar.paths {
+                    if path.alg == self.alg && path.kid == self.kid {
+                        continue;
+                    }
+                    if path.alg != LEGACY_ALGORITHM || path.kid != self.kid {
+                        return Err(MigrateError::ForeignKey {
+                            pointer: pointer.clone(),
+                            alg: path.alg.clone(),
+                            kid: path.kid.clone(),
+                        });
+                    }
+                    pointers.push(pointer.clone());
+                }
+                (sidecar, pointers)
+            }
+            None if self.pointers.is_empty() => return Err(MigrateError::NoPointers),
+            None => (Sidecar::default(), self.pointers.clone()),
+        };
+        if let Some(reserved) = pointers.iter().find(|pointer| is_reserved(pointer)) {
+            return Err(MigrateError::Reserved(reserved.clone()));
+        }
+        pointer::check(document, &pointers)?;
+        let values = pointers
+            .into_iter()
+            .map(|pointer| {
+                let value = document.pointer(&pointer).expect("checked above");
+                match Encryptor::decrypt(&Base64Encryptor, value) {
+                    Ok(plaintext) => Ok((pointer, plaintext)),
+                    Err(_) => Err(MigrateError::NotLegacy(pointer)),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Pending { sidecar, values })
+    }
+
+    /// Writes the re-encrypted values of `pending` into `document` and
+    /// records them in its sidecar.
+    fn finish(&self, document: &mut Value, pending: Pending) {
+        let Pending {
+            mut sidecar,
+            values,
+        } = pending;
+        for (pointer, ciphertext) in values {
+            *document.pointer_mut(&pointer).expect("checked by pending") = ciphertext;
+            let path = EncryptedPath {
+                alg: self.alg.clone(),
+                kid: self.kid.clone(),
+            };
+            sidecar.paths.insert(pointer, path);
+        }
+        let sidecar = serde_json::to_value(sidecar).expect("a sidecar serializes to JSON");
+        if let Value::Object(map) = document {
+            map.insert(SIDECAR.to_string(), sidecar);
+        }
+    }
+}
+
+/// Whether `pointer` is the whole document or lies in its sidecar, which
+/// migrated values must stay out of.
+fn is_reserved(pointer: &str) -> bool {
+    let sidecar = pointer::top_level(SIDECAR);
+    pointer.is_empty()
+        || pointer
+            .strip_prefix(&sidecar)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Stands in for a real cipher: tags the plaintext.
+    struct Tagging;
+
+    impl Encryptor for Tagging {
+        fn encrypt(&self, value: &Value) -> Result<Value, EncryptError> {
+            Ok(json!({ "tagged": value }))
+        }
+
+        fn decrypt(&self, _value: &Value) -> Result<Value, crate::crypto::encryptor::DecryptError> {
+            unimplemented!()
+        }
+    }
+
+    fn legacy(value: Value) -> Value {
+        Encryptor::encrypt(&Base64Encryptor, &value).unwrap()
+    }
+
+    fn migration() -> Migration {
+        Migration::new("tagging", "encryption")
+    }
+
+    #[test]
+    fn sidecar_entries_are_moved_and_rerecorded() {
+        let mut document = json!({
+            "email": legacy(json!("a@example.com")),
+            "done": { "tagged": 1 },
+            "_crypto": { "paths": {
+                "/email": { "alg": "base64", "kid": "encryption" },
+                "/done": { "alg": "tagging", "kid": "encryption" },
+            }},
+        });
+        assert_eq!(
+            migration().migrate(&Tagging, &mut document, false).unwrap(),
+            1
+        );
+        assert_eq!(
+            document,
+            json!({
+                "email": { "tagged": "a@example.com" },
+                "done": { "tagged": 1 },
+                "_crypto": { "paths": {
+                    "/email": { "alg": "tagging", "kid": "encryption" },
+                    "/done": { "alg": "tagging", "kid": "encryption" },
+                }},
+            })
+        );
+        // A second run finds nothing left to move.
+        assert_eq!(
+            migration().migrate(&Tagging, &mut document, false).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn only_the_named_values_are_moved_without_a_sidecar() {
+        // `note` is plain text that happens to decode as base64 JSON.
+        let note = legacy(json!(42));
+        let mut document = json!({ "ssn": legacy(json!("123")), "note": note.clone() });
+        let migration = migration().pointers(vec!["/ssn".into()]);
+        assert_eq!(
+            migration.migrate(&Tagging, &mut document, false).unwrap(),
+            1
+        );
+        assert_eq!(document["ssn"], json!({ "tagged": "123" }));
+        assert_eq!(document["note"], note);
+        assert_eq!(
+            document["_crypto"],
+            json!({ "paths": { "/ssn": { "alg": "tagging", "kid": "encryption" } } })
+        );
+    }
+
+    #[test]
+    fn documents_without_a_sidecar_need_pointers() {
+        let mut document = json!({ "ssn": legacy(json!("123")) });
+        assert!(matches!(
+            migration().migrate(&Tagging, &mut document, false),
+            Err(MigrateError::NoPointers)
+        ));
+    }
+
+    #[test]
+    fn values_that_are_not_legacy_ciphertext_are_refused() {
+        let mut document = json!({ "ssn": "not base64!" });
+        let migration = migration().pointers(vec!["/ssn".into()]);
+        assert!(matches!(
+            migration.migrate(&Tagging, &mut document, false),
+            Err(MigrateError::NotLegacy(pointer)) if pointer == "/ssn"
+        ));
+    }
+
+    #[test]
+    fn values_under_other_keys_are_refused() {
+        let mut document = json!({
+            "ssn": "...",
+            "_crypto": { "paths": { "/ssn": { "alg": "aes-gcm", "kid": "other" } } },
+        });
+        assert!(matches!(
+            migration().migrate(&Tagging, &mut document, false),
+            Err(MigrateError::ForeignKey { .. })
+        ));
+    }
+
+    #[test]
+    fn dry_runs_leave_the_document_alone() {
+        let original = json!({ "ssn": legacy(json!("123")) });
+        let mut document = original.clone();
+        let migration = migration().pointers(vec!["/ssn".into()]);
+        assert_eq!(migration.migrate(&Tagging, &mut document, true).unwrap(), 1);
+        assert_eq!(document, original);
+    }
+
+    #[test]
+    fn moving_to_base64_records_the_values_in_a_sidecar() {
+        let ssn = legacy(json!("123"));
+        let mut document = json!({ "ssn": ssn.clone() });
+        let migration =
+            Migration::new(LEGACY_ALGORITHM, "encryption").pointers(vec!["/ssn".into()]);
+        assert_eq!(
+            migration
+                .migrate(&Base64Encryptor, &mut document, false)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            document,
+            json!({
+                "ssn": ssn,
+                "_crypto": { "paths": { "/ssn": { "alg": "base64", "kid": "encryption" } } },
+            })
+        );
+    }
+}
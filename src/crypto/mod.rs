@@ -1,4 +1,73 @@
+use std::future::Future;
+use std::pin::Pin;
+
+#[cfg(feature = "asymmetric")]
+pub mod asymmetric;
+#[cfg(feature = "encryption")]
 pub mod base64;
+pub(crate) mod buffers;
+#[cfg(feature = "signing")]
+pub mod cache;
+pub mod canonical;
+#[cfg(feature = "signing")]
+pub mod challenge;
+#[cfg(any(
+    feature = "encryption",
+    feature = "signing",
+    feature = "response-encryption",
+    feature = "escrow"
+))]
+pub(crate) mod codec;
+#[cfg(feature = "signing")]
+pub mod compact;
+#[cfg(feature = "config-encryption")]
+pub mod config_value;
+pub mod constant_time;
+#[cfg(feature = "asymmetric")]
+pub mod dpop;
+#[cfg(feature = "encryption")]
 pub mod encryptor;
+#[cfg(feature = "signing")]
+pub mod envelope;
+#[cfg(feature = "escrow")]
+pub mod escrow;
+#[cfg(feature = "hd-keys")]
+pub mod hd;
+#[cfg(feature = "signing")]
 pub mod hmac;
+#[cfg(feature = "signing")]
+pub mod http_signature;
+#[cfg(any(feature = "signing", feature = "response-encryption"))]
+pub mod jwk;
+#[cfg(feature = "asymmetric")]
+pub mod keys;
+#[cfg(feature = "signing")]
+pub mod merkle;
+#[cfg(feature = "encryption")]
+pub mod migrate;
+#[cfg(feature = "encryption")]
+pub mod pointer;
+#[cfg(feature = "signing")]
+pub mod prehash;
+#[cfg(feature = "signing")]
+pub mod registry;
+#[cfg(feature = "json-schema")]
+pub mod schema;
+#[cfg(any(feature = "response-encryption", feature = "escrow"))]
+pub mod seal;
+pub mod selftest;
+#[cfg(feature = "signing")]
 pub mod signer;
+#[cfg(feature = "signing")]
+pub mod sigv4;
+#[cfg(feature = "blobs")]
+pub mod stream;
+pub mod strict_json;
+#[cfg(feature = "signing")]
+pub mod test_vectors;
+#[cfg(feature = "signing")]
+pub mod webhook;
+
+/// Boxed future returned by the async crypto traits, which need to stay
+/// object-safe so backends can be swapped behind an `Arc<dyn ...>`.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
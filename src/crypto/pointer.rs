@@ -0,0 +1,314 @@
+//! Encryption of the values at chosen RFC 6901 JSON Pointers, anywhere in a
+//! document, leaving everything else as it is: `/contact/email` encrypts
+//! only the email inside `contact`.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde_json::Value;
+use serde_json::value::RawValue;
+
+use crate::crypto::encryptor::{AsyncEncryptor, DecryptError, EncryptError};
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum PointerError {
+    #[error("`{0}` is not a JSON Pointer (RFC 6901)")]
+    Invalid(String),
+    #[error("no value at `{0}`")]
+    Missing(String),
+    #[error("`{inner}` lies within `{outer}`; list one or the other")]
+    Overlapping { outer: String, inner: String },
+    #[error("document is not valid JSON: {0}")]
+    InvalidDocument(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PointerEncryptError {
+    #[error(transparent)]
+    Pointer(#[from] PointerError),
+    #[error(transparent)]
+    Encrypt(#[from] EncryptError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PointerDecryptError {
+    #[error(transparent)]
+    Pointer(#[from] PointerError),
+    #[error(transparent)]
+    Decrypt(#[from] DecryptError),
+}
+
+/// Encrypts the value at each of `pointers` in place. Every pointer must
+/// name an existing value, and none may lie within another, so no value is
+/// encrypted twice.
+pub async fn encrypt_pointers_async(
+    encryptor: &dyn AsyncEncryptor,
+    mut payload: Value,
+    pointers: &[String],
+) -> Result<Value, PointerEncryptError> {
+    check(&payload, pointers)?;
+    for pointer in pointers {
+        let value = payload.pointer_mut(pointer).expect("checked above");
+        *value = encryptor.encrypt(value).await?;
+    }
+    Ok(payload)
+}
+
+/// Decrypts the value at each of `pointers` in place, leaving values that
+/// are not ciphertext unchanged, as `/decrypt` does.
+pub async fn decrypt_pointers_async(
+    encryptor: &dyn AsyncEncryptor,
+    mut payload: Value,
+    pointers: &[String],
+) -> Result<Value, PointerDecryptError> {
+    check(&payload, pointers)?;
+    for pointer in pointers {
+        let value = payload.pointer_mut(pointer).expect("checked above");
+        match encryptor.decrypt(value).await {
+            Ok(decrypted) => *value = decrypted,
+            Err(DecryptError::NotCiphertext) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(payload)
+}
+
+/// [`encrypt_pointers_async`] on JSON text, splicing each ciphertext over
+/// the bytes of the value it replaces, so whitespace, key order, number
+/// formatting and every other value stay byte for byte as they were.
+///
+/// A repeated key is resolved to its last occurrence, as everywhere else;
+/// callers should refuse documents that repeat keys, or the earlier
+/// occurrences stay in plaintext.
+pub async fn encrypt_patch_async(
+    encryptor: &dyn AsyncEncryptor,
+    document: &str,
+    pointers: &[String],
+) -> Result<String, PointerEncryptError> {
+    let payload: Value = serde_json::from_str(document)
+        .map_err(|err| PointerError::InvalidDocument(err.to_string()))?;
+    check(&payload, pointers)?;
+    let mut patches = Vec::with_capacity(pointers.len());
+    for pointer in pointers {
+        let tokens = tokens(pointer).expect("checked above");
+        let span = span(document, &tokens).expect("checked above");
+        let value = payload.pointer(pointer).expect("checked above");
+        patches.push((span, encryptor.encrypt(value).await?.to_string()));
+    }
+    patches.sort_by_key(|(span, _)| span.start);
+    let mut patched = String::with_capacity(document.len());
+    let mut copied = 0;
+    for (span, ciphertext) in patches {
+        patched.push_str(&document[copied..span.start]);
+        patched.push_str(&ciphertext);
+        copied = span.end;
+    }
+    patched.push_str(&document[copied..]);
+    Ok(patched)
+}
+
+/// The byte range of the value at `tokens` in the JSON text `document`.
+fn span(document: &str, tokens: &[String]) -> Option<Range<usize>> {
+    let mut raw: &RawValue = serde_json::from_str(document).ok()?;
+    for token in tokens {
+        raw = match raw.get().as_bytes().first() {
+            Some(b'{') => {
+                let members: HashMap<String, &RawValue> = serde_json::from_str(raw.get()).ok()?;
+                members.get(token).copied()?
+            }
+            Some(b'[') => {
+                let items: Vec<&RawValue> = serde_json::from_str(raw.get()).ok()?;
+                items.get(token.parse::<usize>().ok()?).copied()?
+            }
+            _ => return None,
+        };
+    }
+    // Borrowed raw values are slices of `document`.
+    let start = raw.get().as_ptr() as usize - document.as_ptr() as usize;
+    Some(start..start + raw.get().len())
+}
+
+/// The pointer to the top-level property `name`.
+pub fn top_level(name: &str) -> String {
+    format!("/{}", name.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Checks that every pointer is well-formed, names a value of `payload`,
+/// and is disjoint from the others.
+pub(crate) fn check(payload: &Value, pointers: &[String]) -> Result<(), PointerError> {
+    let mut parsed = Vec::with_capacity(pointers.len());
+    for pointer in pointers {
+        let tokens = tokens(pointer).ok_or_else(|| PointerError::Invalid(pointer.clone()))?;
+        if payload.pointer(pointer).is_none() {
+            return Err(PointerError::Missing(pointer.clone()));
+        }
+        parsed.push((pointer, tokens));
+    }
+    for (i, (first, first_tokens)) in parsed.iter().enumerate() {
+        for (second, second_tokens) in &parsed[i + 1..] {
+            let (outer, inner) = match first_tokens.len() <= second_tokens.len() {
+                true => ((first, first_tokens), (second, second_tokens)),
+                false => ((second, second_tokens), (first, first_tokens)),
+            };
+            if inner.1.starts_with(outer.1) {
+                return Err(PointerError::Overlapping {
+                    outer: outer.0.to_string(),
+                    inner: inner.0.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The unescaped reference tokens of `pointer`, or `None` if it is not a
+/// JSON Pointer.
+fn tokens(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+    pointer
+        .strip_prefix('/')?
+        .split('/')
+        .map(|token| {
+            let mut unescaped = String::with_capacity(token.len());
+            let mut chars = token.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '~' => match chars.next() {
+                        Some('0') => unescaped.push('~'),
+                        Some('1') => unescaped.push('/'),
+                        _ => return None,
+                    },
+                    c => unescaped.push(c),
+                }
+            }
+            Some(unescaped)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::crypto::base64::Base64Encryptor;
+
+    fn pointers(pointers: &[&str]) -> Vec<String> {
+        pointers.iter().map(|pointer| pointer.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn only_the_pointed_values_are_encrypted() {
+        let document = json!({
+            "contact": {"email": "a@example.com", "name": "Alice"},
+            "payment": {"card": {"number": "4111", "expiry": "12/30"}},
+            "items": [{"sku": "x"}, {"sku": "y"}],
+            "a/b": {"~": 1}
+        });
+        let selected = pointers(&[
+            "/contact/email",
+            "/payment/card/number",
+            "/items/1/sku",
+            "/a~1b/~0",
+        ]);
+        let encrypted = encrypt_pointers_async(&Base64Encryptor, document.clone(), &selected)
+            .await
+            .unwrap();
+        assert_ne!(encrypted["contact"]["email"], document["contact"]["email"]);
+        assert_eq!(encrypted["contact"]["name"], json!("Alice"));
+        assert_eq!(encrypted["payment"]["card"]["expiry"], json!("12/30"));
+        assert_eq!(encrypted["items"][0], json!({"sku": "x"}));
+        assert!(encrypted["a/b"]["~"].is_string());
+
+        let decrypted = decrypt_pointers_async(&Base64Encryptor, encrypted, &selected)
+            .await
+            .unwrap();
+        assert_eq!(decrypted, document);
+    }
+
+    #[tokio::test]
+    async fn patching_leaves_every_other_byte_alone() {
+        let document = "{ \"b\": 1.50,\n  \"a\": {\"x\" : \"secret\", \"y\": [ 1e2, \"card\" ]},\n  \"b\": 2 }";
+        let patched =
+            encrypt_patch_async(&Base64Encryptor, document, &pointers(&["/a/x", "/a/y/1"]))
+                .await
+                .unwrap();
+        let secret = AsyncEncryptor::encrypt(&Base64Encryptor, &json!("secret"))
+            .await
+            .unwrap();
+        let card = AsyncEncryptor::encrypt(&Base64Encryptor, &json!("card"))
+            .await
+            .unwrap();
+        assert_eq!(
+            patched,
+            format!(
+                "{{ \"b\": 1.50,\n  \"a\": {{\"x\" : {secret}, \"y\": [ 1e2, {card} ]}},\n  \"b\": 2 }}"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn patching_checks_the_document_and_pointers() {
+        let err = encrypt_patch_async(&Base64Encryptor, "{\"a\":", &pointers(&["/a"]))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PointerEncryptError::Pointer(PointerError::InvalidDocument(_))
+        ));
+        let err = encrypt_patch_async(&Base64Encryptor, r#"{"a": [1]}"#, &pointers(&["/a/1"]))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PointerEncryptError::Pointer(PointerError::Missing(_))
+        ));
+    }
+
+    #[test]
+    fn spans_cover_exactly_the_value() {
+        let document = r#" {"a~b": {"c/d": [true, {"e": null}]}} "#;
+        let nested = span(document, &["a~b".into(), "c/d".into(), "1".into()]).unwrap();
+        assert_eq!(&document[nested], r#"{"e": null}"#);
+        assert_eq!(&document[span(document, &[]).unwrap()], document.trim());
+    }
+
+    #[test]
+    fn top_level_names_are_escaped() {
+        let document = json!({"a/b~c": 1});
+        let pointer = top_level("a/b~c");
+        assert_eq!(pointer, "/a~1b~0c");
+        assert_eq!(document.pointer(&pointer), Some(&json!(1)));
+    }
+
+    #[test]
+    fn pointers_must_be_valid_present_and_disjoint() {
+        let document = json!({"a": {"b": 1}, "c": 2});
+        assert_eq!(
+            check(&document, &pointers(&["a"])),
+            Err(PointerError::Invalid("a".into()))
+        );
+        assert_eq!(
+            check(&document, &pointers(&["/a/~2"])),
+            Err(PointerError::Invalid("/a/~2".into()))
+        );
+        assert_eq!(
+            check(&document, &pointers(&["/a/x"])),
+            Err(PointerError::Missing("/a/x".into()))
+        );
+        assert_eq!(
+            check(&document, &pointers(&["/a/b", "/a"])),
+            Err(PointerError::Overlapping {
+                outer: "/a".into(),
+                inner: "/a/b".into()
+            })
+        );
+        assert!(matches!(
+            check(&document, &pointers(&["/c", "/c"])),
+            Err(PointerError::Overlapping { .. })
+        ));
+        assert_eq!(check(&document, &pointers(&["/a/b", "/c"])), Ok(()));
+    }
+}
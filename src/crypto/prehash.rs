@@ -0,0 +1,155 @@
+//! Signing of digests computed by the client, for payloads too large to
+//! send. With `?prehashed=true`, a `/sign` or `/verify` payload of exactly
+//! `{"digest": "<base64>", "digest_alg": "sha256"}` is signed as
+//! [`Prehashed::signing_input`] rather than in canonical form. Without it,
+//! such an object is an ordinary payload like any other.
+//!
+//! The signed bytes start with `0xFF`, which never occurs in UTF-8 and so
+//! never in a canonical payload: a digest signature cannot be passed off as
+//! the signature of any JSON object, nor the other way round.
+
+use serde_json::{Map, Value};
+
+use crate::crypto::codec;
+
+/// Prefix of every signed digest, followed by the algorithm name, a NUL
+/// byte and the digest.
+pub const DOMAIN_TAG: &[u8] = b"\xfftake-home prehashed v1\0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+}
+
+impl DigestAlgorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DigestAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            DigestAlgorithm::Sha256 => 32,
+        }
+    }
+}
+
+/// A digest sent in place of the payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prehashed {
+    pub algorithm: DigestAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum PrehashError {
+    #[error("a prehashed payload must be exactly {{\"digest\": string, \"digest_alg\": string}}")]
+    NotADigest,
+    #[error("unsupported digest_alg `{0}`; expected `sha256`")]
+    UnsupportedAlgorithm(String),
+    #[error("digest must be the base64 encoding of a {0}-byte digest")]
+    InvalidDigest(usize),
+}
+
+impl Prehashed {
+    /// The digest `map` carries, which must have exactly the two string
+    /// members `digest` and `digest_alg`.
+    pub fn parse(map: &Map<String, Value>) -> Result<Self, PrehashError> {
+        let member = |name| map.get(name).and_then(Value::as_str);
+        match (map.len(), member("digest"), member("digest_alg")) {
+            (2, Some(digest), Some(algorithm)) => Self::decode(algorithm, digest),
+            _ => Err(PrehashError::NotADigest),
+        }
+    }
+
+    fn decode(algorithm: &str, digest: &str) -> Result<Self, PrehashError> {
+        let algorithm = match algorithm {
+            "sha256" => DigestAlgorithm::Sha256,
+            other => return Err(PrehashError::UnsupportedAlgorithm(other.into())),
+        };
+        match codec::decode(digest) {
+            Some(digest) if digest.len() == algorithm.len() => Ok(Self { algorithm, digest }),
+            _ => Err(PrehashError::InvalidDigest(algorithm.len())),
+        }
+    }
+
+    /// The bytes the signers sign in place of a canonical payload.
+    pub fn signing_input(&self) -> Vec<u8> {
+        let algorithm = self.algorithm.as_str().as_bytes();
+        let mut input =
+            Vec::with_capacity(DOMAIN_TAG.len() + algorithm.len() + 1 + self.digest.len());
+        input.extend_from_slice(DOMAIN_TAG);
+        input.extend_from_slice(algorithm);
+        input.push(0);
+        input.extend_from_slice(&self.digest);
+        input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn map(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn digests_must_have_their_exact_shape() {
+        let digest = codec::encode(&[7; 32]);
+        let parsed = Prehashed::parse(&map(json!({"digest": digest, "digest_alg": "sha256"})));
+        assert_eq!(
+            parsed,
+            Ok(Prehashed {
+                algorithm: DigestAlgorithm::Sha256,
+                digest: vec![7; 32],
+            })
+        );
+
+        let extra = json!({"digest": digest, "digest_alg": "sha256", "note": "x"});
+        assert_eq!(Prehashed::parse(&map(extra)), Err(PrehashError::NotADigest));
+        let missing = json!({"digest": digest});
+        assert_eq!(
+            Prehashed::parse(&map(missing)),
+            Err(PrehashError::NotADigest)
+        );
+        let number = json!({"digest": digest, "digest_alg": 256});
+        assert_eq!(
+            Prehashed::parse(&map(number)),
+            Err(PrehashError::NotADigest)
+        );
+    }
+
+    #[test]
+    fn malformed_digests_are_rejected() {
+        let md5 = json!({"digest": codec::encode(&[0; 16]), "digest_alg": "md5"});
+        assert_eq!(
+            Prehashed::parse(&map(md5)),
+            Err(PrehashError::UnsupportedAlgorithm("md5".into()))
+        );
+        let short = json!({"digest": codec::encode(&[0; 16]), "digest_alg": "sha256"});
+        assert_eq!(
+            Prehashed::parse(&map(short)),
+            Err(PrehashError::InvalidDigest(32))
+        );
+    }
+
+    #[test]
+    fn signing_input_is_never_a_canonical_payload() {
+        let prehashed = Prehashed {
+            algorithm: DigestAlgorithm::Sha256,
+            digest: vec![0; 32],
+        };
+        let input = prehashed.signing_input();
+        assert!(input.starts_with(DOMAIN_TAG));
+        assert!(input[DOMAIN_TAG.len()..].starts_with(b"sha256\0"));
+        assert_eq!(input.len(), DOMAIN_TAG.len() + 7 + 32);
+        assert!(std::str::from_utf8(&input).is_err());
+    }
+}
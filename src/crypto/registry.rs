@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::crypto::signer::AsyncSigner;
+
+/// Signers keyed by algorithm name, with one of them used when a request
+/// does not name an algorithm.
+#[derive(Clone)]
+pub struct SignerRegistry {
+    default_alg: String,
+    signers: HashMap<String, Arc<dyn AsyncSigner>>,
+}
+
+impl SignerRegistry {
+    pub fn new(default_alg: impl Into<String>, signer: Arc<dyn AsyncSigner>) -> Self {
+        let default_alg = default_alg.into();
+        let signers = HashMap::from([(default_alg.clone(), signer)]);
+        Self {
+            default_alg,
+            signers,
+        }
+    }
+
+    /// Registers `signer` for `alg`, replacing any previous one.
+    pub fn with(mut self, alg: impl Into<String>, signer: Arc<dyn AsyncSigner>) -> Self {
+        self.signers.insert(alg.into(), signer);
+        self
+    }
+
+    pub fn get(&self, alg: &str) -> Option<&Arc<dyn AsyncSigner>> {
+        self.signers.get(alg)
+    }
+
+    pub fn default_alg(&self) -> &str {
+        &self.default_alg
+    }
+
+    pub fn default_signer(&self) -> &Arc<dyn AsyncSigner> {
+        &self.signers[&self.default_alg]
+    }
+
+    /// The same algorithms, each served by `wrap` of its signer.
+    pub fn map(&self, wrap: impl Fn(&Arc<dyn AsyncSigner>) -> Arc<dyn AsyncSigner>) -> Self {
+        Self {
+            default_alg: self.default_alg.clone(),
+            signers: self
+                .signers
+                .iter()
+                .map(|(alg, signer)| (alg.clone(), wrap(signer)))
+                .collect(),
+        }
+    }
+
+    /// Registered algorithm names, sorted.
+    pub fn algorithms(&self) -> Vec<&str> {
+        let mut algs: Vec<&str> = self.signers.keys().map(String::as_str).collect();
+        algs.sort_unstable();
+        algs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hmac::HMacSigner;
+
+    fn signer(key: &str) -> Arc<dyn AsyncSigner> {
+        Arc::new(HMacSigner::new(key.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn default_is_registered() {
+        let registry = SignerRegistry::new("hmac-sha256", signer("k"));
+        assert_eq!(registry.default_alg(), "hmac-sha256");
+        assert!(registry.get("hmac-sha256").is_some());
+        assert!(registry.get("ed25519").is_none());
+    }
+
+    #[test]
+    fn with_adds_and_replaces() {
+        let registry = SignerRegistry::new("a", signer("1"))
+            .with("b", signer("2"))
+            .with("a", signer("3"));
+        assert_eq!(registry.algorithms(), vec!["a", "b"]);
+    }
+}
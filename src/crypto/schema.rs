@@ -0,0 +1,134 @@
+//! Named JSON Schemas that payloads are checked against before they are
+//! signed, so a structurally invalid document never gets a signature.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use jsonschema::Validator;
+use serde_json::Value;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum SchemaError {
+    #[error("unknown schema `{name}` (available: {available})")]
+    Unknown { name: String, available: String },
+    #[error("{0}")]
+    Invalid(String),
+    #[error("payload does not match schema `{name}`: {reason}")]
+    Mismatch { name: String, reason: String },
+}
+
+/// Compiled schemas by name. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: BTreeMap<String, Arc<Validator>>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles `schema` and registers it as `name`, replacing any schema
+    /// already registered under that name. References to other documents
+    /// are not fetched.
+    pub fn with(mut self, name: &str, schema: &Value) -> Result<Self, SchemaError> {
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|err| SchemaError::Invalid(err.to_string()))?;
+        self.schemas.insert(name.to_string(), Arc::new(validator));
+        Ok(self)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.schemas.keys().map(String::as_str).collect()
+    }
+
+    /// Checks `payload` against the schema registered as `name`. A mismatch
+    /// names the first failing location as a JSON Pointer.
+    pub fn validate(&self, name: &str, payload: &Value) -> Result<(), SchemaError> {
+        let validator = self.schemas.get(name).ok_or_else(|| SchemaError::Unknown {
+            name: name.to_string(),
+            available: match self.names() {
+                names if names.is_empty() => "none".to_string(),
+                names => names.join(", "),
+            },
+        })?;
+        validator.validate(payload).map_err(|err| {
+            let pointer = err.instance_path().to_string();
+            SchemaError::Mismatch {
+                name: name.to_string(),
+                reason: if pointer.is_empty() {
+                    err.to_string()
+                } else {
+                    format!("`{pointer}`: {err}")
+                },
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn payments() -> SchemaRegistry {
+        let schema = json!({
+            "type": "object",
+            "required": ["amount", "currency"],
+            "properties": {
+                "amount": {"type": "integer", "minimum": 1},
+                "currency": {"enum": ["EUR", "USD"]},
+            },
+            "additionalProperties": false,
+        });
+        SchemaRegistry::new().with("payment-v2", &schema).unwrap()
+    }
+
+    #[test]
+    fn matching_payload_passes() {
+        let payload = json!({"amount": 100, "currency": "EUR"});
+        assert_eq!(payments().validate("payment-v2", &payload), Ok(()));
+    }
+
+    #[test]
+    fn mismatch_names_the_failing_location() {
+        let payload = json!({"amount": 0, "currency": "EUR"});
+        let Err(SchemaError::Mismatch { name, reason }) =
+            payments().validate("payment-v2", &payload)
+        else {
+            panic!("expected a mismatch");
+        };
+        assert_eq!(name, "payment-v2");
+        assert!(reason.starts_with("`/amount`: "), "{reason}");
+    }
+
+    #[test]
+    fn missing_property_is_reported_at_the_root() {
+        let payload = json!({"amount": 5});
+        let err = payments().validate("payment-v2", &payload).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "payload does not match schema `payment-v2`: \"currency\" is a required property"
+        );
+    }
+
+    #[test]
+    fn unknown_schema_lists_the_registered_ones() {
+        assert_eq!(
+            payments().validate("payment-v1", &json!({})),
+            Err(SchemaError::Unknown {
+                name: "payment-v1".into(),
+                available: "payment-v2".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_schema_is_refused() {
+        let err = SchemaRegistry::new()
+            .with("broken", &json!({"type": "no-such-type"}))
+            .err();
+        assert!(matches!(err, Some(SchemaError::Invalid(_))));
+    }
+}
@@ -0,0 +1,165 @@
+//! Public-key sealing: X25519 key agreement with an ephemeral key, HKDF-SHA256
+//! key derivation and ChaCha20-Poly1305, so a payload can be encrypted to a
+//! recipient that only published its public key.
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use crate::crypto::codec;
+
+/// Identifier of the construction, sent alongside sealed payloads.
+pub const SEAL_ALGORITHM: &str = "x25519-hkdf-sha256-chacha20poly1305";
+
+const HKDF_INFO: &[u8] = b"take-home seal v1";
+
+/// A sealed payload. All fields are standard base64.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sealed {
+    /// Ephemeral public key of the sender.
+    pub epk: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum SealError {
+    #[error("invalid X25519 public key")]
+    InvalidKey,
+    #[error("malformed sealed payload")]
+    Malformed,
+    #[error("sealed payload failed authentication")]
+    AuthenticationFailed,
+}
+
+/// Parses a base64-encoded 32-byte X25519 public key.
+pub fn parse_public_key(encoded: &str) -> Result<PublicKey, SealError> {
+    let bytes: [u8; 32] = codec::decode(encoded.trim())
+        .and_then(|b| b.try_into().ok())
+        .ok_or(SealError::InvalidKey)?;
+    Ok(PublicKey::from(bytes))
+}
+
+/// Encrypts `plaintext` so that only the holder of `recipient`'s secret key
+/// can read it.
+pub fn seal(recipient: &PublicKey, plaintext: &[u8]) -> Result<Sealed, SealError> {
+    let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+    let epk = PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(recipient);
+    // A low-order recipient key yields an all-zero secret anyone can compute.
+    if !shared.was_contributory() {
+        return Err(SealError::InvalidKey);
+    }
+    let cipher = ChaCha20Poly1305::new(&derive_key(shared.as_bytes(), &epk, recipient));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| SealError::Malformed)?;
+    Ok(Sealed {
+        epk: codec::encode(epk.as_bytes()),
+        nonce: codec::encode(&nonce),
+        ciphertext: codec::encode(&ciphertext),
+    })
+}
+
+/// Decrypts a payload produced by [`seal`] for the public key of `secret`.
+pub fn open(secret: &StaticSecret, sealed: &Sealed) -> Result<Vec<u8>, SealError> {
+    let epk = parse_public_key(&sealed.epk).map_err(|_| SealError::Malformed)?;
+    let nonce: [u8; 12] = codec::decode(&sealed.nonce)
+        .and_then(|b| b.try_into().ok())
+        .ok_or(SealError::Malformed)?;
+    let ciphertext = codec::decode(&sealed.ciphertext).ok_or(SealError::Malformed)?;
+    let shared = secret.diffie_hellman(&epk);
+    if !shared.was_contributory() {
+        return Err(SealError::Malformed);
+    }
+    let recipient = PublicKey::from(secret);
+    let cipher = ChaCha20Poly1305::new(&derive_key(shared.as_bytes(), &epk, &recipient));
+    cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| SealError::AuthenticationFailed)
+}
+
+/// Binds the derived key to both public keys so a sealed payload cannot be
+/// replayed against another recipient.
+fn derive_key(shared: &[u8; 32], epk: &PublicKey, recipient: &PublicKey) -> Key {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(epk.as_bytes());
+    salt[32..].copy_from_slice(recipient.as_bytes());
+    let mut key = Key::default();
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(HKDF_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keypair() -> (StaticSecret, PublicKey) {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        (secret, public)
+    }
+
+    // ── seal / open ────────────────────────────────────────────────
+
+    #[test]
+    fn round_trip() {
+        let (secret, public) = keypair();
+        let sealed = seal(&public, b"{\"ssn\":\"123\"}").unwrap();
+        assert_eq!(open(&secret, &sealed).unwrap(), b"{\"ssn\":\"123\"}");
+    }
+
+    #[test]
+    fn sealing_is_randomized() {
+        let (_, public) = keypair();
+        assert_ne!(seal(&public, b"x").unwrap(), seal(&public, b"x").unwrap());
+    }
+
+    #[test]
+    fn other_recipient_cannot_open() {
+        let (_, public) = keypair();
+        let (other, _) = keypair();
+        let sealed = seal(&public, b"x").unwrap();
+        assert_eq!(open(&other, &sealed), Err(SealError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn tampered_ciphertext_fails_authentication() {
+        let (secret, public) = keypair();
+        let mut sealed = seal(&public, b"hello").unwrap();
+        let mut bytes = codec::decode(&sealed.ciphertext).unwrap();
+        bytes[0] ^= 1;
+        sealed.ciphertext = codec::encode(&bytes);
+        assert_eq!(open(&secret, &sealed), Err(SealError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn low_order_recipient_is_rejected() {
+        let zero = PublicKey::from([0u8; 32]);
+        assert_eq!(seal(&zero, b"x"), Err(SealError::InvalidKey));
+    }
+
+    // ── parse_public_key ───────────────────────────────────────────
+
+    #[test]
+    fn parses_base64_public_key() {
+        let (_, public) = keypair();
+        let encoded = codec::encode(public.as_bytes());
+        assert_eq!(parse_public_key(&encoded).unwrap(), public);
+    }
+
+    #[test]
+    fn wrong_length_key_is_rejected() {
+        assert_eq!(
+            parse_public_key(&codec::encode(&[1u8; 16])),
+            Err(SealError::InvalidKey)
+        );
+        assert_eq!(parse_public_key("not base64!"), Err(SealError::InvalidKey));
+    }
+}
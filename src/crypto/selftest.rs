@@ -0,0 +1,371 @@
+//! Known-answer tests: each algorithm is run on a published test vector
+//! and its output compared with the published result, so a miscompiled or
+//! misconfigured primitive is caught at startup instead of producing bad
+//! signatures or ciphertexts.
+
+#[cfg(feature = "signing")]
+use crate::crypto::hmac::HmacDigest;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum SelfTestError {
+    #[error("known-answer test for {0} failed")]
+    KnownAnswer(&'static str),
+    #[error("no known-answer test for `{0}`")]
+    Unsupported(String),
+}
+
+/// Runs the known-answer tests of [`primitives`] and of every algorithm in
+/// `algorithms`, stopping at the first failure. Algorithms without a test
+/// fail too, so nothing configured goes unchecked.
+pub fn run<'a>(algorithms: impl IntoIterator<Item = &'a str>) -> Result<(), SelfTestError> {
+    primitives()?;
+    algorithms.into_iter().try_for_each(known_answer)
+}
+
+/// Runs the known-answer test of the signature or encryption algorithm
+/// `algorithm`, named as in the `alg` query parameter or
+/// `encryption.algorithm`.
+pub fn known_answer(algorithm: &str) -> Result<(), SelfTestError> {
+    match algorithm {
+        #[cfg(feature = "signing")]
+        "hmac-sha256" => hmac(
+            "hmac-sha256",
+            HmacDigest::Sha256,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        ),
+        #[cfg(feature = "signing")]
+        "hmac-sha384" => hmac(
+            "hmac-sha384",
+            HmacDigest::Sha384,
+            "af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e\
+             8e2240ca5e69e2c78b3239ecfab21649",
+        ),
+        #[cfg(feature = "signing")]
+        "hmac-sha512" => hmac(
+            "hmac-sha512",
+            HmacDigest::Sha512,
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+             9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+        ),
+        #[cfg(feature = "asymmetric")]
+        "rsa-v1_5-sha256" => rsa_v1_5_sha256(),
+        #[cfg(feature = "asymmetric")]
+        "ecdsa-p256-sha256" => ecdsa_p256_sha256(),
+        #[cfg(feature = "asymmetric")]
+        "ed25519" => ed25519(),
+        #[cfg(feature = "encryption")]
+        "base64" => base64(),
+        other => Err(SelfTestError::Unsupported(other.to_string())),
+    }
+}
+
+/// Runs the known-answer tests of the primitives built into this binary
+/// that have no algorithm name of their own: the AEAD, key derivation and
+/// key agreement behind sealed responses, key escrow and the SQLite tenant
+/// key store.
+pub fn primitives() -> Result<(), SelfTestError> {
+    #[cfg(any(
+        feature = "response-encryption",
+        feature = "escrow",
+        feature = "sqlite"
+    ))]
+    {
+        chacha20_poly1305()?;
+        hkdf_sha256()?;
+    }
+    #[cfg(any(feature = "response-encryption", feature = "escrow"))]
+    x25519()?;
+    Ok(())
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "signing",
+        feature = "encryption",
+        feature = "response-encryption",
+        feature = "escrow",
+        feature = "sqlite"
+    )),
+    allow(dead_code)
+)]
+fn check(name: &'static str, passed: bool) -> Result<(), SelfTestError> {
+    if passed {
+        Ok(())
+    } else {
+        Err(SelfTestError::KnownAnswer(name))
+    }
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "signing",
+        feature = "response-encryption",
+        feature = "escrow",
+        feature = "sqlite"
+    )),
+    allow(dead_code)
+)]
+fn unhex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("test vectors are valid hex"))
+        .collect()
+}
+
+/// RFC 4231, test case 2.
+#[cfg(feature = "signing")]
+fn hmac(name: &'static str, digest: HmacDigest, expected: &str) -> Result<(), SelfTestError> {
+    use crate::crypto::hmac::HMacSigner;
+
+    let signer = HMacSigner::with_digest(b"Jefe".to_vec(), digest);
+    let data = b"what do ya want for nothing?";
+    let expected = unhex(expected);
+    check(
+        name,
+        signer.mac(data) == expected
+            && signer.verify_mac(data, &expected)
+            && !signer.verify_mac(b"what do ya want for something?", &expected),
+    )
+}
+
+/// Verifies a signature made by OpenSSL over `hello`; RSA signing keys are
+/// too large to embed for a signing vector.
+#[cfg(feature = "asymmetric")]
+fn rsa_v1_5_sha256() -> Result<(), SelfTestError> {
+    use base64::Engine as _;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use rsa::pkcs8::DecodePublicKey as _;
+    use rsa::signature::Verifier as _;
+
+    const PUBLIC_KEY: &str = "-----BEGIN PUBLIC KEY-----
+MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA0Vm/oF9rLPt7WNfbpwWL
+cWHQRTodfIR2+dCCs3XfHUNZxbbzn29XRJjMzKQ3TIPlhvXxTNlDkpI+ZvHpcTVs
+omJaGUeTOveaAN/In9mlCF52gOb4sUEp8Xi4znfdAr5G8J16NaMRZ9xJBgkFyVGO
+SXFDtfJPWYsLZpg3Vqm+MnVCgO0Z0p9b9M/3uMNIHQWmkwMtKl4oWY8afZowMYEm
+6HlejGCg5tuI48Hnq4rHqupaOGJ90CUMmUjHjupEsy+sdoClt3iP5zVKMEN2lGSp
+DjdNYD+PeNjgZ3Fi+eTfVFri52OCJilCx5e7FOkp+xUZTriZcHFuqjwzs0ZUzkYA
+UQIDAQAB
+-----END PUBLIC KEY-----";
+    const SIGNATURE: &str = "uqD0tDPUu5nmPPsq50qiHUo_tGH_wY-F7kDKhcvrQFRL931tZzu7_f6lbFf8gF25\
+        B6Vz1L0sHJafACL4LoVzMiYpz6zqsxMDzVF4GQNwEzjP7sez1uwXjs8pQWOFZ4yvsgF54_sUrm6fpJ2g1Z6mp2Y_\
+        YzPoIvtlLlbQ4OTf3EL-SWB90iWn_44CdTXt7g0OckjgamzauVzbJED7XlgqN25dH1sK2M52hzID9kKbT1CU_GiJ\
+        HHoSaEc5_-H6ewhacFhSkaoT4j2LgPXcfTCUjuX_ioY4U551QtNM9wI0waCo1FprBAuUT50sIpwt7VfPhWBtOERJ\
+        Ao5tcBCgcx_2yg";
+
+    let failed = || SelfTestError::KnownAnswer("rsa-v1_5-sha256");
+    let key = rsa::pkcs1v15::VerifyingKey::<sha2::Sha256>::from_public_key_pem(PUBLIC_KEY)
+        .map_err(|_| failed())?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(SIGNATURE)
+        .ok()
+        .and_then(|bytes| rsa::pkcs1v15::Signature::try_from(bytes.as_slice()).ok())
+        .ok_or_else(failed)?;
+    check(
+        "rsa-v1_5-sha256",
+        key.verify(b"hello", &signature).is_ok() && key.verify(b"hellO", &signature).is_err(),
+    )
+}
+
+/// RFC 6979, A.2.5, SHA-256 over `sample`: deterministic nonces make the
+/// signature reproducible.
+#[cfg(feature = "asymmetric")]
+fn ecdsa_p256_sha256() -> Result<(), SelfTestError> {
+    use crate::crypto::keys::PrivateKey;
+
+    let secret = unhex("c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721");
+    let key = p256::SecretKey::from_slice(&secret)
+        .map_err(|_| SelfTestError::KnownAnswer("ecdsa-p256-sha256"))?;
+    let expected = unhex(
+        "efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716\
+         f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8",
+    );
+    signs_as(
+        "ecdsa-p256-sha256",
+        PrivateKey::EcdsaP256(key),
+        b"sample",
+        &expected,
+    )
+}
+
+/// RFC 8032, section 7.1, test 1 (empty message).
+#[cfg(feature = "asymmetric")]
+fn ed25519() -> Result<(), SelfTestError> {
+    use crate::crypto::keys::PrivateKey;
+
+    let secret = unhex("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60");
+    let secret = <[u8; 32]>::try_from(secret).expect("the vector holds 32 bytes");
+    let expected = unhex(
+        "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555\
+         fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+    );
+    let key = PrivateKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&secret));
+    signs_as("ed25519", key, b"", &expected)
+}
+
+/// Signs `message` through [`AsymmetricSigner`], the code path serving
+/// requests, and checks the signature and its verification.
+///
+/// [`AsymmetricSigner`]: crate::crypto::asymmetric::AsymmetricSigner
+#[cfg(feature = "asymmetric")]
+fn signs_as(
+    name: &'static str,
+    key: crate::crypto::keys::PrivateKey,
+    message: &[u8],
+    expected: &[u8],
+) -> Result<(), SelfTestError> {
+    use base64::Engine as _;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    use crate::crypto::asymmetric::AsymmetricSigner;
+    use crate::crypto::signer::Signer;
+
+    let signer = AsymmetricSigner::new(key);
+    let signature = signer.sign_bytes(message);
+    check(
+        name,
+        URL_SAFE_NO_PAD
+            .decode(&signature)
+            .is_ok_and(|bytes| bytes == expected)
+            && signer.verify_bytes(message, &signature)
+            && !signer.verify_bytes(b"tampered", &signature),
+    )
+}
+
+/// RFC 4648 test vector wrapped in the JSON string it encodes.
+#[cfg(feature = "encryption")]
+fn base64() -> Result<(), SelfTestError> {
+    use serde_json::json;
+
+    use crate::crypto::base64::Base64Encryptor;
+    use crate::crypto::encryptor::Encryptor;
+
+    let encryptor = Base64Encryptor;
+    let encrypted = encryptor.encrypt(&json!("foobar"));
+    check(
+        "base64",
+        encrypted
+            .as_ref()
+            .is_ok_and(|value| value == "ImZvb2JhciI=")
+            && encrypted.is_ok_and(|value| encryptor.decrypt(&value) == Ok(json!("foobar"))),
+    )
+}
+
+/// RFC 8439, section 2.8.2.
+#[cfg(any(
+    feature = "response-encryption",
+    feature = "escrow",
+    feature = "sqlite"
+))]
+fn chacha20_poly1305() -> Result<(), SelfTestError> {
+    use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+    use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+    let key = unhex("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+    let nonce = unhex("070000004041424344454647");
+    let aad = unhex("50515253c0c1c2c3c4c5c6c7");
+    let plaintext: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+        only one tip for the future, sunscreen would be it.";
+    let expected = unhex(
+        "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6\
+         3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36\
+         92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+         3ff4def08e4b7a9de576d26586cec64b6116\
+         1ae10b594f09e26a7e902ecbd0600691",
+    );
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let nonce = Nonce::from_slice(&nonce);
+    let sealed = cipher.encrypt(
+        nonce,
+        Payload {
+            msg: plaintext,
+            aad: &aad,
+        },
+    );
+    let opened = cipher.decrypt(
+        nonce,
+        Payload {
+            msg: &expected,
+            aad: &aad,
+        },
+    );
+    let forged = cipher.decrypt(
+        nonce,
+        Payload {
+            msg: &expected,
+            aad: b"other",
+        },
+    );
+    check(
+        "chacha20-poly1305",
+        sealed.is_ok_and(|sealed| sealed == expected)
+            && opened.is_ok_and(|opened| opened == plaintext)
+            && forged.is_err(),
+    )
+}
+
+/// RFC 5869, test case 1.
+#[cfg(any(
+    feature = "response-encryption",
+    feature = "escrow",
+    feature = "sqlite"
+))]
+fn hkdf_sha256() -> Result<(), SelfTestError> {
+    let ikm = [0x0b; 22];
+    let salt = unhex("000102030405060708090a0b0c");
+    let info = unhex("f0f1f2f3f4f5f6f7f8f9");
+    let expected = unhex(
+        "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf\
+         34007208d5b887185865",
+    );
+    let mut okm = [0; 42];
+    let expanded = hkdf::Hkdf::<sha2::Sha256>::new(Some(&salt), &ikm).expand(&info, &mut okm);
+    check(
+        "hkdf-sha256",
+        expanded.is_ok() && okm.as_slice() == expected,
+    )
+}
+
+/// RFC 7748, section 6.1.
+#[cfg(any(feature = "response-encryption", feature = "escrow"))]
+fn x25519() -> Result<(), SelfTestError> {
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    let key = |hex| <[u8; 32]>::try_from(unhex(hex)).expect("the vector holds 32 bytes");
+    let alice = StaticSecret::from(key(
+        "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a",
+    ));
+    let bob = PublicKey::from(key(
+        "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f",
+    ));
+    let expected = key("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+    check(
+        "x25519",
+        alice.diffie_hellman(&bob).as_bytes() == &expected
+            && PublicKey::from(&alice).as_bytes()
+                == &key("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_built_in_algorithm_passes() {
+        run([]).unwrap();
+        #[cfg(feature = "signing")]
+        for algorithm in ["hmac-sha256", "hmac-sha384", "hmac-sha512"] {
+            known_answer(algorithm).unwrap();
+        }
+        #[cfg(feature = "encryption")]
+        known_answer("base64").unwrap();
+        #[cfg(feature = "asymmetric")]
+        for algorithm in ["rsa-v1_5-sha256", "ecdsa-p256-sha256", "ed25519"] {
+            known_answer(algorithm).unwrap();
+        }
+        assert_eq!(
+            run(["rot13"]),
+            Err(SelfTestError::Unsupported("rot13".into()))
+        );
+    }
+}
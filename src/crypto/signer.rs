@@ -1,6 +1,133 @@
 use serde_json::{Map, Value};
 
-pub trait Signer {
-    fn sign(&self, map: &Map<String, Value>) -> Value;
-    fn verify(&self, map: &Map<String, Value>, signature: &str) -> bool;
+use crate::crypto::BoxFuture;
+use crate::crypto::canonical::{
+    canonicalize, canonicalize_array, with_canonical, with_canonical_array,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SignError {
+    #[error("signing backend failed: {0}")]
+    Backend(String),
+    #[error("signing backend timed out")]
+    Timeout,
+    /// The key may verify but no longer sign.
+    #[error("{0}")]
+    KeyUnavailable(String),
+    /// The key was loaded before its store became unreachable; it verifies
+    /// until the store is back but does not sign.
+    #[error("key store unavailable: {0}")]
+    StoreUnavailable(String),
+}
+
+pub trait Signer: Send + Sync {
+    /// Signs raw bytes, returning the encoded signature.
+    fn sign_bytes(&self, bytes: &[u8]) -> String;
+    fn verify_bytes(&self, bytes: &[u8], signature: &str) -> bool;
+
+    /// Signs the canonical form of a JSON object.
+    fn sign(&self, map: &Map<String, Value>) -> String {
+        with_canonical(map, |bytes| self.sign_bytes(bytes))
+    }
+
+    fn verify(&self, map: &Map<String, Value>, signature: &str) -> bool {
+        with_canonical(map, |bytes| self.verify_bytes(bytes, signature))
+    }
+
+    /// Signs the canonical form of a top-level JSON array, such as a batch
+    /// of events.
+    fn sign_array(&self, items: &[Value]) -> String {
+        with_canonical_array(items, |bytes| self.sign_bytes(bytes))
+    }
+
+    fn verify_array(&self, items: &[Value], signature: &str) -> bool {
+        with_canonical_array(items, |bytes| self.verify_bytes(bytes, signature))
+    }
+}
+
+/// Async counterpart of [`Signer`] for backends that sign out of process
+/// (KMS, Vault, HSM) and can therefore fail or time out. Every [`Signer`]
+/// is an `AsyncSigner`, so in-process signers need no extra code.
+pub trait AsyncSigner: Send + Sync {
+    fn sign_bytes<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>>;
+    fn verify_bytes<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>>;
+
+    /// Signs the canonical form of a JSON object.
+    fn sign<'a>(&'a self, map: &'a Map<String, Value>) -> BoxFuture<'a, Result<String, SignError>> {
+        let canonical = canonicalize(map);
+        Box::pin(async move { self.sign_bytes(canonical.as_bytes()).await })
+    }
+
+    fn verify<'a>(
+        &'a self,
+        map: &'a Map<String, Value>,
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        let canonical = canonicalize(map);
+        Box::pin(async move { self.verify_bytes(canonical.as_bytes(), signature).await })
+    }
+
+    /// Signs the canonical form of a top-level JSON array.
+    fn sign_array<'a>(&'a self, items: &'a [Value]) -> BoxFuture<'a, Result<String, SignError>> {
+        let canonical = canonicalize_array(items);
+        Box::pin(async move { self.sign_bytes(canonical.as_bytes()).await })
+    }
+
+    fn verify_array<'a>(
+        &'a self,
+        items: &'a [Value],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        let canonical = canonicalize_array(items);
+        Box::pin(async move { self.verify_bytes(canonical.as_bytes(), signature).await })
+    }
+}
+
+impl<T: Signer + ?Sized> AsyncSigner for T {
+    fn sign_bytes<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+        let signature = Signer::sign_bytes(self, bytes);
+        Box::pin(async move { Ok(signature) })
+    }
+
+    fn verify_bytes<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        let valid = Signer::verify_bytes(self, bytes, signature);
+        Box::pin(async move { Ok(valid) })
+    }
+
+    // Forwarded so signers that canonicalize incrementally keep doing so.
+    fn sign<'a>(&'a self, map: &'a Map<String, Value>) -> BoxFuture<'a, Result<String, SignError>> {
+        let signature = Signer::sign(self, map);
+        Box::pin(async move { Ok(signature) })
+    }
+
+    fn verify<'a>(
+        &'a self,
+        map: &'a Map<String, Value>,
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        let valid = Signer::verify(self, map, signature);
+        Box::pin(async move { Ok(valid) })
+    }
+
+    fn sign_array<'a>(&'a self, items: &'a [Value]) -> BoxFuture<'a, Result<String, SignError>> {
+        let signature = Signer::sign_array(self, items);
+        Box::pin(async move { Ok(signature) })
+    }
+
+    fn verify_array<'a>(
+        &'a self,
+        items: &'a [Value],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        let valid = Signer::verify_array(self, items, signature);
+        Box::pin(async move { Ok(valid) })
+    }
 }
@@ -0,0 +1,321 @@
+//! Chunked ChaCha20-Poly1305 in the STREAM construction (Hoang,
+//! Reyhanitabar, Rogaway and Vizár, "Online Authenticated-Encryption and
+//! its Nonce-Reuse Misuse-Resistance", 2015). A plaintext is cut into
+//! fixed-size chunks, each sealed under a nonce made of a random per-stream
+//! prefix, the chunk's sequence number and a flag marking the last chunk,
+//! so chunks cannot be reordered, dropped or cut off without failing
+//! authentication, and any one of them can be opened on its own.
+//!
+//! A sealed stream is a [`StreamHeader`] followed by the sealed chunks:
+//!
+//! ```text
+//! "THS1" | chunk size (u32 BE) | plaintext length (u64 BE) | nonce prefix (7)
+//! chunk 0 | chunk 1 | ... | last chunk      (each: ciphertext | 16-byte tag)
+//! ```
+//!
+//! Every chunk authenticates the header and a caller-chosen context (a
+//! blob's address, say), so neither can be swapped for another's.
+
+use std::ops::Range;
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Identifier of the construction.
+pub const STREAM_ALGORITHM: &str = "chacha20poly1305-stream-be32";
+
+const MAGIC: &[u8; 4] = b"THS1";
+const PREFIX_LEN: usize = 7;
+const TAG_LEN: u64 = 16;
+
+/// Bytes of a [`StreamHeader`] at the start of a sealed stream.
+pub const HEADER_LEN: usize = MAGIC.len() + 4 + 8 + PREFIX_LEN;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum StreamError {
+    #[error("malformed sealed stream")]
+    Malformed,
+    #[error("sealed stream failed authentication")]
+    AuthenticationFailed,
+    #[error("a stream holds at most 2^32 chunks")]
+    TooLong,
+}
+
+/// A ChaCha20-Poly1305 key for streams, derived with HKDF-SHA256.
+pub struct StreamKey(ChaCha20Poly1305);
+
+impl StreamKey {
+    /// Derives the key for `purpose` from `secret`, so one secret can key
+    /// unrelated streams without their keys being related.
+    pub fn derive(secret: &[u8], purpose: &[u8]) -> Self {
+        let mut key = Key::default();
+        Hkdf::<Sha256>::new(None, secret)
+            .expand(purpose, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        Self(ChaCha20Poly1305::new(&key))
+    }
+}
+
+/// The clear start of a sealed stream: everything needed to find and open
+/// a single chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamHeader {
+    pub chunk_size: u32,
+    /// Plaintext bytes in the whole stream.
+    pub length: u64,
+    prefix: [u8; PREFIX_LEN],
+}
+
+impl StreamHeader {
+    /// Whether `sealed` starts like a sealed stream.
+    pub fn is_stream(sealed: &[u8]) -> bool {
+        sealed.starts_with(MAGIC)
+    }
+
+    /// Parses the first [`HEADER_LEN`] bytes of a sealed stream.
+    pub fn parse(sealed: &[u8]) -> Result<Self, StreamError> {
+        let header = sealed.get(..HEADER_LEN).ok_or(StreamError::Malformed)?;
+        let (magic, rest) = header.split_at(MAGIC.len());
+        let (chunk_size, rest) = rest.split_at(4);
+        let (length, prefix) = rest.split_at(8);
+        let chunk_size = u32::from_be_bytes(chunk_size.try_into().expect("4 bytes"));
+        if magic != MAGIC || chunk_size == 0 {
+            return Err(StreamError::Malformed);
+        }
+        let header = Self {
+            chunk_size,
+            length: u64::from_be_bytes(length.try_into().expect("8 bytes")),
+            prefix: prefix.try_into().expect("7 bytes"),
+        };
+        if header.chunk_count() > 1 << 32 {
+            return Err(StreamError::Malformed);
+        }
+        Ok(header)
+    }
+
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[..4].copy_from_slice(MAGIC);
+        bytes[4..8].copy_from_slice(&self.chunk_size.to_be_bytes());
+        bytes[8..16].copy_from_slice(&self.length.to_be_bytes());
+        bytes[16..].copy_from_slice(&self.prefix);
+        bytes
+    }
+
+    /// Chunks in the stream. An empty plaintext still has one, empty, last
+    /// chunk, so an empty stream is authenticated too.
+    pub fn chunk_count(&self) -> u64 {
+        self.length.div_ceil(u64::from(self.chunk_size)).max(1)
+    }
+
+    /// The chunk holding plaintext byte `offset`.
+    pub fn chunk_of(&self, offset: u64) -> u64 {
+        offset / u64::from(self.chunk_size)
+    }
+
+    /// Where chunk `index` lies in the plaintext.
+    pub fn plaintext_range(&self, index: u64) -> Range<u64> {
+        let start = index * u64::from(self.chunk_size);
+        start..(start + u64::from(self.chunk_size)).min(self.length)
+    }
+
+    /// Where chunk `index` lies in the sealed stream.
+    pub fn sealed_range(&self, index: u64) -> Range<u64> {
+        let plaintext = self.plaintext_range(index);
+        let start = HEADER_LEN as u64 + index * (u64::from(self.chunk_size) + TAG_LEN);
+        start..start + (plaintext.end - plaintext.start) + TAG_LEN
+    }
+
+    /// Bytes of the whole sealed stream.
+    pub fn sealed_len(&self) -> u64 {
+        self.sealed_range(self.chunk_count() - 1).end
+    }
+
+    fn nonce(&self, index: u64) -> Nonce {
+        let mut nonce = Nonce::default();
+        nonce[..PREFIX_LEN].copy_from_slice(&self.prefix);
+        nonce[PREFIX_LEN..11].copy_from_slice(&(index as u32).to_be_bytes());
+        nonce[11] = u8::from(index + 1 == self.chunk_count());
+        nonce
+    }
+
+    fn aad(&self, context: &[u8]) -> Vec<u8> {
+        [context, &self.to_bytes()].concat()
+    }
+}
+
+/// Seals `plaintext` in chunks of `chunk_size` bytes, bound to `context`.
+pub fn seal(
+    key: &StreamKey,
+    context: &[u8],
+    plaintext: &[u8],
+    chunk_size: u32,
+) -> Result<Vec<u8>, StreamError> {
+    if chunk_size == 0 {
+        return Err(StreamError::Malformed);
+    }
+    let mut prefix = [0; PREFIX_LEN];
+    OsRng.fill_bytes(&mut prefix);
+    let header = StreamHeader {
+        chunk_size,
+        length: plaintext.len() as u64,
+        prefix,
+    };
+    if header.chunk_count() > 1 << 32 {
+        return Err(StreamError::TooLong);
+    }
+    let aad = header.aad(context);
+    let mut sealed = Vec::with_capacity(header.sealed_len() as usize);
+    sealed.extend_from_slice(&header.to_bytes());
+    for index in 0..header.chunk_count() {
+        let range = header.plaintext_range(index);
+        let chunk = &plaintext[range.start as usize..range.end as usize];
+        let payload = Payload {
+            msg: chunk,
+            aad: &aad,
+        };
+        let ciphertext = key
+            .0
+            .encrypt(&header.nonce(index), payload)
+            .map_err(|_| StreamError::Malformed)?;
+        sealed.extend_from_slice(&ciphertext);
+    }
+    Ok(sealed)
+}
+
+/// Opens chunk `index` of the stream `header` starts, given the bytes at
+/// [`StreamHeader::sealed_range`].
+pub fn open_chunk(
+    key: &StreamKey,
+    context: &[u8],
+    header: &StreamHeader,
+    index: u64,
+    sealed_chunk: &[u8],
+) -> Result<Vec<u8>, StreamError> {
+    if index >= header.chunk_count() {
+        return Err(StreamError::Malformed);
+    }
+    let range = header.sealed_range(index);
+    if sealed_chunk.len() as u64 != range.end - range.start {
+        return Err(StreamError::Malformed);
+    }
+    let payload = Payload {
+        msg: sealed_chunk,
+        aad: &header.aad(context),
+    };
+    key.0
+        .decrypt(&header.nonce(index), payload)
+        .map_err(|_| StreamError::AuthenticationFailed)
+}
+
+/// Opens a whole sealed stream.
+pub fn open(key: &StreamKey, context: &[u8], sealed: &[u8]) -> Result<Vec<u8>, StreamError> {
+    let header = StreamHeader::parse(sealed)?;
+    if sealed.len() as u64 != header.sealed_len() {
+        return Err(StreamError::Malformed);
+    }
+    let mut plaintext = Vec::with_capacity(header.length as usize);
+    for index in 0..header.chunk_count() {
+        let range = header.sealed_range(index);
+        let chunk = &sealed[range.start as usize..range.end as usize];
+        plaintext.extend(open_chunk(key, context, &header, index, chunk)?);
+    }
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> StreamKey {
+        StreamKey::derive(b"secret", b"test")
+    }
+
+    fn chunk<'a>(sealed: &'a [u8], header: &StreamHeader, index: u64) -> &'a [u8] {
+        let range = header.sealed_range(index);
+        &sealed[range.start as usize..range.end as usize]
+    }
+
+    #[test]
+    fn streams_round_trip_at_every_length() {
+        for length in [0, 1, 15, 16, 17, 48, 100] {
+            let plaintext: Vec<u8> = (0..length as u8).collect();
+            let sealed = seal(&key(), b"ctx", &plaintext, 16).unwrap();
+            let header = StreamHeader::parse(&sealed).unwrap();
+            assert_eq!(header.sealed_len(), sealed.len() as u64, "{length}");
+            assert_eq!(open(&key(), b"ctx", &sealed).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn chunks_open_on_their_own() {
+        let plaintext: Vec<u8> = (0..40).collect();
+        let sealed = seal(&key(), b"ctx", &plaintext, 16).unwrap();
+        let header = StreamHeader::parse(&sealed).unwrap();
+        assert_eq!(header.chunk_count(), 3);
+        assert_eq!(header.chunk_of(39), 2);
+        assert_eq!(header.plaintext_range(2), 32..40);
+        let last = open_chunk(&key(), b"ctx", &header, 2, chunk(&sealed, &header, 2)).unwrap();
+        assert_eq!(last, &plaintext[32..]);
+    }
+
+    #[test]
+    fn chunks_are_bound_to_their_position_context_and_key() {
+        let plaintext = [7u8; 48];
+        let sealed = seal(&key(), b"ctx", &plaintext, 16).unwrap();
+        let header = StreamHeader::parse(&sealed).unwrap();
+        // Equal plaintext chunks still only open at their own index.
+        let first = chunk(&sealed, &header, 0);
+        assert_eq!(
+            open_chunk(&key(), b"ctx", &header, 1, first),
+            Err(StreamError::AuthenticationFailed)
+        );
+        assert_eq!(
+            open_chunk(&key(), b"other", &header, 0, first),
+            Err(StreamError::AuthenticationFailed)
+        );
+        let other_key = StreamKey::derive(b"secret", b"other");
+        assert_eq!(
+            open_chunk(&other_key, b"ctx", &header, 0, first),
+            Err(StreamError::AuthenticationFailed)
+        );
+    }
+
+    #[test]
+    fn truncation_is_detected() {
+        let sealed = seal(&key(), b"ctx", &[1u8; 48], 16).unwrap();
+        let header = StreamHeader::parse(&sealed).unwrap();
+        // Dropping the last chunk means claiming a shorter length, which
+        // changes the header every chunk authenticates.
+        let mut truncated = StreamHeader {
+            length: 32,
+            ..header
+        }
+        .to_bytes()
+        .to_vec();
+        truncated.extend_from_slice(&sealed[HEADER_LEN..header.sealed_range(1).end as usize]);
+        assert_eq!(
+            open(&key(), b"ctx", &truncated),
+            Err(StreamError::AuthenticationFailed)
+        );
+        assert_eq!(
+            open(&key(), b"ctx", &sealed[..sealed.len() - 1]),
+            Err(StreamError::Malformed)
+        );
+    }
+
+    #[test]
+    fn malformed_headers_are_refused() {
+        assert!(!StreamHeader::is_stream(b"\"abc\""));
+        assert_eq!(StreamHeader::parse(b"THS1"), Err(StreamError::Malformed));
+        let mut zero_chunks = seal(&key(), b"", b"x", 16).unwrap();
+        zero_chunks[4..8].copy_from_slice(&0u32.to_be_bytes());
+        assert_eq!(
+            StreamHeader::parse(&zero_chunks),
+            Err(StreamError::Malformed)
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! Checks on raw JSON documents that `serde_json` does not make while
+//! parsing. It keeps the last of several members with the same name, so
+//! `{"amount":1,"amount":9999}` would otherwise sign as `9999` while another
+//! parser might read `1`. It also accepts any number of members and, up to
+//! its own recursion limit of 128, any nesting depth and strings of any
+//! length, which makes adversarial documents expensive to canonicalize.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum StrictJsonError {
+    #[error("duplicate JSON key at `{0}`")]
+    DuplicateKey(String),
+    #[error("JSON nesting exceeds the limit of {0} levels")]
+    TooDeep(usize),
+    #[error("JSON document exceeds the limit of {0} keys and array elements")]
+    TooManyEntries(usize),
+    #[error("JSON object exceeds the limit of {0} top-level fields")]
+    TooManyFields(usize),
+    #[error("JSON string at `{pointer}` exceeds the limit of {limit} bytes")]
+    ValueTooLarge { pointer: String, limit: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrictJson {
+    pub reject_duplicate_keys: bool,
+    /// Deepest allowed nesting; a top-level object is at depth 1.
+    pub max_depth: usize,
+    /// Most object members plus array elements allowed in one document.
+    pub max_entries: usize,
+    /// Most members allowed in the top-level object.
+    pub max_fields: usize,
+    /// Longest string, key or value, allowed anywhere in the document.
+    pub max_value_bytes: usize,
+}
+
+impl Default for StrictJson {
+    fn default() -> Self {
+        Self {
+            reject_duplicate_keys: false,
+            max_depth: 32,
+            max_entries: 10_000,
+            max_fields: 1_000,
+            max_value_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl StrictJson {
+    /// Scans `json` without building it. Duplicate keys are reported as an
+    /// RFC 6901 JSON Pointer. Syntax errors are not reported here; they are
+    /// left to the parser that reads the document afterwards.
+    pub fn check(&self, json: &[u8]) -> Result<(), StrictJsonError> {
+        let scan = RefCell::new(Scan::default());
+        let mut deserializer = serde_json::Deserializer::from_slice(json);
+        let checker = Checker {
+            options: self,
+            pointer: "",
+            depth: 0,
+            scan: &scan,
+        };
+        let result = checker.deserialize(&mut deserializer);
+        match (result, scan.into_inner().violation) {
+            (Err(_), Some(violation)) => Err(violation),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Scan {
+    entries: usize,
+    violation: Option<StrictJsonError>,
+}
+
+/// Walks one value, tracking its pointer and depth.
+struct Checker<'a> {
+    options: &'a StrictJson,
+    pointer: &'a str,
+    depth: usize,
+    scan: &'a RefCell<Scan>,
+}
+
+impl Checker<'_> {
+    fn fail<E: de::Error>(&self, violation: StrictJsonError) -> E {
+        let message = violation.to_string();
+        self.scan.borrow_mut().violation = Some(violation);
+        E::custom(message)
+    }
+
+    /// Depth of the container being entered.
+    fn enter<E: de::Error>(&self) -> Result<usize, E> {
+        let depth = self.depth + 1;
+        if depth > self.options.max_depth {
+            return Err(self.fail(StrictJsonError::TooDeep(self.options.max_depth)));
+        }
+        Ok(depth)
+    }
+
+    fn count_entry<E: de::Error>(&self) -> Result<(), E> {
+        let entries = {
+            let mut scan = self.scan.borrow_mut();
+            scan.entries += 1;
+            scan.entries
+        };
+        if entries > self.options.max_entries {
+            return Err(self.fail(StrictJsonError::TooManyEntries(self.options.max_entries)));
+        }
+        Ok(())
+    }
+
+    fn check_length<E: de::Error>(&self, pointer: &str, value: &str) -> Result<(), E> {
+        if value.len() > self.options.max_value_bytes {
+            return Err(self.fail(StrictJsonError::ValueTooLarge {
+                pointer: pointer.to_string(),
+                limit: self.options.max_value_bytes,
+            }));
+        }
+        Ok(())
+    }
+
+    fn child<'c>(&'c self, pointer: &'c str, depth: usize) -> Checker<'c> {
+        Checker {
+            options: self.options,
+            pointer,
+            depth,
+            scan: self.scan,
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Checker<'_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Checker<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<(), E> {
+        self.check_length(self.pointer, value)
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let depth = self.enter()?;
+        let mut index = 0usize;
+        loop {
+            let pointer = format!("{}/{index}", self.pointer);
+            if seq
+                .next_element_seed(self.child(&pointer, depth))?
+                .is_none()
+            {
+                return Ok(());
+            }
+            self.count_entry()?;
+            index += 1;
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let depth = self.enter()?;
+        let mut seen = HashSet::new();
+        let mut fields = 0usize;
+        while let Some(key) = map.next_key::<String>()? {
+            self.count_entry()?;
+            fields += 1;
+            if depth == 1 && fields > self.options.max_fields {
+                return Err(self.fail(StrictJsonError::TooManyFields(self.options.max_fields)));
+            }
+            let pointer = format!(
+                "{}/{}",
+                self.pointer,
+                key.replace('~', "~0").replace('/', "~1")
+            );
+            self.check_length(&pointer, &key)?;
+            if !seen.insert(key) && self.options.reject_duplicate_keys {
+                return Err(self.fail(StrictJsonError::DuplicateKey(pointer)));
+            }
+            map.next_value_seed(self.child(&pointer, depth))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strict() -> StrictJson {
+        StrictJson {
+            reject_duplicate_keys: true,
+            ..StrictJson::default()
+        }
+    }
+
+    #[test]
+    fn duplicate_top_level_key_is_reported() {
+        assert_eq!(
+            strict().check(br#"{"amount":1,"amount":9999}"#),
+            Err(StrictJsonError::DuplicateKey("/amount".into()))
+        );
+    }
+
+    #[test]
+    fn nested_duplicates_are_reported_with_their_pointer() {
+        let json = br#"{"data": {"items": [{"id": 1}, {"a/b": 1, "a/b": 2}]}}"#;
+        assert_eq!(
+            strict().check(json),
+            Err(StrictJsonError::DuplicateKey("/data/items/1/a~1b".into()))
+        );
+    }
+
+    #[test]
+    fn escaped_spellings_of_the_same_key_are_duplicates() {
+        assert_eq!(
+            strict().check(br#"{"a":1,"\u0061":2}"#),
+            Err(StrictJsonError::DuplicateKey("/a".into()))
+        );
+    }
+
+    #[test]
+    fn same_key_in_sibling_objects_is_fine() {
+        assert_eq!(
+            strict().check(br#"{"a": {"id": 1}, "b": {"id": 2}, "c": [{"id": 3}]}"#),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn duplicates_pass_unless_rejected() {
+        assert_eq!(
+            StrictJson::default().check(br#"{"amount":1,"amount":9999}"#),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn syntax_errors_are_left_to_the_parser() {
+        assert_eq!(strict().check(b"{\"a\": "), Ok(()));
+        assert_eq!(strict().check(b"not json"), Ok(()));
+    }
+
+    #[test]
+    fn nesting_deeper_than_the_limit_is_refused() {
+        let limits = StrictJson {
+            max_depth: 3,
+            ..StrictJson::default()
+        };
+        assert_eq!(limits.check(br#"{"a": [{"b": 1}]}"#), Ok(()));
+        assert_eq!(
+            limits.check(br#"{"a": [{"b": [1]}]}"#),
+            Err(StrictJsonError::TooDeep(3))
+        );
+    }
+
+    #[test]
+    fn deep_documents_stop_at_the_limit_not_the_parser() {
+        let json = format!("{}{}", "[".repeat(100), "]".repeat(100));
+        assert_eq!(
+            StrictJson::default().check(json.as_bytes()),
+            Err(StrictJsonError::TooDeep(32))
+        );
+    }
+
+    #[test]
+    fn members_and_elements_count_across_the_document() {
+        let limits = StrictJson {
+            max_entries: 4,
+            ..StrictJson::default()
+        };
+        // 2 members + 2 elements.
+        assert_eq!(limits.check(br#"{"a": [1, 2], "b": {}}"#), Ok(()));
+        assert_eq!(
+            limits.check(br#"{"a": [1, 2], "b": {"c": 3}}"#),
+            Err(StrictJsonError::TooManyEntries(4))
+        );
+    }
+    #[test]
+    fn only_top_level_fields_count_against_the_field_limit() {
+        let limits = StrictJson {
+            max_fields: 2,
+            ..StrictJson::default()
+        };
+        assert_eq!(
+            limits.check(br#"{"a": {"x": 1, "y": 2, "z": 3}, "b": 1}"#),
+            Ok(())
+        );
+        assert_eq!(
+            limits.check(br#"{"a": 1, "b": 2, "c": 3}"#),
+            Err(StrictJsonError::TooManyFields(2))
+        );
+    }
+
+    #[test]
+    fn long_strings_are_reported_with_their_pointer() {
+        let limits = StrictJson {
+            max_value_bytes: 4,
+            ..StrictJson::default()
+        };
+        assert_eq!(limits.check(br#"{"abcd": ["wxyz"]}"#), Ok(()));
+        assert_eq!(
+            limits.check(br#"{"a": ["ok", "too long"]}"#),
+            Err(StrictJsonError::ValueTooLarge {
+                pointer: "/a/1".into(),
+                limit: 4
+            })
+        );
+        assert_eq!(
+            limits.check(br#"{"a": {"abcde": 1}}"#),
+            Err(StrictJsonError::ValueTooLarge {
+                pointer: "/a/abcde".into(),
+                limit: 4
+            })
+        );
+    }
+}
@@ -0,0 +1,348 @@
+//! Verification of inbound webhook deliveries signed with the Stripe, GitHub
+//! or Slack HMAC-SHA256 schemes.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::crypto::constant_time;
+use crate::crypto::hmac::{HMacSigner, decode_hex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provider {
+    /// `Stripe-Signature: t=<ts>,v1=<hex>` over `<ts>.<body>`.
+    Stripe,
+    /// `X-Hub-Signature-256: sha256=<hex>` over the body.
+    GitHub,
+    /// `X-Slack-Signature: v0=<hex>` over `v0:<ts>:<body>`, with the timestamp
+    /// in `X-Slack-Request-Timestamp`.
+    Slack,
+}
+
+impl Provider {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Provider::Stripe => "stripe",
+            Provider::GitHub => "github",
+            Provider::Slack => "slack",
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Provider {
+    type Err = WebhookError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stripe" => Ok(Provider::Stripe),
+            "github" => Ok(Provider::GitHub),
+            "slack" => Ok(Provider::Slack),
+            other => Err(WebhookError::UnknownProvider(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum WebhookError {
+    #[error("unknown webhook provider `{0}`")]
+    UnknownProvider(String),
+    #[error("no secret is configured for {0} webhooks")]
+    NotConfigured(Provider),
+    #[error("missing `{0}` header")]
+    MissingHeader(&'static str),
+    #[error("malformed signature header: {0}")]
+    Malformed(&'static str),
+    #[error(
+        "webhook timestamp is more than {tolerance_secs}s (+{skew_secs}s clock skew) from the server clock"
+    )]
+    Expired { tolerance_secs: u64, skew_secs: u64 },
+    #[error("webhook signature does not match")]
+    Invalid,
+}
+
+/// Per-provider webhook secrets.
+pub struct WebhookVerifier {
+    secrets: HashMap<Provider, HMacSigner>,
+    tolerance_secs: u64,
+    skew_secs: u64,
+}
+
+impl WebhookVerifier {
+    /// Timestamped schemes (Stripe, Slack) reject deliveries more than
+    /// `tolerance_secs` away from the verifier's clock.
+    pub fn new(tolerance_secs: u64) -> Self {
+        Self {
+            secrets: HashMap::new(),
+            tolerance_secs,
+            skew_secs: 0,
+        }
+    }
+
+    /// Widens the tolerance window by `secs` either way, for senders whose
+    /// clock drifts from the verifier's.
+    pub fn clock_skew_secs(mut self, secs: u64) -> Self {
+        self.skew_secs = secs;
+        self
+    }
+
+    pub fn with_secret(mut self, provider: Provider, secret: Vec<u8>) -> Self {
+        self.secrets.insert(provider, HMacSigner::new(secret));
+        self
+    }
+
+    /// Checks the signature headers of a raw delivery. `now` is the current
+    /// Unix time.
+    pub fn verify(
+        &self,
+        provider: Provider,
+        headers: &[(String, String)],
+        body: &[u8],
+        now: u64,
+    ) -> Result<(), WebhookError> {
+        let key = self
+            .secrets
+            .get(&provider)
+            .ok_or(WebhookError::NotConfigured(provider))?;
+        match provider {
+            Provider::Stripe => {
+                let field = header(headers, "stripe-signature")
+                    .ok_or(WebhookError::MissingHeader("Stripe-Signature"))?;
+                let mut timestamp = None;
+                let mut signatures = Vec::new();
+                for item in field.split(',') {
+                    match item.trim().split_once('=') {
+                        Some(("t", value)) => timestamp = Some(value),
+                        Some(("v1", value)) => signatures.push(value),
+                        _ => {}
+                    }
+                }
+                let timestamp =
+                    timestamp.ok_or(WebhookError::Malformed("Stripe-Signature has no `t`"))?;
+                self.check_timestamp(timestamp, now)?;
+                let message = [timestamp.as_bytes(), b".", body].concat();
+                // Stripe sends one `v1` entry per active secret during rotation.
+                let valid = constant_time::any(
+                    signatures
+                        .iter()
+                        .filter_map(|signature| decode_hex(signature))
+                        .map(|tag| key.verify_mac(&message, &tag)),
+                );
+                valid.then_some(()).ok_or(WebhookError::Invalid)
+            }
+            Provider::GitHub => {
+                let field = header(headers, "x-hub-signature-256")
+                    .ok_or(WebhookError::MissingHeader("X-Hub-Signature-256"))?;
+                let tag = field
+                    .strip_prefix("sha256=")
+                    .and_then(decode_hex)
+                    .ok_or(WebhookError::Malformed("expected `sha256=<hex>`"))?;
+                verify_tag(key, body, &tag)
+            }
+            Provider::Slack => {
+                let field = header(headers, "x-slack-signature")
+                    .ok_or(WebhookError::MissingHeader("X-Slack-Signature"))?;
+                let timestamp = header(headers, "x-slack-request-timestamp")
+                    .ok_or(WebhookError::MissingHeader("X-Slack-Request-Timestamp"))?;
+                self.check_timestamp(timestamp, now)?;
+                let tag = field
+                    .strip_prefix("v0=")
+                    .and_then(decode_hex)
+                    .ok_or(WebhookError::Malformed("expected `v0=<hex>`"))?;
+                let message = [b"v0:", timestamp.as_bytes(), b":", body].concat();
+                verify_tag(key, &message, &tag)
+            }
+        }
+    }
+
+    fn check_timestamp(&self, timestamp: &str, now: u64) -> Result<(), WebhookError> {
+        let timestamp: u64 = timestamp
+            .parse()
+            .map_err(|_| WebhookError::Malformed("timestamp is not a number"))?;
+        if timestamp.abs_diff(now) > self.tolerance_secs.saturating_add(self.skew_secs) {
+            return Err(WebhookError::Expired {
+                tolerance_secs: self.tolerance_secs,
+                skew_secs: self.skew_secs,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A `Stripe-Signature` value, `t=<ts>,v1=<hex>` over `<ts>.<body>`, for
+/// deliveries this service sends: receivers can check it with
+/// [`Provider::Stripe`] verification or any Stripe library.
+pub fn stripe_signature(key: &HMacSigner, timestamp: u64, body: &[u8]) -> String {
+    let tag = key.mac(&[timestamp.to_string().as_bytes(), b".", body].concat());
+    let hex: String = tag.iter().map(|b| format!("{b:02x}")).collect();
+    format!("t={timestamp},v1={hex}")
+}
+
+fn verify_tag(key: &HMacSigner, message: &[u8], tag: &[u8]) -> Result<(), WebhookError> {
+    if key.verify_mac(message, tag) {
+        Ok(())
+    } else {
+        Err(WebhookError::Invalid)
+    }
+}
+
+fn header<'h>(headers: &'h [(String, String)], name: &str) -> Option<&'h str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn provider_names_round_trip() {
+        for provider in [Provider::Stripe, Provider::GitHub, Provider::Slack] {
+            assert_eq!(provider.as_str().parse::<Provider>(), Ok(provider));
+        }
+        assert!(matches!(
+            "paypal".parse::<Provider>(),
+            Err(WebhookError::UnknownProvider(_))
+        ));
+    }
+
+    /// Example from GitHub's "Validating webhook deliveries" documentation.
+    #[test]
+    fn github_documentation_example_verifies() {
+        let verifier = WebhookVerifier::new(300)
+            .with_secret(Provider::GitHub, b"It's a Secret to Everybody".to_vec());
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        let headers = headers(&[("X-Hub-Signature-256", signature)]);
+
+        assert_eq!(
+            verifier.verify(Provider::GitHub, &headers, b"Hello, World!", 0),
+            Ok(())
+        );
+        assert_eq!(
+            verifier.verify(Provider::GitHub, &headers, b"Hello, World?", 0),
+            Err(WebhookError::Invalid)
+        );
+    }
+
+    /// Example from Slack's "Verifying requests from Slack" documentation.
+    #[test]
+    fn slack_documentation_example_verifies() {
+        let verifier = WebhookVerifier::new(300).with_secret(
+            Provider::Slack,
+            b"8f742231b10e8888abcd99yyyzzz85a5".to_vec(),
+        );
+        let body = "token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&team_domain=testteamnow&\
+                    channel_id=G8PSS9T3V&channel_name=foobar&user_id=U2CERLKJA&\
+                    user_name=roadrunner&command=%2Fwebhook-collect&text=&\
+                    response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2FT1DC2JH3J%2F\
+                    397700885554%2F96rGlfmibIGlgcZRskXaIFfN&\
+                    trigger_id=398738663015.47445629121.803a0bc887a14d10d2c447fce8b6703c";
+        let headers = headers(&[
+            ("X-Slack-Request-Timestamp", "1531420618"),
+            (
+                "X-Slack-Signature",
+                "v0=a2114d57b48eac39b9ad189dd8316235a7b4a8d21a10bd27519666489c69b503",
+            ),
+        ]);
+
+        assert_eq!(
+            verifier.verify(Provider::Slack, &headers, body.as_bytes(), 1531420618),
+            Ok(())
+        );
+        assert_eq!(
+            verifier.verify(Provider::Slack, &headers, body.as_bytes(), 1531420618 + 301),
+            Err(WebhookError::Expired {
+                tolerance_secs: 300,
+                skew_secs: 0
+            })
+        );
+
+        let verifier = verifier.clock_skew_secs(60);
+        assert_eq!(
+            verifier.verify(Provider::Slack, &headers, body.as_bytes(), 1531420618 + 360),
+            Ok(())
+        );
+        assert_eq!(
+            verifier.verify(Provider::Slack, &headers, body.as_bytes(), 1531420618 - 361),
+            Err(WebhookError::Expired {
+                tolerance_secs: 300,
+                skew_secs: 60
+            })
+        );
+    }
+
+    #[test]
+    fn stripe_accepts_any_matching_v1_signature() {
+        let secret = b"whsec_test".to_vec();
+        let verifier = WebhookVerifier::new(300).with_secret(Provider::Stripe, secret.clone());
+        let body = br#"{"id":"evt_1"}"#;
+        let tag = hex(&HMacSigner::new(secret).mac(&[b"1700000000.", &body[..]].concat()));
+        let field = format!("t=1700000000,v1={},v1={tag},v0=ignored", "00".repeat(32));
+        let headers = headers(&[("Stripe-Signature", &field)]);
+
+        assert_eq!(
+            verifier.verify(Provider::Stripe, &headers, body, 1700000100),
+            Ok(())
+        );
+        assert_eq!(
+            verifier.verify(Provider::Stripe, &headers, b"{}", 1700000100),
+            Err(WebhookError::Invalid)
+        );
+        assert_eq!(
+            verifier.verify(Provider::Stripe, &headers, body, 1700000400),
+            Err(WebhookError::Expired {
+                tolerance_secs: 300,
+                skew_secs: 0
+            })
+        );
+    }
+
+    #[test]
+    fn stripe_signatures_verify() {
+        let secret = b"whsec_test".to_vec();
+        let verifier = WebhookVerifier::new(300).with_secret(Provider::Stripe, secret.clone());
+        let field = stripe_signature(&HMacSigner::new(secret), 1700000000, b"{}");
+        let headers = headers(&[("Stripe-Signature", &field)]);
+
+        assert_eq!(
+            verifier.verify(Provider::Stripe, &headers, b"{}", 1700000000),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn unconfigured_provider_is_reported() {
+        let verifier = WebhookVerifier::new(300);
+        assert_eq!(
+            verifier.verify(Provider::GitHub, &[], b"", 0),
+            Err(WebhookError::NotConfigured(Provider::GitHub))
+        );
+    }
+
+    #[test]
+    fn missing_header_is_reported() {
+        let verifier = WebhookVerifier::new(300).with_secret(Provider::Stripe, b"s".to_vec());
+        assert_eq!(
+            verifier.verify(Provider::Stripe, &[], b"", 0),
+            Err(WebhookError::MissingHeader("Stripe-Signature"))
+        );
+    }
+}
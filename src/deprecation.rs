@@ -0,0 +1,227 @@
+//! Retirement of the legacy formats: bare signatures, which do not name
+//! their algorithm, and base64 "ciphertext", which is only an encoding.
+//! Every use is counted. Once `deprecation.deprecated_at` is set, responses
+//! using a legacy format carry RFC 9745 `Deprecation` and RFC 8594 `Sunset`
+//! headers, and with `deprecation.reject_after_sunset` legacy formats are
+//! refused from the sunset on.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "encryption")]
+use axum::extract::{Request, State};
+use axum::http::{HeaderName, HeaderValue};
+#[cfg(feature = "encryption")]
+use axum::middleware::Next;
+#[cfg(feature = "encryption")]
+use axum::response::{IntoResponse, Response};
+use axum::response::{IntoResponseParts, ResponseParts};
+
+use crate::config::DeprecationConfig;
+#[cfg(feature = "encryption")]
+use crate::crypto::migrate::LEGACY_ALGORITHM;
+use crate::error::Error;
+use crate::models::LegacyFormatStats;
+#[cfg(feature = "encryption")]
+use crate::state::AppState;
+
+pub const DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+pub const SUNSET_HEADER: HeaderName = HeaderName::from_static("sunset");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyFormat {
+    /// A signature without a `v1.<alg>.` envelope, sent to `/verify` or
+    /// returned by `/sign`.
+    BareSignature,
+    /// Values encrypted, or decrypted, with the `base64` algorithm.
+    Base64Ciphertext,
+}
+
+impl LegacyFormat {
+    pub const ALL: [LegacyFormat; 2] =
+        [LegacyFormat::BareSignature, LegacyFormat::Base64Ciphertext];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LegacyFormat::BareSignature => "bare_signature",
+            LegacyFormat::Base64Ciphertext => "base64_ciphertext",
+        }
+    }
+}
+
+/// The deprecation schedule, and counts of legacy formats used and
+/// refused.
+#[derive(Debug, Default)]
+pub struct Deprecations {
+    notice: LegacyNotice,
+    reject_from: Option<u64>,
+    used: [AtomicU64; 2],
+    rejected: [AtomicU64; 2],
+}
+
+impl Deprecations {
+    pub fn new(config: &DeprecationConfig) -> Self {
+        let deprecated_at = config.deprecated_at;
+        Self {
+            notice: LegacyNotice {
+                deprecation: deprecated_at.map(|at| header_value(format!("@{at}"))),
+                sunset: deprecated_at
+                    .and(config.sunset_at)
+                    .map(|at| header_value(http_date(at))),
+            },
+            reject_from: config.sunset_at.filter(|_| config.reject_after_sunset),
+            ..Self::default()
+        }
+    }
+
+    /// Counts a use of `format` at Unix time `now`, and returns the headers
+    /// announcing its deprecation, or refuses it once it is past its
+    /// sunset.
+    pub fn check(&self, format: LegacyFormat, now: u64) -> Result<LegacyNotice, Error> {
+        let index = format as usize;
+        if self.reject_from.is_some_and(|sunset_at| now >= sunset_at) {
+            self.rejected[index].fetch_add(1, Ordering::Relaxed);
+            return Err(Error::Sunset(format!(
+                "the {} format was retired; see the `Sunset` date it was announced with",
+                format.as_str()
+            )));
+        }
+        self.used[index].fetch_add(1, Ordering::Relaxed);
+        Ok(self.notice.clone())
+    }
+
+    pub fn stats(&self) -> Vec<LegacyFormatStats> {
+        LegacyFormat::ALL
+            .into_iter()
+            .map(|format| LegacyFormatStats {
+                format: format.as_str().to_string(),
+                used: self.used[format as usize].load(Ordering::Relaxed),
+                rejected: self.rejected[format as usize].load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// Middleware of the encryption routes: while they still use the base64
+/// encoding, counts every request and announces, or refuses, the format.
+#[cfg(feature = "encryption")]
+pub async fn base64_ciphertext(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.encryption_algorithm != LEGACY_ALGORITHM {
+        return next.run(request).await;
+    }
+    match state
+        .deprecations
+        .check(LegacyFormat::Base64Ciphertext, state.clock.now())
+    {
+        Ok(notice) => (notice, next.run(request).await).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// The `Deprecation` and `Sunset` headers of a response using a legacy
+/// format; none before a deprecation is scheduled.
+#[derive(Debug, Clone, Default)]
+pub struct LegacyNotice {
+    deprecation: Option<HeaderValue>,
+    sunset: Option<HeaderValue>,
+}
+
+impl IntoResponseParts for LegacyNotice {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let headers = res.headers_mut();
+        if let Some(value) = self.deprecation {
+            headers.insert(DEPRECATION_HEADER, value);
+        }
+        if let Some(value) = self.sunset {
+            headers.insert(SUNSET_HEADER, value);
+        }
+        Ok(res)
+    }
+}
+
+fn header_value(value: String) -> HeaderValue {
+    HeaderValue::try_from(value).expect("dates are ASCII")
+}
+
+/// IMF-fixdate (RFC 9110 §5.6.7) of a Unix time, e.g.
+/// `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(unix: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (days, seconds) = (unix / 86_400, unix % 86_400);
+    // Proleptic Gregorian date of a day count (Howard Hinnant's
+    // `civil_from_days`).
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        MONTHS[(month - 1) as usize],
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_dates_are_imf_fixdates() {
+        assert_eq!(http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(http_date(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(http_date(1_709_208_000), "Thu, 29 Feb 2024 12:00:00 GMT");
+    }
+
+    #[test]
+    fn legacy_formats_are_refused_from_the_sunset_when_configured() {
+        let deprecations = Deprecations::new(&DeprecationConfig {
+            deprecated_at: Some(1_000),
+            sunset_at: Some(2_000),
+            reject_after_sunset: true,
+        });
+        let notice = deprecations
+            .check(LegacyFormat::BareSignature, 1_999)
+            .unwrap();
+        assert_eq!(notice.deprecation.unwrap(), "@1000");
+        assert_eq!(notice.sunset.unwrap(), "Thu, 01 Jan 1970 00:33:20 GMT");
+        assert!(matches!(
+            deprecations.check(LegacyFormat::BareSignature, 2_000),
+            Err(Error::Sunset(_))
+        ));
+
+        let stats = deprecations.stats();
+        assert_eq!((stats[0].used, stats[0].rejected), (1, 1));
+        assert_eq!((stats[1].used, stats[1].rejected), (0, 0));
+    }
+
+    #[test]
+    fn nothing_is_announced_before_a_deprecation_is_scheduled() {
+        let deprecations = Deprecations::new(&DeprecationConfig::default());
+        let notice = deprecations
+            .check(LegacyFormat::Base64Ciphertext, u64::MAX)
+            .unwrap();
+        assert!(notice.deprecation.is_none() && notice.sunset.is_none());
+        assert_eq!(deprecations.stats()[1].used, 1);
+    }
+}
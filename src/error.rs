@@ -0,0 +1,491 @@
+use std::borrow::Cow;
+
+#[cfg(feature = "blobs")]
+use crate::blobs::BlobError;
+#[cfg(feature = "server")]
+use crate::blocking::BlockingError;
+#[cfg(all(feature = "admin", feature = "escrow"))]
+use crate::ceremony::CeremonyError;
+#[cfg(feature = "signing")]
+use crate::crypto::canonical::RejectedNumber;
+#[cfg(feature = "signing")]
+use crate::crypto::challenge::ChallengeError;
+#[cfg(feature = "encryption")]
+use crate::crypto::encryptor::{DecryptError, EncryptError};
+#[cfg(feature = "escrow")]
+use crate::crypto::escrow::EscrowError;
+#[cfg(feature = "signing")]
+use crate::crypto::http_signature::HttpSignatureError;
+#[cfg(feature = "encryption")]
+use crate::crypto::migrate::MigrateError;
+#[cfg(feature = "encryption")]
+use crate::crypto::pointer::{PointerDecryptError, PointerEncryptError, PointerError};
+#[cfg(feature = "signing")]
+use crate::crypto::prehash::PrehashError;
+#[cfg(feature = "json-schema")]
+use crate::crypto::schema::SchemaError;
+#[cfg(feature = "signing")]
+use crate::crypto::signer::SignError;
+#[cfg(feature = "signing")]
+use crate::crypto::sigv4::SigV4Error;
+#[cfg(any(feature = "signing", feature = "encryption"))]
+use crate::crypto::strict_json::StrictJsonError;
+#[cfg(feature = "signing")]
+use crate::crypto::webhook::WebhookError;
+#[cfg(feature = "server")]
+use crate::replay::ReplayError;
+#[cfg(feature = "tenancy")]
+use crate::tenancy::TenancyError;
+#[cfg(all(feature = "server", feature = "asymmetric"))]
+use crate::transparency::ProofError;
+#[cfg(feature = "vault")]
+use crate::vault::VaultError;
+
+/// Errors surfaced by the service. Each variant maps to one HTTP status and
+/// a stable machine-readable code, so clients can tell an invalid signature
+/// apart from a malformed request or an unavailable backend.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Validation(String),
+    #[error("invalid signature")]
+    InvalidSignature,
+    /// A valid signature over data whose `exp` claim has passed.
+    #[error("{0}")]
+    Expired(String),
+    #[error("ciphertext failed authentication")]
+    DecryptionFailed,
+    #[error("{0}")]
+    Unauthorized(String),
+    /// The authorization policy denied the request.
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    /// A legacy format was used after its sunset date.
+    #[error("{0}")]
+    Sunset(String),
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+    #[error("request body is too large")]
+    PayloadTooLarge,
+    #[error("{0}")]
+    LimitExceeded(String),
+    /// An API key or tenant used up its operation quota.
+    #[error("{0}")]
+    QuotaExceeded(String),
+    /// A `Range` header selects no bytes of a `length`-byte resource.
+    #[error("range not satisfiable for {length} bytes")]
+    RangeNotSatisfiable { length: u64 },
+    #[error("crypto operation failed: {0}")]
+    Crypto(String),
+    #[error("key store unavailable: {0}")]
+    KeyStore(String),
+    #[error("storage unavailable: {0}")]
+    Storage(String),
+    #[error("backend did not respond in time")]
+    Timeout,
+    /// A backend failed repeatedly and calls to it are suspended.
+    #[error("{0}")]
+    CircuitOpen(String),
+    /// The authorization policy could not be evaluated.
+    #[error("policy unavailable: {0}")]
+    PolicyUnavailable(String),
+}
+
+impl Error {
+    /// Stable identifier returned in the `error.code` property.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Validation(_) => "validation_failed",
+            Error::InvalidSignature => "invalid_signature",
+            Error::Expired(_) => "expired",
+            Error::DecryptionFailed => "decryption_failed",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::Forbidden(_) => "forbidden",
+            Error::NotFound(_) => "not_found",
+            Error::Sunset(_) => "sunset",
+            Error::UnsupportedMediaType(_) => "unsupported_media_type",
+            Error::PayloadTooLarge => "payload_too_large",
+            Error::LimitExceeded(_) => "limit_exceeded",
+            Error::QuotaExceeded(_) => "quota_exceeded",
+            Error::RangeNotSatisfiable { .. } => "range_not_satisfiable",
+            Error::Crypto(_) => "crypto_failure",
+            Error::KeyStore(_) => "key_store_unavailable",
+            Error::Storage(_) => "storage_unavailable",
+            Error::Timeout => "backend_timeout",
+            Error::CircuitOpen(_) => "circuit_open",
+            Error::PolicyUnavailable(_) => "policy_unavailable",
+        }
+    }
+
+    /// HTTP status code this error is reported with.
+    pub fn status(&self) -> u16 {
+        match self {
+            Error::Validation(_)
+            | Error::InvalidSignature
+            | Error::Expired(_)
+            | Error::DecryptionFailed => 400,
+            Error::Unauthorized(_) => 401,
+            Error::Forbidden(_) => 403,
+            Error::NotFound(_) => 404,
+            Error::Sunset(_) => 410,
+            Error::PayloadTooLarge => 413,
+            Error::UnsupportedMediaType(_) => 415,
+            Error::RangeNotSatisfiable { .. } => 416,
+            Error::LimitExceeded(_) => 422,
+            Error::QuotaExceeded(_) => 429,
+            Error::Crypto(_) => 500,
+            Error::KeyStore(_) => 503,
+            Error::Storage(_) => 503,
+            Error::Timeout => 504,
+            Error::CircuitOpen(_) => 503,
+            Error::PolicyUnavailable(_) => 503,
+        }
+    }
+
+    /// Message safe to return to callers. Server-side failures are logged
+    /// in full but only described generically in responses.
+    pub fn public_message(&self) -> Cow<'_, str> {
+        match self {
+            Error::Crypto(_) => "crypto operation failed".into(),
+            Error::KeyStore(_) => "key store unavailable".into(),
+            Error::Storage(_) => "storage unavailable".into(),
+            Error::PolicyUnavailable(_) => "policy unavailable".into(),
+            other => other.to_string().into(),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl From<EncryptError> for Error {
+    fn from(err: EncryptError) -> Self {
+        match err {
+            EncryptError::Timeout => Error::Timeout,
+            other => Error::Crypto(other.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl From<DecryptError> for Error {
+    fn from(err: DecryptError) -> Self {
+        match err {
+            DecryptError::NotCiphertext => Error::Validation(err.to_string()),
+            DecryptError::AuthenticationFailed => Error::DecryptionFailed,
+            DecryptError::Backend(_) => Error::Crypto(err.to_string()),
+            DecryptError::Timeout => Error::Timeout,
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl From<PointerError> for Error {
+    fn from(err: PointerError) -> Self {
+        Error::Validation(err.to_string())
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl From<MigrateError> for Error {
+    fn from(err: MigrateError) -> Self {
+        match err {
+            MigrateError::Pointer(err) => err.into(),
+            MigrateError::Encrypt(err) => err.into(),
+            other => Error::Validation(other.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl From<PointerEncryptError> for Error {
+    fn from(err: PointerEncryptError) -> Self {
+        match err {
+            PointerEncryptError::Pointer(err) => err.into(),
+            PointerEncryptError::Encrypt(err) => err.into(),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl From<PointerDecryptError> for Error {
+    fn from(err: PointerDecryptError) -> Self {
+        match err {
+            PointerDecryptError::Pointer(err) => err.into(),
+            PointerDecryptError::Decrypt(err) => err.into(),
+        }
+    }
+}
+
+#[cfg(feature = "signing")]
+impl From<SignError> for Error {
+    fn from(err: SignError) -> Self {
+        match err {
+            SignError::Backend(_) => Error::Crypto(err.to_string()),
+            SignError::Timeout => Error::Timeout,
+            SignError::KeyUnavailable(message) => Error::Unauthorized(message),
+            SignError::StoreUnavailable(_) => Error::KeyStore(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "signing")]
+impl From<PrehashError> for Error {
+    fn from(err: PrehashError) -> Self {
+        Error::Validation(err.to_string())
+    }
+}
+
+#[cfg(feature = "signing")]
+impl From<RejectedNumber> for Error {
+    fn from(err: RejectedNumber) -> Self {
+        Error::Validation(err.to_string())
+    }
+}
+
+#[cfg(feature = "blobs")]
+impl From<BlobError> for Error {
+    fn from(err: BlobError) -> Self {
+        match err {
+            BlobError::InvalidAddress => Error::Validation(err.to_string()),
+            BlobError::NotFound(_) => Error::NotFound(err.to_string()),
+            BlobError::Corrupted(_) => Error::Crypto(err.to_string()),
+            BlobError::Encrypt(err) => err.into(),
+            BlobError::Decrypt(err) => err.into(),
+            BlobError::Storage(_) => Error::Storage(err.to_string()),
+            BlobError::NoChunkKey(_) => Error::Crypto(err.to_string()),
+            BlobError::TooLarge(_) => Error::LimitExceeded(err.to_string()),
+            BlobError::RangeNotSatisfiable(length) => Error::RangeNotSatisfiable { length },
+        }
+    }
+}
+
+#[cfg(feature = "vault")]
+impl From<VaultError> for Error {
+    fn from(err: VaultError) -> Self {
+        match err {
+            VaultError::InvalidName => Error::Validation(err.to_string()),
+            VaultError::NotFound(_) => Error::NotFound(err.to_string()),
+            VaultError::Corrupted(_) => Error::Crypto(err.to_string()),
+            VaultError::Encrypt(err) => err.into(),
+            VaultError::Decrypt(err) => err.into(),
+            VaultError::Storage(_) => Error::Storage(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "tenancy")]
+impl From<TenancyError> for Error {
+    fn from(err: TenancyError) -> Self {
+        match err {
+            TenancyError::InvalidTenantId | TenancyError::MissingTenant => {
+                Error::Validation(err.to_string())
+            }
+            TenancyError::UnknownTenant(_) | TenancyError::KeyUnavailable { .. } => {
+                Error::Unauthorized(err.to_string())
+            }
+            TenancyError::ReadOnlySource
+            | TenancyError::ListingUnsupported
+            | TenancyError::InvalidCursor
+            | TenancyError::InvalidState(_)
+            | TenancyError::InvalidTransition { .. } => Error::Validation(err.to_string()),
+            TenancyError::Backend(_) => Error::KeyStore(err.to_string()),
+            TenancyError::Timeout => Error::Timeout,
+            TenancyError::CircuitOpen => Error::CircuitOpen(err.to_string()),
+        }
+    }
+}
+
+#[cfg(all(feature = "server", feature = "asymmetric"))]
+impl From<ProofError> for Error {
+    fn from(err: ProofError) -> Self {
+        match err {
+            ProofError::UnknownRoot(_) => Error::NotFound(err.to_string()),
+            ProofError::Order { .. } => Error::Validation(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "escrow")]
+impl From<EscrowError> for Error {
+    fn from(err: EscrowError) -> Self {
+        match err {
+            EscrowError::UnknownKey { .. } | EscrowError::UnknownEscrowKey { .. } => {
+                Error::Validation(err.to_string())
+            }
+            _ => Error::Crypto(err.to_string()),
+        }
+    }
+}
+
+#[cfg(all(feature = "admin", feature = "escrow"))]
+impl From<CeremonyError> for Error {
+    fn from(err: CeremonyError) -> Self {
+        match err {
+            CeremonyError::UnknownCeremony(_) => Error::NotFound(err.to_string()),
+            CeremonyError::NotCustodian(_) => Error::Forbidden(err.to_string()),
+            CeremonyError::Escrow(err) => err.into(),
+            _ => Error::Validation(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<BlockingError> for Error {
+    fn from(err: BlockingError) -> Self {
+        Error::Crypto(err.to_string())
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<ReplayError> for Error {
+    fn from(err: ReplayError) -> Self {
+        Error::Storage(err.to_string())
+    }
+}
+
+#[cfg(feature = "json-schema")]
+impl From<SchemaError> for Error {
+    fn from(err: SchemaError) -> Self {
+        Error::Validation(err.to_string())
+    }
+}
+
+#[cfg(any(feature = "signing", feature = "encryption"))]
+impl From<StrictJsonError> for Error {
+    fn from(err: StrictJsonError) -> Self {
+        match err {
+            StrictJsonError::DuplicateKey(_) => Error::Validation(err.to_string()),
+            StrictJsonError::TooDeep(_)
+            | StrictJsonError::TooManyEntries(_)
+            | StrictJsonError::TooManyFields(_)
+            | StrictJsonError::ValueTooLarge { .. } => Error::LimitExceeded(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "signing")]
+impl From<HttpSignatureError> for Error {
+    fn from(err: HttpSignatureError) -> Self {
+        match err {
+            HttpSignatureError::Invalid
+            | HttpSignatureError::UnknownKey
+            | HttpSignatureError::DigestMismatch => Error::InvalidSignature,
+            HttpSignatureError::Expired { .. } | HttpSignatureError::Stale { .. } => {
+                Error::Expired(err.to_string())
+            }
+            other => Error::Validation(other.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "signing")]
+impl From<SigV4Error> for Error {
+    fn from(err: SigV4Error) -> Self {
+        match err {
+            SigV4Error::Invalid | SigV4Error::UnknownAccessKey | SigV4Error::PayloadMismatch => {
+                Error::InvalidSignature
+            }
+            SigV4Error::Skewed { .. } => Error::Expired(err.to_string()),
+            other => Error::Validation(other.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "signing")]
+impl From<ChallengeError> for Error {
+    fn from(err: ChallengeError) -> Self {
+        match err {
+            ChallengeError::Malformed(_) => Error::Validation(err.to_string()),
+            _ => Error::InvalidSignature,
+        }
+    }
+}
+
+#[cfg(feature = "signing")]
+impl From<WebhookError> for Error {
+    fn from(err: WebhookError) -> Self {
+        match err {
+            WebhookError::Invalid => Error::InvalidSignature,
+            WebhookError::Expired { .. } => Error::Expired(err.to_string()),
+            other => Error::Validation(other.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+mod response {
+    use axum::Json;
+    use axum::extract::rejection::JsonRejection;
+    use axum::http::{StatusCode, header};
+    use axum::response::{IntoResponse, Response};
+
+    use super::Error;
+    use crate::models::{ErrorDetail, ErrorResponse};
+
+    impl IntoResponse for Error {
+        fn into_response(self) -> Response {
+            let status =
+                StatusCode::from_u16(self.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            if status.is_server_error() {
+                tracing::error!(code = self.code(), "{self}");
+            } else {
+                tracing::debug!(code = self.code(), "{self}");
+            }
+            let body = ErrorResponse {
+                error: ErrorDetail {
+                    code: self.code().to_string(),
+                    message: self.public_message().into_owned(),
+                },
+            };
+            let mut response = (status, Json(body)).into_response();
+            if let Error::RangeNotSatisfiable { length } = self {
+                response.headers_mut().insert(
+                    header::CONTENT_RANGE,
+                    format!("bytes */{length}")
+                        .parse()
+                        .expect("a valid header value"),
+                );
+            }
+            response
+        }
+    }
+
+    impl From<JsonRejection> for Error {
+        fn from(rejection: JsonRejection) -> Self {
+            match rejection {
+                JsonRejection::MissingJsonContentType(r) => {
+                    Error::UnsupportedMediaType(r.body_text())
+                }
+                r if r.status() == StatusCode::PAYLOAD_TOO_LARGE => Error::PayloadTooLarge,
+                r => Error::Validation(r.body_text()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_errors_hide_details_from_callers() {
+        let err = Error::KeyStore("connection refused to 10.0.0.3".into());
+        assert_eq!(err.public_message(), "key store unavailable");
+        assert_eq!(err.status(), 503);
+    }
+
+    #[test]
+    fn client_errors_keep_their_message() {
+        let err = Error::Validation("missing field `data`".into());
+        assert_eq!(err.public_message(), "missing field `data`");
+        assert_eq!(err.code(), "validation_failed");
+        assert_eq!(err.status(), 400);
+    }
+
+    #[test]
+    fn invalid_signature_has_its_own_code() {
+        assert_eq!(Error::InvalidSignature.code(), "invalid_signature");
+        assert_eq!(Error::InvalidSignature.status(), 400);
+    }
+}
@@ -0,0 +1,192 @@
+//! Live feed of audit and key lifecycle events, served as server-sent
+//! events by the admin `GET /events`, so a SOC can watch activity as it
+//! happens instead of polling the audit store. Events are fanned out through
+//! a bounded broadcast channel: a subscriber more than `events.buffer`
+//! events behind misses the oldest ones and is told how many. Nothing is
+//! kept for subscribers that connect later.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::audit::AuditEvent;
+#[cfg(feature = "tenancy")]
+use crate::notifications::{KeyEvent, NotificationSink};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventType {
+    /// An [`AuditEvent`].
+    Audit,
+    /// A tenant key lifecycle event.
+    Key,
+}
+
+impl EventType {
+    pub const ALL: [EventType; 2] = [EventType::Audit, EventType::Key];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventType::Audit => "audit",
+            EventType::Key => "key",
+        }
+    }
+}
+
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("unknown event type `{s}`"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum StreamEvent {
+    Audit(AuditEvent),
+    #[cfg(feature = "tenancy")]
+    Key(KeyEvent),
+}
+
+impl StreamEvent {
+    pub fn kind(&self) -> EventType {
+        match self {
+            StreamEvent::Audit(_) => EventType::Audit,
+            #[cfg(feature = "tenancy")]
+            StreamEvent::Key(_) => EventType::Key,
+        }
+    }
+}
+
+/// An event as sent to subscribers.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Published {
+    /// Counts up from 1 in the order events were published; starts over on
+    /// restart.
+    pub id: u64,
+    pub event: StreamEvent,
+}
+
+/// Hands published events to every current subscriber.
+pub struct EventHub {
+    sender: broadcast::Sender<Arc<Published>>,
+    next_id: AtomicU64,
+    keep_alive: Duration,
+}
+
+impl EventHub {
+    /// Subscribers may fall `buffer` events behind before they miss some.
+    pub fn new(buffer: usize) -> Self {
+        Self {
+            sender: broadcast::Sender::new(buffer.max(1)),
+            next_id: AtomicU64::new(1),
+            keep_alive: Duration::from_secs(15),
+        }
+    }
+
+    /// How long an idle stream waits before sending a comment, so proxies
+    /// do not close it.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+
+    pub fn keep_alive_interval(&self) -> Duration {
+        self.keep_alive
+    }
+
+    /// Sends `event` to the current subscribers, if any.
+    pub fn publish(&self, event: StreamEvent) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        // Fails only when every subscriber left in the meantime.
+        let _ = self.sender.send(Arc::new(Published { id, event }));
+    }
+
+    /// Events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Published>> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+#[cfg(feature = "tenancy")]
+impl NotificationSink for EventHub {
+    fn notify(&self, event: &KeyEvent) {
+        self.publish(StreamEvent::Key(event.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use broadcast::error::TryRecvError;
+
+    use super::*;
+
+    fn audit(resource: &str) -> StreamEvent {
+        StreamEvent::Audit(AuditEvent {
+            at: 1_700_000_000,
+            action: "vault.read",
+            resource: resource.into(),
+            client: Some("soc".into()),
+            success: true,
+        })
+    }
+
+    #[test]
+    fn subscribers_get_events_published_after_they_joined() {
+        let hub = EventHub::new(8);
+        hub.publish(audit("before"));
+        let mut first = hub.subscribe();
+        hub.publish(audit("a"));
+        let mut second = hub.subscribe();
+        hub.publish(audit("b"));
+
+        let received = |receiver: &mut broadcast::Receiver<Arc<Published>>| {
+            std::iter::from_fn(|| receiver.try_recv().ok())
+                .map(|published| published.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(received(&mut first), [1, 2]);
+        assert_eq!(received(&mut second), [2]);
+        assert_eq!(hub.subscribers(), 2);
+    }
+
+    #[test]
+    fn slow_subscribers_learn_how_many_they_missed() {
+        let hub = EventHub::new(2);
+        let mut receiver = hub.subscribe();
+        for resource in ["a", "b", "c", "d"] {
+            hub.publish(audit(resource));
+        }
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Lagged(2)));
+        assert_eq!(receiver.try_recv().unwrap().event, audit("c"));
+    }
+
+    #[test]
+    fn event_types_round_trip() {
+        for kind in EventType::ALL {
+            assert_eq!(kind.as_str().parse::<EventType>(), Ok(kind));
+        }
+        assert!("metrics".parse::<EventType>().is_err());
+    }
+}
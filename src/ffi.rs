@@ -0,0 +1,204 @@
+//! C ABI over the crypto module, so native pipelines can link against the
+//! exact same canonicalization and crypto code as the server.
+//!
+//! Strings crossing the boundary are NUL-terminated UTF-8. Strings returned
+//! by this module are owned by the caller and must be released with
+//! [`free_string`]; a NULL return means the input was invalid.
+
+use std::ffi::{CStr, CString, c_char, c_int};
+
+use serde_json::Value;
+
+use crate::crypto::base64::Base64Encryptor;
+use crate::crypto::encryptor::Encryptor;
+use crate::crypto::hmac::HMacSigner;
+use crate::crypto::signer::Signer;
+
+/// # Safety
+/// `ptr` must be NULL or a valid NUL-terminated string.
+unsafe fn read_json(ptr: *const c_char) -> Option<Value> {
+    if ptr.is_null() {
+        return None;
+    }
+    let text = unsafe { CStr::from_ptr(ptr) }.to_str().ok()?;
+    serde_json::from_str(text).ok()
+}
+
+/// # Safety
+/// `key` must be NULL or point to `key_len` readable bytes.
+unsafe fn read_key(key: *const u8, key_len: usize) -> Option<HMacSigner> {
+    if key.is_null() || key_len == 0 {
+        return None;
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(key, key_len) };
+    Some(HMacSigner::new(bytes.to_vec()))
+}
+
+fn into_c_string(value: String) -> *mut c_char {
+    CString::new(value).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Signs a JSON object with HMAC-SHA256 and returns the hex signature, or
+/// NULL if `json` is not a JSON object or the key is empty.
+///
+/// # Safety
+/// `key` must point to `key_len` readable bytes and `json` must be a valid
+/// NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sign_json(
+    key: *const u8,
+    key_len: usize,
+    json: *const c_char,
+) -> *mut c_char {
+    let (Some(signer), Some(Value::Object(map))) = (unsafe { read_key(key, key_len) }, unsafe {
+        read_json(json)
+    }) else {
+        return std::ptr::null_mut();
+    };
+    into_c_string(signer.sign(&map))
+}
+
+/// Returns 1 if `signature` is valid for the JSON object `json`, 0 if it is
+/// not, and -1 if the inputs are invalid.
+///
+/// # Safety
+/// `key` must point to `key_len` readable bytes; `json` and `signature` must
+/// be valid NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn verify_json(
+    key: *const u8,
+    key_len: usize,
+    json: *const c_char,
+    signature: *const c_char,
+) -> c_int {
+    let (Some(signer), Some(Value::Object(map))) = (unsafe { read_key(key, key_len) }, unsafe {
+        read_json(json)
+    }) else {
+        return -1;
+    };
+    if signature.is_null() {
+        return -1;
+    }
+    let Ok(signature) = unsafe { CStr::from_ptr(signature) }.to_str() else {
+        return -1;
+    };
+    c_int::from(signer.verify(&map, signature))
+}
+
+/// Encrypts a single JSON value and returns the ciphertext as JSON text, or
+/// NULL if `json` is not valid JSON.
+///
+/// # Safety
+/// `json` must be a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn encrypt_value(json: *const c_char) -> *mut c_char {
+    match unsafe { read_json(json) }.and_then(|v| Base64Encryptor.encrypt(&v).ok()) {
+        Some(value) => into_c_string(value.to_string()),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Decrypts a ciphertext produced by [`encrypt_value`] and returns the
+/// original value as JSON text, or NULL if the input is not ciphertext.
+///
+/// # Safety
+/// `json` must be a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn decrypt_value(json: *const c_char) -> *mut c_char {
+    match unsafe { read_json(json) }.and_then(|v| Base64Encryptor.decrypt(&v).ok()) {
+        Some(value) => into_c_string(value.to_string()),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Releases a string returned by this library. Passing NULL is a no-op.
+///
+/// # Safety
+/// `ptr` must be NULL or a pointer returned by this library that has not
+/// been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn free_string(ptr: *mut c_char) {
+    if !ptr.is_null() {
+        drop(unsafe { CString::from_raw(ptr) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"ffi-secret";
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    /// Copies and frees a string returned by the library.
+    fn take(ptr: *mut c_char) -> Option<String> {
+        if ptr.is_null() {
+            return None;
+        }
+        let s = unsafe { CStr::from_ptr(ptr) }.to_str().unwrap().to_string();
+        unsafe { free_string(ptr) };
+        Some(s)
+    }
+
+    fn sign(json: &str) -> Option<String> {
+        take(unsafe { sign_json(KEY.as_ptr(), KEY.len(), c(json).as_ptr()) })
+    }
+
+    fn verify(json: &str, signature: &str) -> c_int {
+        unsafe {
+            verify_json(
+                KEY.as_ptr(),
+                KEY.len(),
+                c(json).as_ptr(),
+                c(signature).as_ptr(),
+            )
+        }
+    }
+
+    #[test]
+    fn sign_matches_rust_signer() {
+        let signature = sign(r#"{"message":"Hello World","timestamp":1616161616}"#).unwrap();
+        let map =
+            serde_json::from_str(r#"{"timestamp":1616161616,"message":"Hello World"}"#).unwrap();
+        assert_eq!(HMacSigner::new(KEY.to_vec()).sign(&map), signature);
+    }
+
+    #[test]
+    fn sign_then_verify_roundtrip() {
+        let signature = sign(r#"{"a":1}"#).unwrap();
+        assert_eq!(verify(r#"{"a":1}"#, &signature), 1);
+        assert_eq!(verify(r#"{"a":2}"#, &signature), 0);
+    }
+
+    #[test]
+    fn invalid_inputs_are_reported() {
+        assert_eq!(sign("[1, 2]"), None);
+        assert_eq!(sign("not json"), None);
+        assert_eq!(verify("not json", "00"), -1);
+        assert!(unsafe { sign_json(std::ptr::null(), 0, c("{}").as_ptr()) }.is_null());
+    }
+
+    #[test]
+    fn encrypt_then_decrypt_roundtrip() {
+        let encrypted = take(unsafe { encrypt_value(c(r#"{"a":[1,2]}"#).as_ptr()) }).unwrap();
+        assert!(encrypted.starts_with('"'));
+        let decrypted = take(unsafe { decrypt_value(c(&encrypted).as_ptr()) }).unwrap();
+        assert_eq!(decrypted, r#"{"a":[1,2]}"#);
+    }
+
+    #[test]
+    fn decrypt_non_ciphertext_returns_null() {
+        assert_eq!(
+            take(unsafe { decrypt_value(c(r#""1998-11-19""#).as_ptr()) }),
+            None
+        );
+    }
+
+    #[test]
+    fn free_string_accepts_null() {
+        unsafe { free_string(std::ptr::null_mut()) };
+    }
+}
@@ -0,0 +1,771 @@
+use std::convert::Infallible;
+#[cfg(feature = "escrow")]
+use std::sync::Arc;
+
+#[cfg(feature = "escrow")]
+use axum::Extension;
+#[cfg(feature = "encryption")]
+use axum::body::{Body, BodyDataStream};
+#[cfg(any(feature = "escrow", feature = "tenancy"))]
+use axum::extract::Path;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, extract::State};
+use futures_util::Stream;
+#[cfg(feature = "encryption")]
+use futures_util::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+
+#[cfg(feature = "escrow")]
+use crate::api_keys::ApiKey;
+#[cfg(feature = "escrow")]
+use crate::ceremony::{Ceremonies, CeremonyError};
+#[cfg(feature = "tenancy")]
+use crate::config::SigningAlgorithm;
+#[cfg(feature = "encryption")]
+use crate::crypto::encryptor::ENCRYPTION_KEY_ID;
+#[cfg(feature = "escrow")]
+use crate::crypto::escrow::EscrowBundle;
+#[cfg(feature = "encryption")]
+use crate::crypto::migrate::Migration;
+use crate::error::Error;
+use crate::events::{EventType, Published};
+#[cfg(any(feature = "escrow", feature = "tenancy", feature = "encryption"))]
+use crate::handlers::audit;
+#[cfg(any(feature = "escrow", feature = "tenancy"))]
+use crate::handlers::extract::ValidJson;
+use crate::handlers::extract::ValidQuery;
+#[cfg(feature = "signing")]
+use crate::models::AlgorithmsResponse;
+#[cfg(feature = "signing")]
+use crate::models::SignatureCacheStats;
+#[cfg(feature = "escrow")]
+use crate::models::{
+    CeremonyShareRequest, CeremonyStatus, EscrowExportRequest, StartCeremonyRequest,
+};
+use crate::models::{
+    EventStreamParams, EventsMissed, KeyUsageParams, KeyUsageResponse, MetricsResponse, Readiness,
+    ReadinessResponse,
+};
+#[cfg(feature = "encryption")]
+use crate::models::{MigrateParams, MigrationFailure, MigrationReport};
+#[cfg(feature = "tenancy")]
+use crate::models::{
+    RotateKeyParams, SortOrder, TenantListParams, TenantListResponse, TenantStateRequest,
+    TenantSummary,
+};
+use crate::state::AppState;
+#[cfg(feature = "tenancy")]
+use crate::tenancy::{KeyState, TenancyError};
+
+/// Liveness probe served on the admin listener only, so orchestration
+/// tooling does not need access to the data-plane port.
+pub async fn healthz() -> StatusCode {
+    StatusCode::NO_CONTENT
+}
+
+/// Readiness probe. A degraded instance still answers `200`: it keeps
+/// verifying with the keys it loaded before its key store failed, so it
+/// should stay in rotation. An instance whose signer or encryptor fails the
+/// watchdog's self-check answers `503`. The body names the failing backends.
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    #[cfg_attr(not(feature = "tenancy"), allow(unused_mut))]
+    let mut unavailable = state.watchdog.failing();
+    let status = if unavailable.is_empty() {
+        Readiness::Ready
+    } else {
+        Readiness::Unavailable
+    };
+    #[cfg(feature = "tenancy")]
+    if let Some(outage) = state.tenants.as_ref().and_then(|tenants| tenants.outage()) {
+        unavailable.push(crate::models::UnavailableBackend {
+            backend: "tenancy".into(),
+            since: outage.since,
+        });
+    }
+    let status = match status {
+        Readiness::Ready if !unavailable.is_empty() => Readiness::Degraded,
+        status => status,
+    };
+    let code = match status {
+        Readiness::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Readiness::Ready | Readiness::Degraded => StatusCode::OK,
+    };
+    (
+        code,
+        Json(ReadinessResponse {
+            status,
+            unavailable,
+        }),
+    )
+}
+
+/// Load of the blocking pool running CPU-heavy crypto, key usage and
+/// security events. Scrapers that accept `application/openmetrics-text`
+/// get the counters in that format instead.
+pub async fn metrics(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let openmetrics = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("application/openmetrics-text"));
+    if openmetrics {
+        let text = crate::openmetrics::render(
+            &state.key_usage.stats(),
+            &state.security.stats(),
+            &state.deprecations.stats(),
+        );
+        return (
+            [(header::CONTENT_TYPE, crate::openmetrics::CONTENT_TYPE)],
+            text,
+        )
+            .into_response();
+    }
+    Json(MetricsResponse {
+        blocking_pool: state.blocking.stats(),
+        #[cfg(feature = "signing")]
+        signature_cache: state.signature_cache.as_ref().map(|cache| {
+            let stats = cache.stats();
+            let lookups = stats.hits + stats.misses;
+            SignatureCacheStats {
+                capacity: stats.capacity as u64,
+                entries: stats.entries as u64,
+                hits: stats.hits,
+                misses: stats.misses,
+                hit_rate: if lookups == 0 {
+                    0.0
+                } else {
+                    stats.hits as f64 / lookups as f64
+                },
+            }
+        }),
+        #[cfg(not(feature = "signing"))]
+        signature_cache: None,
+        #[cfg(feature = "signing")]
+        coalescing: state.sign_flights.as_ref().map(|flights| flights.stats()),
+        #[cfg(not(feature = "signing"))]
+        coalescing: None,
+        key_usage: state.key_usage.stats(),
+        retries: state.retries.stats(),
+        watchdog: state.watchdog.stats(),
+        security: state.security.stats(),
+        legacy_formats: state.deprecations.stats(),
+    })
+    .into_response()
+}
+
+/// Reports the operations each key has served since startup, to inform
+/// rotation schedules. `idle_secs` keeps only keys unused for that long,
+/// which are candidates for retirement.
+pub async fn key_usage(
+    State(state): State<AppState>,
+    ValidQuery(params): ValidQuery<KeyUsageParams>,
+) -> Json<KeyUsageResponse> {
+    let mut keys = state.key_usage.stats();
+    if let Some(idle_secs) = params.idle_secs {
+        let cutoff = state.clock.now().saturating_sub(idle_secs);
+        keys.retain(|key| key.last_used.is_none_or(|last_used| last_used <= cutoff));
+    }
+    Json(KeyUsageResponse { keys })
+}
+
+/// Streams audit and key lifecycle events as server-sent events, from the
+/// moment of the request until the client disconnects. Each is sent as
+/// `event: audit` or `event: key` with its JSON in `data` and a sequence
+/// number in `id`; `types=` keeps only some types. A subscriber that falls
+/// too far behind gets a `lagged` event saying how many it missed.
+pub async fn events(
+    State(state): State<AppState>,
+    ValidQuery(params): ValidQuery<EventStreamParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Error> {
+    let types: Vec<EventType> = match &params.types {
+        Some(types) => types
+            .split(',')
+            .map(|kind| kind.trim().parse().map_err(Error::Validation))
+            .collect::<Result<_, _>>()?,
+        None => EventType::ALL.to_vec(),
+    };
+    let receiver = state.events.subscribe();
+    let stream =
+        futures_util::stream::unfold((receiver, types), |(mut receiver, types)| async move {
+            let event = loop {
+                match receiver.recv().await {
+                    Ok(published) if types.contains(&published.event.kind()) => {
+                        break sse_event(&published);
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        break Event::default()
+                            .event("lagged")
+                            .json_data(EventsMissed { missed })
+                            .expect("missed counts serialize");
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            };
+            Some((Ok(event), (receiver, types)))
+        });
+    let keep_alive = KeepAlive::new().interval(state.events.keep_alive_interval());
+    Ok(Sse::new(stream).keep_alive(keep_alive))
+}
+
+fn sse_event(published: &Published) -> Event {
+    Event::default()
+        .id(published.id.to_string())
+        .event(published.event.kind().as_str())
+        .json_data(&published.event)
+        .expect("stream events serialize")
+}
+
+/// Lists the signing algorithms registered on this instance.
+#[cfg(feature = "signing")]
+pub async fn algorithms(State(state): State<AppState>) -> Json<AlgorithmsResponse> {
+    Json(AlgorithmsResponse {
+        signing: state
+            .signers
+            .algorithms()
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+/// Exports a signing key sealed to a configured escrow key. The key never
+/// leaves in plaintext; every attempt is audited as `key.escrow_export`.
+#[cfg(feature = "escrow")]
+pub async fn escrow_export(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidJson(request): ValidJson<EscrowExportRequest>,
+) -> Result<Json<EscrowBundle>, Error> {
+    let alg = request
+        .alg
+        .as_deref()
+        .unwrap_or(state.signers.default_alg());
+    let result = state.escrow.load().export(alg, &request.escrow_key);
+    audit(
+        &state,
+        &headers,
+        "key.escrow_export",
+        &format!("{alg} -> {}", request.escrow_key),
+        result.is_ok(),
+    )
+    .await;
+    Ok(Json(result?))
+}
+
+/// Starts a key ceremony collecting a share from every custodian; see
+/// [`crate::ceremony`]. Audited as `ceremony.start`.
+#[cfg(feature = "escrow")]
+pub async fn start_ceremony(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    ValidJson(request): ValidJson<StartCeremonyRequest>,
+) -> Result<(StatusCode, Json<CeremonyStatus>), Error> {
+    let started_by = api_key.as_ref().map(|Extension(key)| key.name.as_str());
+    let result = ceremonies(&state)?.start(
+        &request.kid,
+        &request.escrow_key,
+        &state.escrow.load(),
+        started_by,
+    );
+    let resource = match &result {
+        Ok(status) => format!("{} ({} -> {})", status.id, status.kid, status.escrow_key),
+        Err(_) => format!("{} -> {}", request.kid, request.escrow_key),
+    };
+    audit(
+        &state,
+        &headers,
+        "ceremony.start",
+        &resource,
+        result.is_ok(),
+    )
+    .await;
+    Ok((StatusCode::CREATED, Json(result?)))
+}
+
+#[cfg(feature = "escrow")]
+pub async fn ceremony(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<CeremonyStatus>, Error> {
+    Ok(Json(ceremonies(&state)?.status(&id)?))
+}
+
+/// Takes the share of the custodian whose API key sent the request. Every
+/// attempt is audited as `ceremony.share`, naming the custodian; the one
+/// that completes the ceremony also as `ceremony.complete`.
+#[cfg(feature = "escrow")]
+pub async fn contribute_share(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    ValidJson(request): ValidJson<CeremonyShareRequest>,
+) -> Result<Json<CeremonyStatus>, Error> {
+    let ceremonies = ceremonies(&state)?;
+    let Some(Extension(custodian)) = api_key else {
+        return Err(Error::Unauthorized(
+            "shares must be sent with a custodian's API key".into(),
+        ));
+    };
+    let result = crate::crypto::codec::decode(&request.share)
+        .ok_or(CeremonyError::InvalidShare)
+        .and_then(|share| {
+            ceremonies.contribute(&id, &custodian.name, &share, &state.escrow.load())
+        });
+    let resource = format!("{id} by {}", custodian.name);
+    audit(
+        &state,
+        &headers,
+        "ceremony.share",
+        &resource,
+        result.is_ok(),
+    )
+    .await;
+    let status = result?;
+    if status.state == "complete" {
+        let resource = format!("{id} ({})", status.kid);
+        audit(&state, &headers, "ceremony.complete", &resource, true).await;
+    }
+    Ok(Json(status))
+}
+
+/// Calls off a ceremony still collecting shares. Audited as
+/// `ceremony.abort`.
+#[cfg(feature = "escrow")]
+pub async fn abort_ceremony(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<CeremonyStatus>, Error> {
+    let result = ceremonies(&state)?.abort(&id);
+    audit(&state, &headers, "ceremony.abort", &id, result.is_ok()).await;
+    Ok(Json(result?))
+}
+
+/// The key of a complete ceremony, sealed to its escrow key. Audited as
+/// `ceremony.bundle`.
+#[cfg(feature = "escrow")]
+pub async fn ceremony_bundle(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<EscrowBundle>, Error> {
+    let result = ceremonies(&state)?.bundle(&id);
+    audit(&state, &headers, "ceremony.bundle", &id, result.is_ok()).await;
+    Ok(Json(result?))
+}
+
+#[cfg(feature = "escrow")]
+fn ceremonies(state: &AppState) -> Result<&Ceremonies, Error> {
+    state
+        .ceremonies
+        .as_deref()
+        .ok_or_else(|| Error::NotFound("key ceremonies are not enabled".into()))
+}
+
+/// Documents between two progress log lines of [`migrate_encryption`].
+#[cfg(feature = "encryption")]
+const MIGRATION_PROGRESS_EVERY: usize = 1000;
+
+/// Re-encrypts documents sent as NDJSON, one per line, onto the configured
+/// encryptor, and streams them back as NDJSON in the same order. Documents
+/// with a `_crypto` sidecar have the base64 values it lists moved; those
+/// without one have the values at `pointers=` moved, and are refused if it
+/// is not given, since nothing tells base64 ciphertext apart from a plain
+/// string. Lines are read and answered one at a time, each at most
+/// `limits.max_body_bytes` long. A document that cannot be migrated ends
+/// the response early, and its line is logged and audited; `dry_run=true`
+/// instead answers with a [`MigrationReport`] listing every such line, and
+/// encrypts nothing.
+#[cfg(feature = "encryption")]
+pub async fn migrate_encryption(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidQuery(params): ValidQuery<MigrateParams>,
+    body: Body,
+) -> Result<Response, Error> {
+    let pointers = match &params.pointers {
+        Some(pointers) => pointers.split(',').map(str::to_string).collect(),
+        None => Vec::new(),
+    };
+    let migration =
+        Migration::new(state.encryption_algorithm, ENCRYPTION_KEY_ID).pointers(pointers);
+    let lines = NdjsonLines::new(body, state.migrate_max_line_bytes);
+    if params.dry_run {
+        return Ok(Json(dry_run_migration(&state, &migration, lines).await?).into_response());
+    }
+    let run = MigrationRun {
+        state,
+        headers,
+        migration,
+        lines,
+        documents: 0,
+        values: 0,
+    };
+    let documents = futures_util::stream::unfold(Some(run), |run| async move {
+        let mut run = run?;
+        match run.next().await {
+            Ok(Some(document)) => Some((Ok(document), Some(run))),
+            Ok(None) => {
+                run.done().await;
+                None
+            }
+            Err(err) => Some((Err(err), None)),
+        }
+    });
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(documents),
+    )
+        .into_response())
+}
+
+/// Checks every document, collecting those that cannot be migrated.
+#[cfg(feature = "encryption")]
+async fn dry_run_migration(
+    state: &AppState,
+    migration: &Migration,
+    mut lines: NdjsonLines,
+) -> Result<MigrationReport, Error> {
+    let mut report = MigrationReport {
+        documents: 0,
+        values: 0,
+        failures: Vec::new(),
+    };
+    while let Some((line, bytes)) = lines.next().await? {
+        report.documents += 1;
+        match migrate_line(state, migration, &bytes, true).await {
+            Ok((_, values)) => report.values += values,
+            Err(err) => report.failures.push(MigrationFailure {
+                line,
+                error: err.to_string(),
+            }),
+        }
+        if report.documents.is_multiple_of(MIGRATION_PROGRESS_EVERY) {
+            tracing::info!(
+                documents = report.documents,
+                values = report.values,
+                dry_run = true,
+                "migrating ciphertext"
+            );
+        }
+    }
+    tracing::info!(
+        documents = report.documents,
+        values = report.values,
+        failed = report.failures.len(),
+        dry_run = true,
+        to = state.encryption_algorithm,
+        "ciphertext migration done"
+    );
+    Ok(report)
+}
+
+/// A migration answering each document as soon as it is re-encrypted.
+#[cfg(feature = "encryption")]
+struct MigrationRun {
+    state: AppState,
+    headers: HeaderMap,
+    migration: Migration,
+    lines: NdjsonLines,
+    documents: usize,
+    values: usize,
+}
+
+#[cfg(feature = "encryption")]
+impl MigrationRun {
+    /// The next document, re-encrypted, as an NDJSON line.
+    async fn next(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let result = async {
+            let Some((line, bytes)) = self.lines.next().await? else {
+                return Ok(None);
+            };
+            let (document, values) = migrate_line(&self.state, &self.migration, &bytes, false)
+                .await
+                .map_err(|err| match err {
+                    Error::Validation(message) => {
+                        Error::Validation(format!("line {line}: {message}"))
+                    }
+                    other => other,
+                })?;
+            Ok(Some((line, document, values)))
+        }
+        .await;
+        let (line, document, values) = match result {
+            Ok(Some(migrated)) => migrated,
+            Ok(None) => return Ok(None),
+            Err(err) => {
+                tracing::warn!("ciphertext migration failed: {err}");
+                let resource = format!("after {} documents", self.documents);
+                audit(
+                    &self.state,
+                    &self.headers,
+                    "encryption.migrate",
+                    &resource,
+                    false,
+                )
+                .await;
+                return Err(err);
+            }
+        };
+        self.documents += 1;
+        self.values += values;
+        if self.documents.is_multiple_of(MIGRATION_PROGRESS_EVERY) {
+            tracing::info!(
+                documents = self.documents,
+                values = self.values,
+                line,
+                "migrating ciphertext"
+            );
+        }
+        let mut migrated = serde_json::to_vec(&document).expect("a JSON value serializes");
+        migrated.push(b'\n');
+        Ok(Some(migrated))
+    }
+
+    /// Logs and audits a migration that went through every document.
+    async fn done(self) {
+        tracing::info!(
+            documents = self.documents,
+            values = self.values,
+            to = self.state.encryption_algorithm,
+            "ciphertext migration done"
+        );
+        let resource = format!("{} documents, {} values", self.documents, self.values);
+        audit(
+            &self.state,
+            &self.headers,
+            "encryption.migrate",
+            &resource,
+            true,
+        )
+        .await;
+    }
+}
+
+/// Parses one NDJSON line and migrates it, returning the document and how
+/// many of its values were (or, with `dry_run`, would be) re-encrypted.
+#[cfg(feature = "encryption")]
+async fn migrate_line(
+    state: &AppState,
+    migration: &Migration,
+    line: &[u8],
+    dry_run: bool,
+) -> Result<(serde_json::Value, usize), Error> {
+    state.encrypt_limits.check(line)?;
+    let mut document: serde_json::Value = serde_json::from_slice(line)
+        .map_err(|err| Error::Validation(format!("invalid JSON: {err}")))?;
+    let values = migration
+        .migrate_async(state.encryptor.as_ref(), &mut document, dry_run)
+        .await?;
+    Ok((document, values))
+}
+
+/// The lines of a request body, read as they arrive so no more than one is
+/// held at a time.
+#[cfg(feature = "encryption")]
+struct NdjsonLines {
+    body: BodyDataStream,
+    buffer: Vec<u8>,
+    /// Leading bytes of `buffer` known to hold no newline.
+    scanned: usize,
+    line: usize,
+    max_line_bytes: usize,
+    ended: bool,
+}
+
+#[cfg(feature = "encryption")]
+impl NdjsonLines {
+    fn new(body: Body, max_line_bytes: usize) -> Self {
+        Self {
+            body: body.into_data_stream(),
+            buffer: Vec::new(),
+            scanned: 0,
+            line: 0,
+            max_line_bytes,
+            ended: false,
+        }
+    }
+
+    /// The next line that is not blank, with its 1-based number.
+    async fn next(&mut self) -> Result<Option<(usize, Vec<u8>)>, Error> {
+        loop {
+            let newline = self.buffer[self.scanned..]
+                .iter()
+                .position(|byte| *byte == b'\n');
+            let mut bytes = match newline {
+                Some(at) => {
+                    let bytes = self.buffer.drain(..=self.scanned + at).collect();
+                    self.scanned = 0;
+                    bytes
+                }
+                None if self.ended && self.buffer.is_empty() => return Ok(None),
+                None if self.ended => std::mem::take(&mut self.buffer),
+                None if self.buffer.len() > self.max_line_bytes => {
+                    return Err(self.too_long(self.line + 1));
+                }
+                None => {
+                    self.scanned = self.buffer.len();
+                    match self.body.next().await {
+                        Some(chunk) => self.buffer.extend_from_slice(&chunk.map_err(|err| {
+                            Error::Validation(format!("failed to read the body: {err}"))
+                        })?),
+                        None => self.ended = true,
+                    }
+                    continue;
+                }
+            };
+            self.line += 1;
+            if bytes.last() == Some(&b'\n') {
+                bytes.pop();
+            }
+            if bytes.len() > self.max_line_bytes {
+                return Err(self.too_long(self.line));
+            }
+            if !bytes.trim_ascii().is_empty() {
+                return Ok(Some((self.line, bytes)));
+            }
+        }
+    }
+
+    fn too_long(&self, line: usize) -> Error {
+        Error::LimitExceeded(format!(
+            "line {line} is longer than {} bytes",
+            self.max_line_bytes
+        ))
+    }
+}
+
+/// Drops the cached keys of one tenant, e.g. after rotating its key in the
+/// key store, so the next request reloads them.
+#[cfg(feature = "tenancy")]
+pub async fn invalidate_tenant(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+) -> Result<StatusCode, Error> {
+    tenants(&state)?.invalidate(&tenant);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Gives a tenant a new random key, active unless `state=pending`, creating
+/// the tenant if needed. Only tenant key sources the service writes to
+/// (SQLite) support this; keys in the config file, Redis or Postgres are
+/// managed there.
+#[cfg(feature = "tenancy")]
+pub async fn rotate_tenant_key(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+    headers: HeaderMap,
+    ValidQuery(params): ValidQuery<RotateKeyParams>,
+) -> Result<StatusCode, Error> {
+    let key_state = params
+        .state
+        .as_deref()
+        .map(str::parse)
+        .transpose()?
+        .unwrap_or(KeyState::Active);
+    let result = tenants(&state)?.rotate(&tenant, key_state).await;
+    audit(
+        &state,
+        &headers,
+        "tenant.rotate_key",
+        &tenant,
+        result.is_ok(),
+    )
+    .await;
+    result?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Moves a tenant's key to another lifecycle state. A destroyed key still
+/// verifies, and can be restored as disabled, until
+/// `tenancy.destruction_grace_secs` have passed.
+#[cfg(feature = "tenancy")]
+pub async fn set_tenant_key_state(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+    headers: HeaderMap,
+    ValidJson(request): ValidJson<TenantStateRequest>,
+) -> Result<StatusCode, Error> {
+    let key_state: KeyState = request.state.parse()?;
+    let result = tenants(&state)?.transition(&tenant, key_state).await;
+    audit(
+        &state,
+        &headers,
+        "tenant.set_state",
+        &tenant,
+        result.is_ok(),
+    )
+    .await;
+    result.map_err(|err| match err {
+        TenancyError::UnknownTenant(_) => Error::NotFound(err.to_string()),
+        other => other.into(),
+    })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lists tenants page by page. Keys are never included.
+#[cfg(feature = "tenancy")]
+pub async fn list_tenants(
+    State(state): State<AppState>,
+    ValidQuery(params): ValidQuery<TenantListParams>,
+) -> Result<Json<TenantListResponse>, Error> {
+    use crate::tenancy::TenantListing;
+
+    let limit = params.limit.unwrap_or(50);
+    if !(1..=500).contains(&limit) {
+        return Err(Error::Validation(
+            "`limit` must be between 1 and 500".into(),
+        ));
+    }
+    let listing = TenantListing {
+        limit,
+        after: params.cursor.as_deref().map(str::parse).transpose()?,
+        newest_first: params.order == SortOrder::Desc,
+        state: params.state.as_deref().map(str::parse).transpose()?,
+    };
+    let tenants = tenants(&state)?;
+    // Every tenant key is an HMAC-SHA256 key.
+    let algorithm = SigningAlgorithm::HmacSha256.as_str();
+    if params.algorithm.is_some_and(|wanted| wanted != algorithm) {
+        return Ok(Json(TenantListResponse {
+            tenants: Vec::new(),
+            next_cursor: None,
+        }));
+    }
+    let page = tenants.list(&listing).await?;
+    Ok(Json(TenantListResponse {
+        tenants: page
+            .tenants
+            .into_iter()
+            .map(|entry| TenantSummary {
+                tenant: entry.tenant,
+                algorithm: algorithm.to_string(),
+                created_at: entry.created_at,
+                updated_at: entry.updated_at,
+                state: entry.state.to_string(),
+                destroy_at: entry.destroy_at,
+            })
+            .collect(),
+        next_cursor: page.next.map(|cursor| cursor.to_string()),
+    }))
+}
+
+/// Drops the cached keys of every tenant.
+#[cfg(feature = "tenancy")]
+pub async fn invalidate_tenants(State(state): State<AppState>) -> Result<StatusCode, Error> {
+    tenants(&state)?.invalidate_all();
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(feature = "tenancy")]
+fn tenants(state: &AppState) -> Result<&crate::tenancy::Tenants, Error> {
+    state
+        .tenants
+        .as_deref()
+        .ok_or_else(|| Error::NotFound("tenancy is not enabled".into()))
+}
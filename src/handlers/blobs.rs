@@ -0,0 +1,87 @@
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::rejection::BytesRejection;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+use crate::blobs::{BlobAddress, ByteRange, get_blob, get_blob_range, put_blob};
+use crate::error::Error;
+use crate::models::BlobResponse;
+use crate::state::AppState;
+
+/// Stores the raw request body. Answers `201 Created` for a new blob and
+/// `200 OK` if the same content was already stored.
+pub async fn put(
+    State(state): State<AppState>,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<impl IntoResponse, Error> {
+    let body = body.map_err(|rejection| match rejection.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => Error::PayloadTooLarge,
+        _ => Error::Validation(rejection.body_text()),
+    })?;
+    let (address, created) = put_blob(
+        state.blobs.as_ref(),
+        state.encryptor.as_ref(),
+        state.blob_chunks.as_deref(),
+        &body,
+    )
+    .await?;
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((
+        status,
+        [(header::LOCATION, format!("/blobs/{address}"))],
+        Json(BlobResponse {
+            address: address.to_string(),
+        }),
+    ))
+}
+
+/// Returns the blob as `application/octet-stream`, after checking that it
+/// still hashes to `hash`. A single `Range: bytes=` range of a blob sealed
+/// in chunks is answered with `206 Partial Content`, opening only the
+/// chunks it covers; other ranges are ignored and the whole blob sent.
+pub async fn get(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let address: BlobAddress = hash.parse()?;
+    let chunks = state.blob_chunks.as_deref();
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(ByteRange::parse);
+    if let Some(range) = range
+        && let Some(part) = get_blob_range(state.blobs.as_ref(), chunks, address, range).await?
+    {
+        let content_range = format!(
+            "bytes {}-{}/{}",
+            part.range.start,
+            part.range.end - 1,
+            part.length
+        );
+        return Ok((
+            StatusCode::PARTIAL_CONTENT,
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (header::ACCEPT_RANGES, "bytes".to_string()),
+                (header::CONTENT_RANGE, content_range),
+            ],
+            part.bytes,
+        )
+            .into_response());
+    }
+    let blob = get_blob(
+        state.blobs.as_ref(),
+        state.encryptor.as_ref(),
+        chunks,
+        address,
+    )
+    .await?;
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], blob).into_response())
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::State;
+use rand_core::{OsRng, RngCore};
+
+use crate::anomaly::Caller;
+use crate::crypto::challenge::{self, Challenge, ChallengeError};
+use crate::error::Error;
+use crate::handlers::extract::ValidJson;
+use crate::handlers::record_verification;
+use crate::models::{
+    ChallengeAnswerRequest, ChallengeAnswerResponse, ChallengeRequest, ChallengeResponse,
+};
+use crate::security::{SecurityEvent, VerifyFailure, VerifyScheme};
+use crate::state::AppState;
+
+/// Issues a challenge for the holder of `keyid`, signed with the service's
+/// default key.
+pub async fn issue(
+    State(state): State<AppState>,
+    ValidJson(request): ValidJson<ChallengeRequest>,
+) -> Result<Json<ChallengeResponse>, Error> {
+    if state
+        .http_signature_keys
+        .load()
+        .get(Some(&request.keyid))
+        .is_none()
+    {
+        return Err(Error::Validation(format!(
+            "unknown keyid `{}`",
+            request.keyid
+        )));
+    }
+    let mut nonce = [0; 16];
+    OsRng.fill_bytes(&mut nonce);
+    let issued = Challenge::new(
+        &request.keyid,
+        &nonce,
+        state.clock.now(),
+        state.challenge_ttl_secs,
+    );
+    let payload = issued.payload();
+    let signature = state
+        .signers
+        .default_signer()
+        .sign_bytes(payload.as_bytes())
+        .await?;
+    Ok(Json(ChallengeResponse {
+        challenge: format!("{payload}.{signature}"),
+        keyid: issued.keyid,
+        expires_at: issued.exp,
+    }))
+}
+
+/// Checks the key holder's signature over a challenge issued by
+/// [`issue`]. Each challenge is accepted once.
+pub async fn respond(
+    State(state): State<AppState>,
+    caller: Caller,
+    ValidJson(request): ValidJson<ChallengeAnswerRequest>,
+) -> Result<Json<ChallengeAnswerResponse>, Error> {
+    let now = state.clock.now();
+    let (issued, payload, signature) = Challenge::parse(&request.challenge)?;
+    let genuine = state
+        .signers
+        .default_signer()
+        .verify_bytes(payload.as_bytes(), signature)
+        .await?;
+    let verdict = if !genuine {
+        Err(ChallengeError::Forged)
+    } else if issued.is_expired(now) {
+        Err(ChallengeError::Expired)
+    } else {
+        match state.http_signature_keys.load().get(Some(&issued.keyid)) {
+            Some(key) => challenge::verify_response(key, &request.challenge, &request.signature),
+            None => Err(ChallengeError::UnknownKey),
+        }
+    };
+    if let Err(err) = verdict {
+        let event = match err {
+            ChallengeError::UnknownKey => SecurityEvent::KeyNotFound,
+            ChallengeError::Expired => failed(VerifyFailure::Expired),
+            _ => failed(VerifyFailure::Mismatch),
+        };
+        state.security.record(event, Some(&issued.keyid), None);
+        record_verification(&state, &caller, false);
+        return Err(err.into());
+    }
+    // Only answered challenges are spent, so a bad answer does not burn
+    // the challenge of the actual key holder.
+    let ttl = Duration::from_secs(issued.exp - now + 1);
+    if !state.challenges.check_and_set(&issued.nonce, ttl).await? {
+        record_verification(&state, &caller, false);
+        return Err(ChallengeError::Replayed.into());
+    }
+    record_verification(&state, &caller, true);
+    Ok(Json(ChallengeAnswerResponse {
+        keyid: issued.keyid,
+        issued_at: issued.iat,
+    }))
+}
+
+fn failed(reason: VerifyFailure) -> SecurityEvent {
+    SecurityEvent::VerifyFailed {
+        scheme: VerifyScheme::Challenge,
+        reason,
+    }
+}
@@ -1,30 +1,305 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+
 use axum::Json;
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
 use serde_json::{Map, Value};
 
-use crate::crypto::base64::Base64Encryptor;
-use crate::crypto::encryptor::Encryptor;
+use crate::blocking::BlockingPool;
+use crate::crypto::encryptor::{
+    AsyncEncryptor, DecryptError, decrypt_fields_async, encrypt_fields_async,
+};
+use crate::crypto::migrate::SIDECAR;
+use crate::crypto::pointer::{
+    self, decrypt_pointers_async, encrypt_patch_async, encrypt_pointers_async,
+};
+use crate::crypto::strict_json::StrictJson;
+use crate::error::Error;
+use crate::handlers::extract::{LimitedJson, ValidJson, ValidQuery};
+use crate::models::{
+    CryptoSidecar, DecryptParams, DecryptRequest, DecryptResponse, DecryptStatusResponse,
+    EncryptParams, EncryptRequest, EncryptResponse, EncryptedPath, FieldStatus, PatchRequest,
+    PatchResponse, PointersRequest,
+};
+use crate::state::{AppState, ENCRYPTION_KEY_ID};
+
+pub async fn encrypt(
+    State(state): State<AppState>,
+    ValidQuery(params): ValidQuery<EncryptParams>,
+    LimitedJson(EncryptRequest(payload)): LimitedJson<EncryptRequest>,
+) -> Result<Json<EncryptResponse>, Error> {
+    let sidecar = match params.sidecar {
+        true => {
+            check_sidecar_target(&payload)?;
+            let fields = payload.as_object().map_or_else(Vec::new, |map| {
+                map.keys().map(|name| pointer::top_level(name)).collect()
+            });
+            Some(fields)
+        }
+        false => None,
+    };
+    let mut encrypted = if field_count(&payload) >= state.parallel_min_fields {
+        let encryptor = state.encryptor.clone();
+        merge(
+            in_parallel(&state.blocking, payload, move |chunk| {
+                let encryptor = encryptor.clone();
+                async move { encrypt_fields_async(encryptor.as_ref(), chunk).await }
+            })
+            .await?,
+        )
+    } else {
+        encrypt_fields_async(state.encryptor.as_ref(), payload).await?
+    };
+    if let Some(pointers) = sidecar {
+        add_sidecar(&state, &mut encrypted, pointers);
+    }
+    Ok(Json(EncryptResponse(encrypted)))
+}
+
+/// Encrypts only the values at the listed JSON Pointers, at any depth.
+pub async fn encrypt_pointers(
+    State(state): State<AppState>,
+    ValidQuery(params): ValidQuery<EncryptParams>,
+    LimitedJson(PointersRequest { data, pointers }): LimitedJson<PointersRequest>,
+) -> Result<Json<EncryptResponse>, Error> {
+    if params.sidecar {
+        check_sidecar_target(&data)?;
+    }
+    let mut encrypted = encrypt_pointers_async(state.encryptor.as_ref(), data, &pointers).await?;
+    if params.sidecar {
+        add_sidecar(&state, &mut encrypted, pointers);
+    }
+    Ok(Json(EncryptResponse(encrypted)))
+}
+
+/// Encrypts the values at the listed JSON Pointers of a document sent as
+/// text, changing no other byte of it. The document is held to the
+/// `/encrypt` limits, and may not repeat a key: only the last occurrence
+/// would be encrypted.
+pub async fn encrypt_patch(
+    State(state): State<AppState>,
+    LimitedJson(PatchRequest { document, pointers }): LimitedJson<PatchRequest>,
+) -> Result<Json<PatchResponse>, Error> {
+    let limits = StrictJson {
+        reject_duplicate_keys: true,
+        ..state.encrypt_limits
+    };
+    limits.check(document.as_bytes())?;
+    let document = encrypt_patch_async(state.encryptor.as_ref(), &document, &pointers).await?;
+    Ok(Json(PatchResponse { document }))
+}
+
+/// Refuses documents that cannot carry a sidecar.
+fn check_sidecar_target(payload: &Value) -> Result<(), Error> {
+    match payload.as_object() {
+        Some(map) if map.contains_key(SIDECAR) => Err(Error::Validation(format!(
+            "`{SIDECAR}` is reserved for the encryption sidecar"
+        ))),
+        Some(_) => Ok(()),
+        None => Err(Error::Validation(format!(
+            "a `{SIDECAR}` sidecar can only be added to a JSON object"
+        ))),
+    }
+}
+
+/// Records that the values at `pointers` were encrypted by this service's
+/// encryptor.
+fn add_sidecar(state: &AppState, document: &mut Value, pointers: Vec<String>) {
+    let path = EncryptedPath {
+        alg: state.encryption_algorithm.to_string(),
+        kid: ENCRYPTION_KEY_ID.to_string(),
+    };
+    let sidecar = CryptoSidecar {
+        paths: pointers
+            .into_iter()
+            .map(|pointer| (pointer, path.clone()))
+            .collect(),
+    };
+    let sidecar = serde_json::to_value(sidecar).expect("a sidecar serializes to JSON");
+    if let Value::Object(map) = document {
+        map.insert(SIDECAR.to_string(), sidecar);
+    }
+}
+
+/// Removes and parses the sidecar of `payload`, if it has one, returning
+/// the pointers it lists. Values encrypted with another algorithm or key
+/// cannot be decrypted here.
+fn take_sidecar(state: &AppState, payload: &mut Value) -> Result<Option<Vec<String>>, Error> {
+    let Some(sidecar) = payload.as_object_mut().and_then(|map| map.remove(SIDECAR)) else {
+        return Ok(None);
+    };
+    let sidecar: CryptoSidecar = serde_json::from_value(sidecar)
+        .map_err(|err| Error::Validation(format!("invalid `{SIDECAR}` sidecar: {err}")))?;
+    for (pointer, path) in &sidecar.paths {
+        if path.alg != state.encryption_algorithm || path.kid != ENCRYPTION_KEY_ID {
+            return Err(Error::Validation(format!(
+                "`{pointer}` was encrypted with {} key `{}`, which this service does not hold",
+                path.alg, path.kid
+            )));
+        }
+    }
+    Ok(Some(sidecar.paths.into_keys().collect()))
+}
+
+/// Decrypts the values at the listed JSON Pointers, passing through those
+/// that are not ciphertext.
+pub async fn decrypt_pointers(
+    State(state): State<AppState>,
+    ValidJson(PointersRequest { data, pointers }): ValidJson<PointersRequest>,
+) -> Result<Json<DecryptResponse>, Error> {
+    let decrypted = decrypt_pointers_async(state.encryptor.as_ref(), data, &pointers).await?;
+    Ok(Json(DecryptResponse(decrypted)))
+}
 
-pub async fn encrypt(Json(payload): Json<Value>) -> Json<Value> {
-    Json(apply_method_to_values(&payload, &|v| {
-        Base64Encryptor.encrypt(v)
-    }))
+pub async fn decrypt(
+    State(state): State<AppState>,
+    ValidQuery(params): ValidQuery<DecryptParams>,
+    ValidJson(DecryptRequest(mut payload)): ValidJson<DecryptRequest>,
+) -> Result<Response, Error> {
+    if let Some(pointers) = take_sidecar(&state, &mut payload)? {
+        if params.field_status {
+            return Err(Error::Validation(format!(
+                "field_status does not apply to documents with a `{SIDECAR}` sidecar"
+            )));
+        }
+        let decrypted =
+            decrypt_pointers_async(state.encryptor.as_ref(), payload, &pointers).await?;
+        return Ok(Json(DecryptResponse(decrypted)).into_response());
+    }
+    if params.field_status {
+        return decrypt_with_status(&state, payload)
+            .await
+            .map(|response| Json(response).into_response());
+    }
+    let decrypted = if field_count(&payload) >= state.parallel_min_fields {
+        let encryptor = state.encryptor.clone();
+        merge(
+            in_parallel(&state.blocking, payload, move |chunk| {
+                let encryptor = encryptor.clone();
+                async move { decrypt_fields_async(encryptor.as_ref(), chunk).await }
+            })
+            .await?,
+        )
+    } else {
+        decrypt_fields_async(state.encryptor.as_ref(), payload).await?
+    };
+    Ok(Json(DecryptResponse(decrypted)).into_response())
 }
 
-pub async fn decrypt(Json(payload): Json<Value>) -> Json<Value> {
-    Json(apply_method_to_values(&payload, &|v| {
-        Base64Encryptor.decrypt(v).unwrap_or(v.clone())
-    }))
+/// Decrypts the top-level properties of an object, keeping those that fail
+/// authentication as they are, and reports what happened to each. Backend
+/// failures still fail the request.
+async fn decrypt_with_status(
+    state: &AppState,
+    payload: Value,
+) -> Result<DecryptStatusResponse, Error> {
+    if !payload.is_object() {
+        return Err(Error::Validation(
+            "field_status requires a JSON object".into(),
+        ));
+    }
+    let chunks = if field_count(&payload) >= state.parallel_min_fields {
+        let encryptor = state.encryptor.clone();
+        in_parallel(&state.blocking, payload, move |chunk| {
+            let encryptor = encryptor.clone();
+            async move { decrypt_reporting(encryptor.as_ref(), chunk).await }
+        })
+        .await?
+    } else {
+        vec![decrypt_reporting(state.encryptor.as_ref(), payload).await?]
+    };
+    let mut response = DecryptStatusResponse {
+        data: Map::new(),
+        fields: BTreeMap::new(),
+    };
+    for chunk in chunks {
+        response.data.extend(chunk.data);
+        response.fields.extend(chunk.fields);
+    }
+    Ok(response)
 }
 
-fn apply_method_to_values(values: &Value, method: &dyn Fn(&Value) -> Value) -> Value {
-    match values {
-        Value::Object(map) => {
-            let mut out = Map::with_capacity(map.len());
-            for (key, value) in map.iter() {
-                out.insert(key.clone(), method(value));
+async fn decrypt_reporting(
+    encryptor: &dyn AsyncEncryptor,
+    payload: Value,
+) -> Result<DecryptStatusResponse, DecryptError> {
+    let Value::Object(mut data) = payload else {
+        unreachable!("checked to be an object by the caller");
+    };
+    let mut fields = BTreeMap::new();
+    for (key, value) in data.iter_mut() {
+        let status = match encryptor.decrypt(value).await {
+            Ok(decrypted) => {
+                *value = decrypted;
+                FieldStatus::Decrypted
             }
-            Value::Object(out)
+            Err(DecryptError::NotCiphertext) => FieldStatus::Passthrough,
+            Err(DecryptError::AuthenticationFailed) => FieldStatus::Failed,
+            Err(err) => return Err(err),
+        };
+        fields.insert(key.clone(), status);
+    }
+    Ok(DecryptStatusResponse { data, fields })
+}
+
+fn field_count(payload: &Value) -> usize {
+    payload.as_object().map_or(0, Map::len)
+}
+
+/// Splits the properties of `payload` into one chunk per available core and
+/// runs `transform` on each chunk on the blocking pool, so large documents
+/// do not hold up the async workers. Results come back in key order.
+async fn in_parallel<F, Fut, T, E>(
+    pool: &BlockingPool,
+    payload: Value,
+    transform: F,
+) -> Result<Vec<T>, Error>
+where
+    F: Fn(Value) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<T, E>>,
+    T: Send + 'static,
+    E: Into<Error> + Send + 'static,
+{
+    let Value::Object(map) = payload else {
+        return Ok(vec![transform(payload).await.map_err(Into::into)?]);
+    };
+    let workers = std::thread::available_parallelism().map_or(1, usize::from);
+    let chunk_len = map.len().div_ceil(workers).max(1);
+    let mut entries = map.into_iter();
+    let mut tasks = Vec::with_capacity(workers);
+    loop {
+        let chunk: Map<String, Value> = entries.by_ref().take(chunk_len).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        let transform = transform.clone();
+        let runtime = tokio::runtime::Handle::current();
+        let pool = pool.clone();
+        tasks.push(tokio::spawn(async move {
+            pool.run(move || runtime.block_on(transform(Value::Object(chunk))))
+                .await
+        }));
+    }
+    let mut out = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        let chunk = task
+            .await
+            .map_err(|err| Error::Crypto(format!("encryption worker failed: {err}")))??
+            .map_err(Into::into)?;
+        out.push(chunk);
+    }
+    Ok(out)
+}
+
+/// Joins the objects [`in_parallel`] returned back into one.
+fn merge(chunks: Vec<Value>) -> Value {
+    let mut out = Map::new();
+    for chunk in chunks {
+        match chunk {
+            Value::Object(chunk) => out.extend(chunk),
+            other => return other,
         }
-        other => method(other),
     }
+    Value::Object(out)
 }
@@ -0,0 +1,201 @@
+use std::convert::Infallible;
+
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use serde::de::DeserializeOwned;
+
+use crate::anomaly::Caller;
+#[cfg(any(feature = "signing", feature = "encryption"))]
+use crate::crypto::strict_json::StrictJson;
+use crate::error::Error;
+use crate::layers::{CLIENT_ID_HEADER, TENANT_ID_HEADER};
+#[cfg(any(feature = "signing", feature = "encryption"))]
+use crate::state::AppState;
+
+/// `Json` extractor whose rejections are reported as [`Error`]s, so bodies
+/// that do not match the expected model get a `400` with an error code
+/// rather than axum's plain-text `422`.
+pub struct ValidJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::<T>::from_request(req, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// [`ValidJson`] for payloads that get signed or verified. The body is
+/// buffered once and checked against [`AppState::strict_json`] (the depth
+/// and size limits and, with `signing.reject_duplicate_keys`, repeated keys
+/// that `serde_json` would otherwise resolve to the last occurrence), then
+/// deserialized straight from that buffer.
+#[cfg(feature = "signing")]
+pub struct SignedJson<T>(pub T);
+
+#[cfg(feature = "signing")]
+impl<T> FromRequest<AppState> for SignedJson<T>
+where
+    T: DeserializeOwned,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+        checked_json(req, state, &state.strict_json).await.map(Self)
+    }
+}
+
+/// [`ValidJson`] for `/encrypt` bodies, checked against
+/// [`AppState::encrypt_limits`] before they are deserialized.
+#[cfg(feature = "encryption")]
+pub struct LimitedJson<T>(pub T);
+
+#[cfg(feature = "encryption")]
+impl<T> FromRequest<AppState> for LimitedJson<T>
+where
+    T: DeserializeOwned,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+        checked_json(req, state, &state.encrypt_limits)
+            .await
+            .map(Self)
+    }
+}
+
+/// Buffers a JSON body, checks it against `limits`, then deserializes it
+/// from that buffer.
+#[cfg(any(feature = "signing", feature = "encryption"))]
+async fn checked_json<T: DeserializeOwned>(
+    req: Request,
+    state: &AppState,
+    limits: &StrictJson,
+) -> Result<T, Error> {
+    use axum::body::Bytes;
+    use axum::http::StatusCode;
+
+    if !has_json_content_type(req.headers()) {
+        return Err(Error::UnsupportedMediaType(
+            "Expected request with `Content-Type: application/json`".into(),
+        ));
+    }
+    let bytes =
+        Bytes::from_request(req, state)
+            .await
+            .map_err(|rejection| match rejection.status() {
+                StatusCode::PAYLOAD_TOO_LARGE => Error::PayloadTooLarge,
+                _ => Error::Validation(rejection.body_text()),
+            })?;
+    limits.check(&bytes)?;
+    let axum::Json(value) = axum::Json::<T>::from_bytes(&bytes)?;
+    Ok(value)
+}
+
+/// `application/json` or any `application/*+json` type, as axum's `Json`
+/// accepts.
+#[cfg(any(feature = "signing", feature = "encryption"))]
+fn has_json_content_type(headers: &axum::http::HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    match essence.split_once('/') {
+        Some((kind, subtype)) => {
+            let subtype = subtype.to_ascii_lowercase();
+            kind.eq_ignore_ascii_case("application")
+                && (subtype == "json" || subtype.ends_with("+json"))
+        }
+        None => false,
+    }
+}
+
+/// `Query` extractor whose rejections are reported as [`Error`]s.
+pub struct ValidQuery<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for ValidQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::<T>::from_request_parts(parts, state)
+                .await
+                .map_err(|e| Error::Validation(e.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+/// The caller as named by its `X-Client-Id` and `X-Tenant-Id` headers;
+/// headers that are missing or not text are left out.
+impl<S> FromRequestParts<S> for Caller
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = |name| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Ok(Caller {
+            client: header(CLIENT_ID_HEADER),
+            tenant: header(TENANT_ID_HEADER),
+        })
+    }
+}
+
+/// Signers serving the request, with the `kid` naming their key: those of
+/// the tenant named in `X-Tenant-Id` (`tenant:<id>`) when tenancy is
+/// enabled, the service's own (`signing.key_id`) otherwise.
+#[cfg(feature = "signing")]
+pub struct Signers(pub crate::crypto::registry::SignerRegistry, pub String);
+
+#[cfg(feature = "signing")]
+impl FromRequestParts<AppState> for Signers {
+    type Rejection = Error;
+
+    #[cfg_attr(not(feature = "tenancy"), allow(unused_variables))]
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        #[cfg(feature = "tenancy")]
+        if let Some(tenants) = &state.tenants {
+            use crate::security::SecurityEvent;
+            use crate::tenancy::{TENANT_ID_HEADER, TenancyError};
+
+            match parts.headers.get(TENANT_ID_HEADER) {
+                Some(tenant) => {
+                    let tenant = tenant.to_str().map_err(|_| TenancyError::InvalidTenantId)?;
+                    let signers = tenants.signers(tenant).await.inspect_err(|err| {
+                        if let TenancyError::UnknownTenant(tenant) = err {
+                            state
+                                .security
+                                .record(SecurityEvent::KeyNotFound, None, Some(tenant));
+                        }
+                    })?;
+                    return Ok(Self(signers, format!("tenant:{tenant}")));
+                }
+                None if tenants.is_required() => return Err(TenancyError::MissingTenant.into()),
+                None => {}
+            }
+        }
+        Ok(Self(state.signers.clone(), state.key_id.clone()))
+    }
+}
@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+
+use axum::Json;
+use axum::extract::State;
+
+use crate::anomaly::Caller;
+use crate::crypto::http_signature::{
+    self, HttpRequest, HttpSignatureError, SignatureParams, VerifiedSignature, content_digest,
+    verify_content_digest,
+};
+use crate::error::Error;
+use crate::handlers::extract::ValidJson;
+use crate::handlers::record_verification;
+use crate::models::{HttpSignRequest, HttpSignResponse, HttpVerifyRequest, HttpVerifyResponse};
+use crate::security::{SecurityEvent, VerifyFailure, VerifyScheme};
+use crate::state::AppState;
+
+const CONTENT_DIGEST: &str = "content-digest";
+
+pub async fn sign(
+    State(state): State<AppState>,
+    ValidJson(request): ValidJson<HttpSignRequest>,
+) -> Result<Json<HttpSignResponse>, Error> {
+    let keyring = state.http_signature_keys.load();
+    let keyid = request
+        .keyid
+        .as_deref()
+        .or(keyring.default_keyid())
+        .ok_or_else(|| Error::Validation("keyid is required".into()))?;
+    let key = keyring
+        .get(Some(keyid))
+        .ok_or_else(|| Error::Validation(format!("unknown keyid `{keyid}`")))?;
+
+    let mut headers: Vec<(String, String)> = request.headers.into_iter().collect();
+    let mut components = request.components;
+    let digest = request.body.map(|body| content_digest(body.as_bytes()));
+    if let Some(digest) = &digest {
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case(CONTENT_DIGEST));
+        headers.push((CONTENT_DIGEST.into(), digest.clone()));
+        if !components.iter().any(|c| c == CONTENT_DIGEST) {
+            components.push(CONTENT_DIGEST.into());
+        }
+    }
+    let params = SignatureParams {
+        components,
+        created: Some(request.created.unwrap_or_else(|| state.clock.now())),
+        expires: request.expires,
+        keyid: Some(keyid.to_string()),
+        alg: None,
+        nonce: request.nonce,
+        tag: request.tag,
+    };
+    let message = HttpRequest {
+        method: &request.method,
+        target_uri: &request.target_uri,
+        headers: &headers,
+    };
+    let label = request.label.as_deref().unwrap_or("sig1");
+    let signed = http_signature::sign(key, &message, label, &params)?;
+    Ok(Json(HttpSignResponse {
+        signature_input: signed.signature_input,
+        signature: signed.signature,
+        content_digest: digest,
+    }))
+}
+
+pub async fn verify(
+    State(state): State<AppState>,
+    caller: Caller,
+    ValidJson(request): ValidJson<HttpVerifyRequest>,
+) -> Result<Json<HttpVerifyResponse>, Error> {
+    let headers: Vec<(String, String)> = request.headers.into_iter().collect();
+    let message = HttpRequest {
+        method: &request.method,
+        target_uri: &request.target_uri,
+        headers: &headers,
+    };
+    let keyring = state.http_signature_keys.load();
+    let keyid = RefCell::new(None);
+    let verified = http_signature::verify(
+        &message,
+        request.label.as_deref(),
+        state.clock.now(),
+        state.clock_skew_secs,
+        |id| {
+            *keyid.borrow_mut() = id.map(str::to_string);
+            keyring.get(id)
+        },
+    )
+    .and_then(|verified| {
+        check_content_digest(&verified, &headers, request.body.as_deref())?;
+        Ok(verified)
+    })
+    .inspect_err(|err| {
+        let event = match err {
+            HttpSignatureError::UnknownKey => SecurityEvent::KeyNotFound,
+            HttpSignatureError::Invalid => failed(VerifyFailure::Mismatch),
+            HttpSignatureError::Expired { .. } => failed(VerifyFailure::Expired),
+            HttpSignatureError::DigestMismatch => failed(VerifyFailure::DigestMismatch),
+            _ => return,
+        };
+        state
+            .security
+            .record(event, keyid.borrow().as_deref(), None);
+        record_verification(&state, &caller, false);
+    })?;
+    record_verification(&state, &caller, true);
+    let params = verified.params;
+    Ok(Json(HttpVerifyResponse {
+        label: verified.label,
+        components: params.components,
+        keyid: params.keyid,
+        created: params.created,
+        expires: params.expires,
+    }))
+}
+
+/// Checks `body` against the `Content-Digest` header when the signature
+/// covers it.
+fn check_content_digest(
+    verified: &VerifiedSignature,
+    headers: &[(String, String)],
+    body: Option<&str>,
+) -> Result<(), HttpSignatureError> {
+    if let Some(body) = body
+        && verified
+            .params
+            .components
+            .iter()
+            .any(|c| c == CONTENT_DIGEST)
+    {
+        let field = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(CONTENT_DIGEST))
+            .map(|(_, value)| value.as_str())
+            .ok_or(HttpSignatureError::MissingHeader("Content-Digest"))?;
+        verify_content_digest(field, body.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn failed(reason: VerifyFailure) -> SecurityEvent {
+    SecurityEvent::VerifyFailed {
+        scheme: VerifyScheme::HttpSignature,
+        reason,
+    }
+}
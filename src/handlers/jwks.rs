@@ -0,0 +1,37 @@
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+use crate::state::AppState;
+
+/// Public keys of the service's asymmetric signers as a JWK Set, for
+/// verifiers that check signatures themselves. Responses carry an `ETag`
+/// and may be cached for `signing.jwks_max_age_secs`; a matching
+/// `If-None-Match` gets `304 Not Modified` without a body.
+pub async fn jwks(State(state): State<AppState>, request: HeaderMap) -> Response {
+    let jwks = state.jwks.load();
+    let headers = [
+        (
+            header::ETAG,
+            HeaderValue::from_str(&jwks.etag).expect("entity tags are base64url"),
+        ),
+        (
+            header::CACHE_CONTROL,
+            HeaderValue::from_str(&format!("public, max-age={}", state.jwks_max_age_secs))
+                .expect("numbers are header-safe"),
+        ),
+    ];
+    let not_modified = request
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| jwks.matches(value));
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    let content_type = [(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/jwk-set+json"),
+    )];
+    (headers, content_type, jwks.json.clone()).into_response()
+}
@@ -1,2 +1,106 @@
+#[cfg(feature = "admin")]
+pub mod admin;
+#[cfg(feature = "blobs")]
+pub mod blobs;
+#[cfg(feature = "signing")]
+pub mod challenge;
+#[cfg(feature = "encryption")]
 pub mod encryption;
+pub mod extract;
+#[cfg(feature = "signing")]
+pub mod http_signature;
+#[cfg(feature = "asymmetric")]
+pub mod jwks;
+#[cfg(feature = "asymmetric")]
+pub mod oauth;
+#[cfg(feature = "signing")]
 pub mod signing;
+#[cfg(feature = "signing")]
+pub mod sigv4;
+#[cfg(feature = "asymmetric")]
+pub mod transparency;
+#[cfg(feature = "vault")]
+pub mod vault;
+#[cfg(feature = "signing")]
+pub mod webhook;
+
+#[cfg(any(
+    feature = "vault",
+    all(
+        feature = "admin",
+        any(feature = "escrow", feature = "tenancy", feature = "encryption")
+    )
+))]
+use axum::http::HeaderMap;
+
+#[cfg(feature = "signing")]
+use crate::anomaly::Caller;
+#[cfg(any(
+    feature = "vault",
+    all(
+        feature = "admin",
+        any(feature = "escrow", feature = "tenancy", feature = "encryption")
+    )
+))]
+use crate::audit::AuditEvent;
+#[cfg(any(
+    feature = "vault",
+    all(
+        feature = "admin",
+        any(feature = "escrow", feature = "tenancy", feature = "encryption")
+    )
+))]
+use crate::events::StreamEvent;
+#[cfg(any(
+    feature = "signing",
+    feature = "vault",
+    all(
+        feature = "admin",
+        any(feature = "escrow", feature = "tenancy", feature = "encryption")
+    )
+))]
+use crate::state::AppState;
+
+/// Records an access to `resource`, attributed to the caller's
+/// `X-Client-Id`.
+#[cfg(any(
+    feature = "vault",
+    all(
+        feature = "admin",
+        any(feature = "escrow", feature = "tenancy", feature = "encryption")
+    )
+))]
+pub(crate) async fn audit(
+    state: &AppState,
+    headers: &HeaderMap,
+    action: &'static str,
+    resource: &str,
+    success: bool,
+) {
+    let event = AuditEvent {
+        at: state.clock.now(),
+        action,
+        resource: resource.to_string(),
+        client: headers
+            .get(crate::layers::CLIENT_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        success,
+    };
+    #[cfg(feature = "asymmetric")]
+    if let Some(log) = &state.transparency {
+        log.append(&event);
+    }
+    state.events.publish(StreamEvent::Audit(event.clone()));
+    state.audit.record_async(event).await;
+}
+
+/// Counts a verification by `caller` towards its failure rate, when
+/// anomaly detection is enabled. Only signatures that were checked count:
+/// a request rejected before that says nothing about the caller's keys.
+#[cfg(feature = "signing")]
+pub(crate) fn record_verification(state: &AppState, caller: &Caller, verified: bool) {
+    if let Some(detector) = &state.anomaly {
+        detector.record(caller, verified);
+    }
+}
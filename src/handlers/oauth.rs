@@ -0,0 +1,219 @@
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use serde::Deserialize;
+
+use crate::crypto::constant_time;
+use crate::crypto::dpop::DpopError;
+use crate::models::{DpopVerifyRequest, DpopVerifyResponse, OpenIdConfiguration, TokenResponse};
+use crate::oauth::{OAuthError, no_store};
+use crate::state::AppState;
+
+/// Form body of a token request (RFC 6749 §4.4.2 and §6).
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    scope: Option<String>,
+    refresh_token: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// Form body of a revocation request (RFC 7009 §2.1). `token_type_hint`
+/// is ignored, as only refresh tokens can be revoked.
+#[derive(Deserialize)]
+struct RevokeRequest {
+    token: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// Client-credentials and refresh-token grants: authenticates the client
+/// with HTTP Basic or form credentials and answers a signed access token,
+/// with a new refresh token when they are enabled. A `DPoP` proof binds
+/// the access token to the proof's key.
+pub async fn token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, OAuthError> {
+    let issuer = state
+        .token_issuer
+        .as_ref()
+        .expect("/oauth/token is only routed with a token issuer");
+    let request: TokenRequest = serde_urlencoded::from_bytes(&body)
+        .map_err(|err| OAuthError::InvalidRequest(err.to_string()))?;
+    let now = state.clock.now();
+    let requested = request.scope.as_deref();
+    // Before the grant is checked, so a bad proof does not use up the
+    // refresh token it came with.
+    let jkt = match headers.get("dpop") {
+        Some(proof) => {
+            let proof = proof
+                .to_str()
+                .map_err(|_| DpopError::Malformed("not a header string"))?;
+            let url = issuer.token_endpoint();
+            let proof = issuer.check_proof(proof, "POST", &url, None, now).await?;
+            Some(proof.jkt)
+        }
+        None => None,
+    };
+    let (id, kept, scopes) = match request.grant_type.as_str() {
+        "client_credentials" => {
+            let (id, secret) = credentials(&headers, request.client_id, request.client_secret)?;
+            let scopes = issuer.authorize(&id, &secret, requested)?;
+            (id, scopes.clone(), scopes)
+        }
+        "refresh_token" if issuer.issues_refresh_tokens() => {
+            let token = request.refresh_token.ok_or_else(|| {
+                OAuthError::InvalidRequest("missing `refresh_token` parameter".into())
+            })?;
+            let (id, secret) = credentials(&headers, request.client_id, request.client_secret)?;
+            let (kept, scopes) = issuer.redeem(&id, &secret, &token, requested, now).await?;
+            (id, kept, scopes)
+        }
+        _ => return Err(OAuthError::UnsupportedGrantType),
+    };
+    let token_type = if jkt.is_some() { "DPoP" } else { "Bearer" };
+    let signer = state
+        .signers
+        .get(issuer.algorithm())
+        .expect("the private key's signer is always registered");
+    let token = issuer.issue(signer.as_ref(), &id, scopes, jkt, now).await?;
+    // Refresh tokens are rotated: each use hands out the next one.
+    let refresh_token = issuer.refresh_token(&id, kept, now).await?;
+    let body = TokenResponse {
+        access_token: token.token,
+        token_type: token_type.into(),
+        expires_in: token.expires_in,
+        scope: (!token.scopes.is_empty()).then(|| token.scopes.join(" ")),
+        refresh_token,
+    };
+    Ok((no_store(), Json(body)).into_response())
+}
+
+/// Revokes a refresh token of the authenticated client (RFC 7009). Unknown
+/// tokens are answered like revoked ones.
+pub async fn revoke(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, OAuthError> {
+    let issuer = state
+        .token_issuer
+        .as_ref()
+        .expect("/oauth/revoke is only routed with a token issuer");
+    let request: RevokeRequest = serde_urlencoded::from_bytes(&body)
+        .map_err(|err| OAuthError::InvalidRequest(err.to_string()))?;
+    let (id, secret) = credentials(&headers, request.client_id, request.client_secret)?;
+    issuer
+        .revoke(&id, &secret, &request.token, state.clock.now())
+        .await?;
+    Ok((StatusCode::OK, no_store()).into_response())
+}
+
+/// Checks a DPoP-bound access token and the proof sent with it, for
+/// resource servers: the token must be unexpired and issued here, and the
+/// proof made for the request by the key the token is bound to.
+pub async fn verify_dpop(
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<Json<DpopVerifyResponse>, OAuthError> {
+    let issuer = state
+        .token_issuer
+        .as_ref()
+        .expect("/oauth/dpop/verify is only routed with a token issuer");
+    let request: DpopVerifyRequest =
+        serde_json::from_slice(&body).map_err(|err| OAuthError::InvalidRequest(err.to_string()))?;
+    let now = state.clock.now();
+    let signer = state
+        .signers
+        .get(issuer.algorithm())
+        .expect("the private key's signer is always registered");
+    let claims = issuer
+        .verify(signer.as_ref(), &request.access_token, now)
+        .await?;
+    let jkt = claims.cnf.ok_or(DpopError::Unbound)?.jkt;
+    let proof = issuer
+        .check_proof(
+            &request.proof,
+            &request.method,
+            &request.url,
+            Some(&request.access_token),
+            now,
+        )
+        .await?;
+    if !constant_time::eq(proof.jkt.as_bytes(), jkt.as_bytes()) {
+        return Err(DpopError::WrongKey.into());
+    }
+    Ok(Json(DpopVerifyResponse {
+        client_id: claims.client_id,
+        scope: claims.scope,
+        jkt,
+        expires_at: claims.exp,
+    }))
+}
+
+/// OpenID Connect discovery document of the token issuer, cacheable like
+/// the JWK Set it points to.
+pub async fn openid_configuration(
+    State(state): State<AppState>,
+) -> (
+    [(header::HeaderName, HeaderValue); 1],
+    Json<OpenIdConfiguration>,
+) {
+    let issuer = state
+        .token_issuer
+        .as_ref()
+        .expect("discovery is only routed with a token issuer");
+    let cache_control =
+        HeaderValue::from_str(&format!("public, max-age={}", state.jwks_max_age_secs))
+            .expect("numbers are header-safe");
+    (
+        [(header::CACHE_CONTROL, cache_control)],
+        Json(issuer.discovery()),
+    )
+}
+
+/// The client id and secret, from `Authorization: Basic` or the form. A
+/// request may use only one of them (RFC 6749 §2.3).
+fn credentials(
+    headers: &HeaderMap,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+) -> Result<(String, String), OAuthError> {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "));
+    match (basic, client_id, client_secret) {
+        (Some(encoded), None, None) => {
+            let decoded = STANDARD
+                .decode(encoded.trim())
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .ok_or(OAuthError::InvalidClient)?;
+            let (id, secret) = decoded.split_once(':').ok_or(OAuthError::InvalidClient)?;
+            // Both halves are form-encoded before being joined.
+            Ok((form_decode(id)?, form_decode(secret)?))
+        }
+        (None, Some(id), Some(secret)) => Ok((id, secret)),
+        (None, _, _) => Err(OAuthError::InvalidClient),
+        (Some(_), _, _) => Err(OAuthError::InvalidRequest(
+            "client credentials were sent both in Authorization and in the body".into(),
+        )),
+    }
+}
+
+fn form_decode(value: &str) -> Result<String, OAuthError> {
+    let mut pairs: Vec<(String, String)> =
+        serde_urlencoded::from_str(&format!("v={value}")).map_err(|_| OAuthError::InvalidClient)?;
+    match (pairs.pop(), pairs.is_empty()) {
+        (Some((_, decoded)), true) => Ok(decoded),
+        _ => Err(OAuthError::InvalidClient),
+    }
+}
@@ -1,39 +1,571 @@
-use std::sync::LazyLock;
+use std::sync::Arc;
 
 use axum::Json;
-use axum::http::StatusCode;
-use serde_json::{Value, json};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
-use crate::crypto::hmac::HMacSigner;
-use crate::crypto::signer::Signer;
+use crate::anomaly::Caller;
+use crate::crypto::canonical::{
+    FloatPolicy, apply_float_policy, apply_float_policy_array, canonicalize_array,
+    canonicalize_with,
+};
+use crate::crypto::compact::{self, CompactSignature};
+use crate::crypto::envelope::SignatureEnvelope;
+use crate::crypto::hmac::HmacDigest;
+use crate::crypto::prehash::{PrehashError, Prehashed};
+use crate::crypto::registry::SignerRegistry;
+use crate::crypto::signer::{AsyncSigner, SignError};
+use crate::crypto::test_vectors::{self, PublishedKey};
+use crate::deprecation::{LegacyFormat, LegacyNotice};
+use crate::error::Error;
+use crate::handlers::extract::{SignedJson, Signers, ValidQuery};
+use crate::handlers::record_verification;
+use crate::models::{
+    CanonicalizeResponse, ErrorDetail, Payload, SignParams, SignRequest, SignResponse, TestVector,
+    TestVectorKey, TestVectorsResponse, VerifyBatchRequest, VerifyBatchResponse, VerifyRequest,
+    VerifyVerdict,
+};
+use crate::state::AppState;
 
-static SIGNER: LazyLock<HMacSigner> = LazyLock::new(|| {
-    let key = std::env::var("HMAC_SECRET").expect("HMAC_SECRET environment variable must be set");
-    HMacSigner::new(key.into_bytes())
-});
+/// Signs the payload. Callers that prefer `application/octet-stream` (see
+/// [`compact::requested`]) get the signature as a [`CompactSignature`]
+/// rather than JSON.
+pub async fn sign(
+    State(state): State<AppState>,
+    Signers(signers, kid): Signers,
+    headers: HeaderMap,
+    ValidQuery(params): ValidQuery<SignParams>,
+    SignedJson(SignRequest(payload)): SignedJson<SignRequest>,
+) -> Result<Response, Error> {
+    let compact = compact::requested(
+        headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok()),
+    );
+    if params.check_exp {
+        return Err(Error::Validation(
+            "`check_exp` only applies to /verify".into(),
+        ));
+    }
+    if compact && params.ttl.is_some() {
+        return Err(Error::Validation(
+            "the claims of `ttl` cannot be returned in a binary response".into(),
+        ));
+    }
+    let requested = negotiate(&signers, params.alg.as_deref(), params.digest.as_deref())?;
+    let (alg, signer) = select(&signers, requested)?;
+    // Naming an algorithm or digest explicitly implies the caller
+    // understands envelopes, as does asking for a binary response, whose
+    // header names the algorithm. A transition keeps `signature` bare.
+    let bare =
+        requested.is_none() && !compact && (!state.sign_envelope || state.sign_transition.enabled);
+    let notice = bare.then(|| legacy_signature(&state)).transpose()?;
+    // During a transition, requests that name no algorithm are also signed
+    // in an envelope, with a second key operation only if its algorithm
+    // differs.
+    let transition = match requested {
+        None if state.sign_transition.enabled && !compact => {
+            Some(select(&signers, state.sign_transition.alg.as_deref())?)
+        }
+        _ => None,
+    };
+    let mut keys = vec![signer];
+    keys.extend(
+        transition
+            .filter(|(other, _)| *other != alg)
+            .map(|(_, signer)| signer),
+    );
+    let mut signatures = Vec::with_capacity(keys.len());
+    let mut data = None;
+    match prehashed(&params, &payload)? {
+        Some(_) if params.ttl.is_some() => {
+            return Err(Error::Validation(
+                "a digest cannot carry the claims of `ttl`".into(),
+            ));
+        }
+        Some(prehashed) => {
+            let input = prehashed.signing_input();
+            for key in keys {
+                signatures.push(key.sign_bytes(&input).await?);
+            }
+        }
+        None => match check_schema(&state, params.schema.as_deref(), payload)? {
+            Payload::Object(map) => {
+                let map = match params.ttl {
+                    Some(ttl) => {
+                        let map = with_validity(map, ttl, state.clock.now())?;
+                        data = Some(map.clone());
+                        map
+                    }
+                    None => map,
+                };
+                let map = apply_float_policy(&map, state.float_policy)?;
+                for key in keys {
+                    signatures.push(sign_object(key.as_ref(), &map, state.float_policy).await?);
+                }
+            }
+            Payload::Array(_) if params.ttl.is_some() => {
+                return Err(Error::Validation(
+                    "an array cannot carry the claims of `ttl`".into(),
+                ));
+            }
+            Payload::Array(items) => {
+                let items = apply_float_policy_array(&items, state.float_policy)?;
+                for key in keys {
+                    signatures.push(key.sign_array(&items).await?);
+                }
+            }
+        },
+    }
+    let envelope = transition.map(|(transition_alg, _)| {
+        let signature = signatures.last().expect("signed with at least one key");
+        SignatureEnvelope::new(transition_alg, signature).to_string()
+    });
+    let signature = signatures.swap_remove(0);
+    if compact {
+        let raw = compact::raw_signature(alg, &signature)
+            .ok_or_else(|| Error::Crypto(format!("`{alg}` produced an undecodable signature")))?;
+        let body = CompactSignature::new(alg, &kid, &raw)
+            .encode()
+            .map_err(|err| Error::Crypto(err.to_string()))?;
+        return Ok(([(header::CONTENT_TYPE, compact::CONTENT_TYPE)], body).into_response());
+    }
+    let signature = if bare {
+        signature
+    } else {
+        SignatureEnvelope::new(alg, &signature).to_string()
+    };
+    Ok((
+        notice,
+        Json(SignResponse {
+            signature,
+            envelope,
+            data,
+        }),
+    )
+        .into_response())
+}
 
-pub async fn sign(Json(payload): Json<Value>) -> Result<Json<Value>, StatusCode> {
-    match payload {
-        Value::Object(map) => {
-            let signature = SIGNER.sign(&map);
-            Ok(Json(json!({ "signature": signature })))
+/// Adds the `iat` claim, `now`, and the `exp` claim, `ttl` seconds later,
+/// to `map`, which must not carry either already.
+fn with_validity(
+    mut map: Map<String, Value>,
+    ttl: u64,
+    now: u64,
+) -> Result<Map<String, Value>, Error> {
+    if ttl == 0 {
+        return Err(Error::Validation("`ttl` must be at least 1 second".into()));
+    }
+    if let Some(claim) = ["iat", "exp"]
+        .into_iter()
+        .find(|claim| map.contains_key(*claim))
+    {
+        return Err(Error::Validation(format!(
+            "`{claim}` is set by `ttl` and cannot be sent with it"
+        )));
+    }
+    let exp = now
+        .checked_add(ttl)
+        .ok_or_else(|| Error::Validation("`ttl` is out of range".into()))?;
+    map.insert("iat".into(), now.into());
+    map.insert("exp".into(), exp.into());
+    Ok(map)
+}
+
+/// Refuses data whose integer `exp` claim, plus `skew_secs`, is not after
+/// `now`. Other `exp` values are left to the caller, as is every `exp`
+/// unless `/verify` was asked to `check_exp`: only the caller knows whether
+/// `exp` is a validity claim `ttl` added or a field of their own.
+fn check_expiry(exp: Option<u64>, now: u64, skew_secs: u64) -> Result<(), Error> {
+    match exp {
+        Some(exp) if now >= exp.saturating_add(skew_secs) => Err(Error::Expired(format!(
+            "the signed data expired at {exp} (+{skew_secs}s clock skew)"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Counts a bare signature, made or checked, or refuses it past its
+/// sunset.
+fn legacy_signature(state: &AppState) -> Result<LegacyNotice, Error> {
+    state
+        .deprecations
+        .check(LegacyFormat::BareSignature, state.clock.now())
+}
+
+pub async fn verify(
+    State(state): State<AppState>,
+    caller: Caller,
+    Signers(signers, _): Signers,
+    ValidQuery(params): ValidQuery<SignParams>,
+    SignedJson(request): SignedJson<VerifyRequest>,
+) -> Result<(Option<LegacyNotice>, StatusCode), Error> {
+    let result = verify_pair(&state, &signers, &params, request).await;
+    record_outcome(&state, &caller, &result);
+    Ok((result?, StatusCode::NO_CONTENT))
+}
+
+/// Verifies every pair as `/verify` would, up to
+/// `signing.batch.concurrency` at a time, and reports each verdict rather
+/// than failing on the first bad pair. Query parameters apply to all pairs.
+pub async fn verify_batch(
+    State(state): State<AppState>,
+    caller: Caller,
+    Signers(signers, _): Signers,
+    ValidQuery(params): ValidQuery<SignParams>,
+    SignedJson(VerifyBatchRequest { items }): SignedJson<VerifyBatchRequest>,
+) -> Result<(Option<LegacyNotice>, Json<VerifyBatchResponse>), Error> {
+    let max_items = state.verify_batch.max_items;
+    if items.len() > max_items {
+        return Err(Error::LimitExceeded(format!(
+            "batches are limited to {max_items} items (`signing.batch.max_items`)"
+        )));
+    }
+    let permits = Arc::new(Semaphore::new(state.verify_batch.concurrency));
+    let context = Arc::new((state, signers, params));
+    let mut tasks = JoinSet::new();
+    let count = items.len();
+    for (index, item) in items.into_iter().enumerate() {
+        let permits = permits.clone();
+        let context = context.clone();
+        tasks.spawn(async move {
+            let _permit = permits
+                .acquire_owned()
+                .await
+                .expect("the semaphore is never closed");
+            let (state, signers, params) = &*context;
+            (index, verify_pair(state, signers, params, item).await)
+        });
+    }
+    let mut results = vec![None; count];
+    let mut notice = None;
+    while let Some(joined) = tasks.join_next().await {
+        let (index, mut result) =
+            joined.map_err(|err| Error::Crypto(format!("verification task failed: {err}")))?;
+        record_outcome(&context.0, &caller, &result);
+        if let Ok(legacy) = &mut result {
+            notice = notice.or(legacy.take());
+        }
+        results[index] = Some(verdict(result));
+    }
+    let results: Vec<VerifyVerdict> = results
+        .into_iter()
+        .map(|verdict| verdict.expect("every item was verified"))
+        .collect();
+    let valid = results.iter().filter(|verdict| verdict.valid).count();
+    Ok((
+        notice,
+        Json(VerifyBatchResponse {
+            valid,
+            invalid: results.len() - valid,
+            results,
+        }),
+    ))
+}
+
+/// Counts a signature that was checked, valid or not, towards the caller's
+/// failure rate.
+fn record_outcome<T>(state: &AppState, caller: &Caller, result: &Result<T, Error>) {
+    match result {
+        Ok(_) => record_verification(state, caller, true),
+        Err(Error::InvalidSignature) => record_verification(state, caller, false),
+        Err(_) => {}
+    }
+}
+
+/// A batch item's result, with the error `/verify` would have answered.
+fn verdict<T>(result: Result<T, Error>) -> VerifyVerdict {
+    match result {
+        Ok(_) => VerifyVerdict {
+            valid: true,
+            error: None,
+        },
+        Err(err) => {
+            if err.status() >= 500 {
+                tracing::error!(code = err.code(), "{err}");
+            }
+            VerifyVerdict {
+                valid: false,
+                error: Some(ErrorDetail {
+                    code: err.code().to_string(),
+                    message: err.public_message().into_owned(),
+                }),
+            }
         }
-        _ => Err(StatusCode::BAD_REQUEST),
     }
 }
 
-pub async fn verify(Json(payload): Json<Value>) -> StatusCode {
-    let signature = payload.get("signature").and_then(|s| s.as_str());
-    let data = payload.get("data");
+/// Checks one `{signature, data}` pair; `Ok` means it verified, with the
+/// notice of a bare signature.
+async fn verify_pair(
+    state: &AppState,
+    signers: &SignerRegistry,
+    params: &SignParams,
+    request: VerifyRequest,
+) -> Result<Option<LegacyNotice>, Error> {
+    if params.ttl.is_some() {
+        return Err(Error::Validation("`ttl` only applies to /sign".into()));
+    }
+    let requested = negotiate(signers, params.alg.as_deref(), params.digest.as_deref())?;
+    let envelope = SignatureEnvelope::parse(&request.signature);
+    let notice = match envelope {
+        Some(_) => None,
+        None => Some(legacy_signature(state)?),
+    };
+    let (alg, signature) = match envelope {
+        Some(envelope) => {
+            if requested.is_some_and(|alg| alg != envelope.alg) {
+                return Err(Error::Validation(format!(
+                    "alg does not match the signature envelope ({})",
+                    envelope.alg
+                )));
+            }
+            (Some(envelope.alg), envelope.signature)
+        }
+        None => (requested, request.signature.as_str()),
+    };
 
-    match (signature, data) {
-        (Some(sig), Some(Value::Object(map))) => {
-            if SIGNER.verify(map, sig) {
-                StatusCode::NO_CONTENT
-            } else {
-                StatusCode::BAD_REQUEST
+    let (_, signer) = select(signers, alg)?;
+    let mut exp = None;
+    let valid = match prehashed(params, &request.data)? {
+        Some(prehashed) => {
+            let input = prehashed.signing_input();
+            signer.verify_bytes(&input, signature).await?
+        }
+        None => match check_schema(state, params.schema.as_deref(), request.data)? {
+            Payload::Object(map) => {
+                if params.check_exp {
+                    exp = map.get("exp").and_then(Value::as_u64);
+                }
+                let map = apply_float_policy(&map, state.float_policy)?;
+                verify_object(signer.as_ref(), &map, signature, state.float_policy).await?
+            }
+            Payload::Array(items) => {
+                let items = apply_float_policy_array(&items, state.float_policy)?;
+                signer.verify_array(&items, signature).await?
             }
+        },
+    };
+    if !valid {
+        return Err(Error::InvalidSignature);
+    }
+    check_expiry(exp, state.clock.now(), state.clock_skew_secs)?;
+    Ok(notice)
+}
+
+/// Signs `map` in the form `policy` signs: under `canonical`, the RFC 8785
+/// form of its numbers, which the signers do not build themselves.
+async fn sign_object(
+    signer: &dyn AsyncSigner,
+    map: &Map<String, Value>,
+    policy: FloatPolicy,
+) -> Result<String, SignError> {
+    match policy {
+        FloatPolicy::Canonical => {
+            signer
+                .sign_bytes(canonicalize_with(map, policy).as_bytes())
+                .await
+        }
+        _ => signer.sign(map).await,
+    }
+}
+
+/// [`sign_object`] for verification.
+async fn verify_object(
+    signer: &dyn AsyncSigner,
+    map: &Map<String, Value>,
+    signature: &str,
+    policy: FloatPolicy,
+) -> Result<bool, SignError> {
+    match policy {
+        FloatPolicy::Canonical => {
+            signer
+                .verify_bytes(canonicalize_with(map, policy).as_bytes(), signature)
+                .await
         }
-        _ => StatusCode::BAD_REQUEST,
+        _ => signer.verify(map, signature).await,
+    }
+}
+
+/// The digest sent in place of the payload, when `prehashed` says one was.
+/// The payload itself is not sent, so it cannot be checked against a
+/// schema.
+fn prehashed(params: &SignParams, payload: &Payload) -> Result<Option<Prehashed>, Error> {
+    if !params.prehashed {
+        return Ok(None);
+    }
+    if params.schema.is_some() {
+        return Err(Error::Validation(
+            "a digest cannot be checked against a schema".into(),
+        ));
+    }
+    match payload {
+        Payload::Object(map) => Ok(Some(Prehashed::parse(map)?)),
+        Payload::Array(_) => Err(PrehashError::NotADigest.into()),
     }
 }
+
+/// The bytes `/sign` signs for the same body, after the float policy, and
+/// their SHA-256, so clients in other languages can check their own
+/// canonicalization against the service's.
+pub async fn canonicalize(
+    State(state): State<AppState>,
+    SignedJson(SignRequest(payload)): SignedJson<SignRequest>,
+) -> Result<Json<CanonicalizeResponse>, Error> {
+    let canonical = match payload {
+        Payload::Object(map) => {
+            let map = apply_float_policy(&map, state.float_policy)?;
+            canonicalize_with(&map, state.float_policy)
+        }
+        Payload::Array(items) => {
+            let items = apply_float_policy_array(&items, state.float_policy)?;
+            canonicalize_array(&items)
+        }
+    };
+    let sha256 = Sha256::digest(canonical.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(Json(CanonicalizeResponse { canonical, sha256 }))
+}
+
+/// Every published test payload in canonical form, signed with the test key
+/// of each algorithm built in. Payloads the float policy refuses are left
+/// out.
+pub async fn test_vectors(State(state): State<AppState>) -> Json<TestVectorsResponse> {
+    let canonical: Vec<_> = test_vectors::payloads()
+        .into_iter()
+        .filter_map(|payload| {
+            let canonical = match &payload {
+                Value::Object(map) => {
+                    let map = apply_float_policy(map, state.float_policy).ok()?;
+                    canonicalize_with(&map, state.float_policy)
+                }
+                Value::Array(items) => {
+                    let items = apply_float_policy_array(items, state.float_policy).ok()?;
+                    canonicalize_array(&items)
+                }
+                _ => unreachable!("test payloads are objects or arrays"),
+            };
+            Some((payload, canonical))
+        })
+        .collect();
+    let keys = test_vectors::keys();
+    let vectors = keys
+        .iter()
+        .flat_map(|key| {
+            canonical.iter().map(|(payload, canonical)| TestVector {
+                algorithm: key.algorithm.to_string(),
+                payload: payload.clone(),
+                canonical: canonical.clone(),
+                signature: SignatureEnvelope::new(
+                    key.algorithm,
+                    &key.signer.sign_bytes(canonical.as_bytes()),
+                )
+                .to_string(),
+            })
+        })
+        .collect();
+    let keys = keys
+        .iter()
+        .map(|key| {
+            let (secret_hex, public_key) = match &key.published {
+                PublishedKey::Secret(secret) => (
+                    Some(secret.iter().map(|byte| format!("{byte:02x}")).collect()),
+                    None,
+                ),
+                PublishedKey::PublicKey(pem) => (None, Some(pem.clone())),
+            };
+            TestVectorKey {
+                algorithm: key.algorithm.to_string(),
+                secret_hex,
+                public_key,
+            }
+        })
+        .collect();
+    Json(TestVectorsResponse {
+        float_policy: state.float_policy.as_str().to_string(),
+        keys,
+        vectors,
+    })
+}
+
+/// Validates `payload` against the schema named by the request, if any,
+/// and hands it back. Runs before the float policy, which may rewrite
+/// numbers as strings.
+#[cfg_attr(not(feature = "json-schema"), allow(unused_variables))]
+fn check_schema(
+    state: &AppState,
+    schema: Option<&str>,
+    payload: Payload,
+) -> Result<Payload, Error> {
+    let Some(name) = schema else {
+        return Ok(payload);
+    };
+    #[cfg(feature = "json-schema")]
+    {
+        let payload = Value::from(payload);
+        state.schemas.validate(name, &payload)?;
+        match payload {
+            Value::Object(map) => Ok(Payload::Object(map)),
+            Value::Array(items) => Ok(Payload::Array(items)),
+            _ => unreachable!("wrapped a payload above"),
+        }
+    }
+    #[cfg(not(feature = "json-schema"))]
+    Err(Error::Validation(format!(
+        "cannot validate against schema `{name}`: schema support is not enabled"
+    )))
+}
+
+/// The algorithm a request names: `alg`, with its digest replaced by
+/// `digest` if one is given. A digest alone applies to the default
+/// algorithm, which must then be an HMAC.
+fn negotiate<'a>(
+    signers: &'a SignerRegistry,
+    alg: Option<&'a str>,
+    digest: Option<&str>,
+) -> Result<Option<&'a str>, Error> {
+    let Some(name) = digest else {
+        return Ok(alg);
+    };
+    let digest = HmacDigest::from_name(name).ok_or_else(|| {
+        Error::Validation(format!(
+            "unsupported digest `{name}` (available: sha256, sha384, sha512)"
+        ))
+    })?;
+    let base = alg.unwrap_or(signers.default_alg());
+    if !HmacDigest::ALL.iter().any(|hmac| hmac.algorithm() == base) {
+        return Err(Error::Validation(format!(
+            "`{base}` has a fixed digest; `digest` only applies to HMAC algorithms"
+        )));
+    }
+    if alg.is_some_and(|alg| alg != digest.algorithm()) {
+        return Err(Error::Validation(format!(
+            "alg `{base}` does not use digest `{name}`"
+        )));
+    }
+    Ok(Some(digest.algorithm()))
+}
+
+/// Picks the signer for `alg`, or the default one when no algorithm is
+/// requested.
+fn select<'a>(
+    signers: &'a SignerRegistry,
+    alg: Option<&'a str>,
+) -> Result<(&'a str, &'a Arc<dyn AsyncSigner>), Error> {
+    let alg = alg.unwrap_or(signers.default_alg());
+    let signer = signers.get(alg).ok_or_else(|| {
+        Error::Validation(format!(
+            "unknown signing algorithm `{alg}` (available: {})",
+            signers.algorithms().join(", ")
+        ))
+    })?;
+    Ok((alg, signer))
+}
@@ -0,0 +1,58 @@
+use axum::Json;
+use axum::extract::State;
+
+use crate::anomaly::Caller;
+use crate::crypto::sigv4::{self, SigV4Error, SigV4Request};
+use crate::error::Error;
+use crate::handlers::extract::ValidJson;
+use crate::handlers::record_verification;
+use crate::models::{SigV4VerifyRequest, SigV4VerifyResponse};
+use crate::security::{SecurityEvent, VerifyFailure, VerifyScheme};
+use crate::state::AppState;
+
+pub async fn verify(
+    State(state): State<AppState>,
+    caller: Caller,
+    ValidJson(request): ValidJson<SigV4VerifyRequest>,
+) -> Result<Json<SigV4VerifyResponse>, Error> {
+    let headers: Vec<(String, String)> = request.headers.into_iter().collect();
+    let message = SigV4Request {
+        method: &request.method,
+        uri: &request.uri,
+        headers: &headers,
+        body: request.body.as_bytes(),
+    };
+    let verified = sigv4::verify(
+        &state.sigv4_credentials,
+        &message,
+        state.clock.now(),
+        state.sigv4_max_skew_secs,
+    )
+    .inspect_err(|err| {
+        let event = match err {
+            SigV4Error::UnknownAccessKey => SecurityEvent::KeyNotFound,
+            SigV4Error::Invalid => failed(VerifyFailure::Mismatch),
+            SigV4Error::Skewed { .. } => failed(VerifyFailure::Expired),
+            SigV4Error::PayloadMismatch => failed(VerifyFailure::DigestMismatch),
+            _ => return,
+        };
+        let access_key_id = sigv4::claimed_access_key_id(&message);
+        state.security.record(event, access_key_id.as_deref(), None);
+        record_verification(&state, &caller, false);
+    })?;
+    record_verification(&state, &caller, true);
+    Ok(Json(SigV4VerifyResponse {
+        access_key_id: verified.access_key_id,
+        date: verified.date,
+        region: verified.region,
+        service: verified.service,
+        signed_headers: verified.signed_headers,
+    }))
+}
+
+fn failed(reason: VerifyFailure) -> SecurityEvent {
+    SecurityEvent::VerifyFailed {
+        scheme: VerifyScheme::SigV4,
+        reason,
+    }
+}
@@ -0,0 +1,41 @@
+use axum::Json;
+use axum::extract::State;
+
+use crate::error::Error;
+use crate::handlers::extract::ValidQuery;
+use crate::models::{
+    ConsistencyParams, ConsistencyProofResponse, TransparencyRootsParams, TransparencyRootsResponse,
+};
+use crate::state::AppState;
+use crate::transparency::{TransparencyLog, hex};
+
+/// The sealed roots of the audit log, oldest first; `after=<size>` keeps
+/// only those covering more events, so monitors can poll for new ones.
+pub async fn roots(
+    State(state): State<AppState>,
+    ValidQuery(params): ValidQuery<TransparencyRootsParams>,
+) -> Result<Json<TransparencyRootsResponse>, Error> {
+    let roots = log(&state)?.roots(params.after);
+    Ok(Json(TransparencyRootsResponse { roots }))
+}
+
+/// The proof that the root sealed at `first` events covers a prefix of the
+/// events under the root sealed at `second`.
+pub async fn consistency(
+    State(state): State<AppState>,
+    ValidQuery(params): ValidQuery<ConsistencyParams>,
+) -> Result<Json<ConsistencyProofResponse>, Error> {
+    let proof = log(&state)?.consistency_proof(params.first, params.second)?;
+    Ok(Json(ConsistencyProofResponse {
+        first: params.first,
+        second: params.second,
+        proof: proof.iter().map(hex).collect(),
+    }))
+}
+
+fn log(state: &AppState) -> Result<&TransparencyLog, Error> {
+    state
+        .transparency
+        .as_deref()
+        .ok_or_else(|| Error::NotFound("the transparency log is disabled".into()))
+}
@@ -0,0 +1,83 @@
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+
+use crate::error::Error;
+use crate::handlers::audit;
+use crate::handlers::extract::ValidJson;
+use crate::models::{VaultEntry, VaultPutRequest, VaultSecret};
+use crate::state::AppState;
+use crate::vault::{VaultMetadata, delete_secret, get_secret, put_secret};
+
+/// Creates or replaces an entry. Answers `201 Created` for a new entry.
+pub async fn put(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    ValidJson(request): ValidJson<VaultPutRequest>,
+) -> Result<(StatusCode, Json<VaultEntry>), Error> {
+    let result = async {
+        let size = serde_json::to_vec(&request.value)
+            .map_err(|err| Error::Validation(err.to_string()))?
+            .len();
+        if size > state.vault_max_value_bytes {
+            return Err(Error::LimitExceeded(format!(
+                "vault values are limited to {} bytes",
+                state.vault_max_value_bytes
+            )));
+        }
+        let metadata = put_secret(
+            state.vault.as_ref(),
+            state.encryptor.as_ref(),
+            &name,
+            &request.value,
+            request.labels,
+            state.clock.now(),
+        )
+        .await?;
+        let status = if metadata.version == 1 {
+            StatusCode::CREATED
+        } else {
+            StatusCode::OK
+        };
+        Ok((status, Json(entry(&name, metadata))))
+    }
+    .await;
+    audit(&state, &headers, "vault.write", &name, result.is_ok()).await;
+    result
+}
+
+pub async fn get(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<VaultSecret>, Error> {
+    let result = get_secret(state.vault.as_ref(), state.encryptor.as_ref(), &name).await;
+    audit(&state, &headers, "vault.read", &name, result.is_ok()).await;
+    let (value, metadata) = result?;
+    Ok(Json(VaultSecret {
+        value,
+        entry: entry(&name, metadata),
+    }))
+}
+
+pub async fn delete(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, Error> {
+    let result = delete_secret(state.vault.as_ref(), &name).await;
+    audit(&state, &headers, "vault.delete", &name, result.is_ok()).await;
+    result?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn entry(name: &str, metadata: VaultMetadata) -> VaultEntry {
+    VaultEntry {
+        name: name.to_string(),
+        version: metadata.version,
+        created_at: metadata.created_at,
+        updated_at: metadata.updated_at,
+        labels: metadata.labels,
+    }
+}
@@ -0,0 +1,71 @@
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, header};
+use serde_json::{Map, Value};
+
+use crate::anomaly::Caller;
+use crate::crypto::webhook::{Provider, WebhookError};
+use crate::error::Error;
+use crate::handlers::record_verification;
+use crate::models::WebhookVerifyResponse;
+use crate::security::{SecurityEvent, VerifyFailure, VerifyScheme};
+use crate::state::AppState;
+
+/// Verifies a raw webhook delivery forwarded with its original headers and
+/// body, and returns the parsed payload.
+pub async fn verify(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    caller: Caller,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<WebhookVerifyResponse>, Error> {
+    let provider: Provider = provider.parse()?;
+    let fields: Vec<(String, String)> = headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    state
+        .webhooks
+        .verify(provider, &fields, &body, state.clock.now())
+        .inspect_err(|err| {
+            let reason = match err {
+                WebhookError::Invalid => VerifyFailure::Mismatch,
+                WebhookError::Expired { .. } => VerifyFailure::Expired,
+                _ => return,
+            };
+            let event = SecurityEvent::VerifyFailed {
+                scheme: VerifyScheme::Webhook,
+                reason,
+            };
+            state
+                .security
+                .record(event, Some(&provider.to_string()), None);
+            record_verification(&state, &caller, false);
+        })?;
+    record_verification(&state, &caller, true);
+    Ok(Json(WebhookVerifyResponse {
+        provider: provider.to_string(),
+        payload: parse_payload(&headers, &body)?,
+    }))
+}
+
+fn parse_payload(headers: &HeaderMap, body: &[u8]) -> Result<Value, Error> {
+    let form = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+    if form {
+        let pairs: Vec<(String, String)> = serde_urlencoded::from_bytes(body)
+            .map_err(|err| Error::Validation(format!("invalid form payload: {err}")))?;
+        let map: Map<String, Value> = pairs
+            .into_iter()
+            .map(|(name, value)| (name, Value::String(value)))
+            .collect();
+        Ok(Value::Object(map))
+    } else {
+        serde_json::from_slice(body)
+            .map_err(|err| Error::Validation(format!("invalid JSON payload: {err}")))
+    }
+}
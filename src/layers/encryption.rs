@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::Json;
+use axum::body::{Body, to_bytes};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Request, Response, header};
+use axum::response::IntoResponse;
+use tower::{Layer, Service};
+use x25519_dalek::PublicKey;
+
+use super::{BoxFuture, CLIENT_ID_HEADER, DEFAULT_MAX_BODY_BYTES};
+use crate::blocking::BlockingPool;
+use crate::crypto::seal::{SEAL_ALGORITHM, parse_public_key, seal};
+use crate::error::Error;
+
+/// Request header carrying a base64 X25519 public key to seal the response to.
+pub const RESPONSE_KEY_HEADER: HeaderName = HeaderName::from_static("x-response-key");
+
+/// Response header set to the sealing algorithm on encrypted responses.
+pub const CONTENT_ENCRYPTION_HEADER: HeaderName = HeaderName::from_static("x-content-encryption");
+
+/// Seals response bodies to the caller's X25519 public key, taken from the
+/// `X-Response-Key` header or looked up from `X-Client-Id`. The body is
+/// replaced by a JSON [`Sealed`](crate::crypto::seal::Sealed) object and the
+/// status code is kept. Requests naming no key pass through unless
+/// [`required`](Self::required) is set.
+#[derive(Clone)]
+pub struct EncryptResponseLayer {
+    clients: Arc<HashMap<String, PublicKey>>,
+    required: bool,
+    max_body_bytes: usize,
+    offload: Option<(BlockingPool, usize)>,
+}
+
+impl Default for EncryptResponseLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EncryptResponseLayer {
+    pub fn new() -> Self {
+        Self {
+            clients: Arc::default(),
+            required: false,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            offload: None,
+        }
+    }
+
+    /// Registers the public key used for requests sent with
+    /// `X-Client-Id: <client_id>`.
+    pub fn client_key(mut self, client_id: impl Into<String>, key: PublicKey) -> Self {
+        Arc::make_mut(&mut self.clients).insert(client_id.into(), key);
+        self
+    }
+
+    /// Rejects requests that name no key with `400 Bad Request` instead of
+    /// answering in plaintext.
+    pub fn required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+
+    /// Maximum response size buffered for sealing; larger responses are
+    /// replaced with `500 Internal Server Error` rather than sent in
+    /// plaintext.
+    pub fn max_body_bytes(mut self, limit: usize) -> Self {
+        self.max_body_bytes = limit;
+        self
+    }
+
+    /// Seals bodies of at least `min_bytes` on `pool` instead of on the
+    /// async worker handling the request.
+    pub fn blocking_pool(mut self, pool: BlockingPool, min_bytes: usize) -> Self {
+        self.offload = Some((pool, min_bytes));
+        self
+    }
+
+    fn recipient(&self, headers: &HeaderMap) -> Result<Option<PublicKey>, Error> {
+        if let Some(value) = headers.get(&RESPONSE_KEY_HEADER) {
+            let key = value
+                .to_str()
+                .ok()
+                .and_then(|v| parse_public_key(v).ok())
+                .ok_or_else(|| {
+                    Error::Validation(format!("{RESPONSE_KEY_HEADER} is not an X25519 public key"))
+                })?;
+            return Ok(Some(key));
+        }
+        if let Some(value) = headers.get(&CLIENT_ID_HEADER) {
+            let client = value.to_str().unwrap_or_default();
+            let key = self.clients.get(client).ok_or_else(|| {
+                Error::Validation(format!("no response key registered for client `{client}`"))
+            })?;
+            return Ok(Some(*key));
+        }
+        if self.required {
+            return Err(Error::Validation(format!(
+                "responses are encrypted: send {RESPONSE_KEY_HEADER} or {CLIENT_ID_HEADER}"
+            )));
+        }
+        Ok(None)
+    }
+}
+
+impl<S> Layer<S> for EncryptResponseLayer {
+    type Service = EncryptResponse<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EncryptResponse {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EncryptResponse<S> {
+    inner: S,
+    layer: EncryptResponseLayer,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for EncryptResponse<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let recipient = match layer.recipient(request.headers()) {
+                Ok(Some(recipient)) => recipient,
+                Ok(None) => return inner.call(request).await,
+                Err(err) => return Ok(err.into_response()),
+            };
+            let response = inner.call(request).await?;
+            let (parts, body) = response.into_parts();
+            let bytes = match to_bytes(body, layer.max_body_bytes).await {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    let message = format!("failed to buffer response for sealing: {err}");
+                    return Ok(Error::Crypto(message).into_response());
+                }
+            };
+            let sealed = match &layer.offload {
+                Some((pool, min_bytes)) if bytes.len() >= *min_bytes => {
+                    match pool.run(move || seal(&recipient, &bytes)).await {
+                        Ok(sealed) => sealed,
+                        Err(err) => return Ok(Error::from(err).into_response()),
+                    }
+                }
+                _ => seal(&recipient, &bytes),
+            };
+            let sealed = match sealed {
+                Ok(sealed) => sealed,
+                Err(err) => return Ok(Error::Validation(err.to_string()).into_response()),
+            };
+            let mut response = (parts.status, Json(sealed)).into_response();
+            let headers = response.headers_mut();
+            for (name, value) in parts.headers.iter() {
+                if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+                    headers.append(name, value.clone());
+                }
+            }
+            headers.insert(
+                CONTENT_ENCRYPTION_HEADER,
+                HeaderValue::from_static(SEAL_ALGORITHM),
+            );
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::body::{Body, to_bytes};
+use axum::http::request::Parts;
+use axum::http::{Request, Response, header};
+use axum::response::IntoResponse;
+use tower::{Layer, Service};
+
+use super::{BoxFuture, DEFAULT_MAX_BODY_BYTES};
+use crate::clock::Clock;
+use crate::crypto::http_signature::{
+    HttpRequest, HttpSignatureError, Keyring, VerifiedSignature, verify, verify_content_digest,
+};
+use crate::error::Error;
+use crate::replay::ReplayStore;
+
+const CONTENT_DIGEST: &str = "content-digest";
+
+/// Rejects requests without a valid RFC 9421 HTTP Message Signature with
+/// `401 Unauthorized`. When the signature covers `Content-Digest`, the digest
+/// is also checked against the body. The [`VerifiedSignature`] is added to
+/// the request extensions for handlers that need the `keyid`.
+#[derive(Clone)]
+pub struct VerifyHttpSignatureLayer {
+    keys: Arc<Keyring>,
+    scheme: &'static str,
+    required_components: Vec<String>,
+    max_body_bytes: usize,
+    replay: Option<(Arc<dyn ReplayStore>, Duration)>,
+    max_age_secs: Option<u64>,
+    skew_secs: u64,
+    clock: Arc<dyn Clock>,
+}
+
+impl VerifyHttpSignatureLayer {
+    pub fn new(keys: Arc<Keyring>) -> Self {
+        Self {
+            keys,
+            scheme: "http",
+            required_components: Vec::new(),
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            replay: None,
+            max_age_secs: None,
+            skew_secs: 0,
+            clock: crate::clock::system(),
+        }
+    }
+
+    /// The clock `expires` is checked against. Defaults to the system
+    /// clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Accepts signatures up to `secs` past their `expires`, for callers
+    /// whose clock drifts from this one.
+    pub fn clock_skew_secs(mut self, secs: u64) -> Self {
+        self.skew_secs = secs;
+        self
+    }
+
+    /// Scheme used to rebuild `@target-uri` for origin-form requests.
+    /// Defaults to `http`; set `https` behind a TLS-terminating proxy.
+    pub fn scheme(mut self, scheme: &'static str) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Components every accepted signature must cover, e.g. `@method` and
+    /// `content-digest`.
+    pub fn required_components<I, C>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = C>,
+        C: Into<String>,
+    {
+        self.required_components = components.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Maximum body size buffered for digest checks; larger bodies are
+    /// rejected with `413 Payload Too Large`.
+    pub fn max_body_bytes(mut self, limit: usize) -> Self {
+        self.max_body_bytes = limit;
+        self
+    }
+
+    /// Requires every signature to carry a `nonce` and rejects a nonce
+    /// already seen under the same `keyid` within `ttl`, which should cover
+    /// how long signatures stay acceptable. Use a shared store such as
+    /// [`RedisReplayStore`](crate::replay::RedisReplayStore) when several
+    /// replicas verify the same callers.
+    pub fn replay_store(mut self, store: Arc<dyn ReplayStore>, ttl: Duration) -> Self {
+        self.replay = Some((store, ttl));
+        self
+    }
+
+    /// Requires every signature to carry a `created` at most `secs` in the
+    /// past and not in the future, give or take the clock skew. With a
+    /// [`replay_store`](Self::replay_store) whose TTL covers the window, a
+    /// signature is then accepted once at most.
+    pub fn max_age_secs(mut self, secs: u64) -> Self {
+        self.max_age_secs = Some(secs);
+        self
+    }
+
+    /// Records the nonce of `verified`, failing if it was seen before.
+    async fn check_replay(&self, verified: &VerifiedSignature) -> Result<(), Error> {
+        let Some((store, ttl)) = &self.replay else {
+            return Ok(());
+        };
+        let params = &verified.params;
+        let nonce = params.nonce.as_deref().ok_or_else(|| {
+            Error::Unauthorized(
+                HttpSignatureError::Malformed("signature must carry a `nonce`".into()).to_string(),
+            )
+        })?;
+        let key = format!(
+            "http-signature:{}:{nonce}",
+            params.keyid.as_deref().unwrap_or_default()
+        );
+        if store.check_and_set(&key, *ttl).await? {
+            Ok(())
+        } else {
+            Err(Error::Unauthorized(
+                "signature nonce was already used".into(),
+            ))
+        }
+    }
+
+    fn verify(&self, parts: &Parts, body: &[u8]) -> Result<VerifiedSignature, HttpSignatureError> {
+        let headers: Vec<(String, String)> = parts
+            .headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let target_uri = if parts.uri.scheme().is_some() {
+            parts.uri.to_string()
+        } else {
+            let host = parts
+                .headers
+                .get(header::HOST)
+                .and_then(|v| v.to_str().ok())
+                .ok_or(HttpSignatureError::MissingHeader("Host"))?;
+            let path = parts.uri.path_and_query().map_or("/", |pq| pq.as_str());
+            format!("{}://{host}{path}", self.scheme)
+        };
+        let request = HttpRequest {
+            method: parts.method.as_str(),
+            target_uri: &target_uri,
+            headers: &headers,
+        };
+        let verified = verify(&request, None, self.clock.now(), self.skew_secs, |id| {
+            self.keys.get(id)
+        })?;
+        if let Some(max_age_secs) = self.max_age_secs {
+            let created = verified.params.created.ok_or_else(|| {
+                HttpSignatureError::Malformed("signature must carry `created`".into())
+            })?;
+            let now = self.clock.now();
+            if created > now.saturating_add(self.skew_secs)
+                || created
+                    .saturating_add(max_age_secs)
+                    .saturating_add(self.skew_secs)
+                    < now
+            {
+                return Err(HttpSignatureError::Stale {
+                    created,
+                    max_age_secs,
+                    skew_secs: self.skew_secs,
+                });
+            }
+        }
+        let covers = |component: &str| verified.params.components.iter().any(|c| c == component);
+        if let Some(missing) = self.required_components.iter().find(|c| !covers(c)) {
+            return Err(HttpSignatureError::Malformed(format!(
+                "signature must cover `{missing}`"
+            )));
+        }
+        if covers(CONTENT_DIGEST) {
+            let field = parts
+                .headers
+                .get(CONTENT_DIGEST)
+                .and_then(|v| v.to_str().ok())
+                .ok_or(HttpSignatureError::MissingHeader("Content-Digest"))?;
+            verify_content_digest(field, body)?;
+        }
+        Ok(verified)
+    }
+}
+
+impl<S> Layer<S> for VerifyHttpSignatureLayer {
+    type Service = VerifyHttpSignature<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifyHttpSignature {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct VerifyHttpSignature<S> {
+    inner: S,
+    layer: VerifyHttpSignatureLayer,
+}
+
+impl<S> Service<Request<Body>> for VerifyHttpSignature<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let (mut parts, body) = request.into_parts();
+            let Ok(bytes) = to_bytes(body, layer.max_body_bytes).await else {
+                return Ok(Error::PayloadTooLarge.into_response());
+            };
+            let verified = match layer.verify(&parts, &bytes) {
+                Ok(verified) => verified,
+                Err(err) => return Ok(Error::Unauthorized(err.to_string()).into_response()),
+            };
+            if let Err(err) = layer.check_replay(&verified).await {
+                return Ok(err.into_response());
+            }
+            parts.extensions.insert(verified);
+            inner
+                .call(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        })
+    }
+}
@@ -0,0 +1,66 @@
+//! Reusable `tower` layers that let any axum service verify signed request
+//! bodies, sign its response bodies with an [`AsyncSigner`] (any
+//! [`Signer`](crate::crypto::signer::Signer) qualifies), verify RFC 9421 HTTP
+//! Message Signatures or SigV4-style `Authorization` headers and seal response
+//! bodies to a per-client key, without proxying calls through this service.
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use take_home::crypto::{hmac::HMacSigner, signer::AsyncSigner};
+//! # use take_home::layers::{SignResponseLayer, VerifySignatureLayer};
+//! let signer: Arc<dyn AsyncSigner> = Arc::new(HMacSigner::new(b"secret".to_vec()));
+//! let app: axum::Router = axum::Router::new()
+//!     .layer(VerifySignatureLayer::new(signer.clone()))
+//!     .layer(SignResponseLayer::new(signer));
+//! ```
+//!
+//! [`AsyncSigner`]: crate::crypto::signer::AsyncSigner
+
+use std::future::Future;
+use std::pin::Pin;
+
+use axum::http::HeaderName;
+
+#[cfg(feature = "response-encryption")]
+mod encryption;
+#[cfg(feature = "signing")]
+mod http_signature;
+#[cfg(feature = "signing")]
+mod signature;
+#[cfg(feature = "signing")]
+mod sigv4;
+
+#[cfg(feature = "response-encryption")]
+pub use encryption::{
+    CONTENT_ENCRYPTION_HEADER, EncryptResponse, EncryptResponseLayer, RESPONSE_KEY_HEADER,
+};
+#[cfg(feature = "signing")]
+pub use http_signature::{VerifyHttpSignature, VerifyHttpSignatureLayer};
+#[cfg(feature = "signing")]
+pub use signature::{
+    KEY_ID_HEADER, RESPONSE_DOMAIN_TAG, SIGNATURE_HEADER, SignResponse, SignResponseLayer,
+    VerifySignature, VerifySignatureLayer, response_signing_input,
+};
+#[cfg(feature = "signing")]
+pub use sigv4::{VerifySigV4, VerifySigV4Layer};
+
+/// Request header naming the calling client: it selects a registered key
+/// for response encryption and is recorded in audit events.
+pub const CLIENT_ID_HEADER: HeaderName = HeaderName::from_static("x-client-id");
+
+/// Request header naming the tenant whose keys serve the request.
+pub const TENANT_ID_HEADER: HeaderName = HeaderName::from_static("x-tenant-id");
+
+/// Largest body the layers will buffer by default (same as axum's default
+/// body limit).
+#[cfg_attr(
+    not(any(feature = "signing", feature = "response-encryption")),
+    allow(dead_code)
+)]
+const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+#[cfg_attr(
+    not(any(feature = "signing", feature = "response-encryption")),
+    allow(dead_code)
+)]
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
@@ -0,0 +1,242 @@
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::body::{Body, Bytes, to_bytes};
+use axum::http::{HeaderName, HeaderValue, Request, Response, header};
+use axum::response::IntoResponse;
+use tower::{Layer, Service};
+
+use super::{BoxFuture, DEFAULT_MAX_BODY_BYTES};
+use crate::crypto::signer::AsyncSigner;
+use crate::error::Error;
+
+/// Header carrying the signature of the message body.
+pub const SIGNATURE_HEADER: HeaderName = HeaderName::from_static("x-signature");
+
+/// Header naming the key that produced a response signature, so callers can
+/// pick the right verification key during rotation.
+pub const KEY_ID_HEADER: HeaderName = HeaderName::from_static("x-signature-key-id");
+
+/// Prefix of every response body [`SignResponseLayer`] signs. Like the
+/// prehash tag it starts with `0xFF`, which never occurs in UTF-8: a
+/// response whose body the caller chose (a blob, an echo) never yields a
+/// signature over a canonical payload or a challenge.
+pub const RESPONSE_DOMAIN_TAG: &[u8] = b"\xfftake-home response v1\0";
+
+/// The bytes signed for a response `body`: [`RESPONSE_DOMAIN_TAG`], then
+/// the body.
+pub fn response_signing_input(body: &[u8]) -> Vec<u8> {
+    [RESPONSE_DOMAIN_TAG, body].concat()
+}
+
+/// Rejects requests whose body does not carry a valid signature in the
+/// `X-Signature` header with `401 Unauthorized`.
+#[derive(Clone)]
+pub struct VerifySignatureLayer {
+    signer: Arc<dyn AsyncSigner>,
+    header: HeaderName,
+    max_body_bytes: usize,
+}
+
+impl VerifySignatureLayer {
+    pub fn new(signer: Arc<dyn AsyncSigner>) -> Self {
+        Self {
+            signer,
+            header: SIGNATURE_HEADER,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+
+    /// Reads the signature from `header` instead of `X-Signature`.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Maximum body size buffered for verification; larger bodies are
+    /// rejected with `413 Payload Too Large`.
+    pub fn max_body_bytes(mut self, limit: usize) -> Self {
+        self.max_body_bytes = limit;
+        self
+    }
+}
+
+impl<S> Layer<S> for VerifySignatureLayer {
+    type Service = VerifySignature<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifySignature {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct VerifySignature<S> {
+    inner: S,
+    layer: VerifySignatureLayer,
+}
+
+impl<S> Service<Request<Body>> for VerifySignature<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // Use the instance that was driven to readiness, leaving a fresh
+        // clone in its place for the next call.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let Some(signature) = parts
+                .headers
+                .get(&layer.header)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+            else {
+                let message = format!("missing {} header", layer.header);
+                return Ok(Error::Unauthorized(message).into_response());
+            };
+            let Ok(bytes) = to_bytes(body, layer.max_body_bytes).await else {
+                return Ok(Error::PayloadTooLarge.into_response());
+            };
+            match layer.signer.verify_bytes(&bytes, &signature).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    let message = "request body signature is invalid".to_string();
+                    return Ok(Error::Unauthorized(message).into_response());
+                }
+                Err(err) => return Ok(Error::from(err).into_response()),
+            }
+            inner
+                .call(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        })
+    }
+}
+
+/// Signs every response body, as [`response_signing_input`], and puts the
+/// signature in the `X-Signature` header, so callers can detect tampering
+/// in transit. Event streams
+/// (`text/event-stream`) never end, so they cannot be buffered and pass
+/// through unsigned.
+#[derive(Clone)]
+pub struct SignResponseLayer {
+    signer: Arc<dyn AsyncSigner>,
+    header: HeaderName,
+    key_id: Option<HeaderValue>,
+    max_body_bytes: usize,
+}
+
+impl SignResponseLayer {
+    pub fn new(signer: Arc<dyn AsyncSigner>) -> Self {
+        Self {
+            signer,
+            header: SIGNATURE_HEADER,
+            key_id: None,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+
+    /// Also emits `key_id` in the `X-Signature-Key-Id` header.
+    pub fn key_id(mut self, key_id: HeaderValue) -> Self {
+        self.key_id = Some(key_id);
+        self
+    }
+
+    /// Writes the signature to `header` instead of `X-Signature`.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Maximum response size buffered for signing; larger responses are
+    /// replaced with `500 Internal Server Error` rather than sent unsigned.
+    pub fn max_body_bytes(mut self, limit: usize) -> Self {
+        self.max_body_bytes = limit;
+        self
+    }
+}
+
+impl<S> Layer<S> for SignResponseLayer {
+    type Service = SignResponse<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SignResponse {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SignResponse<S> {
+    inner: S,
+    layer: SignResponseLayer,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for SignResponse<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let response = inner.call(request).await?;
+            let event_stream = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.starts_with("text/event-stream"));
+            if event_stream {
+                return Ok(response);
+            }
+            let (mut parts, body) = response.into_parts();
+            let bytes: Bytes = match to_bytes(body, layer.max_body_bytes).await {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    let message = format!("failed to buffer response for signing: {err}");
+                    return Ok(Error::Crypto(message).into_response());
+                }
+            };
+            let input = response_signing_input(&bytes);
+            let signature = match layer.signer.sign_bytes(&input).await {
+                Ok(signature) => signature,
+                Err(err) => return Ok(Error::from(err).into_response()),
+            };
+            // Signatures are hex/base64 text, so this never fails in practice.
+            if let Ok(value) = HeaderValue::from_str(&signature) {
+                parts.headers.insert(layer.header, value);
+            }
+            if let Some(key_id) = layer.key_id {
+                parts.headers.insert(KEY_ID_HEADER, key_id);
+            }
+            Ok(Response::from_parts(parts, Body::from(bytes)))
+        })
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::body::{Body, to_bytes};
+use axum::http::{Request, Response, header};
+use axum::response::IntoResponse;
+use tower::{Layer, Service};
+
+use super::{BoxFuture, DEFAULT_MAX_BODY_BYTES};
+use crate::clock::Clock;
+use crate::crypto::sigv4::{Credentials, SigV4Request, VerifiedSigV4, verify};
+use crate::error::Error;
+use crate::replay::ReplayStore;
+
+/// Rejects requests without a valid SigV4-style `Authorization` header with
+/// `401 Unauthorized`. The
+/// [`VerifiedSigV4`](crate::crypto::sigv4::VerifiedSigV4) is added to the
+/// request extensions for handlers that need the access key id.
+#[derive(Clone)]
+pub struct VerifySigV4Layer {
+    credentials: Arc<Credentials>,
+    max_skew_secs: u64,
+    max_body_bytes: usize,
+    replay: Option<Arc<dyn ReplayStore>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl VerifySigV4Layer {
+    pub fn new(credentials: Arc<Credentials>) -> Self {
+        Self {
+            credentials,
+            max_skew_secs: 900,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            replay: None,
+            clock: crate::clock::system(),
+        }
+    }
+
+    /// The clock `X-Amz-Date` is checked against. Defaults to the system
+    /// clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Largest accepted distance between `X-Amz-Date` and the server clock.
+    /// Defaults to 15 minutes.
+    pub fn max_skew_secs(mut self, secs: u64) -> Self {
+        self.max_skew_secs = secs;
+        self
+    }
+
+    /// Maximum body size buffered for hashing; larger bodies are rejected
+    /// with `413 Payload Too Large`.
+    pub fn max_body_bytes(mut self, limit: usize) -> Self {
+        self.max_body_bytes = limit;
+        self
+    }
+
+    /// Rejects a signature already seen, for as long as its `X-Amz-Date`
+    /// is within the skew window. Use a shared store such as
+    /// [`RedisReplayStore`](crate::replay::RedisReplayStore) when several
+    /// replicas verify the same callers.
+    pub fn replay_store(mut self, store: Arc<dyn ReplayStore>) -> Self {
+        self.replay = Some(store);
+        self
+    }
+
+    async fn check_replay(
+        &self,
+        verified: &VerifiedSigV4,
+        authorization: &str,
+    ) -> Result<(), Error> {
+        let Some(store) = &self.replay else {
+            return Ok(());
+        };
+        // Verification succeeded, so the header carries a signature.
+        let signature = authorization
+            .rsplit_once("Signature=")
+            .map_or(authorization, |(_, signature)| signature);
+        let signature = signature.split(',').next().unwrap_or_default().trim();
+        let key = format!("sigv4:{}:{signature}", verified.access_key_id);
+        // A request is accepted from `skew` before to `skew` after its date.
+        let ttl = Duration::from_secs(self.max_skew_secs.saturating_mul(2));
+        if store.check_and_set(&key, ttl).await? {
+            Ok(())
+        } else {
+            Err(Error::Unauthorized(
+                "request signature was already used".into(),
+            ))
+        }
+    }
+}
+
+impl<S> Layer<S> for VerifySigV4Layer {
+    type Service = VerifySigV4<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        VerifySigV4 {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct VerifySigV4<S> {
+    inner: S,
+    layer: VerifySigV4Layer,
+}
+
+impl<S> Service<Request<Body>> for VerifySigV4<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let (mut parts, body) = request.into_parts();
+            let Ok(bytes) = to_bytes(body, layer.max_body_bytes).await else {
+                return Ok(Error::PayloadTooLarge.into_response());
+            };
+            let headers: Vec<(String, String)> = parts
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect();
+            let uri = parts.uri.path_and_query().map_or("/", |pq| pq.as_str());
+            let request = SigV4Request {
+                method: parts.method.as_str(),
+                uri,
+                headers: &headers,
+                body: &bytes,
+            };
+            let verified = match verify(
+                &layer.credentials,
+                &request,
+                layer.clock.now(),
+                layer.max_skew_secs,
+            ) {
+                Ok(verified) => verified,
+                Err(err) => return Ok(Error::Unauthorized(err.to_string()).into_response()),
+            };
+            let authorization = parts
+                .headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            if let Err(err) = layer.check_replay(&verified, authorization).await {
+                return Ok(err.into_response());
+            }
+            parts.extensions.insert(verified);
+            inner
+                .call(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        })
+    }
+}
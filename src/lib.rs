@@ -1,2 +1,81 @@
+#[cfg(feature = "server")]
+pub mod anomaly;
+#[cfg(feature = "server")]
+pub mod api_keys;
+#[cfg(feature = "server")]
+pub mod app;
+#[cfg(feature = "server")]
+pub mod audit;
+#[cfg(feature = "blobs")]
+pub mod blobs;
+#[cfg(feature = "server")]
+pub mod blocking;
+#[cfg(feature = "tenancy")]
+pub mod breaker;
+#[cfg(all(feature = "admin", feature = "escrow"))]
+pub mod ceremony;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "server")]
+pub mod clock;
+#[cfg(all(feature = "server", feature = "signing"))]
+pub mod coalesce;
+#[cfg(feature = "server")]
+pub mod config;
 pub mod crypto;
+#[cfg(feature = "server")]
+pub mod deprecation;
+mod error;
+#[cfg(feature = "server")]
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "server")]
 pub mod handlers;
+#[cfg(feature = "server")]
+pub mod layers;
+#[cfg(any(feature = "server", feature = "client"))]
+pub mod models;
+#[cfg(feature = "tenancy")]
+pub mod notifications;
+#[cfg(all(feature = "server", feature = "asymmetric"))]
+pub mod oauth;
+#[cfg(feature = "admin")]
+mod openmetrics;
+#[cfg(feature = "server")]
+pub mod policy;
+#[cfg(all(feature = "server", feature = "postgres"))]
+mod postgres;
+#[cfg(feature = "server")]
+pub mod quota;
+#[cfg(all(feature = "server", feature = "asymmetric"))]
+pub mod refresh;
+#[cfg(feature = "server")]
+pub mod reload;
+#[cfg(feature = "server")]
+pub mod replay;
+#[cfg(feature = "server")]
+pub mod retry;
+#[cfg(feature = "server")]
+pub mod security;
+#[cfg(feature = "server")]
+pub mod serve;
+#[cfg(feature = "server")]
+pub mod state;
+#[cfg(feature = "tenancy")]
+pub mod tenancy;
+#[cfg(all(feature = "server", feature = "asymmetric"))]
+pub mod transparency;
+#[cfg(feature = "server")]
+pub mod usage;
+#[cfg(feature = "vault")]
+pub mod vault;
+#[cfg(feature = "server")]
+pub mod watchdog;
+
+pub use error::Error;
+
+#[cfg(feature = "admin")]
+pub use app::{admin_app, admin_router};
+#[cfg(feature = "server")]
+pub use app::{app, router};
@@ -1,18 +1,138 @@
-use axum::{Router, routing::post};
+use std::net::{IpAddr, SocketAddr};
 
-use take_home::handlers;
+use clap::Parser;
+use tokio::net::TcpListener;
+
+use take_home::config::{Cli, Config};
+use take_home::state::AppState;
 
 #[tokio::main]
 async fn main() {
-    let app = Router::new()
-        .route("/encrypt", post(handlers::encryption::encrypt))
-        .route("/decrypt", post(handlers::encryption::decrypt))
-        .route("/sign", post(handlers::signing::sign))
-        .route("/verify", post(handlers::signing::verify));
-
-    let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let addr = format!("0.0.0.0:{port}");
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    println!("Server running on http://localhost:{port}");
-    axum::serve(listener, app).await.unwrap();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "info,tower_http=debug".into()),
+        )
+        .init();
+
+    let cli = Cli::parse();
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("configuration error: {err}");
+            std::process::exit(2);
+        }
+    };
+
+    // Secrets are redacted; the overrides came from flags or the environment.
+    tracing::info!(
+        file = ?cli.config,
+        overrides = ?cli.overrides(),
+        "effective configuration:\n{}",
+        config.effective()
+    );
+
+    // Every backend is built, and every key loaded, before anything listens:
+    // a bad setting stops the process here rather than on the first request.
+    // Both listeners share one set of backends.
+    let state = AppState::from_config(&config);
+    // Refuse to serve with crypto that does not reproduce its test vectors.
+    let encryption = [
+        #[cfg(feature = "encryption")]
+        config.encryption.algorithm.as_str(),
+    ];
+    #[cfg(feature = "signing")]
+    let algorithms = state.signers.algorithms().into_iter().chain(encryption);
+    #[cfg(not(feature = "signing"))]
+    let algorithms = encryption;
+    let algorithms: Vec<&str> = algorithms.into_iter().collect();
+    if let Err(err) = take_home::crypto::selftest::run(algorithms.iter().copied()) {
+        eprintln!("self-test failed: {err}");
+        std::process::exit(1);
+    }
+    tracing::info!(?algorithms, "crypto self-tests passed");
+    if cli.check_config {
+        eprintln!("configuration OK");
+        return;
+    }
+    if config.watchdog.enabled {
+        let interval = std::time::Duration::from_secs(config.watchdog.interval_secs);
+        state.watchdog.spawn(interval);
+    }
+    if config.reload.enabled {
+        let interval = std::time::Duration::from_secs(config.reload.interval_secs);
+        tracing::info!(files = ?config.key_files(), "reloading keys when their files change");
+        take_home::reload::spawn(state.clone(), cli, &config, interval);
+    }
+    #[cfg(feature = "asymmetric")]
+    if config.transparency.enabled {
+        let interval = std::time::Duration::from_secs(config.transparency.seal_interval_secs);
+        take_home::transparency::spawn(state.clone(), interval);
+    }
+    #[cfg(feature = "tenancy")]
+    if let Some(tenants) = &state.tenants {
+        tracing::info!(
+            required = config.tenancy.required,
+            cache_ttl_secs = config.tenancy.cache_ttl_secs,
+            "tenancy enabled"
+        );
+        let notifications = &config.notifications;
+        if !notifications.webhooks.is_empty() {
+            tenants.spawn_expiry_checks(
+                std::time::Duration::from_secs(notifications.expiry_check_interval_secs),
+                std::time::Duration::from_secs(notifications.expiry_warning_secs),
+            );
+        }
+    }
+    let app = take_home::router(state.clone(), &config);
+    let server = &config.server;
+    let listener = bind(server.bind_address, server.port, "PORT (`server.port`)").await;
+    tracing::info!(
+        "Server running on http://{}",
+        listener.local_addr().unwrap()
+    );
+
+    #[cfg(feature = "admin")]
+    {
+        // Admin routes live on their own listener so network policy can keep
+        // them off the public data plane.
+        let admin = take_home::admin_router(state, &config);
+        let admin_listener = bind(
+            server.bind_address,
+            server.admin_port,
+            "ADMIN_PORT (`server.admin_port`)",
+        )
+        .await;
+        tracing::info!(
+            "Admin server running on http://{}",
+            admin_listener.local_addr().unwrap()
+        );
+
+        let (served, admin_served) = tokio::join!(
+            take_home::serve::serve(listener, app, server),
+            take_home::serve::serve(admin_listener, admin, server),
+        );
+        served.unwrap();
+        admin_served.unwrap();
+    }
+
+    #[cfg(not(feature = "admin"))]
+    take_home::serve::serve(listener, app, server)
+        .await
+        .unwrap();
+}
+
+/// Binds a listener, or exits naming the setting to change.
+async fn bind(address: IpAddr, port: u16, setting: &str) -> TcpListener {
+    let address = SocketAddr::new(address, port);
+    match TcpListener::bind(address).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!(
+                "cannot listen on {address}: {err}; choose another address with \
+                 BIND_ADDRESS (`server.bind_address`) or port with {setting}"
+            );
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,982 @@
+//! Request and response bodies of the HTTP API. Every model derives a JSON
+//! Schema, which is the basis for generated API documentation.
+
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// `/encrypt` input: any JSON payload. Every top-level property is encrypted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct EncryptRequest(pub Value);
+
+/// `/encrypt` output: the payload with every top-level property encrypted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct EncryptResponse(pub Value);
+
+/// `/decrypt` input: any JSON payload. Top-level properties that are
+/// ciphertext are decrypted; all others are returned unchanged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct DecryptRequest(pub Value);
+
+/// `/decrypt` output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct DecryptResponse(pub Value);
+
+/// `/encrypt/pointers` and `/decrypt/pointers` input: a document and the
+/// RFC 6901 JSON Pointers of the values to encrypt or decrypt in place.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PointersRequest {
+    pub data: Value,
+    pub pointers: Vec<String>,
+}
+
+/// `/encrypt/patch` input: a document as JSON text, and the RFC 6901 JSON
+/// Pointers of the values to encrypt in it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PatchRequest {
+    pub document: String,
+    pub pointers: Vec<String>,
+}
+
+/// `/encrypt/patch` output: the same text, with only the selected values
+/// replaced by their ciphertext.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PatchResponse {
+    pub document: String,
+}
+
+/// Query parameters of `/encrypt` and `/encrypt/pointers`. `sidecar` adds
+/// a [`CryptoSidecar`] to the document, as its `_crypto` member.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptParams {
+    #[serde(default)]
+    pub sidecar: bool,
+}
+
+/// The `_crypto` member of a document: which of its values are encrypted,
+/// by JSON Pointer, and with what. `/decrypt` decrypts exactly those values
+/// of a document that carries one, and removes it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CryptoSidecar {
+    pub paths: BTreeMap<String, EncryptedPath>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptedPath {
+    pub alg: String,
+    pub kid: String,
+}
+
+/// Query parameters of `/decrypt`. `field_status` answers with a
+/// [`DecryptStatusResponse`] instead of the bare payload.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DecryptParams {
+    #[serde(default)]
+    pub field_status: bool,
+}
+
+/// `/decrypt?field_status=true` output: the payload, plus what happened to
+/// each of its top-level properties (`decrypted`, `passthrough` for values
+/// that are not ciphertext, `failed` for ciphertext that did not
+/// authenticate, returned unchanged).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DecryptStatusResponse {
+    pub data: Map<String, Value>,
+    pub fields: BTreeMap<String, FieldStatus>,
+}
+
+/// What `/decrypt` did with one top-level property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldStatus {
+    Decrypted,
+    Passthrough,
+    Failed,
+}
+
+/// `PUT /blobs` output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct BlobResponse {
+    /// Hex SHA-256 of the blob, to be passed to `GET /blobs/{hash}`.
+    pub address: String,
+}
+
+/// `PUT /vault/{name}` input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VaultPutRequest {
+    /// Any JSON value; stored encrypted.
+    pub value: Value,
+    /// Labels stored in the clear next to the value. Replaced on every write.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Metadata of a vault entry, returned by `PUT /vault/{name}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct VaultEntry {
+    pub name: String,
+    /// Starts at 1 and grows with every write.
+    pub version: u64,
+    /// Unix time in seconds.
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// `GET /vault/{name}` output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct VaultSecret {
+    pub value: Value,
+    #[serde(flatten)]
+    pub entry: VaultEntry,
+}
+
+/// `/sign` input: any JSON object, or an array such as a batch of events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct SignRequest(pub Payload);
+
+/// A signed document. Objects and arrays have distinct canonical forms, so
+/// a signature over one never verifies the other.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged, expecting = "a JSON object or array")]
+pub enum Payload {
+    Object(Map<String, Value>),
+    Array(Vec<Value>),
+}
+
+impl From<Map<String, Value>> for Payload {
+    fn from(map: Map<String, Value>) -> Self {
+        Payload::Object(map)
+    }
+}
+
+impl From<Vec<Value>> for Payload {
+    fn from(items: Vec<Value>) -> Self {
+        Payload::Array(items)
+    }
+}
+
+impl From<Payload> for Value {
+    fn from(payload: Payload) -> Self {
+        match payload {
+            Payload::Object(map) => Value::Object(map),
+            Payload::Array(items) => Value::Array(items),
+        }
+    }
+}
+
+/// `/sign` output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SignResponse {
+    pub signature: String,
+    /// The same payload signed as a `v1.<alg>.<signature>` envelope, next
+    /// to a bare `signature`, while `signing.transition` is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub envelope: Option<String>,
+    /// The payload as signed, with the `iat` and `exp` claims `ttl` added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Map<String, Value>>,
+}
+
+/// `/canonicalize` output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CanonicalizeResponse {
+    /// The exact text `/sign` signs for the same body, as UTF-8.
+    pub canonical: String,
+    /// Lowercase hex SHA-256 of `canonical`.
+    pub sha256: String,
+}
+
+/// `/testvectors` output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TestVectorsResponse {
+    /// `signing.float_policy`, which the canonical forms follow.
+    pub float_policy: String,
+    pub keys: Vec<TestVectorKey>,
+    pub vectors: Vec<TestVector>,
+}
+
+/// A published test key. Anyone can sign with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TestVectorKey {
+    pub algorithm: String,
+    /// Lowercase hex HMAC secret.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_hex: Option<String>,
+    /// SPKI `PUBLIC KEY` PEM of an asymmetric key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+/// A payload, the bytes `/sign` signs for it, and their signature with the
+/// test key of `algorithm`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TestVector {
+    pub algorithm: String,
+    pub payload: Value,
+    /// The canonical form of `payload`, as UTF-8.
+    pub canonical: String,
+    /// `v1.<algorithm>.<signature>`.
+    pub signature: String,
+}
+
+/// `GET /transparency/roots` query.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TransparencyRootsParams {
+    /// Only roots covering more than this many audit events.
+    pub after: Option<u64>,
+}
+
+/// `GET /transparency/roots` output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TransparencyRootsResponse {
+    /// Oldest first.
+    pub roots: Vec<SealedRoot>,
+}
+
+/// A signed Merkle tree head over the first `tree_size` audit events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SealedRoot {
+    pub tree_size: u64,
+    /// Lowercase hex RFC 6962 root hash.
+    pub root_hash: String,
+    /// Unix seconds.
+    pub sealed_at: u64,
+    /// The `kid` of the JWKS key that signed the root.
+    pub key_id: String,
+    /// `v1.<algorithm>.<signature>` of the tree size, root hash and seal
+    /// time, as `take_home::transparency::tree_head_signing_input` lays
+    /// them out.
+    pub signature: String,
+}
+
+/// `GET /transparency/consistency` query: the sizes of two sealed roots.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConsistencyParams {
+    pub first: u64,
+    pub second: u64,
+}
+
+/// `GET /transparency/consistency` output: the RFC 6962 proof that the tree
+/// of `first` events is a prefix of that of `second` events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ConsistencyProofResponse {
+    pub first: u64,
+    pub second: u64,
+    /// Lowercase hex node hashes.
+    pub proof: Vec<String>,
+}
+
+/// Query parameters of `/sign` and `/verify`. `alg` selects a registered
+/// signing algorithm instead of the configured default; `digest` selects
+/// the hash inside an HMAC (`sha256`, `sha384` or `sha512`); `schema` names
+/// a registered JSON Schema the payload must match. `ttl`, on `/sign`
+/// only, adds `iat` and `exp` claims valid for that many seconds;
+/// `check_exp`, on `/verify` only, refuses data past its `exp` claim.
+/// `prehashed` takes the payload for a `{"digest", "digest_alg"}` digest
+/// computed by the client, signed under its domain tag.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SignParams {
+    pub alg: Option<String>,
+    pub digest: Option<String>,
+    pub schema: Option<String>,
+    pub ttl: Option<u64>,
+    #[serde(default)]
+    pub check_exp: bool,
+    #[serde(default)]
+    pub prehashed: bool,
+}
+
+/// `/verify` input. Unknown properties are rejected so that a misspelled
+/// `data` cannot silently verify something else.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyRequest {
+    pub signature: String,
+    pub data: Payload,
+}
+
+/// `/verify/batch` input: pairs checked as by `/verify`, each on its own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyBatchRequest {
+    pub items: Vec<VerifyRequest>,
+}
+
+/// `/verify/batch` output. `results` follows the order of the request's
+/// `items`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct VerifyBatchResponse {
+    pub valid: usize,
+    pub invalid: usize,
+    pub results: Vec<VerifyVerdict>,
+}
+
+/// Outcome of one pair: why it did not verify, if it did not, with the
+/// code and message `/verify` would have answered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct VerifyVerdict {
+    pub valid: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetail>,
+}
+
+/// `/http-signatures/sign` input: the request to sign with RFC 9421
+/// HTTP Message Signatures.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HttpSignRequest {
+    pub method: String,
+    /// Absolute URI, e.g. `https://example.com/orders?id=1`.
+    pub target_uri: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Component identifiers to cover, e.g. `["@method", "@path", "date"]`.
+    pub components: Vec<String>,
+    /// Signature label. Defaults to `sig1`.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Key to sign with. Defaults to the service key.
+    #[serde(default)]
+    pub keyid: Option<String>,
+    /// Unix time of signing. Defaults to now.
+    #[serde(default)]
+    pub created: Option<u64>,
+    #[serde(default)]
+    pub expires: Option<u64>,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Request body. When present, a `Content-Digest` header is computed,
+    /// added to `headers` and covered by the signature.
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+/// `/http-signatures/sign` output: header values to attach to the request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct HttpSignResponse {
+    pub signature_input: String,
+    pub signature: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_digest: Option<String>,
+}
+
+/// `/http-signatures/verify` input: a received request, including its
+/// `Signature-Input` and `Signature` headers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HttpVerifyRequest {
+    pub method: String,
+    pub target_uri: String,
+    pub headers: BTreeMap<String, String>,
+    /// Label of the signature to check. Defaults to the first one.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Request body, checked against `Content-Digest` when it is covered.
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+/// `/http-signatures/verify` output: the parameters of the valid signature.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct HttpVerifyResponse {
+    pub label: String,
+    pub components: Vec<String>,
+    pub keyid: Option<String>,
+    pub created: Option<u64>,
+    pub expires: Option<u64>,
+}
+
+/// `/sigv4/verify` input: a received request carrying a SigV4-style
+/// `Authorization` header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SigV4VerifyRequest {
+    pub method: String,
+    /// Path and query as sent, e.g. `/?Action=ListUsers&Version=2010-05-08`.
+    pub uri: String,
+    pub headers: BTreeMap<String, String>,
+    /// Request body, hashed into the canonical request. Defaults to empty.
+    #[serde(default)]
+    pub body: String,
+}
+
+/// `/sigv4/verify` output: the credential scope of the valid signature.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SigV4VerifyResponse {
+    pub access_key_id: String,
+    pub date: String,
+    pub region: String,
+    pub service: String,
+    pub signed_headers: Vec<String>,
+}
+
+/// `/challenge` input: the key whose holder is challenged, from
+/// `[http_signatures.keys]` or `signing.key_id`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ChallengeRequest {
+    pub keyid: String,
+}
+
+/// `/challenge` output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ChallengeResponse {
+    /// Signed by the key holder, unchanged, in `/challenge/respond`.
+    pub challenge: String,
+    pub keyid: String,
+    /// Unix seconds after which the challenge cannot be answered.
+    pub expires_at: u64,
+}
+
+/// `/challenge/respond` input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ChallengeAnswerRequest {
+    pub challenge: String,
+    /// Hex HMAC-SHA256 of `challenge` under the challenged key.
+    pub signature: String,
+}
+
+/// `/challenge/respond` output: the key whose possession was proven.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ChallengeAnswerResponse {
+    pub keyid: String,
+    /// Unix seconds the challenge was issued.
+    pub issued_at: u64,
+}
+
+/// `/oauth/token` output (RFC 6749 §5.1).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TokenResponse {
+    /// A JWT signed with the service's asymmetric key.
+    pub access_token: String,
+    /// `DPoP` for tokens bound to the key of a DPoP proof, `Bearer`
+    /// otherwise.
+    pub token_type: String,
+    /// Seconds until the token expires.
+    pub expires_in: u64,
+    /// Space-separated granted scopes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Opaque, single-use token for the `refresh_token` grant, when
+    /// `oauth.refresh_tokens` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+/// `/.well-known/openid-configuration` output (OpenID Connect Discovery
+/// 1.0 §3). This service issues access tokens only, so the ID token and
+/// response type entries just satisfy fields the specification requires.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct OpenIdConfiguration {
+    /// Equal to the `iss` of issued tokens.
+    pub issuer: String,
+    pub jwks_uri: String,
+    pub token_endpoint: String,
+    pub grant_types_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    /// Scopes of the registered clients.
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    /// Algorithms accepted for DPoP proofs (RFC 9449 §5.1).
+    pub dpop_signing_alg_values_supported: Vec<String>,
+    /// `POST /oauth/revoke`, present when refresh tokens are issued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation_endpoint: Option<String>,
+}
+
+/// `/oauth/dpop/verify` input: a DPoP-bound access token and the proof a
+/// resource server received with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct DpopVerifyRequest {
+    pub access_token: String,
+    /// The `DPoP` header of the request.
+    pub proof: String,
+    /// Method of the request, e.g. `GET`.
+    pub method: String,
+    /// URL of the request as the client sent it; query and fragment are
+    /// ignored.
+    pub url: String,
+}
+
+/// `/oauth/dpop/verify` output when the proof holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct DpopVerifyResponse {
+    pub client_id: String,
+    /// Space-separated scopes of the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Thumbprint of the key the token is bound to.
+    pub jkt: String,
+    /// Unix seconds the token expires.
+    pub expires_at: u64,
+}
+
+/// `/webhooks/verify/{provider}` output for a genuine delivery.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct WebhookVerifyResponse {
+    pub provider: String,
+    /// The delivery body: parsed JSON, or an object of strings for
+    /// form-encoded deliveries (e.g. Slack slash commands).
+    pub payload: Value,
+}
+
+/// Admin `/algorithms` output: the algorithms this instance can serve.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AlgorithmsResponse {
+    pub signing: Vec<String>,
+}
+
+/// Admin `/readyz` output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ReadinessResponse {
+    pub status: Readiness,
+    /// Key stores, signers (`signer:<alg>`) and encryptors that are failing;
+    /// operations that need them get `503`.
+    #[serde(default)]
+    pub unavailable: Vec<UnavailableBackend>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Readiness {
+    /// Every backend is reachable.
+    Ready,
+    /// Some backend is failing; verification keeps working with the keys
+    /// loaded before it failed.
+    Degraded,
+    /// A signer or encryptor fails its periodic self-check; the instance
+    /// should be taken out of rotation.
+    Unavailable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct UnavailableBackend {
+    pub backend: String,
+    /// Unix seconds of the first failure.
+    pub since: u64,
+}
+
+/// Admin `/metrics` output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct MetricsResponse {
+    pub blocking_pool: BlockingPoolStats,
+    /// Present when `signing.cache.enabled` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_cache: Option<SignatureCacheStats>,
+    /// Present when `signing.coalesce` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coalescing: Option<CoalescingStats>,
+    /// Operations served by each key since startup.
+    #[serde(default)]
+    pub key_usage: Vec<KeyUsageStats>,
+    /// Retries of remote signing and encryption backends.
+    #[serde(default)]
+    pub retries: RetryStats,
+    /// Periodic self-checks of the signers and encryptor.
+    #[serde(default)]
+    pub watchdog: WatchdogStats,
+    /// Verification failures and unknown keys outside the signing keys.
+    #[serde(default)]
+    pub security: Vec<SecurityEventStats>,
+    /// Uses of deprecated formats.
+    #[serde(default)]
+    pub legacy_formats: Vec<LegacyFormatStats>,
+}
+
+/// Requests that used one legacy format since startup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct LegacyFormatStats {
+    /// `bare_signature` or `base64_ciphertext`.
+    pub format: String,
+    /// Uses served.
+    pub used: u64,
+    /// Uses refused after the sunset.
+    pub rejected: u64,
+}
+
+/// Occurrences of one security event since startup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SecurityEventStats {
+    /// `verify_failure` or `key_not_found`.
+    pub event: String,
+    /// What was verified: `http_signature`, `sigv4` or `webhook`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    /// Why verification failed: `mismatch`, `expired` or `digest_mismatch`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Key id, access key id or webhook provider, when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub count: u64,
+}
+
+/// Self-checks run since startup, and how many of them failed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct WatchdogStats {
+    pub checks: u64,
+    pub failures: u64,
+}
+
+/// Retries made since startup, and operations that failed even after the
+/// last attempt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RetryStats {
+    pub retries: u64,
+    pub exhausted: u64,
+}
+
+/// Operations one key has served since startup. Tenant keys are listed
+/// under `tenant:<id>` once first used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct KeyUsageStats {
+    pub key_id: String,
+    pub algorithm: String,
+    pub sign: u64,
+    pub verify: u64,
+    pub encrypt: u64,
+    pub decrypt: u64,
+    /// Verifications, among `verify`, whose signature did not match.
+    #[serde(default)]
+    pub verify_failures: u64,
+    /// Ciphertexts that failed authentication.
+    #[serde(default)]
+    pub decrypt_failures: u64,
+    /// Unix time in seconds of the latest operation; absent if none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<u64>,
+}
+
+/// Admin `GET /keys/usage` query.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct KeyUsageParams {
+    /// Only keys unused for at least this many seconds, or never used.
+    pub idle_secs: Option<u64>,
+}
+
+/// Admin `GET /events` query.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EventStreamParams {
+    /// Comma-separated event types to stream, `audit` and `key`; all of
+    /// them if absent.
+    pub types: Option<String>,
+}
+
+/// `lagged` event of admin `GET /events`: the subscriber fell behind and
+/// missed events.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct EventsMissed {
+    pub missed: u64,
+}
+
+/// Admin `GET /keys/usage` output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct KeyUsageResponse {
+    pub keys: Vec<KeyUsageStats>,
+}
+
+/// Effectiveness of the signature LRU.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SignatureCacheStats {
+    pub capacity: u64,
+    pub entries: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// `hits / (hits + misses)`, `0` before the first lookup.
+    pub hit_rate: f64,
+}
+
+/// Sign requests that shared another identical request's key operation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct CoalescingStats {
+    /// Key operations currently in flight.
+    pub in_flight: u64,
+    /// Key operations run, each on behalf of one or more requests.
+    pub led: u64,
+    /// Requests answered by an operation another request started.
+    pub coalesced: u64,
+}
+
+/// Load of the pool running CPU-heavy crypto off the async workers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct BlockingPoolStats {
+    /// Tasks allowed to run at once.
+    pub max_concurrent: u64,
+    /// Tasks waiting for a free slot.
+    pub queued: u64,
+    pub running: u64,
+    /// Tasks that returned, and tasks that panicked, since startup.
+    pub completed: u64,
+    pub failed: u64,
+    /// Time spent waiting for a slot, summed over all tasks.
+    pub wait_micros_total: u64,
+    /// Time spent running, summed over all tasks.
+    pub run_micros_total: u64,
+}
+
+/// Admin `GET /tenants` query. Tenants are listed by creation time, oldest
+/// first unless `order` is `desc`; `cursor` continues from the
+/// `next_cursor` of the previous page.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TenantListParams {
+    /// Tenants per page, 1 to 500; 50 by default.
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    /// Only tenants whose keys use this algorithm.
+    pub algorithm: Option<String>,
+    /// Only tenants whose keys are in this state.
+    pub state: Option<String>,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Admin `GET /tenants` output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TenantListResponse {
+    pub tenants: Vec<TenantSummary>,
+    /// Present when more tenants follow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TenantSummary {
+    pub tenant: String,
+    pub algorithm: String,
+    /// Unix time in seconds; absent for tenants from the config file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<u64>,
+    /// `pending`, `active`, `disabled`, `compromised` or `destroyed`.
+    pub state: String,
+    /// When a destroyed key is deleted, in Unix seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destroy_at: Option<u64>,
+}
+
+/// Admin `PUT /tenants/{tenant}/key` query.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RotateKeyParams {
+    /// State of the new key, `pending` or `active`; `active` by default.
+    pub state: Option<String>,
+}
+
+/// Admin `PUT /tenants/{tenant}/state` input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TenantStateRequest {
+    /// `pending`, `active`, `disabled`, `compromised` or `destroyed`.
+    pub state: String,
+}
+
+/// Admin `POST /keys/escrow` input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EscrowExportRequest {
+    /// Name of the configured escrow public key to seal the key to.
+    pub escrow_key: String,
+    /// Algorithm of the signing key to export; defaults to the default
+    /// signing algorithm.
+    #[serde(default)]
+    pub alg: Option<String>,
+}
+
+/// Admin `POST /ceremonies` input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StartCeremonyRequest {
+    /// Key id of the master key to create.
+    pub kid: String,
+    /// Name of the configured escrow public key the key is sealed to.
+    pub escrow_key: String,
+}
+
+/// Admin `POST /ceremonies/{id}/shares` input, sent by a custodian with its
+/// own API key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CeremonyShareRequest {
+    /// Base64 of at least 32 random bytes.
+    pub share: String,
+}
+
+/// A key ceremony, as admin `/ceremonies` reports it. Shares are never
+/// included.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CeremonyStatus {
+    pub id: String,
+    pub kid: String,
+    /// Signing algorithm of the key, `hmac-sha256`.
+    pub algorithm: String,
+    pub escrow_key: String,
+    /// `collecting`, `complete`, `aborted` or `expired`.
+    pub state: String,
+    /// API key that started the ceremony.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_by: Option<String>,
+    /// API keys of every custodian the ceremony needs a share from.
+    pub custodians: Vec<String>,
+    /// Custodians whose share is in, in the order they came.
+    pub contributed: Vec<String>,
+    /// Unix time in seconds.
+    pub started_at: u64,
+    /// When a ceremony still collecting shares expires, in Unix seconds.
+    pub expires_at: u64,
+    /// When the ceremony completed, was aborted or expired.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ended_at: Option<u64>,
+    /// Lowercase hex SHA-256 of the key, once complete.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+/// Query parameters of admin `POST /encryption/migrate`. `pointers` lists,
+/// comma-separated, the JSON Pointers of the base64 values in documents
+/// without a `_crypto` sidecar. `dry_run` checks every document and counts
+/// what would be re-encrypted, without encrypting anything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MigrateParams {
+    #[serde(default)]
+    pub pointers: Option<String>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Admin `POST /encryption/migrate?dry_run=true` output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct MigrationReport {
+    /// Documents read, failed ones included.
+    pub documents: usize,
+    /// Values that would be re-encrypted.
+    pub values: usize,
+    pub failures: Vec<MigrationFailure>,
+}
+
+/// A document that cannot be migrated, by its 1-based line number.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct MigrationFailure {
+    pub line: usize,
+    pub error: String,
+}
+
+/// Body of every error response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorDetail {
+    /// Stable machine-readable identifier, e.g. `invalid_signature`.
+    pub code: String,
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sign_request_accepts_any_object() {
+        let request: SignRequest = serde_json::from_value(json!({"a": 1, "b": [true]})).unwrap();
+        assert!(matches!(request.0, Payload::Object(map) if map.len() == 2));
+    }
+
+    #[test]
+    fn sign_request_accepts_top_level_arrays() {
+        let request: SignRequest = serde_json::from_value(json!([{"a": 1}, 2])).unwrap();
+        assert_eq!(request.0, Payload::Array(vec![json!({"a": 1}), json!(2)]));
+    }
+
+    #[test]
+    fn sign_request_rejects_scalars() {
+        let err = serde_json::from_value::<SignRequest>(json!("text")).unwrap_err();
+        assert_eq!(err.to_string(), "a JSON object or array");
+        assert!(serde_json::from_value::<SignRequest>(json!(null)).is_err());
+    }
+
+    #[test]
+    fn encrypt_request_accepts_any_value() {
+        let request: EncryptRequest = serde_json::from_value(json!(42)).unwrap();
+        assert_eq!(request.0, json!(42));
+    }
+
+    #[test]
+    fn verify_request_rejects_unknown_fields() {
+        let err = serde_json::from_value::<VerifyRequest>(
+            json!({"signature": "ab", "data": {}, "date": {}}),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown field `date`"));
+    }
+
+    #[test]
+    fn verify_request_requires_object_or_array_data() {
+        assert!(
+            serde_json::from_value::<VerifyRequest>(json!({"signature": "ab", "data": "x"}))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn verify_request_schema_lists_required_properties() {
+        let schema = serde_json::to_value(schemars::schema_for!(VerifyRequest)).unwrap();
+        let required = schema["required"].as_array().unwrap();
+        assert!(required.contains(&json!("signature")));
+        assert!(required.contains(&json!("data")));
+        assert_eq!(schema["additionalProperties"], json!(false));
+    }
+
+    #[test]
+    fn sign_response_serializes_signature_property() {
+        let response = SignResponse {
+            signature: "abc".into(),
+            envelope: None,
+            data: None,
+        };
+        assert_eq!(
+            serde_json::to_value(response).unwrap(),
+            json!({"signature": "abc"})
+        );
+    }
+}
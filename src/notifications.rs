@@ -0,0 +1,392 @@
+//! Notifications of tenant key lifecycle events, so services that cache
+//! tenant keys or signatures can refresh before a stale key bites them.
+//! [`Tenants`](crate::tenancy::Tenants) raises a [`KeyEvent`] when a key is
+//! created, rotated or changes state, and when a destroyed key's grace
+//! period is about to end; each is logged under the `notifications` tracing
+//! target and handed to every [`NotificationSink`], such as a webhook.
+//!
+//! Webhook deliveries carry a `Take-Home-Signature` header in Stripe's
+//! `t=<ts>,v1=<hex>` format, an HMAC-SHA256 over `<ts>.<body>` with the
+//! webhook's secret, so receivers can check them with a Stripe library.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, Uri, header};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use serde::{Serialize, Serializer};
+
+use crate::clock::Clock;
+use crate::crypto::hmac::HMacSigner;
+use crate::crypto::webhook::stripe_signature;
+use crate::tenancy::KeyState;
+
+pub const SIGNATURE_HEADER: &str = "take-home-signature";
+pub const EVENT_HEADER: &str = "take-home-event";
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Wait before the first redelivery, doubled for each one after it.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyEventKind {
+    /// A tenant got its first key.
+    Created,
+    /// A tenant's key was replaced; signatures of the old one no longer
+    /// verify.
+    Rotated,
+    Activated,
+    Disabled,
+    Compromised,
+    /// The key only verifies until its `destroy_at`.
+    Destroyed,
+    /// A destroyed key's grace period ends soon.
+    Expiring,
+}
+
+impl KeyEventKind {
+    pub const ALL: [KeyEventKind; 7] = [
+        KeyEventKind::Created,
+        KeyEventKind::Rotated,
+        KeyEventKind::Activated,
+        KeyEventKind::Disabled,
+        KeyEventKind::Compromised,
+        KeyEventKind::Destroyed,
+        KeyEventKind::Expiring,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyEventKind::Created => "key.created",
+            KeyEventKind::Rotated => "key.rotated",
+            KeyEventKind::Activated => "key.activated",
+            KeyEventKind::Disabled => "key.disabled",
+            KeyEventKind::Compromised => "key.compromised",
+            KeyEventKind::Destroyed => "key.destroyed",
+            KeyEventKind::Expiring => "key.expiring",
+        }
+    }
+
+    /// The event of a transition into `state`; `None` for moves that
+    /// cannot happen, into pending.
+    pub fn entering(state: KeyState) -> Option<Self> {
+        match state {
+            KeyState::Pending => None,
+            KeyState::Active => Some(KeyEventKind::Activated),
+            KeyState::Disabled => Some(KeyEventKind::Disabled),
+            KeyState::Compromised => Some(KeyEventKind::Compromised),
+            KeyState::Destroyed => Some(KeyEventKind::Destroyed),
+        }
+    }
+}
+
+impl fmt::Display for KeyEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for KeyEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("unknown key event `{s}`"))
+    }
+}
+
+impl Serialize for KeyEventKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyEvent {
+    /// Random, and the same across redeliveries, so receivers can drop
+    /// duplicates.
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: KeyEventKind,
+    /// Unix time in seconds.
+    pub at: u64,
+    pub tenant: String,
+    /// State of the key after the event.
+    pub state: String,
+    /// When a destroyed key's material is deleted, in Unix seconds.
+    pub destroy_at: Option<u64>,
+}
+
+impl KeyEvent {
+    /// An event of Unix time `at`.
+    pub fn new(
+        kind: KeyEventKind,
+        tenant: &str,
+        state: KeyState,
+        destroy_at: Option<u64>,
+        at: u64,
+    ) -> Self {
+        use rand_core::{OsRng, RngCore};
+
+        let mut id = [0; 16];
+        OsRng.fill_bytes(&mut id);
+        Self {
+            id: id.iter().map(|b| format!("{b:02x}")).collect(),
+            kind,
+            at,
+            tenant: tenant.to_string(),
+            state: state.to_string(),
+            destroy_at,
+        }
+    }
+}
+
+pub trait NotificationSink: Send + Sync {
+    fn notify(&self, event: &KeyEvent);
+}
+
+/// Logs each event at `info` under the `notifications` target.
+#[derive(Debug, Default)]
+pub struct TracingNotificationSink;
+
+impl NotificationSink for TracingNotificationSink {
+    fn notify(&self, event: &KeyEvent) {
+        tracing::info!(
+            target: "notifications",
+            id = %event.id,
+            kind = %event.kind,
+            tenant = %event.tenant,
+            state = %event.state,
+            destroy_at = event.destroy_at,
+            "key lifecycle event"
+        );
+    }
+}
+
+/// Keeps events in memory, for tests and embedders that ship them
+/// elsewhere.
+#[derive(Debug, Default)]
+pub struct MemoryNotificationSink {
+    events: Mutex<Vec<KeyEvent>>,
+}
+
+impl MemoryNotificationSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<KeyEvent> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl NotificationSink for MemoryNotificationSink {
+    fn notify(&self, event: &KeyEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(event.clone());
+    }
+}
+
+/// POSTs the events it subscribes to as signed JSON to an `http://` URL, in
+/// the background. A failed delivery is retried with a doubling delay until
+/// `max_attempts` are used up, then logged.
+pub struct WebhookNotificationSink(Arc<Webhook>);
+
+struct Webhook {
+    url: Uri,
+    key: HMacSigner,
+    /// Empty for every event.
+    events: Vec<KeyEventKind>,
+    max_attempts: u32,
+    client: Client<HttpConnector, Body>,
+    clock: Arc<dyn Clock>,
+}
+
+impl WebhookNotificationSink {
+    /// Delivers `events`, or every event if empty, in up to `max_attempts`
+    /// tries each.
+    pub fn new(url: Uri, secret: Vec<u8>, events: Vec<KeyEventKind>, max_attempts: u32) -> Self {
+        Self(Arc::new(Webhook {
+            url,
+            key: HMacSigner::new(secret),
+            events,
+            max_attempts: max_attempts.max(1),
+            client: Client::builder(TokioExecutor::new()).build_http(),
+            clock: crate::clock::system(),
+        }))
+    }
+
+    /// The clock deliveries are timestamped by. Defaults to the system
+    /// clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        Arc::get_mut(&mut self.0)
+            .expect("the sink is configured before it delivers")
+            .clock = clock;
+        self
+    }
+}
+
+impl Webhook {
+    fn subscribes(&self, kind: KeyEventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+
+    /// The signed delivery of `body`, sent at `now`.
+    fn request(&self, kind: KeyEventKind, body: &[u8], now: u64) -> Request<Body> {
+        Request::post(self.url.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, kind.as_str())
+            .header(SIGNATURE_HEADER, stripe_signature(&self.key, now, body))
+            .body(Body::from(body.to_vec()))
+            .expect("a valid request")
+    }
+
+    async fn deliver(&self, event: &KeyEvent, body: Vec<u8>) {
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1..=self.max_attempts {
+            // Signed afresh, so a late redelivery is not rejected as stale.
+            let request = self.request(event.kind, &body, self.clock.now());
+            let failure =
+                match tokio::time::timeout(WEBHOOK_TIMEOUT, self.client.request(request)).await {
+                    Ok(Ok(response)) if response.status().is_success() => return,
+                    Ok(Ok(response)) => format!("status {}", response.status()),
+                    Ok(Err(err)) => err.to_string(),
+                    Err(_) => "timed out".into(),
+                };
+            if attempt == self.max_attempts {
+                tracing::warn!(
+                    target: "notifications",
+                    url = %self.url,
+                    id = %event.id,
+                    error = %failure,
+                    "key event webhook failed"
+                );
+                return;
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+}
+
+impl NotificationSink for WebhookNotificationSink {
+    fn notify(&self, event: &KeyEvent) {
+        if !self.0.subscribes(event.kind) {
+            return;
+        }
+        let body = serde_json::to_vec(event).expect("key events serialize");
+        let webhook = self.0.clone();
+        let event = event.clone();
+        tokio::spawn(async move { webhook.deliver(&event, body).await });
+    }
+}
+
+/// Hands key events to its sinks.
+pub struct Notifier {
+    sinks: Vec<Arc<dyn NotificationSink>>,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Notifier {
+    /// A notifier that logs its events.
+    pub fn new() -> Self {
+        Self {
+            sinks: vec![Arc::new(TracingNotificationSink)],
+        }
+    }
+
+    /// Also hands events to `sink`.
+    pub fn with_sink(mut self, sink: Arc<dyn NotificationSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn notify(&self, event: KeyEvent) {
+        for sink in &self.sinks {
+            sink.notify(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::webhook::{Provider, WebhookVerifier};
+
+    #[test]
+    fn event_names_round_trip() {
+        for kind in KeyEventKind::ALL {
+            assert_eq!(kind.as_str().parse::<KeyEventKind>(), Ok(kind));
+        }
+        assert!("key.renamed".parse::<KeyEventKind>().is_err());
+        assert_eq!(KeyEventKind::entering(KeyState::Pending), None);
+    }
+
+    #[test]
+    fn events_serialize_with_their_type() {
+        let event = KeyEvent::new(
+            KeyEventKind::Destroyed,
+            "payments",
+            KeyState::Destroyed,
+            Some(1_700_000_000),
+            1_699_000_000,
+        );
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "key.destroyed");
+        assert_eq!(json["tenant"], "payments");
+        assert_eq!(json["state"], "destroyed");
+        assert_eq!(json["destroy_at"], 1_700_000_000);
+        assert_eq!(event.id.len(), 32);
+    }
+
+    #[test]
+    fn deliveries_verify_as_stripe_webhooks() {
+        let secret = b"notification-secret".to_vec();
+        let sink = WebhookNotificationSink::new(
+            Uri::from_static("http://hooks.example/keys"),
+            secret.clone(),
+            Vec::new(),
+            1,
+        );
+        let body = br#"{"type":"key.rotated"}"#;
+        let request = sink.0.request(KeyEventKind::Rotated, body, 1_700_000_000);
+        let header = |name: &str| request.headers()[name].to_str().unwrap().to_string();
+
+        assert_eq!(header(EVENT_HEADER), "key.rotated");
+        let signature = header(SIGNATURE_HEADER);
+        let headers = vec![("Stripe-Signature".to_string(), signature)];
+        let verifier = WebhookVerifier::new(300).with_secret(Provider::Stripe, secret);
+        assert_eq!(
+            verifier.verify(Provider::Stripe, &headers, body, 1_700_000_000),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn webhooks_deliver_only_their_events() {
+        let sink = WebhookNotificationSink::new(
+            Uri::from_static("http://hooks.example"),
+            vec![1],
+            vec![KeyEventKind::Rotated],
+            1,
+        );
+        assert!(sink.0.subscribes(KeyEventKind::Rotated));
+        assert!(!sink.0.subscribes(KeyEventKind::Expiring));
+    }
+}
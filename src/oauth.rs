@@ -0,0 +1,808 @@
+//! A minimal OAuth 2.0 token authority: registered clients trade their
+//! secret for a short-lived JWT access token (RFC 9068) through the
+//! client-credentials grant of `POST /oauth/token` (RFC 6749 §4.4). Tokens
+//! are signed with the service's asymmetric key, so resource servers verify
+//! them against `/.well-known/jwks.json` without calling back. With
+//! refresh tokens enabled, clients also get an opaque refresh token that
+//! the `refresh_token` grant trades for a new access token, and that
+//! `POST /oauth/revoke` (RFC 7009) revokes; see [`crate::refresh`].
+//! Clients sending a DPoP proof (RFC 9449) get tokens bound to the proof's
+//! key, which `POST /oauth/dpop/verify` checks for resource servers.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Json;
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::constant_time;
+use crate::crypto::dpop::{self, DpopError, Expected, Proof};
+use crate::crypto::signer::{AsyncSigner, SignError};
+use crate::error::Error;
+use crate::models::OpenIdConfiguration;
+use crate::refresh::{RefreshError, RefreshGrant, RefreshTokenStore, token_hash};
+use crate::replay::{MemoryReplayStore, ReplayError, ReplayStore};
+
+/// Errors of the token endpoint, answered in the RFC 6749 §5.2 format that
+/// OAuth client libraries expect rather than as an [`Error`].
+#[derive(Debug, thiserror::Error)]
+pub enum OAuthError {
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error("client authentication failed")]
+    InvalidClient,
+    #[error("this grant type is not supported")]
+    UnsupportedGrantType,
+    #[error("{0}")]
+    InvalidScope(String),
+    /// The refresh token is unknown, expired, revoked or another client's.
+    #[error("refresh token is invalid, expired or revoked")]
+    InvalidGrant,
+    #[error(transparent)]
+    InvalidDpopProof(#[from] DpopError),
+    /// An access token presented for verification was not issued by this
+    /// service or has expired.
+    #[error("{0}")]
+    InvalidToken(&'static str),
+    #[error("access token expired (+{skew_secs}s clock skew)")]
+    TokenExpired { skew_secs: u64 },
+    #[error(transparent)]
+    Service(#[from] Error),
+}
+
+impl OAuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OAuthError::InvalidRequest(_) => "invalid_request",
+            OAuthError::InvalidClient => "invalid_client",
+            OAuthError::UnsupportedGrantType => "unsupported_grant_type",
+            OAuthError::InvalidScope(_) => "invalid_scope",
+            OAuthError::InvalidGrant => "invalid_grant",
+            OAuthError::InvalidDpopProof(_) => "invalid_dpop_proof",
+            OAuthError::InvalidToken(_) | OAuthError::TokenExpired { .. } => "invalid_token",
+            OAuthError::Service(_) => "server_error",
+        }
+    }
+}
+
+impl From<SignError> for OAuthError {
+    fn from(err: SignError) -> Self {
+        OAuthError::Service(err.into())
+    }
+}
+
+impl From<RefreshError> for OAuthError {
+    fn from(err: RefreshError) -> Self {
+        OAuthError::Service(Error::Storage(err.to_string()))
+    }
+}
+
+impl From<ReplayError> for OAuthError {
+    fn from(err: ReplayError) -> Self {
+        OAuthError::Service(err.into())
+    }
+}
+
+/// RFC 6749 §5.2 error body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OAuthErrorResponse {
+    pub error: String,
+    pub error_description: String,
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        // Failures of the service itself keep its own error format.
+        let status = match self {
+            OAuthError::Service(err) => return err.into_response(),
+            OAuthError::InvalidClient => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        let body = OAuthErrorResponse {
+            error: self.code().to_string(),
+            error_description: self.to_string(),
+        };
+        let mut response = (status, no_store(), Json(body)).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static("Basic realm=\"oauth\""),
+            );
+        }
+        response
+    }
+}
+
+/// Token responses must not be cached (RFC 6749 §5.1).
+pub fn no_store() -> [(header::HeaderName, HeaderValue); 2] {
+    [
+        (header::CACHE_CONTROL, HeaderValue::from_static("no-store")),
+        (header::PRAGMA, HeaderValue::from_static("no-cache")),
+    ]
+}
+
+/// Claims of issued access tokens.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    pub iat: u64,
+    pub exp: u64,
+    pub jti: String,
+    pub client_id: String,
+    /// Space-separated granted scopes; absent when none were granted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// The key the token is bound to, for DPoP-bound tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<Confirmation>,
+}
+
+/// Confirmation claim (RFC 7800) of a DPoP-bound token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Confirmation {
+    /// RFC 7638 thumbprint of the DPoP key.
+    pub jkt: String,
+}
+
+/// A signed access token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessToken {
+    pub token: String,
+    pub expires_in: u64,
+    pub scopes: Vec<String>,
+}
+
+struct OAuthClient {
+    secret: Vec<u8>,
+    scopes: Vec<String>,
+}
+
+struct RefreshTokens {
+    store: Arc<dyn RefreshTokenStore>,
+    ttl_secs: u64,
+}
+
+struct DpopProofs {
+    /// `jti`s of accepted proofs.
+    seen: Arc<dyn ReplayStore>,
+    max_age_secs: u64,
+}
+
+/// The JWS `alg` of the service's asymmetric signer, by its RFC 9421 name.
+pub fn jws_algorithm(alg: &str) -> Option<&'static str> {
+    match alg {
+        "rsa-v1_5-sha256" => Some("RS256"),
+        "ecdsa-p256-sha256" => Some("ES256"),
+        "ed25519" => Some("EdDSA"),
+        _ => None,
+    }
+}
+
+/// Registered clients, and how their tokens are made.
+pub struct TokenIssuer {
+    issuer: String,
+    audience: Option<String>,
+    ttl_secs: u64,
+    kid: String,
+    alg: &'static str,
+    jws_alg: &'static str,
+    clients: Vec<(String, OAuthClient)>,
+    refresh: Option<RefreshTokens>,
+    dpop: DpopProofs,
+    skew_secs: u64,
+}
+
+impl TokenIssuer {
+    /// Issues tokens as `issuer`, signed by the signer registered as `alg`
+    /// (an RFC 9421 name) under key id `kid`. `None` when `alg` has no JWS
+    /// equivalent.
+    pub fn new(issuer: &str, kid: &str, alg: &'static str) -> Option<Self> {
+        Some(Self {
+            issuer: issuer.to_string(),
+            audience: None,
+            ttl_secs: 300,
+            kid: kid.to_string(),
+            alg,
+            jws_alg: jws_algorithm(alg)?,
+            clients: Vec::new(),
+            refresh: None,
+            dpop: DpopProofs {
+                seen: Arc::new(MemoryReplayStore::new()),
+                max_age_secs: 60,
+            },
+            skew_secs: 0,
+        })
+    }
+
+    /// The `aud` claim of every token.
+    pub fn audience(mut self, audience: Option<String>) -> Self {
+        self.audience = audience;
+        self
+    }
+
+    /// Lifetime of access tokens.
+    pub fn ttl_secs(mut self, ttl_secs: u64) -> Self {
+        self.ttl_secs = ttl_secs;
+        self
+    }
+
+    /// Leeway for clocks behind the service's, allowed past the `exp` of
+    /// access tokens and either way around the `iat` of DPoP proofs.
+    pub fn clock_skew_secs(mut self, secs: u64) -> Self {
+        self.skew_secs = secs;
+        self
+    }
+
+    /// Registers client `id`, authenticated by `secret` and allowed to be
+    /// granted `scopes`.
+    pub fn client(mut self, id: &str, secret: &[u8], scopes: Vec<String>) -> Self {
+        self.clients.retain(|(client, _)| client != id);
+        self.clients.push((
+            id.to_string(),
+            OAuthClient {
+                secret: secret.to_vec(),
+                scopes,
+            },
+        ));
+        self
+    }
+
+    /// Hands out refresh tokens valid for `ttl_secs` with every access
+    /// token, keeping their hashes in `store`.
+    pub fn refresh_tokens(mut self, store: Arc<dyn RefreshTokenStore>, ttl_secs: u64) -> Self {
+        self.refresh = Some(RefreshTokens { store, ttl_secs });
+        self
+    }
+
+    /// Accepts DPoP proofs issued up to `max_age_secs` away from the
+    /// current time, remembering their `jti` in `seen`.
+    pub fn dpop_proofs(mut self, seen: Arc<dyn ReplayStore>, max_age_secs: u64) -> Self {
+        self.dpop = DpopProofs { seen, max_age_secs };
+        self
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// Registry name of the signer tokens are signed with.
+    pub fn algorithm(&self) -> &'static str {
+        self.alg
+    }
+
+    /// The JWS `alg` of issued tokens.
+    pub fn jws_algorithm(&self) -> &'static str {
+        self.jws_alg
+    }
+
+    pub fn issues_refresh_tokens(&self) -> bool {
+        self.refresh.is_some()
+    }
+
+    /// URL of `/oauth/token`, which DPoP proofs sent to it must name.
+    pub fn token_endpoint(&self) -> String {
+        format!("{}/oauth/token", self.issuer.trim_end_matches('/'))
+    }
+
+    /// The discovery document of this issuer, pointing at the endpoints
+    /// under [`Self::issuer`].
+    pub fn discovery(&self) -> OpenIdConfiguration {
+        let base = self.issuer.trim_end_matches('/');
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        let mut scopes: Vec<String> = self
+            .clients
+            .iter()
+            .flat_map(|(_, client)| client.scopes.iter().cloned())
+            .collect();
+        scopes.sort();
+        scopes.dedup();
+        let mut grant_types = vec!["client_credentials"];
+        if self.refresh.is_some() {
+            grant_types.push("refresh_token");
+        }
+        OpenIdConfiguration {
+            issuer: self.issuer.clone(),
+            jwks_uri: format!("{base}/.well-known/jwks.json"),
+            token_endpoint: self.token_endpoint(),
+            grant_types_supported: strings(&grant_types),
+            token_endpoint_auth_methods_supported: strings(&[
+                "client_secret_basic",
+                "client_secret_post",
+            ]),
+            scopes_supported: scopes,
+            response_types_supported: strings(&["token"]),
+            subject_types_supported: strings(&["public"]),
+            id_token_signing_alg_values_supported: strings(&[self.jws_alg]),
+            dpop_signing_alg_values_supported: strings(&dpop::ALGORITHMS),
+            revocation_endpoint: self
+                .refresh
+                .as_ref()
+                .map(|_| format!("{base}/oauth/revoke")),
+        }
+    }
+
+    /// The scopes client `id` is granted for `requested`, a space-separated
+    /// list defaulting to all of its scopes, once its `secret` is checked.
+    pub fn authorize(
+        &self,
+        id: &str,
+        secret: &str,
+        requested: Option<&str>,
+    ) -> Result<Vec<String>, OAuthError> {
+        let client = self.authenticate(id, secret)?;
+        granted(&client.scopes, requested)
+    }
+
+    /// A new refresh token for client `id` and `scopes`, valid from `now`;
+    /// `None` when refresh tokens are not enabled.
+    pub async fn refresh_token(
+        &self,
+        id: &str,
+        scopes: Vec<String>,
+        now: u64,
+    ) -> Result<Option<String>, OAuthError> {
+        let Some(refresh) = &self.refresh else {
+            return Ok(None);
+        };
+        let mut bytes = [0; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token = URL_SAFE_NO_PAD.encode(bytes);
+        let grant = RefreshGrant {
+            client_id: id.to_string(),
+            scopes,
+            expires_at: now.saturating_add(refresh.ttl_secs),
+        };
+        refresh
+            .store
+            .insert(&token_hash(&token), grant, now)
+            .await?;
+        Ok(Some(token))
+    }
+
+    /// Redeems refresh `token` of client `id`, once its `secret` is checked.
+    /// Returns the scopes of the original grant that the client still has,
+    /// which a rotated refresh token carries on, and those of them granted
+    /// for `requested`. The token cannot be redeemed again.
+    pub async fn redeem(
+        &self,
+        id: &str,
+        secret: &str,
+        token: &str,
+        requested: Option<&str>,
+        now: u64,
+    ) -> Result<(Vec<String>, Vec<String>), OAuthError> {
+        let client = self.authenticate(id, secret)?;
+        let refresh = self
+            .refresh
+            .as_ref()
+            .ok_or(OAuthError::UnsupportedGrantType)?;
+        let hash = token_hash(token);
+        let grant = refresh
+            .store
+            .take(&hash)
+            .await?
+            .ok_or(OAuthError::InvalidGrant)?;
+        if grant.expires_at < now {
+            return Err(OAuthError::InvalidGrant);
+        }
+        // Refused requests leave the token to its client.
+        if grant.client_id != id {
+            refresh.store.insert(&hash, grant, now).await?;
+            return Err(OAuthError::InvalidGrant);
+        }
+        let kept: Vec<String> = grant
+            .scopes
+            .iter()
+            .filter(|scope| client.scopes.contains(scope))
+            .cloned()
+            .collect();
+        match granted(&kept, requested) {
+            Ok(scopes) => Ok((kept, scopes)),
+            Err(err) => {
+                refresh.store.insert(&hash, grant, now).await?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Revokes refresh `token` of client `id` at `now`, once its `secret` is
+    /// checked. Unknown tokens, including access tokens, which expire on
+    /// their own, are ignored (RFC 7009 §2.2).
+    pub async fn revoke(
+        &self,
+        id: &str,
+        secret: &str,
+        token: &str,
+        now: u64,
+    ) -> Result<(), OAuthError> {
+        self.authenticate(id, secret)?;
+        let Some(refresh) = &self.refresh else {
+            return Ok(());
+        };
+        let hash = token_hash(token);
+        match refresh.store.take(&hash).await? {
+            Some(grant) if grant.client_id != id => {
+                refresh.store.insert(&hash, grant, now).await?;
+                Err(OAuthError::InvalidGrant)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn authenticate(&self, id: &str, secret: &str) -> Result<&OAuthClient, OAuthError> {
+        let client = constant_time::find(&self.clients, id).ok_or(OAuthError::InvalidClient)?;
+        if !constant_time::eq(&client.secret, secret.as_bytes()) {
+            return Err(OAuthError::InvalidClient);
+        }
+        Ok(client)
+    }
+
+    /// Checks a DPoP `proof` sent at `now` with a `method` request to `url`
+    /// and, outside the token endpoint, with `access_token`. Each proof is
+    /// accepted once.
+    pub async fn check_proof(
+        &self,
+        proof: &str,
+        method: &str,
+        url: &str,
+        access_token: Option<&str>,
+        now: u64,
+    ) -> Result<Proof, OAuthError> {
+        let expected = Expected {
+            method,
+            url,
+            access_token,
+            now,
+            max_age_secs: self.dpop.max_age_secs,
+            skew_secs: self.skew_secs,
+        };
+        let proof = dpop::verify(proof, &expected)?;
+        // Proofs are refused once `iat` is further than the max age and
+        // skew away, so their `jti` need not be kept longer than that.
+        let window = self.dpop.max_age_secs.saturating_add(self.skew_secs);
+        let ttl = Duration::from_secs(window.saturating_mul(2).saturating_add(1));
+        let key = format!("{}:{}", proof.jkt, proof.claims.jti);
+        if !self.dpop.seen.check_and_set(&key, ttl).await? {
+            return Err(DpopError::Replayed.into());
+        }
+        Ok(proof)
+    }
+
+    /// Checks that `token` is an unexpired access token issued by this
+    /// service at `now`, whose signature `signer` verifies.
+    pub async fn verify(
+        &self,
+        signer: &dyn AsyncSigner,
+        token: &str,
+        now: u64,
+    ) -> Result<AccessTokenClaims, OAuthError> {
+        const INVALID: OAuthError = OAuthError::InvalidToken("access token is not valid");
+        let (signing_input, signature) = token.rsplit_once('.').ok_or(INVALID)?;
+        let (header, claims) = signing_input.split_once('.').ok_or(INVALID)?;
+        let header: serde_json::Value = decode(header).ok_or(INVALID)?;
+        if header["alg"] != self.jws_alg || header["kid"] != self.kid.as_str() {
+            return Err(INVALID);
+        }
+        if !signer
+            .verify_bytes(signing_input.as_bytes(), signature)
+            .await?
+        {
+            return Err(INVALID);
+        }
+        let claims: AccessTokenClaims = decode(claims).ok_or(INVALID)?;
+        if claims.iss != self.issuer {
+            return Err(INVALID);
+        }
+        if claims.exp.saturating_add(self.skew_secs) < now {
+            return Err(OAuthError::TokenExpired {
+                skew_secs: self.skew_secs,
+            });
+        }
+        Ok(claims)
+    }
+
+    /// Signs an access token for client `id` with `signer`, valid from `now`
+    /// and bound to the DPoP key with thumbprint `jkt`, if any.
+    pub async fn issue(
+        &self,
+        signer: &dyn AsyncSigner,
+        id: &str,
+        scopes: Vec<String>,
+        jkt: Option<String>,
+        now: u64,
+    ) -> Result<AccessToken, SignError> {
+        let mut jti = [0; 16];
+        OsRng.fill_bytes(&mut jti);
+        let claims = AccessTokenClaims {
+            iss: self.issuer.clone(),
+            sub: id.to_string(),
+            aud: self.audience.clone(),
+            iat: now,
+            exp: now.saturating_add(self.ttl_secs),
+            jti: URL_SAFE_NO_PAD.encode(jti),
+            client_id: id.to_string(),
+            scope: (!scopes.is_empty()).then(|| scopes.join(" ")),
+            cnf: jkt.map(|jkt| Confirmation { jkt }),
+        };
+        let header = serde_json::json!({"alg": self.jws_alg, "kid": self.kid, "typ": "at+jwt"});
+        let signing_input = format!("{}.{}", encode(&header), encode(&claims));
+        // Asymmetric signatures are already base64url, as JWS needs them.
+        let signature = signer.sign_bytes(signing_input.as_bytes()).await?;
+        Ok(AccessToken {
+            token: format!("{signing_input}.{signature}"),
+            expires_in: self.ttl_secs,
+            scopes,
+        })
+    }
+}
+
+/// The scopes of `allowed` granted for `requested`, a space-separated list
+/// defaulting to all of them.
+fn granted(allowed: &[String], requested: Option<&str>) -> Result<Vec<String>, OAuthError> {
+    let Some(requested) = requested else {
+        return Ok(allowed.to_vec());
+    };
+    let mut granted: Vec<String> = Vec::new();
+    for scope in requested.split(' ').filter(|scope| !scope.is_empty()) {
+        if !allowed.iter().any(|allowed| allowed == scope) {
+            return Err(OAuthError::InvalidScope(format!(
+                "scope `{scope}` is not allowed for this client"
+            )));
+        }
+        if !granted.iter().any(|seen| seen == scope) {
+            granted.push(scope.to_string());
+        }
+    }
+    Ok(granted)
+}
+
+/// Base64url JSON of a JWT header or claims set.
+fn encode(value: &impl Serialize) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(value).expect("JWT parts serialize"))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(part: &str) -> Option<T> {
+    serde_json::from_slice(&URL_SAFE_NO_PAD.decode(part).ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::asymmetric::AsymmetricSigner;
+    use crate::crypto::keys::load_private_key;
+    use crate::crypto::signer::Signer;
+    use crate::refresh::MemoryRefreshTokenStore;
+
+    fn issuer() -> TokenIssuer {
+        TokenIssuer::new("https://auth.internal", "primary", "ed25519")
+            .unwrap()
+            .audience(Some("billing".into()))
+            .ttl_secs(60)
+            .client("reports", b"s3cret", vec!["read".into(), "write".into()])
+    }
+
+    #[test]
+    fn clients_are_granted_their_scopes() {
+        let issuer = issuer();
+        assert_eq!(
+            issuer.authorize("reports", "s3cret", None).unwrap(),
+            ["read", "write"]
+        );
+        assert_eq!(
+            issuer
+                .authorize("reports", "s3cret", Some("read  read"))
+                .unwrap(),
+            ["read"]
+        );
+        assert!(matches!(
+            issuer.authorize("reports", "s3cret", Some("admin")),
+            Err(OAuthError::InvalidScope(_))
+        ));
+        assert!(matches!(
+            issuer.authorize("reports", "wrong", None),
+            Err(OAuthError::InvalidClient)
+        ));
+        assert!(matches!(
+            issuer.authorize("ghost", "s3cret", None),
+            Err(OAuthError::InvalidClient)
+        ));
+    }
+
+    #[tokio::test]
+    async fn tokens_are_jws_signed_by_the_service_key() {
+        let key = load_private_key(
+            include_str!("../tests/fixtures/keys/ed25519.pem").as_bytes(),
+            None,
+        )
+        .unwrap();
+        let signer = Arc::new(AsymmetricSigner::new(key));
+        let token = issuer()
+            .issue(signer.as_ref(), "reports", vec!["read".into()], None, 1_000)
+            .await
+            .unwrap();
+        assert_eq!(token.expires_in, 60);
+
+        let parts: Vec<&str> = token.token.split('.').collect();
+        let [header, claims, signature] = parts[..] else {
+            panic!("a JWS has three parts");
+        };
+        let header: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).unwrap()).unwrap();
+        assert_eq!(
+            header,
+            serde_json::json!({"alg": "EdDSA", "kid": "primary", "typ": "at+jwt"})
+        );
+        let claims: AccessTokenClaims =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).unwrap()).unwrap();
+        assert_eq!(claims.iss, "https://auth.internal");
+        assert_eq!(claims.sub, "reports");
+        assert_eq!(claims.aud.as_deref(), Some("billing"));
+        assert_eq!((claims.iat, claims.exp), (1_000, 1_060));
+        assert_eq!(claims.scope.as_deref(), Some("read"));
+        let signing_input = token.token.rsplit_once('.').unwrap().0;
+        assert!(Signer::verify_bytes(
+            &*signer,
+            signing_input.as_bytes(),
+            signature
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_tokens_are_single_use_and_revocable() {
+        let store = Arc::new(MemoryRefreshTokenStore::new());
+        let issuer = issuer().refresh_tokens(store, 3_600);
+        let scopes = vec!["read".to_string(), "write".to_string()];
+        let token = issuer
+            .refresh_token("reports", scopes.clone(), 1_000)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (kept, granted) = issuer
+            .redeem("reports", "s3cret", &token, Some("read"), 1_000)
+            .await
+            .unwrap();
+        assert_eq!((kept, granted), (scopes.clone(), vec!["read".to_string()]));
+        assert!(matches!(
+            issuer
+                .redeem("reports", "s3cret", &token, None, 1_000)
+                .await,
+            Err(OAuthError::InvalidGrant)
+        ));
+
+        // Refused redemptions leave the token usable by its client.
+        let token = issuer
+            .refresh_token("reports", scopes.clone(), 1_000)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            issuer
+                .redeem("reports", "s3cret", &token, Some("admin"), 1_000)
+                .await,
+            Err(OAuthError::InvalidScope(_))
+        ));
+        assert!(matches!(
+            issuer.redeem("reports", "wrong", &token, None, 1_000).await,
+            Err(OAuthError::InvalidClient)
+        ));
+        issuer
+            .revoke("reports", "s3cret", &token, 1_000)
+            .await
+            .unwrap();
+        assert!(matches!(
+            issuer
+                .redeem("reports", "s3cret", &token, None, 1_000)
+                .await,
+            Err(OAuthError::InvalidGrant)
+        ));
+
+        let token = issuer
+            .refresh_token("reports", scopes, 1_000)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            issuer
+                .redeem("reports", "s3cret", &token, None, 4_601)
+                .await,
+            Err(OAuthError::InvalidGrant)
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_tokens_are_off_by_default() {
+        let issuer = issuer();
+        assert_eq!(
+            issuer
+                .refresh_token("reports", Vec::new(), 1_000)
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(matches!(
+            issuer
+                .redeem("reports", "s3cret", "token", None, 1_000)
+                .await,
+            Err(OAuthError::UnsupportedGrantType)
+        ));
+    }
+
+    #[tokio::test]
+    async fn issued_tokens_verify_until_they_expire() {
+        let key = load_private_key(
+            include_str!("../tests/fixtures/keys/ed25519.pem").as_bytes(),
+            None,
+        )
+        .unwrap();
+        let signer = Arc::new(AsymmetricSigner::new(key));
+        let issuer = issuer();
+        let token = issuer
+            .issue(
+                signer.as_ref(),
+                "reports",
+                Vec::new(),
+                Some("jkt".into()),
+                1_000,
+            )
+            .await
+            .unwrap()
+            .token;
+
+        let claims = issuer.verify(signer.as_ref(), &token, 1_060).await.unwrap();
+        assert_eq!(claims.cnf, Some(Confirmation { jkt: "jkt".into() }));
+        assert_eq!(claims.scope, None);
+        assert!(matches!(
+            issuer.verify(signer.as_ref(), &token, 1_061).await,
+            Err(OAuthError::TokenExpired { skew_secs: 0 })
+        ));
+        let other = TokenIssuer::new("https://other.internal", "primary", "ed25519").unwrap();
+        assert!(matches!(
+            other.verify(signer.as_ref(), &token, 1_000).await,
+            Err(OAuthError::InvalidToken(_))
+        ));
+        let tampered = format!("{}x", &token[..token.len() - 1]);
+        assert!(matches!(
+            issuer.verify(signer.as_ref(), &tampered, 1_000).await,
+            Err(OAuthError::InvalidToken(_))
+        ));
+    }
+
+    #[test]
+    fn discovery_points_below_the_issuer() {
+        let document = TokenIssuer::new("https://auth.internal/", "primary", "ecdsa-p256-sha256")
+            .unwrap()
+            .client("a", b"s", vec!["write".into(), "read".into()])
+            .client("b", b"s", vec!["read".into()])
+            .discovery();
+        assert_eq!(document.issuer, "https://auth.internal/");
+        assert_eq!(
+            document.jwks_uri,
+            "https://auth.internal/.well-known/jwks.json"
+        );
+        assert_eq!(document.token_endpoint, "https://auth.internal/oauth/token");
+        assert_eq!(document.scopes_supported, ["read", "write"]);
+        assert_eq!(document.id_token_signing_alg_values_supported, ["ES256"]);
+        assert_eq!(document.grant_types_supported, ["client_credentials"]);
+        assert_eq!(document.revocation_endpoint, None);
+        assert_eq!(
+            document.dpop_signing_alg_values_supported,
+            ["RS256", "ES256", "EdDSA"]
+        );
+    }
+
+    #[test]
+    fn only_asymmetric_algorithms_have_a_jws_name() {
+        assert!(TokenIssuer::new("iss", "kid", "hmac-sha256").is_none());
+        assert_eq!(jws_algorithm("rsa-v1_5-sha256"), Some("RS256"));
+        assert_eq!(jws_algorithm("ecdsa-p256-sha256"), Some("ES256"));
+    }
+}
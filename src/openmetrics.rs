@@ -0,0 +1,224 @@
+//! The counters of `GET /metrics` in the OpenMetrics text format, for
+//! Prometheus-compatible scrapers: operations served by each key, the
+//! failures that matter most for security, and uses of legacy formats.
+
+use std::fmt::Write as _;
+
+use crate::models::{KeyUsageStats, LegacyFormatStats, SecurityEventStats};
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Renders key usage, security events and legacy format uses as an
+/// OpenMetrics exposition.
+pub fn render(
+    keys: &[KeyUsageStats],
+    events: &[SecurityEventStats],
+    legacy: &[LegacyFormatStats],
+) -> String {
+    let mut out = String::new();
+    family(
+        &mut out,
+        "take_home_key_operations",
+        "Operations served by each key.",
+    );
+    for key in keys {
+        for (operation, count) in [
+            ("sign", key.sign),
+            ("verify", key.verify),
+            ("encrypt", key.encrypt),
+            ("decrypt", key.decrypt),
+        ] {
+            let mut labels = key_labels(key);
+            labels.push(("operation", operation));
+            sample(&mut out, "take_home_key_operations", &labels, count);
+        }
+    }
+
+    family(
+        &mut out,
+        "take_home_verify_failures",
+        "Signatures that failed verification, by scheme and reason.",
+    );
+    for key in keys.iter().filter(|key| key.verify > 0) {
+        let mut labels = vec![("scheme", "payload"), ("reason", "mismatch")];
+        labels.extend(key_labels(key));
+        sample(
+            &mut out,
+            "take_home_verify_failures",
+            &labels,
+            key.verify_failures,
+        );
+    }
+    for event in events
+        .iter()
+        .filter(|event| event.event == "verify_failure")
+    {
+        let mut labels = Vec::new();
+        labels.extend(event.scheme.as_deref().map(|scheme| ("scheme", scheme)));
+        labels.extend(event.reason.as_deref().map(|reason| ("reason", reason)));
+        labels.extend(event_labels(event));
+        sample(&mut out, "take_home_verify_failures", &labels, event.count);
+    }
+
+    family(
+        &mut out,
+        "take_home_decrypt_auth_failures",
+        "Ciphertexts that failed authentication.",
+    );
+    for key in keys
+        .iter()
+        .filter(|key| key.decrypt > 0 || key.decrypt_failures > 0)
+    {
+        sample(
+            &mut out,
+            "take_home_decrypt_auth_failures",
+            &key_labels(key),
+            key.decrypt_failures,
+        );
+    }
+
+    family(
+        &mut out,
+        "take_home_key_not_found",
+        "Requests naming a key id, access key or tenant that is not known.",
+    );
+    for event in events.iter().filter(|event| event.event == "key_not_found") {
+        sample(
+            &mut out,
+            "take_home_key_not_found",
+            &event_labels(event),
+            event.count,
+        );
+    }
+
+    family(
+        &mut out,
+        "take_home_legacy_format_uses",
+        "Requests using a deprecated format, by format and outcome.",
+    );
+    for stats in legacy {
+        for (outcome, count) in [("accepted", stats.used), ("rejected", stats.rejected)] {
+            let labels = [("format", stats.format.as_str()), ("outcome", outcome)];
+            sample(&mut out, "take_home_legacy_format_uses", &labels, count);
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+fn family(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {name} counter");
+    let _ = writeln!(out, "# HELP {name} {help}");
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: u64) {
+    let _ = write!(out, "{name}_total");
+    if !labels.is_empty() {
+        out.push('{');
+        for (index, (label, value)) in labels.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{label}=\"");
+            escape(out, value);
+            out.push('"');
+        }
+        out.push('}');
+    }
+    let _ = writeln!(out, " {value}");
+}
+
+/// Tenant keys are metered as `tenant:<id>`; they are labeled by tenant.
+fn key_labels(key: &KeyUsageStats) -> Vec<(&'static str, &str)> {
+    let owner = match key.key_id.strip_prefix("tenant:") {
+        Some(tenant) => ("tenant", tenant),
+        None => ("kid", key.key_id.as_str()),
+    };
+    vec![owner, ("algorithm", key.algorithm.as_str())]
+}
+
+fn event_labels(event: &SecurityEventStats) -> Vec<(&'static str, &str)> {
+    let mut labels = Vec::new();
+    labels.extend(event.kid.as_deref().map(|kid| ("kid", kid)));
+    labels.extend(event.tenant.as_deref().map(|tenant| ("tenant", tenant)));
+    labels
+}
+
+fn escape(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key_id: &str, verify: u64, verify_failures: u64) -> KeyUsageStats {
+        KeyUsageStats {
+            key_id: key_id.into(),
+            algorithm: "hmac-sha256".into(),
+            sign: 1,
+            verify,
+            encrypt: 0,
+            decrypt: 0,
+            verify_failures,
+            decrypt_failures: 0,
+            last_used: None,
+        }
+    }
+
+    #[test]
+    fn renders_counters_with_labels() {
+        let keys = [key("default", 3, 1), key("tenant:acme", 0, 0)];
+        let events = [
+            SecurityEventStats {
+                event: "verify_failure".into(),
+                scheme: Some("webhook".into()),
+                reason: Some("expired".into()),
+                kid: Some("stripe".into()),
+                tenant: None,
+                count: 2,
+            },
+            SecurityEventStats {
+                event: "key_not_found".into(),
+                scheme: None,
+                reason: None,
+                kid: None,
+                tenant: Some("ghost".into()),
+                count: 1,
+            },
+        ];
+        let legacy = [LegacyFormatStats {
+            format: "bare_signature".into(),
+            used: 4,
+            rejected: 0,
+        }];
+        let text = render(&keys, &events, &legacy);
+        for line in [
+            "# TYPE take_home_key_operations counter",
+            r#"take_home_key_operations_total{kid="default",algorithm="hmac-sha256",operation="verify"} 3"#,
+            r#"take_home_key_operations_total{tenant="acme",algorithm="hmac-sha256",operation="sign"} 1"#,
+            r#"take_home_verify_failures_total{scheme="payload",reason="mismatch",kid="default",algorithm="hmac-sha256"} 1"#,
+            r#"take_home_verify_failures_total{scheme="webhook",reason="expired",kid="stripe"} 2"#,
+            r#"take_home_key_not_found_total{tenant="ghost"} 1"#,
+            r#"take_home_legacy_format_uses_total{format="bare_signature",outcome="accepted"} 4"#,
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line}\n{text}");
+        }
+        assert!(!text.contains(r#"reason="mismatch",tenant="acme""#));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let mut out = String::new();
+        escape(&mut out, "a\"b\\c\nd");
+        assert_eq!(out, r#"a\"b\\c\nd"#);
+    }
+}
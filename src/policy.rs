@@ -0,0 +1,527 @@
+//! Authorization of requests against per-endpoint, per-key and per-tenant
+//! rules, such as "team-payments may sign with kid `payments-*` only". Each
+//! data-plane and admin request is described as an [`AccessRequest`] and
+//! decided by a [`PolicyBackend`] before it reaches its handler: the
+//! built-in [`RulePolicy`] from `[[policy.rules]]`, or [`OpaPolicy`], which
+//! asks an Open Policy Agent over HTTP. Denied requests get
+//! `403 forbidden`; when the backend cannot decide, requests are refused
+//! with `503 policy_unavailable`.
+//!
+//! Callers are named by their `X-Client-Id` and `X-Tenant-Id` headers,
+//! which are not authenticated: put the service behind something that sets
+//! them before relying on rules about them, or write rules about the
+//! [API key](crate::api_keys) a request presented instead.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{Method, Uri, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use serde::{Deserialize, Serialize};
+
+use crate::api_keys::ApiKey;
+use crate::crypto::BoxFuture;
+use crate::error::Error;
+use crate::layers::{CLIENT_ID_HEADER, TENANT_ID_HEADER};
+use crate::state::AppState;
+
+/// Largest OPA response read.
+const MAX_OPA_RESPONSE_BYTES: usize = 64 * 1024;
+
+/// What a request does with a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Encrypt,
+    Decrypt,
+    Sign,
+    Verify,
+    /// Any protected admin endpoint.
+    Admin,
+}
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Encrypt => "encrypt",
+            Action::Decrypt => "decrypt",
+            Action::Sign => "sign",
+            Action::Verify => "verify",
+            Action::Admin => "admin",
+        }
+    }
+
+    /// The action of a data-plane `method` request to `path`; `None` for
+    /// endpoints that use no key, such as `/canonicalize`, `/testvectors`,
+    /// `/transparency/*` and the JWKS.
+    pub fn of(method: &Method, path: &str) -> Option<Self> {
+        match path {
+            "/encrypt" | "/encrypt/pointers" | "/encrypt/patch" | "/blobs" => Some(Action::Encrypt),
+            "/decrypt" | "/decrypt/pointers" => Some(Action::Decrypt),
+            "/sign" | "/http-signatures/sign" => Some(Action::Sign),
+            "/verify"
+            | "/verify/batch"
+            | "/http-signatures/verify"
+            | "/sigv4/verify"
+            | "/challenge"
+            | "/challenge/respond"
+            | "/oauth/dpop/verify" => Some(Action::Verify),
+            _ if path.starts_with("/webhooks/verify/") => Some(Action::Verify),
+            _ if path.starts_with("/blobs/") => Some(Action::Decrypt),
+            _ if path.starts_with("/vault/") => match *method {
+                Method::GET | Method::HEAD => Some(Action::Decrypt),
+                _ => Some(Action::Encrypt),
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A request as seen by the policy, and the `input` of OPA queries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccessRequest {
+    pub action: Action,
+    pub method: String,
+    /// Request path, e.g. `/sign` or `/vault/db-password`.
+    pub path: String,
+    /// `X-Client-Id`.
+    pub client: Option<String>,
+    /// Name of the [API key](crate::api_keys) the request presented.
+    pub api_key: Option<String>,
+    /// `X-Tenant-Id`.
+    pub tenant: Option<String>,
+    /// Key serving the request: `tenant:<id>` for tenant keys,
+    /// `signing.key_id` for the service's signing keys and `encryption` for
+    /// its encryptor. Absent for admin requests and where the key is named
+    /// inside the request, as with HTTP message signatures, SigV4 and
+    /// webhooks.
+    pub kid: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("policy backend failed: {0}")]
+    Backend(String),
+    #[error("policy backend did not respond in time")]
+    Timeout,
+}
+
+pub trait PolicyBackend: Send + Sync {
+    /// Whether `request` may proceed.
+    fn allows<'a>(&'a self, request: &'a AccessRequest)
+    -> BoxFuture<'a, Result<bool, PolicyError>>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Allow,
+    #[default]
+    Deny,
+}
+
+/// A rule of [`RulePolicy`]. It matches a request when every non-empty list
+/// has an entry matching it; patterns may use `*` for any run of
+/// characters. A list of clients, API keys, tenants or kids never matches a
+/// request without one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyRule {
+    pub effect: Effect,
+    pub actions: Vec<Action>,
+    /// Request path patterns, e.g. `/vault/payments-*`.
+    pub endpoints: Vec<String>,
+    pub clients: Vec<String>,
+    /// Names of API keys.
+    pub api_keys: Vec<String>,
+    pub tenants: Vec<String>,
+    pub kids: Vec<String>,
+}
+
+impl PolicyRule {
+    pub fn matches(&self, request: &AccessRequest) -> bool {
+        let any = |patterns: &[String], value: Option<&str>| {
+            patterns.is_empty()
+                || value.is_some_and(|value| patterns.iter().any(|p| glob_matches(p, value)))
+        };
+        (self.actions.is_empty() || self.actions.contains(&request.action))
+            && any(&self.endpoints, Some(&request.path))
+            && any(&self.clients, request.client.as_deref())
+            && any(&self.api_keys, request.api_key.as_deref())
+            && any(&self.tenants, request.tenant.as_deref())
+            && any(&self.kids, request.kid.as_deref())
+    }
+}
+
+/// Whether `value` matches `pattern`, where `*` stands for any run of
+/// characters.
+fn glob_matches(pattern: &str, value: &str) -> bool {
+    let Some((prefix, rest)) = pattern.split_once('*') else {
+        return pattern == value;
+    };
+    let Some(mut value) = value.strip_prefix(prefix) else {
+        return false;
+    };
+    let mut parts = rest.split('*').peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            return value.ends_with(part);
+        }
+        match value.find(part) {
+            Some(at) => value = &value[at + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Rules evaluated in order; the first one matching a request decides it,
+/// and requests no rule matches get the default effect.
+#[derive(Debug, Clone, Default)]
+pub struct RulePolicy {
+    rules: Vec<PolicyRule>,
+    default: Effect,
+}
+
+impl RulePolicy {
+    pub fn new(rules: Vec<PolicyRule>, default: Effect) -> Self {
+        Self { rules, default }
+    }
+
+    pub fn decide(&self, request: &AccessRequest) -> Effect {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(request))
+            .map_or(self.default, |rule| rule.effect)
+    }
+}
+
+impl PolicyBackend for RulePolicy {
+    fn allows<'a>(
+        &'a self,
+        request: &'a AccessRequest,
+    ) -> BoxFuture<'a, Result<bool, PolicyError>> {
+        Box::pin(async move { Ok(self.decide(request) == Effect::Allow) })
+    }
+}
+
+/// Asks an Open Policy Agent, POSTing `{"input": <AccessRequest>}` to a
+/// decision URL such as `http://opa:8181/v1/data/take_home/allow`. A
+/// `result` of `true`, or an object with `"allow": true`, allows the
+/// request; anything else, including an undefined decision, denies it.
+pub struct OpaPolicy {
+    url: Uri,
+    timeout: Duration,
+    client: Client<HttpConnector, Body>,
+}
+
+#[derive(Serialize)]
+struct OpaQuery<'a> {
+    input: &'a AccessRequest,
+}
+
+#[derive(Deserialize)]
+struct OpaResponse {
+    result: Option<serde_json::Value>,
+}
+
+impl OpaPolicy {
+    pub fn new(url: Uri, timeout: Duration) -> Self {
+        Self {
+            url,
+            timeout,
+            client: Client::builder(TokioExecutor::new()).build_http(),
+        }
+    }
+
+    async fn query(&self, request: &AccessRequest) -> Result<bool, PolicyError> {
+        let body = serde_json::to_vec(&OpaQuery { input: request }).expect("requests serialize");
+        let request = axum::http::Request::post(self.url.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .expect("a valid request");
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|err| PolicyError::Backend(err.to_string()))?;
+        if !response.status().is_success() {
+            return Err(PolicyError::Backend(format!(
+                "status {}",
+                response.status()
+            )));
+        }
+        let body = axum::body::to_bytes(Body::new(response.into_body()), MAX_OPA_RESPONSE_BYTES)
+            .await
+            .map_err(|err| PolicyError::Backend(err.to_string()))?;
+        let response: OpaResponse =
+            serde_json::from_slice(&body).map_err(|err| PolicyError::Backend(err.to_string()))?;
+        Ok(allowed(response.result))
+    }
+}
+
+/// Whether an OPA decision allows the request.
+fn allowed(result: Option<serde_json::Value>) -> bool {
+    match result {
+        Some(serde_json::Value::Bool(allow)) => allow,
+        Some(serde_json::Value::Object(decision)) => {
+            decision.get("allow") == Some(&serde_json::Value::Bool(true))
+        }
+        _ => false,
+    }
+}
+
+impl PolicyBackend for OpaPolicy {
+    fn allows<'a>(
+        &'a self,
+        request: &'a AccessRequest,
+    ) -> BoxFuture<'a, Result<bool, PolicyError>> {
+        Box::pin(async move {
+            tokio::time::timeout(self.timeout, self.query(request))
+                .await
+                .map_err(|_| PolicyError::Timeout)?
+        })
+    }
+}
+
+impl From<PolicyError> for Error {
+    fn from(err: PolicyError) -> Self {
+        Error::PolicyUnavailable(err.to_string())
+    }
+}
+
+/// Middleware deciding data-plane requests with [`AppState::policy`].
+pub async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    match Action::of(request.method(), request.uri().path()) {
+        Some(action) => check(&state, action, request, next).await,
+        None => next.run(request).await,
+    }
+}
+
+/// Middleware deciding protected admin requests with
+/// [`AppState::policy`].
+pub async fn authorize_admin(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    check(&state, Action::Admin, request, next).await
+}
+
+async fn check(state: &AppState, action: Action, request: Request, next: Next) -> Response {
+    let Some(policy) = &state.policy else {
+        return next.run(request).await;
+    };
+    let access = access_request(state, action, &request);
+    match policy.allows(&access).await {
+        Ok(true) => next.run(request).await,
+        Ok(false) => {
+            tracing::info!(
+                target: "policy",
+                action = %access.action,
+                path = %access.path,
+                client = access.client.as_deref(),
+                tenant = access.tenant.as_deref(),
+                kid = access.kid.as_deref(),
+                "request denied"
+            );
+            Error::Forbidden("request denied by policy".into()).into_response()
+        }
+        Err(err) => Error::from(err).into_response(),
+    }
+}
+
+fn access_request(state: &AppState, action: Action, request: &Request) -> AccessRequest {
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let path = request.uri().path();
+    let tenant = header(TENANT_ID_HEADER);
+    AccessRequest {
+        action,
+        method: request.method().to_string(),
+        path: path.to_string(),
+        client: header(CLIENT_ID_HEADER),
+        api_key: request
+            .extensions()
+            .get::<Arc<ApiKey>>()
+            .map(|key| key.name.clone()),
+        kid: key_id(state, action, path, tenant.as_deref()),
+        tenant,
+    }
+}
+
+/// The key serving a request to `path`, if it is known before the body is
+/// read.
+#[cfg_attr(
+    not(all(feature = "tenancy", feature = "encryption")),
+    allow(unused_variables)
+)]
+fn key_id(state: &AppState, action: Action, path: &str, tenant: Option<&str>) -> Option<String> {
+    match action {
+        #[cfg(feature = "signing")]
+        Action::Sign | Action::Verify if matches!(path, "/sign" | "/verify" | "/verify/batch") => {
+            #[cfg(feature = "tenancy")]
+            if let (Some(tenant), Some(_)) = (tenant, &state.tenants) {
+                return Some(format!("tenant:{tenant}"));
+            }
+            Some(state.key_id.clone())
+        }
+        #[cfg(feature = "encryption")]
+        Action::Encrypt | Action::Decrypt => Some(crate::state::ENCRYPTION_KEY_ID.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(action: Action, client: &str, kid: &str) -> AccessRequest {
+        AccessRequest {
+            action,
+            method: "POST".into(),
+            path: format!("/{action}"),
+            client: Some(client.into()),
+            api_key: None,
+            tenant: None,
+            kid: Some(kid.into()),
+        }
+    }
+
+    fn rule(effect: Effect) -> PolicyRule {
+        PolicyRule {
+            effect,
+            ..PolicyRule::default()
+        }
+    }
+
+    #[test]
+    fn globs_match_any_run_of_characters() {
+        assert!(glob_matches("payments-*", "payments-eu"));
+        assert!(glob_matches("payments-*", "payments-"));
+        assert!(glob_matches("*-eu", "payments-eu"));
+        assert!(glob_matches("p*-*u", "payments-eu"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("exact", "exact"));
+        assert!(!glob_matches("payments-*", "billing-eu"));
+        assert!(!glob_matches("a*a", "a"));
+        assert!(!glob_matches("exact", "exactly"));
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        // Team payments may sign with its own keys only.
+        let policy = RulePolicy::new(
+            vec![
+                PolicyRule {
+                    actions: vec![Action::Sign],
+                    clients: vec!["team-payments".into()],
+                    kids: vec!["payments-*".into()],
+                    ..rule(Effect::Allow)
+                },
+                PolicyRule {
+                    clients: vec!["team-payments".into()],
+                    ..rule(Effect::Deny)
+                },
+                rule(Effect::Allow),
+            ],
+            Effect::Deny,
+        );
+        let decide = |action, client, kid| policy.decide(&request(action, client, kid));
+
+        assert_eq!(
+            decide(Action::Sign, "team-payments", "payments-1"),
+            Effect::Allow
+        );
+        assert_eq!(
+            decide(Action::Sign, "team-payments", "billing-1"),
+            Effect::Deny
+        );
+        assert_eq!(
+            decide(Action::Verify, "team-payments", "payments-1"),
+            Effect::Deny
+        );
+        assert_eq!(
+            decide(Action::Sign, "team-billing", "billing-1"),
+            Effect::Allow
+        );
+        assert_eq!(
+            RulePolicy::default().decide(&request(Action::Sign, "a", "b")),
+            Effect::Deny
+        );
+    }
+
+    #[test]
+    fn lists_never_match_missing_values() {
+        let rule = PolicyRule {
+            tenants: vec!["*".into()],
+            ..rule(Effect::Allow)
+        };
+        let mut request = request(Action::Sign, "client", "kid");
+        assert!(!rule.matches(&request));
+        request.tenant = Some("acme".into());
+        assert!(rule.matches(&request));
+
+        let rule = PolicyRule {
+            api_keys: vec!["partner-*".into()],
+            ..rule
+        };
+        assert!(!rule.matches(&request));
+        request.api_key = Some("partner-acme".into());
+        assert!(rule.matches(&request));
+    }
+
+    #[test]
+    fn endpoints_map_to_actions() {
+        let cases = [
+            (Method::POST, "/encrypt", Some(Action::Encrypt)),
+            (Method::POST, "/decrypt/pointers", Some(Action::Decrypt)),
+            (Method::PUT, "/blobs", Some(Action::Encrypt)),
+            (Method::GET, "/blobs/abc", Some(Action::Decrypt)),
+            (Method::GET, "/vault/db", Some(Action::Decrypt)),
+            (Method::DELETE, "/vault/db", Some(Action::Encrypt)),
+            (Method::POST, "/sign", Some(Action::Sign)),
+            (
+                Method::POST,
+                "/webhooks/verify/stripe",
+                Some(Action::Verify),
+            ),
+            (Method::POST, "/canonicalize", None),
+            (Method::GET, "/testvectors", None),
+            (Method::GET, "/transparency/roots", None),
+            (Method::GET, "/.well-known/jwks.json", None),
+        ];
+        for (method, path, action) in cases {
+            assert_eq!(Action::of(&method, path), action, "{method} {path}");
+        }
+    }
+
+    #[test]
+    fn opa_decisions_allow_only_true() {
+        let decide =
+            |json: &str| allowed(serde_json::from_str::<OpaResponse>(json).unwrap().result);
+        assert!(decide(r#"{"result": true}"#));
+        assert!(decide(r#"{"result": {"allow": true, "reason": "ok"}}"#));
+        assert!(!decide(r#"{"result": false}"#));
+        assert!(!decide(r#"{"result": {"allow": "yes"}}"#));
+        assert!(!decide("{}"));
+    }
+}
@@ -0,0 +1,38 @@
+//! Lazily opened Postgres connection shared by the Postgres backends.
+
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use tokio_postgres::{Client, Config, NoTls};
+
+/// Opened on first use and reopened after it drops. Connections are not
+/// encrypted.
+pub(crate) struct Connection {
+    config: Config,
+    client: Mutex<Option<Arc<Client>>>,
+}
+
+impl Connection {
+    pub(crate) fn new(url: &str) -> Result<Self, tokio_postgres::Error> {
+        Ok(Self {
+            config: url.parse()?,
+            client: Mutex::new(None),
+        })
+    }
+
+    /// The open client, connecting first if needed. `Ok((client, true))`
+    /// when this call opened it.
+    pub(crate) async fn client(&self) -> Result<(Arc<Client>, bool), tokio_postgres::Error> {
+        let mut client = self.client.lock().await;
+        if let Some(open) = client.as_ref().filter(|open| !open.is_closed()) {
+            return Ok((open.clone(), false));
+        }
+        let (opened, connection) = self.config.connect(NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                tracing::warn!(error = %err, "database connection failed");
+            }
+        });
+        Ok((client.insert(Arc::new(opened)).clone(), true))
+    }
+}
@@ -0,0 +1,481 @@
+//! Hourly and daily operation quotas per API key and per tenant, so teams
+//! sharing the service cannot use up its capacity or a partner's budget.
+//! Every request that uses a key counts as one operation against the
+//! quotas of its authenticated [API key](crate::api_keys) and of the tenant
+//! its `X-Tenant-Id` names, once [tenancy](crate::tenancy) knows that tenant:
+//! ids the tenant source does not know are not counted, since their
+//! requests are refused anyway and counting them would let any caller
+//! create counters.
+//! Windows are fixed UTC hours and days. Counters live in a
+//! [`CounterStore`]: [`MemoryCounterStore`] is per-process; with several
+//! replicas use [`RedisCounterStore`] so they share one budget.
+//!
+//! Responses carry the tightest quota in `X-RateLimit-Limit`,
+//! `X-RateLimit-Remaining` and `X-RateLimit-Reset`; requests over it get
+//! `429 quota_exceeded` with a `Retry-After`. When the store fails, requests
+//! are let through and the failure is logged, so quotas never take the
+//! service down.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::api_keys::ApiKey;
+use crate::crypto::BoxFuture;
+use crate::error::Error;
+use crate::policy::Action;
+use crate::state::AppState;
+
+pub const LIMIT_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+pub const REMAINING_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+/// Unix time in seconds at which the window ends.
+pub const RESET_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// Limits entry applying to API keys or tenants without one of their own.
+pub const ANY: &str = "*";
+
+#[derive(Debug, thiserror::Error)]
+pub enum QuotaError {
+    #[error("quota store failed: {0}")]
+    Backend(String),
+    #[error("quota store holds its maximum of {0} counters")]
+    Full(usize),
+}
+
+/// Operations allowed per window; `None` for no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaLimits {
+    pub hourly: Option<u64>,
+    pub daily: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Hour,
+    Day,
+}
+
+impl Period {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Period::Hour => "hour",
+            Period::Day => "day",
+        }
+    }
+
+    pub fn secs(self) -> u64 {
+        match self {
+            Period::Hour => 60 * 60,
+            Period::Day => 24 * 60 * 60,
+        }
+    }
+}
+
+pub trait CounterStore: Send + Sync {
+    /// Adds one to the counter `key`, created with a lifetime of `ttl`, and
+    /// returns its new value.
+    fn increment<'a>(
+        &'a self,
+        key: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<u64, QuotaError>>;
+}
+
+/// Keeps counters in memory; they are lost on restart and not shared
+/// between replicas.
+#[derive(Debug)]
+pub struct MemoryCounterStore {
+    counters: Mutex<Counters>,
+    max_counters: usize,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    values: HashMap<String, (u64, Instant)>,
+    /// Size from which expired counters are swept on the next insert.
+    sweep_at: usize,
+}
+
+impl MemoryCounterStore {
+    pub const DEFAULT_MAX_COUNTERS: usize = 100_000;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Most live counters kept; past it, counters for new keys are refused
+    /// with [`QuotaError::Full`] until others expire.
+    pub fn max_counters(mut self, max_counters: usize) -> Self {
+        self.max_counters = max_counters;
+        self
+    }
+}
+
+impl Default for MemoryCounterStore {
+    fn default() -> Self {
+        Self {
+            counters: Mutex::default(),
+            max_counters: Self::DEFAULT_MAX_COUNTERS,
+        }
+    }
+}
+
+impl CounterStore for MemoryCounterStore {
+    fn increment<'a>(
+        &'a self,
+        key: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<u64, QuotaError>> {
+        Box::pin(async move {
+            let now = Instant::now();
+            // The map holds no invariant a panicking holder could break.
+            let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+            let full = counters.values.len() >= self.max_counters;
+            if full || counters.values.len() >= counters.sweep_at {
+                counters.values.retain(|_, (_, expiry)| *expiry > now);
+                counters.sweep_at = (counters.values.len() * 2).max(1024);
+            }
+            if counters.values.len() >= self.max_counters && !counters.values.contains_key(key) {
+                return Err(QuotaError::Full(self.max_counters));
+            }
+            let expiry = now.checked_add(ttl).unwrap_or(now);
+            let (count, expires) = counters
+                .values
+                .entry(key.to_string())
+                .or_insert((0, expiry));
+            if *expires <= now {
+                *count = 0;
+                *expires = expiry;
+            }
+            *count += 1;
+            Ok(*count)
+        })
+    }
+}
+
+/// Counts in `<prefix><key>` with `SET NX PX` and `INCR` in one
+/// transaction, which Redis applies atomically across all replicas.
+#[cfg(feature = "redis")]
+pub struct RedisCounterStore {
+    client: redis::Client,
+    connection: tokio::sync::OnceCell<redis::aio::ConnectionManager>,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisCounterStore {
+    /// Parses `url`; the connection is opened on first use.
+    pub fn new(url: &str, prefix: impl Into<String>) -> Result<Self, QuotaError> {
+        Ok(Self {
+            client: redis::Client::open(url).map_err(backend)?,
+            connection: tokio::sync::OnceCell::new(),
+            prefix: prefix.into(),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl CounterStore for RedisCounterStore {
+    fn increment<'a>(
+        &'a self,
+        key: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<u64, QuotaError>> {
+        Box::pin(async move {
+            // The manager reconnects by itself once established.
+            let mut connection = self
+                .connection
+                .get_or_try_init(|| self.client.get_connection_manager())
+                .await
+                .map_err(backend)?
+                .clone();
+            let key = format!("{}{key}", self.prefix);
+            // Redis rejects a zero expiry.
+            let millis = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1);
+            let (count,): (u64,) = redis::pipe()
+                .atomic()
+                .cmd("SET")
+                .arg(&key)
+                .arg(0)
+                .arg("NX")
+                .arg("PX")
+                .arg(millis)
+                .ignore()
+                .cmd("INCR")
+                .arg(&key)
+                .query_async(&mut connection)
+                .await
+                .map_err(backend)?;
+            Ok(count)
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+fn backend(err: redis::RedisError) -> QuotaError {
+    QuotaError::Backend(err.to_string())
+}
+
+/// Standing of a request against one quota.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Usage {
+    pub limit: u64,
+    /// Operations left in the window after this one.
+    pub remaining: u64,
+    /// Unix time in seconds at which the window ends.
+    pub reset: u64,
+    pub exceeded: bool,
+}
+
+impl Usage {
+    /// `X-RateLimit-*` headers, and `Retry-After` once exceeded.
+    fn headers(&self, now: u64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(LIMIT_HEADER, HeaderValue::from(self.limit));
+        headers.insert(REMAINING_HEADER, HeaderValue::from(self.remaining));
+        headers.insert(RESET_HEADER, HeaderValue::from(self.reset));
+        if self.exceeded {
+            let wait = self.reset.saturating_sub(now).max(1);
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(wait));
+        }
+        headers
+    }
+}
+
+/// The quotas of API keys and tenants, and where they are counted.
+pub struct Quotas {
+    store: Arc<dyn CounterStore>,
+    api_keys: BTreeMap<String, QuotaLimits>,
+    tenants: BTreeMap<String, QuotaLimits>,
+}
+
+impl Quotas {
+    pub fn new(store: Arc<dyn CounterStore>) -> Self {
+        Self {
+            store,
+            api_keys: BTreeMap::new(),
+            tenants: BTreeMap::new(),
+        }
+    }
+
+    /// Limits the API key `name`, or every key without limits of its own
+    /// if `name` is [`ANY`].
+    pub fn api_key(mut self, name: &str, limits: QuotaLimits) -> Self {
+        self.api_keys.insert(name.to_string(), limits);
+        self
+    }
+
+    /// Limits the tenant `id`, or every tenant without limits of its own if
+    /// `id` is [`ANY`].
+    pub fn tenant(mut self, id: &str, limits: QuotaLimits) -> Self {
+        self.tenants.insert(id.to_string(), limits);
+        self
+    }
+
+    /// Counts one operation by `api_key` for `tenant` at `now`, in Unix
+    /// seconds, against every quota that applies. Returns the quota the
+    /// request exceeded, or else the one with the fewest operations left.
+    pub async fn consume(
+        &self,
+        api_key: Option<&str>,
+        tenant: Option<&str>,
+        now: u64,
+    ) -> Result<Option<Usage>, QuotaError> {
+        let subjects = [
+            ("key", api_key, &self.api_keys),
+            ("tenant", tenant, &self.tenants),
+        ];
+        let mut tightest: Option<Usage> = None;
+        for (kind, name, limits) in subjects {
+            let Some(name) = name else { continue };
+            let Some(limits) = limits.get(name).or_else(|| limits.get(ANY)) else {
+                continue;
+            };
+            let periods = [(Period::Hour, limits.hourly), (Period::Day, limits.daily)];
+            for (period, limit) in periods {
+                let Some(limit) = limit else { continue };
+                let start = now - now % period.secs();
+                let reset = start + period.secs();
+                let key = format!("{kind}:{name}:{}:{start}", period.as_str());
+                let ttl = Duration::from_secs(reset - now);
+                let count = self.store.increment(&key, ttl).await?;
+                let usage = Usage {
+                    limit,
+                    remaining: limit.saturating_sub(count),
+                    reset,
+                    exceeded: count > limit,
+                };
+                let tighter = tightest.as_ref().is_none_or(|tightest| {
+                    (usage.exceeded, std::cmp::Reverse(usage.remaining))
+                        > (tightest.exceeded, std::cmp::Reverse(tightest.remaining))
+                });
+                if tighter {
+                    tightest = Some(usage);
+                }
+            }
+        }
+        Ok(tightest)
+    }
+}
+
+/// The tenant `X-Tenant-Id` names, if the tenant source knows it. Its
+/// signers are cached, so the handler resolving them again costs nothing.
+#[cfg(feature = "tenancy")]
+async fn known_tenant(state: &AppState, headers: &HeaderMap) -> Option<String> {
+    use crate::tenancy::TENANT_ID_HEADER;
+
+    let tenant = headers.get(TENANT_ID_HEADER)?.to_str().ok()?;
+    state.tenants.as_ref()?.signers(tenant).await.ok()?;
+    Some(tenant.to_string())
+}
+
+/// Without tenancy no tenant is known, so tenant quotas never apply.
+#[cfg(not(feature = "tenancy"))]
+async fn known_tenant(_: &AppState, _: &HeaderMap) -> Option<String> {
+    None
+}
+
+/// Middleware counting data-plane requests against
+/// [`AppState::quotas`].
+pub async fn enforce(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(quotas) = &state.quotas else {
+        return next.run(request).await;
+    };
+    if Action::of(request.method(), request.uri().path()).is_none() {
+        return next.run(request).await;
+    }
+    let api_key = request
+        .extensions()
+        .get::<Arc<ApiKey>>()
+        .map(|key| key.name.clone());
+    let tenant = known_tenant(&state, request.headers()).await;
+    let now = state.clock.now();
+    let usage = match quotas
+        .consume(api_key.as_deref(), tenant.as_deref(), now)
+        .await
+    {
+        Ok(usage) => usage,
+        Err(err) => {
+            tracing::warn!(target: "quota", error = %err, "quota not enforced");
+            None
+        }
+    };
+    let Some(usage) = usage else {
+        return next.run(request).await;
+    };
+    let mut response = if usage.exceeded {
+        Error::QuotaExceeded("operation quota exceeded".into()).into_response()
+    } else {
+        next.run(request).await
+    };
+    response.headers_mut().extend(usage.headers(now));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOON: u64 = 1_760_443_200;
+
+    fn quotas() -> Quotas {
+        Quotas::new(Arc::new(MemoryCounterStore::new()))
+    }
+
+    #[tokio::test]
+    async fn operations_past_the_limit_are_exceeded() {
+        let limits = QuotaLimits {
+            hourly: Some(2),
+            daily: None,
+        };
+        let quotas = quotas().api_key("partner", limits);
+
+        let first = quotas.consume(Some("partner"), None, NOON).await.unwrap();
+        assert_eq!(
+            first,
+            Some(Usage {
+                limit: 2,
+                remaining: 1,
+                reset: NOON + 3600,
+                exceeded: false
+            })
+        );
+        let second = quotas.consume(Some("partner"), None, NOON + 10).await;
+        assert_eq!(second.unwrap().unwrap().remaining, 0);
+        let third = quotas.consume(Some("partner"), None, NOON + 20).await;
+        assert!(third.unwrap().unwrap().exceeded);
+        // The next hour starts over.
+        let later = quotas.consume(Some("partner"), None, NOON + 3600).await;
+        assert!(!later.unwrap().unwrap().exceeded);
+        // Other keys are not limited.
+        assert_eq!(quotas.consume(Some("ops"), None, NOON).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn the_tightest_quota_is_reported() {
+        let quotas = quotas()
+            .api_key(
+                "partner",
+                QuotaLimits {
+                    hourly: Some(100),
+                    daily: Some(1000),
+                },
+            )
+            .tenant(
+                ANY,
+                QuotaLimits {
+                    hourly: None,
+                    daily: Some(1),
+                },
+            );
+
+        let usage = quotas.consume(Some("partner"), Some("acme"), NOON).await;
+        let usage = usage.unwrap().unwrap();
+        assert_eq!((usage.limit, usage.remaining), (1, 0));
+        assert_eq!(usage.reset, NOON + 12 * 3600);
+        let usage = quotas.consume(Some("partner"), Some("acme"), NOON).await;
+        assert!(usage.unwrap().unwrap().exceeded);
+        // Each tenant has its own budget.
+        let usage = quotas.consume(Some("partner"), Some("globex"), NOON).await;
+        assert!(!usage.unwrap().unwrap().exceeded);
+    }
+
+    #[tokio::test]
+    async fn memory_counters_are_capped() {
+        let store = MemoryCounterStore::new().max_counters(2);
+        let ttl = Duration::from_secs(60);
+        store.increment("a", ttl).await.unwrap();
+        store.increment("b", ttl).await.unwrap();
+        assert!(matches!(
+            store.increment("c", ttl).await,
+            Err(QuotaError::Full(2))
+        ));
+        // Live counters keep counting.
+        assert_eq!(store.increment("a", ttl).await.unwrap(), 2);
+        // Expired ones make room.
+        let store = MemoryCounterStore::new().max_counters(1);
+        store.increment("a", Duration::ZERO).await.unwrap();
+        assert_eq!(store.increment("b", ttl).await.unwrap(), 1);
+    }
+
+    #[test]
+    fn exceeded_quotas_say_when_to_retry() {
+        let usage = Usage {
+            limit: 10,
+            remaining: 0,
+            reset: NOON + 60,
+            exceeded: true,
+        };
+        let headers = usage.headers(NOON);
+        assert_eq!(headers[LIMIT_HEADER], "10");
+        assert_eq!(headers[REMAINING_HEADER], "0");
+        assert_eq!(headers[header::RETRY_AFTER], "60");
+    }
+}
@@ -0,0 +1,218 @@
+//! Storage of the refresh tokens handed out by [`crate::oauth`]. Tokens are
+//! opaque random strings; only their SHA-256 is stored, so a leaked store
+//! does not leak usable tokens. Redeeming a token removes it, which makes
+//! every token single-use and revocation a plain removal.
+//! [`MemoryRefreshTokenStore`] is per-process; with several replicas use
+//! [`RedisRefreshTokenStore`] so a token issued by one is redeemed by any.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::crypto::BoxFuture;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RefreshError {
+    #[error("refresh token store failed: {0}")]
+    Backend(String),
+}
+
+/// What a refresh token was issued for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefreshGrant {
+    pub client_id: String,
+    /// Scopes of the original grant; refreshed access tokens get these or
+    /// fewer.
+    pub scopes: Vec<String>,
+    /// Unix seconds after which the token is refused.
+    pub expires_at: u64,
+}
+
+/// The key a refresh token is stored under: its hex SHA-256.
+pub fn token_hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+pub trait RefreshTokenStore: Send + Sync {
+    /// Stores `grant` under `hash` until `grant.expires_at`, at Unix time
+    /// `now`.
+    fn insert<'a>(
+        &'a self,
+        hash: &'a str,
+        grant: RefreshGrant,
+        now: u64,
+    ) -> BoxFuture<'a, Result<(), RefreshError>>;
+
+    /// Removes and returns the grant stored under `hash`, atomically so a
+    /// token is redeemed once even when presented twice at the same time.
+    fn take<'a>(
+        &'a self,
+        hash: &'a str,
+    ) -> BoxFuture<'a, Result<Option<RefreshGrant>, RefreshError>>;
+}
+
+/// Keeps grants in memory; they are lost on restart and not shared between
+/// replicas.
+#[derive(Debug, Default)]
+pub struct MemoryRefreshTokenStore {
+    grants: Mutex<Grants>,
+}
+
+#[derive(Debug, Default)]
+struct Grants {
+    by_hash: HashMap<String, RefreshGrant>,
+    /// Size from which expired grants are swept on the next insert.
+    sweep_at: usize,
+}
+
+impl MemoryRefreshTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn grants(&self) -> std::sync::MutexGuard<'_, Grants> {
+        // The map holds no invariant a panicking holder could break.
+        self.grants.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl RefreshTokenStore for MemoryRefreshTokenStore {
+    fn insert<'a>(
+        &'a self,
+        hash: &'a str,
+        grant: RefreshGrant,
+        now: u64,
+    ) -> BoxFuture<'a, Result<(), RefreshError>> {
+        Box::pin(async move {
+            let mut grants = self.grants();
+            if grants.by_hash.len() >= grants.sweep_at {
+                grants.by_hash.retain(|_, grant| grant.expires_at >= now);
+                grants.sweep_at = (grants.by_hash.len() * 2).max(1024);
+            }
+            grants.by_hash.insert(hash.to_string(), grant);
+            Ok(())
+        })
+    }
+
+    fn take<'a>(
+        &'a self,
+        hash: &'a str,
+    ) -> BoxFuture<'a, Result<Option<RefreshGrant>, RefreshError>> {
+        Box::pin(async move { Ok(self.grants().by_hash.remove(hash)) })
+    }
+}
+
+/// Stores grants as JSON under `<prefix><hash>`, expiring with the token,
+/// and redeems them with `GETDEL` (Redis 6.2 or later).
+#[cfg(feature = "redis")]
+pub struct RedisRefreshTokenStore {
+    client: redis::Client,
+    connection: tokio::sync::OnceCell<redis::aio::ConnectionManager>,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisRefreshTokenStore {
+    /// Parses `url`; the connection is opened on first use.
+    pub fn new(url: &str, prefix: impl Into<String>) -> Result<Self, RefreshError> {
+        Ok(Self {
+            client: redis::Client::open(url).map_err(backend)?,
+            connection: tokio::sync::OnceCell::new(),
+            prefix: prefix.into(),
+        })
+    }
+
+    async fn connection(&self) -> Result<redis::aio::ConnectionManager, RefreshError> {
+        // The manager reconnects by itself once established.
+        Ok(self
+            .connection
+            .get_or_try_init(|| self.client.get_connection_manager())
+            .await
+            .map_err(backend)?
+            .clone())
+    }
+}
+
+#[cfg(feature = "redis")]
+impl RefreshTokenStore for RedisRefreshTokenStore {
+    fn insert<'a>(
+        &'a self,
+        hash: &'a str,
+        grant: RefreshGrant,
+        now: u64,
+    ) -> BoxFuture<'a, Result<(), RefreshError>> {
+        Box::pin(async move {
+            let mut connection = self.connection().await?;
+            // Redis rejects a zero expiry.
+            let secs = grant.expires_at.saturating_sub(now).max(1);
+            let json = serde_json::to_string(&grant).expect("grants serialize");
+            let () = redis::cmd("SET")
+                .arg(format!("{}{hash}", self.prefix))
+                .arg(json)
+                .arg("EX")
+                .arg(secs)
+                .query_async(&mut connection)
+                .await
+                .map_err(backend)?;
+            Ok(())
+        })
+    }
+
+    fn take<'a>(
+        &'a self,
+        hash: &'a str,
+    ) -> BoxFuture<'a, Result<Option<RefreshGrant>, RefreshError>> {
+        Box::pin(async move {
+            let mut connection = self.connection().await?;
+            let json: Option<String> = redis::cmd("GETDEL")
+                .arg(format!("{}{hash}", self.prefix))
+                .query_async(&mut connection)
+                .await
+                .map_err(backend)?;
+            json.map(|json| {
+                serde_json::from_str(&json).map_err(|err| RefreshError::Backend(err.to_string()))
+            })
+            .transpose()
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+fn backend(err: redis::RedisError) -> RefreshError {
+    RefreshError::Backend(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(expires_at: u64) -> RefreshGrant {
+        RefreshGrant {
+            client_id: "reports".into(),
+            scopes: vec!["read".into()],
+            expires_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn grants_are_taken_once() {
+        let store = MemoryRefreshTokenStore::new();
+        let hash = token_hash("token");
+        store.insert(&hash, grant(u64::MAX), 0).await.unwrap();
+        assert_eq!(store.take(&hash).await.unwrap(), Some(grant(u64::MAX)));
+        assert_eq!(store.take(&hash).await.unwrap(), None);
+    }
+
+    #[test]
+    fn tokens_are_stored_by_hash() {
+        let hash = token_hash("token");
+        assert_eq!(hash.len(), 64);
+        assert_ne!(hash, token_hash("other"));
+        assert!(!hash.contains("token"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Reloading of keys from files that change under a running process, such
+//! as Kubernetes secret mounts updated by a rotation. The config file and
+//! every key file it names are polled; when one changes, the configuration
+//! is loaded again and its keys replace the current ones through
+//! [`AppState::reload_keys`]. Each component switches to the new keys at
+//! once, so no request sees half of a key set. Settings other than keys
+//! still need a restart.
+
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::config::{Cli, Config};
+#[cfg(feature = "signing")]
+use crate::crypto::BoxFuture;
+#[cfg(feature = "signing")]
+use crate::crypto::signer::{AsyncSigner, SignError};
+use crate::state::AppState;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ReloadError {
+    /// The new keys cannot be swapped in place.
+    #[error("{0} needs a restart")]
+    NeedsRestart(&'static str),
+}
+
+/// A value replaced as a whole when keys are reloaded. Readers keep the
+/// version they loaded for as long as they hold it.
+pub struct Reloadable<T>(RwLock<Arc<T>>);
+
+impl<T> Reloadable<T> {
+    pub fn new(value: T) -> Self {
+        Self(RwLock::new(Arc::new(value)))
+    }
+
+    pub fn load(&self) -> Arc<T> {
+        // Only whole values are stored, so a panicking holder leaves a
+        // consistent one behind.
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn store(&self, value: T) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(value);
+    }
+}
+
+/// A signer whose key can be replaced while requests use it. Placed under
+/// the caching, coalescing and metering layers, so those survive a reload.
+#[cfg(feature = "signing")]
+pub struct ReloadableSigner(Reloadable<Arc<dyn AsyncSigner>>);
+
+#[cfg(feature = "signing")]
+impl ReloadableSigner {
+    pub fn new(signer: Arc<dyn AsyncSigner>) -> Self {
+        Self(Reloadable::new(signer))
+    }
+
+    pub fn replace(&self, signer: Arc<dyn AsyncSigner>) {
+        self.0.store(signer);
+    }
+}
+
+#[cfg(feature = "signing")]
+impl AsyncSigner for ReloadableSigner {
+    fn sign_bytes<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async move {
+            let signer = self.0.load();
+            signer.sign_bytes(bytes).await
+        })
+    }
+
+    fn verify_bytes<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(async move {
+            let signer = self.0.load();
+            signer.verify_bytes(bytes, signature).await
+        })
+    }
+}
+
+/// Notices changes to a set of files by their modification time and size.
+/// Metadata follows symlinks, so a secret mount swapping its `..data` link
+/// counts as a change of every file behind it.
+pub struct FileWatch {
+    files: Vec<(PathBuf, Option<(SystemTime, u64)>)>,
+}
+
+impl FileWatch {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            files: paths
+                .into_iter()
+                .map(|path| {
+                    let stamp = stamp(&path);
+                    (path, stamp)
+                })
+                .collect(),
+        }
+    }
+
+    /// Files that changed, appeared or disappeared since the last call.
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        self.files
+            .iter_mut()
+            .filter_map(|(path, seen)| {
+                let now = stamp(path);
+                (now != *seen).then(|| {
+                    *seen = now;
+                    path.clone()
+                })
+            })
+            .collect()
+    }
+}
+
+fn stamp(path: &std::path::Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// The files a reload would read again: the config file and the key files
+/// `config` names.
+fn watched(cli: &Cli, config: &Config) -> FileWatch {
+    FileWatch::new(cli.config.iter().cloned().chain(config.key_files()))
+}
+
+/// Polls the files every `interval`, for as long as the runtime runs. A
+/// configuration that no longer loads, or keys that cannot be swapped in,
+/// are logged and the current keys kept; the next change is tried again.
+pub fn spawn(state: AppState, cli: Cli, config: &Config, interval: Duration) {
+    let mut files = watched(&cli, config);
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let changed = files.changed();
+            if changed.is_empty() {
+                continue;
+            }
+            let config = match Config::load(&cli) {
+                Ok(config) => config,
+                Err(err) => {
+                    tracing::error!(?changed, "not reloading keys: {err}");
+                    continue;
+                }
+            };
+            match state.reload_keys(&config) {
+                Ok(()) => tracing::info!(?changed, "keys reloaded"),
+                Err(err) => tracing::error!(?changed, "not reloading keys: {err}"),
+            }
+            files = watched(&cli, &config);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reloadable_values_are_replaced_whole() {
+        let value = Reloadable::new(1);
+        let held = value.load();
+        value.store(2);
+        assert_eq!((*held, *value.load()), (1, 2));
+    }
+
+    #[test]
+    fn file_changes_are_noticed_once() {
+        let path = std::env::temp_dir().join(format!("take-home-reload-{}", std::process::id()));
+        std::fs::write(&path, "v1").unwrap();
+        let mut watch = FileWatch::new([path.clone()]);
+        assert!(watch.changed().is_empty());
+
+        std::fs::write(&path, "v2-longer").unwrap();
+        assert_eq!(watch.changed(), vec![path.clone()]);
+        assert!(watch.changed().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(watch.changed(), [path]);
+    }
+
+    #[cfg(feature = "signing")]
+    #[tokio::test]
+    async fn reloadable_signers_use_the_latest_key() {
+        use crate::crypto::hmac::HMacSigner;
+
+        let signer = ReloadableSigner::new(Arc::new(HMacSigner::new(b"old".to_vec())));
+        let old = signer.sign_bytes(b"payload").await.unwrap();
+        signer.replace(Arc::new(HMacSigner::new(b"new".to_vec())));
+        assert!(!signer.verify_bytes(b"payload", &old).await.unwrap());
+        assert_ne!(signer.sign_bytes(b"payload").await.unwrap(), old);
+    }
+}
@@ -0,0 +1,164 @@
+//! Memory of recently seen one-time values (signature nonces, SigV4
+//! signatures), so a captured request cannot be sent again while its
+//! signature is still valid. Each value is recorded with an atomic
+//! check-and-set and forgotten after its TTL. [`MemoryReplayStore`] is
+//! per-process; with several replicas use [`RedisReplayStore`] so they share
+//! what they have seen.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::crypto::BoxFuture;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("replay store failed: {0}")]
+    Backend(String),
+}
+
+pub trait ReplayStore: Send + Sync {
+    /// Records `key` for `ttl`. Returns `false`, without extending the TTL,
+    /// if it is already recorded.
+    fn check_and_set<'a>(
+        &'a self,
+        key: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<bool, ReplayError>>;
+}
+
+/// Keeps keys in memory; they are lost on restart and not shared between
+/// replicas.
+#[derive(Debug, Default)]
+pub struct MemoryReplayStore {
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    /// `None` for TTLs too long to represent, which never expire.
+    expiries: HashMap<String, Option<Instant>>,
+    /// Size from which expired keys are swept on the next insert.
+    sweep_at: usize,
+}
+
+impl MemoryReplayStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ReplayStore for MemoryReplayStore {
+    fn check_and_set<'a>(
+        &'a self,
+        key: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<bool, ReplayError>> {
+        Box::pin(async move {
+            let now = Instant::now();
+            // The map holds no invariant a panicking holder could break.
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            let live = |expiry: &Option<Instant>| expiry.is_none_or(|expiry| expiry > now);
+            if entries.expiries.get(key).is_some_and(live) {
+                return Ok(false);
+            }
+            if entries.expiries.len() >= entries.sweep_at {
+                entries.expiries.retain(|_, expiry| live(expiry));
+                entries.sweep_at = (entries.expiries.len() * 2).max(1024);
+            }
+            entries
+                .expiries
+                .insert(key.to_string(), now.checked_add(ttl));
+            Ok(true)
+        })
+    }
+}
+
+/// Records keys as `<prefix><key>` with `SET NX PX`, which Redis applies
+/// atomically across all replicas.
+#[cfg(feature = "redis")]
+pub struct RedisReplayStore {
+    client: redis::Client,
+    connection: tokio::sync::OnceCell<redis::aio::ConnectionManager>,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisReplayStore {
+    /// Parses `url`; the connection is opened on first use.
+    pub fn new(url: &str, prefix: impl Into<String>) -> Result<Self, ReplayError> {
+        Ok(Self {
+            client: redis::Client::open(url).map_err(backend)?,
+            connection: tokio::sync::OnceCell::new(),
+            prefix: prefix.into(),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl ReplayStore for RedisReplayStore {
+    fn check_and_set<'a>(
+        &'a self,
+        key: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<bool, ReplayError>> {
+        Box::pin(async move {
+            // The manager reconnects by itself once established.
+            let mut connection = self
+                .connection
+                .get_or_try_init(|| self.client.get_connection_manager())
+                .await
+                .map_err(backend)?
+                .clone();
+            // Redis rejects a zero expiry.
+            let millis = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1);
+            let set: Option<String> = redis::cmd("SET")
+                .arg(format!("{}{key}", self.prefix))
+                .arg(1)
+                .arg("NX")
+                .arg("PX")
+                .arg(millis)
+                .query_async(&mut connection)
+                .await
+                .map_err(backend)?;
+            Ok(set.is_some())
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+fn backend(err: redis::RedisError) -> ReplayError {
+    ReplayError::Backend(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keys_are_accepted_once() {
+        let store = MemoryReplayStore::new();
+        let ttl = Duration::from_secs(60);
+        assert!(store.check_and_set("a", ttl).await.unwrap());
+        assert!(!store.check_and_set("a", ttl).await.unwrap());
+        assert!(store.check_and_set("b", ttl).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn keys_are_forgotten_after_their_ttl() {
+        let store = MemoryReplayStore::new();
+        assert!(store.check_and_set("a", Duration::ZERO).await.unwrap());
+        assert!(store.check_and_set("a", Duration::ZERO).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn expired_keys_are_swept() {
+        let store = MemoryReplayStore::new();
+        for n in 0..2048 {
+            let key = n.to_string();
+            store.check_and_set(&key, Duration::ZERO).await.unwrap();
+        }
+        let entries = store.entries.lock().unwrap();
+        assert!(entries.expiries.len() <= 1024, "{}", entries.expiries.len());
+    }
+}
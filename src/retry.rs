@@ -0,0 +1,331 @@
+//! Retries of caller-provided signing and encryption backends (KMS, Vault,
+//! HSM adapters plugged in with [`AppState::with_signer_for`] and
+//! [`AppState::with_encryptor`]) when they fail with a transient error:
+//! `Backend` or `Timeout`. Each retry waits a random delay of up to
+//! `initial_backoff` doubled per attempt, capped at `max_backoff`, so
+//! replicas retrying the same outage spread out. The built-in backends
+//! run in-process and are not wrapped.
+//!
+//! [`AppState::with_signer_for`]: crate::state::AppState::with_signer_for
+//! [`AppState::with_encryptor`]: crate::state::AppState::with_encryptor
+
+use std::hash::{BuildHasher, Hasher};
+#[cfg(any(feature = "signing", feature = "encryption"))]
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::crypto::BoxFuture;
+#[cfg(feature = "encryption")]
+use crate::crypto::encryptor::{AsyncEncryptor, DecryptError, EncryptError};
+#[cfg(feature = "signing")]
+use crate::crypto::signer::{AsyncSigner, SignError};
+use crate::models::RetryStats;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts per operation, the first included.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+/// Retries made, and operations that still failed after the last attempt,
+/// across every retrying backend.
+#[derive(Debug, Default)]
+pub struct RetryCounters {
+    retries: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+impl RetryCounters {
+    pub fn stats(&self) -> RetryStats {
+        RetryStats {
+            retries: self.retries.load(Ordering::Relaxed),
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Runs `operation` until it succeeds, fails with an error `transient`
+    /// rejects, or `max_attempts` are used up.
+    #[cfg_attr(
+        not(any(feature = "signing", feature = "encryption")),
+        allow(dead_code)
+    )]
+    async fn run<'a, T, E>(
+        &self,
+        counters: &RetryCounters,
+        transient: fn(&E) -> bool,
+        operation: impl Fn() -> BoxFuture<'a, Result<T, E>>,
+    ) -> Result<T, E> {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(err) if transient(&err) => {
+                    if attempt >= self.max_attempts {
+                        counters.exhausted.fetch_add(1, Ordering::Relaxed);
+                        return Err(err);
+                    }
+                    counters.retries.fetch_add(1, Ordering::Relaxed);
+                    tokio::time::sleep(self.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Random delay before retry `retry` (1 for the first): anything up to
+    /// `initial_backoff * 2^(retry - 1)`, capped at `max_backoff`.
+    #[cfg_attr(
+        not(any(feature = "signing", feature = "encryption")),
+        allow(dead_code)
+    )]
+    fn backoff(&self, retry: u32) -> Duration {
+        let bound = self
+            .initial_backoff
+            .saturating_mul(1 << (retry - 1).min(16))
+            .min(self.max_backoff);
+        // Randomly keyed by the standard library; good enough for jitter.
+        let random = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        bound.mul_f64(random as f64 / u64::MAX as f64)
+    }
+}
+
+/// Retries the transient failures of a remote signer.
+#[cfg(feature = "signing")]
+pub struct RetryingSigner {
+    inner: Arc<dyn AsyncSigner>,
+    policy: RetryPolicy,
+    counters: Arc<RetryCounters>,
+}
+
+#[cfg(feature = "signing")]
+impl RetryingSigner {
+    pub fn new(
+        inner: Arc<dyn AsyncSigner>,
+        policy: RetryPolicy,
+        counters: Arc<RetryCounters>,
+    ) -> Self {
+        Self {
+            inner,
+            policy,
+            counters,
+        }
+    }
+
+    async fn run<'a, T>(
+        &'a self,
+        operation: impl Fn() -> BoxFuture<'a, Result<T, SignError>>,
+    ) -> Result<T, SignError> {
+        let transient = |err: &SignError| matches!(err, SignError::Backend(_) | SignError::Timeout);
+        self.policy.run(&self.counters, transient, operation).await
+    }
+}
+
+#[cfg(feature = "signing")]
+impl AsyncSigner for RetryingSigner {
+    fn sign_bytes<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(self.run(move || self.inner.sign_bytes(bytes)))
+    }
+
+    fn verify_bytes<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(self.run(move || self.inner.verify_bytes(bytes, signature)))
+    }
+
+    fn sign<'a>(
+        &'a self,
+        map: &'a serde_json::Map<String, serde_json::Value>,
+    ) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(self.run(move || self.inner.sign(map)))
+    }
+
+    fn verify<'a>(
+        &'a self,
+        map: &'a serde_json::Map<String, serde_json::Value>,
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(self.run(move || self.inner.verify(map, signature)))
+    }
+
+    fn sign_array<'a>(
+        &'a self,
+        items: &'a [serde_json::Value],
+    ) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(self.run(move || self.inner.sign_array(items)))
+    }
+
+    fn verify_array<'a>(
+        &'a self,
+        items: &'a [serde_json::Value],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(self.run(move || self.inner.verify_array(items, signature)))
+    }
+}
+
+/// Retries the transient failures of a remote encryptor.
+#[cfg(feature = "encryption")]
+pub struct RetryingEncryptor {
+    inner: Arc<dyn AsyncEncryptor>,
+    policy: RetryPolicy,
+    counters: Arc<RetryCounters>,
+}
+
+#[cfg(feature = "encryption")]
+impl RetryingEncryptor {
+    pub fn new(
+        inner: Arc<dyn AsyncEncryptor>,
+        policy: RetryPolicy,
+        counters: Arc<RetryCounters>,
+    ) -> Self {
+        Self {
+            inner,
+            policy,
+            counters,
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl AsyncEncryptor for RetryingEncryptor {
+    fn encrypt<'a>(
+        &'a self,
+        value: &'a serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, EncryptError>> {
+        let transient =
+            |err: &EncryptError| matches!(err, EncryptError::Backend(_) | EncryptError::Timeout);
+        Box::pin(
+            self.policy
+                .run(&self.counters, transient, move || self.inner.encrypt(value)),
+        )
+    }
+
+    fn decrypt<'a>(
+        &'a self,
+        value: &'a serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, DecryptError>> {
+        let transient =
+            |err: &DecryptError| matches!(err, DecryptError::Backend(_) | DecryptError::Timeout);
+        Box::pin(
+            self.policy
+                .run(&self.counters, transient, move || self.inner.decrypt(value)),
+        )
+    }
+}
+
+#[cfg(all(test, feature = "signing"))]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+
+    /// Fails with `error` the first `failures` times it is asked to sign.
+    struct Flaky {
+        calls: AtomicU32,
+        failures: u32,
+        error: fn() -> SignError,
+    }
+
+    impl AsyncSigner for Flaky {
+        fn sign_bytes<'a>(&'a self, _: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+            let call = self.calls.fetch_add(1, Ordering::Relaxed);
+            let result = if call < self.failures {
+                Err((self.error)())
+            } else {
+                Ok("signed".into())
+            };
+            Box::pin(async move { result })
+        }
+
+        fn verify_bytes<'a>(
+            &'a self,
+            _: &'a [u8],
+            _: &'a str,
+        ) -> BoxFuture<'a, Result<bool, SignError>> {
+            Box::pin(async { Ok(true) })
+        }
+    }
+
+    fn retrying(failures: u32, error: fn() -> SignError) -> (RetryingSigner, Arc<RetryCounters>) {
+        let counters = Arc::new(RetryCounters::default());
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(100),
+        };
+        let inner = Arc::new(Flaky {
+            calls: AtomicU32::new(0),
+            failures,
+            error,
+        });
+        (
+            RetryingSigner::new(inner, policy, counters.clone()),
+            counters,
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transient_failures_are_retried() {
+        let (signer, counters) = retrying(2, || SignError::Timeout);
+        assert_eq!(signer.sign_bytes(b"x").await.unwrap(), "signed");
+        assert_eq!(
+            counters.stats(),
+            RetryStats {
+                retries: 2,
+                exhausted: 0
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_stop_after_the_last_attempt() {
+        let (signer, counters) = retrying(5, || SignError::Backend("refused".into()));
+        assert!(matches!(
+            signer.sign_bytes(b"x").await,
+            Err(SignError::Backend(_))
+        ));
+        assert_eq!(
+            counters.stats(),
+            RetryStats {
+                retries: 2,
+                exhausted: 1
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn other_failures_are_not_retried() {
+        let (signer, counters) = retrying(1, || SignError::KeyUnavailable("disabled".into()));
+        assert!(signer.sign_bytes(b"x").await.is_err());
+        assert_eq!(
+            counters.stats(),
+            RetryStats {
+                retries: 0,
+                exhausted: 0
+            }
+        );
+    }
+
+    #[test]
+    fn backoff_grows_up_to_the_cap() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+        };
+        for _ in 0..100 {
+            assert!(policy.backoff(1) <= Duration::from_millis(10));
+            assert!(policy.backoff(3) <= Duration::from_millis(40));
+            assert!(policy.backoff(9) <= Duration::from_millis(50));
+        }
+    }
+}
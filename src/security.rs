@@ -0,0 +1,175 @@
+//! Counts of the failures that signal tampering or misconfiguration but
+//! are not tied to a signing key: HTTP message signatures, SigV4 requests
+//! and webhooks that fail verification, and requests naming a key or
+//! tenant that does not exist. Rejections by signing keys and failed
+//! decryptions are counted per key in [`crate::usage`]. Both are reported
+//! by the admin `GET /metrics` endpoint.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::models::SecurityEventStats;
+
+/// Label sets kept before further ones are folded into one. Key ids and
+/// tenants of failed requests are chosen by callers, so they must not grow
+/// the map without bound.
+const MAX_SERIES: usize = 1024;
+/// Stands in for the key id and tenant of events past [`MAX_SERIES`].
+pub const OVERFLOW_LABEL: &str = "_other";
+
+/// What failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SecurityEvent {
+    /// A signature over a request or webhook did not verify.
+    VerifyFailed {
+        scheme: VerifyScheme,
+        reason: VerifyFailure,
+    },
+    /// A request named a key id, access key or tenant that is not known.
+    KeyNotFound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VerifyScheme {
+    HttpSignature,
+    SigV4,
+    Webhook,
+    Challenge,
+}
+
+impl VerifyScheme {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VerifyScheme::HttpSignature => "http_signature",
+            VerifyScheme::SigV4 => "sigv4",
+            VerifyScheme::Webhook => "webhook",
+            VerifyScheme::Challenge => "challenge",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VerifyFailure {
+    /// The signature does not match.
+    Mismatch,
+    /// The signature expired, or its timestamp is outside the allowed skew.
+    Expired,
+    /// The signature matches but the body does not match its digest.
+    DigestMismatch,
+}
+
+impl VerifyFailure {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VerifyFailure::Mismatch => "mismatch",
+            VerifyFailure::Expired => "expired",
+            VerifyFailure::DigestMismatch => "digest_mismatch",
+        }
+    }
+}
+
+/// An event, key id and tenant.
+type Series = (SecurityEvent, Option<String>, Option<String>);
+
+/// Event counts by event, key id and tenant.
+#[derive(Debug, Default)]
+pub struct SecurityEvents {
+    series: Mutex<BTreeMap<Series, u64>>,
+}
+
+impl SecurityEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts one `event` for the key `kid` of `tenant`, either of which
+    /// may be unknown.
+    pub fn record(&self, event: SecurityEvent, kid: Option<&str>, tenant: Option<&str>) {
+        let mut series = self.series();
+        let key = (event, kid.map(str::to_string), tenant.map(str::to_string));
+        let key = if series.len() < MAX_SERIES || series.contains_key(&key) {
+            key
+        } else {
+            let overflow = Some(OVERFLOW_LABEL.to_string());
+            (event, overflow.clone(), overflow)
+        };
+        *series.entry(key).or_default() += 1;
+    }
+
+    /// Every count, ordered by event, key id and tenant.
+    pub fn stats(&self) -> Vec<SecurityEventStats> {
+        self.series()
+            .iter()
+            .map(|((event, kid, tenant), &count)| {
+                let (event, scheme, reason) = match *event {
+                    SecurityEvent::VerifyFailed { scheme, reason } => (
+                        "verify_failure",
+                        Some(scheme.as_str().to_string()),
+                        Some(reason.as_str().to_string()),
+                    ),
+                    SecurityEvent::KeyNotFound => ("key_not_found", None, None),
+                };
+                SecurityEventStats {
+                    event: event.to_string(),
+                    scheme,
+                    reason,
+                    kid: kid.clone(),
+                    tenant: tenant.clone(),
+                    count,
+                }
+            })
+            .collect()
+    }
+
+    fn series(&self) -> std::sync::MutexGuard<'_, BTreeMap<Series, u64>> {
+        // Only counters are updated under the lock.
+        self.series
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_counted_per_label_set() {
+        let events = SecurityEvents::new();
+        let expired = SecurityEvent::VerifyFailed {
+            scheme: VerifyScheme::HttpSignature,
+            reason: VerifyFailure::Expired,
+        };
+        events.record(expired, Some("client-1"), None);
+        events.record(expired, Some("client-1"), None);
+        events.record(SecurityEvent::KeyNotFound, None, Some("acme"));
+
+        let stats = events.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].event, "verify_failure");
+        assert_eq!(stats[0].scheme.as_deref(), Some("http_signature"));
+        assert_eq!(stats[0].reason.as_deref(), Some("expired"));
+        assert_eq!(stats[0].kid.as_deref(), Some("client-1"));
+        assert_eq!(stats[0].count, 2);
+        assert_eq!(stats[1].event, "key_not_found");
+        assert_eq!(stats[1].tenant.as_deref(), Some("acme"));
+    }
+
+    #[test]
+    fn label_sets_past_the_limit_are_folded() {
+        let events = SecurityEvents::new();
+        for n in 0..MAX_SERIES + 10 {
+            events.record(SecurityEvent::KeyNotFound, Some(&format!("k{n}")), None);
+        }
+        events.record(SecurityEvent::KeyNotFound, Some("k0"), None);
+
+        let stats = events.stats();
+        assert_eq!(stats.len(), MAX_SERIES + 1);
+        let overflow = stats
+            .iter()
+            .find(|stats| stats.kid.as_deref() == Some(OVERFLOW_LABEL))
+            .unwrap();
+        assert_eq!(overflow.count, 10);
+        assert_eq!(stats.iter().map(|stats| stats.count).sum::<u64>(), 1035);
+    }
+}
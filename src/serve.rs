@@ -0,0 +1,64 @@
+//! Accept loop for both listeners. Each connection is served by hyper's
+//! auto-detecting builder: HTTP/1.1 with keep-alive, or cleartext HTTP/2
+//! (h2c) when the client opens with the HTTP/2 preface, so a gateway can
+//! multiplex many small requests over one connection.
+
+use std::io;
+use std::time::Duration;
+
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use tokio::net::TcpListener;
+
+use crate::config::ServerConfig;
+
+/// Serves `router` on `listener` until the task is dropped. Failing to
+/// accept one connection (e.g. running out of file descriptors) is logged
+/// and does not stop the server.
+pub async fn serve(listener: TcpListener, router: Router, config: &ServerConfig) -> io::Result<()> {
+    let builder = builder(config);
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to accept connection");
+                // Give the process a moment to release descriptors.
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                continue;
+            }
+        };
+        let builder = builder.clone();
+        let service = TowerToHyperService::new(router.clone());
+        tokio::spawn(async move {
+            if let Err(err) = builder
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!(%peer, error = %err, "connection closed with an error");
+            }
+        });
+    }
+}
+
+fn builder(config: &ServerConfig) -> Builder<TokioExecutor> {
+    let mut builder = Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(config.http1.keep_alive)
+        .header_read_timeout(Duration::from_secs(config.http1.header_read_timeout_secs));
+    let http2 = &config.http2;
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(http2.max_concurrent_streams)
+        .keep_alive_interval(http2.keep_alive_interval_secs.map(Duration::from_secs))
+        .keep_alive_timeout(Duration::from_secs(http2.keep_alive_timeout_secs));
+    if http2.enabled {
+        builder
+    } else {
+        builder.http1_only()
+    }
+}
@@ -0,0 +1,926 @@
+#[cfg(feature = "signing")]
+use std::collections::BTreeMap;
+#[cfg(feature = "signing")]
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::anomaly::{AnomalyDetector, WebhookAlertSink};
+use crate::api_keys::ApiKeys;
+use crate::audit::{AuditSink, BatchSettings, BatchingAuditSink, TracingAuditSink};
+#[cfg(feature = "blobs")]
+use crate::blobs::{BlobStore, ChunkedSealing, DirBlobStore, MemoryBlobStore};
+use crate::blocking::BlockingPool;
+#[cfg(feature = "asymmetric")]
+use crate::blocking::OffloadedSigner;
+#[cfg(all(feature = "admin", feature = "escrow"))]
+use crate::ceremony::Ceremonies;
+use crate::clock::Clock;
+#[cfg(feature = "signing")]
+use crate::coalesce::{CoalescingSigner, SignFlights};
+use crate::config::Config;
+#[cfg(feature = "encryption")]
+use crate::config::EncryptionAlgorithm;
+#[cfg(feature = "signing")]
+use crate::config::{FloatPolicy, SignatureTransitionConfig, VerifyBatchConfig};
+#[cfg(feature = "asymmetric")]
+use crate::crypto::asymmetric::AsymmetricSigner;
+#[cfg(feature = "encryption")]
+use crate::crypto::base64::Base64Encryptor;
+#[cfg(feature = "signing")]
+use crate::crypto::cache::{CachingSigner, SignatureCache};
+#[cfg(feature = "encryption")]
+use crate::crypto::encryptor::AsyncEncryptor;
+#[cfg(feature = "escrow")]
+use crate::crypto::escrow::{EscrowedKey, KeyEscrow};
+#[cfg(feature = "signing")]
+use crate::crypto::hmac::{HMacSigner, HmacDigest};
+#[cfg(feature = "signing")]
+use crate::crypto::http_signature::Keyring;
+#[cfg(feature = "asymmetric")]
+use crate::crypto::jwk::{Jwk, JwkSet, PublishedJwks};
+#[cfg(feature = "signing")]
+use crate::crypto::registry::SignerRegistry;
+#[cfg(feature = "json-schema")]
+use crate::crypto::schema::SchemaRegistry;
+#[cfg(feature = "signing")]
+use crate::crypto::signer::AsyncSigner;
+#[cfg(feature = "signing")]
+use crate::crypto::sigv4::Credentials;
+#[cfg(any(feature = "signing", feature = "encryption"))]
+use crate::crypto::strict_json::StrictJson;
+#[cfg(feature = "signing")]
+use crate::crypto::webhook::{Provider, WebhookVerifier};
+use crate::deprecation::Deprecations;
+use crate::events::EventHub;
+#[cfg(feature = "tenancy")]
+use crate::notifications::{Notifier, WebhookNotificationSink};
+#[cfg(feature = "asymmetric")]
+use crate::oauth::TokenIssuer;
+use crate::policy::{OpaPolicy, PolicyBackend, RulePolicy};
+use crate::quota::Quotas;
+#[cfg(feature = "signing")]
+use crate::reload::ReloadableSigner;
+use crate::reload::{ReloadError, Reloadable};
+#[cfg(feature = "signing")]
+use crate::replay::ReplayStore;
+#[cfg(feature = "encryption")]
+use crate::retry::RetryingEncryptor;
+#[cfg(feature = "signing")]
+use crate::retry::RetryingSigner;
+use crate::retry::{RetryCounters, RetryPolicy};
+use crate::security::SecurityEvents;
+#[cfg(feature = "tenancy")]
+use crate::tenancy::Tenants;
+#[cfg(feature = "asymmetric")]
+use crate::transparency::TransparencyLog;
+use crate::usage::KeyUsage;
+#[cfg(feature = "encryption")]
+use crate::usage::MeteredEncryptor;
+#[cfg(feature = "signing")]
+use crate::usage::MeteredSigner;
+#[cfg(feature = "vault")]
+use crate::vault::{DirVaultStore, MemoryVaultStore, VaultStore};
+use crate::watchdog::Watchdog;
+
+/// Shared state handed to every handler through axum's `State` extractor.
+#[derive(Clone)]
+pub struct AppState {
+    #[cfg(feature = "signing")]
+    pub signers: SignerRegistry,
+    /// The service's own keys under `signers`, below their caching and
+    /// metering layers, by algorithm; replaced by [`AppState::reload_keys`].
+    #[cfg(feature = "signing")]
+    pub reloadable_signers: Arc<BTreeMap<String, Arc<ReloadableSigner>>>,
+    /// Identifier of the service's own signing keys, `signing.key_id`.
+    #[cfg(feature = "signing")]
+    pub key_id: String,
+    /// Recent signatures of the configured signers, when
+    /// `signing.cache.enabled` is set.
+    #[cfg(feature = "signing")]
+    pub signature_cache: Option<Arc<SignatureCache>>,
+    /// Sign operations in flight, shared by identical concurrent requests
+    /// when `signing.coalesce` is set.
+    #[cfg(feature = "signing")]
+    pub sign_flights: Option<Arc<SignFlights>>,
+    /// Whether `/sign` envelopes signatures by default.
+    #[cfg(feature = "signing")]
+    pub sign_envelope: bool,
+    /// Whether `/sign` also returns an envelope next to a bare signature,
+    /// and with which algorithm.
+    #[cfg(feature = "signing")]
+    pub sign_transition: SignatureTransitionConfig,
+    /// Treatment of non-integer numbers in signed payloads.
+    #[cfg(feature = "signing")]
+    pub float_policy: FloatPolicy,
+    /// Bounds on `/verify/batch`.
+    #[cfg(feature = "signing")]
+    pub verify_batch: VerifyBatchConfig,
+    /// Duplicate-key and size checks on `/sign` and `/verify` bodies.
+    #[cfg(feature = "signing")]
+    pub strict_json: StrictJson,
+    /// Schemas selectable with `/sign?schema=<name>`.
+    #[cfg(feature = "json-schema")]
+    pub schemas: SchemaRegistry,
+    /// Shared secrets for HTTP Message Signatures (RFC 9421).
+    #[cfg(feature = "signing")]
+    pub http_signature_keys: Arc<Reloadable<Keyring>>,
+    /// Secret access keys for SigV4-style request verification.
+    #[cfg(feature = "signing")]
+    pub sigv4_credentials: Arc<Credentials>,
+    #[cfg(feature = "signing")]
+    pub sigv4_max_skew_secs: u64,
+    /// `clock.skew_secs`, allowed past the `exp` of signed data and the
+    /// `expires` of HTTP message signatures.
+    #[cfg(feature = "signing")]
+    pub clock_skew_secs: u64,
+    /// Per-provider secrets for inbound webhook verification.
+    #[cfg(feature = "signing")]
+    pub webhooks: Arc<WebhookVerifier>,
+    /// How long `/challenge` challenges can be answered.
+    #[cfg(feature = "signing")]
+    pub challenge_ttl_secs: u64,
+    /// Nonces of answered challenges, each accepted once.
+    #[cfg(feature = "signing")]
+    pub challenges: Arc<dyn ReplayStore>,
+    #[cfg(feature = "encryption")]
+    pub encryptor: Arc<dyn AsyncEncryptor>,
+    /// Algorithm of `encryptor` as recorded in `_crypto` sidecars:
+    /// `encryption.algorithm`, or `custom` after [`AppState::with_encryptor`].
+    #[cfg(feature = "encryption")]
+    pub encryption_algorithm: &'static str,
+    /// Property count from which `/encrypt` and `/decrypt` work on several
+    /// threads.
+    #[cfg(feature = "encryption")]
+    pub parallel_min_fields: usize,
+    /// Size checks on `/encrypt` bodies.
+    #[cfg(feature = "encryption")]
+    pub encrypt_limits: StrictJson,
+    /// Longest line admin `/encryption/migrate` reads: `limits.max_body_bytes`.
+    #[cfg(all(feature = "admin", feature = "encryption"))]
+    pub migrate_max_line_bytes: usize,
+    /// Where `/blobs` keeps encrypted blobs.
+    #[cfg(feature = "blobs")]
+    pub blobs: Arc<dyn BlobStore>,
+    /// Seals new blobs in chunks when `blobs.key` is set.
+    #[cfg(feature = "blobs")]
+    pub blob_chunks: Option<Arc<ChunkedSealing>>,
+    /// Where `/vault` keeps encrypted entries.
+    #[cfg(feature = "vault")]
+    pub vault: Arc<dyn VaultStore>,
+    #[cfg(feature = "vault")]
+    pub vault_max_value_bytes: usize,
+    /// Signing keys the admin API can export to escrow holders.
+    #[cfg(feature = "escrow")]
+    pub escrow: Arc<Reloadable<KeyEscrow>>,
+    /// Key ceremonies, when `ceremony.custodians` is set.
+    #[cfg(all(feature = "admin", feature = "escrow"))]
+    pub ceremonies: Option<Arc<Ceremonies>>,
+    /// Public keys of the asymmetric signers, served as a JWK Set.
+    #[cfg(feature = "asymmetric")]
+    pub jwks: Arc<Reloadable<PublishedJwks>>,
+    #[cfg(feature = "asymmetric")]
+    pub jwks_max_age_secs: u64,
+    /// Issues `/oauth/token` access tokens, when `oauth.enabled` is set.
+    #[cfg(feature = "asymmetric")]
+    pub token_issuer: Option<Arc<TokenIssuer>>,
+    /// Per-tenant signers, when `tenancy.enabled` is set.
+    #[cfg(feature = "tenancy")]
+    pub tenants: Option<Arc<Tenants>>,
+    /// Merkle tree over the audit events, when `transparency.enabled` is
+    /// set.
+    #[cfg(feature = "asymmetric")]
+    pub transparency: Option<Arc<TransparencyLog>>,
+    /// The time expiries, timestamps and audit records are taken from.
+    pub clock: Arc<dyn Clock>,
+    /// Receives an event for every access to stored secrets.
+    pub audit: Arc<dyn AuditSink>,
+    /// Audit and key lifecycle events for `GET /events` subscribers.
+    pub events: Arc<EventHub>,
+    /// Operations served by each key.
+    pub key_usage: Arc<KeyUsage>,
+    /// Verification failures and unknown keys outside the signing keys.
+    pub security: Arc<SecurityEvents>,
+    /// Deprecation schedule of the legacy formats, and their uses.
+    pub deprecations: Arc<Deprecations>,
+    /// Alerts on callers failing verification often, when
+    /// `anomaly.enabled` is set.
+    pub anomaly: Option<Arc<AnomalyDetector>>,
+    /// Decides which requests may proceed; set when `policy.enabled` is.
+    pub policy: Option<Arc<dyn PolicyBackend>>,
+    /// Keys requests must present; set when `api_keys.keys` is not empty.
+    pub api_keys: Option<Arc<Reloadable<ApiKeys>>>,
+    /// Operation quotas; set when any are configured.
+    pub quotas: Option<Arc<Quotas>>,
+    /// How caller-provided signers and encryptors are retried.
+    pub retry: RetryPolicy,
+    pub retries: Arc<RetryCounters>,
+    /// Periodic self-checks of the signers and encryptor.
+    pub watchdog: Arc<Watchdog>,
+    /// Runs CPU-heavy crypto off the async workers.
+    pub blocking: BlockingPool,
+}
+
+impl AppState {
+    /// Builds the backends selected by a validated [`Config`], on the
+    /// system clock shifted by `clock.offset_secs`.
+    pub fn from_config(config: &Config) -> Self {
+        Self::from_config_with_clock(config, Arc::new(config.clock.clock()))
+    }
+
+    /// Like [`AppState::from_config`], with every component reading the
+    /// time from `clock`, e.g. a [`TestClock`](crate::clock::TestClock).
+    #[cfg_attr(
+        not(any(feature = "encryption", feature = "signing")),
+        allow(unused_variables)
+    )]
+    pub fn from_config_with_clock(config: &Config, clock: Arc<dyn Clock>) -> Self {
+        let blocking = match config.blocking.max_concurrent {
+            Some(max_concurrent) => BlockingPool::new(max_concurrent),
+            None => BlockingPool::default(),
+        };
+        #[cfg(feature = "signing")]
+        let signature_cache = config.signing.cache.enabled.then(|| {
+            let capacity = NonZeroUsize::new(config.signing.cache.capacity)
+                .expect("validated configuration has a positive cache capacity");
+            Arc::new(SignatureCache::new(capacity))
+        });
+        #[cfg(feature = "signing")]
+        let sign_flights = config
+            .signing
+            .coalesce
+            .then(|| Arc::new(SignFlights::new()));
+        // Every configured signer is deterministic, so its signatures can be
+        // served from the cache.
+        let key_usage = Arc::new(KeyUsage::new().clock(clock.clone()));
+        let events = Arc::new(
+            EventHub::new(config.events.buffer)
+                .keep_alive(Duration::from_secs(config.events.keep_alive_secs)),
+        );
+        let watchdog = Arc::new(
+            Watchdog::new(Duration::from_secs(config.watchdog.timeout_secs)).clock(clock.clone()),
+        );
+        // Cache hits and coalesced requests count as operations of the key:
+        // metering wraps both.
+        #[cfg(feature = "signing")]
+        let cached = |alg: &str, signer: Arc<dyn AsyncSigner>| -> Arc<dyn AsyncSigner> {
+            watchdog.watch_signer(alg, signer.clone());
+            let signer = match &signature_cache {
+                Some(cache) => Arc::new(CachingSigner::new(
+                    signer,
+                    cache.clone(),
+                    &config.signing.key_id,
+                    alg,
+                )),
+                None => signer,
+            };
+            let signer = match &sign_flights {
+                Some(flights) => Arc::new(CoalescingSigner::new(
+                    signer,
+                    flights.clone(),
+                    &config.signing.key_id,
+                    alg,
+                )),
+                None => signer,
+            };
+            let counters = key_usage.counters(&config.signing.key_id, alg);
+            Arc::new(MeteredSigner::new(signer, counters))
+        };
+        #[cfg(feature = "signing")]
+        let reloadable_signers: BTreeMap<String, Arc<ReloadableSigner>> =
+            key_signers(config, &blocking)
+                .into_iter()
+                .map(|(alg, signer)| (alg, Arc::new(ReloadableSigner::new(signer))))
+                .collect();
+        // The secret is registered with every HMAC digest, so signatures made
+        // before `signing.algorithm` moved off SHA-256 still verify.
+        #[cfg(feature = "signing")]
+        let signers = {
+            let default = config.signing.algorithm.as_str();
+            reloadable_signers
+                .iter()
+                .filter(|(alg, _)| *alg != default)
+                .fold(
+                    SignerRegistry::new(
+                        default,
+                        cached(default, reloadable_signers[default].clone()),
+                    ),
+                    |registry, (alg, signer)| registry.with(alg, cached(alg, signer.clone())),
+                )
+        };
+        #[cfg(feature = "asymmetric")]
+        let transparency = config
+            .transparency
+            .enabled
+            .then(|| Arc::new(transparency_log(config, &reloadable_signers, &clock)));
+        Self {
+            #[cfg(feature = "signing")]
+            signers,
+            #[cfg(feature = "signing")]
+            reloadable_signers: Arc::new(reloadable_signers),
+            #[cfg(feature = "signing")]
+            key_id: config.signing.key_id.clone(),
+            #[cfg(feature = "signing")]
+            signature_cache,
+            #[cfg(feature = "signing")]
+            sign_flights,
+            #[cfg(feature = "signing")]
+            sign_envelope: config.signing.envelope,
+            #[cfg(feature = "signing")]
+            sign_transition: config.signing.transition.clone(),
+            #[cfg(feature = "signing")]
+            verify_batch: config.signing.batch.clone(),
+            #[cfg(feature = "signing")]
+            float_policy: config.signing.float_policy,
+            #[cfg(feature = "signing")]
+            strict_json: json_limits(config, config.signing.reject_duplicate_keys),
+            #[cfg(feature = "json-schema")]
+            schemas: config
+                .signing
+                .schemas()
+                .expect("validated configuration always has compilable schemas"),
+            #[cfg(feature = "signing")]
+            http_signature_keys: Arc::new(Reloadable::new(http_signature_keys(config))),
+            #[cfg(feature = "signing")]
+            sigv4_credentials: Arc::new(sigv4_credentials(config)),
+            #[cfg(feature = "signing")]
+            sigv4_max_skew_secs: config.sigv4.max_skew_secs,
+            #[cfg(feature = "signing")]
+            clock_skew_secs: config.clock.skew_secs,
+            #[cfg(feature = "signing")]
+            webhooks: Arc::new(webhook_verifier(config)),
+            #[cfg(feature = "signing")]
+            challenge_ttl_secs: config.challenge.ttl_secs,
+            #[cfg(feature = "signing")]
+            challenges: config
+                .challenge
+                .store()
+                .expect("validated configuration has a valid challenge store"),
+            #[cfg(feature = "encryption")]
+            encryptor: {
+                let algorithm = config.encryption.algorithm;
+                let encryptor: Arc<dyn AsyncEncryptor> = match algorithm {
+                    EncryptionAlgorithm::Base64 => Arc::new(Base64Encryptor),
+                };
+                watchdog.watch_encryptor(encryptor.clone());
+                let counters = key_usage.counters(ENCRYPTION_KEY_ID, algorithm.as_str());
+                Arc::new(MeteredEncryptor::new(encryptor, counters))
+            },
+            #[cfg(feature = "encryption")]
+            encryption_algorithm: config.encryption.algorithm.as_str(),
+            #[cfg(feature = "encryption")]
+            parallel_min_fields: config.encryption.parallel_min_fields,
+            #[cfg(feature = "encryption")]
+            encrypt_limits: json_limits(config, false),
+            #[cfg(all(feature = "admin", feature = "encryption"))]
+            migrate_max_line_bytes: config.limits.max_body_bytes,
+            #[cfg(feature = "blobs")]
+            blobs: match &config.blobs.dir {
+                Some(dir) => Arc::new(DirBlobStore::new(dir)),
+                None => Arc::new(MemoryBlobStore::new()),
+            },
+            #[cfg(feature = "blobs")]
+            blob_chunks: config
+                .blobs
+                .key
+                .as_ref()
+                .map(|key| Arc::new(ChunkedSealing::new(key.expose(), config.blobs.chunk_size))),
+            #[cfg(feature = "vault")]
+            vault: match &config.vault.dir {
+                Some(dir) => Arc::new(DirVaultStore::new(dir)),
+                None => Arc::new(MemoryVaultStore::new()),
+            },
+            #[cfg(feature = "vault")]
+            vault_max_value_bytes: config.vault.max_value_bytes,
+            #[cfg(feature = "escrow")]
+            escrow: Arc::new(Reloadable::new(key_escrow(config))),
+            #[cfg(all(feature = "admin", feature = "escrow"))]
+            ceremonies: (!config.ceremony.custodians.is_empty()).then(|| {
+                Arc::new(
+                    Ceremonies::new(
+                        config.ceremony.custodians.clone(),
+                        Duration::from_secs(config.ceremony.ttl_secs),
+                    )
+                    .clock(clock.clone()),
+                )
+            }),
+            #[cfg(feature = "asymmetric")]
+            jwks: Arc::new(Reloadable::new(jwks(config))),
+            #[cfg(feature = "asymmetric")]
+            jwks_max_age_secs: config.signing.jwks_max_age_secs,
+            #[cfg(feature = "asymmetric")]
+            token_issuer: config.oauth.enabled.then(|| Arc::new(token_issuer(config))),
+            #[cfg(feature = "tenancy")]
+            tenants: config
+                .tenancy
+                .enabled
+                .then(|| Arc::new(tenants(config, key_usage.clone(), events.clone(), &clock))),
+            #[cfg(feature = "asymmetric")]
+            transparency,
+            audit: audit_sink(config, &clock),
+            events,
+            key_usage,
+            security: Arc::new(SecurityEvents::new()),
+            deprecations: Arc::new(Deprecations::new(&config.deprecation)),
+            anomaly: config
+                .anomaly
+                .enabled
+                .then(|| Arc::new(anomaly_detector(config, &clock))),
+            policy: config.policy.enabled.then(|| policy(config)),
+            api_keys: (!config.api_keys.keys.is_empty())
+                .then(|| Arc::new(Reloadable::new(api_keys(config)))),
+            quotas: (!config.quotas.is_empty()).then(|| Arc::new(quotas(config))),
+            retry: config.retry.policy(),
+            retries: Arc::new(RetryCounters::default()),
+            watchdog,
+            blocking,
+            clock,
+        }
+    }
+
+    /// Replaces the keys read from files with those of `config`, a newer
+    /// load of the configuration this state was built from; see
+    /// [`crate::reload`]. Nothing is replaced if any key cannot be: adding,
+    /// removing or changing the type of the private key, changing
+    /// `signing.key_id` and turning API keys on or off need a restart.
+    pub fn reload_keys(&self, config: &Config) -> Result<(), ReloadError> {
+        #[cfg(feature = "signing")]
+        let signers = key_signers(config, &self.blocking);
+        #[cfg(feature = "signing")]
+        if !signers.keys().eq(self.reloadable_signers.keys()) {
+            return Err(ReloadError::NeedsRestart(
+                "adding, removing or changing the type of the private key",
+            ));
+        }
+        #[cfg(feature = "signing")]
+        if config.signing.key_id != self.key_id {
+            return Err(ReloadError::NeedsRestart("changing `signing.key_id`"));
+        }
+        if self.api_keys.is_some() == config.api_keys.keys.is_empty() {
+            return Err(ReloadError::NeedsRestart("turning API keys on or off"));
+        }
+        #[cfg(feature = "signing")]
+        {
+            for (alg, signer) in signers {
+                self.reloadable_signers[&alg].replace(signer);
+            }
+            // Cached signatures were made with the old keys.
+            if let Some(cache) = &self.signature_cache {
+                cache.clear();
+            }
+            self.http_signature_keys.store(http_signature_keys(config));
+        }
+        #[cfg(feature = "escrow")]
+        self.escrow.store(key_escrow(config));
+        #[cfg(feature = "asymmetric")]
+        self.jwks.store(jwks(config));
+        if let Some(keys) = &self.api_keys {
+            keys.store(api_keys(config));
+        }
+        Ok(())
+    }
+
+    /// Replaces the signer of the default algorithm, e.g. with a remote backend or a mock
+    /// in tests. Any [`Signer`](crate::crypto::signer::Signer) qualifies.
+    #[cfg(feature = "signing")]
+    pub fn with_signer(self, signer: Arc<dyn AsyncSigner>) -> Self {
+        let alg = self.signers.default_alg().to_string();
+        self.with_signer_for(&alg, signer)
+    }
+
+    /// Registers an additional signer selectable with `?alg=<alg>` or a
+    /// signature envelope. Its transient failures are retried per
+    /// [`AppState::retry`].
+    #[cfg(feature = "signing")]
+    pub fn with_signer_for(mut self, alg: &str, signer: Arc<dyn AsyncSigner>) -> Self {
+        let signer = Arc::new(RetryingSigner::new(
+            signer,
+            self.retry,
+            self.retries.clone(),
+        ));
+        self.watchdog.watch_signer(alg, signer.clone());
+        let signer: Arc<dyn AsyncSigner> = match &self.sign_flights {
+            Some(flights) => Arc::new(CoalescingSigner::new(
+                signer,
+                flights.clone(),
+                &self.key_id,
+                alg,
+            )),
+            None => signer,
+        };
+        let counters = self.key_usage.counters(&self.key_id, alg);
+        let signer = Arc::new(MeteredSigner::new(signer, counters));
+        self.signers = self.signers.with(alg, signer);
+        self
+    }
+
+    /// Replaces the store of answered `/challenge` nonces, e.g. with a
+    /// [`RedisReplayStore`](crate::replay::RedisReplayStore) shared by
+    /// several replicas.
+    #[cfg(feature = "signing")]
+    pub fn with_challenge_store(mut self, store: Arc<dyn ReplayStore>) -> Self {
+        self.challenges = store;
+        self
+    }
+
+    /// Replaces the configured encryptor, e.g. with a remote backend or a
+    /// mock in tests. Any [`Encryptor`](crate::crypto::encryptor::Encryptor)
+    /// qualifies. Its transient failures are retried per [`AppState::retry`].
+    #[cfg(feature = "encryption")]
+    pub fn with_encryptor(mut self, encryptor: Arc<dyn AsyncEncryptor>) -> Self {
+        let encryptor = Arc::new(RetryingEncryptor::new(
+            encryptor,
+            self.retry,
+            self.retries.clone(),
+        ));
+        self.watchdog.watch_encryptor(encryptor.clone());
+        let counters = self.key_usage.counters(ENCRYPTION_KEY_ID, "custom");
+        self.encryptor = Arc::new(MeteredEncryptor::new(encryptor, counters));
+        self.encryption_algorithm = "custom";
+        self
+    }
+
+    /// Replaces the configured vault store, e.g. with a database.
+    #[cfg(feature = "vault")]
+    pub fn with_vault_store(mut self, store: Arc<dyn VaultStore>) -> Self {
+        self.vault = store;
+        self
+    }
+
+    /// Enables tenancy with caller-provided tenants, e.g. backed by another
+    /// key store.
+    #[cfg(feature = "tenancy")]
+    pub fn with_tenants(mut self, tenants: Arc<Tenants>) -> Self {
+        self.tenants = Some(tenants);
+        self
+    }
+
+    /// Replaces the audit sink, which logs events by default.
+    pub fn with_audit_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.audit = sink;
+        self
+    }
+
+    /// Replaces the configured blob store, e.g. with object storage.
+    #[cfg(feature = "blobs")]
+    pub fn with_blob_store(mut self, store: Arc<dyn BlobStore>) -> Self {
+        self.blobs = store;
+        self
+    }
+
+    /// Enables anomaly detection with a caller-provided detector, e.g. one
+    /// with additional alert sinks.
+    pub fn with_anomaly_detector(mut self, detector: Arc<AnomalyDetector>) -> Self {
+        self.anomaly = Some(detector);
+        self
+    }
+
+    /// Requires requests to present one of `keys`.
+    pub fn with_api_keys(mut self, keys: ApiKeys) -> Self {
+        self.api_keys = (!keys.is_empty()).then(|| Arc::new(Reloadable::new(keys)));
+        self
+    }
+
+    /// Enforces caller-provided quotas, e.g. counted in another store.
+    pub fn with_quotas(mut self, quotas: Arc<Quotas>) -> Self {
+        self.quotas = Some(quotas);
+        self
+    }
+
+    /// Authorizes requests with a caller-provided policy backend.
+    pub fn with_policy(mut self, policy: Arc<dyn PolicyBackend>) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+}
+
+/// OPA if `policy.opa_url` is set, else the built-in rules.
+fn policy(config: &Config) -> Arc<dyn PolicyBackend> {
+    let policy = &config.policy;
+    match &policy.opa_url {
+        Some(url) => {
+            let url = policy
+                .opa_uri(url)
+                .expect("validated configuration has an http:// OPA URL");
+            let timeout = Duration::from_millis(policy.opa_timeout_ms);
+            Arc::new(OpaPolicy::new(url, timeout))
+        }
+        None => Arc::new(RulePolicy::new(policy.rules.clone(), policy.default)),
+    }
+}
+
+fn api_keys(config: &Config) -> ApiKeys {
+    config
+        .api_keys
+        .keys
+        .iter()
+        .fold(ApiKeys::new(), |keys, (name, key)| {
+            keys.with_key(name, key.key.expose(), key.scopes.clone())
+        })
+}
+
+fn quotas(config: &Config) -> Quotas {
+    let settings = &config.quotas;
+    let store = settings
+        .store()
+        .expect("validated configuration has a valid quota store");
+    let quotas = settings
+        .api_keys
+        .iter()
+        .fold(Quotas::new(store), |quotas, (name, &limits)| {
+            quotas.api_key(name, limits)
+        });
+    settings
+        .tenants
+        .iter()
+        .fold(quotas, |quotas, (tenant, &limits)| {
+            quotas.tenant(tenant, limits)
+        })
+}
+
+/// The detector of `anomaly`, posting to its webhook if one is set.
+fn anomaly_detector(config: &Config, clock: &Arc<dyn Clock>) -> AnomalyDetector {
+    let detector = AnomalyDetector::new(config.anomaly.thresholds()).clock(clock.clone());
+    match &config.anomaly.webhook_url {
+        Some(url) => {
+            let url = config
+                .anomaly
+                .webhook_uri(url)
+                .expect("validated configuration has an http:// alert webhook");
+            detector.with_sink(Arc::new(WebhookAlertSink::new(url)))
+        }
+        None => detector,
+    }
+}
+
+/// The `limits.max_json_*` checks, plus duplicate keys if asked.
+#[cfg(any(feature = "signing", feature = "encryption"))]
+fn json_limits(config: &Config, reject_duplicate_keys: bool) -> StrictJson {
+    StrictJson {
+        reject_duplicate_keys,
+        max_depth: config.limits.max_json_depth,
+        max_entries: config.limits.max_json_entries,
+        max_fields: config.limits.max_json_fields,
+        max_value_bytes: config.limits.max_json_value_bytes,
+    }
+}
+
+/// The signers of the service's own keys by algorithm: the HMAC secret
+/// under every digest, and the private key if one is set.
+#[cfg(feature = "signing")]
+#[cfg_attr(not(feature = "asymmetric"), allow(unused_mut, unused_variables))]
+fn key_signers(config: &Config, blocking: &BlockingPool) -> BTreeMap<String, Arc<dyn AsyncSigner>> {
+    let secret = config
+        .signing
+        .secret
+        .as_ref()
+        .expect("validated configuration always has a signing secret");
+    let hmac = |digest: HmacDigest| -> Arc<dyn AsyncSigner> {
+        Arc::new(HMacSigner::with_digest(secret.expose().to_vec(), digest))
+    };
+    let mut signers: BTreeMap<String, Arc<dyn AsyncSigner>> = HmacDigest::ALL
+        .into_iter()
+        .map(|digest| (digest.algorithm().to_string(), hmac(digest)))
+        .collect();
+    #[cfg(feature = "asymmetric")]
+    if let Some(key) = config
+        .signing
+        .private_key()
+        .expect("validated configuration always has a loadable private key")
+    {
+        // RSA and ECDSA take milliseconds per signature, too long to hold an
+        // async worker.
+        let signer = AsymmetricSigner::new(key);
+        let algorithm = signer.algorithm().to_string();
+        let signer = OffloadedSigner::new(Arc::new(signer), blocking.clone());
+        signers.insert(algorithm, Arc::new(signer));
+    }
+    signers
+}
+
+/// The service's own secret under `signing.key_id` (also used for
+/// signatures without a `keyid`), plus the configured partner keys.
+#[cfg(feature = "signing")]
+fn http_signature_keys(config: &Config) -> Keyring {
+    let keyring = match &config.signing.secret {
+        Some(secret) => Keyring::new()
+            .with_key(
+                &config.signing.key_id,
+                HMacSigner::new(secret.expose().to_vec()),
+            )
+            .with_default(&config.signing.key_id),
+        None => Keyring::new(),
+    };
+    config
+        .http_signatures
+        .keys
+        .iter()
+        .fold(keyring, |keyring, (keyid, secret)| {
+            keyring.with_key(keyid, HMacSigner::new(secret.expose().to_vec()))
+        })
+}
+
+/// The service's own signing keys, exportable to the configured escrow
+/// keys.
+#[cfg(feature = "escrow")]
+fn key_escrow(config: &Config) -> KeyEscrow {
+    let signing = &config.signing;
+    let escrow = match &signing.secret {
+        Some(secret) => KeyEscrow::new().with_key(EscrowedKey::hmac(
+            signing.algorithm.as_str(),
+            &signing.key_id,
+            secret.expose(),
+        )),
+        None => KeyEscrow::new(),
+    };
+    #[cfg(feature = "asymmetric")]
+    let escrow = match signing
+        .private_key()
+        .expect("validated configuration always has a loadable private key")
+    {
+        Some(key) => escrow.with_key(
+            EscrowedKey::private_key(&signing.key_id, &key)
+                .expect("loaded private keys always encode as PKCS#8"),
+        ),
+        None => escrow,
+    };
+    config
+        .escrow
+        .keys
+        .iter()
+        .fold(escrow, |escrow, (name, key)| {
+            let key = crate::crypto::seal::parse_public_key(key)
+                .expect("validated configuration has valid escrow keys");
+            escrow.with_recipient(name, key)
+        })
+}
+
+#[cfg(feature = "asymmetric")]
+fn jwks(config: &Config) -> PublishedJwks {
+    let signing = &config.signing;
+    let keys = signing
+        .private_key()
+        .expect("validated configuration always has a loadable private key")
+        .map(|key| Jwk::from_private_key(Some(signing.key_id.clone()), &key));
+    PublishedJwks::new(&JwkSet {
+        keys: keys.into_iter().collect(),
+    })
+}
+
+/// The audit log's Merkle tree, whose roots are signed with the private
+/// key, as the JWKS publishes it.
+#[cfg(feature = "asymmetric")]
+fn transparency_log(
+    config: &Config,
+    signers: &BTreeMap<String, Arc<ReloadableSigner>>,
+    clock: &Arc<dyn Clock>,
+) -> TransparencyLog {
+    let alg = config
+        .signing
+        .private_key()
+        .expect("validated configuration always has a loadable private key")
+        .expect("validated transparency configuration has a private key")
+        .algorithm();
+    TransparencyLog::new(signers[alg].clone(), alg, &config.signing.key_id)
+        .max_roots(config.transparency.max_roots)
+        .clock(clock.clone())
+}
+
+/// The clients of `oauth`, whose tokens are signed with the private key.
+#[cfg(feature = "asymmetric")]
+fn token_issuer(config: &Config) -> TokenIssuer {
+    let oauth = &config.oauth;
+    let key = config
+        .signing
+        .private_key()
+        .expect("validated configuration always has a loadable private key")
+        .expect("validated OAuth configuration has a private key");
+    let issuer = TokenIssuer::new(&oauth.issuer, &config.signing.key_id, key.algorithm())
+        .expect("every private key type has a JWS algorithm")
+        .audience(oauth.audience.clone())
+        .ttl_secs(oauth.token_ttl_secs)
+        .clock_skew_secs(config.clock.skew_secs)
+        .dpop_proofs(
+            oauth
+                .dpop_store()
+                .expect("validated configuration always has a usable DPoP proof store"),
+            oauth.dpop_max_age_secs,
+        );
+    let issuer = if oauth.refresh_tokens {
+        let store = oauth
+            .refresh_store()
+            .expect("validated configuration always has a usable refresh token store");
+        issuer.refresh_tokens(store, oauth.refresh_token_ttl_secs)
+    } else {
+        issuer
+    };
+    oauth.clients.iter().fold(issuer, |issuer, (id, client)| {
+        issuer.client(id, client.secret.expose(), client.scopes.clone())
+    })
+}
+
+#[cfg(feature = "encryption")]
+pub(crate) use crate::crypto::encryptor::ENCRYPTION_KEY_ID;
+
+#[cfg(feature = "tenancy")]
+fn tenants(
+    config: &Config,
+    key_usage: Arc<KeyUsage>,
+    events: Arc<EventHub>,
+    clock: &Arc<dyn Clock>,
+) -> Tenants {
+    let tenancy = &config.tenancy;
+    let source = tenancy
+        .source(clock.clone())
+        .expect("validated configuration has a valid tenant key source");
+    let capacity = NonZeroUsize::new(tenancy.cache_capacity)
+        .expect("validated configuration has a positive tenant cache capacity");
+    Tenants::new(
+        source,
+        capacity,
+        std::time::Duration::from_secs(tenancy.cache_ttl_secs),
+    )
+    .required(tenancy.required)
+    .destruction_grace(std::time::Duration::from_secs(
+        tenancy.destruction_grace_secs,
+    ))
+    .key_usage(key_usage)
+    .notifier(Arc::new(notifier(config, events, clock)))
+    .clock(clock.clone())
+}
+
+/// Logs key events, streams them to `GET /events` subscribers and delivers
+/// them to the `notifications.webhooks`.
+#[cfg(feature = "tenancy")]
+fn notifier(config: &Config, events: Arc<EventHub>, clock: &Arc<dyn Clock>) -> Notifier {
+    let notifications = &config.notifications;
+    notifications
+        .webhooks
+        .iter()
+        .fold(Notifier::new().with_sink(events), |notifier, webhook| {
+            let url = webhook
+                .uri()
+                .expect("validated configuration has http:// notification webhooks");
+            let events = webhook
+                .events
+                .iter()
+                .map(|event| event.parse())
+                .collect::<Result<_, _>>()
+                .expect("validated configuration has known key events");
+            notifier.with_sink(Arc::new(
+                WebhookNotificationSink::new(
+                    url,
+                    webhook.secret.expose().to_vec(),
+                    events,
+                    notifications.max_attempts,
+                )
+                .clock(clock.clone()),
+            ))
+        })
+}
+
+/// Spawns the batching writer when a durable audit log is configured, so
+/// this must then run inside a Tokio runtime.
+fn audit_sink(config: &Config, clock: &Arc<dyn Clock>) -> Arc<dyn AuditSink> {
+    let writer = config
+        .audit
+        .writer()
+        .expect("validated configuration has a valid audit sink");
+    match writer {
+        Some(writer) => Arc::new(BatchingAuditSink::spawn(
+            writer,
+            BatchSettings {
+                clock: clock.clone(),
+                ..config.audit.batch_settings()
+            },
+        )),
+        None => Arc::new(TracingAuditSink),
+    }
+}
+
+#[cfg(feature = "signing")]
+fn sigv4_credentials(config: &Config) -> Credentials {
+    config
+        .sigv4
+        .credentials
+        .iter()
+        .fold(Credentials::new(), |credentials, (id, secret)| {
+            credentials.with_secret(id, secret.expose().to_vec())
+        })
+}
+
+#[cfg(feature = "signing")]
+fn webhook_verifier(config: &Config) -> WebhookVerifier {
+    let webhooks = &config.webhooks;
+    [
+        (Provider::Stripe, &webhooks.stripe),
+        (Provider::GitHub, &webhooks.github),
+        (Provider::Slack, &webhooks.slack),
+    ]
+    .into_iter()
+    .filter_map(|(provider, secret)| Some((provider, secret.as_ref()?)))
+    .fold(
+        WebhookVerifier::new(webhooks.tolerance_secs).clock_skew_secs(config.clock.skew_secs),
+        |verifier, (provider, secret)| verifier.with_secret(provider, secret.expose().to_vec()),
+    )
+}
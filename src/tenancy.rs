@@ -0,0 +1,1737 @@
+//! Per-tenant signing keys. A request naming a tenant in `X-Tenant-Id` is
+//! signed and verified with that tenant's HMAC key instead of the service's
+//! own. Keys come from a [`TenantKeySource`] (the config file, Redis,
+//! Postgres or an embedded SQLite database) and are kept in a bounded cache
+//! for `tenancy.cache_ttl_secs`; the admin API drops entries early after a
+//! key changes, and creates, rotates and moves keys through their
+//! [`KeyState`]s in sources that can store them. While the source is
+//! unreachable, tenants whose keys were loaded before keep verifying with
+//! them but cannot sign; [`Tenants::outage`] reports the failure. Key
+//! changes made through [`Tenants`] are announced to its
+//! [`Notifier`](crate::notifications::Notifier), if it has one.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+use crate::breaker::{BreakerError, BreakerSettings, CircuitBreaker};
+use crate::clock::Clock;
+use crate::config::Secret;
+use crate::crypto::BoxFuture;
+use crate::crypto::hmac::{HMacSigner, HmacDigest};
+use crate::crypto::registry::SignerRegistry;
+use crate::crypto::signer::{AsyncSigner, SignError};
+use crate::notifications::{KeyEvent, KeyEventKind, Notifier};
+use crate::usage::{KeyUsage, MeteredSigner};
+
+pub use crate::layers::TENANT_ID_HEADER;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TenancyError {
+    #[error("tenant ids must be 1 to 64 characters of `A-Z a-z 0-9 . _ -`")]
+    InvalidTenantId,
+    #[error("unknown tenant `{0}`")]
+    UnknownTenant(String),
+    #[error("requests must name a tenant in `X-Tenant-Id`")]
+    MissingTenant,
+    #[error("tenant keys are managed outside the service")]
+    ReadOnlySource,
+    #[error("the tenant key source cannot list tenants")]
+    ListingUnsupported,
+    #[error("invalid tenant listing cursor")]
+    InvalidCursor,
+    #[error("unknown key state `{0}`")]
+    InvalidState(String),
+    #[error("a {from} key cannot become {to}")]
+    InvalidTransition { from: KeyState, to: KeyState },
+    #[error("the key of tenant `{tenant}` is {state}")]
+    KeyUnavailable { tenant: String, state: KeyState },
+    #[error("tenant key source failed: {0}")]
+    Backend(String),
+    #[error("tenant key source did not respond in time")]
+    Timeout,
+    #[error("tenant key source is failing; calls are suspended")]
+    CircuitOpen,
+}
+
+impl TenancyError {
+    /// Whether the key source, rather than the request, is at fault.
+    pub fn is_source_failure(&self) -> bool {
+        matches!(
+            self,
+            TenancyError::Backend(_) | TenancyError::Timeout | TenancyError::CircuitOpen
+        )
+    }
+}
+
+/// Checks that `tenant` can be used as a tenant id, and so as part of a
+/// Redis key.
+pub fn validate_tenant_id(tenant: &str) -> Result<(), TenancyError> {
+    let valid = (1..=64).contains(&tenant.len())
+        && tenant
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(TenancyError::InvalidTenantId)
+    }
+}
+
+/// Where a tenant key is in its lifecycle. Keys from the config file, Redis
+/// and Postgres are always active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum KeyState {
+    /// Created but not in use yet.
+    Pending,
+    #[default]
+    Active,
+    /// Taken out of use; can be reactivated.
+    Disabled,
+    /// Must never be trusted again, not even to verify.
+    Compromised,
+    /// Verifies existing signatures until its grace period ends, then its
+    /// material is deleted.
+    Destroyed,
+}
+
+impl KeyState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyState::Pending => "pending",
+            KeyState::Active => "active",
+            KeyState::Disabled => "disabled",
+            KeyState::Compromised => "compromised",
+            KeyState::Destroyed => "destroyed",
+        }
+    }
+
+    /// Whether the key may make new signatures.
+    pub fn can_sign(self) -> bool {
+        self == KeyState::Active
+    }
+
+    /// Whether signatures made with the key still verify.
+    pub fn can_verify(self) -> bool {
+        matches!(self, KeyState::Active | KeyState::Destroyed)
+    }
+
+    /// Whether a key may move from this state to `next`. A destroyed key
+    /// can be restored, as disabled, during its grace period only.
+    pub fn can_become(self, next: KeyState) -> bool {
+        use KeyState::*;
+
+        matches!(
+            (self, next),
+            (Pending, Active | Disabled | Destroyed)
+                | (Active, Disabled | Compromised | Destroyed)
+                | (Disabled, Active | Compromised | Destroyed)
+                | (Compromised, Destroyed)
+                | (Destroyed, Disabled)
+        )
+    }
+}
+
+impl std::fmt::Display for KeyState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for KeyState {
+    type Err = TenancyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            KeyState::Pending,
+            KeyState::Active,
+            KeyState::Disabled,
+            KeyState::Compromised,
+            KeyState::Destroyed,
+        ]
+        .into_iter()
+        .find(|state| state.as_str() == s)
+        .ok_or_else(|| TenancyError::InvalidState(s.to_string()))
+    }
+}
+
+/// Key material of one tenant.
+#[derive(Debug, Clone)]
+pub struct TenantKey {
+    /// HMAC-SHA256 secret.
+    pub secret: Secret,
+    pub state: KeyState,
+    /// When a destroyed key's grace period ends, in Unix seconds.
+    pub destroy_at: Option<u64>,
+}
+
+impl TenantKey {
+    pub fn active(secret: Secret) -> Self {
+        Self {
+            secret,
+            state: KeyState::Active,
+            destroy_at: None,
+        }
+    }
+}
+
+/// Position in a tenant listing: the last tenant of the previous page.
+/// Written as `<created_at>.<tenant>`, which callers treat as opaque.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TenantCursor {
+    /// `0` for tenants whose creation time is unknown.
+    pub created_at: u64,
+    pub tenant: String,
+}
+
+impl std::fmt::Display for TenantCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.created_at, self.tenant)
+    }
+}
+
+impl std::str::FromStr for TenantCursor {
+    type Err = TenancyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (created_at, tenant) = s.split_once('.').ok_or(TenancyError::InvalidCursor)?;
+        let created_at = created_at
+            .parse()
+            .map_err(|_| TenancyError::InvalidCursor)?;
+        validate_tenant_id(tenant).map_err(|_| TenancyError::InvalidCursor)?;
+        Ok(Self {
+            created_at,
+            tenant: tenant.to_string(),
+        })
+    }
+}
+
+/// Which tenants a [`TenantKeySource::list`] call returns: at most `limit`,
+/// ordered by creation time then id, starting after `after`, and only
+/// those in `state` if set.
+#[derive(Debug, Clone)]
+pub struct TenantListing {
+    pub limit: usize,
+    pub after: Option<TenantCursor>,
+    pub newest_first: bool,
+    pub state: Option<KeyState>,
+}
+
+/// A tenant as listed by the admin API. Keys are never included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantEntry {
+    pub tenant: String,
+    /// Unix time in seconds, if the source records it.
+    pub created_at: Option<u64>,
+    pub updated_at: Option<u64>,
+    pub state: KeyState,
+    pub destroy_at: Option<u64>,
+}
+
+impl TenantEntry {
+    pub fn cursor(&self) -> TenantCursor {
+        TenantCursor {
+            created_at: self.created_at.unwrap_or(0),
+            tenant: self.tenant.clone(),
+        }
+    }
+}
+
+/// One page of a tenant listing.
+#[derive(Debug, Clone)]
+pub struct TenantPage {
+    pub tenants: Vec<TenantEntry>,
+    /// Where the next page starts; `None` on the last page.
+    pub next: Option<TenantCursor>,
+}
+
+/// Where tenant keys are looked up. Called on cache misses only.
+pub trait TenantKeySource: Send + Sync {
+    /// `None` for tenants the source does not know.
+    fn load<'a>(
+        &'a self,
+        tenant: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantKey>, TenancyError>>;
+
+    /// Lists the tenants the source knows, for the admin API. Sources that
+    /// cannot enumerate their keys keep the default, which refuses.
+    fn list<'a>(
+        &'a self,
+        listing: &'a TenantListing,
+    ) -> BoxFuture<'a, Result<Vec<TenantEntry>, TenancyError>> {
+        let _ = listing;
+        Box::pin(async { Err(TenancyError::ListingUnsupported) })
+    }
+
+    /// Creates or replaces the key of `tenant`. Sources whose keys are
+    /// managed elsewhere keep the default, which refuses.
+    fn store<'a>(
+        &'a self,
+        tenant: &'a str,
+        key: &'a TenantKey,
+    ) -> BoxFuture<'a, Result<(), TenancyError>> {
+        let _ = (tenant, key);
+        Box::pin(async { Err(TenancyError::ReadOnlySource) })
+    }
+
+    /// Moves the key of `tenant` to `state` if [`KeyState::can_become`]
+    /// allows it, atomically. `destroy_at` accompanies
+    /// [`KeyState::Destroyed`]. Sources whose keys are managed elsewhere
+    /// keep the default, which refuses.
+    fn transition<'a>(
+        &'a self,
+        tenant: &'a str,
+        state: KeyState,
+        destroy_at: Option<u64>,
+    ) -> BoxFuture<'a, Result<(), TenancyError>> {
+        let _ = (tenant, state, destroy_at);
+        Box::pin(async { Err(TenancyError::ReadOnlySource) })
+    }
+}
+
+/// Sends the calls to a remote source through a [`CircuitBreaker`], so a
+/// failing or slow one is given up on quickly.
+pub struct BreakerSource {
+    inner: Arc<dyn TenantKeySource>,
+    breaker: CircuitBreaker,
+}
+
+impl BreakerSource {
+    pub fn new(inner: Arc<dyn TenantKeySource>, settings: BreakerSettings) -> Self {
+        Self {
+            inner,
+            breaker: CircuitBreaker::new(settings),
+        }
+    }
+
+    async fn call<T>(
+        &self,
+        call: impl Future<Output = Result<T, TenancyError>>,
+    ) -> Result<T, TenancyError> {
+        self.breaker.call(call).await.map_err(|err| match err {
+            BreakerError::Open => TenancyError::CircuitOpen,
+            BreakerError::Timeout(_) => TenancyError::Timeout,
+            BreakerError::Inner(err) => err,
+        })
+    }
+}
+
+impl TenantKeySource for BreakerSource {
+    fn load<'a>(
+        &'a self,
+        tenant: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantKey>, TenancyError>> {
+        Box::pin(self.call(self.inner.load(tenant)))
+    }
+
+    fn list<'a>(
+        &'a self,
+        listing: &'a TenantListing,
+    ) -> BoxFuture<'a, Result<Vec<TenantEntry>, TenancyError>> {
+        Box::pin(self.call(self.inner.list(listing)))
+    }
+
+    fn store<'a>(
+        &'a self,
+        tenant: &'a str,
+        key: &'a TenantKey,
+    ) -> BoxFuture<'a, Result<(), TenancyError>> {
+        Box::pin(self.call(self.inner.store(tenant, key)))
+    }
+
+    fn transition<'a>(
+        &'a self,
+        tenant: &'a str,
+        state: KeyState,
+        destroy_at: Option<u64>,
+    ) -> BoxFuture<'a, Result<(), TenancyError>> {
+        Box::pin(self.call(self.inner.transition(tenant, state, destroy_at)))
+    }
+}
+
+/// Tenants listed under `[tenancy.tenants]` in the config file.
+#[derive(Debug, Default)]
+pub struct StaticTenantSource {
+    keys: HashMap<String, TenantKey>,
+}
+
+impl StaticTenantSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tenant(mut self, tenant: impl Into<String>, secret: Secret) -> Self {
+        self.keys.insert(tenant.into(), TenantKey::active(secret));
+        self
+    }
+}
+
+impl TenantKeySource for StaticTenantSource {
+    fn load<'a>(
+        &'a self,
+        tenant: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantKey>, TenancyError>> {
+        Box::pin(async move { Ok(self.keys.get(tenant).cloned()) })
+    }
+
+    fn list<'a>(
+        &'a self,
+        listing: &'a TenantListing,
+    ) -> BoxFuture<'a, Result<Vec<TenantEntry>, TenancyError>> {
+        let mut tenants: Vec<_> = self
+            .keys
+            .keys()
+            .map(|tenant| TenantEntry {
+                tenant: tenant.clone(),
+                created_at: None,
+                updated_at: None,
+                state: KeyState::Active,
+                destroy_at: None,
+            })
+            .filter(|entry| listing.state.is_none_or(|state| state == entry.state))
+            .collect();
+        tenants.sort_by_key(TenantEntry::cursor);
+        if listing.newest_first {
+            tenants.reverse();
+        }
+        let after = |entry: &TenantEntry| match &listing.after {
+            Some(cursor) if listing.newest_first => entry.cursor() < *cursor,
+            Some(cursor) => entry.cursor() > *cursor,
+            None => true,
+        };
+        tenants.retain(after);
+        tenants.truncate(listing.limit);
+        Box::pin(async move { Ok(tenants) })
+    }
+}
+
+/// Reads the `secret` field of the hash `<prefix><tenant>`, e.g.
+/// `HSET tenant:payments secret <key>`.
+#[cfg(feature = "redis")]
+pub struct RedisTenantSource {
+    client: redis::Client,
+    connection: tokio::sync::OnceCell<redis::aio::ConnectionManager>,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisTenantSource {
+    /// Parses `url`; the connection is opened on first use.
+    pub fn new(url: &str, prefix: impl Into<String>) -> Result<Self, TenancyError> {
+        Ok(Self {
+            client: redis::Client::open(url).map_err(backend)?,
+            connection: tokio::sync::OnceCell::new(),
+            prefix: prefix.into(),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl TenantKeySource for RedisTenantSource {
+    fn load<'a>(
+        &'a self,
+        tenant: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantKey>, TenancyError>> {
+        use redis::AsyncCommands;
+
+        Box::pin(async move {
+            // The manager reconnects by itself once established.
+            let mut connection = self
+                .connection
+                .get_or_try_init(|| self.client.get_connection_manager())
+                .await
+                .map_err(backend)?
+                .clone();
+            let secret: Option<Vec<u8>> = connection
+                .hget(format!("{}{tenant}", self.prefix), "secret")
+                .await
+                .map_err(backend)?;
+            Ok(secret.map(|secret| TenantKey::active(Secret::from_bytes(secret))))
+        })
+    }
+}
+
+/// Runs a query taking the tenant id as `$1` and returning the secret
+/// (`bytea` or `text`) in the first column of at most one row.
+#[cfg(feature = "postgres")]
+pub struct PostgresTenantSource {
+    connection: crate::postgres::Connection,
+    query: String,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresTenantSource {
+    /// Parses `url`; the connection is opened on first use and reopened
+    /// after it drops. Connections are not encrypted.
+    pub fn new(url: &str, query: impl Into<String>) -> Result<Self, TenancyError> {
+        Ok(Self {
+            connection: crate::postgres::Connection::new(url).map_err(backend)?,
+            query: query.into(),
+        })
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl TenantKeySource for PostgresTenantSource {
+    fn load<'a>(
+        &'a self,
+        tenant: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantKey>, TenancyError>> {
+        Box::pin(async move {
+            let (client, _) = self.connection.client().await.map_err(backend)?;
+            let Some(row) = client
+                .query_opt(&self.query, &[&tenant])
+                .await
+                .map_err(backend)?
+            else {
+                return Ok(None);
+            };
+            let secret = row
+                .try_get::<_, Vec<u8>>(0)
+                .or_else(|_| row.try_get::<_, String>(0).map(String::into_bytes))
+                .map_err(backend)?;
+            Ok(Some(TenantKey::active(Secret::from_bytes(secret))))
+        })
+    }
+}
+
+/// Keeps tenant keys in a local SQLite database, for single-node
+/// deployments that create and rotate keys through the admin API. Secrets
+/// are encrypted with ChaCha20-Poly1305 under a key derived from the master
+/// key, and bound to their tenant id, so a copy of the database file alone
+/// reveals nothing and rows cannot be swapped between tenants.
+#[cfg(feature = "sqlite")]
+pub struct SqliteTenantSource {
+    path: std::path::PathBuf,
+    cipher: chacha20poly1305::ChaCha20Poly1305,
+    connection: Arc<Mutex<Option<rusqlite::Connection>>>,
+    clock: Arc<dyn Clock>,
+}
+
+#[cfg(feature = "sqlite")]
+const SQLITE_KEY_INFO: &[u8] = b"take-home tenant keys v1";
+
+#[cfg(feature = "sqlite")]
+impl SqliteTenantSource {
+    /// The database is created, or opened, on first use.
+    pub fn new(path: impl Into<std::path::PathBuf>, master_key: &Secret) -> Self {
+        use chacha20poly1305::KeyInit;
+
+        let mut key = chacha20poly1305::Key::default();
+        hkdf::Hkdf::<sha2::Sha256>::new(None, master_key.expose())
+            .expand(SQLITE_KEY_INFO, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        Self {
+            path: path.into(),
+            cipher: chacha20poly1305::ChaCha20Poly1305::new(&key),
+            connection: Arc::new(Mutex::new(None)),
+            clock: crate::clock::system(),
+        }
+    }
+
+    /// The clock rows are timestamped, and destroyed keys deleted, by.
+    /// Defaults to the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn now(&self) -> i64 {
+        sqlite_secs(self.clock.now())
+    }
+
+    /// Runs `query` on the connection, opening it first if needed, off the
+    /// async workers.
+    async fn with_connection<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> Result<T, TenancyError> {
+        let connection = self.connection.clone();
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            // The connection holds no invariant a panicking holder could
+            // break; SQLite rolls back unfinished statements itself.
+            let mut connection = connection.lock().unwrap_or_else(|e| e.into_inner());
+            let connection = match connection.as_mut() {
+                Some(open) => open,
+                None => connection.insert(open_sqlite(&path)?),
+            };
+            query(connection)
+        })
+        .await
+        .map_err(backend)?
+        .map_err(backend)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(path: &std::path::Path) -> rusqlite::Result<rusqlite::Connection> {
+    let connection = rusqlite::Connection::open(path)?;
+    connection.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         CREATE TABLE IF NOT EXISTS tenant_keys (
+             tenant_id TEXT PRIMARY KEY,
+             nonce BLOB NOT NULL,
+             ciphertext BLOB NOT NULL,
+             created_at INTEGER NOT NULL,
+             updated_at INTEGER NOT NULL
+         );
+         CREATE INDEX IF NOT EXISTS tenant_keys_created
+             ON tenant_keys (created_at, tenant_id);",
+    )?;
+    // Databases created before key states existed lack their columns.
+    let has_state = connection
+        .prepare("SELECT 1 FROM pragma_table_info('tenant_keys') WHERE name = 'state'")?
+        .exists([])?;
+    if !has_state {
+        connection.execute_batch(
+            "ALTER TABLE tenant_keys ADD COLUMN state TEXT NOT NULL DEFAULT 'active';
+             ALTER TABLE tenant_keys ADD COLUMN destroy_at INTEGER;",
+        )?;
+    }
+    Ok(connection)
+}
+
+#[cfg(feature = "sqlite")]
+impl TenantKeySource for SqliteTenantSource {
+    fn load<'a>(
+        &'a self,
+        tenant: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantKey>, TenancyError>> {
+        use chacha20poly1305::aead::{Aead, Payload};
+        use rusqlite::OptionalExtension;
+
+        Box::pin(async move {
+            let id = tenant.to_string();
+            let now = self.now();
+            let row = self
+                .with_connection(move |connection| {
+                    // Destroyed keys whose grace period is over are gone.
+                    connection.execute(
+                        "DELETE FROM tenant_keys WHERE tenant_id = ?1 AND destroy_at <= ?2",
+                        rusqlite::params![id, now],
+                    )?;
+                    connection
+                        .query_row(
+                            "SELECT nonce, ciphertext, state, destroy_at
+                             FROM tenant_keys WHERE tenant_id = ?1",
+                            [id],
+                            |row| {
+                                Ok((
+                                    row.get::<_, Vec<u8>>(0)?,
+                                    row.get::<_, Vec<u8>>(1)?,
+                                    sqlite_state(row, 2)?,
+                                    sqlite_time(row, 3)?,
+                                ))
+                            },
+                        )
+                        .optional()
+                })
+                .await?;
+            let Some((nonce, ciphertext, state, destroy_at)) = row else {
+                return Ok(None);
+            };
+            let nonce = <[u8; 12]>::try_from(nonce.as_slice())
+                .map_err(|_| TenancyError::Backend(format!("malformed key of `{tenant}`")))?;
+            let secret = self
+                .cipher
+                .decrypt(
+                    &nonce.into(),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: tenant.as_bytes(),
+                    },
+                )
+                .map_err(|_| {
+                    TenancyError::Backend(format!(
+                        "key of `{tenant}` failed authentication; wrong master key?"
+                    ))
+                })?;
+            Ok(Some(TenantKey {
+                secret: Secret::from_bytes(secret),
+                state,
+                destroy_at,
+            }))
+        })
+    }
+
+    fn list<'a>(
+        &'a self,
+        listing: &'a TenantListing,
+    ) -> BoxFuture<'a, Result<Vec<TenantEntry>, TenancyError>> {
+        let (comparison, order) = if listing.newest_first {
+            ("<", "DESC")
+        } else {
+            (">", "ASC")
+        };
+        let query = format!(
+            "SELECT tenant_id, created_at, updated_at, state, destroy_at FROM tenant_keys
+             WHERE (?1 IS NULL OR (created_at, tenant_id) {comparison} (?1, ?2))
+                 AND (?4 IS NULL OR state = ?4)
+                 AND (destroy_at IS NULL OR destroy_at > ?5)
+             ORDER BY created_at {order}, tenant_id {order}
+             LIMIT ?3"
+        );
+        let after = listing.after.clone().map(|cursor| {
+            let created_at = i64::try_from(cursor.created_at).unwrap_or(i64::MAX);
+            (created_at, cursor.tenant)
+        });
+        let (created_at, tenant) = after.unzip();
+        let limit = i64::try_from(listing.limit).unwrap_or(i64::MAX);
+        let state = listing.state.map(KeyState::as_str);
+        let now = self.now();
+        Box::pin(self.with_connection(move |connection| {
+            let mut statement = connection.prepare(&query)?;
+            let rows = statement.query_map(
+                rusqlite::params![created_at, tenant, limit, state, now],
+                sqlite_entry,
+            )?;
+            rows.collect()
+        }))
+    }
+
+    fn store<'a>(
+        &'a self,
+        tenant: &'a str,
+        key: &'a TenantKey,
+    ) -> BoxFuture<'a, Result<(), TenancyError>> {
+        use chacha20poly1305::aead::{Aead, AeadCore, OsRng, Payload};
+
+        Box::pin(async move {
+            let nonce = chacha20poly1305::ChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let ciphertext = self
+                .cipher
+                .encrypt(
+                    &nonce,
+                    Payload {
+                        msg: key.secret.expose(),
+                        aad: tenant.as_bytes(),
+                    },
+                )
+                .map_err(backend)?;
+            let id = tenant.to_string();
+            let now = self.now();
+            let state = key.state.as_str();
+            let destroy_at = key.destroy_at.map(sqlite_secs);
+            self.with_connection(move |connection| {
+                connection.execute(
+                    "INSERT INTO tenant_keys
+                         (tenant_id, nonce, ciphertext, created_at, updated_at, state, destroy_at)
+                     VALUES (?1, ?2, ?3, ?4, ?4, ?5, ?6)
+                     ON CONFLICT (tenant_id) DO UPDATE SET
+                         nonce = excluded.nonce,
+                         ciphertext = excluded.ciphertext,
+                         updated_at = excluded.updated_at,
+                         state = excluded.state,
+                         destroy_at = excluded.destroy_at",
+                    rusqlite::params![id, nonce.as_slice(), ciphertext, now, state, destroy_at],
+                )
+            })
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn transition<'a>(
+        &'a self,
+        tenant: &'a str,
+        state: KeyState,
+        destroy_at: Option<u64>,
+    ) -> BoxFuture<'a, Result<(), TenancyError>> {
+        use rusqlite::OptionalExtension;
+
+        let id = tenant.to_string();
+        let now = self.now();
+        let destroy_at = destroy_at.map(sqlite_secs);
+        Box::pin(async move {
+            self.with_connection(move |connection| {
+                let transaction = connection.unchecked_transaction()?;
+                let current = transaction
+                    .query_row(
+                        "SELECT state FROM tenant_keys
+                         WHERE tenant_id = ?1 AND (destroy_at IS NULL OR destroy_at > ?2)",
+                        rusqlite::params![id, now],
+                        |row| sqlite_state(row, 0),
+                    )
+                    .optional()?;
+                let Some(current) = current else {
+                    return Ok(Err(TenancyError::UnknownTenant(id)));
+                };
+                if !current.can_become(state) {
+                    return Ok(Err(TenancyError::InvalidTransition {
+                        from: current,
+                        to: state,
+                    }));
+                }
+                transaction.execute(
+                    "UPDATE tenant_keys SET state = ?2, destroy_at = ?3, updated_at = ?4
+                     WHERE tenant_id = ?1",
+                    rusqlite::params![id, state.as_str(), destroy_at, now],
+                )?;
+                transaction.commit()?;
+                Ok(Ok(()))
+            })
+            .await?
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_secs(secs: u64) -> i64 {
+    i64::try_from(secs).unwrap_or(i64::MAX)
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_time(row: &rusqlite::Row<'_>, column: usize) -> rusqlite::Result<Option<u64>> {
+    row.get::<_, Option<i64>>(column)
+        .map(|secs| secs.map(|secs| u64::try_from(secs).unwrap_or(0)))
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_state(row: &rusqlite::Row<'_>, column: usize) -> rusqlite::Result<KeyState> {
+    let state = row.get::<_, String>(column)?;
+    state.parse().map_err(|err: TenancyError| {
+        rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, err.into())
+    })
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_entry(row: &rusqlite::Row<'_>) -> rusqlite::Result<TenantEntry> {
+    Ok(TenantEntry {
+        tenant: row.get(0)?,
+        created_at: sqlite_time(row, 1)?,
+        updated_at: sqlite_time(row, 2)?,
+        state: sqlite_state(row, 3)?,
+        destroy_at: sqlite_time(row, 4)?,
+    })
+}
+
+#[cfg(any(feature = "redis", feature = "postgres", feature = "sqlite"))]
+fn backend(err: impl std::fmt::Display) -> TenancyError {
+    TenancyError::Backend(err.to_string())
+}
+
+/// Resolves tenants to their signers through a [`TenantKeySource`], caching
+/// the result. Unknown tenants are cached too, so they do not reach the
+/// source on every request; source failures are not.
+pub struct Tenants {
+    source: Arc<dyn TenantKeySource>,
+    cache: Mutex<LruCache<String, Cached>>,
+    ttl: Duration,
+    required: bool,
+    destruction_grace: Duration,
+    key_usage: Option<Arc<KeyUsage>>,
+    notifier: Option<Arc<Notifier>>,
+    /// Destroyed keys already announced as expiring, with their
+    /// `destroy_at`.
+    expiring: Mutex<HashSet<(String, u64)>>,
+    outage: Mutex<Option<Outage>>,
+    clock: Arc<dyn Clock>,
+}
+
+/// A failure of the key source that has not been followed by a successful
+/// load yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outage {
+    /// Unix seconds of the first failed load.
+    pub since: u64,
+}
+
+struct Cached {
+    loaded: Instant,
+    lookup: Lookup,
+}
+
+#[derive(Clone)]
+enum Lookup {
+    Unknown,
+    /// The key is in a state that neither signs nor verifies.
+    Unavailable(KeyState),
+    Ready(SignerRegistry),
+}
+
+/// Verifies with a key that may no longer sign.
+struct VerifyOnly {
+    signer: HMacSigner,
+    tenant: String,
+    state: KeyState,
+}
+
+impl AsyncSigner for VerifyOnly {
+    fn sign_bytes<'a>(&'a self, _bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+        let err = TenancyError::KeyUnavailable {
+            tenant: self.tenant.clone(),
+            state: self.state,
+        };
+        Box::pin(async move { Err(SignError::KeyUnavailable(err.to_string())) })
+    }
+
+    fn verify_bytes<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        AsyncSigner::verify_bytes(&self.signer, bytes, signature)
+    }
+
+    fn verify<'a>(
+        &'a self,
+        map: &'a serde_json::Map<String, serde_json::Value>,
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        AsyncSigner::verify(&self.signer, map, signature)
+    }
+
+    fn verify_array<'a>(
+        &'a self,
+        items: &'a [serde_json::Value],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        AsyncSigner::verify_array(&self.signer, items, signature)
+    }
+}
+
+/// Verifies with a key loaded before the key source became unreachable.
+struct Degraded(Arc<dyn AsyncSigner>);
+
+impl AsyncSigner for Degraded {
+    fn sign_bytes<'a>(&'a self, _bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async {
+            Err(SignError::StoreUnavailable(
+                "tenant keys can only verify until the key source is back".into(),
+            ))
+        })
+    }
+
+    fn verify_bytes<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        self.0.verify_bytes(bytes, signature)
+    }
+
+    fn verify<'a>(
+        &'a self,
+        map: &'a serde_json::Map<String, serde_json::Value>,
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        self.0.verify(map, signature)
+    }
+
+    fn verify_array<'a>(
+        &'a self,
+        items: &'a [serde_json::Value],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        self.0.verify_array(items, signature)
+    }
+}
+
+impl Tenants {
+    pub fn new(source: Arc<dyn TenantKeySource>, capacity: NonZeroUsize, ttl: Duration) -> Self {
+        Self {
+            source,
+            cache: Mutex::new(LruCache::new(capacity)),
+            ttl,
+            required: false,
+            destruction_grace: Duration::from_secs(7 * 24 * 60 * 60),
+            key_usage: None,
+            notifier: None,
+            expiring: Mutex::new(HashSet::new()),
+            outage: Mutex::new(None),
+            clock: crate::clock::system(),
+        }
+    }
+
+    /// The clock destruction deadlines, key events and outages are timed
+    /// by. Defaults to the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Counts the operations of each tenant's key under the key id
+    /// `tenant:<id>`.
+    pub fn key_usage(mut self, key_usage: Arc<KeyUsage>) -> Self {
+        self.key_usage = Some(key_usage);
+        self
+    }
+
+    /// Announces key changes, and destroyed keys about to be deleted, to
+    /// `notifier`.
+    pub fn notifier(mut self, notifier: Arc<Notifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// How long a destroyed key keeps verifying, and can be restored,
+    /// before its material is deleted.
+    pub fn destruction_grace(mut self, grace: Duration) -> Self {
+        self.destruction_grace = grace;
+        self
+    }
+
+    /// Rejects requests that name no tenant instead of serving them with
+    /// the service's own keys.
+    pub fn required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// Signers of `tenant`, from the cache while the entry is fresh. Keys
+    /// that may verify but not sign give signers that refuse to sign. If
+    /// the source fails, an expired entry still serves, verify-only.
+    pub async fn signers(&self, tenant: &str) -> Result<SignerRegistry, TenancyError> {
+        validate_tenant_id(tenant)?;
+        let cached = self
+            .cache()
+            .get(tenant)
+            .filter(|cached| cached.loaded.elapsed() < self.ttl)
+            .map(|cached| cached.lookup.clone());
+        let lookup = match cached {
+            Some(lookup) => lookup,
+            None => match self.source.load(tenant).await {
+                Ok(key) => {
+                    self.source_recovered();
+                    let lookup = match key {
+                        None => Lookup::Unknown,
+                        Some(key) => self.resolve(tenant, key),
+                    };
+                    self.cache().put(
+                        tenant.to_string(),
+                        Cached {
+                            loaded: Instant::now(),
+                            lookup: lookup.clone(),
+                        },
+                    );
+                    lookup
+                }
+                Err(err) if err.is_source_failure() => {
+                    self.source_failed(&err);
+                    let stale = self
+                        .cache()
+                        .peek(tenant)
+                        .map(|cached| cached.lookup.clone());
+                    match stale {
+                        Some(Lookup::Ready(signers)) => {
+                            Lookup::Ready(signers.map(|signer| Arc::new(Degraded(signer.clone()))))
+                        }
+                        Some(lookup) => lookup,
+                        None => return Err(err),
+                    }
+                }
+                Err(err) => return Err(err),
+            },
+        };
+        match lookup {
+            Lookup::Unknown => Err(TenancyError::UnknownTenant(tenant.to_string())),
+            Lookup::Unavailable(state) => Err(TenancyError::KeyUnavailable {
+                tenant: tenant.to_string(),
+                state,
+            }),
+            Lookup::Ready(signers) => Ok(signers),
+        }
+    }
+
+    /// One page of the tenants the source knows.
+    pub async fn list(&self, listing: &TenantListing) -> Result<TenantPage, TenancyError> {
+        // One more than asked tells whether another page follows.
+        let probe = TenantListing {
+            limit: listing.limit.saturating_add(1),
+            ..listing.clone()
+        };
+        let mut tenants = self.source.list(&probe).await?;
+        let next = (tenants.len() > listing.limit).then(|| {
+            tenants.truncate(listing.limit);
+            tenants.last().map(TenantEntry::cursor)
+        });
+        Ok(TenantPage {
+            tenants,
+            next: next.flatten(),
+        })
+    }
+
+    /// Gives `tenant` a new random key in `state`, which must be pending or
+    /// active, creating the tenant if the source does not know it yet.
+    /// Signatures made with the previous key no longer verify.
+    pub async fn rotate(&self, tenant: &str, state: KeyState) -> Result<(), TenancyError> {
+        use rand_core::{OsRng, RngCore};
+
+        validate_tenant_id(tenant)?;
+        if !matches!(state, KeyState::Pending | KeyState::Active) {
+            return Err(TenancyError::InvalidTransition {
+                from: KeyState::Pending,
+                to: state,
+            });
+        }
+        let mut secret = vec![0; 32];
+        OsRng.fill_bytes(&mut secret);
+        let key = TenantKey {
+            secret: Secret::from_bytes(secret),
+            state,
+            destroy_at: None,
+        };
+        let existed = match &self.notifier {
+            Some(_) => self.source.load(tenant).await?.is_some(),
+            None => false,
+        };
+        self.source.store(tenant, &key).await?;
+        self.invalidate(tenant);
+        let kind = if existed {
+            KeyEventKind::Rotated
+        } else {
+            KeyEventKind::Created
+        };
+        self.notify(kind, tenant, state, None);
+        Ok(())
+    }
+
+    /// Moves the key of `tenant` to `state`. Destroyed keys keep verifying
+    /// for the destruction grace period and are deleted after it.
+    pub async fn transition(&self, tenant: &str, state: KeyState) -> Result<(), TenancyError> {
+        validate_tenant_id(tenant)?;
+        let destroy_at = (state == KeyState::Destroyed).then(|| {
+            self.clock
+                .now()
+                .saturating_add(self.destruction_grace.as_secs())
+        });
+        self.source.transition(tenant, state, destroy_at).await?;
+        self.invalidate(tenant);
+        if let Some(kind) = KeyEventKind::entering(state) {
+            self.notify(kind, tenant, state, destroy_at);
+        }
+        Ok(())
+    }
+
+    /// Announces, once each, the destroyed keys whose material is deleted
+    /// within `warning`. Needs a source that can list its tenants.
+    pub async fn announce_expiring(&self, warning: Duration) -> Result<(), TenancyError> {
+        let Some(notifier) = &self.notifier else {
+            return Ok(());
+        };
+        let now = self.clock.now();
+        let horizon = now.saturating_add(warning.as_secs());
+        let mut listing = TenantListing {
+            limit: 500,
+            after: None,
+            newest_first: false,
+            state: Some(KeyState::Destroyed),
+        };
+        let mut due = Vec::new();
+        loop {
+            let page = self.list(&listing).await?;
+            due.extend(page.tenants.into_iter().filter_map(|entry| {
+                let destroy_at = entry.destroy_at.filter(|at| *at <= horizon)?;
+                Some((entry.tenant, destroy_at))
+            }));
+            match page.next {
+                Some(next) => listing.after = Some(next),
+                None => break,
+            }
+        }
+        let mut announced = self
+            .expiring
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        // Deleted keys are not listed again.
+        announced.retain(|(_, destroy_at)| *destroy_at > now);
+        for (tenant, destroy_at) in due {
+            if announced.insert((tenant.clone(), destroy_at)) {
+                notifier.notify(KeyEvent::new(
+                    KeyEventKind::Expiring,
+                    &tenant,
+                    KeyState::Destroyed,
+                    Some(destroy_at),
+                    now,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs [`Tenants::announce_expiring`] every `interval`, logging
+    /// failures, unless the source cannot list its tenants.
+    pub fn spawn_expiry_checks(self: &Arc<Self>, interval: Duration, warning: Duration) {
+        let tenants = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                match tenants.announce_expiring(warning).await {
+                    Ok(()) => {}
+                    Err(TenancyError::ListingUnsupported) => {
+                        tracing::info!(
+                            target: "notifications",
+                            "the tenant key source cannot list keys; not checking for expiry"
+                        );
+                        return;
+                    }
+                    Err(err) => {
+                        tracing::warn!(target: "notifications", error = %err, "expiry check failed");
+                    }
+                }
+            }
+        });
+    }
+
+    /// The current failure of the key source, if its latest load failed.
+    /// Cleared by the next load that succeeds.
+    pub fn outage(&self) -> Option<Outage> {
+        *self.outage_slot()
+    }
+
+    /// Drops the cached keys of `tenant`, so the next request reloads them.
+    /// Returns whether there was an entry.
+    pub fn invalidate(&self, tenant: &str) -> bool {
+        self.cache().pop(tenant).is_some()
+    }
+
+    /// Drops every cached entry.
+    pub fn invalidate_all(&self) {
+        self.cache().clear();
+    }
+
+    fn resolve(&self, tenant: &str, key: TenantKey) -> Lookup {
+        if !key.state.can_verify() {
+            return Lookup::Unavailable(key.state);
+        }
+        // Tenant keys serve every HMAC digest, like the service's own key.
+        let signer = |digest: HmacDigest| -> Arc<dyn AsyncSigner> {
+            let algorithm = digest.algorithm();
+            let signer = HMacSigner::with_digest(key.secret.expose().to_vec(), digest);
+            let signer: Arc<dyn AsyncSigner> = if key.state.can_sign() {
+                Arc::new(signer)
+            } else {
+                Arc::new(VerifyOnly {
+                    signer,
+                    tenant: tenant.to_string(),
+                    state: key.state,
+                })
+            };
+            match &self.key_usage {
+                Some(usage) => {
+                    let counters = usage.counters(&format!("tenant:{tenant}"), algorithm);
+                    Arc::new(MeteredSigner::new(signer, counters))
+                }
+                None => signer,
+            }
+        };
+        let default = HmacDigest::Sha256;
+        let registry = HmacDigest::ALL
+            .into_iter()
+            .filter(|digest| *digest != default)
+            .fold(
+                SignerRegistry::new(default.algorithm(), signer(default)),
+                |registry, digest| registry.with(digest.algorithm(), signer(digest)),
+            );
+        Lookup::Ready(registry)
+    }
+
+    fn notify(&self, kind: KeyEventKind, tenant: &str, state: KeyState, destroy_at: Option<u64>) {
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(KeyEvent::new(
+                kind,
+                tenant,
+                state,
+                destroy_at,
+                self.clock.now(),
+            ));
+        }
+    }
+
+    fn source_failed(&self, err: &TenancyError) {
+        let mut outage = self.outage_slot();
+        if outage.is_none() {
+            tracing::warn!(error = %err, "tenant key source unavailable");
+            *outage = Some(Outage {
+                since: self.clock.now(),
+            });
+        }
+    }
+
+    fn source_recovered(&self) {
+        if self.outage_slot().take().is_some() {
+            tracing::info!("tenant key source recovered");
+        }
+    }
+
+    fn outage_slot(&self) -> std::sync::MutexGuard<'_, Option<Outage>> {
+        // The slot holds no invariant a panicking holder could break.
+        self.outage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, LruCache<String, Cached>> {
+        // The cache holds no invariant a panicking holder could break.
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Counts the lookups that reach it, and fails them while `down`.
+    struct Counting {
+        inner: StaticTenantSource,
+        loads: AtomicUsize,
+        down: AtomicBool,
+    }
+
+    impl TenantKeySource for Counting {
+        fn load<'a>(
+            &'a self,
+            tenant: &'a str,
+        ) -> BoxFuture<'a, Result<Option<TenantKey>, TenancyError>> {
+            self.loads.fetch_add(1, Ordering::Relaxed);
+            if self.down.load(Ordering::Relaxed) {
+                return Box::pin(async { Err(TenancyError::Backend("connection refused".into())) });
+            }
+            self.inner.load(tenant)
+        }
+
+        fn list<'a>(
+            &'a self,
+            listing: &'a TenantListing,
+        ) -> BoxFuture<'a, Result<Vec<TenantEntry>, TenancyError>> {
+            self.inner.list(listing)
+        }
+    }
+
+    fn setup(ttl: Duration) -> (Arc<Counting>, Tenants) {
+        let source = Arc::new(Counting {
+            inner: StaticTenantSource::new()
+                .with_tenant("payments", Secret::new("payments-secret"))
+                .with_tenant("search", Secret::new("search-secret")),
+            loads: AtomicUsize::new(0),
+            down: AtomicBool::new(false),
+        });
+        let tenants = Tenants::new(source.clone(), NonZeroUsize::new(8).unwrap(), ttl);
+        (source, tenants)
+    }
+
+    async fn sign(tenants: &Tenants, tenant: &str) -> String {
+        let signers = tenants.signers(tenant).await.unwrap();
+        let Some(payload) = json!({"amount": 1}).as_object().cloned() else {
+            unreachable!()
+        };
+        signers.default_signer().sign(&payload).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn tenants_get_their_own_keys() {
+        let (_, tenants) = setup(Duration::from_secs(60));
+        assert_ne!(
+            sign(&tenants, "payments").await,
+            sign(&tenants, "search").await
+        );
+        let signers = tenants.signers("payments").await.unwrap();
+        assert_eq!(signers.default_alg(), "hmac-sha256");
+        assert_eq!(
+            signers.algorithms(),
+            vec!["hmac-sha256", "hmac-sha384", "hmac-sha512"]
+        );
+    }
+
+    #[tokio::test]
+    async fn tenant_key_usage_is_counted() {
+        let (source, _) = setup(Duration::from_secs(60));
+        let usage = Arc::new(KeyUsage::new());
+        let tenants = Tenants::new(source, NonZeroUsize::new(8).unwrap(), Duration::ZERO)
+            .key_usage(usage.clone());
+        sign(&tenants, "payments").await;
+        sign(&tenants, "payments").await;
+        let stats = usage.stats();
+        let used: Vec<_> = stats.iter().filter(|key| key.sign > 0).collect();
+        assert_eq!(used.len(), 1);
+        assert_eq!(
+            (
+                used[0].key_id.as_str(),
+                used[0].algorithm.as_str(),
+                used[0].sign
+            ),
+            ("tenant:payments", "hmac-sha256", 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_are_cached_until_invalidated() {
+        let (source, tenants) = setup(Duration::from_secs(60));
+        sign(&tenants, "payments").await;
+        sign(&tenants, "payments").await;
+        assert_eq!(source.loads.load(Ordering::Relaxed), 1);
+
+        assert!(tenants.invalidate("payments"));
+        assert!(!tenants.invalidate("payments"));
+        sign(&tenants, "payments").await;
+        assert_eq!(source.loads.load(Ordering::Relaxed), 2);
+
+        tenants.invalidate_all();
+        sign(&tenants, "payments").await;
+        assert_eq!(source.loads.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn expired_entries_are_reloaded() {
+        let (source, tenants) = setup(Duration::ZERO);
+        sign(&tenants, "payments").await;
+        sign(&tenants, "payments").await;
+        assert_eq!(source.loads.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn loaded_keys_verify_but_do_not_sign_while_the_source_is_down() {
+        let (source, tenants) = setup(Duration::ZERO);
+        let signature = sign(&tenants, "payments").await;
+        assert_eq!(tenants.outage(), None);
+
+        source.down.store(true, Ordering::Relaxed);
+        let signers = tenants.signers("payments").await.unwrap();
+        let Some(payload) = json!({"amount": 1}).as_object().cloned() else {
+            unreachable!()
+        };
+        assert!(
+            signers
+                .default_signer()
+                .verify(&payload, &signature)
+                .await
+                .unwrap()
+        );
+        assert!(matches!(
+            signers.default_signer().sign(&payload).await,
+            Err(SignError::StoreUnavailable(_))
+        ));
+        assert!(matches!(
+            tenants.signers("search").await,
+            Err(TenancyError::Backend(_))
+        ));
+        let outage = tenants.outage().unwrap();
+
+        // Still the same outage.
+        tenants.signers("payments").await.unwrap();
+        assert_eq!(tenants.outage(), Some(outage));
+
+        source.down.store(false, Ordering::Relaxed);
+        assert_eq!(sign(&tenants, "payments").await, signature);
+        assert_eq!(tenants.outage(), None);
+    }
+
+    #[tokio::test]
+    async fn unknown_tenants_are_cached_and_rejected() {
+        let (source, tenants) = setup(Duration::from_secs(60));
+        for _ in 0..2 {
+            assert!(matches!(
+                tenants.signers("billing").await,
+                Err(TenancyError::UnknownTenant(tenant)) if tenant == "billing"
+            ));
+        }
+        assert_eq!(source.loads.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn read_only_sources_cannot_rotate_keys() {
+        let (_, tenants) = setup(Duration::from_secs(60));
+        let before = sign(&tenants, "payments").await;
+        assert!(matches!(
+            tenants.rotate("payments", KeyState::Active).await,
+            Err(TenancyError::ReadOnlySource)
+        ));
+        assert_eq!(sign(&tenants, "payments").await, before);
+    }
+
+    #[tokio::test]
+    async fn read_only_sources_keep_their_keys_active() {
+        let (_, tenants) = setup(Duration::from_secs(60));
+        assert!(matches!(
+            tenants.transition("payments", KeyState::Disabled).await,
+            Err(TenancyError::ReadOnlySource)
+        ));
+        sign(&tenants, "payments").await;
+    }
+
+    #[test]
+    fn key_states_allow_only_their_transitions() {
+        use KeyState::*;
+
+        let states = [Pending, Active, Disabled, Compromised, Destroyed];
+        for state in states {
+            assert_eq!(state.as_str().parse::<KeyState>().unwrap(), state);
+            assert!(!state.can_become(state), "{state}");
+            assert!(!state.can_become(Pending), "{state}");
+        }
+        assert!(Disabled.can_become(Active));
+        assert!(Destroyed.can_become(Disabled));
+        assert!(!Compromised.can_become(Active));
+        assert!(!Destroyed.can_become(Active));
+        assert!(matches!(
+            "revoked".parse::<KeyState>(),
+            Err(TenancyError::InvalidState(_))
+        ));
+    }
+
+    async fn list_all(tenants: &Tenants, limit: usize, newest_first: bool) -> Vec<String> {
+        let mut listing = TenantListing {
+            limit,
+            after: None,
+            newest_first,
+            state: None,
+        };
+        let mut listed = Vec::new();
+        loop {
+            let page = tenants.list(&listing).await.unwrap();
+            assert!(page.tenants.len() <= limit);
+            listed.extend(page.tenants.into_iter().map(|entry| entry.tenant));
+            match page.next {
+                Some(next) => listing.after = Some(next),
+                None => return listed,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn listings_are_paginated_both_ways() {
+        let (_, tenants) = setup(Duration::from_secs(60));
+        assert_eq!(list_all(&tenants, 1, false).await, ["payments", "search"]);
+        assert_eq!(list_all(&tenants, 1, true).await, ["search", "payments"]);
+        assert_eq!(list_all(&tenants, 5, false).await, ["payments", "search"]);
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = TenantCursor {
+            created_at: 1_700_000_000,
+            tenant: "a.b".into(),
+        };
+        assert_eq!(cursor.to_string().parse::<TenantCursor>().unwrap(), cursor);
+        for invalid in ["", "payments", "x.payments", "1.a/b"] {
+            assert!(invalid.parse::<TenantCursor>().is_err(), "{invalid}");
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_tenants_are_listed_by_creation_time() {
+        let path = std::env::temp_dir().join(format!("tenants-list-{}.db", std::process::id()));
+        let source = Arc::new(SqliteTenantSource::new(&path, &Secret::new("master-key")));
+        let tenants = Tenants::new(
+            source.clone(),
+            NonZeroUsize::new(8).unwrap(),
+            Duration::from_secs(60),
+        );
+        for tenant in ["c", "a", "b"] {
+            tenants.rotate(tenant, KeyState::Active).await.unwrap();
+        }
+        // Created within the same second, so ordered by id.
+        assert_eq!(list_all(&tenants, 2, false).await, ["a", "b", "c"]);
+        assert_eq!(list_all(&tenants, 2, true).await, ["c", "b", "a"]);
+        tenants.rotate("a", KeyState::Active).await.unwrap();
+        let page = tenants
+            .list(&TenantListing {
+                limit: 1,
+                after: None,
+                newest_first: false,
+                state: None,
+            })
+            .await
+            .unwrap();
+        let entry = &page.tenants[0];
+        assert!(entry.created_at.is_some() && entry.updated_at >= entry.created_at);
+        drop(tenants);
+        drop(source);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_keys_are_rotated_and_survive_reopening() {
+        let path = std::env::temp_dir().join(format!("tenants-{}.db", std::process::id()));
+        let master = Secret::new("master-key");
+        let open = || {
+            let source = Arc::new(SqliteTenantSource::new(&path, &master));
+            Tenants::new(
+                source,
+                NonZeroUsize::new(8).unwrap(),
+                Duration::from_secs(60),
+            )
+        };
+        let tenants = open();
+        assert!(matches!(
+            tenants.signers("payments").await,
+            Err(TenancyError::UnknownTenant(_))
+        ));
+        tenants.rotate("payments", KeyState::Active).await.unwrap();
+        let first = sign(&tenants, "payments").await;
+        tenants.rotate("payments", KeyState::Active).await.unwrap();
+        let second = sign(&tenants, "payments").await;
+        assert_ne!(first, second);
+
+        assert_eq!(sign(&open(), "payments").await, second);
+        let wrong = Arc::new(SqliteTenantSource::new(&path, &Secret::new("other")));
+        assert!(matches!(
+            wrong.load("payments").await,
+            Err(TenancyError::Backend(_))
+        ));
+        drop(tenants);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_key_states_gate_signing_and_verifying() {
+        let path = std::env::temp_dir().join(format!("tenants-states-{}.db", std::process::id()));
+        let source = Arc::new(SqliteTenantSource::new(&path, &Secret::new("master-key")));
+        let tenants = Tenants::new(source, NonZeroUsize::new(8).unwrap(), Duration::ZERO);
+        let Some(payload) = json!({"amount": 1}).as_object().cloned() else {
+            unreachable!()
+        };
+        let can_sign = async |tenants: &Tenants| match tenants.signers("ads").await {
+            Ok(signers) => signers.default_signer().sign(&payload).await.is_ok(),
+            Err(TenancyError::KeyUnavailable { .. }) => false,
+            Err(err) => panic!("{err}"),
+        };
+
+        assert!(matches!(
+            tenants.rotate("ads", KeyState::Disabled).await,
+            Err(TenancyError::InvalidTransition { .. })
+        ));
+        tenants.rotate("ads", KeyState::Pending).await.unwrap();
+        assert!(!can_sign(&tenants).await);
+        tenants.transition("ads", KeyState::Active).await.unwrap();
+        assert!(can_sign(&tenants).await);
+        let signature = sign(&tenants, "ads").await;
+
+        tenants
+            .transition("ads", KeyState::Compromised)
+            .await
+            .unwrap();
+        assert!(!can_sign(&tenants).await);
+        assert!(matches!(
+            tenants.transition("ads", KeyState::Active).await,
+            Err(TenancyError::InvalidTransition {
+                from: KeyState::Compromised,
+                to: KeyState::Active
+            })
+        ));
+
+        tenants
+            .transition("ads", KeyState::Destroyed)
+            .await
+            .unwrap();
+        let signers = tenants.signers("ads").await.unwrap();
+        let signer = signers.default_signer();
+        assert!(signer.verify(&payload, &signature).await.unwrap());
+        assert!(matches!(
+            signer.sign(&payload).await,
+            Err(SignError::KeyUnavailable(_))
+        ));
+
+        // Without a grace period the key is gone at once.
+        let tenants = tenants.destruction_grace(Duration::ZERO);
+        tenants.transition("ads", KeyState::Disabled).await.unwrap();
+        tenants
+            .transition("ads", KeyState::Destroyed)
+            .await
+            .unwrap();
+        assert!(matches!(
+            tenants.signers("ads").await,
+            Err(TenancyError::UnknownTenant(_))
+        ));
+        assert!(matches!(
+            tenants.transition("ads", KeyState::Disabled).await,
+            Err(TenancyError::UnknownTenant(_))
+        ));
+        drop(tenants);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_key_changes_are_announced() {
+        use crate::notifications::MemoryNotificationSink;
+
+        let path = std::env::temp_dir().join(format!("tenants-events-{}.db", std::process::id()));
+        let source = Arc::new(SqliteTenantSource::new(&path, &Secret::new("master-key")));
+        let sink = Arc::new(MemoryNotificationSink::new());
+        let tenants = Tenants::new(source, NonZeroUsize::new(8).unwrap(), Duration::ZERO)
+            .destruction_grace(Duration::from_secs(3600))
+            .notifier(Arc::new(Notifier::new().with_sink(sink.clone())));
+
+        tenants.rotate("ads", KeyState::Pending).await.unwrap();
+        tenants.transition("ads", KeyState::Active).await.unwrap();
+        tenants.rotate("ads", KeyState::Active).await.unwrap();
+        tenants.transition("ads", KeyState::Disabled).await.unwrap();
+        assert!(tenants.transition("ads", KeyState::Pending).await.is_err());
+        tenants
+            .transition("ads", KeyState::Destroyed)
+            .await
+            .unwrap();
+        // Not within the warning yet, then announced once.
+        tenants
+            .announce_expiring(Duration::from_secs(60))
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            tenants
+                .announce_expiring(Duration::from_secs(7200))
+                .await
+                .unwrap();
+        }
+
+        let events = sink.events();
+        let kinds: Vec<&str> = events.iter().map(|event| event.kind.as_str()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "key.created",
+                "key.activated",
+                "key.rotated",
+                "key.disabled",
+                "key.destroyed",
+                "key.expiring"
+            ]
+        );
+        assert!(events.iter().all(|event| event.tenant == "ads"));
+        assert_eq!(events[0].state, "pending");
+        assert!(events[4].destroy_at.is_some());
+        assert_eq!(events[5].destroy_at, events[4].destroy_at);
+        drop(tenants);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_ids_never_reach_the_source() {
+        let (source, tenants) = setup(Duration::from_secs(60));
+        for tenant in ["", "a/b", "tenant:x", &"a".repeat(65)] {
+            assert!(matches!(
+                tenants.signers(tenant).await,
+                Err(TenancyError::InvalidTenantId)
+            ));
+        }
+        assert_eq!(source.loads.load(Ordering::Relaxed), 0);
+    }
+}
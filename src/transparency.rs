@@ -0,0 +1,382 @@
+//! Tamper evidence for the audit log. Every audit event is appended as a
+//! leaf of an RFC 6962 Merkle tree ([`crate::crypto::merkle`]). Every
+//! `transparency.seal_interval_secs` the root of the tree, if it grew, is
+//! signed with the private key published in the JWKS and listed at
+//! `GET /transparency/roots`; `GET /transparency/consistency` proves that
+//! the tree of an earlier root is a prefix of that of a later one. A monitor
+//! that keeps the roots it has seen can so tell when entries under them are
+//! later dropped or rewritten.
+//!
+//! The tree is kept in memory, per process, and starts empty on restart.
+//! Only the latest `transparency.max_roots` roots are kept, with the leaves
+//! appended since the oldest of them: older proofs are the monitors' to
+//! keep.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::audit::AuditEvent;
+use crate::clock::Clock;
+use crate::crypto::envelope::SignatureEnvelope;
+use crate::crypto::merkle::{self, Frontier, Hash};
+use crate::crypto::signer::{AsyncSigner, SignError};
+use crate::models::SealedRoot;
+use crate::state::AppState;
+
+/// Prefixes what a tree head signature covers, so that it can never be
+/// taken for a signature over a payload or a response. `0xFF` never starts
+/// UTF-8 text.
+pub const TREE_HEAD_DOMAIN_TAG: &[u8] = b"\xfftake-home tree head v1\0";
+
+/// What the signature of a root covers: [`TREE_HEAD_DOMAIN_TAG`], then
+/// `tree_size` and `sealed_at` as big-endian 64-bit integers around the
+/// 32 bytes of the root hash.
+pub fn tree_head_signing_input(tree_size: u64, root_hash: &Hash, sealed_at: u64) -> Vec<u8> {
+    [
+        TREE_HEAD_DOMAIN_TAG,
+        &tree_size.to_be_bytes(),
+        root_hash,
+        &sealed_at.to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// The default of `transparency.max_roots`: a day of roots sealed every
+/// minute.
+pub const DEFAULT_MAX_ROOTS: usize = 1440;
+
+#[derive(Debug)]
+struct Retained {
+    root: SealedRoot,
+    /// The tree of `root`, to prove consistency from.
+    frontier: Frontier,
+}
+
+#[derive(Debug, Default)]
+struct Tree {
+    /// Leaves from the `offset`th on, the first of those not yet under the
+    /// oldest retained root.
+    leaves: Vec<Hash>,
+    offset: u64,
+    frontier: Frontier,
+    /// Sealed roots, oldest (smallest) first.
+    roots: VecDeque<Retained>,
+}
+
+pub struct TransparencyLog {
+    signer: Arc<dyn AsyncSigner>,
+    alg: &'static str,
+    key_id: String,
+    max_roots: usize,
+    tree: Mutex<Tree>,
+    clock: Arc<dyn Clock>,
+}
+
+impl TransparencyLog {
+    /// A log whose roots `signer`, an `alg` key published under `key_id`,
+    /// signs.
+    pub fn new(signer: Arc<dyn AsyncSigner>, alg: &'static str, key_id: &str) -> Self {
+        Self {
+            signer,
+            alg,
+            key_id: key_id.to_string(),
+            max_roots: DEFAULT_MAX_ROOTS,
+            tree: Mutex::default(),
+            clock: crate::clock::system(),
+        }
+    }
+
+    /// Keeps only the latest `max_roots` (at least one) sealed roots.
+    pub fn max_roots(mut self, max_roots: usize) -> Self {
+        self.max_roots = max_roots.max(1);
+        self
+    }
+
+    /// Dates sealed roots by `clock` instead of the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Appends `event`, hashed as its JSON serialization, as the next leaf.
+    pub fn append(&self, event: &AuditEvent) {
+        let json = serde_json::to_vec(event).expect("audit events serialize");
+        let leaf = merkle::leaf_hash(&json);
+        let mut tree = self.tree();
+        tree.leaves.push(leaf);
+        tree.frontier.push(leaf);
+    }
+
+    /// Leaves appended so far, sealed or not.
+    pub fn size(&self) -> u64 {
+        self.tree().frontier.size()
+    }
+
+    /// Signs the root of the tree, unless no leaf was appended since the
+    /// latest sealed root. The signature covers
+    /// [`tree_head_signing_input`].
+    pub async fn seal(&self) -> Result<Option<SealedRoot>, SignError> {
+        let frontier = {
+            let tree = self.tree();
+            let sealed = tree.roots.back().map_or(0, |latest| latest.root.tree_size);
+            if tree.frontier.size() <= sealed {
+                return Ok(None);
+            }
+            tree.frontier.clone()
+        };
+        let (tree_size, root) = (frontier.size(), frontier.root());
+        let sealed_at = self.clock.now();
+        let signature = self
+            .signer
+            .sign_bytes(&tree_head_signing_input(tree_size, &root, sealed_at))
+            .await?;
+        let sealed = SealedRoot {
+            tree_size,
+            root_hash: hex(&root),
+            sealed_at,
+            key_id: self.key_id.clone(),
+            signature: SignatureEnvelope::new(self.alg, &signature).to_string(),
+        };
+        // A concurrent seal may have published a larger root meanwhile.
+        let mut tree = self.tree();
+        if tree
+            .roots
+            .back()
+            .is_some_and(|latest| latest.root.tree_size >= tree_size)
+        {
+            return Ok(None);
+        }
+        tree.roots.push_back(Retained {
+            root: sealed.clone(),
+            frontier,
+        });
+        while tree.roots.len() > self.max_roots {
+            tree.roots.pop_front();
+        }
+        let oldest = tree.roots.front().map_or(0, |oldest| oldest.root.tree_size);
+        let dropped = usize::try_from(oldest - tree.offset).expect("retained leaves fit in memory");
+        tree.leaves.drain(..dropped);
+        tree.offset = oldest;
+        Ok(Some(sealed))
+    }
+
+    pub fn latest(&self) -> Option<SealedRoot> {
+        self.tree().roots.back().map(|latest| latest.root.clone())
+    }
+
+    /// Retained sealed roots, oldest first; only those covering more than
+    /// `after` leaves if set.
+    pub fn roots(&self, after: Option<u64>) -> Vec<SealedRoot> {
+        let tree = self.tree();
+        let start = after.map_or(0, |after| {
+            tree.roots
+                .partition_point(|retained| retained.root.tree_size <= after)
+        });
+        tree.roots
+            .range(start..)
+            .map(|retained| retained.root.clone())
+            .collect()
+    }
+
+    /// The proof that the tree of the root sealed at `first` leaves is a
+    /// prefix of that of the root sealed at `second`, both sizes of
+    /// retained roots with `first <= second`.
+    pub fn consistency_proof(&self, first: u64, second: u64) -> Result<Vec<Hash>, ProofError> {
+        if first > second {
+            return Err(ProofError::Order { first, second });
+        }
+        let tree = self.tree();
+        let find = |size: u64| {
+            tree.roots
+                .binary_search_by_key(&size, |retained| retained.root.tree_size)
+                .map(|index| &tree.roots[index])
+                .map_err(|_| ProofError::UnknownRoot(size))
+        };
+        let start = find(first)?;
+        find(second)?;
+        let range =
+            |size: u64| usize::try_from(size - tree.offset).expect("retained leaves fit in memory");
+        let appended = &tree.leaves[range(first)..range(second)];
+        Ok(merkle::consistency_proof_from(&start.frontier, appended))
+    }
+
+    fn tree(&self) -> MutexGuard<'_, Tree> {
+        self.tree.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProofError {
+    #[error("no root was sealed at {0} entries")]
+    UnknownRoot(u64),
+    #[error("`first` ({first}) must not be larger than `second` ({second})")]
+    Order { first: u64, second: u64 },
+}
+
+/// Seals the root of `state`'s log, if the log is enabled and grew since
+/// its latest root.
+pub async fn seal(state: &AppState) -> Result<Option<SealedRoot>, SignError> {
+    match &state.transparency {
+        Some(log) => log.seal().await,
+        None => Ok(None),
+    }
+}
+
+/// Seals a root every `interval`, for as long as the runtime runs. Failed
+/// seals are logged and tried again at the next tick.
+pub fn spawn(state: AppState, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            match seal(&state).await {
+                Ok(Some(root)) => tracing::debug!(
+                    target: "transparency",
+                    tree_size = root.tree_size,
+                    root_hash = %root.root_hash,
+                    "root sealed"
+                ),
+                Ok(None) => {}
+                Err(err) => tracing::error!(target: "transparency", "cannot seal root: {err}"),
+            }
+        }
+    });
+}
+
+pub(crate) fn hex(hash: &Hash) -> String {
+    hash.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use crate::crypto::asymmetric::AsymmetricSigner;
+    use crate::crypto::keys::PrivateKey;
+    use crate::crypto::signer::Signer;
+
+    fn event(n: u64) -> AuditEvent {
+        AuditEvent {
+            at: n,
+            action: "vault.read",
+            resource: format!("entry-{n}"),
+            client: None,
+            success: true,
+        }
+    }
+
+    fn signer() -> Arc<AsymmetricSigner> {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        Arc::new(AsymmetricSigner::new(PrivateKey::Ed25519(key)))
+    }
+
+    fn log() -> TransparencyLog {
+        TransparencyLog::new(signer(), "ed25519", "log-key")
+    }
+
+    #[tokio::test]
+    async fn roots_are_sealed_only_when_the_tree_grows() {
+        let clock = Arc::new(TestClock::new(1_000));
+        let log = log().clock(clock.clone());
+        assert_eq!(log.seal().await.unwrap(), None);
+
+        for n in 0..3 {
+            log.append(&event(n));
+        }
+        let first = log.seal().await.unwrap().unwrap();
+        assert_eq!((first.tree_size, first.sealed_at), (3, 1_000));
+        assert_eq!(first.key_id, "log-key");
+        assert!(first.signature.starts_with("v1.ed25519."));
+        assert_eq!(log.seal().await.unwrap(), None);
+
+        clock.advance(60);
+        log.append(&event(3));
+        let second = log.seal().await.unwrap().unwrap();
+        assert_eq!((second.tree_size, second.sealed_at), (4, 1_060));
+        assert_eq!(log.roots(None), [first.clone(), second.clone()]);
+        assert_eq!(log.roots(Some(3)), [second]);
+    }
+
+    #[tokio::test]
+    async fn tree_heads_are_signed_under_their_domain_tag() {
+        let log = log();
+        log.append(&event(0));
+        let root = log.seal().await.unwrap().unwrap();
+        let envelope = SignatureEnvelope::parse(&root.signature).unwrap();
+        let input = tree_head_signing_input(1, &unhex(&root.root_hash), root.sealed_at);
+        let signer = signer();
+        assert!(input.starts_with(TREE_HEAD_DOMAIN_TAG));
+        assert!(Signer::verify_bytes(&*signer, &input, envelope.signature));
+        let untagged = &input[TREE_HEAD_DOMAIN_TAG.len()..];
+        assert!(!Signer::verify_bytes(
+            &*signer,
+            untagged,
+            envelope.signature
+        ));
+    }
+
+    #[tokio::test]
+    async fn proofs_link_sealed_roots_only() {
+        let log = log();
+        let mut roots = Vec::new();
+        for n in 0..7 {
+            log.append(&event(n));
+            if n % 3 == 1 {
+                roots.push(log.seal().await.unwrap().unwrap());
+            }
+        }
+        let (first, second) = (&roots[0], &roots[1]);
+        let proof = log.consistency_proof(2, 5).unwrap();
+        assert!(merkle::verify_consistency(
+            2,
+            5,
+            &unhex(&first.root_hash),
+            &unhex(&second.root_hash),
+            &proof
+        ));
+        assert!(matches!(
+            log.consistency_proof(2, 7),
+            Err(ProofError::UnknownRoot(7))
+        ));
+        assert!(matches!(
+            log.consistency_proof(5, 2),
+            Err(ProofError::Order { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn only_the_latest_roots_and_their_leaves_are_kept() {
+        let log = log().max_roots(2);
+        let mut roots = Vec::new();
+        for n in 0..9 {
+            log.append(&event(n));
+            if n % 3 == 2 {
+                roots.push(log.seal().await.unwrap().unwrap());
+            }
+        }
+        assert_eq!(log.roots(None), roots[1..]);
+        assert_eq!(log.tree().leaves.len(), 3);
+        assert!(matches!(
+            log.consistency_proof(3, 9),
+            Err(ProofError::UnknownRoot(3))
+        ));
+        let proof = log.consistency_proof(6, 9).unwrap();
+        assert!(merkle::verify_consistency(
+            6,
+            9,
+            &unhex(&roots[1].root_hash),
+            &unhex(&roots[2].root_hash),
+            &proof
+        ));
+    }
+
+    fn unhex(hex: &str) -> Hash {
+        let mut hash = [0; 32];
+        for (byte, pair) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap();
+        }
+        hash
+    }
+}
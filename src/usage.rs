@@ -0,0 +1,294 @@
+//! Counts the operations each key serves and when it last served one, so
+//! operators can see which keys are still in use before rotating them and
+//! spot keys nothing uses anymore. Keys are registered when their signer or
+//! encryptor is wrapped, so unused keys are reported too; the counters are
+//! reported by the admin `GET /metrics` and `GET /keys/usage` endpoints.
+//! Signatures a key rejected and ciphertexts that failed its authentication
+//! are counted too, as security signals.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::clock::Clock;
+#[cfg(any(feature = "signing", feature = "encryption"))]
+use crate::crypto::BoxFuture;
+#[cfg(feature = "encryption")]
+use crate::crypto::encryptor::{AsyncEncryptor, DecryptError, EncryptError};
+#[cfg(feature = "signing")]
+use crate::crypto::signer::{AsyncSigner, SignError};
+use crate::models::KeyUsageStats;
+
+/// Shared by the metered signers and encryptors of one instance.
+#[derive(Debug)]
+pub struct KeyUsage {
+    keys: Mutex<BTreeMap<(String, String), Arc<Counters>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for KeyUsage {
+    fn default() -> Self {
+        Self {
+            keys: Mutex::default(),
+            clock: crate::clock::system(),
+        }
+    }
+}
+
+/// Counters of one key, held by its metered signer or encryptor so
+/// recording an operation takes no lock.
+#[derive(Debug)]
+pub struct Counters {
+    sign: AtomicU64,
+    verify: AtomicU64,
+    encrypt: AtomicU64,
+    decrypt: AtomicU64,
+    verify_failures: AtomicU64,
+    decrypt_failures: AtomicU64,
+    /// Unix seconds; `0` before the first operation.
+    last_used: AtomicU64,
+    clock: Arc<dyn Clock>,
+}
+
+impl Counters {
+    fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            sign: AtomicU64::default(),
+            verify: AtomicU64::default(),
+            encrypt: AtomicU64::default(),
+            decrypt: AtomicU64::default(),
+            verify_failures: AtomicU64::default(),
+            decrypt_failures: AtomicU64::default(),
+            last_used: AtomicU64::default(),
+            clock,
+        }
+    }
+
+    #[cfg_attr(
+        not(any(feature = "signing", feature = "encryption")),
+        allow(dead_code)
+    )]
+    fn record(&self, operation: &AtomicU64) {
+        operation.fetch_add(1, Ordering::Relaxed);
+        self.last_used
+            .fetch_max(self.clock.now(), Ordering::Relaxed);
+    }
+}
+
+impl KeyUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The clock `last_used` is read from. Defaults to the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Counters of the key `key_id` used with `algorithm`, registering it
+    /// on first call. Later calls return the same counters, so reloading a
+    /// key keeps its history.
+    pub fn counters(&self, key_id: &str, algorithm: &str) -> Arc<Counters> {
+        self.keys()
+            .entry((key_id.to_string(), algorithm.to_string()))
+            .or_insert_with(|| Arc::new(Counters::new(self.clock.clone())))
+            .clone()
+    }
+
+    /// Every registered key, ordered by key id then algorithm.
+    pub fn stats(&self) -> Vec<KeyUsageStats> {
+        self.keys()
+            .iter()
+            .map(|((key_id, algorithm), counters)| KeyUsageStats {
+                key_id: key_id.clone(),
+                algorithm: algorithm.clone(),
+                sign: counters.sign.load(Ordering::Relaxed),
+                verify: counters.verify.load(Ordering::Relaxed),
+                encrypt: counters.encrypt.load(Ordering::Relaxed),
+                decrypt: counters.decrypt.load(Ordering::Relaxed),
+                verify_failures: counters.verify_failures.load(Ordering::Relaxed),
+                decrypt_failures: counters.decrypt_failures.load(Ordering::Relaxed),
+                last_used: Some(counters.last_used.load(Ordering::Relaxed))
+                    .filter(|&secs| secs > 0),
+            })
+            .collect()
+    }
+
+    fn keys(&self) -> std::sync::MutexGuard<'_, BTreeMap<(String, String), Arc<Counters>>> {
+        // The map holds no invariant a panicking holder could break.
+        self.keys
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Counts the signatures made and checked by `inner`. Failed operations
+/// are not counted; rejected signatures are, and also as failures.
+#[cfg(feature = "signing")]
+pub struct MeteredSigner {
+    inner: Arc<dyn AsyncSigner>,
+    counters: Arc<Counters>,
+}
+
+#[cfg(feature = "signing")]
+impl MeteredSigner {
+    pub fn new(inner: Arc<dyn AsyncSigner>, counters: Arc<Counters>) -> Self {
+        Self { inner, counters }
+    }
+
+    fn signed<T>(&self, result: Result<T, SignError>) -> Result<T, SignError> {
+        if result.is_ok() {
+            self.counters.record(&self.counters.sign);
+        }
+        result
+    }
+
+    fn verified(&self, result: Result<bool, SignError>) -> Result<bool, SignError> {
+        if let Ok(valid) = result {
+            self.counters.record(&self.counters.verify);
+            if !valid {
+                self.counters
+                    .verify_failures
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(feature = "signing")]
+impl AsyncSigner for MeteredSigner {
+    fn sign_bytes<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async move { self.signed(self.inner.sign_bytes(bytes).await) })
+    }
+
+    fn verify_bytes<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(async move { self.verified(self.inner.verify_bytes(bytes, signature).await) })
+    }
+
+    fn sign<'a>(
+        &'a self,
+        map: &'a serde_json::Map<String, serde_json::Value>,
+    ) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async move { self.signed(self.inner.sign(map).await) })
+    }
+
+    fn verify<'a>(
+        &'a self,
+        map: &'a serde_json::Map<String, serde_json::Value>,
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(async move { self.verified(self.inner.verify(map, signature).await) })
+    }
+
+    fn sign_array<'a>(
+        &'a self,
+        items: &'a [serde_json::Value],
+    ) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async move { self.signed(self.inner.sign_array(items).await) })
+    }
+
+    fn verify_array<'a>(
+        &'a self,
+        items: &'a [serde_json::Value],
+        signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(async move { self.verified(self.inner.verify_array(items, signature).await) })
+    }
+}
+
+/// Counts the values encrypted and decrypted by `inner`, and ciphertexts
+/// that failed authentication.
+#[cfg(feature = "encryption")]
+pub struct MeteredEncryptor {
+    inner: Arc<dyn AsyncEncryptor>,
+    counters: Arc<Counters>,
+}
+
+#[cfg(feature = "encryption")]
+impl MeteredEncryptor {
+    pub fn new(inner: Arc<dyn AsyncEncryptor>, counters: Arc<Counters>) -> Self {
+        Self { inner, counters }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl AsyncEncryptor for MeteredEncryptor {
+    fn encrypt<'a>(
+        &'a self,
+        value: &'a serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, EncryptError>> {
+        Box::pin(async move {
+            let result = self.inner.encrypt(value).await;
+            if result.is_ok() {
+                self.counters.record(&self.counters.encrypt);
+            }
+            result
+        })
+    }
+
+    fn decrypt<'a>(
+        &'a self,
+        value: &'a serde_json::Value,
+    ) -> BoxFuture<'a, Result<serde_json::Value, DecryptError>> {
+        Box::pin(async move {
+            let result = self.inner.decrypt(value).await;
+            match result {
+                Ok(_) => self.counters.record(&self.counters.decrypt),
+                Err(DecryptError::AuthenticationFailed) => {
+                    self.counters
+                        .decrypt_failures
+                        .fetch_add(1, Ordering::Relaxed);
+                }
+                Err(_) => {}
+            }
+            result
+        })
+    }
+}
+
+#[cfg(all(test, feature = "signing"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::clock::TestClock;
+    use crate::crypto::hmac::HMacSigner;
+
+    #[tokio::test]
+    async fn operations_are_counted_per_key() {
+        let usage = KeyUsage::new().clock(Arc::new(TestClock::new(1_000)));
+        let signer = MeteredSigner::new(
+            Arc::new(HMacSigner::new(b"secret".to_vec())),
+            usage.counters("default", "hmac-sha256"),
+        );
+        usage.counters("retired", "hmac-sha256");
+        let Some(payload) = json!({"amount": 1}).as_object().cloned() else {
+            unreachable!()
+        };
+        let signature = signer.sign(&payload).await.unwrap();
+        assert!(signer.verify(&payload, &signature).await.unwrap());
+        assert!(!signer.verify(&payload, "forged").await.unwrap());
+
+        let stats = usage.stats();
+        assert_eq!(stats.len(), 2);
+        let (used, unused) = (&stats[0], &stats[1]);
+        assert_eq!(
+            (
+                used.key_id.as_str(),
+                used.sign,
+                used.verify,
+                used.verify_failures
+            ),
+            ("default", 1, 2, 1)
+        );
+        assert_eq!(used.last_used, Some(1_000));
+        assert_eq!((unused.key_id.as_str(), unused.sign), ("retired", 0));
+        assert_eq!(unused.last_used, None);
+    }
+}
@@ -0,0 +1,362 @@
+//! Small named secrets behind `PUT/GET/DELETE /vault/{name}`. Values are
+//! encrypted with the configured encryptor before they reach a
+//! [`VaultStore`]; the metadata next to them is stored in the clear.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::crypto::BoxFuture;
+use crate::crypto::encryptor::{AsyncEncryptor, DecryptError, EncryptError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum VaultError {
+    #[error(
+        "vault entry names must be 1 to 128 characters of `A-Z a-z 0-9 . _ -` \
+         and not start with `.`"
+    )]
+    InvalidName,
+    #[error("no vault entry named `{0}`")]
+    NotFound(String),
+    #[error("vault entry `{0}` could not be decrypted")]
+    Corrupted(String),
+    #[error(transparent)]
+    Encrypt(#[from] EncryptError),
+    #[error(transparent)]
+    Decrypt(DecryptError),
+    #[error("vault storage failed: {0}")]
+    Storage(#[from] std::io::Error),
+}
+
+/// Checks that `name` can be used as an entry name, and so as a file name.
+pub fn validate_name(name: &str) -> Result<(), VaultError> {
+    let valid = (1..=128).contains(&name.len())
+        && !name.starts_with('.')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(VaultError::InvalidName)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultMetadata {
+    /// Starts at 1 and grows with every write.
+    pub version: u64,
+    /// Unix time in seconds.
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Caller-supplied labels, e.g. owner or rotation policy.
+    pub labels: BTreeMap<String, String>,
+}
+
+/// What a [`VaultStore`] holds for one entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultRecord {
+    /// Output of the encryptor.
+    pub ciphertext: Value,
+    pub metadata: VaultMetadata,
+}
+
+/// Where vault records are kept. Stores only ever see ciphertext.
+pub trait VaultStore: Send + Sync {
+    fn get<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<VaultRecord>, VaultError>>;
+    fn put<'a>(
+        &'a self,
+        name: &'a str,
+        record: VaultRecord,
+    ) -> BoxFuture<'a, Result<(), VaultError>>;
+    /// Returns whether there was an entry to delete.
+    fn delete<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<bool, VaultError>>;
+}
+
+/// Keeps records in memory; they are lost on restart.
+#[derive(Default)]
+pub struct MemoryVaultStore {
+    records: RwLock<HashMap<String, VaultRecord>>,
+}
+
+impl MemoryVaultStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl VaultStore for MemoryVaultStore {
+    fn get<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<VaultRecord>, VaultError>> {
+        let record = self
+            .records
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned();
+        Box::pin(async move { Ok(record) })
+    }
+
+    fn put<'a>(
+        &'a self,
+        name: &'a str,
+        record: VaultRecord,
+    ) -> BoxFuture<'a, Result<(), VaultError>> {
+        self.records
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), record);
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<bool, VaultError>> {
+        let deleted = self
+            .records
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(name)
+            .is_some();
+        Box::pin(async move { Ok(deleted) })
+    }
+}
+
+/// Keeps each record in `<dir>/<name>.json`, written under a temporary
+/// name and renamed so readers never see partial records.
+pub struct DirVaultStore {
+    dir: PathBuf,
+}
+
+impl DirVaultStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.json"))
+    }
+}
+
+impl VaultStore for DirVaultStore {
+    fn get<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<VaultRecord>, VaultError>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.path(name)).await {
+                Ok(contents) => serde_json::from_slice(&contents)
+                    .map(Some)
+                    .map_err(|_| VaultError::Corrupted(name.to_string())),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        name: &'a str,
+        record: VaultRecord,
+    ) -> BoxFuture<'a, Result<(), VaultError>> {
+        Box::pin(async move {
+            let contents = serde_json::to_vec(&record).map_err(EncryptError::from)?;
+            tokio::fs::create_dir_all(&self.dir).await?;
+            let partial = self.dir.join(format!(".{name}.{}", std::process::id()));
+            tokio::fs::write(&partial, contents).await?;
+            tokio::fs::rename(&partial, self.path(name)).await?;
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<bool, VaultError>> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(name)).await {
+                Ok(()) => Ok(true),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+                Err(err) => Err(err.into()),
+            }
+        })
+    }
+}
+
+/// Encrypts `value` and stores it as `name` at Unix time `now`, bumping the
+/// version of an existing entry.
+pub async fn put_secret(
+    store: &dyn VaultStore,
+    encryptor: &dyn AsyncEncryptor,
+    name: &str,
+    value: &Value,
+    labels: BTreeMap<String, String>,
+    now: u64,
+) -> Result<VaultMetadata, VaultError> {
+    validate_name(name)?;
+    let previous = store.get(name).await?.map(|record| record.metadata);
+    let metadata = VaultMetadata {
+        version: previous.as_ref().map_or(1, |m| m.version + 1),
+        created_at: previous.as_ref().map_or(now, |m| m.created_at),
+        updated_at: now,
+        labels,
+    };
+    let ciphertext = encryptor.encrypt(value).await?;
+    store
+        .put(
+            name,
+            VaultRecord {
+                ciphertext,
+                metadata: metadata.clone(),
+            },
+        )
+        .await?;
+    Ok(metadata)
+}
+
+/// Loads and decrypts the entry `name`.
+pub async fn get_secret(
+    store: &dyn VaultStore,
+    encryptor: &dyn AsyncEncryptor,
+    name: &str,
+) -> Result<(Value, VaultMetadata), VaultError> {
+    validate_name(name)?;
+    let record = store
+        .get(name)
+        .await?
+        .ok_or_else(|| VaultError::NotFound(name.to_string()))?;
+    let value = match encryptor.decrypt(&record.ciphertext).await {
+        Ok(value) => value,
+        Err(DecryptError::NotCiphertext | DecryptError::AuthenticationFailed) => {
+            return Err(VaultError::Corrupted(name.to_string()));
+        }
+        Err(err) => return Err(VaultError::Decrypt(err)),
+    };
+    Ok((value, record.metadata))
+}
+
+pub async fn delete_secret(store: &dyn VaultStore, name: &str) -> Result<(), VaultError> {
+    validate_name(name)?;
+    if store.delete(name).await? {
+        Ok(())
+    } else {
+        Err(VaultError::NotFound(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::crypto::base64::Base64Encryptor;
+
+    #[test]
+    fn names_must_be_file_safe() {
+        for name in ["db-password", "stripe.live_key", "A1"] {
+            assert!(validate_name(name).is_ok(), "{name}");
+        }
+        let long = "a".repeat(129);
+        for name in ["", ".hidden", "../etc/passwd", "a/b", "with space", &long] {
+            assert!(
+                matches!(validate_name(name), Err(VaultError::InvalidName)),
+                "{name}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_bump_the_version_and_keep_created_at() {
+        let store = MemoryVaultStore::new();
+        let labels = BTreeMap::from([("owner".to_string(), "payments".to_string())]);
+        let first = put_secret(
+            &store,
+            &Base64Encryptor,
+            "db",
+            &json!("s3cret"),
+            labels,
+            100,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (first.version, first.created_at, first.updated_at),
+            (1, 100, 100)
+        );
+
+        let second = put_secret(
+            &store,
+            &Base64Encryptor,
+            "db",
+            &json!("n3w"),
+            BTreeMap::new(),
+            200,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (second.version, second.created_at, second.updated_at),
+            (2, 100, 200)
+        );
+        assert!(second.labels.is_empty());
+
+        let (value, metadata) = get_secret(&store, &Base64Encryptor, "db").await.unwrap();
+        assert_eq!(value, json!("n3w"));
+        assert_eq!(metadata, second);
+    }
+
+    #[tokio::test]
+    async fn values_are_stored_encrypted() {
+        let store = MemoryVaultStore::new();
+        put_secret(
+            &store,
+            &Base64Encryptor,
+            "db",
+            &json!("s3cret"),
+            BTreeMap::new(),
+            1,
+        )
+        .await
+        .unwrap();
+        let record = store.get("db").await.unwrap().unwrap();
+        assert!(!record.ciphertext.to_string().contains("s3cret"));
+    }
+
+    #[tokio::test]
+    async fn deleted_entries_are_gone() {
+        let store = MemoryVaultStore::new();
+        put_secret(
+            &store,
+            &Base64Encryptor,
+            "db",
+            &json!(1),
+            BTreeMap::new(),
+            1,
+        )
+        .await
+        .unwrap();
+        delete_secret(&store, "db").await.unwrap();
+        assert!(matches!(
+            get_secret(&store, &Base64Encryptor, "db").await,
+            Err(VaultError::NotFound(_))
+        ));
+        assert!(matches!(
+            delete_secret(&store, "db").await,
+            Err(VaultError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn dir_store_persists_across_instances() {
+        let dir = std::env::temp_dir().join(format!("take-home-vault-{}", std::process::id()));
+        put_secret(
+            &DirVaultStore::new(&dir),
+            &Base64Encryptor,
+            "db",
+            &json!({"user": "app"}),
+            BTreeMap::new(),
+            1,
+        )
+        .await
+        .unwrap();
+        let read = get_secret(&DirVaultStore::new(&dir), &Base64Encryptor, "db").await;
+        let deleted = delete_secret(&DirVaultStore::new(&dir), "db").await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(read.unwrap().0, json!({"user": "app"}));
+        assert!(deleted.is_ok());
+    }
+}
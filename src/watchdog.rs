@@ -0,0 +1,279 @@
+//! Periodic round trips through the configured signers and encryptor, to
+//! notice a backend that stopped working between requests: an HSM session
+//! died, a Vault token expired. Each check signs and verifies, or encrypts
+//! and decrypts, a fixed probe. While a backend fails its checks the admin
+//! `/readyz` answers `503`, and every failed check is logged at error level
+//! under the `watchdog` target, so alerts can key on it.
+//!
+//! Backends are checked as registered, beneath the signature cache and the
+//! usage counters: probes neither come from the cache nor count as uses of
+//! a key.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::clock::Clock;
+#[cfg(feature = "encryption")]
+use crate::crypto::encryptor::AsyncEncryptor;
+#[cfg(feature = "signing")]
+use crate::crypto::signer::AsyncSigner;
+use crate::models::{UnavailableBackend, WatchdogStats};
+
+/// Signed, or encrypted, by every check.
+#[cfg(any(feature = "signing", feature = "encryption"))]
+const PROBE: &str = "take-home watchdog probe";
+
+pub struct Watchdog {
+    timeout: Duration,
+    targets: Mutex<BTreeMap<String, Target>>,
+    /// Failing backends, with the Unix seconds of their first failed check.
+    failing: Mutex<BTreeMap<String, u64>>,
+    checks: AtomicU64,
+    failures: AtomicU64,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Clone)]
+enum Target {
+    #[cfg(feature = "signing")]
+    Signer(Arc<dyn AsyncSigner>),
+    #[cfg(feature = "encryption")]
+    Encryptor(Arc<dyn AsyncEncryptor>),
+}
+
+impl Watchdog {
+    /// A watchdog that fails checks taking longer than `timeout`.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            targets: Mutex::new(BTreeMap::new()),
+            failing: Mutex::new(BTreeMap::new()),
+            checks: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            clock: crate::clock::system(),
+        }
+    }
+
+    /// The clock failures are dated by. Defaults to the system clock.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Checks the signer of `alg`, reported as `signer:<alg>`, replacing any
+    /// previous one.
+    #[cfg(feature = "signing")]
+    pub fn watch_signer(&self, alg: &str, signer: Arc<dyn AsyncSigner>) {
+        self.targets()
+            .insert(format!("signer:{alg}"), Target::Signer(signer));
+    }
+
+    /// Checks the encryptor, reported as `encryptor`.
+    #[cfg(feature = "encryption")]
+    pub fn watch_encryptor(&self, encryptor: Arc<dyn AsyncEncryptor>) {
+        self.targets()
+            .insert("encryptor".into(), Target::Encryptor(encryptor));
+    }
+
+    /// Checks every backend once.
+    pub async fn check(&self) {
+        let targets: Vec<(String, Target)> = self
+            .targets()
+            .iter()
+            .map(|(name, target)| (name.clone(), target.clone()))
+            .collect();
+        for (name, target) in targets {
+            self.checks.fetch_add(1, Ordering::Relaxed);
+            let result = match tokio::time::timeout(self.timeout, probe(target)).await {
+                Ok(result) => result,
+                Err(_) => Err(format!("no answer within {:?}", self.timeout)),
+            };
+            let mut failing = self.failing_slot();
+            match result {
+                Ok(()) => {
+                    if failing.remove(&name).is_some() {
+                        tracing::info!(target: "watchdog", backend = %name, "backend self-check recovered");
+                    }
+                }
+                Err(error) => {
+                    self.failures.fetch_add(1, Ordering::Relaxed);
+                    let since = *failing
+                        .entry(name.clone())
+                        .or_insert_with(|| self.clock.now());
+                    tracing::error!(target: "watchdog", backend = %name, %error, since, "backend self-check failed");
+                }
+            }
+        }
+    }
+
+    /// Checks every backend each `interval`, for as long as the runtime
+    /// runs.
+    pub fn spawn(self: &Arc<Self>, interval: Duration) {
+        let watchdog = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                watchdog.check().await;
+            }
+        });
+    }
+
+    /// Backends whose latest check failed.
+    pub fn failing(&self) -> Vec<UnavailableBackend> {
+        self.failing_slot()
+            .iter()
+            .map(|(backend, since)| UnavailableBackend {
+                backend: backend.clone(),
+                since: *since,
+            })
+            .collect()
+    }
+
+    pub fn stats(&self) -> WatchdogStats {
+        WatchdogStats {
+            checks: self.checks.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+        }
+    }
+
+    // Both maps are only ever updated by single inserts and removes.
+    fn targets(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Target>> {
+        self.targets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn failing_slot(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, u64>> {
+        self.failing
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// One round trip through `target`.
+async fn probe(target: Target) -> Result<(), String> {
+    match target {
+        #[cfg(feature = "signing")]
+        Target::Signer(signer) => {
+            let signature = signer
+                .sign_bytes(PROBE.as_bytes())
+                .await
+                .map_err(|err| err.to_string())?;
+            let valid = signer
+                .verify_bytes(PROBE.as_bytes(), &signature)
+                .await
+                .map_err(|err| err.to_string())?;
+            match valid {
+                true => Ok(()),
+                false => Err("the probe signature did not verify".into()),
+            }
+        }
+        #[cfg(feature = "encryption")]
+        Target::Encryptor(encryptor) => {
+            let probe = serde_json::Value::from(PROBE);
+            let ciphertext = encryptor
+                .encrypt(&probe)
+                .await
+                .map_err(|err| err.to_string())?;
+            let plaintext = encryptor
+                .decrypt(&ciphertext)
+                .await
+                .map_err(|err| err.to_string())?;
+            match plaintext == probe {
+                true => Ok(()),
+                false => Err("the probe did not decrypt to itself".into()),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "signing"))]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use super::*;
+    use crate::crypto::BoxFuture;
+    use crate::crypto::hmac::HMacSigner;
+    use crate::crypto::signer::SignError;
+
+    /// Signs with HMAC, or fails while `down` is set.
+    struct Session {
+        signer: HMacSigner,
+        down: AtomicBool,
+    }
+
+    impl AsyncSigner for Session {
+        fn sign_bytes<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+            if self.down.load(Ordering::Relaxed) {
+                return Box::pin(async { Err(SignError::Backend("session expired".into())) });
+            }
+            AsyncSigner::sign_bytes(&self.signer, bytes)
+        }
+
+        fn verify_bytes<'a>(
+            &'a self,
+            bytes: &'a [u8],
+            signature: &'a str,
+        ) -> BoxFuture<'a, Result<bool, SignError>> {
+            AsyncSigner::verify_bytes(&self.signer, bytes, signature)
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_backends_are_reported_until_they_recover() {
+        let session = Arc::new(Session {
+            signer: HMacSigner::new(b"k".to_vec()),
+            down: AtomicBool::new(false),
+        });
+        let watchdog = Watchdog::new(Duration::from_secs(1));
+        watchdog.watch_signer("hmac-sha256", session.clone());
+        watchdog.check().await;
+        assert!(watchdog.failing().is_empty());
+
+        session.down.store(true, Ordering::Relaxed);
+        watchdog.check().await;
+        watchdog.check().await;
+        let failing = watchdog.failing();
+        assert_eq!(failing.len(), 1);
+        assert_eq!(failing[0].backend, "signer:hmac-sha256");
+        assert_eq!(
+            watchdog.stats(),
+            WatchdogStats {
+                checks: 3,
+                failures: 2
+            }
+        );
+
+        session.down.store(false, Ordering::Relaxed);
+        watchdog.check().await;
+        assert!(watchdog.failing().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hung_backends_fail_their_check() {
+        struct Hung;
+
+        impl AsyncSigner for Hung {
+            fn sign_bytes<'a>(&'a self, _: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+                Box::pin(std::future::pending())
+            }
+
+            fn verify_bytes<'a>(
+                &'a self,
+                _: &'a [u8],
+                _: &'a str,
+            ) -> BoxFuture<'a, Result<bool, SignError>> {
+                Box::pin(std::future::pending())
+            }
+        }
+
+        let watchdog = Watchdog::new(Duration::from_secs(1));
+        watchdog.watch_signer("remote", Arc::new(Hung));
+        watchdog.check().await;
+        assert_eq!(watchdog.failing()[0].backend, "signer:remote");
+    }
+}
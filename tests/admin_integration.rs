@@ -0,0 +1,635 @@
+#![cfg(feature = "admin")]
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode},
+};
+use take_home::config::{Config, Secret};
+use tower::ServiceExt;
+
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    config
+}
+
+fn app() -> Router {
+    take_home::admin_app(&test_config())
+}
+
+#[tokio::test]
+async fn healthz_returns_204() {
+    let request = Request::builder()
+        .method("GET")
+        .uri("/healthz")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn post_healthz_returns_method_not_allowed() {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/healthz")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn admin_routes_are_not_served_on_the_data_plane() {
+    let config = test_config();
+    let request = Request::builder()
+        .method("GET")
+        .uri("/healthz")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = take_home::app(&config).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn metrics_report_the_blocking_pool() {
+    use http_body_util::BodyExt;
+    use take_home::models::MetricsResponse;
+
+    let mut config = test_config();
+    config.blocking.max_concurrent = Some(3);
+    let request = Request::builder()
+        .method("GET")
+        .uri("/metrics")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = take_home::admin_app(&config)
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let metrics: MetricsResponse = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(metrics.blocking_pool.max_concurrent, 3);
+    assert_eq!(metrics.blocking_pool.completed, 0);
+    assert_eq!(metrics.signature_cache, None);
+}
+
+#[cfg(feature = "signing")]
+#[tokio::test]
+async fn metrics_report_signature_cache_hits() {
+    use http_body_util::BodyExt;
+    use take_home::models::MetricsResponse;
+    use take_home::state::AppState;
+
+    let mut config = test_config();
+    config.signing.cache.enabled = true;
+    let state = AppState::from_config(&config);
+    for _ in 0..3 {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/sign")
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{"a":1}"#))
+            .unwrap();
+        let response = take_home::router(state.clone(), &config)
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let request = Request::builder()
+        .uri("/metrics")
+        .body(Body::empty())
+        .unwrap();
+    let response = take_home::admin_router(state, &config)
+        .oneshot(request)
+        .await
+        .unwrap();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let metrics: MetricsResponse = serde_json::from_slice(&bytes).unwrap();
+    let cache = metrics.signature_cache.unwrap();
+    assert_eq!((cache.hits, cache.misses, cache.entries), (2, 1, 1));
+    assert!((cache.hit_rate - 2.0 / 3.0).abs() < 1e-9);
+}
+
+#[cfg(feature = "signing")]
+#[tokio::test]
+async fn metrics_report_retries_of_a_remote_signer() {
+    use std::sync::Arc;
+
+    use http_body_util::BodyExt;
+    use take_home::crypto::BoxFuture;
+    use take_home::crypto::signer::{AsyncSigner, SignError};
+    use take_home::models::{MetricsResponse, RetryStats};
+    use take_home::state::AppState;
+
+    /// Remote backend that never answers in time.
+    struct UnreachableSigner;
+
+    impl AsyncSigner for UnreachableSigner {
+        fn sign_bytes<'a>(&'a self, _: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+            Box::pin(async { Err(SignError::Timeout) })
+        }
+
+        fn verify_bytes<'a>(
+            &'a self,
+            _: &'a [u8],
+            _: &'a str,
+        ) -> BoxFuture<'a, Result<bool, SignError>> {
+            Box::pin(async { Err(SignError::Timeout) })
+        }
+    }
+
+    let mut config = test_config();
+    config.retry.initial_backoff_ms = 1;
+    let state = AppState::from_config(&config).with_signer(Arc::new(UnreachableSigner));
+    let request = Request::builder()
+        .method("POST")
+        .uri("/sign")
+        .header("Content-Type", "application/json")
+        .body(Body::from(r#"{"a":1}"#))
+        .unwrap();
+    let response = take_home::router(state.clone(), &config)
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+    let request = Request::builder()
+        .uri("/metrics")
+        .body(Body::empty())
+        .unwrap();
+    let response = take_home::admin_router(state, &config)
+        .oneshot(request)
+        .await
+        .unwrap();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let metrics: MetricsResponse = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(
+        metrics.retries,
+        RetryStats {
+            retries: 2,
+            exhausted: 1
+        }
+    );
+}
+
+#[cfg(all(feature = "signing", feature = "encryption"))]
+#[tokio::test]
+async fn key_usage_counts_operations_per_key() {
+    use http_body_util::BodyExt;
+    use take_home::models::KeyUsageResponse;
+    use take_home::state::AppState;
+
+    let config = test_config();
+    let state = AppState::from_config(&config);
+    for uri in ["/sign", "/sign", "/encrypt"] {
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{"a":1,"b":2}"#))
+            .unwrap();
+        let response = take_home::router(state.clone(), &config)
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{uri}");
+    }
+
+    let usage = async |uri: &str| {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = take_home::admin_router(state.clone(), &config)
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice::<KeyUsageResponse>(&bytes)
+            .unwrap()
+            .keys
+    };
+    let keys = usage("/keys/usage").await;
+    let signing = keys
+        .iter()
+        .find(|key| key.key_id == "default" && key.algorithm == "hmac-sha256")
+        .unwrap();
+    assert_eq!((signing.sign, signing.verify), (2, 0));
+    assert!(signing.last_used.is_some());
+    let encryption = keys.iter().find(|key| key.key_id == "encryption").unwrap();
+    // One operation per encrypted property.
+    assert_eq!((encryption.encrypt, encryption.decrypt), (2, 0));
+
+    // Every key was just used, but not with the other HMAC digests.
+    let idle: Vec<String> = usage("/keys/usage?idle_secs=3600")
+        .await
+        .into_iter()
+        .map(|key| key.algorithm)
+        .collect();
+    assert_eq!(idle, ["hmac-sha384", "hmac-sha512"]);
+}
+
+#[cfg(feature = "signing")]
+#[tokio::test]
+async fn openmetrics_report_verification_failures() {
+    use http_body_util::BodyExt;
+    use take_home::state::AppState;
+
+    let config = test_config();
+    let state = AppState::from_config(&config);
+    let request = Request::builder()
+        .method("POST")
+        .uri("/verify")
+        .header("Content-Type", "application/json")
+        .body(Body::from(r#"{"signature":"forged","data":{"a":1}}"#))
+        .unwrap();
+    let response = take_home::router(state.clone(), &config)
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = Request::builder()
+        .uri("/metrics")
+        .header(
+            "Accept",
+            "application/openmetrics-text;version=1.0.0,text/plain;q=0.5",
+        )
+        .body(Body::empty())
+        .unwrap();
+    let response = take_home::admin_router(state, &config)
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("application/openmetrics-text")
+    );
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let text = String::from_utf8(bytes.to_vec()).unwrap();
+    assert!(text.contains(
+        r#"take_home_verify_failures_total{scheme="payload",reason="mismatch",kid="default",algorithm="hmac-sha256"} 1"#
+    ));
+    assert!(text.ends_with("# EOF\n"));
+}
+
+// ── signed admin requests ──────────────────────────────────────────
+
+#[cfg(feature = "signing")]
+fn signed_config() -> Config {
+    let mut config = test_config();
+    config.middleware.verify_admin_requests = true;
+    config
+        .admin_signatures
+        .keys
+        .insert("ops".into(), Secret::new("ops-admin-secret"));
+    config
+}
+
+#[cfg(feature = "signing")]
+fn signed_app() -> Router {
+    take_home::admin_app(&signed_config())
+}
+
+/// A `method` request to `uri` with an empty body, signed as an admin client
+/// would with `secret` under `keyid`, created at `created`.
+#[cfg(feature = "signing")]
+fn admin_request(
+    method: &str,
+    uri: &str,
+    keyid: &str,
+    secret: &[u8],
+    created: u64,
+    nonce: &str,
+) -> Request<Body> {
+    use take_home::crypto::hmac::HMacSigner;
+    use take_home::crypto::http_signature::{HttpRequest, SignatureParams, content_digest, sign};
+
+    let digest = content_digest(b"");
+    let headers = vec![("content-digest".to_string(), digest.clone())];
+    let target_uri = format!("http://admin.internal{uri}");
+    let request = HttpRequest {
+        method,
+        target_uri: &target_uri,
+        headers: &headers,
+    };
+    let params = SignatureParams {
+        components: vec![
+            "@method".into(),
+            "@request-target".into(),
+            "content-digest".into(),
+        ],
+        created: Some(created),
+        keyid: Some(keyid.into()),
+        nonce: Some(nonce.into()),
+        ..SignatureParams::default()
+    };
+    let signed = sign(
+        &HMacSigner::new(secret.to_vec()),
+        &request,
+        "admin",
+        &params,
+    )
+    .unwrap();
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Host", "admin.internal")
+        .header("Content-Digest", digest)
+        .header("Signature-Input", signed.signature_input)
+        .header("Signature", signed.signature)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(feature = "signing")]
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(feature = "signing")]
+fn get(uri: &str, signature: Option<&str>) -> Request<Body> {
+    let mut builder = Request::builder().method("GET").uri(uri);
+    if let Some(signature) = signature {
+        builder = builder.header("X-Signature", signature);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+#[cfg(feature = "signing")]
+#[tokio::test]
+async fn admin_api_is_open_by_default() {
+    let response = app().oneshot(get("/algorithms", None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[cfg(feature = "signing")]
+#[tokio::test]
+async fn unsigned_admin_request_returns_401() {
+    let response = signed_app()
+        .oneshot(get("/algorithms", None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[cfg(feature = "signing")]
+#[tokio::test]
+async fn admin_requests_signed_with_an_admin_key_are_accepted_once() {
+    let app = signed_app();
+    let request = || {
+        admin_request(
+            "GET",
+            "/algorithms",
+            "ops",
+            b"ops-admin-secret",
+            now(),
+            "n1",
+        )
+    };
+    let response = app.clone().oneshot(request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // The same signature, captured and sent again.
+    let response = app.clone().oneshot(request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Signed for another route.
+    let mut request = admin_request("GET", "/metrics", "ops", b"ops-admin-secret", now(), "n2");
+    *request.uri_mut() = "/algorithms".parse().unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    for (keyid, secret, created) in [
+        ("ops", &b"other-secret"[..], now()),
+        ("default", b"test-secret", now()),
+        ("ops", b"ops-admin-secret", now() - 301),
+        ("ops", b"ops-admin-secret", now() + 60),
+    ] {
+        let request = admin_request("GET", "/algorithms", keyid, secret, created, "n3");
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::UNAUTHORIZED,
+            "{keyid} {created}"
+        );
+    }
+}
+
+#[cfg(all(feature = "signing", feature = "tenancy"))]
+#[tokio::test]
+async fn data_plane_signatures_are_refused_by_the_admin_api() {
+    use http_body_util::BodyExt;
+
+    let mut config = signed_config();
+    config.tenancy.enabled = true;
+    // The signature of an empty body, which `/sign` hands to anyone.
+    let request = Request::builder()
+        .method("POST")
+        .uri("/sign")
+        .header("Content-Type", "application/json")
+        .body(Body::from("{}"))
+        .unwrap();
+    let response = take_home::app(&config).oneshot(request).await.unwrap();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    let signature = body["signature"].as_str().unwrap();
+
+    let request = Request::builder()
+        .method("DELETE")
+        .uri("/tenants/cache")
+        .header("X-Signature", signature)
+        .body(Body::empty())
+        .unwrap();
+    let response = take_home::admin_app(&config)
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Nor can the signing secret be configured as an admin key.
+    config
+        .admin_signatures
+        .keys
+        .insert("default".into(), Secret::new("test-secret"));
+    assert!(config.validate().is_err());
+}
+
+#[cfg(feature = "encryption")]
+mod migration {
+    use std::sync::Arc;
+
+    use http_body_util::BodyExt;
+    use serde_json::{Value, json};
+    use take_home::crypto::base64::Base64Encryptor;
+    use take_home::crypto::encryptor::{DecryptError, EncryptError, Encryptor};
+    use take_home::models::MigrationReport;
+    use take_home::state::AppState;
+
+    use super::*;
+
+    /// Stands in for a real successor of the base64 encoding.
+    struct TaggingEncryptor;
+
+    impl Encryptor for TaggingEncryptor {
+        fn encrypt(&self, value: &Value) -> Result<Value, EncryptError> {
+            Ok(json!({ "tagged": value }))
+        }
+
+        fn decrypt(&self, value: &Value) -> Result<Value, DecryptError> {
+            value
+                .get("tagged")
+                .cloned()
+                .ok_or(DecryptError::NotCiphertext)
+        }
+    }
+
+    fn legacy(value: Value) -> Value {
+        Encryptor::encrypt(&Base64Encryptor, &value).unwrap()
+    }
+
+    fn migrating_app(config: &Config) -> Router {
+        let state = AppState::from_config(config).with_encryptor(Arc::new(TaggingEncryptor));
+        take_home::admin_router(state, config)
+    }
+
+    async fn migrate(app: Router, uri: &str, documents: &[Value]) -> axum::response::Response {
+        let body: String = documents.iter().map(|doc| format!("{doc}\n")).collect();
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("Content-Type", "application/x-ndjson")
+            .body(Body::from(body))
+            .unwrap();
+        app.oneshot(request).await.unwrap()
+    }
+
+    async fn migrated(response: axum::response::Response) -> Vec<Value> {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        std::str::from_utf8(&bytes)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn documents_are_re_encrypted_line_by_line() {
+        let documents = [
+            json!({"name": legacy(json!("Alice")), "age": 30}),
+            json!({
+                "card": {"number": legacy(json!("4111"))},
+                "_crypto": {"paths": {"/card/number": {"alg": "base64", "kid": "encryption"}}},
+            }),
+        ];
+        let app = migrating_app(&test_config());
+        let response = migrate(app, "/encryption/migrate?pointers=/name", &documents).await;
+        assert_eq!(
+            migrated(response).await,
+            [
+                json!({
+                    "name": {"tagged": "Alice"},
+                    "age": 30,
+                    "_crypto": {"paths": {"/name": {"alg": "custom", "kid": "encryption"}}},
+                }),
+                json!({
+                    "card": {"number": {"tagged": "4111"}},
+                    "_crypto": {"paths": {"/card/number": {"alg": "custom", "kid": "encryption"}}},
+                }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn strings_that_decode_as_base64_are_left_alone() {
+        // "MTIz" is base64 for `123`, yet here it is the user's own text.
+        let documents = [json!({"ssn": legacy(json!("078-05-1120")), "nickname": "MTIz"})];
+        let app = migrating_app(&test_config());
+        let response = migrate(app, "/encryption/migrate?pointers=/ssn", &documents).await;
+        let migrated = migrated(response).await;
+        assert_eq!(migrated[0]["ssn"], json!({"tagged": "078-05-1120"}));
+        assert_eq!(migrated[0]["nickname"], "MTIz");
+    }
+
+    #[tokio::test]
+    async fn dry_runs_report_every_failing_line() {
+        let documents = [
+            json!({
+                "name": legacy(json!("Alice")),
+                "_crypto": {"paths": {"/name": {"alg": "base64", "kid": "encryption"}}},
+            }),
+            json!({
+                "card": "4111",
+                "_crypto": {"paths": {"/card": {"alg": "base64", "kid": "encryption"}}},
+            }),
+            json!({
+                "card": "...",
+                "_crypto": {"paths": {"/card": {"alg": "aes-256-gcm", "kid": "old"}}},
+            }),
+            json!({"name": legacy(json!("Bob"))}),
+        ];
+        let app = migrating_app(&test_config());
+        let response = migrate(app, "/encryption/migrate?dry_run=true", &documents).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let report: MigrationReport = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!((report.documents, report.values), (4, 1));
+        let lines: Vec<usize> = report.failures.iter().map(|f| f.line).collect();
+        assert_eq!(lines, [2, 3, 4]);
+        assert!(report.failures[2].error.contains("no pointers were given"));
+    }
+
+    #[tokio::test]
+    async fn a_failing_document_ends_the_response() {
+        let documents = [
+            json!({"name": legacy(json!("Alice"))}),
+            json!({"name": "not base64"}),
+            json!({"name": legacy(json!("Carol"))}),
+        ];
+        let app = migrating_app(&test_config());
+        let response = migrate(app, "/encryption/migrate?pointers=/name", &documents).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body();
+        let first = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        let first: Value = serde_json::from_slice(&first).unwrap();
+        assert_eq!(first["name"], json!({"tagged": "Alice"}));
+        assert!(body.frame().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn lines_are_bounded_by_the_body_limit() {
+        let mut config = test_config();
+        config.limits.max_body_bytes = 64;
+        let documents = [json!({"name": legacy(json!("x".repeat(100)))})];
+        let uri = "/encryption/migrate?pointers=/name&dry_run=true";
+        let response = migrate(migrating_app(&config), uri, &documents).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn values_are_recorded_when_base64_stays_configured() {
+        let documents = [json!({"name": legacy(json!("Alice"))})];
+        let response = migrate(app(), "/encryption/migrate?pointers=/name", &documents).await;
+        assert_eq!(
+            migrated(response).await,
+            [json!({
+                "name": legacy(json!("Alice")),
+                "_crypto": {"paths": {"/name": {"alg": "base64", "kid": "encryption"}}},
+            })]
+        );
+    }
+}
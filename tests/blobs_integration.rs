@@ -0,0 +1,252 @@
+#![cfg(feature = "blobs")]
+
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use take_home::blobs::{BlobAddress, BlobStore, MemoryBlobStore};
+use take_home::config::{Config, Secret};
+use take_home::state::AppState;
+use tower::ServiceExt;
+
+const HELLO: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    config
+}
+
+fn app_with(store: Arc<MemoryBlobStore>) -> Router {
+    app_with_config(store, test_config())
+}
+
+fn chunked_config() -> Config {
+    let mut config = test_config();
+    config.blobs.key = Some(Secret::new("blob-key"));
+    config.blobs.chunk_size = 4;
+    config
+}
+
+fn app_with_config(store: Arc<MemoryBlobStore>, config: Config) -> Router {
+    take_home::router(
+        AppState::from_config(&config).with_blob_store(store),
+        &config,
+    )
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, header::HeaderMap, Vec<u8>) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, headers, bytes.to_vec())
+}
+
+fn put(body: &'static [u8]) -> Request<Body> {
+    Request::builder()
+        .method("PUT")
+        .uri("/blobs")
+        .header("Content-Type", "application/octet-stream")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn get(hash: &str) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/blobs/{hash}"))
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn get_range(hash: &str, range: &str) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/blobs/{hash}"))
+        .header(header::RANGE, range)
+        .body(Body::empty())
+        .unwrap()
+}
+
+// ── PUT /blobs ────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn put_returns_the_sha256_address() {
+    let store = Arc::new(MemoryBlobStore::new());
+    let (status, headers, body) = send(app_with(store.clone()), put(b"hello")).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(headers[header::LOCATION], format!("/blobs/{HELLO}"));
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body, json!({"address": HELLO}));
+
+    let (status, _, _) = send(app_with(store), put(b"hello")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn blobs_are_encrypted_at_rest() {
+    let store = Arc::new(MemoryBlobStore::new());
+    send(app_with(store.clone()), put(b"hello")).await;
+    let sealed = store.get(HELLO.parse().unwrap()).await.unwrap().unwrap();
+    assert!(!sealed.windows(5).any(|w| w == b"hello"));
+}
+
+#[tokio::test]
+async fn oversized_blob_is_rejected() {
+    let mut config = test_config();
+    config.limits.max_body_bytes = 4;
+    let (status, _, body) = send(take_home::app(&config), put(b"hello")).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"]["code"], json!("payload_too_large"));
+}
+
+// ── GET /blobs/{hash} ─────────────────────────────────────────────
+
+/// A blob holding a canonical form, signed on the way out, must not give a
+/// signature `/verify` accepts for that payload.
+#[cfg(feature = "signing")]
+#[tokio::test]
+async fn signed_blob_downloads_are_not_payload_signatures() {
+    let mut config = test_config();
+    config.middleware.sign_responses = true;
+    let app = app_with_config(Arc::new(MemoryBlobStore::new()), config);
+    let (_, _, body) = send(app.clone(), put(b"a=1;")).await;
+    let address: Value = serde_json::from_slice(&body).unwrap();
+    let (status, headers, body) =
+        send(app.clone(), get(address["address"].as_str().unwrap())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, b"a=1;");
+
+    let verify = Request::builder()
+        .method("POST")
+        .uri("/verify")
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            json!({"signature": headers["x-signature"].to_str().unwrap(), "data": {"a": 1}})
+                .to_string(),
+        ))
+        .unwrap();
+    let (status, _, body) = send(app, verify).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"]["code"], json!("invalid_signature"));
+}
+
+#[tokio::test]
+async fn stored_blob_is_returned() {
+    let store = Arc::new(MemoryBlobStore::new());
+    send(app_with(store.clone()), put(b"hello")).await;
+    let (status, headers, body) = send(app_with(store), get(HELLO)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "application/octet-stream");
+    assert_eq!(body, b"hello");
+}
+
+#[tokio::test]
+async fn unknown_and_malformed_addresses() {
+    let store = Arc::new(MemoryBlobStore::new());
+    let (status, _, body) = send(app_with(store.clone()), get(HELLO)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"]["code"], json!("not_found"));
+
+    let (status, _, _) = send(app_with(store), get("not-a-hash")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn tampered_blob_is_not_served() {
+    let store = Arc::new(MemoryBlobStore::new());
+    send(app_with(store.clone()), put(b"other")).await;
+    let other = store.get(BlobAddress::of(b"other")).await.unwrap().unwrap();
+    store.put(HELLO.parse().unwrap(), other).await.unwrap();
+
+    let (status, _, body) = send(app_with(store), get(HELLO)).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"]["code"], json!("crypto_failure"));
+}
+
+// ── chunked blobs ─────────────────────────────────────────────────
+
+const FOX: &[u8] = b"the quick brown fox";
+
+#[tokio::test]
+async fn chunked_blob_serves_ranges() {
+    let store = Arc::new(MemoryBlobStore::new());
+    let (status, _, _) = send(app_with_config(store.clone(), chunked_config()), put(FOX)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let address = BlobAddress::of(FOX).to_string();
+
+    let (status, headers, body) = send(
+        app_with_config(store.clone(), chunked_config()),
+        get_range(&address, "bytes=4-8"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(headers[header::CONTENT_RANGE], "bytes 4-8/19");
+    assert_eq!(headers[header::ACCEPT_RANGES], "bytes");
+    assert_eq!(body, b"quick");
+
+    let (status, headers, body) = send(
+        app_with_config(store.clone(), chunked_config()),
+        get_range(&address, "bytes=-3"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(headers[header::CONTENT_RANGE], "bytes 16-18/19");
+    assert_eq!(body, b"fox");
+
+    let (status, _, body) = send(app_with_config(store, chunked_config()), get(&address)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, FOX);
+}
+
+#[tokio::test]
+async fn range_past_the_end_is_not_satisfiable() {
+    let store = Arc::new(MemoryBlobStore::new());
+    send(app_with_config(store.clone(), chunked_config()), put(FOX)).await;
+    let address = BlobAddress::of(FOX).to_string();
+    let (status, headers, body) = send(
+        app_with_config(store, chunked_config()),
+        get_range(&address, "bytes=19-"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(headers[header::CONTENT_RANGE], "bytes */19");
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"]["code"], json!("range_not_satisfiable"));
+}
+
+#[tokio::test]
+async fn ranges_of_unchunked_blobs_get_the_whole_blob() {
+    let store = Arc::new(MemoryBlobStore::new());
+    send(app_with(store.clone()), put(b"hello")).await;
+    let (status, headers, body) = send(
+        app_with_config(store, chunked_config()),
+        get_range(HELLO, "bytes=1-2"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.get(header::CONTENT_RANGE).is_none());
+    assert_eq!(body, b"hello");
+}
+
+#[tokio::test]
+async fn multiple_ranges_get_the_whole_blob() {
+    let store = Arc::new(MemoryBlobStore::new());
+    send(app_with_config(store.clone(), chunked_config()), put(FOX)).await;
+    let address = BlobAddress::of(FOX).to_string();
+    let (status, _, body) = send(
+        app_with_config(store, chunked_config()),
+        get_range(&address, "bytes=0-1,4-5"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, FOX);
+}
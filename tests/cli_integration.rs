@@ -0,0 +1,347 @@
+#![cfg(feature = "cli")]
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use serde_json::{Value, json};
+
+fn cli(args: &[&str], stdin: &Value) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_take-home-cli"))
+        .args(args)
+        .env_remove("HMAC_SECRET")
+        .env_remove("HMAC_SECRET_FILE")
+        .env_remove("CONFIG_MASTER_KEY")
+        .env_remove("CONFIG_MASTER_KEY_FILE")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The CLI may exit before reading stdin (e.g. on a missing secret), so a
+    // broken pipe here is expected and not a failure.
+    let _ = child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.to_string().as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn stdout_json(output: &Output) -> Value {
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn encrypt_then_decrypt_roundtrip() {
+    let original = json!({"name": "John Doe", "age": 30, "contact": {"email": "john@example.com"}});
+
+    let encrypted = cli(&["encrypt"], &original);
+    assert!(encrypted.status.success());
+    let encrypted = stdout_json(&encrypted);
+    assert!(encrypted["contact"].is_string());
+
+    let decrypted = cli(&["decrypt", "-"], &encrypted);
+    assert!(decrypted.status.success());
+    assert_eq!(stdout_json(&decrypted), original);
+}
+
+#[test]
+fn sign_then_verify_roundtrip() {
+    let data = json!({"message": "Hello World", "timestamp": 1616161616});
+
+    let signed = cli(&["sign", "--secret", "cli-secret"], &data);
+    assert!(signed.status.success());
+    let signature = stdout_json(&signed)["signature"].clone();
+
+    let verified = cli(
+        &["verify", "--secret", "cli-secret"],
+        &json!({"signature": signature, "data": data}),
+    );
+    assert_eq!(verified.status.code(), Some(0));
+}
+
+#[test]
+fn verify_tampered_data_exits_with_1() {
+    let data = json!({"message": "Hello World"});
+    let signed = cli(&["sign", "--secret", "cli-secret"], &data);
+    let signature = stdout_json(&signed)["signature"].clone();
+
+    let verified = cli(
+        &["verify", "--secret", "cli-secret"],
+        &json!({"signature": signature, "data": {"message": "Goodbye World"}}),
+    );
+    assert_eq!(verified.status.code(), Some(1));
+}
+
+#[test]
+fn sign_without_secret_fails() {
+    let output = cli(&["sign"], &json!({"a": 1}));
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no HMAC secret"));
+}
+
+#[test]
+fn sign_non_object_fails() {
+    let output = cli(&["sign", "--secret", "cli-secret"], &json!([1, 2]));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn migrate_records_base64_values_in_sidecars() {
+    let ssn = cli(&["encrypt"], &json!({"ssn": "078-05-1120"}));
+    let ssn = stdout_json(&ssn)["ssn"].clone();
+    let recorded = json!({"paths": {"/ssn": {"alg": "base64", "kid": "encryption"}}});
+    let documents = [
+        json!({"ssn": ssn, "nickname": "MTIz"}),
+        json!({"ssn": ssn, "_crypto": recorded}),
+    ];
+    let path = std::env::temp_dir().join(format!("take-home-migrate-{}", std::process::id()));
+    let ndjson: String = documents.iter().map(|doc| format!("{doc}\n\n")).collect();
+    std::fs::write(&path, ndjson).unwrap();
+
+    let output = cli(
+        &["migrate", "--pointer", "/ssn", path.to_str().unwrap()],
+        &Value::Null,
+    );
+    assert!(output.status.success());
+    let migrated: Vec<Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let expected = json!({"ssn": ssn, "nickname": "MTIz", "_crypto": recorded});
+    assert_eq!(migrated, [expected, documents[1].clone()]);
+
+    // Without pointers, only the document with a sidecar can be migrated.
+    let output = cli(
+        &["migrate", "--dry-run", path.to_str().unwrap()],
+        &Value::Null,
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let report = stdout_json(&output);
+    assert_eq!(report["documents"], 2);
+    assert_eq!(report["failures"][0]["line"], 1);
+
+    let output = cli(&["migrate", path.to_str().unwrap()], &Value::Null);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 1:"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn jwk_export_then_sign_with_jwk_file() {
+    let exported = cli(
+        &["jwk", "export", "--secret", "cli-secret", "--kid", "k1"],
+        &json!(null),
+    );
+    assert!(exported.status.success());
+    let jwk = stdout_json(&exported);
+    assert_eq!(
+        jwk,
+        json!({"kty": "oct", "kid": "k1", "k": "Y2xpLXNlY3JldA"})
+    );
+
+    let path = std::env::temp_dir().join(format!("take-home-cli-{}.jwk", std::process::id()));
+    std::fs::write(&path, jwk.to_string()).unwrap();
+    let data = json!({"message": "Hello World"});
+    let from_jwk = cli(&["sign", "--jwk-file", path.to_str().unwrap()], &data);
+    std::fs::remove_file(&path).unwrap();
+    let from_secret = cli(&["sign", "--secret", "cli-secret"], &data);
+    assert!(from_jwk.status.success());
+    assert_eq!(stdout_json(&from_jwk), stdout_json(&from_secret));
+}
+
+#[test]
+fn jwk_public_strips_private_and_symmetric_keys() {
+    let set = json!({"keys": [
+        {"kty": "oct", "kid": "hmac", "k": "c2VjcmV0"},
+        {"kty": "OKP", "crv": "X25519", "kid": "seal",
+         "x": "hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTmo",
+         "d": "dwdtCnMYpX08FsFyUbJmRd9ML4frwJkqsXf7pR25LCo"}
+    ]});
+    let output = cli(&["jwk", "public"], &set);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout_json(&output),
+        json!({"keys": [{"kty": "OKP", "crv": "X25519", "kid": "seal",
+                         "x": "hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTmo"}]})
+    );
+}
+
+#[cfg(feature = "asymmetric")]
+#[test]
+fn keygen_writes_loadable_key_files_and_prints_settings() {
+    use take_home::crypto::keys::load_private_key;
+
+    let dir = std::env::temp_dir().join(format!("take-home-keygen-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let args = [
+        "keygen",
+        "--type",
+        "p256",
+        "--kid",
+        "edge-1",
+        "--out-dir",
+        dir.to_str().unwrap(),
+    ];
+
+    let output = cli(&args, &Value::Null);
+    assert!(output.status.success());
+    let summary = stdout_json(&output);
+    assert_eq!(summary["algorithm"], "ecdsa-p256-sha256");
+    assert_eq!(summary["public_jwk"]["kid"], "edge-1");
+    assert!(summary["public_jwk"].get("d").is_none());
+    let pem = std::fs::read(dir.join("edge-1.pem")).unwrap();
+    assert!(load_private_key(&pem, None).is_ok());
+    assert_eq!(
+        summary["env"],
+        format!("SIGNING_KEY_FILE={}", dir.join("edge-1.pem").display())
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(dir.join("edge-1.jwk"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // A second run must not overwrite the key.
+    let again = cli(&args, &Value::Null);
+    assert_eq!(again.status.code(), Some(2));
+    assert_eq!(std::fs::read(dir.join("edge-1.pem")).unwrap(), pem);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "escrow")]
+#[test]
+fn escrow_keygen_then_import_recovers_the_signing_key() {
+    use take_home::crypto::escrow::{EscrowedKey, KeyEscrow};
+    use take_home::crypto::jwk::JwkSet;
+    use take_home::crypto::seal::parse_public_key;
+
+    let dir = std::env::temp_dir().join(format!("take-home-escrow-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out_dir = dir.to_str().unwrap();
+
+    let output = cli(
+        &["escrow", "keygen", "--name", "dr", "--out-dir", out_dir],
+        &Value::Null,
+    );
+    assert!(output.status.success());
+    let summary = stdout_json(&output);
+    let public = parse_public_key(summary["public_key"].as_str().unwrap()).unwrap();
+    assert!(
+        summary["config"]
+            .as_str()
+            .unwrap()
+            .starts_with("[escrow.keys]\ndr = ")
+    );
+
+    let bundle = KeyEscrow::new()
+        .with_key(EscrowedKey::hmac("hmac-sha256", "primary", b"s3cret"))
+        .with_recipient("dr", public)
+        .export("hmac-sha256", "dr")
+        .unwrap();
+    let escrow_key = dir.join("dr.jwk");
+    let args = [
+        "escrow",
+        "import",
+        "--escrow-key",
+        escrow_key.to_str().unwrap(),
+        "--out-dir",
+        out_dir,
+    ];
+    let output = cli(&args, &json!(bundle));
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(stdout_json(&output)["kid"], "primary");
+    let jwk = std::fs::read_to_string(dir.join("primary.jwk")).unwrap();
+    let secret = JwkSet::parse(&jwk)
+        .and_then(|set| set.find(Some("primary"))?.symmetric_key())
+        .unwrap();
+    assert_eq!(secret, b"s3cret");
+
+    // A second import must not overwrite the recovered key.
+    assert_eq!(cli(&args, &json!(bundle)).status.code(), Some(2));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "hd-keys")]
+#[test]
+fn keygen_from_mnemonic_is_reproducible() {
+    let dir = std::env::temp_dir().join(format!("take-home-hd-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let output = cli(&["mnemonic", "--words", "12"], &Value::Null);
+    assert!(output.status.success());
+    let mnemonic = stdout_json(&output)["mnemonic"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(mnemonic.split(' ').count(), 12);
+    let seed = dir.join("seed.txt");
+    std::fs::write(&seed, format!("{mnemonic}\n")).unwrap();
+
+    let derive = |kid: &str, path: &str| {
+        let args = [
+            "keygen",
+            "--mnemonic-file",
+            seed.to_str().unwrap(),
+            "--path",
+            path,
+            "--kid",
+            kid,
+            "--out-dir",
+            dir.to_str().unwrap(),
+        ];
+        let output = cli(&args, &Value::Null);
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(stdout_json(&output)["algorithm"], "ed25519");
+        std::fs::read_to_string(dir.join(format!("{kid}.pem"))).unwrap()
+    };
+    let first = derive("purpose-3", "m/44'/0'/3'");
+    let again = derive("purpose-3-restored", "m/44'/0'/3'");
+    let other = derive("purpose-4", "m/44'/0'/4'");
+    std::fs::remove_dir_all(dir).unwrap();
+    assert_eq!(first, again);
+    assert_ne!(first, other);
+}
+
+#[cfg(feature = "config-encryption")]
+#[test]
+fn config_value_encrypt_then_decrypt_roundtrip() {
+    let keygen = cli(&["config-value", "keygen"], &json!(null));
+    assert!(keygen.status.success());
+    let master_key = stdout_json(&keygen)["master_key"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let path = std::env::temp_dir().join(format!("take-home-value-{}", std::process::id()));
+    std::fs::write(&path, "db-password\n").unwrap();
+    let file = path.to_str().unwrap();
+    let encrypted = cli(
+        &["config-value", "encrypt", file, "--master-key", &master_key],
+        &json!(null),
+    );
+    assert!(encrypted.status.success());
+    let value = stdout_json(&encrypted)["value"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(value.starts_with("enc:v1:"));
+
+    std::fs::write(&path, &value).unwrap();
+    let decrypted = cli(
+        &["config-value", "decrypt", file, "--master-key", &master_key],
+        &json!(null),
+    );
+    let wrong_key = cli(
+        &["config-value", "decrypt", file, "--master-key", "other"],
+        &json!(null),
+    );
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(stdout_json(&decrypted), json!({"value": "db-password"}));
+    assert_eq!(wrong_key.status.code(), Some(2));
+}
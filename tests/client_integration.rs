@@ -0,0 +1,257 @@
+#![cfg(all(
+    feature = "client",
+    feature = "server",
+    feature = "encryption",
+    feature = "signing"
+))]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use axum::{Router, http::StatusCode, routing::post};
+use serde_json::json;
+use take_home::client::{Client, ClientError};
+use take_home::config::{ApiKeyConfig, Config, Secret};
+use take_home::models::{FieldStatus, VerifyRequest};
+use take_home::policy::Action;
+
+async fn spawn(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}")
+}
+
+async fn spawn_server() -> String {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    spawn(take_home::app(&config)).await
+}
+
+#[tokio::test]
+async fn encrypt_then_decrypt_roundtrip() {
+    let client = Client::new(&spawn_server().await).unwrap();
+    let original = json!({"name": "John Doe", "age": 30});
+
+    let encrypted = client.encrypt(&original).await.unwrap();
+    assert_ne!(encrypted, original);
+    assert_eq!(client.decrypt(&encrypted).await.unwrap(), original);
+}
+
+#[tokio::test]
+async fn pointer_encryption_roundtrip() {
+    let client = Client::new(&spawn_server().await).unwrap();
+    let original = json!({"contact": {"email": "a@example.com", "name": "Alice"}});
+
+    let encrypted = client
+        .encrypt_pointers(&original, &["/contact/email"])
+        .await
+        .unwrap();
+    assert_eq!(encrypted["contact"]["name"], json!("Alice"));
+    let decrypted = client
+        .decrypt_pointers(&encrypted, &["/contact/email"])
+        .await
+        .unwrap();
+    assert_eq!(decrypted, original);
+}
+
+#[tokio::test]
+async fn encrypt_patch_keeps_the_original_text() {
+    let client = Client::new(&spawn_server().await).unwrap();
+    let document = "{ \"b\": 1.0, \"a\": \"secret\" }";
+    let patched = client.encrypt_patch(document, &["/a"]).await.unwrap();
+    assert!(patched.starts_with("{ \"b\": 1.0, \"a\": \""));
+    assert!(!patched.contains("secret"));
+}
+
+#[tokio::test]
+async fn decrypt_with_status_reports_plain_fields() {
+    let client = Client::new(&spawn_server().await).unwrap();
+    let encrypted = client.encrypt(&json!({"name": "John Doe"})).await.unwrap();
+    let payload = json!({"name": encrypted["name"], "note": "plain text!"});
+
+    let decrypted = client.decrypt_with_status(&payload).await.unwrap();
+    assert_eq!(decrypted.data["name"], json!("John Doe"));
+    assert_eq!(decrypted.fields["name"], FieldStatus::Decrypted);
+    assert_eq!(decrypted.fields["note"], FieldStatus::Passthrough);
+}
+
+#[tokio::test]
+async fn sign_then_verify_roundtrip() {
+    let client = Client::new(&spawn_server().await).unwrap();
+    let data = json!({"message": "Hello World", "timestamp": 1616161616});
+
+    let signature = client.sign(&data).await.unwrap();
+    assert!(client.verify(&data, &signature).await.unwrap());
+    assert!(
+        !client
+            .verify(&json!({"message": "Goodbye World"}), &signature)
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn sign_then_verify_event_batch() {
+    let client = Client::new(&spawn_server().await).unwrap();
+    let events = json!([{"type": "click", "at": 1}, {"type": "view", "at": 2}]);
+    let signature = client.sign(&events).await.unwrap();
+    assert!(client.verify(&events, &signature).await.unwrap());
+    let reordered = json!([{"at": 2, "type": "view"}, {"at": 1, "type": "click"}]);
+    assert!(!client.verify(&reordered, &signature).await.unwrap());
+}
+
+#[tokio::test]
+async fn verify_batch_reports_each_pair() {
+    let client = Client::new(&spawn_server().await).unwrap();
+    let data = json!({"message": "Hello World"});
+    let signature = client.sign(&data).await.unwrap();
+    let pair = |signature: &str| VerifyRequest {
+        signature: signature.to_string(),
+        data: data.as_object().unwrap().clone().into(),
+    };
+
+    let batch = client
+        .verify_batch(&[pair(&signature), pair("00")])
+        .await
+        .unwrap();
+    assert_eq!((batch.valid, batch.invalid), (1, 1));
+    assert!(batch.results[0].valid);
+    assert_eq!(
+        batch.results[1].error.as_ref().unwrap().code,
+        "invalid_signature"
+    );
+}
+
+#[tokio::test]
+async fn sign_scalar_maps_to_status_error() {
+    let client = Client::new(&spawn_server().await).unwrap();
+    let err = client.sign(&json!("text")).await.unwrap_err();
+    assert!(matches!(
+        err,
+        ClientError::Status {
+            status: StatusCode::BAD_REQUEST,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn verify_malformed_request_is_an_error_not_a_mismatch() {
+    let client = Client::new(&spawn_server().await).unwrap();
+    let err = client
+        .verify(&json!("not an object"), "abc123")
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        ClientError::Status { code: Some(ref code), .. } if code == "validation_failed"
+    ));
+}
+
+#[tokio::test]
+async fn transient_failures_are_retried() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let app = Router::new().route(
+        "/encrypt",
+        post(move || {
+            let counter = counter.clone();
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(StatusCode::SERVICE_UNAVAILABLE)
+                } else {
+                    Ok(axum::Json(json!({"ok": true})))
+                }
+            }
+        }),
+    );
+    let client = Client::builder(&spawn(app).await)
+        .initial_backoff(Duration::from_millis(1))
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        client.encrypt(&json!({})).await.unwrap(),
+        json!({"ok": true})
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn retries_give_up_after_max_retries() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let app = Router::new().route(
+        "/encrypt",
+        post(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { StatusCode::BAD_GATEWAY }
+        }),
+    );
+    let client = Client::builder(&spawn(app).await)
+        .max_retries(1)
+        .initial_backoff(Duration::from_millis(1))
+        .build()
+        .unwrap();
+
+    let err = client.encrypt(&json!({})).await.unwrap_err();
+    assert!(matches!(
+        err,
+        ClientError::Status {
+            status: StatusCode::BAD_GATEWAY,
+            ..
+        }
+    ));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn api_key_scopes_limit_what_clients_can_do() {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    for (name, key, scopes) in [
+        ("signer", "sign-key", vec![Action::Sign]),
+        ("partner", "verify-key", vec![Action::Verify]),
+    ] {
+        let key = ApiKeyConfig {
+            key: Secret::new(key),
+            scopes,
+        };
+        config.api_keys.keys.insert(name.into(), key);
+    }
+    let base_url = spawn(take_home::app(&config)).await;
+    let client = |key: Option<&str>| {
+        let builder = Client::builder(&base_url);
+        match key {
+            Some(key) => builder.api_key(key),
+            None => builder,
+        }
+        .build()
+        .unwrap()
+    };
+    let code = |err: ClientError| match err {
+        ClientError::Status { code, .. } => code,
+        other => panic!("unexpected error: {other}"),
+    };
+
+    let data = json!({"a": 1});
+    let signature = client(Some("sign-key")).sign(&data).await.unwrap();
+    let partner = client(Some("verify-key"));
+    assert!(partner.verify(&data, &signature).await.unwrap());
+    assert_eq!(
+        code(partner.sign(&data).await.unwrap_err()).as_deref(),
+        Some("forbidden")
+    );
+    assert_eq!(
+        code(client(None).sign(&data).await.unwrap_err()).as_deref(),
+        Some("unauthorized")
+    );
+    assert_eq!(
+        code(client(Some("guess")).sign(&data).await.unwrap_err()).as_deref(),
+        Some("unauthorized")
+    );
+    // Endpoints that use no key stay open.
+    assert!(client(None).canonicalize(&data).await.is_ok());
+}
@@ -1,17 +1,52 @@
+#![cfg(all(feature = "server", feature = "encryption"))]
+
 use axum::{
     Router,
     body::Body,
-    http::{Request, StatusCode},
-    routing::post,
+    http::{HeaderMap, Request, StatusCode},
 };
 use http_body_util::BodyExt;
 use serde_json::{Value, json};
+use std::sync::Arc;
+use take_home::config::{Config, Secret};
+use take_home::crypto::encryptor::{AsyncEncryptor, DecryptError, EncryptError, Encryptor};
+use take_home::state::AppState;
 use tower::ServiceExt;
 
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    config
+}
+
 fn app() -> Router {
-    Router::new()
-        .route("/encrypt", post(take_home::handlers::encryption::encrypt))
-        .route("/decrypt", post(take_home::handlers::encryption::decrypt))
+    take_home::app(&test_config())
+}
+
+fn app_with(encryptor: Arc<dyn AsyncEncryptor>) -> Router {
+    let config = test_config();
+    take_home::router(
+        AppState::from_config(&config).with_encryptor(encryptor),
+        &config,
+    )
+}
+
+/// Mock that tags values instead of encoding them, to check that the
+/// handlers delegate to whatever `Encryptor` the state carries.
+struct TaggingEncryptor;
+
+impl Encryptor for TaggingEncryptor {
+    fn encrypt(&self, value: &Value) -> Result<Value, EncryptError> {
+        Ok(json!({ "tagged": value }))
+    }
+
+    fn decrypt(&self, value: &Value) -> Result<Value, DecryptError> {
+        match value.get("tagged") {
+            Some(Value::String(s)) if s == "tampered" => Err(DecryptError::AuthenticationFailed),
+            Some(v) => Ok(v.clone()),
+            None => Err(DecryptError::NotCiphertext),
+        }
+    }
 }
 
 async fn post_json(app: Router, uri: &str, body: Value) -> (StatusCode, Value) {
@@ -29,6 +64,20 @@ async fn post_json(app: Router, uri: &str, body: Value) -> (StatusCode, Value) {
     (status, value)
 }
 
+async fn post_json_raw(app: Router, uri: &str, body: Value) -> (StatusCode, Vec<u8>) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, bytes.to_vec())
+}
+
 #[tokio::test]
 async fn encrypt_returns_200() {
     let (status, _) = post_json(app(), "/encrypt", json!({"hello": "world"})).await;
@@ -139,6 +188,19 @@ async fn unknown_route_returns_404() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+#[tokio::test]
+async fn oversized_body_is_rejected_by_configured_limit() {
+    let mut config = test_config();
+    config.limits.max_body_bytes = 16;
+    let (status, _) = post_json_raw(
+        take_home::app(&config),
+        "/encrypt",
+        json!({"name": "a value that is definitely longer than sixteen bytes"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
 // --- Depth-1 encryption tests (from README spec) ---
 
 #[tokio::test]
@@ -269,3 +331,371 @@ async fn encrypt_flat_json_all_values_become_strings() {
     assert!(encrypted["bool_val"].is_string());
     assert!(encrypted["null_val"].is_string());
 }
+
+// --- Injected encryptor ---
+
+#[tokio::test]
+async fn handlers_use_the_injected_encryptor() {
+    let app = app_with(Arc::new(TaggingEncryptor));
+    let (_, encrypted) = post_json(app.clone(), "/encrypt", json!({"name": "Alice"})).await;
+    assert_eq!(encrypted, json!({"name": {"tagged": "Alice"}}));
+
+    let payload = json!({"name": encrypted["name"], "untagged": 1});
+    let (_, decrypted) = post_json(app, "/decrypt", payload).await;
+    assert_eq!(decrypted, json!({"name": "Alice", "untagged": 1}));
+}
+
+#[tokio::test]
+async fn decrypt_unauthenticated_ciphertext_returns_400() {
+    let app = app_with(Arc::new(TaggingEncryptor));
+    let payload = json!({"name": {"tagged": "tampered"}, "untagged": 1});
+    let (status, body) = post_json(app, "/decrypt", payload).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], json!("decryption_failed"));
+}
+
+#[tokio::test]
+async fn field_status_reports_each_property() {
+    let app = app_with(Arc::new(TaggingEncryptor));
+    let payload = json!({
+        "name": {"tagged": "Alice"},
+        "birth_date": "1990-01-01",
+        "ssn": {"tagged": "tampered"}
+    });
+    let (status, body) = post_json(app, "/decrypt?field_status=true", payload).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({
+            "data": {
+                "name": "Alice",
+                "birth_date": "1990-01-01",
+                "ssn": {"tagged": "tampered"}
+            },
+            "fields": {"name": "decrypted", "birth_date": "passthrough", "ssn": "failed"}
+        })
+    );
+}
+
+#[tokio::test]
+async fn field_status_covers_large_payloads() {
+    let mut config = test_config();
+    config.encryption.parallel_min_fields = 2;
+    let app = take_home::router(
+        AppState::from_config(&config).with_encryptor(Arc::new(TaggingEncryptor)),
+        &config,
+    );
+    let payload: serde_json::Map<String, Value> = (0..50)
+        .map(|n| match n % 2 {
+            0 => (format!("f{n}"), json!({"tagged": n})),
+            _ => (format!("f{n}"), json!({"tagged": "tampered"})),
+        })
+        .collect();
+    let (status, body) = post_json(app, "/decrypt?field_status=true", Value::Object(payload)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["f10"], json!(10));
+    assert_eq!(body["fields"]["f10"], json!("decrypted"));
+    assert_eq!(body["fields"]["f11"], json!("failed"));
+    assert_eq!(body["fields"].as_object().unwrap().len(), 50);
+}
+
+#[tokio::test]
+async fn field_status_needs_an_object() {
+    let (status, body) = post_json(app(), "/decrypt?field_status=true", json!("d29ybGQ=")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], json!("validation_failed"));
+}
+
+// ── JSON Pointers ─────────────────────────────────────────────────
+
+#[tokio::test]
+async fn pointers_encrypt_nested_values_in_place() {
+    let document = json!({
+        "contact": {"email": "a@example.com", "name": "Alice"},
+        "payment": {"card": {"number": "4111111111111111", "expiry": "12/30"}}
+    });
+    let pointers = json!(["/contact/email", "/payment/card/number"]);
+    let (status, encrypted) = post_json(
+        app(),
+        "/encrypt/pointers",
+        json!({"data": document, "pointers": pointers}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(encrypted["contact"]["email"], document["contact"]["email"]);
+    assert_eq!(encrypted["contact"]["name"], json!("Alice"));
+    assert_eq!(encrypted["payment"]["card"]["expiry"], json!("12/30"));
+
+    let (status, decrypted) = post_json(
+        app(),
+        "/decrypt/pointers",
+        json!({"data": encrypted, "pointers": pointers}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(decrypted, document);
+}
+
+#[tokio::test]
+async fn unusable_pointers_return_400() {
+    let document = json!({"contact": {"email": "a@example.com"}});
+    for pointers in [
+        json!(["contact"]),
+        json!(["/contact/phone"]),
+        json!(["/contact", "/contact/email"]),
+    ] {
+        let body = json!({"data": document, "pointers": pointers});
+        let (status, body) = post_json(app(), "/encrypt/pointers", body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{pointers}");
+        assert_eq!(body["error"]["code"], json!("validation_failed"));
+    }
+}
+
+// ── patch mode ────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn patch_replaces_only_the_selected_bytes() {
+    let document =
+        "{\"id\": 7,   \"amount\": 1.50,\n \"contact\": {\"email\": \"a@example.com\"}}\n";
+    let (status, body) = post_json(
+        app(),
+        "/encrypt/patch",
+        json!({"document": document, "pointers": ["/contact/email"]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let patched = body["document"].as_str().unwrap();
+    let (before, rest) = patched.split_once("{\"email\": ").unwrap();
+    assert_eq!(before, "{\"id\": 7,   \"amount\": 1.50,\n \"contact\": ");
+    let ciphertext = rest.strip_suffix("}}\n").unwrap();
+
+    // The spliced value is ordinary ciphertext.
+    let (_, decrypted) = post_json(
+        app(),
+        "/decrypt",
+        json!({"email": serde_json::from_str::<Value>(ciphertext).unwrap()}),
+    )
+    .await;
+    assert_eq!(decrypted, json!({"email": "a@example.com"}));
+}
+
+#[tokio::test]
+async fn patch_refuses_unusable_documents() {
+    for document in [r#"{"a": 1"#, r#"{"a": 1, "a": 2}"#, r#"{"b": 1}"#] {
+        let body = json!({"document": document, "pointers": ["/a"]});
+        let (status, body) = post_json(app(), "/encrypt/patch", body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{document}");
+        assert_eq!(body["error"]["code"], json!("validation_failed"));
+    }
+}
+
+// ── _crypto sidecar ───────────────────────────────────────────────
+
+#[tokio::test]
+async fn sidecar_describes_encrypted_values_and_drives_decryption() {
+    let document = json!({"name": "Alice", "a/b": 1});
+    let (status, encrypted) = post_json(app(), "/encrypt?sidecar=true", document.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let entry = json!({"alg": "base64", "kid": "encryption"});
+    assert_eq!(
+        encrypted["_crypto"],
+        json!({"paths": {"/name": entry, "/a~1b": entry}})
+    );
+
+    // The sidecar alone says what to decrypt, and is removed.
+    let (status, decrypted) = post_json(app(), "/decrypt", encrypted).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(decrypted, document);
+}
+
+#[tokio::test]
+async fn sidecar_lists_the_pointers_of_selective_encryption() {
+    let document = json!({"contact": {"email": "a@example.com"}, "note": "IkpvaG4gRG9lIg=="});
+    let body = json!({"data": document, "pointers": ["/contact/email"]});
+    let (_, encrypted) = post_json(app(), "/encrypt/pointers?sidecar=true", body).await;
+    assert_eq!(
+        encrypted["_crypto"]["paths"],
+        json!({"/contact/email": {"alg": "base64", "kid": "encryption"}})
+    );
+
+    // `note` looks like ciphertext, but is not listed in the sidecar.
+    let (_, decrypted) = post_json(app(), "/decrypt", encrypted).await;
+    assert_eq!(decrypted, document);
+}
+
+#[tokio::test]
+async fn unusable_sidecars_return_400() {
+    let foreign = json!({
+        "name": "IkFsaWNlIg==",
+        "_crypto": {"paths": {"/name": {"alg": "aes-256-gcm", "kid": "kms-1"}}}
+    });
+    let malformed = json!({"name": "IkFsaWNlIg==", "_crypto": {"paths": ["/name"]}});
+    for payload in [foreign, malformed] {
+        let (status, body) = post_json(app(), "/decrypt", payload).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], json!("validation_failed"));
+    }
+
+    let reserved = json!({"_crypto": 1});
+    let (status, _) = post_json(app(), "/encrypt?sidecar=true", reserved).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post_json(app(), "/encrypt?sidecar=true", json!("Alice")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+// ── JSON limits ───────────────────────────────────────────────────
+
+fn app_with_json_limits(max_json_fields: usize, max_json_value_bytes: usize) -> Router {
+    let mut config = test_config();
+    config.limits.max_json_fields = max_json_fields;
+    config.limits.max_json_value_bytes = max_json_value_bytes;
+    take_home::app(&config)
+}
+
+#[tokio::test]
+async fn encrypt_refuses_too_many_top_level_fields() {
+    let app = app_with_json_limits(2, 1024);
+    let (status, _) = post_json(
+        app.clone(),
+        "/encrypt",
+        json!({"a": 1, "b": {"c": 2, "d": 3}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = post_json(app, "/encrypt", json!({"a": 1, "b": 2, "c": 3})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["code"], json!("limit_exceeded"));
+    assert_eq!(
+        body["error"]["message"],
+        json!("JSON object exceeds the limit of 2 top-level fields")
+    );
+}
+
+#[tokio::test]
+async fn encrypt_refuses_oversized_values() {
+    let (status, body) = post_json(
+        app_with_json_limits(10, 8),
+        "/encrypt",
+        json!({"name": "short", "bio": "far too long"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["error"]["message"],
+        json!("JSON string at `/bio` exceeds the limit of 8 bytes")
+    );
+}
+
+#[tokio::test]
+async fn decrypt_is_not_held_to_the_value_limit() {
+    let app = app_with_json_limits(10, 8);
+    let (_, encrypted) = post_json(app.clone(), "/encrypt", json!({"name": "12345678"})).await;
+    let (status, body) = post_json(app, "/decrypt", encrypted).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"name": "12345678"}));
+}
+
+// ── parallel fields ───────────────────────────────────────────────
+
+fn parallel_app(encryptor: Arc<dyn AsyncEncryptor>) -> Router {
+    let mut config = test_config();
+    config.encryption.parallel_min_fields = 4;
+    take_home::router(
+        AppState::from_config(&config).with_encryptor(encryptor),
+        &config,
+    )
+}
+
+#[tokio::test]
+async fn large_objects_round_trip_in_key_order() {
+    let payload: serde_json::Map<String, Value> = (0..500)
+        .map(|i| (format!("field-{i:03}"), json!({ "n": i })))
+        .collect();
+    let payload = Value::Object(payload);
+    let app = parallel_app(Arc::new(TaggingEncryptor));
+
+    let (status, body) = post_json_raw(app.clone(), "/encrypt", payload.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let encrypted: Value = serde_json::from_slice(&body).unwrap();
+    let keys: Vec<_> = encrypted.as_object().unwrap().keys().collect();
+    assert_eq!(
+        keys,
+        payload.as_object().unwrap().keys().collect::<Vec<_>>()
+    );
+    assert_eq!(encrypted["field-042"], json!({"tagged": {"n": 42}}));
+
+    let (_, decrypted) = post_json(app, "/decrypt", encrypted).await;
+    assert_eq!(decrypted, payload);
+}
+
+#[tokio::test]
+async fn parallel_decrypt_reports_tampered_fields() {
+    let mut payload: serde_json::Map<String, Value> =
+        (0..10).map(|i| (format!("f{i}"), json!(i))).collect();
+    payload.insert("f7".into(), json!({"tagged": "tampered"}));
+    let app = parallel_app(Arc::new(TaggingEncryptor));
+    let (status, body) = post_json(app, "/decrypt", Value::Object(payload)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], json!("decryption_failed"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn parallel_encrypt_works_on_a_multi_threaded_runtime() {
+    let payload: serde_json::Map<String, Value> =
+        (0..64).map(|i| (format!("f{i:02}"), json!(i))).collect();
+    let (status, encrypted) = post_json(
+        parallel_app(Arc::new(TaggingEncryptor)),
+        "/encrypt",
+        Value::Object(payload),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(encrypted.as_object().unwrap().len(), 64);
+}
+
+// ── legacy format deprecation ─────────────────────────────────────
+
+async fn post_with_headers(app: Router, uri: &str, body: Value) -> (StatusCode, HeaderMap) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    (response.status(), response.headers().clone())
+}
+
+#[tokio::test]
+async fn base64_ciphertext_is_announced_then_refused_after_its_sunset() {
+    let mut config = test_config();
+    config.deprecation.deprecated_at = Some(1_000);
+    config.deprecation.sunset_at = Some(4_102_444_800);
+    let (status, headers) =
+        post_with_headers(take_home::app(&config), "/encrypt", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["deprecation"], "@1000");
+    assert_eq!(headers["sunset"], "Fri, 01 Jan 2100 00:00:00 GMT");
+
+    config.deprecation.sunset_at = Some(2_000);
+    config.deprecation.reject_after_sunset = true;
+    let (status, body) = post_json(take_home::app(&config), "/decrypt", json!({"a": "MQ=="})).await;
+    assert_eq!(status, StatusCode::GONE);
+    assert_eq!(body["error"]["code"], json!("sunset"));
+}
+
+#[tokio::test]
+async fn configured_encryptors_are_not_legacy() {
+    let mut config = test_config();
+    config.deprecation.deprecated_at = Some(1_000);
+    config.deprecation.sunset_at = Some(2_000);
+    config.deprecation.reject_after_sunset = true;
+    let app = take_home::router(
+        AppState::from_config(&config).with_encryptor(Arc::new(TaggingEncryptor)),
+        &config,
+    );
+    let (status, headers) = post_with_headers(app, "/encrypt", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.get("deprecation").is_none());
+}
@@ -0,0 +1,323 @@
+#![cfg(all(feature = "admin", feature = "escrow"))]
+
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode},
+};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use take_home::audit::MemoryAuditSink;
+use take_home::clock::TestClock;
+use take_home::config::{ApiKeyConfig, Config, Secret};
+use take_home::crypto::escrow::{EscrowBundle, EscrowedKey, KeyMaterial, unwrap};
+use take_home::policy::Action;
+use take_home::state::AppState;
+use tower::ServiceExt;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+fn escrow_secret() -> StaticSecret {
+    StaticSecret::from([42u8; 32])
+}
+
+struct Harness {
+    config: Config,
+    audit: Arc<MemoryAuditSink>,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let mut config = Config::default();
+        config.signing.secret = Some(Secret::new("test-secret"));
+        config.signing.key_id = "primary".into();
+        config.escrow.keys.insert(
+            "dr".into(),
+            STANDARD.encode(PublicKey::from(&escrow_secret()).as_bytes()),
+        );
+        Self {
+            config,
+            audit: Arc::new(MemoryAuditSink::new()),
+        }
+    }
+
+    fn app(&self) -> Router {
+        let state = AppState::from_config(&self.config).with_audit_sink(self.audit.clone());
+        take_home::admin_router(state, &self.config)
+    }
+
+    async fn export(&self, body: Value) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/keys/escrow")
+            .header("Content-Type", "application/json")
+            .header("X-Client-Id", "ops")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = self.app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+}
+
+// ── export ────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn exported_key_opens_with_the_escrow_secret() {
+    let harness = Harness::new();
+    let (status, body) = harness.export(json!({"escrow_key": "dr"})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.to_string().contains("test-secret"));
+    assert_eq!(body["alg"], "hmac-sha256");
+    assert_eq!(body["kid"], "primary");
+
+    let bundle: EscrowBundle = serde_json::from_value(body).unwrap();
+    assert_eq!(
+        unwrap(&bundle, &escrow_secret()).unwrap(),
+        EscrowedKey::hmac("hmac-sha256", "primary", b"test-secret")
+    );
+}
+
+#[tokio::test]
+async fn unknown_escrow_key_is_rejected() {
+    let harness = Harness::new();
+    let (status, body) = harness.export(json!({"escrow_key": "offsite"})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        "unknown escrow key `offsite` (available: dr)"
+    );
+}
+
+#[tokio::test]
+async fn unknown_algorithm_is_rejected() {
+    let harness = Harness::new();
+    let (status, _) = harness
+        .export(json!({"escrow_key": "dr", "alg": "rsa-pss-sha512"}))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+// ── audit ─────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn exports_are_audited() {
+    let harness = Harness::new();
+    harness.export(json!({"escrow_key": "dr"})).await;
+    harness.export(json!({"escrow_key": "offsite"})).await;
+
+    let events = harness.audit.events();
+    let summary: Vec<_> = events
+        .iter()
+        .map(|e| {
+            (
+                e.action,
+                e.resource.as_str(),
+                e.client.as_deref(),
+                e.success,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("key.escrow_export", "hmac-sha256 -> dr", Some("ops"), true),
+            (
+                "key.escrow_export",
+                "hmac-sha256 -> offsite",
+                Some("ops"),
+                false
+            ),
+        ]
+    );
+}
+
+// ── key ceremonies ────────────────────────────────────────────────
+
+fn ceremony_config() -> Config {
+    let mut config = Harness::new().config;
+    for (name, scopes) in [
+        ("alice", vec![Action::Admin]),
+        ("bob", vec![Action::Admin]),
+        ("ops", vec![Action::Admin]),
+    ] {
+        config.api_keys.keys.insert(
+            name.into(),
+            ApiKeyConfig {
+                key: Secret::new(format!("{name}-key")),
+                scopes,
+            },
+        );
+    }
+    config.ceremony.custodians = vec!["alice".into(), "bob".into()];
+    config
+}
+
+async fn call(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    api_key: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("X-Api-Key", format!("{api_key}-key"))
+        .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+fn share(byte: u8) -> Value {
+    json!({"share": STANDARD.encode([byte; 32])})
+}
+
+#[tokio::test]
+async fn custodians_create_a_master_key_sealed_to_escrow() {
+    let config = ceremony_config();
+    let audit = Arc::new(MemoryAuditSink::new());
+    let state = AppState::from_config(&config).with_audit_sink(audit.clone());
+    let app = take_home::admin_router(state, &config);
+
+    let (status, started) = call(
+        &app,
+        "POST",
+        "/ceremonies",
+        "ops",
+        Some(json!({"kid": "master-2026", "escrow_key": "dr"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(started["state"], "collecting");
+    assert_eq!(started["started_by"], "ops");
+    assert_eq!(started["custodians"], json!(["alice", "bob"]));
+    let id = started["id"].as_str().unwrap();
+    let shares = format!("/ceremonies/{id}/shares");
+
+    let (status, body) = call(&app, "POST", &shares, "alice", Some(share(1))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["contributed"], json!(["alice"]));
+    let (status, _) = call(&app, "POST", &shares, "alice", Some(share(2))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = call(&app, "POST", &shares, "ops", Some(share(3))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        body["error"]["message"],
+        "`ops` is not a custodian of this ceremony"
+    );
+    let (status, _) = call(
+        &app,
+        "GET",
+        &format!("/ceremonies/{id}/bundle"),
+        "ops",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = call(&app, "POST", &shares, "bob", Some(share(4))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["state"], "complete");
+    assert_eq!(body["contributed"], json!(["alice", "bob"]));
+
+    let (status, bundle) = call(
+        &app,
+        "GET",
+        &format!("/ceremonies/{id}/bundle"),
+        "ops",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let bundle: EscrowBundle = serde_json::from_value(bundle).unwrap();
+    let key = unwrap(&bundle, &escrow_secret()).unwrap();
+    assert_eq!(
+        (key.alg.as_str(), key.kid.as_str()),
+        ("hmac-sha256", "master-2026")
+    );
+    let KeyMaterial::Jwk(jwk) = key.material else {
+        panic!("HMAC keys are escrowed as JWKs");
+    };
+    let secret = jwk.symmetric_key().unwrap();
+    assert_eq!(
+        body["fingerprint"],
+        take_home::ceremony::fingerprint(&secret)
+    );
+
+    let summary: Vec<_> = audit
+        .events()
+        .iter()
+        .map(|e| (e.action, e.success))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("ceremony.start", true),
+            ("ceremony.share", true),
+            ("ceremony.share", false),
+            ("ceremony.share", false),
+            ("ceremony.bundle", false),
+            ("ceremony.share", true),
+            ("ceremony.complete", true),
+            ("ceremony.bundle", true),
+        ]
+    );
+    assert_eq!(audit.events()[5].resource, format!("{id} by bob"));
+}
+
+#[tokio::test]
+async fn unfinished_ceremonies_expire_or_are_aborted() {
+    let config = ceremony_config();
+    let clock = Arc::new(TestClock::new(1_000));
+    let state = AppState::from_config_with_clock(&config, clock.clone());
+    let app = take_home::admin_router(state, &config);
+    let start = json!({"kid": "master-2026", "escrow_key": "dr"});
+
+    let (_, started) = call(&app, "POST", "/ceremonies", "ops", Some(start.clone())).await;
+    let id = started["id"].as_str().unwrap();
+    call(
+        &app,
+        "POST",
+        &format!("/ceremonies/{id}/shares"),
+        "alice",
+        Some(share(1)),
+    )
+    .await;
+    clock.advance(3_600);
+    let (_, body) = call(&app, "GET", &format!("/ceremonies/{id}"), "bob", None).await;
+    assert_eq!(body["state"], "expired");
+    assert_eq!(body["ended_at"], 4_600);
+    let (status, body) = call(
+        &app,
+        "POST",
+        &format!("/ceremonies/{id}/shares"),
+        "bob",
+        Some(share(2)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        "the ceremony is expired, not collecting shares"
+    );
+
+    let (_, started) = call(&app, "POST", "/ceremonies", "ops", Some(start)).await;
+    let id = started["id"].as_str().unwrap();
+    let (status, body) = call(&app, "DELETE", &format!("/ceremonies/{id}"), "ops", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["state"], "aborted");
+    let (status, _) = call(&app, "GET", "/ceremonies/unknown", "ops", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
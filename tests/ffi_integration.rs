@@ -0,0 +1,15 @@
+#![cfg(feature = "ffi")]
+
+const GENERATED: &str = concat!(env!("OUT_DIR"), "/take_home.h");
+
+/// The committed header must be what `build.rs` generates from `src/ffi.rs`;
+/// after changing the C ABI, copy the generated one over it.
+#[test]
+fn committed_header_matches_the_generated_one() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/take_home.h"));
+    let committed = include_str!("../include/take_home.h");
+    assert_eq!(
+        committed, generated,
+        "include/take_home.h is out of date: copy {GENERATED} over it"
+    );
+}
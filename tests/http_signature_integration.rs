@@ -0,0 +1,391 @@
+#![cfg(all(feature = "server", feature = "signing"))]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    Extension, Router,
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+};
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use take_home::config::{Config, Secret};
+use take_home::crypto::challenge::respond;
+use take_home::crypto::hmac::HMacSigner;
+use take_home::crypto::http_signature::{
+    HttpRequest, Keyring, SignatureParams, VerifiedSignature, content_digest, sign,
+};
+use take_home::layers::VerifyHttpSignatureLayer;
+use take_home::replay::MemoryReplayStore;
+use tower::ServiceExt;
+
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    config
+        .http_signatures
+        .keys
+        .insert("partner".into(), Secret::new("partner-secret"));
+    config
+}
+
+fn app() -> Router {
+    take_home::app(&test_config())
+}
+
+async fn post_json(app: Router, uri: &str, body: Value) -> (StatusCode, Option<Value>) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value = serde_json::from_slice(&bytes).ok();
+    (status, value)
+}
+
+fn order_request() -> Value {
+    json!({
+        "method": "POST",
+        "target_uri": "https://example.com/orders?id=1",
+        "headers": {"date": "Tue, 20 Apr 2021 02:07:55 GMT"},
+        "components": ["@method", "@target-uri", "date"],
+    })
+}
+
+async fn signed_headers(request: Value) -> Value {
+    let (status, body) = post_json(app(), "/http-signatures/sign", request).await;
+    assert_eq!(status, StatusCode::OK);
+    body.unwrap()
+}
+
+fn verify_request(signed: &Value) -> Value {
+    json!({
+        "method": "POST",
+        "target_uri": "https://example.com/orders?id=1",
+        "headers": {
+            "date": "Tue, 20 Apr 2021 02:07:55 GMT",
+            "signature-input": signed["signature_input"],
+            "signature": signed["signature"],
+        },
+    })
+}
+
+// ── /http-signatures endpoints ─────────────────────────────────────
+
+#[tokio::test]
+async fn sign_returns_signature_headers_with_service_keyid() {
+    let signed = signed_headers(order_request()).await;
+    let input = signed["signature_input"].as_str().unwrap();
+    assert!(input.starts_with(r#"sig1=("@method" "@target-uri" "date");created="#));
+    assert!(input.ends_with(r#";keyid="default""#));
+    assert!(signed["signature"].as_str().unwrap().starts_with("sig1=:"));
+    assert!(signed.get("content_digest").is_none());
+}
+
+#[tokio::test]
+async fn signed_request_verifies() {
+    let signed = signed_headers(order_request()).await;
+    let (status, body) = post_json(app(), "/http-signatures/verify", verify_request(&signed)).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    assert_eq!(body["label"], "sig1");
+    assert_eq!(body["keyid"], "default");
+    assert_eq!(
+        body["components"],
+        json!(["@method", "@target-uri", "date"])
+    );
+}
+
+#[tokio::test]
+async fn tampered_component_returns_400() {
+    let signed = signed_headers(order_request()).await;
+    let mut request = verify_request(&signed);
+    request["method"] = json!("DELETE");
+
+    let (status, body) = post_json(app(), "/http-signatures/verify", request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "invalid_signature");
+}
+
+#[tokio::test]
+async fn partner_key_is_selected_by_keyid() {
+    let mut request = order_request();
+    request["keyid"] = json!("partner");
+    request["label"] = json!("partner-sig");
+    let signed = signed_headers(request).await;
+    assert!(
+        signed["signature_input"]
+            .as_str()
+            .unwrap()
+            .contains(r#"keyid="partner""#)
+    );
+
+    let mut request = verify_request(&signed);
+    request["label"] = json!("partner-sig");
+    let (status, body) = post_json(app(), "/http-signatures/verify", request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.unwrap()["keyid"], "partner");
+}
+
+#[tokio::test]
+async fn unknown_keyid_returns_400() {
+    let mut request = order_request();
+    request["keyid"] = json!("nobody");
+    let (status, body) = post_json(app(), "/http-signatures/sign", request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "validation_failed");
+}
+
+#[tokio::test]
+async fn expired_signature_returns_400() {
+    let mut request = order_request();
+    request["created"] = json!(1);
+    request["expires"] = json!(2);
+    let signed = signed_headers(request).await;
+
+    let (status, body) = post_json(app(), "/http-signatures/verify", verify_request(&signed)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let error = &body.unwrap()["error"];
+    assert_eq!(error["code"], "expired");
+    assert_eq!(error["message"], "signature expired at 2 (+0s clock skew)");
+}
+
+#[tokio::test]
+async fn body_is_covered_through_content_digest() {
+    let mut request = order_request();
+    request["body"] = json!(r#"{"hello": "world"}"#);
+    let signed = signed_headers(request).await;
+    assert_eq!(
+        signed["content_digest"],
+        "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"
+    );
+    assert!(
+        signed["signature_input"]
+            .as_str()
+            .unwrap()
+            .contains(r#""date" "content-digest")"#)
+    );
+
+    let mut request = verify_request(&signed);
+    request["headers"]["content-digest"] = signed["content_digest"].clone();
+    request["body"] = json!(r#"{"hello": "world"}"#);
+    let (status, _) = post_json(app(), "/http-signatures/verify", request.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    request["body"] = json!(r#"{"hello": "there"}"#);
+    let (status, body) = post_json(app(), "/http-signatures/verify", request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "invalid_signature");
+}
+
+#[tokio::test]
+async fn missing_signature_headers_return_400() {
+    let request = json!({
+        "method": "GET",
+        "target_uri": "https://example.com/",
+        "headers": {},
+    });
+    let (status, body) = post_json(app(), "/http-signatures/verify", request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "validation_failed");
+}
+
+// ── /challenge ─────────────────────────────────────────────────────
+
+async fn issue_challenge(app: Router, keyid: &str) -> String {
+    let (status, body) = post_json(app, "/challenge", json!({"keyid": keyid})).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    assert_eq!(body["keyid"], keyid);
+    body["challenge"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn key_holders_answer_challenges_once() {
+    let app = app();
+    let challenge = issue_challenge(app.clone(), "partner").await;
+    let partner = HMacSigner::new(b"partner-secret".to_vec());
+    let answer = json!({
+        "challenge": challenge,
+        "signature": respond(&partner, &challenge),
+    });
+
+    let (status, body) = post_json(app.clone(), "/challenge/respond", answer.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.unwrap()["keyid"], "partner");
+
+    let (status, body) = post_json(app, "/challenge/respond", answer).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "invalid_signature");
+}
+
+#[tokio::test]
+async fn challenges_reject_other_keys_and_forgeries() {
+    let app = app();
+    let challenge = issue_challenge(app.clone(), "partner").await;
+    let impostor = HMacSigner::new(b"test-secret".to_vec());
+    let answer = json!({
+        "challenge": challenge,
+        "signature": respond(&impostor, &challenge),
+    });
+    let (status, _) = post_json(app.clone(), "/challenge/respond", answer).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Re-targeting the challenge at another key breaks the service's signature.
+    let (_, signature) = challenge.split_once('.').unwrap();
+    let other = issue_challenge(app.clone(), "default").await;
+    let (payload, _) = other.split_once('.').unwrap();
+    let forged = format!("{payload}.{signature}");
+    let answer = json!({"challenge": forged, "signature": respond(&impostor, &forged)});
+    let (status, body) = post_json(app.clone(), "/challenge/respond", answer).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "invalid_signature");
+
+    let (status, body) = post_json(app, "/challenge", json!({"keyid": "ghost"})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "validation_failed");
+}
+
+// ── VerifyHttpSignatureLayer ───────────────────────────────────────
+
+fn keyring() -> Arc<Keyring> {
+    Arc::new(Keyring::new().with_key("k1", HMacSigner::new(b"layer-secret".to_vec())))
+}
+
+fn echo() -> Router {
+    Router::new().route(
+        "/echo",
+        post(
+            |Extension(verified): Extension<VerifiedSignature>, body: String| async move {
+                format!("{}:{body}", verified.params.keyid.unwrap_or_default())
+            },
+        ),
+    )
+}
+
+fn signed_request(body: &'static str, components: &[&str]) -> Request<Body> {
+    signed_request_with_nonce(body, components, None)
+}
+
+fn signed_request_with_nonce(
+    body: &'static str,
+    components: &[&str],
+    nonce: Option<&str>,
+) -> Request<Body> {
+    let digest = content_digest(body.as_bytes());
+    let headers = vec![
+        ("host".to_string(), "example.com".to_string()),
+        ("content-digest".to_string(), digest.clone()),
+    ];
+    let params = SignatureParams {
+        components: components.iter().map(|c| c.to_string()).collect(),
+        created: Some(1_618_884_473),
+        keyid: Some("k1".into()),
+        nonce: nonce.map(str::to_string),
+        ..SignatureParams::default()
+    };
+    let message = HttpRequest {
+        method: "POST",
+        target_uri: "http://example.com/echo",
+        headers: &headers,
+    };
+    let key = HMacSigner::new(b"layer-secret".to_vec());
+    let signed = sign(&key, &message, "sig1", &params).unwrap();
+    Request::builder()
+        .method("POST")
+        .uri("/echo")
+        .header("Host", "example.com")
+        .header("Content-Digest", digest)
+        .header("Signature-Input", signed.signature_input)
+        .header("Signature", signed.signature)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn layer_accepts_signed_request_and_exposes_keyid() {
+    let app = echo().layer(VerifyHttpSignatureLayer::new(keyring()));
+    let request = signed_request("hello", &["@method", "@path", "content-digest"]);
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&body[..], b"k1:hello");
+}
+
+#[tokio::test]
+async fn layer_rejects_unsigned_request() {
+    let app = echo().layer(VerifyHttpSignatureLayer::new(keyring()));
+    let request = Request::builder()
+        .method("POST")
+        .uri("/echo")
+        .header("Host", "example.com")
+        .body(Body::from("hello"))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn layer_rejects_body_not_matching_digest() {
+    let app = echo().layer(VerifyHttpSignatureLayer::new(keyring()));
+    let (parts, _) = signed_request("hello", &["@method", "@path", "content-digest"]).into_parts();
+    let request = Request::from_parts(parts, Body::from("hell0"));
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn layer_enforces_required_components() {
+    let layer = VerifyHttpSignatureLayer::new(keyring()).required_components(["content-digest"]);
+    let response = echo()
+        .layer(layer.clone())
+        .oneshot(signed_request("hello", &["@method", "@path"]))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = echo()
+        .layer(layer)
+        .oneshot(signed_request("hello", &["@method", "content-digest"]))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn layer_rebuilds_target_uri_with_configured_scheme() {
+    let app = echo().layer(VerifyHttpSignatureLayer::new(keyring()).scheme("https"));
+    let request = signed_request("hello", &["@target-uri"]);
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn layer_rejects_replayed_nonces() {
+    let layer = VerifyHttpSignatureLayer::new(keyring())
+        .replay_store(Arc::new(MemoryReplayStore::new()), Duration::from_secs(300));
+    let app = echo().layer(layer);
+    let send = |nonce| {
+        let app = app.clone();
+        async move {
+            let request = signed_request_with_nonce("hello", &["@method"], nonce);
+            app.oneshot(request).await.unwrap().status()
+        }
+    };
+    assert_eq!(send(Some("n-1")).await, StatusCode::OK);
+    assert_eq!(send(Some("n-1")).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(send(Some("n-2")).await, StatusCode::OK);
+    // Without a nonce there is nothing to detect a replay with.
+    assert_eq!(send(None).await, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,142 @@
+#![cfg(all(feature = "server", feature = "signing"))]
+
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{HeaderName, HeaderValue, Request, StatusCode},
+    routing::post,
+};
+use http_body_util::BodyExt;
+use take_home::crypto::hmac::HMacSigner;
+use take_home::crypto::signer::Signer;
+use take_home::layers::{SignResponseLayer, VerifySignatureLayer, response_signing_input};
+use tower::ServiceExt;
+
+fn signer() -> Arc<HMacSigner> {
+    Arc::new(HMacSigner::new(b"layer-secret".to_vec()))
+}
+
+fn echo() -> Router {
+    Router::new().route("/echo", post(|body: String| async move { body }))
+}
+
+fn request(body: &'static str, signature: Option<&str>) -> Request<Body> {
+    let mut builder = Request::builder().method("POST").uri("/echo");
+    if let Some(signature) = signature {
+        builder = builder.header("X-Signature", signature);
+    }
+    builder.body(Body::from(body)).unwrap()
+}
+
+// ── VerifySignatureLayer ───────────────────────────────────────────
+
+#[tokio::test]
+async fn valid_signature_reaches_handler_with_intact_body() {
+    let app = echo().layer(VerifySignatureLayer::new(signer()));
+    let signature = signer().sign_bytes(b"hello");
+
+    let response = app
+        .oneshot(request("hello", Some(&signature)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&body[..], b"hello");
+}
+
+#[tokio::test]
+async fn missing_signature_returns_401() {
+    let app = echo().layer(VerifySignatureLayer::new(signer()));
+    let response = app.oneshot(request("hello", None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn tampered_body_returns_401() {
+    let app = echo().layer(VerifySignatureLayer::new(signer()));
+    let signature = signer().sign_bytes(b"hello");
+    let response = app
+        .oneshot(request("hell0", Some(&signature)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn oversized_body_returns_413() {
+    let app = echo().layer(VerifySignatureLayer::new(signer()).max_body_bytes(4));
+    let signature = signer().sign_bytes(b"hello");
+    let response = app
+        .oneshot(request("hello", Some(&signature)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn custom_header_name_is_used() {
+    let header = HeaderName::from_static("x-body-mac");
+    let app = echo().layer(VerifySignatureLayer::new(signer()).header(header));
+    let signature = signer().sign_bytes(b"hello");
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/echo")
+        .header("X-Body-Mac", signature)
+        .body(Body::from("hello"))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+// ── SignResponseLayer ──────────────────────────────────────────────
+
+#[tokio::test]
+async fn response_carries_signature_of_its_body() {
+    let app = echo().layer(SignResponseLayer::new(signer()));
+    let response = app.oneshot(request("payload", None)).await.unwrap();
+
+    let signature = response.headers()["x-signature"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&body[..], b"payload");
+    assert!(signer().verify_bytes(&response_signing_input(&body), &signature));
+    assert!(!signer().verify_bytes(&body, &signature));
+}
+
+#[tokio::test]
+async fn response_carries_key_id_when_configured() {
+    let layer = SignResponseLayer::new(signer()).key_id(HeaderValue::from_static("k1"));
+    let response = echo()
+        .layer(layer)
+        .oneshot(request("payload", None))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-signature-key-id"], "k1");
+
+    let response = echo()
+        .layer(SignResponseLayer::new(signer()))
+        .oneshot(request("payload", None))
+        .await
+        .unwrap();
+    assert!(response.headers().get("x-signature-key-id").is_none());
+}
+
+#[tokio::test]
+async fn layers_compose_for_signed_round_trip() {
+    let app = echo()
+        .layer(VerifySignatureLayer::new(signer()))
+        .layer(SignResponseLayer::new(signer()));
+    let signature = signer().sign_bytes(b"ping");
+
+    let response = app
+        .oneshot(request("ping", Some(&signature)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key("x-signature"));
+}
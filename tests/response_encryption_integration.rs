@@ -0,0 +1,153 @@
+#![cfg(feature = "response-encryption")]
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use http_body_util::BodyExt;
+use rand_core::OsRng;
+use serde_json::{Value, json};
+use take_home::crypto::seal::{SEAL_ALGORITHM, Sealed, open};
+use take_home::layers::EncryptResponseLayer;
+use tower::ServiceExt;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+fn keypair() -> (StaticSecret, PublicKey) {
+    let secret = StaticSecret::random_from_rng(OsRng);
+    let public = PublicKey::from(&secret);
+    (secret, public)
+}
+
+fn echo() -> Router {
+    Router::new().route(
+        "/echo",
+        post(|body: String| async move { (StatusCode::CREATED, body) }),
+    )
+}
+
+fn request(headers: &[(&str, &str)]) -> Request<Body> {
+    let mut builder = Request::builder().method("POST").uri("/echo");
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    builder.body(Body::from(r#"{"ssn":"123"}"#)).unwrap()
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, Option<String>, Vec<u8>) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let algorithm = response
+        .headers()
+        .get("x-content-encryption")
+        .map(|v| v.to_str().unwrap().to_string());
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, algorithm, body.to_vec())
+}
+
+fn unseal(secret: &StaticSecret, body: &[u8]) -> Vec<u8> {
+    let sealed: Sealed = serde_json::from_slice(body).unwrap();
+    open(secret, &sealed).unwrap()
+}
+
+// ── EncryptResponseLayer ───────────────────────────────────────────
+
+#[tokio::test]
+async fn request_without_key_passes_through() {
+    let app = echo().layer(EncryptResponseLayer::new());
+    let (status, algorithm, body) = send(app, request(&[])).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(algorithm, None);
+    assert_eq!(body, br#"{"ssn":"123"}"#);
+}
+
+#[tokio::test]
+async fn response_is_sealed_to_header_key() {
+    let (secret, public) = keypair();
+    let key = STANDARD.encode(public.as_bytes());
+    let app = echo().layer(EncryptResponseLayer::new());
+    let (status, algorithm, body) = send(app, request(&[("X-Response-Key", &key)])).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(algorithm.as_deref(), Some(SEAL_ALGORITHM));
+    assert_eq!(unseal(&secret, &body), br#"{"ssn":"123"}"#);
+}
+
+#[tokio::test]
+async fn large_responses_are_sealed_on_the_blocking_pool() {
+    use take_home::blocking::BlockingPool;
+
+    let (secret, public) = keypair();
+    let key = STANDARD.encode(public.as_bytes());
+    let pool = BlockingPool::new(1);
+    let app = echo().layer(EncryptResponseLayer::new().blocking_pool(pool.clone(), 8));
+    let (status, _, body) = send(app, request(&[("X-Response-Key", &key)])).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(unseal(&secret, &body), br#"{"ssn":"123"}"#);
+    assert_eq!(pool.stats().completed, 1);
+
+    let app = echo().layer(EncryptResponseLayer::new().blocking_pool(pool.clone(), 1024));
+    send(app, request(&[("X-Response-Key", &key)])).await;
+    assert_eq!(pool.stats().completed, 1);
+}
+
+#[tokio::test]
+async fn response_is_sealed_to_registered_client_key() {
+    let (secret, public) = keypair();
+    let app = echo().layer(EncryptResponseLayer::new().client_key("billing", public));
+    let (_, _, body) = send(app, request(&[("X-Client-Id", "billing")])).await;
+    assert_eq!(unseal(&secret, &body), br#"{"ssn":"123"}"#);
+}
+
+#[tokio::test]
+async fn unknown_client_returns_400() {
+    let app = echo().layer(EncryptResponseLayer::new());
+    let (status, _, _) = send(app, request(&[("X-Client-Id", "nobody")])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn malformed_key_returns_400() {
+    let app = echo().layer(EncryptResponseLayer::new());
+    let (status, _, _) = send(app, request(&[("X-Response-Key", "short")])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn required_rejects_requests_without_key() {
+    let app = echo().layer(EncryptResponseLayer::new().required(true));
+    let (status, _, body) = send(app, request(&[])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"]["code"], json!("validation_failed"));
+}
+
+// ── configuration ──────────────────────────────────────────────────
+
+#[cfg(feature = "encryption")]
+#[tokio::test]
+async fn configured_router_seals_decrypt_responses() {
+    use take_home::config::{Config, Secret};
+
+    let (secret, public) = keypair();
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    config.response_encryption.enabled = true;
+    config
+        .response_encryption
+        .clients
+        .insert("billing".into(), STANDARD.encode(public.as_bytes()));
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/decrypt")
+        .header("Content-Type", "application/json")
+        .header("X-Client-Id", "billing")
+        .body(Body::from(json!({"name": "IkpvaG4gRG9lIg=="}).to_string()))
+        .unwrap();
+    let (status, _, body) = send(take_home::app(&config), request).await;
+    assert_eq!(status, StatusCode::OK);
+    let plaintext: Value = serde_json::from_slice(&unseal(&secret, &body)).unwrap();
+    assert_eq!(plaintext, json!({"name": "John Doe"}));
+}
@@ -0,0 +1,100 @@
+#![cfg(all(feature = "server", feature = "json-schema"))]
+
+use std::path::Path;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use take_home::config::{Config, Secret};
+use tower::ServiceExt;
+
+fn app() -> Router {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    config.signing.schemas.insert(
+        "payment-v2".into(),
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/schemas/payment-v2.json"),
+    );
+    take_home::app(&config)
+}
+
+async fn post(uri: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+// ── /sign ─────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn matching_payload_is_signed_as_without_a_schema() {
+    let payload = json!({"amount": 100, "currency": "EUR"});
+    let (status, with_schema) = post("/sign?schema=payment-v2", payload.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, without) = post("/sign", payload).await;
+    assert_eq!(with_schema, without);
+}
+
+#[tokio::test]
+async fn mismatching_payload_is_not_signed() {
+    let (status, body) = post(
+        "/sign?schema=payment-v2",
+        json!({"amount": 100, "currency": "GBP"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], json!("validation_failed"));
+    let message = body["error"]["message"].as_str().unwrap();
+    assert!(
+        message.starts_with("payload does not match schema `payment-v2`: `/currency`: "),
+        "{message}"
+    );
+}
+
+#[tokio::test]
+async fn unknown_schema_is_rejected() {
+    let (status, body) = post("/sign?schema=payment-v1", json!({"amount": 1})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        json!("unknown schema `payment-v1` (available: payment-v2)")
+    );
+}
+
+// ── /verify ───────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn verify_checks_the_schema_before_the_signature() {
+    let data = json!({"amount": 100, "currency": "EUR"});
+    let (_, signed) = post("/sign", data.clone()).await;
+    let (status, _) = post(
+        "/verify?schema=payment-v2",
+        json!({"signature": signed["signature"], "data": data}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let data = json!({"amount": -5, "currency": "EUR"});
+    let (_, signed) = post("/sign", data.clone()).await;
+    let (status, body) = post(
+        "/verify?schema=payment-v2",
+        json!({"signature": signed["signature"], "data": data}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], json!("validation_failed"));
+}
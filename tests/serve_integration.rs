@@ -0,0 +1,95 @@
+#![cfg(all(feature = "server", feature = "signing"))]
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode, header};
+use http_body_util::BodyExt;
+use hyper::client::conn::{http1, http2};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use serde_json::{Value, json};
+use take_home::config::{Config, Secret};
+use tokio::net::TcpStream;
+
+async fn spawn(configure: impl FnOnce(&mut Config)) -> String {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    configure(&mut config);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let app = take_home::app(&config);
+    tokio::spawn(async move {
+        take_home::serve::serve(listener, app, &config.server)
+            .await
+            .unwrap()
+    });
+    addr
+}
+
+fn sign_request(n: u64) -> Request<Body> {
+    Request::post("/sign")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"n": n}).to_string()))
+        .unwrap()
+}
+
+async fn into_json(response: hyper::Response<hyper::body::Incoming>) -> Value {
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn h2c_multiplexes_requests_over_one_connection() {
+    let addr = spawn(|_| {}).await;
+    let stream = TcpStream::connect(&addr).await.unwrap();
+    let (sender, connection) = http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+
+    let requests = (0..32).map(|n| {
+        let mut sender = sender.clone();
+        async move {
+            let response = sender.send_request(sign_request(n)).await.unwrap();
+            assert_eq!(response.version(), hyper::Version::HTTP_2);
+            into_json(response).await
+        }
+    });
+    let signatures = join_all(requests).await;
+    assert!(signatures.iter().all(|body| body["signature"].is_string()));
+}
+
+#[tokio::test]
+async fn http1_connections_are_kept_alive() {
+    let addr = spawn(|_| {}).await;
+    let stream = TcpStream::connect(&addr).await.unwrap();
+    let (mut sender, connection) = http1::handshake(TokioIo::new(stream)).await.unwrap();
+    tokio::spawn(connection);
+
+    for n in 0..3 {
+        sender.ready().await.unwrap();
+        let response = sender.send_request(sign_request(n)).await.unwrap();
+        into_json(response).await;
+    }
+}
+
+#[tokio::test]
+async fn h2c_can_be_disabled() {
+    let addr = spawn(|config| config.server.http2.enabled = false).await;
+    let stream = TcpStream::connect(&addr).await.unwrap();
+    let (mut sender, connection) = http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+    assert!(sender.send_request(sign_request(0)).await.is_err());
+}
+
+async fn join_all<F: Future<Output = Value> + Send + 'static>(
+    futures: impl Iterator<Item = F>,
+) -> Vec<Value> {
+    let handles: Vec<_> = futures.map(tokio::spawn).collect();
+    let mut values = Vec::with_capacity(handles.len());
+    for handle in handles {
+        values.push(handle.await.unwrap());
+    }
+    values
+}
@@ -1,17 +1,70 @@
+#![cfg(all(feature = "server", feature = "signing"))]
+
 use axum::{
     Router,
     body::Body,
-    http::{Request, StatusCode},
-    routing::post,
+    http::{HeaderMap, Request, StatusCode},
 };
 use http_body_util::BodyExt;
 use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+use take_home::anomaly::{AnomalyDetector, MemoryAlertSink, Thresholds};
+use take_home::clock::TestClock;
+use take_home::config::{Cli, Config, FloatPolicy, Secret, SigningAlgorithm};
+use take_home::crypto::BoxFuture;
+use take_home::crypto::compact::CompactSignature;
+use take_home::crypto::hmac::HMacSigner;
+use take_home::crypto::signer::{AsyncSigner, SignError, Signer};
+use take_home::policy::{AccessRequest, Action, Effect, PolicyBackend, PolicyError, PolicyRule};
+use take_home::reload::ReloadError;
+use take_home::state::AppState;
 use tower::ServiceExt;
 
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    config
+}
+
 fn app() -> Router {
-    Router::new()
-        .route("/sign", post(take_home::handlers::signing::sign))
-        .route("/verify", post(take_home::handlers::signing::verify))
+    take_home::app(&test_config())
+}
+
+fn app_with(signer: Arc<dyn AsyncSigner>) -> Router {
+    let config = test_config();
+    take_home::router(AppState::from_config(&config).with_signer(signer), &config)
+}
+
+/// Mock signer that signs everything with the same value, to check that the
+/// handlers delegate to whatever `Signer` the state carries.
+struct FixedSigner;
+
+impl Signer for FixedSigner {
+    fn sign_bytes(&self, _bytes: &[u8]) -> String {
+        "fixed".to_string()
+    }
+
+    fn verify_bytes(&self, _bytes: &[u8], signature: &str) -> bool {
+        signature == "fixed"
+    }
+}
+
+/// Remote backend that never answers in time.
+struct UnreachableSigner;
+
+impl AsyncSigner for UnreachableSigner {
+    fn sign_bytes<'a>(&'a self, _bytes: &'a [u8]) -> BoxFuture<'a, Result<String, SignError>> {
+        Box::pin(async { Err(SignError::Timeout) })
+    }
+
+    fn verify_bytes<'a>(
+        &'a self,
+        _bytes: &'a [u8],
+        _signature: &'a str,
+    ) -> BoxFuture<'a, Result<bool, SignError>> {
+        Box::pin(async { Err(SignError::Backend("connection refused".into())) })
+    }
 }
 
 async fn post_json(app: Router, uri: &str, body: Value) -> (StatusCode, Option<Value>) {
@@ -166,7 +219,7 @@ async fn verify_tampered_payload_returns_400() {
         .unwrap()
         .to_string();
 
-    let (status, _) = post_json(
+    let (status, body) = post_json(
         app(),
         "/verify",
         json!({
@@ -176,6 +229,7 @@ async fn verify_tampered_payload_returns_400() {
     )
     .await;
     assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], json!("invalid_signature"));
 }
 
 #[tokio::test]
@@ -202,8 +256,9 @@ async fn verify_tampered_signature_returns_400() {
 
 #[tokio::test]
 async fn verify_missing_signature_returns_400() {
-    let (status, _) = post_json(app(), "/verify", json!({"data": {"message": "Hello"}})).await;
+    let (status, body) = post_json(app(), "/verify", json!({"data": {"message": "Hello"}})).await;
     assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], json!("validation_failed"));
 }
 
 #[tokio::test]
@@ -224,6 +279,134 @@ async fn verify_non_object_data_returns_400() {
     assert_eq!(status, StatusCode::BAD_REQUEST);
 }
 
+#[tokio::test]
+async fn verify_unknown_property_returns_400() {
+    let payload = json!({"message": "Hello World"});
+    let (_, sign_body) = post_json(app(), "/sign", payload.clone()).await;
+    let signature = sign_body.unwrap()["signature"].clone();
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/verify")
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            json!({"signature": signature, "data": payload, "dtaa": {}}).to_string(),
+        ))
+        .unwrap();
+    let response = app().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8_lossy(&body).contains("unknown field `dtaa`"));
+}
+
+// ── /verify/batch endpoint ────────────────────────────────────────
+
+async fn signature_of(data: Value) -> String {
+    let (_, body) = post_json(app(), "/sign", data).await;
+    body.unwrap()["signature"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn verify_batch_returns_a_verdict_per_pair_in_order() {
+    let good = signature_of(json!({"n": 1})).await;
+    let items: Vec<Value> = (0..100)
+        .map(|n| match n % 3 {
+            0 => json!({"signature": good, "data": {"n": 1}}),
+            1 => json!({"signature": good, "data": {"n": 2}}),
+            _ => json!({"signature": "v1.rot13.abc", "data": {"n": 1}}),
+        })
+        .collect();
+    let (status, body) = post_json(app(), "/verify/batch", json!({"items": items})).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    assert_eq!(
+        (body["valid"].clone(), body["invalid"].clone()),
+        (json!(34), json!(66))
+    );
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 100);
+    assert_eq!(results[0], json!({"valid": true}));
+    assert_eq!(results[1]["error"]["code"], json!("invalid_signature"));
+    assert_eq!(results[2]["error"]["code"], json!("validation_failed"));
+    assert_eq!(results[99], json!({"valid": true}));
+}
+
+#[tokio::test]
+async fn verify_batch_reports_backend_failures_per_pair() {
+    let items = json!({"items": [{"signature": "x", "data": {}}]});
+    let (status, body) = post_json(
+        app_with(Arc::new(UnreachableSigner)),
+        "/verify/batch",
+        items,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let result = &body.unwrap()["results"][0];
+    assert_eq!(result["valid"], json!(false));
+    assert_eq!(result["error"]["code"], json!("crypto_failure"));
+    assert_eq!(result["error"]["message"], json!("crypto operation failed"));
+}
+
+#[tokio::test]
+async fn verify_batch_is_bounded() {
+    let mut config = test_config();
+    config.signing.batch.max_items = 2;
+    let app = take_home::app(&config);
+    let item = json!({"signature": "x", "data": {}});
+    let (status, body) =
+        post_json(app, "/verify/batch", json!({"items": [item, item, item]})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body.unwrap()["error"]["code"], json!("limit_exceeded"));
+
+    let (status, _) = post_json(self::app(), "/verify/batch", json!({"pairs": []})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+// ── anomaly detection ─────────────────────────────────────────────
+
+#[tokio::test]
+async fn repeated_forged_signatures_raise_an_alert() {
+    let alerts = Arc::new(MemoryAlertSink::new());
+    let detector = AnomalyDetector::new(Thresholds {
+        window: Duration::from_secs(300),
+        min_failures: 3,
+        min_failure_ratio: 0.5,
+        cooldown: Duration::from_secs(900),
+    })
+    .with_sink(alerts.clone());
+    let config = test_config();
+    let app = take_home::router(
+        AppState::from_config(&config).with_anomaly_detector(Arc::new(detector)),
+        &config,
+    );
+    let verify = |signature: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/verify")
+            .header("Content-Type", "application/json")
+            .header("X-Client-Id", "mallory")
+            .body(Body::from(
+                json!({"signature": signature, "data": {"amount": 100}}).to_string(),
+            ))
+            .unwrap()
+    };
+
+    let signature = signature_of(json!({"amount": 100})).await;
+    let response = app.clone().oneshot(verify(&signature)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    for _ in 0..2 {
+        let response = app.clone().oneshot(verify("forged")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    assert!(alerts.alerts().is_empty());
+
+    app.clone().oneshot(verify("forged")).await.unwrap();
+    let raised = alerts.alerts();
+    assert_eq!(raised.len(), 1);
+    assert_eq!(raised[0].client.as_deref(), Some("mallory"));
+    assert_eq!((raised[0].failures, raised[0].attempts), (3, 4));
+}
+
 // ── sign → verify round-trip ───────────────────────────────────────
 
 #[tokio::test]
@@ -272,54 +455,1240 @@ async fn sign_then_verify_roundtrip_with_nested_values() {
     assert_eq!(status, StatusCode::NO_CONTENT);
 }
 
-// ── HTTP-level edge cases ──────────────────────────────────────────
+// ── injected signer ────────────────────────────────────────────────
 
 #[tokio::test]
-async fn sign_invalid_content_type_returns_error() {
+async fn handlers_use_the_injected_signer() {
+    let (_, body) = post_json(app_with(Arc::new(FixedSigner)), "/sign", json!({"a": 1})).await;
+    assert_eq!(body.unwrap()["signature"], json!("fixed"));
+
+    let (status, _) = post_json(
+        app_with(Arc::new(FixedSigner)),
+        "/verify",
+        json!({"signature": "fixed", "data": {"anything": true}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn async_backend_failures_map_to_error_responses() {
+    let app = app_with(Arc::new(UnreachableSigner));
+    let (status, body) = post_json(app.clone(), "/sign", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(body.unwrap()["error"]["code"], json!("backend_timeout"));
+
+    let payload = json!({"signature": "abc", "data": {"a": 1}});
+    let (status, body) = post_json(app, "/verify", payload).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body.unwrap()["error"]["code"], json!("crypto_failure"));
+}
+
+// ── algorithm dispatch ────────────────────────────────────────────
+
+fn app_with_fixed_alg() -> Router {
+    let config = test_config();
+    let state = AppState::from_config(&config).with_signer_for("fixed", Arc::new(FixedSigner));
+    take_home::router(state, &config)
+}
+
+#[tokio::test]
+async fn sign_with_alg_returns_envelope() {
+    let (status, body) = post_json(app(), "/sign?alg=hmac-sha256", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+    let signature = body.unwrap()["signature"].as_str().unwrap().to_string();
+    assert!(signature.starts_with("v1.hmac-sha256."), "{signature}");
+
+    let (status, _) = post_json(
+        app(),
+        "/verify",
+        json!({"signature": signature, "data": {"a": 1}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn envelope_selects_registered_signer() {
+    let (_, body) = post_json(app_with_fixed_alg(), "/sign?alg=fixed", json!({"a": 1})).await;
+    assert_eq!(body.unwrap()["signature"], json!("v1.fixed.fixed"));
+
+    let (status, _) = post_json(
+        app_with_fixed_alg(),
+        "/verify",
+        json!({"signature": "v1.fixed.fixed", "data": {"b": 2}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn alg_query_selects_signer_for_bare_signature() {
+    let (status, _) = post_json(
+        app_with_fixed_alg(),
+        "/verify?alg=fixed",
+        json!({"signature": "fixed", "data": {"b": 2}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn unknown_alg_returns_400() {
+    let (status, body) = post_json(app(), "/sign?alg=rot13", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], json!("validation_failed"));
+
+    let payload = json!({"signature": "v1.rot13.abc", "data": {"a": 1}});
+    let (status, _) = post_json(app(), "/verify", payload).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn alg_query_conflicting_with_envelope_returns_400() {
+    let payload = json!({"signature": "v1.fixed.fixed", "data": {"a": 1}});
+    let (status, body) = post_json(app_with_fixed_alg(), "/verify?alg=hmac-sha256", payload).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], json!("validation_failed"));
+}
+
+#[tokio::test]
+async fn envelope_config_wraps_default_signatures() {
+    let mut config = test_config();
+    config.signing.envelope = true;
+    let app = take_home::router(AppState::from_config(&config), &config);
+    let (_, body) = post_json(app, "/sign", json!({"a": 1})).await;
+    let signature = body.unwrap()["signature"].as_str().unwrap().to_string();
+    assert!(signature.starts_with("v1.hmac-sha256."), "{signature}");
+}
+
+#[tokio::test]
+async fn transitions_return_both_formats_and_verify_either() {
+    let mut config = test_config();
+    config.signing.transition.enabled = true;
+    config.signing.transition.alg = Some("hmac-sha512".into());
+    let app = take_home::app(&config);
+    let (status, body) = post_json(app.clone(), "/sign", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    let legacy = body["signature"].as_str().unwrap().to_string();
+    let envelope = body["envelope"].as_str().unwrap().to_string();
+    assert_eq!(legacy.len(), 64);
+    assert!(envelope.starts_with("v1.hmac-sha512."), "{envelope}");
+
+    for signature in [legacy, envelope] {
+        let payload = json!({"signature": signature, "data": {"a": 1}});
+        let (status, _) = post_json(app.clone(), "/verify", payload).await;
+        assert_eq!(status, StatusCode::NO_CONTENT, "{signature}");
+    }
+
+    // A request naming its algorithm gets that envelope only.
+    let (_, body) = post_json(app, "/sign?alg=hmac-sha256", json!({"a": 1})).await;
+    let body = body.unwrap();
+    assert!(body.get("envelope").is_none(), "{body}");
+}
+
+#[tokio::test]
+async fn transitions_default_to_the_algorithm_of_the_bare_signature() {
+    let mut config = test_config();
+    config.signing.transition.enabled = true;
+    config.signing.envelope = true;
+    let (_, body) = post_json(take_home::app(&config), "/sign", json!({"a": 1})).await;
+    let body = body.unwrap();
+    let legacy = body["signature"].as_str().unwrap();
+    assert_eq!(body["envelope"], json!(format!("v1.hmac-sha256.{legacy}")));
+}
+
+// ── legacy signature deprecation ─────────────────────────────────
+
+async fn post_with_headers(app: Router, uri: &str, body: Value) -> (StatusCode, HeaderMap) {
     let request = Request::builder()
         .method("POST")
-        .uri("/sign")
-        .header("Content-Type", "text/plain")
-        .body(Body::from("not json"))
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
         .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    (response.status(), response.headers().clone())
+}
 
-    let response = app().oneshot(request).await.unwrap();
-    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+#[tokio::test]
+async fn bare_signatures_carry_deprecation_headers() {
+    let mut config = test_config();
+    config.deprecation.deprecated_at = Some(1_700_000_000);
+    config.deprecation.sunset_at = Some(4_102_444_800);
+    let app = take_home::app(&config);
+    let (status, headers) = post_with_headers(app.clone(), "/sign", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["deprecation"], "@1700000000");
+    assert_eq!(headers["sunset"], "Fri, 01 Jan 2100 00:00:00 GMT");
+
+    let payload = json!({"signature": signature_of(json!({"a": 1})).await, "data": {"a": 1}});
+    let (status, headers) = post_with_headers(app.clone(), "/verify", payload).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(headers["deprecation"], "@1700000000");
+
+    // Enveloped signatures are the current format.
+    let (_, headers) = post_with_headers(app, "/sign?alg=hmac-sha256", json!({"a": 1})).await;
+    assert!(headers.get("deprecation").is_none());
 }
 
 #[tokio::test]
-async fn verify_invalid_content_type_returns_error() {
+async fn bare_signatures_are_refused_after_the_sunset_when_configured() {
+    let mut config = test_config();
+    config.deprecation.deprecated_at = Some(1_000);
+    config.deprecation.sunset_at = Some(2_000);
+    config.deprecation.reject_after_sunset = true;
+    let app = take_home::app(&config);
+    let (status, body) = post_json(app.clone(), "/sign", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::GONE);
+    assert_eq!(body.unwrap()["error"]["code"], json!("sunset"));
+
+    let payload = json!({"signature": signature_of(json!({"a": 1})).await, "data": {"a": 1}});
+    let (status, _) = post_json(app.clone(), "/verify", payload).await;
+    assert_eq!(status, StatusCode::GONE);
+
+    let (_, body) = post_json(app.clone(), "/sign?alg=hmac-sha256", json!({"a": 1})).await;
+    let signature = body.unwrap()["signature"].as_str().unwrap().to_string();
+    let payload = json!({"signature": signature, "data": {"a": 1}});
+    let (status, _) = post_json(app, "/verify", payload).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+// ── validity window ───────────────────────────────────────────────
+
+#[tokio::test]
+async fn ttl_injects_iat_and_exp_into_the_signed_data() {
+    let (status, body) = post_json(app(), "/sign?ttl=60", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    let data = &body["data"];
+    let iat = data["iat"].as_u64().unwrap();
+    assert_eq!(data["exp"].as_u64().unwrap(), iat + 60);
+    assert_eq!(data["a"], json!(1));
+
+    let payload = json!({"signature": body["signature"], "data": data});
+    let (status, _) = post_json(app(), "/verify?check_exp=true", payload).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // Without the claims, the signature does not match.
+    let payload = json!({"signature": body["signature"], "data": {"a": 1}});
+    let (status, _) = post_json(app(), "/verify", payload).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn verify_enforces_exp_when_asked_to() {
+    let data = json!({"a": 1, "exp": 1_000_000_000});
+    let payload = json!({"signature": signature_of(data.clone()).await, "data": data});
+    let (status, body) = post_json(app(), "/verify?check_exp=true", payload.clone()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let error = &body.unwrap()["error"];
+    assert_eq!(error["code"], json!("expired"));
+    assert_eq!(
+        error["message"],
+        json!("the signed data expired at 1000000000 (+0s clock skew)")
+    );
+
+    // An `exp` of the caller's own is just data otherwise.
+    let (status, _) = post_json(app(), "/verify", payload).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // A forged `exp` still fails as a bad signature.
+    let signature = signature_of(json!({"a": 1, "exp": 4_102_444_800u64})).await;
+    let payload = json!({"signature": signature, "data": {"a": 1, "exp": 1_000_000_000}});
+    let (_, body) = post_json(app(), "/verify?check_exp=true", payload).await;
+    assert_eq!(body.unwrap()["error"]["code"], json!("invalid_signature"));
+}
+
+#[tokio::test]
+async fn verify_allows_the_configured_clock_skew_past_exp() {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let data = json!({"a": 1, "exp": now - 10});
+    let payload = json!({"signature": signature_of(data.clone()).await, "data": data});
+
+    let (status, _) = post_json(app(), "/verify?check_exp=true", payload.clone()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let mut config = test_config();
+    config.clock.skew_secs = 60;
+    let app = take_home::app(&config);
+    let (status, _) = post_json(app, "/verify?check_exp=true", payload).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn expiry_follows_an_injected_clock() {
+    let config = test_config();
+    let clock = Arc::new(TestClock::new(1_700_000_000));
+    let app = take_home::router(
+        AppState::from_config_with_clock(&config, clock.clone()),
+        &config,
+    );
+    let (_, body) = post_json(app.clone(), "/sign?ttl=60", json!({"a": 1})).await;
+    let body = body.unwrap();
+    assert_eq!(body["data"]["iat"], json!(1_700_000_000));
+    let payload = json!({"signature": body["signature"], "data": body["data"]});
+
+    clock.advance(59);
+    let uri = "/verify?check_exp=true";
+    let (status, _) = post_json(app.clone(), uri, payload.clone()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    clock.advance(1);
+    let (_, body) = post_json(app, uri, payload).await;
+    assert_eq!(body.unwrap()["error"]["code"], json!("expired"));
+}
+
+#[tokio::test]
+async fn clock_offset_shifts_the_server_time() {
+    let mut config = test_config();
+    config.clock.offset_secs = 86_400;
+    let (_, body) = post_json(take_home::app(&config), "/sign?ttl=60", json!({"a": 1})).await;
+    let (_, unshifted) = post_json(app(), "/sign?ttl=60", json!({"a": 1})).await;
+    let shift = body.unwrap()["data"]["iat"].as_u64().unwrap()
+        - unshifted.unwrap()["data"]["iat"].as_u64().unwrap();
+    assert!((86_399..=86_400).contains(&shift));
+}
+
+#[tokio::test]
+async fn ttl_is_refused_where_it_cannot_apply() {
+    let cases = [
+        ("/sign?ttl=60", json!({"a": 1, "exp": 5})),
+        ("/sign?ttl=0", json!({"a": 1})),
+        ("/sign?ttl=60", json!([1, 2])),
+        (
+            "/verify?ttl=60",
+            json!({"signature": "00", "data": {"a": 1}}),
+        ),
+        ("/sign?check_exp=true", json!({"a": 1})),
+    ];
+    for (uri, body) in cases {
+        let (status, body) = post_json(app(), uri, body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        assert_eq!(body.unwrap()["error"]["code"], json!("validation_failed"));
+    }
+}
+
+// ── digest negotiation ────────────────────────────────────────────
+
+#[tokio::test]
+async fn digest_query_selects_the_hmac_hash_and_records_it() {
+    let (status, body) = post_json(app(), "/sign?digest=sha512", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+    let signature = body.unwrap()["signature"].as_str().unwrap().to_string();
+    let tag = signature.strip_prefix("v1.hmac-sha512.").unwrap();
+    assert_eq!(tag.len(), 128);
+
+    // The envelope alone tells /verify which digest to use.
+    let payload = json!({"signature": signature, "data": {"a": 1}});
+    let (status, _) = post_json(app(), "/verify", payload.clone()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = post_json(app(), "/verify?digest=sha384", payload).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn default_digest_can_move_off_sha256() {
+    let (_, body) = post_json(app(), "/sign?alg=hmac-sha256", json!({"a": 1})).await;
+    let old = body.unwrap()["signature"].as_str().unwrap().to_string();
+
+    let mut config = test_config();
+    config.signing.algorithm = SigningAlgorithm::HmacSha384;
+    let app = take_home::app(&config);
+    let (_, body) = post_json(app.clone(), "/sign?digest=sha384", json!({"a": 1})).await;
+    let new = body.unwrap()["signature"].as_str().unwrap().to_string();
+    assert!(new.starts_with("v1.hmac-sha384."), "{new}");
+
+    for signature in [old, new] {
+        let payload = json!({"signature": signature, "data": {"a": 1}});
+        let (status, _) = post_json(app.clone(), "/verify", payload).await;
+        assert_eq!(status, StatusCode::NO_CONTENT, "{signature}");
+    }
+}
+
+#[tokio::test]
+async fn unusable_digests_return_400() {
+    for uri in [
+        "/sign?digest=md5",
+        "/sign?alg=hmac-sha256&digest=sha512",
+        "/sign?alg=fixed&digest=sha256",
+    ] {
+        let (status, body) = post_json(app_with_fixed_alg(), uri, json!({"a": 1})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        assert_eq!(body.unwrap()["error"]["code"], json!("validation_failed"));
+    }
+}
+
+// ── compact binary signatures ─────────────────────────────────────
+
+async fn sign_compact(app: Router, uri: &str, body: Value) -> (StatusCode, HeaderMap, Vec<u8>) {
+    sign_accepting(app, uri, "application/octet-stream", body).await
+}
+
+async fn sign_accepting(
+    app: Router,
+    uri: &str,
+    accept: &str,
+    body: Value,
+) -> (StatusCode, HeaderMap, Vec<u8>) {
     let request = Request::builder()
         .method("POST")
-        .uri("/verify")
-        .header("Content-Type", "text/plain")
-        .body(Body::from("not json"))
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Accept", accept)
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
         .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let (status, headers) = (response.status(), response.headers().clone());
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, headers, bytes.to_vec())
+}
 
-    let response = app().oneshot(request).await.unwrap();
-    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
 }
 
 #[tokio::test]
-async fn get_sign_returns_method_not_allowed() {
-    let request = Request::builder()
-        .method("GET")
-        .uri("/sign")
-        .body(Body::empty())
-        .unwrap();
+async fn accepting_octet_streams_returns_a_compact_signature() {
+    let mut config = test_config();
+    config.signing.key_id = "2024-01".into();
+    config.deprecation.deprecated_at = Some(1_700_000_000);
+    let app = take_home::app(&config);
+    let (status, headers, body) = sign_compact(app.clone(), "/sign", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["content-type"], "application/octet-stream");
+    // The header names the algorithm, so the signature is not a legacy one.
+    assert!(headers.get("deprecation").is_none());
+    assert_eq!(body.len(), 1 + 1 + 11 + 1 + 7 + 32);
+    let compact = CompactSignature::parse(&body).unwrap();
+    assert_eq!((compact.alg, compact.kid), ("hmac-sha256", "2024-01"));
+    assert_eq!(hex(compact.signature), signature_of(json!({"a": 1})).await);
 
-    let response = app().oneshot(request).await.unwrap();
-    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    let (_, _, body) = sign_compact(app.clone(), "/sign?digest=sha512", json!({"a": 1})).await;
+    let compact = CompactSignature::parse(&body).unwrap();
+    assert_eq!(compact.alg, "hmac-sha512");
+    let signature = format!("v1.{}.{}", compact.alg, hex(compact.signature));
+    let payload = json!({"signature": signature, "data": {"a": 1}});
+    let (status, _) = post_json(app, "/verify", payload).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
 }
 
 #[tokio::test]
-async fn get_verify_returns_method_not_allowed() {
+async fn octet_streams_refused_or_less_preferred_keep_json() {
+    for accept in [
+        "application/octet-stream;q=0",
+        "application/octet-stream;q=0.5, application/json",
+        "application/octet-streams",
+        "*/*",
+    ] {
+        let (status, headers, body) = sign_accepting(app(), "/sign", accept, json!({"a": 1})).await;
+        assert_eq!(status, StatusCode::OK, "{accept}");
+        assert_eq!(headers["content-type"], "application/json", "{accept}");
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["signature"],
+            json!(signature_of(json!({"a": 1})).await)
+        );
+    }
+}
+
+#[tokio::test]
+async fn compact_signatures_cannot_carry_ttl_claims() {
+    let (status, headers, body) = sign_compact(app(), "/sign?ttl=60", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(headers["content-type"], "application/json");
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"]["code"], json!("validation_failed"));
+}
+
+// ── response signing ──────────────────────────────────────────────
+
+#[tokio::test]
+async fn sign_responses_covers_every_route() {
+    let mut config = test_config();
+    config.middleware.sign_responses = true;
+    config.signing.key_id = "2024-01".into();
+    let app = take_home::router(AppState::from_config(&config), &config);
+
     let request = Request::builder()
-        .method("GET")
+        .method("POST")
         .uri("/verify")
-        .body(Body::empty())
+        .header("Content-Type", "application/json")
+        .body(Body::from(r#"{"signature": "bad", "data": {}}"#))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.headers()["x-signature-key-id"], "2024-01");
+    let signature = response.headers()["x-signature"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let signer = HMacSigner::new(b"test-secret".to_vec());
+    let input = take_home::layers::response_signing_input(&body);
+    assert!(Signer::verify_bytes(&signer, &input, &signature));
+}
+
+#[tokio::test]
+async fn response_signatures_never_verify_as_payload_signatures() {
+    let mut config = test_config();
+    config.middleware.sign_responses = true;
+    let app = take_home::router(AppState::from_config(&config), &config);
+
+    // `/canonicalize` echoes a canonical form the caller chose, as a blob
+    // download would.
+    let request = Request::builder()
+        .method("POST")
+        .uri("/canonicalize")
+        .header("Content-Type", "application/json")
+        .body(Body::from(r#"{"a": 1}"#))
         .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let signature = response.headers()["x-signature"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    for data in [body.clone(), json!({"a": 1})] {
+        let (status, _) = post_json(
+            app.clone(),
+            "/verify",
+            json!({"signature": signature, "data": data}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{data}");
+    }
+}
 
+#[tokio::test]
+async fn responses_are_unsigned_by_default() {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/sign")
+        .header("Content-Type", "application/json")
+        .body(Body::from("{}"))
+        .unwrap();
     let response = app().oneshot(request).await.unwrap();
-    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert!(response.headers().get("x-signature").is_none());
+}
+
+// ── float policy ──────────────────────────────────────────────────
+
+fn app_with_float_policy(policy: FloatPolicy) -> Router {
+    let mut config = test_config();
+    config.signing.float_policy = policy;
+    take_home::app(&config)
+}
+
+async fn sign_then_verify_with(policy: FloatPolicy, signed: Value, verified: Value) -> StatusCode {
+    let (status, body) = post_json(app_with_float_policy(policy), "/sign", signed).await;
+    assert_eq!(status, StatusCode::OK);
+    let signature = body.unwrap()["signature"].clone();
+    let (status, _) = post_json(
+        app_with_float_policy(policy),
+        "/verify",
+        json!({"signature": signature, "data": verified}),
+    )
+    .await;
+    status
+}
+
+#[tokio::test]
+async fn canonical_policy_treats_equal_doubles_alike() {
+    let signed: Value = serde_json::from_str(r#"{"amount": 1.0, "rate": 0.10}"#).unwrap();
+    let verified: Value = serde_json::from_str(r#"{"amount": 1, "rate": 1e-1}"#).unwrap();
+    let status = sign_then_verify_with(FloatPolicy::Canonical, signed, verified).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn default_policy_keeps_signing_numbers_as_written() {
+    // Signed as before `float_policy` existed: `serde_json`'s own form.
+    let signer = HMacSigner::new(b"test-secret".to_vec());
+    let signature = Signer::sign_bytes(&signer, b"amount=1.0;big=1000.0;");
+    let data: Value = serde_json::from_str(r#"{"amount": 1.0, "big": 1e3}"#).unwrap();
+    let (status, _) = post_json(
+        app(),
+        "/verify",
+        json!({"signature": signature, "data": data}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, body) = post_json(app(), "/canonicalize", data.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.unwrap()["canonical"], json!("amount=1.0;big=1000.0;"));
+    let status = sign_then_verify_with(
+        FloatPolicy::default(),
+        data,
+        json!({"amount": 1, "big": 1000}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let app = app_with_float_policy(FloatPolicy::Canonical);
+    let (_, body) = post_json(app, "/canonicalize", json!({"amount": 1.0})).await;
+    assert_eq!(body.unwrap()["canonical"], json!("amount=1;"));
+}
+
+#[tokio::test]
+async fn reject_policy_refuses_non_integer_numbers() {
+    let app = app_with_float_policy(FloatPolicy::Reject);
+    let (status, body) = post_json(app, "/sign", json!({"amount": 9.99})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], json!("validation_failed"));
+
+    let app = app_with_float_policy(FloatPolicy::Reject);
+    let (status, _) = post_json(app, "/sign", json!({"amount": 999})).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn reject_policy_refuses_integers_beyond_64_bits() {
+    let app = app_with_float_policy(FloatPolicy::Reject);
+    let (status, body) = post_raw(app, "/sign", r#"{"id": 123456789012345678901234}"#).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let message = body["error"]["message"].as_str().unwrap().to_string();
+    assert!(message.contains("beyond the 64-bit range"), "{message}");
+
+    let app = app_with_float_policy(FloatPolicy::Reject);
+    let (status, _) = post_raw(app, "/sign", r#"{"id": 18446744073709551615}"#).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn string_policy_matches_string_encoded_numbers() {
+    let status = sign_then_verify_with(
+        FloatPolicy::String,
+        json!({"price": 0.1}),
+        json!({"price": "0.1"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+// ── duplicate keys ────────────────────────────────────────────────
+
+fn app_rejecting_duplicates() -> Router {
+    let mut config = test_config();
+    config.signing.reject_duplicate_keys = true;
+    take_home::app(&config)
+}
+
+async fn post_raw(app: Router, uri: &str, body: impl Into<Body>) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .body(body.into())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn duplicate_keys_sign_as_last_value_by_default() {
+    let (status, _) = post_raw(app(), "/sign", r#"{"amount":1,"amount":9999}"#).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn duplicate_keys_are_rejected_when_enabled() {
+    let (status, body) = post_raw(
+        app_rejecting_duplicates(),
+        "/sign",
+        r#"{"amount":1,"amount":9999}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], json!("validation_failed"));
+    assert_eq!(
+        body["error"]["message"],
+        json!("duplicate JSON key at `/amount`")
+    );
+
+    let (status, body) = post_raw(
+        app_rejecting_duplicates(),
+        "/verify",
+        r#"{"signature":"00","data":{"to":"alice","to":"mallory"}}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        json!("duplicate JSON key at `/data/to`")
+    );
+}
+
+#[tokio::test]
+async fn strict_parsing_keeps_other_checks() {
+    let (status, _) = post_raw(app_rejecting_duplicates(), "/sign", r#"{"a":1}"#).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/sign")
+        .body(Body::from(r#"{"a":1}"#))
+        .unwrap();
+    let response = app_rejecting_duplicates().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let (status, _) = post_raw(app_rejecting_duplicates(), "/sign", "{not json").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+// ── /canonicalize endpoint ────────────────────────────────────────
+
+#[tokio::test]
+async fn canonicalize_returns_the_signed_bytes_and_their_hash() {
+    use sha2::{Digest, Sha256};
+
+    let payload = json!({"timestamp": 1616161616, "message": "Hello World"});
+    let (status, body) = post_json(app(), "/canonicalize", payload.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    let canonical = body["canonical"].as_str().unwrap();
+    assert_eq!(canonical, r#"message="Hello World";timestamp=1616161616;"#);
+    let sha256: String = Sha256::digest(canonical.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    assert_eq!(body["sha256"], sha256);
+
+    // Signing the canonical bytes locally reproduces the /sign signature.
+    let (_, signed) = post_json(app(), "/sign", payload).await;
+    let local = Signer::sign_bytes(
+        &HMacSigner::new(b"test-secret".to_vec()),
+        canonical.as_bytes(),
+    );
+    assert_eq!(signed.unwrap()["signature"], local);
+}
+
+#[tokio::test]
+async fn canonicalize_applies_the_float_policy() {
+    let mut config = test_config();
+    config.signing.float_policy = FloatPolicy::String;
+    let (_, body) = post_json(
+        take_home::app(&config),
+        "/canonicalize",
+        json!({"price": 0.1}),
+    )
+    .await;
+    assert_eq!(body.unwrap()["canonical"], r#"price="0.1";"#);
+}
+
+// ── /testvectors endpoint ─────────────────────────────────────────
+
+async fn test_vectors(app: Router) -> Value {
+    let request = Request::builder()
+        .uri("/testvectors")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn test_vectors_match_canonicalize_and_the_published_key() {
+    let body = test_vectors(app()).await;
+    assert_eq!(body["float_policy"], json!("legacy"));
+    let secret = body["keys"][0]["secret_hex"].as_str().unwrap();
+    let secret: Vec<u8> = (0..secret.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&secret[i..i + 2], 16).unwrap())
+        .collect();
+    let signer = HMacSigner::new(secret);
+
+    let vectors: Vec<&Value> = body["vectors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|vector| vector["algorithm"] == json!("hmac-sha256"))
+        .collect();
+    assert_eq!(vectors.len(), 5);
+    for vector in vectors {
+        let (_, canonicalized) = post_json(app(), "/canonicalize", vector["payload"].clone()).await;
+        let canonical = vector["canonical"].as_str().unwrap();
+        assert_eq!(canonicalized.unwrap()["canonical"], canonical);
+        let signature = Signer::sign_bytes(&signer, canonical.as_bytes());
+        assert_eq!(
+            vector["signature"],
+            json!(format!("v1.hmac-sha256.{signature}"))
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_vectors_leave_out_payloads_the_float_policy_refuses() {
+    let mut config = test_config();
+    config.signing.float_policy = FloatPolicy::Reject;
+    let body = test_vectors(take_home::app(&config)).await;
+    assert_eq!(body["float_policy"], json!("reject"));
+    let vectors = body["vectors"].as_array().unwrap();
+    assert!(!vectors.is_empty());
+    assert!(
+        vectors
+            .iter()
+            .all(|vector| vector["payload"].get("tenth").is_none())
+    );
+}
+
+// ── top-level arrays ──────────────────────────────────────────────
+
+#[tokio::test]
+async fn event_batches_verify_whatever_their_nested_key_order() {
+    let events = json!([
+        {"type": "click", "at": 1616161616, "target": {"id": 7, "kind": "button"}},
+        {"type": "view", "at": 1616161617},
+    ]);
+    let signature = signature_of(events).await;
+    let reordered: Value = serde_json::from_str(
+        r#"[{"target": {"kind": "button", "id": 7}, "at": 1616161616, "type": "click"},
+            {"at": 1616161617, "type": "view"}]"#,
+    )
+    .unwrap();
+    let (status, _) = post_json(
+        app(),
+        "/verify",
+        json!({"signature": signature, "data": reordered}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn reordering_array_elements_breaks_the_signature() {
+    let signature = signature_of(json!([{"n": 1}, {"n": 2}])).await;
+    let (status, body) = post_json(
+        app(),
+        "/verify",
+        json!({"signature": signature, "data": [{"n": 2}, {"n": 1}]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], json!("invalid_signature"));
+}
+
+#[tokio::test]
+async fn array_signatures_do_not_verify_objects() {
+    let signature = signature_of(json!([])).await;
+    let (status, _) = post_json(
+        app(),
+        "/verify",
+        json!({"signature": signature, "data": {}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn canonicalize_writes_arrays_with_sorted_keys() {
+    let (status, body) = post_json(
+        app(),
+        "/canonicalize",
+        json!([{"b": 1.0, "a": {"d": null, "c": [2]}}, "x"]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body.unwrap()["canonical"],
+        r#"[{"a":{"c":[2],"d":null},"b":1},"x"]"#
+    );
+}
+
+#[tokio::test]
+async fn float_policy_applies_to_array_elements() {
+    let app = app_with_float_policy(FloatPolicy::Reject);
+    let (status, body) = post_json(app, "/sign", json!([{"amount": 1}, {"amount": 9.99}])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body.unwrap()["error"]["message"],
+        json!("non-integer number at `/1/amount` is not allowed in signed payloads")
+    );
+}
+
+#[tokio::test]
+async fn scalars_cannot_be_signed() {
+    let (status, body) = post_json(app(), "/sign", json!("text")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], json!("validation_failed"));
+}
+
+// ── pre-hashed payloads ───────────────────────────────────────────
+
+#[tokio::test]
+async fn prehashed_payloads_sign_and_verify_with_a_domain_tag() {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+
+    let digest = [42u8; 32];
+    let prehashed = json!({"digest": STANDARD.encode(digest), "digest_alg": "sha256"});
+    let (status, body) = post_json(app(), "/sign?prehashed=true", prehashed.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let signature = body.unwrap()["signature"].as_str().unwrap().to_string();
+
+    let mut input = b"\xfftake-home prehashed v1\0sha256\0".to_vec();
+    input.extend_from_slice(&digest);
+    let local = Signer::sign_bytes(&HMacSigner::new(b"test-secret".to_vec()), &input);
+    assert_eq!(signature, local);
+
+    let body = json!({"signature": signature, "data": prehashed});
+    let (status, _) = post_json(app(), "/verify?prehashed=true", body.clone()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    // Without the flag the same object is an ordinary payload.
+    let (status, _) = post_json(app(), "/verify", body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let other = json!({"digest": STANDARD.encode([0u8; 32]), "digest_alg": "sha256"});
+    let body = json!({"signature": signature, "data": other});
+    let (status, response) = post_json(app(), "/verify?prehashed=true", body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.unwrap()["error"]["code"], "invalid_signature");
+}
+
+#[tokio::test]
+async fn digest_shaped_objects_sign_canonically_unless_prehashed() {
+    let data = json!({"digest": "c2hhMjU2IG9mIG15IGZpbGU=", "digest_alg": "md5"});
+    let (status, body) = post_json(app(), "/sign", data.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let signature = body.unwrap()["signature"].as_str().unwrap().to_string();
+
+    let (status, body) = post_json(app(), "/canonicalize", data.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let body = body.unwrap();
+    let canonical = body["canonical"].as_str().unwrap();
+    assert_eq!(
+        canonical,
+        r#"digest="c2hhMjU2IG9mIG15IGZpbGU=";digest_alg="md5";"#
+    );
+    let local = Signer::sign_bytes(
+        &HMacSigner::new(b"test-secret".to_vec()),
+        canonical.as_bytes(),
+    );
+    assert_eq!(signature, local);
+
+    let body = json!({"signature": signature, "data": data});
+    let (status, _) = post_json(app(), "/verify", body).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn malformed_digests_are_rejected() {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+
+    for body in [
+        json!({"digest": STANDARD.encode([1u8; 16]), "digest_alg": "sha256"}),
+        json!({"digest": STANDARD.encode([1u8; 16]), "digest_alg": "md5"}),
+        json!({"digest": "not base64!", "digest_alg": "sha256"}),
+        json!({"digest": STANDARD.encode([1u8; 32]), "digest_alg": "sha256", "note": "x"}),
+        json!([STANDARD.encode([1u8; 32])]),
+    ] {
+        let (status, response) = post_json(app(), "/sign?prehashed=true", body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.unwrap()["error"]["code"], "validation_failed");
+    }
+}
+
+// ── JSON limits ───────────────────────────────────────────────────
+
+fn app_with_json_limits(max_json_depth: usize, max_json_entries: usize) -> Router {
+    let mut config = test_config();
+    config.limits.max_json_depth = max_json_depth;
+    config.limits.max_json_entries = max_json_entries;
+    take_home::app(&config)
+}
+
+#[tokio::test]
+async fn deep_documents_are_refused_with_422() {
+    let (status, _) = post_raw(app_with_json_limits(3, 100), "/sign", r#"{"a":{"b":[1]}}"#).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = post_raw(
+        app_with_json_limits(3, 100),
+        "/sign",
+        r#"{"a":{"b":[[1]]}}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["code"], json!("limit_exceeded"));
+    assert_eq!(
+        body["error"]["message"],
+        json!("JSON nesting exceeds the limit of 3 levels")
+    );
+}
+
+#[tokio::test]
+async fn wide_documents_are_refused_with_422() {
+    let (status, body) = post_raw(
+        app_with_json_limits(32, 3),
+        "/verify",
+        r#"{"signature":"00","data":{"a":1,"b":2}}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["code"], json!("limit_exceeded"));
+    assert_eq!(
+        body["error"]["message"],
+        json!("JSON document exceeds the limit of 3 keys and array elements")
+    );
+}
+
+#[tokio::test]
+async fn sign_refuses_too_many_fields_and_long_strings() {
+    let mut config = test_config();
+    config.limits.max_json_fields = 2;
+    config.limits.max_json_value_bytes = 4;
+    let app = take_home::app(&config);
+
+    let (status, body) = post_raw(app.clone(), "/sign", r#"{"a":1,"b":2,"c":3}"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body["error"]["message"],
+        json!("JSON object exceeds the limit of 2 top-level fields")
+    );
+
+    let (status, body) = post_raw(app, "/sign", r#"{"a":{"b":"12345"}}"#).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["code"], json!("limit_exceeded"));
+    assert_eq!(
+        body["error"]["message"],
+        json!("JSON string at `/a/b` exceeds the limit of 4 bytes")
+    );
+}
+
+#[tokio::test]
+async fn default_limits_stop_pathological_nesting() {
+    let nested = format!("{}{}", "[".repeat(5000), "]".repeat(5000));
+    let (status, body) = post_raw(app(), "/sign", nested).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["code"], json!("limit_exceeded"));
+}
+
+// ── HTTP-level edge cases ──────────────────────────────────────────
+
+#[tokio::test]
+async fn sign_invalid_content_type_returns_error() {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/sign")
+        .header("Content-Type", "text/plain")
+        .body(Body::from("not json"))
+        .unwrap();
+
+    let response = app().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn verify_invalid_content_type_returns_error() {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/verify")
+        .header("Content-Type", "text/plain")
+        .body(Body::from("not json"))
+        .unwrap();
+
+    let response = app().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn sign_accepts_json_content_type_variants() {
+    for content_type in [
+        "application/json; charset=utf-8",
+        "Application/JSON",
+        "application/vnd.payment+json",
+    ] {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/sign")
+            .header("Content-Type", content_type)
+            .body(Body::from(r#"{"a":1}"#))
+            .unwrap();
+
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{content_type}");
+    }
+}
+
+#[tokio::test]
+async fn sign_without_content_type_returns_error() {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/sign")
+        .body(Body::from(r#"{"a":1}"#))
+        .unwrap();
+
+    let response = app().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn get_sign_returns_method_not_allowed() {
+    let request = Request::builder()
+        .method("GET")
+        .uri("/sign")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn get_verify_returns_method_not_allowed() {
+    let request = Request::builder()
+        .method("GET")
+        .uri("/verify")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
+
+async fn sign_as(app: Router, client: &str) -> StatusCode {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/sign")
+        .header("Content-Type", "application/json")
+        .header("X-Client-Id", client)
+        .body(Body::from(r#"{"a":1}"#))
+        .unwrap();
+    app.oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn policy_rules_restrict_clients_to_their_keys() {
+    let mut config = test_config();
+    config.signing.key_id = "payments-2024".into();
+    config.policy.enabled = true;
+    config.policy.rules = vec![
+        PolicyRule {
+            effect: Effect::Allow,
+            actions: vec![Action::Sign],
+            clients: vec!["team-payments".into()],
+            kids: vec!["payments-*".into()],
+            ..PolicyRule::default()
+        },
+        PolicyRule {
+            effect: Effect::Allow,
+            actions: vec![Action::Verify],
+            ..PolicyRule::default()
+        },
+    ];
+    let app = take_home::app(&config);
+
+    assert_eq!(sign_as(app.clone(), "team-payments").await, StatusCode::OK);
+    assert_eq!(
+        sign_as(app.clone(), "team-billing").await,
+        StatusCode::FORBIDDEN
+    );
+    let (status, body) = post_json(
+        app.clone(),
+        "/verify",
+        json!({"signature": "x", "data": {"a": 1}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "invalid_signature");
+
+    config.signing.key_id = "billing-2024".into();
+    let app = take_home::app(&config);
+    let request = Request::builder()
+        .method("POST")
+        .uri("/sign")
+        .header("Content-Type", "application/json")
+        .header("X-Client-Id", "team-payments")
+        .body(Body::from(r#"{"a":1}"#))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(body["error"]["code"], "forbidden");
+}
+
+/// Policy backend that cannot be reached.
+struct UnreachablePolicy;
+
+impl PolicyBackend for UnreachablePolicy {
+    fn allows<'a>(
+        &'a self,
+        _request: &'a AccessRequest,
+    ) -> BoxFuture<'a, Result<bool, PolicyError>> {
+        Box::pin(async { Err(PolicyError::Timeout) })
+    }
+}
+
+#[tokio::test]
+async fn unavailable_policy_refuses_requests() {
+    let config = test_config();
+    let state = AppState::from_config(&config).with_policy(Arc::new(UnreachablePolicy));
+    let app = take_home::router(state, &config);
+
+    let (status, body) = post_json(app.clone(), "/sign", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body.unwrap()["error"]["code"], "policy_unavailable");
+    // Endpoints that use no key are not decided.
+    let (status, _) = post_json(app, "/canonicalize", json!({"a": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+// ── key reload ─────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn reloading_keys_replaces_the_secret_read_from_its_file() {
+    let path = std::env::temp_dir().join(format!("take-home-rotated-{}", std::process::id()));
+    std::fs::write(&path, "old-secret\n").unwrap();
+    let cli = Cli {
+        hmac_secret_file: Some(path.clone()),
+        ..Cli::default()
+    };
+    let load = || {
+        let mut config = Config::load(&cli).unwrap();
+        config.signing.cache.enabled = true;
+        config
+    };
+    let config = load();
+    let state = AppState::from_config(&config);
+    let app = take_home::router(state.clone(), &config);
+    let data = json!({"message": "rotate"});
+    let (_, old) = post_json(app.clone(), "/sign", data.clone()).await;
+
+    std::fs::write(&path, "new-secret\n").unwrap();
+    let mut rotated = load();
+    state.reload_keys(&rotated).unwrap();
+    let (_, new) = post_json(app.clone(), "/sign", data.clone()).await;
+    let (status, _) = post_json(
+        app,
+        "/verify",
+        json!({"signature": old.unwrap()["signature"], "data": data}),
+    )
+    .await;
+    rotated.signing.key_id = "v2".into();
+    let renamed = state.reload_keys(&rotated);
+    std::fs::remove_file(&path).unwrap();
+
+    let expected = Signer::sign(
+        &HMacSigner::new(b"new-secret".to_vec()),
+        data.as_object().unwrap(),
+    );
+    assert_eq!(new.unwrap()["signature"], json!(expected));
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        renamed,
+        Err(ReloadError::NeedsRestart("changing `signing.key_id`"))
+    );
 }
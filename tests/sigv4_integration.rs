@@ -0,0 +1,246 @@
+#![cfg(all(feature = "server", feature = "signing"))]
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Router,
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+};
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use take_home::config::{Config, Secret};
+use take_home::crypto::sigv4::{Credentials, SigV4Request, VerifiedSigV4, authorization};
+use take_home::layers::VerifySigV4Layer;
+use take_home::replay::MemoryReplayStore;
+use tower::ServiceExt;
+
+const SECRET: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+/// 2015-08-30T12:36:00Z, the date of the AWS documentation example.
+const AMZ_DATE: &str = "20150830T123600Z";
+
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    config
+        .sigv4
+        .credentials
+        .insert("AKIDEXAMPLE".into(), Secret::new(SECRET));
+    // The fixed example date is years old.
+    config.sigv4.max_skew_secs = u64::MAX;
+    config
+}
+
+fn app() -> Router {
+    take_home::app(&test_config())
+}
+
+async fn post_json(app: Router, uri: &str, body: Value) -> (StatusCode, Option<Value>) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value = serde_json::from_slice(&bytes).ok();
+    (status, value)
+}
+
+fn sign(method: &str, uri: &str, body: &str) -> String {
+    let headers = vec![
+        ("host".to_string(), "orders.internal".to_string()),
+        ("x-amz-date".to_string(), AMZ_DATE.to_string()),
+    ];
+    let request = SigV4Request {
+        method,
+        uri,
+        headers: &headers,
+        body: body.as_bytes(),
+    };
+    authorization(
+        "AKIDEXAMPLE",
+        SECRET.as_bytes(),
+        &request,
+        "eu-west-1",
+        "orders",
+        &["host", "x-amz-date"],
+    )
+    .unwrap()
+}
+
+fn verify_request(authorization: &str, body: &str) -> Value {
+    json!({
+        "method": "PUT",
+        "uri": "/orders/42?expand=items",
+        "headers": {
+            "Host": "orders.internal",
+            "X-Amz-Date": AMZ_DATE,
+            "Authorization": authorization,
+        },
+        "body": body,
+    })
+}
+
+// ── /sigv4/verify endpoint ─────────────────────────────────────────
+
+#[tokio::test]
+async fn valid_signature_returns_credential_scope() {
+    let authorization = sign("PUT", "/orders/42?expand=items", r#"{"qty":1}"#);
+    let (status, body) = post_json(
+        app(),
+        "/sigv4/verify",
+        verify_request(&authorization, r#"{"qty":1}"#),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body.unwrap(),
+        json!({
+            "access_key_id": "AKIDEXAMPLE",
+            "date": "20150830",
+            "region": "eu-west-1",
+            "service": "orders",
+            "signed_headers": ["host", "x-amz-date"],
+        })
+    );
+}
+
+#[tokio::test]
+async fn tampered_body_returns_400() {
+    let authorization = sign("PUT", "/orders/42?expand=items", r#"{"qty":1}"#);
+    let (status, body) = post_json(
+        app(),
+        "/sigv4/verify",
+        verify_request(&authorization, r#"{"qty":9}"#),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "invalid_signature");
+}
+
+#[tokio::test]
+async fn unknown_access_key_returns_400() {
+    let authorization =
+        sign("PUT", "/orders/42?expand=items", "").replace("AKIDEXAMPLE", "AKIDOTHER");
+    let (status, body) =
+        post_json(app(), "/sigv4/verify", verify_request(&authorization, "")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "invalid_signature");
+}
+
+#[tokio::test]
+async fn stale_request_is_rejected_with_default_skew() {
+    let mut config = test_config();
+    config.sigv4.max_skew_secs = 900;
+    let authorization = sign("PUT", "/orders/42?expand=items", "");
+    let (status, body) = post_json(
+        take_home::app(&config),
+        "/sigv4/verify",
+        verify_request(&authorization, ""),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let error = &body.unwrap()["error"];
+    assert_eq!(error["code"], "expired");
+    assert_eq!(
+        error["message"],
+        "request time is more than 900s from the server clock"
+    );
+}
+
+#[tokio::test]
+async fn malformed_authorization_returns_validation_error() {
+    let (status, body) =
+        post_json(app(), "/sigv4/verify", verify_request("Bearer token", "")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "validation_failed");
+}
+
+// ── VerifySigV4Layer ───────────────────────────────────────────────
+
+fn layer() -> VerifySigV4Layer {
+    let credentials = Credentials::new().with_secret("AKIDEXAMPLE", SECRET.as_bytes().to_vec());
+    VerifySigV4Layer::new(Arc::new(credentials)).max_skew_secs(u64::MAX)
+}
+
+fn echo() -> Router {
+    Router::new().route(
+        "/orders/42",
+        post(
+            |Extension(verified): Extension<VerifiedSigV4>, body: String| async move {
+                format!("{}:{body}", verified.service)
+            },
+        ),
+    )
+}
+
+fn signed_request(body: &'static str, authorization: Option<String>) -> Request<Body> {
+    let mut builder = Request::builder()
+        .method("POST")
+        .uri("/orders/42?expand=items")
+        .header("Host", "orders.internal")
+        .header("X-Amz-Date", AMZ_DATE);
+    if let Some(authorization) = authorization {
+        builder = builder.header("Authorization", authorization);
+    }
+    builder.body(Body::from(body)).unwrap()
+}
+
+#[tokio::test]
+async fn layer_accepts_signed_request_and_exposes_scope() {
+    let authorization = sign("POST", "/orders/42?expand=items", "hello");
+    let response = echo()
+        .layer(layer())
+        .oneshot(signed_request("hello", Some(authorization)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&body[..], b"orders:hello");
+}
+
+#[tokio::test]
+async fn layer_rejects_unsigned_request() {
+    let response = echo()
+        .layer(layer())
+        .oneshot(signed_request("hello", None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn layer_rejects_tampered_body() {
+    let authorization = sign("POST", "/orders/42?expand=items", "hello");
+    let response = echo()
+        .layer(layer())
+        .oneshot(signed_request("hell0", Some(authorization)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn layer_rejects_replayed_signatures() {
+    let app = echo().layer(layer().replay_store(Arc::new(MemoryReplayStore::new())));
+    let authorization = sign("POST", "/orders/42?expand=items", "hello");
+    for expected in [StatusCode::OK, StatusCode::UNAUTHORIZED] {
+        let response = app
+            .clone()
+            .oneshot(signed_request("hello", Some(authorization.clone())))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), expected);
+    }
+    let other = sign("POST", "/orders/42?expand=items", "world");
+    let response = app
+        .oneshot(signed_request("world", Some(other)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,503 @@
+#![cfg(feature = "tenancy")]
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use take_home::config::{Config, Secret};
+use take_home::crypto::compact::CompactSignature;
+use take_home::quota::QuotaLimits;
+use take_home::state::AppState;
+use take_home::tenancy::{StaticTenantSource, Tenants};
+use tower::ServiceExt;
+
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    config.tenancy.enabled = true;
+    config
+        .tenancy
+        .tenants
+        .insert("payments".into(), Secret::new("payments-secret"));
+    config
+        .tenancy
+        .tenants
+        .insert("search".into(), Secret::new("search-secret"));
+    config
+}
+
+async fn post_json(
+    app: Router,
+    uri: &str,
+    tenant: Option<&str>,
+    body: Value,
+) -> (StatusCode, Option<Value>) {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(tenant) = tenant {
+        request = request.header("X-Tenant-Id", tenant);
+    }
+    let request = request
+        .body(Body::from(serde_json::to_string(&body).unwrap()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&bytes).ok())
+}
+
+async fn sign(app: Router, tenant: Option<&str>) -> String {
+    let (status, body) = post_json(app, "/sign", tenant, json!({"amount": 1})).await;
+    assert_eq!(status, StatusCode::OK);
+    body.unwrap()["signature"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn compact_signatures_name_the_tenant_key() {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/sign")
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/octet-stream")
+        .header("X-Tenant-Id", "payments")
+        .body(Body::from(r#"{"amount": 1}"#))
+        .unwrap();
+    let app = take_home::app(&test_config());
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let compact = CompactSignature::parse(&bytes).unwrap();
+    assert_eq!(
+        (compact.alg, compact.kid),
+        ("hmac-sha256", "tenant:payments")
+    );
+    let signature: String = compact
+        .signature
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    assert_eq!(signature, sign(app, Some("payments")).await);
+}
+
+#[tokio::test]
+async fn tenants_sign_with_their_own_keys() {
+    let app = take_home::app(&test_config());
+    let service = sign(app.clone(), None).await;
+    let payments = sign(app.clone(), Some("payments")).await;
+    let search = sign(app.clone(), Some("search")).await;
+    assert_ne!(payments, service);
+    assert_ne!(payments, search);
+
+    let body = json!({"data": {"amount": 1}, "signature": payments});
+    let (status, _) = post_json(app.clone(), "/verify", Some("payments"), body.clone()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    // One tenant's signatures do not verify for another, nor for the service.
+    for tenant in [Some("search"), None] {
+        let (status, response) = post_json(app.clone(), "/verify", tenant, body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.unwrap()["error"]["code"], "invalid_signature");
+    }
+}
+
+#[tokio::test]
+async fn unknown_and_malformed_tenants_are_rejected() {
+    let app = take_home::app(&test_config());
+    let (status, body) = post_json(app.clone(), "/sign", Some("billing"), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body.unwrap()["error"]["code"], "unauthorized");
+    let (status, body) = post_json(app, "/sign", Some("a/b"), json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "validation_failed");
+}
+
+#[tokio::test]
+async fn required_tenancy_rejects_requests_without_a_tenant() {
+    let mut config = test_config();
+    config.tenancy.required = true;
+    let app = take_home::app(&config);
+    let (status, _) = post_json(app.clone(), "/sign", None, json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    sign(app, Some("payments")).await;
+}
+
+#[tokio::test]
+async fn tenant_header_is_ignored_when_tenancy_is_disabled() {
+    let mut config = test_config();
+    config.tenancy.enabled = false;
+    let app = take_home::app(&config);
+    assert_eq!(
+        sign(app.clone(), Some("payments")).await,
+        sign(app, None).await
+    );
+}
+
+#[tokio::test]
+async fn embedders_can_provide_their_own_tenants() {
+    let config = test_config();
+    let tenants = Tenants::new(
+        Arc::new(StaticTenantSource::new().with_tenant("ads", Secret::new("ads-secret"))),
+        NonZeroUsize::new(16).unwrap(),
+        Duration::from_secs(60),
+    );
+    let state = AppState::from_config(&config).with_tenants(Arc::new(tenants));
+    let app = take_home::router(state, &config);
+    sign(app.clone(), Some("ads")).await;
+    let (status, _) = post_json(app, "/sign", Some("payments"), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[cfg(feature = "admin")]
+#[tokio::test]
+async fn admin_api_invalidates_cached_tenants() {
+    let config = test_config();
+    let admin = take_home::admin_app(&config);
+    for uri in ["/tenants/payments/cache", "/tenants/cache"] {
+        let request = Request::delete(uri).body(Body::empty()).unwrap();
+        let response = admin.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT, "{uri}");
+    }
+
+    let mut config = test_config();
+    config.tenancy.enabled = false;
+    let request = Request::delete("/tenants/cache")
+        .body(Body::empty())
+        .unwrap();
+    let response = take_home::admin_app(&config)
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[cfg(feature = "admin")]
+async fn rotate(config: &Config, tenant: &str) -> StatusCode {
+    let request = Request::put(format!("/tenants/{tenant}/key"))
+        .body(Body::empty())
+        .unwrap();
+    let response = take_home::admin_app(config).oneshot(request).await.unwrap();
+    response.status()
+}
+
+#[cfg(feature = "admin")]
+#[tokio::test]
+async fn keys_from_the_config_file_cannot_be_rotated() {
+    assert_eq!(
+        rotate(&test_config(), "payments").await,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[cfg(all(feature = "admin", feature = "sqlite"))]
+#[tokio::test]
+async fn admin_api_rotates_sqlite_tenant_keys() {
+    let path = std::env::temp_dir().join(format!("tenancy-it-{}.db", std::process::id()));
+    let mut config = test_config();
+    config.tenancy.tenants.clear();
+    config.tenancy.sqlite_path = Some(path.clone());
+    config.tenancy.sqlite_master_key = Some(Secret::new("master-key"));
+
+    let app = take_home::app(&config);
+    let (status, _) = post_json(app.clone(), "/sign", Some("ads"), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    assert_eq!(rotate(&config, "ads").await, StatusCode::NO_CONTENT);
+    // A fresh app stands in for a restart: the key is read back from disk.
+    let app = take_home::app(&config);
+    let signature = sign(app.clone(), Some("ads")).await;
+    assert_eq!(sign(take_home::app(&config), Some("ads")).await, signature);
+
+    assert_eq!(rotate(&config, "a~b").await, StatusCode::BAD_REQUEST);
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+}
+
+#[cfg(all(feature = "admin", feature = "sqlite"))]
+async fn set_state(config: &Config, tenant: &str, state: &str) -> StatusCode {
+    let request = Request::put(format!("/tenants/{tenant}/state"))
+        .header("Content-Type", "application/json")
+        .body(Body::from(json!({"state": state}).to_string()))
+        .unwrap();
+    let response = take_home::admin_app(config).oneshot(request).await.unwrap();
+    response.status()
+}
+
+#[cfg(all(feature = "admin", feature = "sqlite"))]
+#[tokio::test]
+async fn admin_api_moves_sqlite_keys_through_their_lifecycle() {
+    let path = std::env::temp_dir().join(format!("tenancy-states-{}.db", std::process::id()));
+    let mut config = test_config();
+    config.tenancy.tenants.clear();
+    config.tenancy.sqlite_path = Some(path.clone());
+    config.tenancy.sqlite_master_key = Some(Secret::new("master-key"));
+    // Fresh apps below stand in for expired caches.
+    let sign_status =
+        |config: &Config| post_json(take_home::app(config), "/sign", Some("ads"), json!({}));
+
+    let request = Request::put("/tenants/ads/key?state=pending")
+        .body(Body::empty())
+        .unwrap();
+    let response = take_home::admin_app(&config)
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(sign_status(&config).await.0, StatusCode::UNAUTHORIZED);
+
+    assert_eq!(
+        set_state(&config, "ads", "active").await,
+        StatusCode::NO_CONTENT
+    );
+    let signature = sign(take_home::app(&config), Some("ads")).await;
+    let body = json!({"data": {"amount": 1}, "signature": signature});
+
+    assert_eq!(
+        set_state(&config, "ads", "destroyed").await,
+        StatusCode::NO_CONTENT
+    );
+    // Within the grace period existing signatures still verify.
+    let (status, _) = post_json(
+        take_home::app(&config),
+        "/verify",
+        Some("ads"),
+        body.clone(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(sign_status(&config).await.0, StatusCode::UNAUTHORIZED);
+    let (_, page) = get_json(take_home::admin_app(&config), "/tenants?state=destroyed").await;
+    assert_eq!(page["tenants"][0]["tenant"], "ads");
+    assert!(page["tenants"][0]["destroy_at"].is_u64());
+
+    assert_eq!(
+        set_state(&config, "ads", "active").await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        set_state(&config, "ads", "disabled").await,
+        StatusCode::NO_CONTENT
+    );
+    let (status, _) = post_json(take_home::app(&config), "/verify", Some("ads"), body).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    assert_eq!(
+        set_state(&config, "ads", "lost").await,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        set_state(&config, "news", "disabled").await,
+        StatusCode::NOT_FOUND
+    );
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+}
+
+#[cfg(feature = "admin")]
+async fn get_json(app: Router, uri: &str) -> (StatusCode, Value) {
+    let request = Request::get(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[cfg(feature = "admin")]
+#[tokio::test]
+async fn admin_api_lists_tenants_page_by_page() {
+    let mut config = test_config();
+    config
+        .tenancy
+        .tenants
+        .insert("ads".into(), Secret::new("ads-secret"));
+    let admin = take_home::admin_app(&config);
+
+    let (status, page) = get_json(admin.clone(), "/tenants?limit=2&order=desc").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["tenants"][0]["tenant"], "search");
+    assert_eq!(page["tenants"][0]["algorithm"], "hmac-sha256");
+    assert!(page["tenants"][0].get("secret").is_none());
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let (_, page) = get_json(
+        admin.clone(),
+        &format!("/tenants?limit=2&order=desc&cursor={cursor}"),
+    )
+    .await;
+    assert_eq!(
+        page["tenants"],
+        json!([{"tenant": "ads", "algorithm": "hmac-sha256", "state": "active"}])
+    );
+    assert!(page.get("next_cursor").is_none());
+
+    let (_, page) = get_json(admin.clone(), "/tenants?algorithm=ed25519").await;
+    assert_eq!(page["tenants"], json!([]));
+    for query in ["limit=0", "limit=501", "cursor=nope", "sort=name"] {
+        let (status, _) = get_json(admin.clone(), &format!("/tenants?{query}")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+    }
+}
+
+/// Static keys behind a switch that makes every load fail.
+struct Flaky {
+    inner: StaticTenantSource,
+    down: std::sync::atomic::AtomicBool,
+}
+
+impl take_home::tenancy::TenantKeySource for Flaky {
+    fn load<'a>(
+        &'a self,
+        tenant: &'a str,
+    ) -> take_home::crypto::BoxFuture<
+        'a,
+        Result<Option<take_home::tenancy::TenantKey>, take_home::tenancy::TenancyError>,
+    > {
+        if self.down.load(std::sync::atomic::Ordering::Relaxed) {
+            return Box::pin(async {
+                Err(take_home::tenancy::TenancyError::Backend(
+                    "connection refused".into(),
+                ))
+            });
+        }
+        self.inner.load(tenant)
+    }
+}
+
+#[cfg(feature = "admin")]
+#[tokio::test]
+async fn unreachable_key_sources_leave_verification_working() {
+    let config = test_config();
+    let source = Arc::new(Flaky {
+        inner: StaticTenantSource::new().with_tenant("ads", Secret::new("ads-secret")),
+        down: Default::default(),
+    });
+    let tenants = Arc::new(Tenants::new(
+        source.clone(),
+        NonZeroUsize::new(16).unwrap(),
+        Duration::ZERO,
+    ));
+    let state = AppState::from_config(&config).with_tenants(tenants);
+    let app = take_home::router(state.clone(), &config);
+    let admin = take_home::admin_router(state, &config);
+
+    let (_, ready) = get_json(admin.clone(), "/readyz").await;
+    assert_eq!(ready, json!({"status": "ready", "unavailable": []}));
+    let signature = sign(app.clone(), Some("ads")).await;
+
+    source
+        .down
+        .store(true, std::sync::atomic::Ordering::Relaxed);
+    let body = json!({"data": {"amount": 1}, "signature": signature});
+    let (status, _) = post_json(app.clone(), "/verify", Some("ads"), body).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, response) = post_json(app.clone(), "/sign", Some("ads"), json!({})).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.unwrap()["error"]["code"], "key_store_unavailable");
+
+    let (status, ready) = get_json(admin.clone(), "/readyz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ready["status"], "degraded");
+    assert_eq!(ready["unavailable"][0]["backend"], "tenancy");
+
+    source
+        .down
+        .store(false, std::sync::atomic::Ordering::Relaxed);
+    sign(app, Some("ads")).await;
+    let (_, ready) = get_json(admin, "/readyz").await;
+    assert_eq!(ready["status"], "ready");
+}
+
+#[tokio::test]
+async fn failing_key_sources_trip_the_circuit_breaker() {
+    let config = test_config();
+    let source = Arc::new(Flaky {
+        inner: StaticTenantSource::new().with_tenant("ads", Secret::new("ads-secret")),
+        down: std::sync::atomic::AtomicBool::new(true),
+    });
+    let settings = take_home::breaker::BreakerSettings {
+        failure_threshold: 2,
+        call_timeout: Duration::from_secs(1),
+        open_for: Duration::from_secs(60),
+    };
+    let tenants = Tenants::new(
+        Arc::new(take_home::tenancy::BreakerSource::new(
+            source.clone(),
+            settings,
+        )),
+        NonZeroUsize::new(16).unwrap(),
+        Duration::from_secs(60),
+    );
+    let state = AppState::from_config(&config).with_tenants(Arc::new(tenants));
+    let app = take_home::router(state, &config);
+
+    for code in [
+        "key_store_unavailable",
+        "key_store_unavailable",
+        "circuit_open",
+    ] {
+        let (status, body) = post_json(app.clone(), "/sign", Some("ads"), json!({})).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body.unwrap()["error"]["code"], code);
+    }
+    // Open circuits do not reach the source, even once it is back.
+    source
+        .down
+        .store(false, std::sync::atomic::Ordering::Relaxed);
+    let (_, body) = post_json(app, "/sign", Some("ads"), json!({})).await;
+    assert_eq!(body.unwrap()["error"]["code"], "circuit_open");
+}
+
+#[tokio::test]
+async fn quotas_count_operations_of_known_tenants_only() {
+    let mut config = test_config();
+    config.quotas.tenants.insert(
+        "*".into(),
+        QuotaLimits {
+            hourly: Some(2),
+            daily: None,
+        },
+    );
+    let app = take_home::app(&config);
+    let sign = |tenant: &'static str| {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/sign")
+            .header("Content-Type", "application/json")
+            .header("X-Tenant-Id", tenant)
+            .body(Body::from(r#"{"a":1}"#))
+            .unwrap();
+        app.clone().oneshot(request)
+    };
+
+    let response = sign("payments").await.unwrap();
+    assert_eq!(response.headers()["x-ratelimit-limit"], "2");
+    assert_eq!(response.headers()["x-ratelimit-remaining"], "1");
+    sign("payments").await.unwrap();
+    let response = sign("payments").await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["x-ratelimit-remaining"], "0");
+    assert!(response.headers().contains_key("retry-after"));
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(body["error"]["code"], "quota_exceeded");
+
+    let response = sign("search").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // Unknown tenants are refused without a counter of their own.
+    for _ in 0..3 {
+        let response = sign("billing").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(!response.headers().contains_key("x-ratelimit-limit"));
+    }
+}
@@ -0,0 +1,320 @@
+#![cfg(feature = "vault")]
+
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use take_home::audit::MemoryAuditSink;
+use take_home::clock::TestClock;
+use take_home::config::{Config, Secret};
+use take_home::state::AppState;
+use take_home::vault::{MemoryVaultStore, VaultStore};
+use tower::ServiceExt;
+
+struct Harness {
+    config: Config,
+    store: Arc<MemoryVaultStore>,
+    audit: Arc<MemoryAuditSink>,
+    clock: Arc<TestClock>,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let mut config = Config::default();
+        config.signing.secret = Some(Secret::new("test-secret"));
+        Self {
+            config,
+            store: Arc::new(MemoryVaultStore::new()),
+            audit: Arc::new(MemoryAuditSink::new()),
+            clock: Arc::new(TestClock::new(1_700_000_000)),
+        }
+    }
+
+    fn app(&self) -> Router {
+        let state = AppState::from_config_with_clock(&self.config, self.clock.clone())
+            .with_vault_store(self.store.clone())
+            .with_audit_sink(self.audit.clone());
+        take_home::router(state, &self.config)
+    }
+
+    async fn send(&self, method: &str, name: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(format!("/vault/{name}"))
+            .header("Content-Type", "application/json")
+            .header("X-Client-Id", "billing")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+        let response = self.app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+}
+
+// ── PUT / GET / DELETE ────────────────────────────────────────────
+
+#[tokio::test]
+async fn secrets_round_trip_with_metadata() {
+    let vault = Harness::new();
+    let (status, entry) = vault
+        .send(
+            "PUT",
+            "db-password",
+            Some(json!({"value": "s3cret", "labels": {"owner": "payments"}})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(entry["name"], json!("db-password"));
+    assert_eq!(entry["version"], json!(1));
+    assert_eq!(entry["labels"], json!({"owner": "payments"}));
+    assert!(entry.get("value").is_none());
+
+    let (status, secret) = vault.send("GET", "db-password", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(secret["value"], json!("s3cret"));
+    assert_eq!(secret["version"], json!(1));
+    assert_eq!(secret["created_at"], entry["created_at"]);
+
+    let (status, entry) = vault
+        .send("PUT", "db-password", Some(json!({"value": "rotated"})))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(entry["version"], json!(2));
+
+    let (status, _) = vault.send("DELETE", "db-password", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, body) = vault.send("GET", "db-password", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["code"], json!("not_found"));
+}
+
+#[tokio::test]
+async fn values_never_reach_the_store_in_plaintext() {
+    let vault = Harness::new();
+    vault
+        .send("PUT", "api-key", Some(json!({"value": {"key": "s3cret"}})))
+        .await;
+    let record = vault.store.get("api-key").await.unwrap().unwrap();
+    assert!(!record.ciphertext.to_string().contains("s3cret"));
+}
+
+#[tokio::test]
+async fn invalid_names_and_oversized_values_are_rejected() {
+    let vault = Harness::new();
+    let (status, _) = vault
+        .send("PUT", ".hidden", Some(json!({"value": 1})))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let mut vault = Harness::new();
+    vault.config.vault.max_value_bytes = 8;
+    let (status, body) = vault
+        .send("PUT", "big", Some(json!({"value": "0123456789"})))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["code"], json!("limit_exceeded"));
+}
+
+// ── audit ─────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn every_access_is_audited() {
+    let vault = Harness::new();
+    vault.send("PUT", "db", Some(json!({"value": 1}))).await;
+    vault.send("GET", "db", None).await;
+    vault.send("DELETE", "db", None).await;
+    vault.send("GET", "db", None).await;
+
+    let events = vault.audit.events();
+    let summary: Vec<_> = events
+        .iter()
+        .map(|event| (event.action, event.resource.as_str(), event.success))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("vault.write", "db", true),
+            ("vault.read", "db", true),
+            ("vault.delete", "db", true),
+            ("vault.read", "db", false),
+        ]
+    );
+    assert!(
+        events
+            .iter()
+            .all(|event| event.client.as_deref() == Some("billing"))
+    );
+}
+
+#[tokio::test]
+async fn entries_and_audit_events_are_dated_by_the_clock() {
+    let vault = Harness::new();
+    vault.send("PUT", "db", Some(json!({"value": 1}))).await;
+    vault.clock.advance(3_600);
+    let (_, entry) = vault.send("PUT", "db", Some(json!({"value": 2}))).await;
+    assert_eq!(entry["created_at"], json!(1_700_000_000));
+    assert_eq!(entry["updated_at"], json!(1_700_003_600));
+
+    let at: Vec<u64> = vault.audit.events().iter().map(|event| event.at).collect();
+    assert_eq!(at, [1_700_000_000, 1_700_003_600]);
+}
+
+// ── transparency log ──────────────────────────────────────────────
+
+#[cfg(feature = "asymmetric")]
+async fn get(app: &Router, uri: &str) -> (StatusCode, Value) {
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[cfg(feature = "asymmetric")]
+const ED25519_KEY: &str = include_str!("fixtures/keys/ed25519.pem");
+
+#[cfg(feature = "asymmetric")]
+fn unhex(hex: &str) -> [u8; 32] {
+    let mut hash = [0; 32];
+    for (byte, pair) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap();
+    }
+    hash
+}
+
+#[cfg(feature = "asymmetric")]
+#[tokio::test]
+async fn audited_accesses_are_sealed_into_consistent_roots() {
+    use base64::Engine as _;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use take_home::crypto::merkle;
+
+    let mut vault = Harness::new();
+    vault.config.transparency.enabled = true;
+    vault.config.signing.private_key = Some(Secret::new(ED25519_KEY));
+    let state = AppState::from_config_with_clock(&vault.config, vault.clock.clone())
+        .with_vault_store(vault.store.clone())
+        .with_audit_sink(vault.audit.clone());
+    let app = take_home::router(state.clone(), &vault.config);
+    let send = |method: &str, body: Option<Value>| {
+        let request = Request::builder()
+            .method(method)
+            .uri("/vault/db")
+            .header("Content-Type", "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+        app.clone().oneshot(request)
+    };
+    send("PUT", Some(json!({"value": 1}))).await.unwrap();
+    send("GET", None).await.unwrap();
+    let first = take_home::transparency::seal(&state)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(first.tree_size, 2);
+    assert_eq!(first.sealed_at, 1_700_000_000);
+    assert!(
+        take_home::transparency::seal(&state)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // Monitors can recompute a root from the audit events it covers.
+    let leaves: Vec<_> = vault
+        .audit
+        .events()
+        .iter()
+        .map(|event| merkle::leaf_hash(&serde_json::to_vec(event).unwrap()))
+        .collect();
+    let (_, roots) = get(&app, "/transparency/roots").await;
+    assert_eq!(roots["roots"][0]["tree_size"], json!(2));
+    assert_eq!(
+        unhex(roots["roots"][0]["root_hash"].as_str().unwrap()),
+        merkle::root(&leaves)
+    );
+
+    // ... and check its signature with the JWKS key it names.
+    let (_, jwks) = get(&app, "/.well-known/jwks.json").await;
+    assert_eq!(jwks["keys"][0]["kid"], json!(first.key_id));
+    let x = URL_SAFE_NO_PAD
+        .decode(jwks["keys"][0]["x"].as_str().unwrap())
+        .unwrap();
+    let key = ed25519_dalek::VerifyingKey::from_bytes(&x.try_into().unwrap()).unwrap();
+    let signature = first.signature.strip_prefix("v1.ed25519.").unwrap();
+    let signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+    let signature = ed25519_dalek::Signature::from_slice(&signature).unwrap();
+    let input = take_home::transparency::tree_head_signing_input(
+        first.tree_size,
+        &unhex(&first.root_hash),
+        first.sealed_at,
+    );
+    assert!(key.verify_strict(&input, &signature).is_ok());
+
+    vault.clock.advance(60);
+    send("DELETE", None).await.unwrap();
+    send("GET", None).await.unwrap();
+    let second = take_home::transparency::seal(&state)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((second.tree_size, second.sealed_at), (4, 1_700_000_060));
+
+    let (status, roots) = get(&app, "/transparency/roots?after=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(roots["roots"].as_array().unwrap().len(), 1);
+    assert_eq!(roots["roots"][0]["tree_size"], json!(4));
+
+    let (status, body) = get(&app, "/transparency/consistency?first=2&second=4").await;
+    assert_eq!(status, StatusCode::OK);
+    let proof: Vec<_> = body["proof"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hash| unhex(hash.as_str().unwrap()))
+        .collect();
+    assert!(merkle::verify_consistency(
+        2,
+        4,
+        &unhex(&first.root_hash),
+        &unhex(&second.root_hash),
+        &proof
+    ));
+}
+
+#[cfg(feature = "asymmetric")]
+#[tokio::test]
+async fn consistency_proofs_need_sealed_roots() {
+    let mut vault = Harness::new();
+    vault.config.transparency.enabled = true;
+    vault.config.signing.private_key = Some(Secret::new(ED25519_KEY));
+    let state = AppState::from_config_with_clock(&vault.config, vault.clock.clone());
+    let app = take_home::router(state, &vault.config);
+    let (status, body) = get(&app, "/transparency/consistency?first=1&second=2").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        body["error"]["message"],
+        json!("no root was sealed at 1 entries")
+    );
+    let (status, _) = get(&app, "/transparency/consistency?first=2&second=1").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = get(&app, "/transparency/roots").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"roots": []}));
+
+    // Disabled by default.
+    let (status, _) = get(&Harness::new().app(), "/transparency/roots").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,197 @@
+#![cfg(all(feature = "server", feature = "signing"))]
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use take_home::config::{Config, Secret};
+use take_home::crypto::hmac::HMacSigner;
+use tower::ServiceExt;
+
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.signing.secret = Some(Secret::new("test-secret"));
+    config.webhooks.stripe = Some(Secret::new("whsec_stripe"));
+    config.webhooks.github = Some(Secret::new("github-secret"));
+    config.webhooks.slack = Some(Secret::new("slack-secret"));
+    config
+}
+
+fn app() -> Router {
+    take_home::app(&test_config())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn hmac_hex(secret: &str, message: &[u8]) -> String {
+    HMacSigner::new(secret.as_bytes().to_vec())
+        .mac(message)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+async fn deliver(
+    app: Router,
+    provider: &str,
+    headers: &[(&str, String)],
+    content_type: &str,
+    body: &str,
+) -> (StatusCode, Option<Value>) {
+    let mut builder = Request::builder()
+        .method("POST")
+        .uri(format!("/webhooks/verify/{provider}"))
+        .header("Content-Type", content_type);
+    for (name, value) in headers {
+        builder = builder.header(*name, value);
+    }
+    let request = builder.body(Body::from(body.to_string())).unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value = serde_json::from_slice(&bytes).ok();
+    (status, value)
+}
+
+const EVENT: &str = r#"{"id":"evt_1","type":"invoice.paid"}"#;
+
+fn stripe_header(timestamp: u64, body: &str) -> String {
+    let tag = hmac_hex("whsec_stripe", format!("{timestamp}.{body}").as_bytes());
+    format!("t={timestamp},v1={tag}")
+}
+
+// ── Stripe ─────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn stripe_delivery_returns_parsed_payload() {
+    let headers = [("Stripe-Signature", stripe_header(now(), EVENT))];
+    let (status, body) = deliver(app(), "stripe", &headers, "application/json", EVENT).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body.unwrap(),
+        json!({"provider": "stripe", "payload": {"id": "evt_1", "type": "invoice.paid"}})
+    );
+}
+
+#[tokio::test]
+async fn stripe_replayed_delivery_returns_400() {
+    let headers = [("Stripe-Signature", stripe_header(now() - 3600, EVENT))];
+    let (status, body) = deliver(app(), "stripe", &headers, "application/json", EVENT).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let error = &body.unwrap()["error"];
+    assert_eq!(error["code"], "expired");
+    assert_eq!(
+        error["message"],
+        "webhook timestamp is more than 300s (+0s clock skew) from the server clock"
+    );
+}
+
+#[tokio::test]
+async fn stripe_delivery_within_clock_skew_is_accepted() {
+    let mut config = test_config();
+    config.clock.skew_secs = 60;
+    let headers = [("Stripe-Signature", stripe_header(now() - 330, EVENT))];
+    let (status, _) = deliver(
+        take_home::app(&config),
+        "stripe",
+        &headers,
+        "application/json",
+        EVENT,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+// ── GitHub ─────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn github_delivery_returns_parsed_payload() {
+    let signature = format!("sha256={}", hmac_hex("github-secret", EVENT.as_bytes()));
+    let headers = [("X-Hub-Signature-256", signature)];
+    let (status, body) = deliver(app(), "github", &headers, "application/json", EVENT).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.unwrap()["payload"]["id"], "evt_1");
+}
+
+#[tokio::test]
+async fn github_tampered_delivery_returns_400() {
+    let signature = format!("sha256={}", hmac_hex("github-secret", EVENT.as_bytes()));
+    let headers = [("X-Hub-Signature-256", signature)];
+    let (status, body) = deliver(app(), "github", &headers, "application/json", "{}").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "invalid_signature");
+}
+
+// ── Slack ──────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn slack_form_delivery_is_parsed_into_fields() {
+    let body = "command=%2Fdeploy&text=prod";
+    let timestamp = now().to_string();
+    let signature = format!(
+        "v0={}",
+        hmac_hex("slack-secret", format!("v0:{timestamp}:{body}").as_bytes())
+    );
+    let headers = [
+        ("X-Slack-Request-Timestamp", timestamp),
+        ("X-Slack-Signature", signature),
+    ];
+    let (status, response) = deliver(
+        app(),
+        "slack",
+        &headers,
+        "application/x-www-form-urlencoded",
+        body,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        response.unwrap()["payload"],
+        json!({"command": "/deploy", "text": "prod"})
+    );
+}
+
+// ── errors ─────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn unknown_provider_returns_400() {
+    let (status, body) = deliver(app(), "paypal", &[], "application/json", EVENT).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "validation_failed");
+}
+
+#[tokio::test]
+async fn unconfigured_provider_returns_400() {
+    let mut config = test_config();
+    config.webhooks.github = None;
+    let signature = format!("sha256={}", hmac_hex("github-secret", EVENT.as_bytes()));
+    let headers = [("X-Hub-Signature-256", signature)];
+    let (status, body) = deliver(
+        take_home::app(&config),
+        "github",
+        &headers,
+        "application/json",
+        EVENT,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "validation_failed");
+}
+
+#[tokio::test]
+async fn missing_signature_header_returns_400() {
+    let (status, body) = deliver(app(), "github", &[], "application/json", EVENT).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.unwrap()["error"]["code"], "validation_failed");
+}